@@ -0,0 +1,187 @@
+use clap::Parser;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use my_project::{handlers::Handler, options::Options, testing::RequestBuilder};
+use std::{io, net::SocketAddr, str::FromStr};
+use tokio::runtime::Runtime;
+use trust_dns_server::{
+    authority::{MessageRequest, MessageResponse},
+    client::rr::{Name, Record, RecordType},
+    proto::{
+        op::{Message, Query},
+        serialize::binary::{BinDecodable, BinEncoder},
+    },
+    server::{Request, RequestHandler, ResponseHandler, ResponseInfo},
+};
+
+// The domain the benchmarked handler serves.
+const DOMAIN: &str = "mentisnovae.tech";
+
+// The number of queries in flight at once in the burst benchmark.
+const BURST: usize = 256;
+
+// The queries benchmarked one by one, as (benchmark name, query name below the domain).
+const ZONES: &[(&str, &str)] = &[
+    ("myip", "myip"),
+    ("counter", "counter"),
+    ("coin", "coin"),
+    ("dice", "dice"),
+    ("cidr", "cidr.10.0.0.0.8"),
+    ("time", "time.1700000000"),
+    ("lotto", "lotto.6.49"),
+    ("rps", "rps.rock"),
+    ("moon", "moon"),
+    ("sun", "sun.12_97.77_59.+0530"),
+    ("week", "week.2024-02-29"),
+    ("str", "str.reverse.hello"),
+    ("puny", "puny.xn--bcher-kva"),
+    ("luhn", "luhn.4111111111111111"),
+    ("crc32", "crc32.hello"),
+    ("fib", "fib.1000"),
+    ("fact", "fact.100"),
+    ("age", "age.1990-05-17"),
+    ("ulid", "ulid"),
+    ("name", "name"),
+    ("health", "health"),
+    ("stats", "stats"),
+    ("root", ""),
+];
+
+/*
+Description:
+a ResponseHandler that serializes the answer into memory instead of sending it, so the benchmarks measure the handler and the encoding of its answer without any socket I/O.
+*/
+#[derive(Clone, Default)]
+struct NullResponder;
+
+#[async_trait::async_trait]
+impl ResponseHandler for NullResponder {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        let mut bytes = Vec::with_capacity(512);
+        let mut encoder = BinEncoder::new(&mut bytes);
+        let header = response
+            .destructive_emit(&mut encoder)
+            .map_err(io::Error::other)?;
+        Ok(header)
+    }
+}
+
+// Builds a TXT query for a name, as it would arrive over UDP from a fixed client; the labels are taken as raw bytes, as they are off the wire, since some (e.g. sun's "+0530") aren't valid hostnames
+fn request(name: &str) -> Request {
+    let mut name = Name::from_labels(name.split('.').filter(|label| !label.is_empty()).map(str::as_bytes)).unwrap();
+    name.set_fqdn(true);
+
+    RequestBuilder::new(name, RecordType::TXT)
+        .id(4242)
+        .source(SocketAddr::from(([192, 0, 2, 7], 53000)))
+        .build()
+}
+
+// Creates a handler with the default options and no configuration file
+fn handler(runtime: &Runtime) -> Handler {
+    let options = Options::parse_from(["rusty-dns", "--domain", DOMAIN]);
+    let _guard = runtime.enter();
+    Handler::from_options(&options)
+}
+
+// Benchmarks each zone's handler through the full dispatch path
+fn zones(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let handler = handler(&runtime);
+
+    let mut group = c.benchmark_group("zones");
+    group.throughput(Throughput::Elements(1));
+    for (zone, label) in ZONES {
+        let name = match *label {
+            "" => format!("{DOMAIN}."),
+            label => format!("{label}.{DOMAIN}."),
+        };
+        let request = request(&name);
+        group.bench_with_input(BenchmarkId::from_parameter(zone), &request, |b, request| {
+            b.to_async(&runtime).iter(|| handler.handle_request(request, NullResponder))
+        });
+    }
+    group.finish();
+}
+
+// Benchmarks the dispatch path on its own: a name outside every zone walks all the zone checks before it is refused
+fn dispatch(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let handler = handler(&runtime);
+
+    let mut group = c.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(1));
+    for (case, name) in [("outside", "example.com."), ("unknown-keyword", "nosuchzone.mentisnovae.tech.")] {
+        let request = request(name);
+        group.bench_with_input(BenchmarkId::from_parameter(case), &request, |b, request| {
+            b.to_async(&runtime).iter(|| handler.handle_request(request, NullResponder))
+        });
+    }
+    group.finish();
+}
+
+// Benchmarks a burst of concurrent queries to a mix of zones on the multi-threaded runtime, where the allocator is under the most pressure; compare runs with --features jemalloc or mimalloc
+fn burst(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let handler = handler(&runtime);
+    let requests: Vec<Request> = ["str.reverse.hello", "fib.1000", "cidr.10.0.0.0.8", "dice", "ulid", "nosuchzone"]
+        .iter()
+        .map(|label| request(&format!("{label}.{DOMAIN}.")))
+        .collect();
+    let requests = std::sync::Arc::new(requests);
+
+    let mut group = c.benchmark_group("burst");
+    group.throughput(Throughput::Elements(BURST as u64));
+    group.bench_function(BenchmarkId::from_parameter(BURST), |b| {
+        b.to_async(&runtime).iter(|| {
+            let tasks: Vec<_> = (0..BURST)
+                .map(|i| {
+                    let handler = handler.clone();
+                    let requests = requests.clone();
+                    tokio::spawn(async move { handler.handle_request(&requests[i % requests.len()], NullResponder).await })
+                })
+                .collect();
+            async move {
+                for task in tasks {
+                    task.await.unwrap();
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+// Benchmarks answering a name no zone serves from its pre-serialized template, as the batched UDP transport does
+fn templates(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let handler = handler(&runtime);
+
+    let mut message = Message::new();
+    message.add_query(Query::query(Name::from_str("nosuchzone.mentisnovae.tech.").unwrap(), RecordType::TXT));
+    let bytes = message.to_vec().unwrap();
+    let src = SocketAddr::from(([192, 0, 2, 7], 53000));
+
+    let mut answer = Vec::with_capacity(512);
+    c.bench_function("templates/nxdomain", |b| b.iter(|| handler.answer_from_template(src, &bytes, &mut answer)));
+}
+
+// Benchmarks decoding a query from the wire, which happens before every handler call
+fn decode(c: &mut Criterion) {
+    let mut message = Message::new();
+    message.add_query(Query::query(Name::from_str("str.reverse.hello.mentisnovae.tech.").unwrap(), RecordType::TXT));
+    let bytes = message.to_vec().unwrap();
+
+    c.bench_function("decode", |b| b.iter(|| MessageRequest::from_bytes(&bytes).unwrap()));
+}
+
+criterion_group!(benches, zones, dispatch, burst, templates, decode);
+criterion_main!(benches);
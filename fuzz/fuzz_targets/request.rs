@@ -0,0 +1,8 @@
+// Feeds arbitrary buffers through message decoding into the handler, as if they had arrived over UDP
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|bytes: &[u8]| {
+    my_project_fuzz::handle_bytes(bytes);
+});
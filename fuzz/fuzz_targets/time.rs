@@ -0,0 +1,8 @@
+// Feeds arbitrary label sequences into the time zone's parser
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|labels: Vec<Vec<u8>>| {
+    my_project_fuzz::handle_labels("time", &labels);
+});
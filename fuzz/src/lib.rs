@@ -0,0 +1,92 @@
+/*
+Description:
+the setup shared by the fuzz targets: a handler built once with the default options, and helpers that hand it queries the way the UDP transport does. Any panic in the handler, such as a todo!() or an unwrap reached from a query, fails the run.
+*/
+use clap::Parser;
+use my_project::{cache::CaptureResponder, handlers::Handler, options::Options};
+use std::{net::SocketAddr, sync::OnceLock};
+use tokio::runtime::{Builder, Runtime};
+use trust_dns_server::{
+    authority::MessageRequest,
+    client::rr::{Name, RecordType},
+    proto::{
+        op::{Message, MessageType, OpCode, Query},
+        serialize::binary::BinDecodable,
+    },
+    server::{Protocol, Request, RequestHandler},
+};
+
+// The domain the fuzzed handler serves.
+pub const DOMAIN: &str = "mentisnovae.tech";
+
+// The runtime and handler shared by every run, since building a handler takes far longer than a query
+static STATE: OnceLock<(Runtime, Handler)> = OnceLock::new();
+
+// Returns the shared runtime and handler, building them on the first run
+fn state() -> &'static (Runtime, Handler) {
+    STATE.get_or_init(|| {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let options = Options::parse_from(["rusty-dns", "--domain", DOMAIN]);
+        let handler = {
+            let _guard = runtime.enter();
+            Handler::from_options(&options)
+        };
+        (runtime, handler)
+    })
+}
+
+/*
+Description:
+decodes a buffer as a DNS message and, if it decodes, passes it to the handler as a request from a fixed client over UDP.
+
+Parameters:
+bytes: the message in wire format.
+
+Returns:
+None
+*/
+pub fn handle_bytes(bytes: &[u8]) {
+    let Ok(request) = MessageRequest::from_bytes(bytes) else {
+        return;
+    };
+    let request = Request::new(request, SocketAddr::from(([192, 0, 2, 7], 53000)), Protocol::Udp);
+
+    let (runtime, handler) = state();
+    runtime.block_on(handler.handle_request(&request, CaptureResponder::default()));
+}
+
+/*
+Description:
+queries a zone with arbitrary labels, both below the zone (e.g. <labels>.cidr.mentisnovae.tech) and between its keyword and the domain (e.g. cidr.<labels>.mentisnovae.tech), for every record type the zones treat differently. Labels that can't form a name are skipped.
+
+Parameters:
+zone: the zone's keyword, e.g. "cidr".
+labels: the labels, as raw bytes since they come off the wire that way.
+
+Returns:
+None
+*/
+pub fn handle_labels(zone: &str, labels: &[Vec<u8>]) {
+    let domain: Vec<&[u8]> = DOMAIN.split('.').map(str::as_bytes).collect();
+    let labels = labels.iter().map(Vec::as_slice);
+    let below: Vec<&[u8]> = labels.clone().chain([zone.as_bytes()]).chain(domain.iter().copied()).collect();
+    let between: Vec<&[u8]> = [zone.as_bytes()].into_iter().chain(labels).chain(domain.iter().copied()).collect();
+
+    for labels in [below, between] {
+        let Ok(mut name) = Name::from_labels(labels) else {
+            continue;
+        };
+        name.set_fqdn(true);
+
+        for record_type in [RecordType::TXT, RecordType::A, RecordType::AAAA] {
+            let mut message = Message::new();
+            message
+                .set_message_type(MessageType::Query)
+                .set_op_code(OpCode::Query)
+                .add_query(Query::query(name.clone(), record_type));
+            if let Ok(bytes) = message.to_vec() {
+                handle_bytes(&bytes);
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, net::IpAddr, str::FromStr};
+
+/*
+Description:
+an IP network in CIDR notation, e.g. "192.0.2.0/24" or "2001:db8::/32". A bare address is treated as a single-host network.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    // The network address
+    address: IpAddr,
+
+    // The prefix length in bits
+    prefix: u8,
+}
+
+impl Cidr {
+    /*
+    Description:
+    checks whether an address is inside the network. IPv4 networks never contain IPv6 addresses and vice versa, except that IPv4-mapped IPv6 addresses (::ffff:a.b.c.d, as seen on dual-stack sockets) are matched as IPv4.
+
+    Parameters:
+    ip: the address to check.
+
+    Returns:
+    true if the address is inside the network.
+    */
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Treat IPv4-mapped IPv6 addresses as the IPv4 addresses they are
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            ip => ip,
+        };
+
+        match (self.address, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    // Parses a network such as "10.0.0.0/8", or a single address such as "192.0.2.1"
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match text.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (text, None),
+        };
+
+        let address: IpAddr = address
+            .parse()
+            .map_err(|_| format!("invalid address in {text}"))?;
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| format!("invalid prefix length in {text}"))?,
+            None => max_prefix,
+        };
+
+        Ok(Cidr { address, prefix })
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    // Reads a network from its CIDR notation in the configuration file
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Cidr {
+    // Writes a network in its CIDR notation, as the configuration file has it
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+/*
+Description:
+a source-address access control list. Deny entries win over allow entries. What an empty allow list means depends on how the list was created: an open list allows everyone who isn't denied (so "--deny 203.0.113.0/24" alone blocks one network), while a closed list allows no one until networks are explicitly allowed.
+*/
+#[derive(Clone, Debug)]
+pub struct Acl {
+    // The networks that are permitted
+    allow: Vec<Cidr>,
+
+    // The networks that are refused, taking precedence over allow
+    deny: Vec<Cidr>,
+
+    // Whether an empty allow list permits everyone
+    open: bool,
+}
+
+impl Acl {
+    /*
+    Description:
+    creates an access control list that permits everyone unless an allow list is given, as used for ordinary queries.
+
+    Parameters:
+    allow: the networks that are permitted; empty means everyone.
+    deny: the networks that are refused, taking precedence over allow.
+
+    Returns:
+    A new Acl.
+    */
+    pub fn open(allow: Vec<Cidr>, deny: Vec<Cidr>) -> Self {
+        Acl { allow, deny, open: true }
+    }
+
+    /*
+    Description:
+    creates an access control list that permits no one unless an allow list is given, as used for zone transfers and dynamic updates.
+
+    Parameters:
+    allow: the networks that are permitted; empty means no one.
+    deny: the networks that are refused, taking precedence over allow.
+
+    Returns:
+    A new Acl.
+    */
+    pub fn closed(allow: Vec<Cidr>, deny: Vec<Cidr>) -> Self {
+        Acl { allow, deny, open: false }
+    }
+
+    /*
+    Description:
+    checks whether a client may use the server.
+
+    Parameters:
+    ip: the client's address.
+
+    Returns:
+    true if the client is permitted.
+    */
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|cidr| cidr.contains(ip)) {
+            return false;
+        }
+        if self.allow.is_empty() {
+            return self.open;
+        }
+        self.allow.iter().any(|cidr| cidr.contains(ip))
+    }
+}
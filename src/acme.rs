@@ -0,0 +1,446 @@
+use crate::{
+    acl::{Acl, Cidr},
+    audit::AuditEntry,
+    handlers::Handler,
+    httpd::{self, error, json},
+    tasks,
+};
+use http::{Method, Request, Response, StatusCode};
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::*;
+
+// The keyword of the zone the challenge tokens are served in, as acme.<subdomain>.<domain>.
+pub const ZONE: &str = "acme";
+
+// The TTL of the challenge tokens' TXT records, short so that a new token is seen at once.
+pub const TOKEN_TTL: u32 = 1;
+
+// The tokens kept per account: two, so a certificate for a name and its wildcard can be validated together.
+const MAX_TOKENS: usize = 2;
+
+// The length of a DNS-01 token: a base64url SHA-256 digest without padding (RFC 8555 section 8.4).
+const TOKEN_LENGTH: usize = 43;
+
+// The length of the passwords handed out at registration.
+const PASSWORD_LENGTH: usize = 40;
+
+// The largest request body the API accepts.
+const MAX_BODY_SIZE: usize = 4096;
+
+// How long a client gets to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/*
+Description:
+an account of the ACME API: the credentials certbot or lego use to publish tokens, and the one subdomain they may publish them for.
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Account {
+    // The account's user name, sent in the X-Api-User header
+    username: String,
+
+    // The SHA-256 digest of the account's password (the X-Api-Key header), in hexadecimal; the password itself is never stored
+    password_hash: String,
+
+    // The label the account's tokens are served under
+    subdomain: String,
+
+    // The networks the account may publish tokens from; empty for anywhere
+    #[serde(default)]
+    allowfrom: Vec<Cidr>,
+}
+
+/*
+Description:
+the answer to a registration, in acme-dns's format, which is what certbot's and lego's acme-dns plugins store.
+*/
+#[derive(Debug, Serialize)]
+pub struct Registration {
+    pub username: String,
+    pub password: String,
+    pub fulldomain: String,
+    pub subdomain: String,
+    pub allowfrom: Vec<Cidr>,
+}
+
+/*
+Description:
+the ways a request to the ACME API can fail, named by the error codes acme-dns answers with.
+*/
+#[derive(Debug, PartialEq, Eq)]
+pub enum AcmeError {
+    // The credentials are wrong, or the client isn't allowed to register or publish from its address
+    Forbidden,
+
+    // The subdomain is not the account's
+    BadSubdomain,
+
+    // The token is not a DNS-01 token
+    BadTxt,
+
+    // The allowfrom list of a registration holds something other than networks
+    BadAllowFrom,
+
+    // The request body is not the JSON expected
+    MalformedJson,
+
+    // The accounts could not be saved
+    Storage,
+}
+
+impl AcmeError {
+    // Returns the HTTP status and the error code of the failure
+    fn status(&self) -> (StatusCode, &'static str) {
+        match self {
+            AcmeError::Forbidden => (StatusCode::UNAUTHORIZED, "forbidden"),
+            AcmeError::BadSubdomain => (StatusCode::UNAUTHORIZED, "bad_subdomain"),
+            AcmeError::BadTxt => (StatusCode::BAD_REQUEST, "bad_txt"),
+            AcmeError::BadAllowFrom => (StatusCode::BAD_REQUEST, "invalid_allowfrom_cidr"),
+            AcmeError::MalformedJson => (StatusCode::BAD_REQUEST, "malformed_json_payload"),
+            AcmeError::Storage => (StatusCode::INTERNAL_SERVER_ERROR, "db_error"),
+        }
+    }
+}
+
+/*
+Description:
+the ACME DNS-01 challenge responder: accounts registered through its HTTP API, and the challenge tokens they have published, which the acme zone serves as TXT records at acme.<subdomain>.<domain>. To get a certificate for example.com, point _acme-challenge.example.com at an account's full domain with a CNAME and let the client publish its tokens there. Tokens expire after a while, and at most the two newest of an account are kept.
+*/
+#[derive(Debug)]
+pub struct Acme {
+    // The domain the tokens are served under
+    domain: String,
+
+    // The accounts, by user name
+    accounts: RwLock<HashMap<String, Account>>,
+
+    // The tokens published for each subdomain, oldest first, with when they were published
+    tokens: Mutex<HashMap<String, VecDeque<(String, Instant)>>>,
+
+    // The file the accounts are kept in, if they survive restarts
+    accounts_file: Option<PathBuf>,
+
+    // The networks allowed to register accounts
+    register_acl: Acl,
+
+    // How long a token is served
+    token_lifetime: Duration,
+
+    // Held while the accounts file is written
+    saving: tokio::sync::Mutex<()>,
+}
+
+impl Acme {
+    /*
+    Description:
+    creates the challenge responder, loading the accounts registered before if there is an accounts file.
+
+    Parameters:
+    domain: the domain the tokens are served under.
+    accounts_file: the JSON file the accounts are kept in; none keeps them in memory only. A missing file is created at the first registration.
+    register_allow: the networks allowed to register accounts; empty for no one.
+    token_lifetime: how long a token is served after it is published.
+
+    Returns:
+    Ok(Acme), or an io::Error if the accounts file exists but could not be read.
+    */
+    pub fn open(domain: &str, accounts_file: Option<&Path>, register_allow: Vec<Cidr>, token_lifetime: Duration) -> io::Result<Self> {
+        let accounts: Vec<Account> = match accounts_file.map(std::fs::read) {
+            Some(Ok(data)) => serde_json::from_slice(&data).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?,
+            Some(Err(error)) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => Vec::new(),
+        };
+
+        Ok(Acme {
+            domain: domain.trim_end_matches('.').to_ascii_lowercase(),
+            accounts: RwLock::new(accounts.into_iter().map(|account| (account.username.clone(), account)).collect()),
+            tokens: Mutex::new(HashMap::new()),
+            accounts_file: accounts_file.map(Path::to_path_buf),
+            register_acl: Acl::closed(register_allow, Vec::new()),
+            token_lifetime,
+            saving: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    // Returns the name an account's tokens are served at, which _acme-challenge names point to
+    pub fn fulldomain(&self, subdomain: &str) -> String {
+        format!("{ZONE}.{subdomain}.{}", self.domain)
+    }
+
+    // Returns the number of accounts
+    pub fn accounts(&self) -> usize {
+        self.accounts.read().unwrap().len()
+    }
+
+    /*
+    Description:
+    registers a new account with a random user name, password and subdomain, and saves the accounts if there is an accounts file.
+
+    Parameters:
+    client: the address of the client registering.
+    allowfrom: the networks the account may publish tokens from; empty for anywhere.
+
+    Returns:
+    Ok(Registration) with the account's credentials, the only time the password is seen, or an AcmeError if the client may not register or the accounts could not be saved.
+    */
+    pub async fn register(&self, client: IpAddr, allowfrom: Vec<Cidr>) -> Result<Registration, AcmeError> {
+        if !self.register_acl.permits(client) {
+            return Err(AcmeError::Forbidden);
+        }
+
+        // The credentials are secrets, so they come from the OS even when the zones' generator is seeded
+        let password: String = OsRng.sample_iter(&Alphanumeric).take(PASSWORD_LENGTH).map(char::from).collect();
+        let account = Account {
+            username: uuid(),
+            password_hash: hash(&password),
+            subdomain: uuid(),
+            allowfrom,
+        };
+        let registration = Registration {
+            username: account.username.clone(),
+            password,
+            fulldomain: self.fulldomain(&account.subdomain),
+            subdomain: account.subdomain.clone(),
+            allowfrom: account.allowfrom.clone(),
+        };
+
+        self.accounts.write().unwrap().insert(account.username.clone(), account);
+        self.save().await.map_err(|error| {
+            error!("Failed to save the ACME accounts: {error}");
+            AcmeError::Storage
+        })?;
+        Ok(registration)
+    }
+
+    /*
+    Description:
+    publishes a challenge token for an account's subdomain, replacing the oldest of its tokens if it already has two.
+
+    Parameters:
+    client: the address of the client publishing.
+    username: the account's user name.
+    password: the account's password.
+    subdomain: the subdomain to publish the token for, which must be the account's.
+    txt: the token.
+
+    Returns:
+    Ok(()) once the token is served, or an AcmeError if the credentials, the client's address, the subdomain or the token are wrong.
+    */
+    pub fn update(&self, client: IpAddr, username: &str, password: &str, subdomain: &str, txt: &str) -> Result<(), AcmeError> {
+        {
+            let accounts = self.accounts.read().unwrap();
+            // The stored digest is compared rather than the password, so the comparison's timing reveals nothing about the password
+            let account = accounts
+                .get(username)
+                .filter(|account| account.password_hash == hash(password))
+                .ok_or(AcmeError::Forbidden)?;
+            if !account.allowfrom.is_empty() && !account.allowfrom.iter().any(|network| network.contains(client)) {
+                return Err(AcmeError::Forbidden);
+            }
+            if !account.subdomain.eq_ignore_ascii_case(subdomain) {
+                return Err(AcmeError::BadSubdomain);
+            }
+        }
+        let token_chars = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if txt.len() != TOKEN_LENGTH || !txt.chars().all(token_chars) {
+            return Err(AcmeError::BadTxt);
+        }
+
+        let mut tokens = self.tokens.lock().unwrap();
+        let published = tokens.entry(subdomain.to_ascii_lowercase()).or_default();
+        published.push_back((txt.to_string(), Instant::now()));
+        while published.len() > MAX_TOKENS {
+            published.pop_front();
+        }
+        Ok(())
+    }
+
+    /*
+    Description:
+    returns the tokens served for a subdomain, dropping the expired ones.
+
+    Parameters:
+    subdomain: the subdomain, in any case.
+
+    Returns:
+    Some(Vec<String>) with the live tokens, oldest first, which may be none; or None if no account has the subdomain.
+    */
+    pub fn tokens(&self, subdomain: &str) -> Option<Vec<String>> {
+        let subdomain = subdomain.to_ascii_lowercase();
+        if !self.accounts.read().unwrap().values().any(|account| account.subdomain == subdomain) {
+            return None;
+        }
+
+        let mut tokens = self.tokens.lock().unwrap();
+        let Some(published) = tokens.get_mut(&subdomain) else {
+            return Some(Vec::new());
+        };
+        published.retain(|(_, added)| added.elapsed() < self.token_lifetime);
+        let live = published.iter().map(|(token, _)| token.clone()).collect();
+        if published.is_empty() {
+            tokens.remove(&subdomain);
+        }
+        Some(live)
+    }
+
+    // Writes the accounts to the accounts file, if there is one, through a temporary file so a crash never leaves half of them behind
+    async fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.accounts_file else {
+            return Ok(());
+        };
+
+        // One registration saves at a time, each with every account registered so far
+        let _saving = self.saving.lock().await;
+        let accounts: Vec<Account> = self.accounts.read().unwrap().values().cloned().collect();
+        let data = serde_json::to_vec_pretty(&accounts).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, data).await?;
+        tokio::fs::rename(&tmp, path).await
+    }
+}
+
+/*
+Description:
+serves the ACME API, compatible with acme-dns so certbot's and lego's acme-dns plugins can use it: POST /register creates an account (optionally limited to the networks in a JSON body's allowfrom list), POST /update publishes a token given as {"subdomain": ..., "txt": ...} with the account's X-Api-User and X-Api-Key headers, and GET /health answers 200. Every connection carries one request. Serve it over HTTPS through a reverse proxy if clients reach it across a network, since the keys travel in the clear.
+
+Parameters:
+listener: the TCP listener to accept API connections on.
+handler: the DNS handler, whose challenge responder the API manages and whose audit log records registrations and updates.
+
+Returns:
+None; runs until the process exits.
+*/
+pub async fn serve(listener: TcpListener, handler: Handler) {
+    loop {
+        // Accept the next connection, carrying on if a single accept fails
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(error) => {
+                warn!("Failed to accept ACME API connection: {error}");
+                continue;
+            }
+        };
+
+        // Serve each connection in its own task
+        let handler = handler.clone();
+        tasks::spawn("acme-connection", async move {
+            if let Err(error) = serve_connection(stream, peer, &handler).await {
+                debug!("ACME API connection from {peer} failed: {error}");
+            }
+        });
+    }
+}
+
+// Reads a request from a connection, answers it and records it in the audit log
+async fn serve_connection(mut stream: TcpStream, peer: SocketAddr, handler: &Handler) -> io::Result<()> {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, httpd::read_request(&mut stream, MAX_BODY_SIZE))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request timed out"))??;
+
+    let response = match &handler.acme {
+        Some(acme) => route(acme, peer.ip(), &request).await,
+        None => error(StatusCode::NOT_FOUND),
+    };
+
+    if let Some(audit) = &handler.audit {
+        let user = header(&request, "X-Api-User").unwrap_or("-");
+        audit.record(AuditEntry::now(
+            format!("acme-api/{user}"),
+            peer.to_string(),
+            request.method().as_str(),
+            request.uri().path().to_string(),
+            response.status().as_u16().to_string(),
+        ));
+    }
+    httpd::write_response(&mut stream, &response).await
+}
+
+// Routes an API request to the matching endpoint
+async fn route(acme: &Acme, client: IpAddr, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    #[derive(Deserialize, Default)]
+    struct RegisterBody {
+        #[serde(default)]
+        allowfrom: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct UpdateBody {
+        subdomain: String,
+        txt: String,
+    }
+
+    let result = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/health") => return json(StatusCode::OK, &serde_json::json!({})),
+        (&Method::POST, "/register") => {
+            let body = match request.body().is_empty() {
+                true => Ok(RegisterBody::default()),
+                false => serde_json::from_slice::<RegisterBody>(request.body()).map_err(|_| AcmeError::MalformedJson),
+            };
+            let allowfrom = body.and_then(|body| {
+                body.allowfrom
+                    .iter()
+                    .map(|network| network.parse::<Cidr>().map_err(|_| AcmeError::BadAllowFrom))
+                    .collect::<Result<Vec<_>, _>>()
+            });
+            match allowfrom {
+                Ok(allowfrom) => acme
+                    .register(client, allowfrom)
+                    .await
+                    .map(|registration| json(StatusCode::CREATED, &registration)),
+                Err(error) => Err(error),
+            }
+        }
+        (&Method::POST, "/update") => {
+            let (Some(username), Some(password)) = (header(request, "X-Api-User"), header(request, "X-Api-Key")) else {
+                return acme_error(&AcmeError::Forbidden);
+            };
+            serde_json::from_slice::<UpdateBody>(request.body())
+                .map_err(|_| AcmeError::MalformedJson)
+                .and_then(|body| {
+                    acme.update(client, username, password, &body.subdomain, &body.txt)
+                        .map(|()| json(StatusCode::OK, &serde_json::json!({ "txt": body.txt })))
+                })
+        }
+        (_, "/health" | "/register" | "/update") => return error(StatusCode::METHOD_NOT_ALLOWED),
+        _ => return error(StatusCode::NOT_FOUND),
+    };
+
+    result.unwrap_or_else(|error| acme_error(&error))
+}
+
+// Builds the response of a failed request, with acme-dns's error code
+fn acme_error(error: &AcmeError) -> Response<Vec<u8>> {
+    let (status, code) = error.status();
+    json(status, &serde_json::json!({ "error": code }))
+}
+
+// Returns the value of a request header
+fn header<'a>(request: &'a Request<Vec<u8>>, name: &str) -> Option<&'a str> {
+    request.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+// Returns the SHA-256 digest of a password in hexadecimal
+fn hash(password: &str) -> String {
+    let digest = digest::digest(&digest::SHA256, password.as_bytes());
+    digest.as_ref().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// Returns a random version 4 UUID, as acme-dns names its accounts and subdomains
+fn uuid() -> String {
+    let mut bytes: [u8; 16] = OsRng.gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
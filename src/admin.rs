@@ -0,0 +1,196 @@
+use crate::{
+    audit::AuditEntry,
+    dashboard,
+    handlers::Handler,
+    httpd::{self, error, json, query_param},
+    stats, tasks,
+    tenants::Tenant,
+};
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use std::{io, net::SocketAddr, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::*;
+
+// The largest request body read; the admin API takes none, but a client may send one anyway.
+const MAX_BODY_SIZE: usize = 8192;
+
+// How long a client gets to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The number of clients returned by /clients unless a limit is given.
+const DEFAULT_CLIENT_LIMIT: usize = 100;
+
+/*
+Description:
+serves the admin HTTP API and the dashboard. This is a deliberately small HTTP/1.1 server: every connection carries one request, answered with a JSON body (or the dashboard page) and closed, except for the dashboard's event stream, which stays open. It is meant to be bound to a private address, since it exposes information about the server's clients; requests carrying a hosted domain's admin token as "Authorization: Bearer <token>" only see that domain's state, so its owner can be let in. The /healthz and /readyz endpoints are there for orchestrators and load balancers to probe.
+
+Parameters:
+listener: the TCP listener to accept admin connections on.
+handler: the DNS handler whose state is exposed.
+
+Returns:
+None; runs until the process exits.
+*/
+pub async fn serve(listener: TcpListener, handler: Handler) {
+    loop {
+        // Accept the next connection, carrying on if a single accept fails
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(error) => {
+                warn!("Failed to accept admin connection: {error}");
+                continue;
+            }
+        };
+
+        // Serve each connection in its own task
+        let handler = handler.clone();
+        tasks::spawn("admin-connection", async move {
+            if let Err(error) = serve_connection(stream, peer, &handler).await {
+                debug!("Admin connection from {peer} failed: {error}");
+            }
+        });
+    }
+}
+
+/*
+Description:
+reads a single request from a connection, routes it to the handler of the hosted domain whose admin token it carries, or the main handler if it carries none, and writes the response. Requests with a token no domain has are answered 401. Requests for the dashboard's event stream are handed over to the dashboard, which keeps the connection.
+
+Parameters:
+stream: the client connection.
+peer: the client's address, for the audit log.
+handler: the DNS handler whose state is exposed.
+
+Returns:
+Ok(()) once the response is written, or an io::Error if the connection failed or the request was malformed.
+*/
+async fn serve_connection(mut stream: TcpStream, peer: SocketAddr, handler: &Handler) -> io::Result<()> {
+    // Read and parse the request head
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, httpd::read_request(&mut stream, MAX_BODY_SIZE))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request timed out"))??;
+
+    // Scope the request to the hosted domain whose admin token it carries
+    let tenant = match bearer_token(&request) {
+        Some(token) => match handler.tenants.as_ref().and_then(|tenants| tenants.for_token(token)) {
+            Some(tenant) => Some(tenant),
+            None => {
+                let response = error(StatusCode::UNAUTHORIZED);
+                audit(handler, None, peer, &request, response.status());
+                return httpd::write_response(&mut stream, &response).await;
+            }
+        },
+        None => None,
+    };
+    let scoped = tenant.map_or(handler, |tenant| &tenant.handler);
+
+    // The dashboard's event stream keeps the connection open
+    if request.method() == Method::GET && request.uri().path() == "/events" {
+        audit(handler, tenant, peer, &request, StatusCode::OK);
+        return dashboard::stream(stream, scoped).await;
+    }
+
+    // Route the request
+    let response = route(scoped, &request);
+    audit(handler, tenant, peer, &request, response.status());
+
+    // Write the response
+    httpd::write_response(&mut stream, &response).await
+}
+
+/*
+Description:
+routes an admin request to the matching endpoint.
+
+Parameters:
+handler: the DNS handler whose state is exposed.
+request: the admin request.
+
+Returns:
+The Response to send, with a JSON body or the dashboard page.
+*/
+fn route(handler: &Handler, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    // The admin API is read-only
+    if request.method() != Method::GET {
+        return error(StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    match request.uri().path() {
+        // The dashboard page, which then subscribes to /events
+        "/" | "/dashboard" => {
+            let mut response = Response::new(dashboard::INDEX_HTML.as_bytes().to_vec());
+            response
+                .headers_mut()
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+            response
+        }
+        // A single dashboard snapshot, for scripts that don't want the event stream
+        "/dashboard.json" => json(StatusCode::OK, &dashboard::snapshot(handler)),
+        // Handler and total latency percentiles per zone and per transport
+        "/latency" => {
+            let metrics = handler.metrics.snapshot();
+            json(StatusCode::OK, &serde_json::json!({ "zones": metrics.zone_latency, "transports": metrics.transport_latency }))
+        }
+        // Every counter the server keeps, as in a SIGUSR1 stats dump
+        "/stats" => json(StatusCode::OK, &stats::collect(handler)),
+        // Liveness: the process is up and serving the admin API
+        "/healthz" => json(StatusCode::OK, &handler.health.report(handler.counter.load())),
+        // Readiness: the DNS sockets are bound and queries can be answered
+        "/readyz" => {
+            let status = if handler.health.is_ready() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+            json(status, &handler.health.report(handler.counter.load()))
+        }
+        // The busiest clients, optionally limited with ?limit=<n>
+        "/clients" => {
+            let limit = query_param(request, "limit")
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(DEFAULT_CLIENT_LIMIT);
+            json(StatusCode::OK, &handler.clients.top(limit))
+        }
+        // The heavy hitters of the sliding window, optionally limited with ?limit=<n>
+        "/top/clients" | "/top/qnames" => {
+            let limit = query_param(request, "limit")
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(DEFAULT_CLIENT_LIMIT);
+            let window = handler.talkers.window().as_secs();
+            match request.uri().path() {
+                "/top/clients" => json(StatusCode::OK, &serde_json::json!({ "window": window, "top": handler.talkers.top_clients(limit) })),
+                _ => json(StatusCode::OK, &serde_json::json!({ "window": window, "top": handler.talkers.top_qnames(limit) })),
+            }
+        }
+        // Blocklist size, total blocks and the clients with the most blocked queries
+        "/blocklist" => match &handler.blocklist {
+            Some(blocklist) => {
+                let limit = query_param(request, "limit")
+                    .and_then(|limit| limit.parse().ok())
+                    .unwrap_or(DEFAULT_CLIENT_LIMIT);
+                json(StatusCode::OK, &blocklist.stats(limit))
+            }
+            None => error(StatusCode::NOT_FOUND),
+        },
+        _ => error(StatusCode::NOT_FOUND),
+    }
+}
+
+// Returns the admin token a request carries in its "Authorization: Bearer <token>" header, if any
+fn bearer_token(request: &Request<Vec<u8>>) -> Option<&str> {
+    let value = request.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    value.strip_prefix("Bearer ").map(str::trim)
+}
+
+// Records an admin request in the audit log; requests without a hosted domain's token have no authentication, so the client is known only by its address
+fn audit(handler: &Handler, tenant: Option<&Tenant>, peer: SocketAddr, request: &Request<Vec<u8>>, status: StatusCode) {
+    if let Some(audit) = &handler.audit {
+        let who = match tenant {
+            Some(tenant) => format!("admin-api/{}", tenant.domain),
+            None => "admin-api".to_string(),
+        };
+        audit.record(AuditEntry::now(
+            who,
+            peer.to_string(),
+            request.method().as_str(),
+            request.uri().to_string(),
+            status.as_u16().to_string(),
+        ));
+    }
+}
@@ -0,0 +1,43 @@
+use std::{io, mem};
+
+/*
+Description:
+lists the CPUs the process may run on, which can be fewer than the machine has when it runs under taskset or in a container with a CPU set.
+
+Parameters:
+None
+
+Returns:
+Ok(Vec) with the CPU numbers in ascending order, or an io::Error if the affinity mask could not be read.
+*/
+pub fn allowed_cpus() -> io::Result<Vec<usize>> {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+
+    // SAFETY: the set is a valid cpu_set_t of the size passed.
+    if unsafe { libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok((0..libc::CPU_SETSIZE as usize).filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) }).collect())
+}
+
+/*
+Description:
+pins the calling thread to one CPU, so the scheduler never moves it and its caches stay warm.
+
+Parameters:
+cpu: the CPU number, one of those from allowed_cpus.
+
+Returns:
+Ok(()) if the thread was pinned, or an io::Error if the CPU is not available.
+*/
+pub fn pin_current_thread(cpu: usize) -> io::Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    unsafe { libc::CPU_SET(cpu, &mut set) };
+
+    // SAFETY: the set is a valid cpu_set_t of the size passed; 0 means the calling thread.
+    if unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
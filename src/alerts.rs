@@ -0,0 +1,177 @@
+use crate::{
+    config::{AlertsConfig, WebhookFormat},
+    handlers::Handler,
+    tasks,
+};
+use serde_json::json;
+use std::time::{Duration, Instant};
+use tracing::*;
+
+// How long to wait for the webhook to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The thresholds that can be configured, each firing and clearing independently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rule {
+    Qps,
+    ServfailRate,
+    UpstreamFailures,
+}
+
+impl Rule {
+    // The rule's name, used in the PagerDuty deduplication key
+    fn name(self) -> &'static str {
+        match self {
+            Rule::Qps => "qps",
+            Rule::ServfailRate => "servfail-rate",
+            Rule::UpstreamFailures => "upstream-failures",
+        }
+    }
+}
+
+// The counters at the previous check, for measuring rates over the interval
+#[derive(Clone, Copy, Debug, Default)]
+struct Totals {
+    queries: u64,
+    servfails: u64,
+    upstream_failures: u64,
+}
+
+impl Totals {
+    // Reads the current counters
+    fn read(handler: &Handler) -> Self {
+        let zones = handler.metrics.snapshot().zones;
+        Totals {
+            queries: handler.counter.load(),
+            servfails: zones.iter().map(|(_, zone)| zone.servfails).sum(),
+            upstream_failures: handler.fx.failures(),
+        }
+    }
+}
+
+// Whether a rule is firing, and when its alert was last sent
+#[derive(Clone, Copy, Debug, Default)]
+struct RuleState {
+    firing: bool,
+    last_sent: Option<Instant>,
+}
+
+/*
+Description:
+spawns the alert monitor: a background task that checks the configured thresholds every interval and POSTs to the webhook when one is crossed, again every cooldown while it stays crossed, and once more when it clears. Must be called from within the tokio runtime.
+
+Parameters:
+handler: the DNS handler whose counters are watched.
+config: the [alerts] section of the config file.
+source: the name the server reports itself as in alerts, e.g. the served domain.
+
+Returns:
+None
+*/
+pub fn spawn(handler: Handler, config: AlertsConfig, source: String) {
+    tasks::spawn("alerts", async move {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        let interval = Duration::from_secs(config.interval.max(1));
+        let cooldown = Duration::from_secs(config.cooldown);
+        let mut states = [RuleState::default(); 3];
+
+        let mut previous = Totals::read(&handler);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            // Measure the rates over the interval
+            let totals = Totals::read(&handler);
+            let queries = totals.queries.saturating_sub(previous.queries);
+            let servfails = totals.servfails.saturating_sub(previous.servfails);
+            let upstream_failures = totals.upstream_failures.saturating_sub(previous.upstream_failures);
+            previous = totals;
+
+            let qps = queries as f64 / interval.as_secs_f64();
+            let servfail_rate = if queries > 0 { servfails as f64 / queries as f64 } else { 0.0 };
+
+            // Check each configured threshold
+            let checks = [
+                config.qps_above.map(|limit| {
+                    (Rule::Qps, qps > limit, format!("{qps:.1} queries per second (threshold {limit})"))
+                }),
+                config.servfail_rate_above.map(|limit| {
+                    (
+                        Rule::ServfailRate,
+                        queries >= config.min_queries && servfail_rate > limit,
+                        format!("{:.2}% of {queries} queries answered with SERVFAIL (threshold {:.2}%)", servfail_rate * 100.0, limit * 100.0),
+                    )
+                }),
+                config.upstream_failures_above.map(|limit| {
+                    (
+                        Rule::UpstreamFailures,
+                        upstream_failures > limit,
+                        format!("{upstream_failures} upstream lookups failed (threshold {limit})"),
+                    )
+                }),
+            ];
+
+            for (state, check) in states.iter_mut().zip(checks) {
+                let Some((rule, crossed, summary)) = check else {
+                    continue;
+                };
+
+                // Alert when the threshold is first crossed and again after every cooldown, and say when it clears
+                let due = state.last_sent.is_none_or(|sent| sent.elapsed() >= cooldown);
+                if crossed && (!state.firing || due) {
+                    send(&client, &config, &source, rule, true, &summary).await;
+                    state.last_sent = Some(Instant::now());
+                } else if !crossed && state.firing {
+                    send(&client, &config, &source, rule, false, &summary).await;
+                    state.last_sent = None;
+                }
+                state.firing = crossed;
+            }
+        }
+    });
+}
+
+/*
+Description:
+POSTs an alert, or the news that it cleared, to the webhook. Failures are logged and otherwise ignored; the alert is tried again at the next check if it is still firing and due.
+
+Parameters:
+client: the HTTP client.
+config: the [alerts] section, giving the webhook, format and PagerDuty routing key.
+source: the name the server reports itself as.
+rule: the threshold concerned.
+firing: true when the threshold was crossed, false when it cleared.
+summary: what was measured.
+
+Returns:
+None
+*/
+async fn send(client: &reqwest::Client, config: &AlertsConfig, source: &str, rule: Rule, firing: bool, summary: &str) {
+    let payload = match config.format {
+        WebhookFormat::Slack => {
+            let status = if firing { "ALERT" } else { "RESOLVED" };
+            json!({ "text": format!("[{status}] {source}: {summary}") })
+        }
+        WebhookFormat::Pagerduty => json!({
+            "routing_key": config.routing_key,
+            "event_action": if firing { "trigger" } else { "resolve" },
+            "dedup_key": format!("rusty-dns/{source}/{}", rule.name()),
+            "payload": {
+                "summary": format!("{source}: {summary}"),
+                "source": source,
+                "severity": "critical",
+            },
+        }),
+    };
+
+    let result = client.post(&config.webhook).json(&payload).send().await.and_then(|response| response.error_for_status());
+    match result {
+        Ok(_) => info!("Sent {} alert ({}): {summary}", rule.name(), if firing { "firing" } else { "resolved" }),
+        Err(error) => warn!("Failed to send {} alert to the webhook: {error}", rule.name()),
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+use trust_dns_server::{
+    client::rr::Name,
+    proto::op::Message,
+};
+
+/*
+Description:
+strips personal data from what the server logs. Client addresses are truncated to their network (a /24 for IPv4 and a /48 for IPv6 by default), and queried names can be replaced by a keyed hash whose salt rotates, so the same name can be counted within one salt period but not traced back to what was asked, or linked across periods.
+*/
+#[derive(Debug)]
+pub struct Anonymizer {
+    // The served domain, kept in hashed names so the zone stays visible
+    domain: String,
+
+    // The number of leading bits kept of IPv4 addresses
+    ipv4_prefix: u8,
+
+    // The number of leading bits kept of IPv6 addresses
+    ipv6_prefix: u8,
+
+    // Whether queried names are hashed
+    hash_qnames: bool,
+
+    // How long a salt is used before it is replaced
+    salt_rotation: Duration,
+
+    // The current salt and when it was created
+    salt: RwLock<(RandomState, Instant)>,
+}
+
+impl Anonymizer {
+    /*
+    Description:
+    creates an anonymizer with a fresh random salt.
+
+    Parameters:
+    domain: the served domain.
+    ipv4_prefix: the number of leading bits kept of IPv4 addresses, at most 32.
+    ipv6_prefix: the number of leading bits kept of IPv6 addresses, at most 128.
+    hash_qnames: whether queried names are hashed.
+    salt_rotation: how long a salt is used before it is replaced.
+
+    Returns:
+    A new Anonymizer.
+    */
+    pub fn new(domain: &str, ipv4_prefix: u8, ipv6_prefix: u8, hash_qnames: bool, salt_rotation: Duration) -> Self {
+        Anonymizer {
+            domain: domain.trim_end_matches('.').to_lowercase(),
+            ipv4_prefix: ipv4_prefix.min(32),
+            ipv6_prefix: ipv6_prefix.min(128),
+            hash_qnames,
+            salt_rotation,
+            salt: RwLock::new((RandomState::new(), Instant::now())),
+        }
+    }
+
+    /*
+    Description:
+    truncates a client address to its network.
+
+    Parameters:
+    ip: the client address.
+
+    Returns:
+    The address with every bit past the configured prefix cleared, e.g. 192.0.2.0 for 192.0.2.77.
+    */
+    pub fn ip(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - self.ipv4_prefix as u32).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask))
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX.checked_shl(128 - self.ipv6_prefix as u32).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+            }
+        }
+    }
+
+    /*
+    Description:
+    hashes the part of a queried name that may carry personal data. For names in the served domain the zone keyword is kept and its arguments are replaced by one hashed label (e.g. age.1990-05-17.mentisnovae.tech. becomes age.3fa2c1d09b7e4a15.mentisnovae.tech.), and names without arguments are kept as they are. Other names are hashed whole. Names are returned unchanged if qname hashing is off.
+
+    Parameters:
+    qname: the queried name.
+
+    Returns:
+    The name to log.
+    */
+    pub fn qname(&self, qname: &str) -> String {
+        if !self.hash_qnames {
+            return qname.to_string();
+        }
+
+        let name = qname.trim_end_matches('.').to_lowercase();
+        let Some(labels) = name.strip_suffix(&self.domain).and_then(|labels| labels.strip_suffix('.')) else {
+            return if name == self.domain { qname.to_string() } else { format!("{:016x}.", self.hash(&name)) };
+        };
+
+        match labels.split_once('.') {
+            Some((keyword, arguments)) => format!("{keyword}.{:016x}.{}.", self.hash(arguments), self.domain),
+            None => qname.to_string(),
+        }
+    }
+
+    /*
+    Description:
+    anonymizes a DNS message for dnstap. Only the header and question are kept, with the queried name hashed if qname hashing is on; answers, EDNS options such as the client subnet, and every other section are dropped, since they may repeat the client's address or the data it asked about.
+
+    Parameters:
+    bytes: the message in wire format.
+
+    Returns:
+    The anonymized message in wire format, or None if the message could not be decoded.
+    */
+    pub fn message(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        let message = Message::from_vec(bytes).ok()?;
+
+        let mut anonymized = Message::new();
+        anonymized.set_header(*message.header());
+        for query in message.queries() {
+            let mut query = query.clone();
+            if self.hash_qnames {
+                if let Ok(name) = Name::from_str(&self.qname(&query.name().to_string())) {
+                    query.set_name(name);
+                }
+            }
+            anonymized.add_query(query);
+        }
+
+        anonymized.to_vec().ok()
+    }
+
+    // Hashes text with the current salt, replacing the salt first if it has expired
+    fn hash(&self, text: &str) -> u64 {
+        if self.salt.read().unwrap().1.elapsed() >= self.salt_rotation {
+            let mut salt = self.salt.write().unwrap();
+            if salt.1.elapsed() >= self.salt_rotation {
+                *salt = (RandomState::new(), Instant::now());
+            }
+        }
+
+        self.salt.read().unwrap().0.hash_one(text)
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use std::f64::consts::PI;
+
+// The mean length of a lunar cycle (new moon to new moon) in days.
+const SYNODIC_MONTH: f64 = 29.530588853;
+
+/*
+Description:
+the phase of the moon at a given moment.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct MoonPhase {
+    // The conventional name of the phase, e.g. "Waxing Gibbous"
+    pub name: &'static str,
+
+    // The illuminated fraction of the moon's disc, as a percentage
+    pub illumination: f64,
+
+    // The number of days since the last new moon
+    pub age: f64,
+}
+
+/*
+Description:
+computes the phase of the moon at a given moment. The moon's age is measured from a known new moon (2000-01-06 18:14 UTC) using the mean synodic month, which is accurate to within a few hours and plenty for naming the phase. The illuminated fraction follows from the phase angle as (1 - cos(angle)) / 2.
+
+Parameters:
+at: the moment, in UTC, for which to compute the phase.
+
+Returns:
+The MoonPhase at the given moment.
+*/
+pub fn moon_phase(at: NaiveDateTime) -> MoonPhase {
+    // A known new moon to count lunar cycles from
+    let reference = NaiveDate::from_ymd_opt(2000, 1, 6)
+        .unwrap()
+        .and_hms_opt(18, 14, 0)
+        .unwrap();
+
+    // Days since the reference new moon, folded into the current cycle
+    let days = (at - reference).num_seconds() as f64 / 86400.0;
+    let age = days.rem_euclid(SYNODIC_MONTH);
+
+    // How far through the cycle we are, from 0 (new) through 0.5 (full) back to 1 (new)
+    let fraction = age / SYNODIC_MONTH;
+
+    // The illuminated fraction of the disc
+    let illumination = (1.0 - (2.0 * PI * fraction).cos()) / 2.0 * 100.0;
+
+    // Name the phase, giving each of the eight phases an equal slice of the cycle
+    let name = match fraction {
+        f if f < 1.0 / 16.0 => "New Moon",
+        f if f < 3.0 / 16.0 => "Waxing Crescent",
+        f if f < 5.0 / 16.0 => "First Quarter",
+        f if f < 7.0 / 16.0 => "Waxing Gibbous",
+        f if f < 9.0 / 16.0 => "Full Moon",
+        f if f < 11.0 / 16.0 => "Waning Gibbous",
+        f if f < 13.0 / 16.0 => "Last Quarter",
+        f if f < 15.0 / 16.0 => "Waning Crescent",
+        _ => "New Moon",
+    };
+
+    MoonPhase {
+        name,
+        illumination,
+        age,
+    }
+}
+
+/*
+Description:
+when the sun rises and sets on a given day, or why it doesn't.
+*/
+#[derive(Clone, Copy, Debug)]
+pub enum SunTimes {
+    // The sun rises and sets at the given moments (UTC)
+    RiseAndSet(NaiveDateTime, NaiveDateTime),
+
+    // The sun stays above the horizon all day
+    PolarDay,
+
+    // The sun stays below the horizon all day
+    PolarNight,
+}
+
+/*
+Description:
+computes sunrise and sunset for a location on a given day using the sunrise equation (the simplified NOAA solar position formulas). The results are typically within a minute or two of published tables, which is all a TXT answer needs. Sunrise and sunset are taken as the moments the sun's upper limb touches the horizon, including atmospheric refraction (-0.833 degrees).
+
+Parameters:
+date: the day (UTC) for which to compute the times.
+latitude: the latitude of the location in degrees, north positive.
+longitude: the longitude of the location in degrees, east positive.
+
+Returns:
+SunTimes with the sunrise and sunset in UTC, or PolarDay/PolarNight if the sun does not cross the horizon that day.
+*/
+pub fn sun_times(date: NaiveDate, latitude: f64, longitude: f64) -> SunTimes {
+    // Days since the J2000 epoch (2000-01-01 12:00 UTC) at noon of the requested day
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+    let n = (date - epoch).num_days() as f64;
+
+    // Mean solar noon at this longitude
+    let mean_noon = n - longitude / 360.0;
+
+    // Solar mean anomaly
+    let anomaly = (357.5291 + 0.98560028 * mean_noon).rem_euclid(360.0);
+    let m = anomaly.to_radians();
+
+    // Equation of the center
+    let center = 1.9148 * m.sin() + 0.0200 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+
+    // Ecliptic longitude of the sun
+    let ecliptic = (anomaly + center + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+
+    // Solar transit (true solar noon) as a Julian day
+    let transit = 2451545.0 + mean_noon + 0.0053 * m.sin() - 0.0069 * (2.0 * ecliptic).sin();
+
+    // Declination of the sun
+    let declination = (ecliptic.sin() * 23.4397_f64.to_radians().sin()).asin();
+
+    // Hour angle at which the sun crosses the horizon
+    let phi = latitude.to_radians();
+    let cos_hour_angle = ((-0.833_f64).to_radians().sin() - phi.sin() * declination.sin())
+        / (phi.cos() * declination.cos());
+
+    // The sun never crosses the horizon at high latitudes around the solstices
+    if cos_hour_angle < -1.0 {
+        return SunTimes::PolarDay;
+    }
+    if cos_hour_angle > 1.0 {
+        return SunTimes::PolarNight;
+    }
+
+    // Sunrise and sunset are symmetric around the solar transit
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    let sunrise = julian_to_datetime(transit - hour_angle / 360.0);
+    let sunset = julian_to_datetime(transit + hour_angle / 360.0);
+
+    SunTimes::RiseAndSet(sunrise, sunset)
+}
+
+// Converts a Julian day to a UTC date and time, rounded to the second
+fn julian_to_datetime(julian_day: f64) -> NaiveDateTime {
+    // The Unix epoch is Julian day 2440587.5
+    let seconds = ((julian_day - 2440587.5) * 86400.0).round() as i64;
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    epoch + chrono::Duration::seconds(seconds)
+}
@@ -0,0 +1,107 @@
+use serde::Serialize;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+use tracing::*;
+
+/*
+Description:
+one audited action: who did what, when, from where, and how it ended.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditEntry {
+    // When the action happened, in RFC 3339 format
+    pub when: String,
+
+    // Who performed the action, e.g. "admin-api" for the unauthenticated admin API, "SIGHUP" or a client address
+    pub who: String,
+
+    // Where the action came from, e.g. the admin client's address or "local"
+    pub from: String,
+
+    // What was done, e.g. "GET", "config-reload" or "update"
+    pub what: String,
+
+    // What it was done to, e.g. an admin API path, the config file or a zone
+    pub target: String,
+
+    // How it ended, e.g. "200", "ok" or "refused"
+    pub outcome: String,
+}
+
+impl AuditEntry {
+    /*
+    Description:
+    creates an audit entry stamped with the current time.
+
+    Parameters:
+    who, from, what, target, outcome: see the fields of AuditEntry.
+
+    Returns:
+    A new AuditEntry.
+    */
+    pub fn now(
+        who: impl Into<String>,
+        from: impl Into<String>,
+        what: impl Into<String>,
+        target: impl Into<String>,
+        outcome: impl Into<String>,
+    ) -> Self {
+        AuditEntry {
+            when: chrono::Utc::now().to_rfc3339(),
+            who: who.into(),
+            from: from.into(),
+            what: what.into(),
+            target: target.into(),
+            outcome: outcome.into(),
+        }
+    }
+}
+
+/*
+Description:
+an append-only audit log of administrative actions: admin API requests, config reloads and dynamic update attempts. Every entry is written as one JSON line straight to a file opened in append mode, so entries are never rewritten and reach the file as soon as they happen.
+*/
+#[derive(Debug)]
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /*
+    Description:
+    opens the audit log, creating it if needed.
+
+    Parameters:
+    path: the log file.
+
+    Returns:
+    Ok(AuditLog) if the file could be opened for appending, or an io::Error.
+    */
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog { file: Mutex::new(file) })
+    }
+
+    /*
+    Description:
+    appends an entry to the log. A failed write is reported in the server log but doesn't fail the action.
+
+    Parameters:
+    entry: the action to record.
+
+    Returns:
+    None
+    */
+    pub fn record(&self, entry: AuditEntry) {
+        let mut line = serde_json::to_vec(&entry).unwrap_or_default();
+        line.push(b'\n');
+
+        if let Err(error) = self.file.lock().unwrap().write_all(&line) {
+            error!("Failed to write audit log entry: {error}");
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::{DateTime, Utc};
+use ring::{digest, hmac};
+use std::collections::BTreeMap;
+
+// The SHA-256 digest of an empty body, which AWS is told a GET request has.
+const EMPTY_PAYLOAD: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/*
+Description:
+the credentials AWS requests are signed with, as the AWS command-line tools read them from the environment.
+*/
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    // The access key ID
+    pub access_key: String,
+
+    // The secret access key
+    pub secret_key: String,
+
+    // The session token of temporary credentials, if any
+    pub session_token: Option<String>,
+}
+
+impl Credentials {
+    // Returns the credentials in AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN, or None if there are none
+    pub fn from_env() -> Option<Self> {
+        let variable = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Some(Credentials {
+            access_key: variable("AWS_ACCESS_KEY_ID")?,
+            secret_key: variable("AWS_SECRET_ACCESS_KEY")?,
+            session_token: variable("AWS_SESSION_TOKEN"),
+        })
+    }
+}
+
+/*
+Description:
+signs a GET request to an AWS API with Signature Version 4, adding the x-amz-* headers and the Authorization header to it.
+
+Parameters:
+client: the HTTP client to send the request with.
+url: the request's URL, whose path is URI-encoded with uri_encode.
+credentials: the credentials to sign with.
+region: the region the request is for, e.g. us-east-1.
+service: the service the request is for, e.g. s3 or route53.
+now: the time of the request.
+
+Returns:
+The signed request.
+*/
+pub fn sign(client: &reqwest::Client, url: reqwest::Url, credentials: &Credentials, region: &str, service: &str, now: DateTime<Utc>) -> reqwest::RequestBuilder {
+    let host = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let query: Vec<(String, String)> = url.query_pairs().map(|(name, value)| (name.into_owned(), value.into_owned())).collect();
+    let query: Vec<(&str, &str)> = query.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+    let path = url.path().to_string();
+
+    let mut headers = BTreeMap::from([
+        ("host".to_string(), host),
+        ("x-amz-content-sha256".to_string(), EMPTY_PAYLOAD.to_string()),
+        ("x-amz-date".to_string(), now.format("%Y%m%dT%H%M%SZ").to_string()),
+    ]);
+    if let Some(token) = &credentials.session_token {
+        headers.insert("x-amz-security-token".to_string(), token.clone());
+    }
+    let authorization = authorization(credentials, region, service, &path, &canonical_query(&query), &headers);
+    headers
+        .into_iter()
+        .filter(|(name, _)| name != "host")
+        .fold(client.get(url), |request, (name, value)| request.header(name, value))
+        .header(reqwest::header::AUTHORIZATION, authorization)
+}
+
+/*
+Description:
+computes the Authorization header of a GET request without a body to an AWS API with Signature Version 4.
+
+Parameters:
+credentials: the credentials to sign with.
+region: the region the request is for, e.g. us-east-1.
+service: the service the request is for, e.g. s3.
+path: the request's path, URI-encoded, e.g. /db.example.com.
+query: the request's canonical query string, as canonical_query builds it, or "" if it has none.
+headers: the headers to sign, with lower-case names: host, x-amz-date and x-amz-content-sha256 at least.
+
+Returns:
+The value of the Authorization header.
+*/
+pub fn authorization(credentials: &Credentials, region: &str, service: &str, path: &str, query: &str, headers: &BTreeMap<String, String>) -> String {
+    let amz_date = headers.get("x-amz-date").map(String::as_str).unwrap_or_default();
+    let date = amz_date.get(..8).unwrap_or_default();
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{name}:{}\n", value.trim())).collect();
+    let signed_headers = headers.keys().map(String::as_str).collect::<Vec<_>>().join(";");
+    let payload = headers.get("x-amz-content-sha256").map(String::as_str).unwrap_or(EMPTY_PAYLOAD);
+    let canonical_request = format!("GET\n{path}\n{query}\n{canonical_headers}\n{signed_headers}\n{payload}");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+    );
+
+    let key = [date, region, service, "aws4_request"]
+        .iter()
+        .fold(format!("AWS4{}", credentials.secret_key).into_bytes(), |key, part| {
+            hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), part.as_bytes()).as_ref().to_vec()
+        });
+    let signature = hex(hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), string_to_sign.as_bytes()).as_ref());
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+        credentials.access_key
+    )
+}
+
+// Returns the canonical query string of query parameters: each name and value URI-encoded, sorted and joined with &
+pub fn canonical_query(query: &[(&str, &str)]) -> String {
+    let mut pairs: Vec<String> = query.iter().map(|(name, value)| format!("{}={}", uri_encode(name), uri_encode(value))).collect();
+    pairs.sort();
+    pairs.join("&")
+}
+
+// Percent-encodes a path segment or query parameter as AWS signatures expect: everything but letters, digits and -._~
+pub fn uri_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+// Returns bytes in lower-case hexadecimal
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
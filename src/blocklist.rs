@@ -0,0 +1,468 @@
+use crate::{
+    config::{BlockResponse, BlocklistConfig},
+    tasks,
+};
+use memmap2::Mmap;
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{self, BufWriter},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+use tokio::sync::{watch, Notify};
+use tracing::*;
+
+// The largest number of clients whose block counts are remembered at once.
+// When the map is full, the client with the fewest blocked queries is forgotten to make room.
+const MAX_CLIENTS: usize = 10_000;
+
+// How long a blocklist download may take.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/*
+Description:
+a Pi-hole-style blocklist. Names from hosts-format or plain domain-list files and URLs are kept in a set, and a query is blocked if its name or any parent domain is listed, so listing "ads.example" also blocks "tracker.ads.example". Allowlisted names and clients in an exempt group are never blocked. The lists are refreshed in the background and swapped in atomically, so queries never see a half-loaded list, and the whole configuration can be replaced at runtime with reload.
+*/
+#[derive(Debug)]
+pub struct Blocklist {
+    // The blocked domains, lower-case and without the trailing dot
+    domains: RwLock<Arc<HashSet<String>>>,
+
+    // The compiled list, if one is configured and could be mapped
+    compiled: RwLock<Option<Arc<CompiledList>>>,
+
+    // The current configuration (sources, response, allowlist and exemptions)
+    config: RwLock<Arc<BlocklistConfig>>,
+
+    // Wakes the refresh task up early after a reload
+    refresh_now: Notify,
+
+    // Set once the sources have been loaded for the first time
+    loaded: watch::Sender<bool>,
+
+    // The number of blocked queries per client
+    clients: Mutex<HashMap<IpAddr, u64>>,
+
+    // The total number of blocked queries
+    blocked: AtomicU64,
+}
+
+/*
+Description:
+a snapshot of the blocklist statistics for the admin API.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct BlocklistStats {
+    // The number of blocked domains
+    pub domains: usize,
+
+    // The total number of blocked queries
+    pub blocked: u64,
+
+    // Blocked queries per client, busiest first
+    pub clients: Vec<(IpAddr, u64)>,
+}
+
+impl Blocklist {
+    /*
+    Description:
+    creates an empty blocklist and spawns the task that loads the sources and refreshes them every refresh interval. Must be called from within the tokio runtime.
+
+    Parameters:
+    config: the [blocklist] section of the configuration file.
+
+    Returns:
+    An Arc holding the new Blocklist, shared with the refresh task.
+    */
+    pub fn spawn(config: &BlocklistConfig) -> Arc<Self> {
+        let blocklist = Arc::new(Blocklist {
+            domains: RwLock::new(Arc::new(HashSet::new())),
+            compiled: RwLock::new(None),
+            config: RwLock::new(Arc::new(config.clone())),
+            refresh_now: Notify::new(),
+            loaded: watch::channel(false).0,
+            clients: Mutex::new(HashMap::new()),
+            blocked: AtomicU64::new(0),
+        });
+
+        tasks::spawn("blocklist-refresh", refresh(
+            blocklist.clone(),
+            Duration::from_secs(config.refresh_interval.max(60)),
+        ));
+
+        blocklist
+    }
+
+    /*
+    Description:
+    replaces the blocklist configuration at runtime, e.g. after the config file was edited. The new allowlist, exemptions and response take effect immediately, and the sources are reloaded right away. The refresh interval is fixed at startup.
+
+    Parameters:
+    config: the new [blocklist] section.
+
+    Returns:
+    None
+    */
+    pub fn reload(&self, config: &BlocklistConfig) {
+        *self.config.write().unwrap() = Arc::new(config.clone());
+        self.refresh_now.notify_one();
+    }
+
+    /*
+    Description:
+    waits until the refresh task has loaded the sources for the first time, for tools that check names against the blocklist right after starting it. Sources that fail to load count as loaded.
+
+    Parameters:
+    None
+
+    Returns:
+    None
+    */
+    pub async fn wait_loaded(&self) {
+        let mut loaded = self.loaded.subscribe();
+        while !*loaded.borrow_and_update() {
+            if loaded.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /*
+    Description:
+    returns how blocked names are answered.
+
+    Parameters:
+    None
+
+    Returns:
+    The configured BlockResponse.
+    */
+    pub fn response(&self) -> BlockResponse {
+        self.config.read().unwrap().response
+    }
+
+    /*
+    Description:
+    checks whether a query should be blocked: the client must not be in an exempt group, the name must not be allowlisted, and the name or one of its parent domains must be listed.
+
+    Parameters:
+    name: the query name, with or without the trailing dot.
+    client: the IP address of the client asking.
+
+    Returns:
+    true if the query is blocked.
+    */
+    pub fn is_blocked(&self, name: &str, client: IpAddr) -> bool {
+        let domains = self.domains.read().unwrap().clone();
+        let compiled = self.compiled.read().unwrap().clone();
+        if domains.is_empty() && compiled.is_none() {
+            return false;
+        }
+
+        // Exempt clients and allowlisted names are never blocked
+        let config = self.config.read().unwrap().clone();
+        let name = name.trim_end_matches('.');
+        if config.exempt.values().flatten().any(|cidr| cidr.contains(client))
+            || config.allow.iter().any(|entry| allows(entry, name))
+        {
+            return false;
+        }
+
+        // Check the name and then each parent domain
+        let mut name = name;
+        loop {
+            if domains.contains(name) || compiled.as_ref().is_some_and(|list| list.contains(name)) {
+                return true;
+            }
+            match name.split_once('.') {
+                Some((_, parent)) => name = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /*
+    Description:
+    records a blocked query for the per-client statistics.
+
+    Parameters:
+    client: the IP address of the client whose query was blocked.
+
+    Returns:
+    None
+    */
+    pub fn record(&self, client: IpAddr) {
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+
+        let mut clients = self.clients.lock().unwrap();
+
+        // Make room for a new client by forgetting the one with the fewest blocked queries
+        if clients.len() >= MAX_CLIENTS && !clients.contains_key(&client) {
+            if let Some(least_blocked) = clients
+                .iter()
+                .min_by_key(|(_, count)| **count)
+                .map(|(addr, _)| *addr)
+            {
+                clients.remove(&least_blocked);
+            }
+        }
+
+        *clients.entry(client).or_default() += 1;
+    }
+
+    /*
+    Description:
+    returns the blocklist statistics.
+
+    Parameters:
+    limit: the largest number of clients to include.
+
+    Returns:
+    BlocklistStats with the list size, the total block count and the clients with the most blocked queries.
+    */
+    pub fn stats(&self, limit: usize) -> BlocklistStats {
+        let mut clients: Vec<(IpAddr, u64)> = self
+            .clients
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(client, count)| (*client, *count))
+            .collect();
+        clients.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        clients.truncate(limit);
+
+        BlocklistStats {
+            domains: self.domains.read().unwrap().len() + self.compiled.read().unwrap().as_ref().map_or(0, |list| list.len()),
+            blocked: self.blocked.load(Ordering::Relaxed),
+            clients,
+        }
+    }
+}
+
+// Checks an allowlist entry (or another name pattern, such as a webhook's watched names) against a name: "*.example.com" covers example.com and everything below it, anything else must match exactly
+pub(crate) fn allows(entry: &str, name: &str) -> bool {
+    let entry = entry.trim_end_matches('.').to_ascii_lowercase();
+    let name = name.to_ascii_lowercase();
+    match entry.strip_prefix("*.") {
+        Some(suffix) => name == suffix || name.ends_with(&format!(".{suffix}")),
+        None => name == entry,
+    }
+}
+
+/*
+Description:
+the refresh task. It loads every configured source, swaps the combined list in, and repeats every interval or as soon as the configuration is reloaded. A source that fails to load keeps its previously loaded entries, so a flaky download doesn't suddenly unblock everything it listed; sources removed from the configuration are dropped.
+
+Parameters:
+blocklist: the blocklist to fill.
+interval: how often to reload the sources.
+
+Returns:
+None
+*/
+async fn refresh(blocklist: Arc<Blocklist>, interval: Duration) {
+    let client = http_client();
+    let mut loaded: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        // Wait for the next refresh, or for a reload
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = blocklist.refresh_now.notified() => {}
+        }
+
+        // Forget sources that are no longer configured
+        let sources = blocklist.config.read().unwrap().sources.clone();
+        loaded.retain(|source, _| sources.contains(source));
+
+        // Load each source, keeping the previous entries of sources that fail
+        for source in &sources {
+            match load(&client, source).await {
+                Ok(domains) => {
+                    info!("Loaded {} blocked domains from {source}", domains.len());
+                    loaded.insert(source.clone(), domains);
+                }
+                Err(error) => warn!("Failed to load blocklist {source}: {error}"),
+            }
+        }
+
+        // Swap in the combined list
+        let combined: HashSet<String> = loaded.values().flatten().cloned().collect();
+        *blocklist.domains.write().unwrap() = Arc::new(combined);
+
+        // Map the compiled list again, so a recompiled file is picked up, keeping the previous mapping if it fails
+        let compiled = blocklist.config.read().unwrap().compiled.clone();
+        match compiled {
+            None => *blocklist.compiled.write().unwrap() = None,
+            Some(path) => match CompiledList::open(&path) {
+                Ok(list) => {
+                    info!("Mapped {} blocked domains from {}", list.len(), path.display());
+                    *blocklist.compiled.write().unwrap() = Some(Arc::new(list));
+                }
+                Err(error) => warn!("Failed to map compiled blocklist {}: {error}", path.display()),
+            },
+        }
+        blocklist.loaded.send_replace(true);
+    }
+}
+
+// Builds the HTTP client that downloads blocklists
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+/*
+Description:
+loads blocklist sources the way the refresh task does, for the blocklist compile subcommand.
+
+Parameters:
+sources: the files and http(s) URLs to load.
+
+Returns:
+Ok(Vec) with each source and its domains, or an error naming the first source that failed to load.
+*/
+pub async fn load_sources(sources: &[String]) -> Result<Vec<(String, HashSet<String>)>, String> {
+    let client = http_client();
+    let mut loaded = Vec::new();
+    for source in sources {
+        let domains = load(&client, source).await.map_err(|error| format!("Failed to load blocklist {source}: {error}"))?;
+        loaded.push((source.clone(), domains));
+    }
+    Ok(loaded)
+}
+
+// Loads a single source, from a URL if it starts with http:// or https:// and from a file otherwise
+async fn load(client: &reqwest::Client, source: &str) -> Result<HashSet<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let text = if source.starts_with("http://") || source.starts_with("https://") {
+        client.get(source).send().await?.error_for_status()?.text().await?
+    } else {
+        tokio::fs::read_to_string(source).await?
+    };
+
+    Ok(parse(&text))
+}
+
+/*
+Description:
+parses a blocklist in hosts format ("0.0.0.0 ads.example") or domain-list format ("ads.example"). Comments starting with '#' and blank lines are skipped, as are the localhost entries found at the top of most hosts files.
+
+Parameters:
+text: the contents of the blocklist.
+
+Returns:
+A HashSet of the listed domains, lower-case and without a trailing dot.
+*/
+pub fn parse(text: &str) -> HashSet<String> {
+    text.lines()
+        .filter_map(|line| {
+            // Drop comments and surrounding whitespace
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let mut fields = line.split_whitespace();
+
+            // Hosts format has an address before the name
+            let first = fields.next()?;
+            let domain = match fields.next() {
+                Some(domain) if first.parse::<IpAddr>().is_ok() => domain,
+                Some(_) => return None,
+                None => first,
+            };
+
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            let is_local = matches!(
+                domain.as_str(),
+                "localhost" | "localhost.localdomain" | "local" | "broadcasthost" | "ip6-localhost" | "ip6-loopback"
+            ) || domain.parse::<IpAddr>().is_ok();
+
+            (!domain.is_empty() && !is_local).then_some(domain)
+        })
+        .collect()
+}
+
+/*
+Description:
+a blocklist compiled by `rusty-dns blocklist compile`: the domains stored as a finite-state transducer set, a minimized trie sharing both prefixes and suffixes, memory-mapped from its file. A list of a million domains takes a few megabytes, loads without being parsed, and its pages are shared with the page cache instead of copied onto the heap.
+*/
+pub struct CompiledList {
+    // The mapped set of domains, lower-case and without the trailing dot
+    set: fst::Set<Mmap>,
+}
+
+impl CompiledList {
+    /*
+    Description:
+    maps a compiled blocklist file and checks that it is one.
+
+    Parameters:
+    path: the file written by compile.
+
+    Returns:
+    Ok(CompiledList), or an error if the file could not be mapped or is not a compiled blocklist.
+    */
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let file = File::open(path)?;
+
+        // SAFETY: compile never writes a list in place, it renames a new file over the old one, so the mapped contents don't change underneath us.
+        let map = unsafe { Mmap::map(&file)? };
+        let set = fst::Set::new(map).map_err(|_| "not a compiled blocklist")?;
+        Ok(CompiledList { set })
+    }
+
+    // Returns whether a domain, lower-case and without the trailing dot, is in the list
+    pub fn contains(&self, domain: &str) -> bool {
+        self.set.contains(domain)
+    }
+
+    // Returns the number of domains in the list
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    // Returns whether the list is empty
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+}
+
+// Shows the list's size rather than every domain in it
+impl fmt::Debug for CompiledList {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_struct("CompiledList").field("domains", &self.len()).finish()
+    }
+}
+
+/*
+Description:
+compiles blocked domains into a file that CompiledList maps. The file is written next to the destination and renamed over it, so a running server that has the old list mapped keeps reading it intact until it maps the new one.
+
+Parameters:
+domains: the domains, lower-case and without the trailing dot; a BTreeSet since the set has to be built in sorted order.
+path: the file to write.
+
+Returns:
+Ok(u64) with the size of the file in bytes, or an io::Error if it could not be written.
+*/
+pub fn compile(domains: &BTreeSet<String>, path: &Path) -> io::Result<u64> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut builder = fst::SetBuilder::new(BufWriter::new(File::create(&temporary)?)).map_err(io::Error::other)?;
+    builder.extend_iter(domains).map_err(io::Error::other)?;
+    let file = builder.into_inner().map_err(io::Error::other)?.into_inner().map_err(|error| error.into_error())?;
+    file.sync_all()?;
+
+    std::fs::rename(&temporary, path)?;
+    file.metadata().map(|metadata| metadata.len())
+}
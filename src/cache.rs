@@ -0,0 +1,312 @@
+use lru::LruCache;
+use serde::Serialize;
+use std::{
+    io,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use trust_dns_server::{
+    authority::MessageResponse,
+    client::rr::{LowerName, Record, RecordType},
+    proto::{
+        op::{Edns, Message, ResponseCode},
+        serialize::binary::BinEncoder,
+    },
+    server::{ResponseHandler, ResponseInfo},
+};
+
+/*
+Description:
+an answer kept in the response cache: everything about it that doesn't depend on the query it answered, so it can be sent again to the next client asking the same question.
+*/
+#[derive(Debug)]
+pub struct CachedResponse {
+    // The response code of the answer
+    pub response_code: ResponseCode,
+
+    // Whether the answer was authoritative
+    pub authoritative: bool,
+
+    // The records of the answer, authority and additional sections
+    pub answers: Vec<Record>,
+    pub name_servers: Vec<Record>,
+    pub additionals: Vec<Record>,
+
+    // The EDNS record of the answer, such as one carrying an Extended DNS Error, if the query had EDNS
+    pub edns: Option<Edns>,
+
+    // When the entry stops being served
+    expires: Instant,
+}
+
+/*
+Description:
+the size and hit rate of the response cache or the name cache in a StatsDump.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct CacheStats {
+    // The number of entries cached
+    pub entries: usize,
+
+    // The number of lookups answered from the cache
+    pub hits: u64,
+
+    // The number of lookups that had to be computed
+    pub misses: u64,
+}
+
+/*
+Description:
+an in-process cache of answers from zones whose answer depends only on the question, such as cidr, str or fib, keyed on the query name and type and on whether the query has EDNS, since only answers to EDNS queries carry an OPT record. Names are matched exactly as the client wrote them, since some zones (e.g. str) preserve the case of their arguments. An entry is kept for the smallest TTL of its records, but never longer than the configured limit, and the least recently used entry is evicted when the cache is full.
+*/
+#[derive(Debug)]
+pub struct ResponseCache {
+    // The cached answers
+    entries: Mutex<LruCache<(String, RecordType, bool), Arc<CachedResponse>>>,
+
+    // The longest an answer is cached, whatever its TTL
+    max_ttl: Duration,
+
+    // The number of queries answered from the cache and computed
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    /*
+    Description:
+    creates an empty response cache.
+
+    Parameters:
+    capacity: the largest number of answers cached at once; 0 is treated as 1.
+    max_ttl: the longest an answer is cached.
+
+    Returns:
+    A new ResponseCache.
+    */
+    pub fn new(capacity: usize, max_ttl: Duration) -> Self {
+        ResponseCache {
+            entries: Mutex::new(LruCache::new(NonZeroUsize::new(capacity.max(1)).unwrap())),
+            max_ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /*
+    Description:
+    looks up the cached answer to a question, counting the lookup as a hit or a miss. Expired answers are removed and count as misses.
+
+    Parameters:
+    name: the query name, as the client wrote it.
+    query_type: the query type.
+    edns: whether the query has EDNS.
+
+    Returns:
+    Some(CachedResponse) if a fresh answer is cached, None otherwise.
+    */
+    pub fn get(&self, name: &str, query_type: RecordType, edns: bool) -> Option<Arc<CachedResponse>> {
+        let key = (name.to_string(), query_type, edns);
+        let mut entries = self.entries.lock().unwrap();
+        let cached = match entries.get(&key) {
+            Some(cached) if cached.expires > Instant::now() => Some(cached.clone()),
+            Some(_) => {
+                entries.pop(&key);
+                None
+            }
+            None => None,
+        };
+        drop(entries);
+
+        let counter = if cached.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    /*
+    Description:
+    caches an answer captured from a zone handler.
+
+    Parameters:
+    name: the query name, as the client wrote it.
+    query_type: the query type.
+    edns: whether the query has EDNS.
+    bytes: the answer in wire format, as captured by a CaptureResponder.
+
+    Returns:
+    Some(CachedResponse) with the decoded answer, cached unless one of its records has a TTL of 0; or None if the answer could not be decoded.
+    */
+    pub fn insert(&self, name: &str, query_type: RecordType, edns: bool, bytes: &[u8]) -> Option<Arc<CachedResponse>> {
+        let mut message = Message::from_vec(bytes).ok()?;
+
+        // Keep the answer for as long as every one of its records may be cached
+        let ttl = message
+            .answers()
+            .iter()
+            .chain(message.name_servers())
+            .chain(message.additionals())
+            .map(|record| Duration::from_secs(record.ttl().into()))
+            .min()
+            .unwrap_or(self.max_ttl)
+            .min(self.max_ttl);
+
+        let cached = Arc::new(CachedResponse {
+            response_code: message.response_code(),
+            authoritative: message.authoritative(),
+            answers: message.take_answers(),
+            name_servers: message.take_name_servers(),
+            additionals: message.take_additionals(),
+            edns: message.extensions_mut().take(),
+            expires: Instant::now() + ttl,
+        });
+        if !ttl.is_zero() {
+            self.entries.lock().unwrap().put((name.to_string(), query_type, edns), cached.clone());
+        }
+        Some(cached)
+    }
+
+    // Returns the size and hit rate of the cache
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.lock().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/*
+Description:
+a query name broken down once: the zone it belongs to and the pieces the zone handlers take it apart into. All of it is derived from the lowercased name.
+*/
+#[derive(Debug)]
+pub struct ParsedName {
+    // The zone the name belongs to, as Handler::zone_name works it out
+    pub zone: Option<String>,
+
+    // Whether the zone is a fixed zone, matched on its suffix, rather than a keyword zone matched on the first label
+    pub fixed: bool,
+
+    // The name as text, e.g. "cidr.10.0.0.0.8.mentisnovae.tech."
+    pub text: String,
+
+    // The text split on dots, e.g. ["cidr", "10", "0", "0", "0", "8", "mentisnovae", "tech", ""]
+    pub labels: Vec<String>,
+
+    // The arguments of the zone: the labels below a fixed zone, or those between the keyword and the root domain of a keyword zone
+    pub args: Vec<String>,
+}
+
+impl ParsedName {
+    // Creates a parsed name from its zone, text and arguments, splitting the text into labels
+    pub fn new(zone: Option<String>, fixed: bool, text: String, args: Vec<String>) -> Self {
+        let labels = text.split('.').map(str::to_string).collect();
+        ParsedName {
+            zone,
+            fixed,
+            text,
+            labels,
+            args,
+        }
+    }
+}
+
+/*
+Description:
+an in-process cache of parsed query names, keyed on the lowercased name, so a popular name is matched to its zone and taken apart once rather than on every query. The least recently used name is evicted when the cache is full; entries never go stale, since the zones are fixed for the handler's lifetime.
+*/
+#[derive(Debug)]
+pub struct NameCache {
+    // The parsed names
+    entries: Mutex<LruCache<LowerName, Arc<ParsedName>>>,
+
+    // The number of lookups answered from the cache and parsed
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl NameCache {
+    // Creates an empty name cache holding up to capacity names; 0 is treated as 1
+    pub fn new(capacity: usize) -> Self {
+        NameCache {
+            entries: Mutex::new(LruCache::new(NonZeroUsize::new(capacity.max(1)).unwrap())),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /*
+    Description:
+    looks up a parsed name, parsing and caching it on a miss.
+
+    Parameters:
+    name: the lowercased query name.
+    parse: parses the name; called outside the cache's lock.
+
+    Returns:
+    The parsed name.
+    */
+    pub fn get_or_parse(&self, name: &LowerName, parse: impl FnOnce() -> ParsedName) -> Arc<ParsedName> {
+        if let Some(parsed) = self.entries.lock().unwrap().get(name) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return parsed.clone();
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let parsed = Arc::new(parse());
+        self.entries.lock().unwrap().put(name.clone(), parsed.clone());
+        parsed
+    }
+
+    // Returns the size and hit rate of the cache
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.lock().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/*
+Description:
+a ResponseHandler that keeps the answer in wire format instead of sending it, so a zone handler's answer can be cached before it is sent to the client.
+*/
+#[derive(Clone, Default)]
+pub struct CaptureResponder {
+    // The captured answer, once the handler has sent it
+    captured: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl CaptureResponder {
+    // Returns the captured answer, if the handler sent one
+    pub fn take(&self) -> Option<Vec<u8>> {
+        self.captured.lock().unwrap().take()
+    }
+}
+
+#[async_trait::async_trait]
+impl ResponseHandler for CaptureResponder {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        let mut bytes = Vec::with_capacity(512);
+        let header = response
+            .destructive_emit(&mut BinEncoder::new(&mut bytes))
+            .map_err(io::Error::other)?;
+        *self.captured.lock().unwrap() = Some(bytes);
+        Ok(header)
+    }
+}
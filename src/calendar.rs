@@ -0,0 +1,192 @@
+use chrono::{Datelike, NaiveDate, SecondsFormat, TimeZone, Utc, Weekday};
+use std::str::FromStr;
+
+/*
+Description:
+calendar facts about a single date: where it falls in the ISO week-numbering year, the ordinal year, and the quarter.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct DateInfo {
+    // The ISO week-numbering year, which can differ from the calendar year around New Year
+    pub iso_year: i32,
+
+    // The ISO week number (1-53)
+    pub iso_week: u32,
+
+    // The day of the week
+    pub weekday: Weekday,
+
+    // The day of the year (1-366)
+    pub day_of_year: u32,
+
+    // The number of days in the calendar year (365 or 366)
+    pub days_in_year: u32,
+
+    // The quarter of the calendar year (1-4)
+    pub quarter: u32,
+}
+
+/*
+Description:
+the ways the time zone writes out a timestamp, chosen by an optional label in front of it.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EpochFormat {
+    // 2023-11-14 22:13:20 UTC
+    #[default]
+    Human,
+
+    // 2023-11-14T22:13:20Z (RFC 3339)
+    Iso,
+
+    // Tue, 14 Nov 2023 22:13:20 +0000 (RFC 2822)
+    Rfc2822,
+
+    // 2023-11-14
+    Date,
+}
+
+impl EpochFormat {
+    // The labels of the formats, in the order they are listed in explanations
+    pub const LABELS: &'static str = "human, iso, rfc2822 or date";
+}
+
+impl FromStr for EpochFormat {
+    type Err = String;
+
+    fn from_str(label: &str) -> Result<Self, Self::Err> {
+        match label {
+            "human" => Ok(EpochFormat::Human),
+            "iso" => Ok(EpochFormat::Iso),
+            "rfc2822" => Ok(EpochFormat::Rfc2822),
+            "date" => Ok(EpochFormat::Date),
+            _ => Err(format!("unknown format {label}, expected {}", EpochFormat::LABELS)),
+        }
+    }
+}
+
+/*
+Description:
+writes out an epoch/unix timestamp in UTC.
+
+Parameters:
+timestamp: the seconds since 1970-01-01 00:00:00 UTC, negative for earlier times.
+format: how to write it.
+
+Returns:
+Some(String) with the date and time, or None if the timestamp is beyond the dates chrono can represent.
+*/
+pub fn format_epoch(timestamp: i64, format: EpochFormat) -> Option<String> {
+    let date_time = Utc.timestamp_opt(timestamp, 0).single()?;
+    Some(match format {
+        EpochFormat::Human => date_time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        EpochFormat::Iso => date_time.to_rfc3339_opts(SecondsFormat::Secs, true),
+        EpochFormat::Rfc2822 => date_time.to_rfc2822(),
+        EpochFormat::Date => date_time.format("%Y-%m-%d").to_string(),
+    })
+}
+
+/*
+Description:
+computes the calendar facts for a date.
+
+Parameters:
+date: the date to describe.
+
+Returns:
+The DateInfo for the date.
+*/
+pub fn date_info(date: NaiveDate) -> DateInfo {
+    // The ISO week and the year it belongs to
+    let iso = date.iso_week();
+
+    // A year has 366 days if December 31st is its 366th day
+    let days_in_year = NaiveDate::from_ymd_opt(date.year(), 12, 31)
+        .map(|last| last.ordinal())
+        .unwrap_or(365);
+
+    DateInfo {
+        iso_year: iso.year(),
+        iso_week: iso.week(),
+        weekday: date.weekday(),
+        day_of_year: date.ordinal(),
+        days_in_year,
+        quarter: (date.month() - 1) / 3 + 1,
+    }
+}
+
+/*
+Description:
+the exact age of someone born on a given date, in calendar years, months and days, and how long it is until their next birthday.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Age {
+    pub years: u32,
+    pub months: u32,
+    pub days: u32,
+
+    // The number of days until the next birthday (0 if it is today)
+    pub days_until_birthday: i64,
+}
+
+/*
+Description:
+computes the age on a given day of someone born on a given date. Years and months are counted in calendar terms, so someone born on January 31st is one month old on the last day of February. People born on February 29th celebrate on February 28th in common years.
+
+Parameters:
+birth: the date of birth.
+today: the date on which to compute the age.
+
+Returns:
+Some(Age) with the age and the days until the next birthday, or None if the birth date is after today.
+*/
+pub fn age(birth: NaiveDate, today: NaiveDate) -> Option<Age> {
+    if birth > today {
+        return None;
+    }
+
+    // Count whole months between the two dates, stepping back one if today's day of month hasn't reached the birth day yet
+    let mut months = (today.year() - birth.year()) * 12 + today.month() as i32 - birth.month() as i32;
+    if add_months(birth, months as u32) > today {
+        months -= 1;
+    }
+    let months = months as u32;
+
+    // The remaining days after the last monthly anniversary
+    let days = (today - add_months(birth, months)).num_days() as u32;
+
+    // The next birthday is this year's, or next year's if this year's has passed
+    let this_year = birthday_in(birth, today.year());
+    let next_birthday = if this_year >= today {
+        this_year
+    } else {
+        birthday_in(birth, today.year() + 1)
+    };
+
+    Some(Age {
+        years: months / 12,
+        months: months % 12,
+        days,
+        days_until_birthday: (next_birthday - today).num_days(),
+    })
+}
+
+// Adds a number of calendar months to a date, clamping the day to the end of shorter months
+fn add_months(date: NaiveDate, months: u32) -> NaiveDate {
+    let total = date.month0() + months;
+    let year = date.year() + (total / 12) as i32;
+    let month = total % 12 + 1;
+
+    // Clamp the day to the last day of the target month
+    (1..=date.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .unwrap_or(date)
+}
+
+// Returns the birthday of someone born on the given date in the given year
+fn birthday_in(birth: NaiveDate, year: i32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, birth.month(), birth.day())
+        .or_else(|| NaiveDate::from_ymd_opt(year, 2, 28))
+        .unwrap_or(birth)
+}
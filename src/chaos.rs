@@ -0,0 +1,112 @@
+use crate::random::Random;
+use clap::ValueEnum;
+use rand::{seq::SliceRandom, Rng};
+use std::{fmt, time::Duration};
+
+/*
+Description:
+the faults chaos mode injects in place of a query's normal answer.
+*/
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    // Answer SERVFAIL
+    Servfail,
+
+    // Answer normally, after a random delay
+    Delay,
+
+    // Answer over UDP with TC set and no records, so the client has to retry over TCP; queries over TCP are answered normally
+    Truncate,
+
+    // Answer NOERROR with a record whose name and type don't match the question
+    Malformed,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            Fault::Servfail => "servfail",
+            Fault::Delay => "delay",
+            Fault::Truncate => "truncate",
+            Fault::Malformed => "malformed",
+        })
+    }
+}
+
+/*
+Description:
+chaos mode: injects a fault into a random share of the queries, picked from the enabled faults with equal odds, so client developers can test their retries and validation against a real server. The draws come from the handler's generator, so a seeded server injects the same faults on every run.
+*/
+#[derive(Debug)]
+pub struct Chaos {
+    // The share of queries a fault is injected into, from 0 to 1
+    probability: f64,
+
+    // The faults to pick from
+    faults: Vec<Fault>,
+
+    // The longest delay injected
+    max_delay: Duration,
+}
+
+impl Chaos {
+    /*
+    Description:
+    creates chaos mode.
+
+    Parameters:
+    probability: the share of queries a fault is injected into, from 0 to 1.
+    faults: the faults to pick from; none disables chaos mode.
+    max_delay: the longest delay injected by the delay fault; each delay is drawn evenly between none and this.
+
+    Returns:
+    The Chaos.
+    */
+    pub fn new(probability: f64, faults: Vec<Fault>, max_delay: Duration) -> Self {
+        Chaos {
+            probability: probability.clamp(0.0, 1.0),
+            faults,
+            max_delay,
+        }
+    }
+
+    // Returns the share of queries a fault is injected into
+    pub fn probability(&self) -> f64 {
+        self.probability
+    }
+
+    // Returns the faults picked from
+    pub fn faults(&self) -> &[Fault] {
+        &self.faults
+    }
+
+    // Decides whether to inject a fault into a query, and which
+    pub fn draw(&self, random: &Random) -> Option<Fault> {
+        random.with_rng(|rng| match rng.gen_bool(self.probability) {
+            true => self.faults.choose(rng).copied(),
+            false => None,
+        })
+    }
+
+    // Draws how long to delay an answer
+    pub fn delay(&self, random: &Random) -> Duration {
+        self.max_delay.mul_f64(random.with_rng(|rng| rng.gen::<f64>()))
+    }
+}
+
+/*
+Description:
+parses the share of queries chaos mode injects faults into, for --chaos.
+
+Parameters:
+text: the share, from 0 to 1.
+
+Returns:
+Ok(f64), or an error if it is not a number from 0 to 1.
+*/
+pub fn parse_probability(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(probability) if (0.0..=1.0).contains(&probability) => Ok(probability),
+        _ => Err(format!("{text} is not a probability from 0 to 1")),
+    }
+}
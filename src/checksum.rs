@@ -0,0 +1,80 @@
+/*
+Description:
+computes the Luhn check digit for a number, i.e. the digit that has to be appended to make the whole number pass the Luhn check.
+
+Parameters:
+payload: the digits of the number without the check digit.
+
+Returns:
+Some(u32) with the check digit, or None if the payload contains anything other than ASCII digits.
+*/
+pub fn luhn_check_digit(payload: &str) -> Option<u32> {
+    let mut sum = 0;
+
+    // Walk the digits from the right; the digit next to the (future) check digit is doubled first
+    for (index, c) in payload.chars().rev().enumerate() {
+        let mut digit = c.to_digit(10)?;
+        if index % 2 == 0 {
+            digit *= 2;
+            if digit > 9 {
+                digit -= 9;
+            }
+        }
+        sum += digit;
+    }
+
+    Some((10 - sum % 10) % 10)
+}
+
+/*
+Description:
+the result of validating a number with the Luhn algorithm.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct LuhnResult {
+    // Whether the number passes the check
+    pub valid: bool,
+
+    // The check digit the number should end with
+    pub expected: u32,
+}
+
+/*
+Description:
+validates a number (e.g. a credit card or IMEI number) with the Luhn algorithm.
+
+Parameters:
+digits: the number to validate, including its check digit.
+
+Returns:
+Some(LuhnResult) with the outcome and the expected check digit, or None if the input is too short or contains anything other than ASCII digits.
+*/
+pub fn luhn(digits: &str) -> Option<LuhnResult> {
+    // A number needs at least one payload digit and a check digit
+    if digits.len() < 2 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    // Split off the check digit and work out what it should have been
+    let (payload, check) = digits.split_at(digits.len() - 1);
+    let expected = luhn_check_digit(payload)?;
+
+    Some(LuhnResult {
+        valid: check.chars().next()?.to_digit(10)? == expected,
+        expected,
+    })
+}
+
+/*
+Description:
+computes the CRC-32 (IEEE) checksum of a piece of text.
+
+Parameters:
+text: the text to checksum.
+
+Returns:
+The CRC-32 of the UTF-8 bytes of the text.
+*/
+pub fn crc32(text: &str) -> u32 {
+    crc32fast::hash(text.as_bytes())
+}
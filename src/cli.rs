@@ -0,0 +1,478 @@
+use crate::options::Options;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
+use trust_dns_server::client::rr::RecordType;
+
+/*
+Description:
+the command line: a subcommand, or the serve flags on their own, which run the server as they did before there were subcommands, so existing service files and scripts keep working.
+
+Parameters:
+NONE
+
+Returns:
+NONE
+*/
+#[derive(Parser, Debug)]
+#[clap(name = "rusty-dns", args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    // The subcommand, if one was given
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    // The server's options, used when no subcommand is given
+    #[clap(flatten)]
+    pub serve: Options,
+}
+
+/*
+Description:
+the subcommands: running the server, and the tools that go with it.
+*/
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    // Run the DNS server; the same as giving the server's flags without a subcommand
+    #[clap(about = "Run the DNS server (the default when no subcommand is given)")]
+    Serve(Box<Options>),
+
+    // Send one query to a server and print the answer
+    #[clap(about = "Send a query to a server and print the answer")]
+    Query(QueryArgs),
+
+    // Validate a configuration file and the server's flags together and print the effective configuration
+    #[clap(about = "Check a configuration file and the server's flags, print the effective configuration and exit non-zero if they are invalid")]
+    CheckConfig(CheckConfigArgs),
+
+    // Query a server, or a handler in this process, interactively
+    #[clap(about = "Open an interactive prompt for querying a server, or the zones in this process with --local")]
+    Repl(ReplArgs),
+
+    // Show how the server would route and answer a query, without starting it
+    #[clap(about = "Show which zone, access list, policy and handler a query would go through with the current configuration, and the answer it would get")]
+    Explain(ExplainArgs),
+
+    // Read a zone file and report the mistakes in it
+    #[clap(about = "Check a BIND zone file for syntax errors, a missing SOA or NS, CNAME conflicts, duplicates and out-of-zone records")]
+    CheckZone(CheckZoneArgs),
+
+    // Load a server with queries and report its rate and latency
+    #[clap(about = "Send queries to a server as fast as it answers them and report the rate and latency")]
+    Bench(BenchArgs),
+
+    // Check a running server against the protocol behaviors the RFCs require
+    #[clap(about = "Check a running server's handling of unknown types, EDNS, truncation, case and op codes against the RFCs and print a compliance matrix")]
+    Conformance(ConformanceArgs),
+
+    // Fetch a running server's counters from its admin API
+    #[clap(about = "Print a running server's stats snapshot, fetched from its admin API, as JSON or CSV")]
+    Stats(StatsArgs),
+
+    // Print a shell's completion script, generated from the flags defined here
+    #[clap(about = "Print the completion script for bash, zsh, fish, elvish or powershell")]
+    Completions(CompletionsArgs),
+
+    // Print the man page, or write the pages of every subcommand
+    #[clap(about = "Print the man page, or write it and one page per subcommand to a directory")]
+    Manpage(ManpageArgs),
+
+    // Work with blocklists ahead of time
+    #[clap(about = "Work with blocklists: compile downloads and merges them into the compact file the server maps at startup")]
+    Blocklist(BlocklistArgs),
+
+    // Generate keys for the server's security features
+    #[clap(about = "Generate a TSIG key, or a DNSSEC zone-signing or key-signing key with its DS record")]
+    Keygen(KeygenArgs),
+
+    // Manage the hosts of the dynamic DNS zone
+    #[clap(about = "Add or remove hosts of the dynamic DNS zone, printing a new host's token for its DynDNS client")]
+    Ddns(DdnsArgs),
+
+    // Copy a zone hosted by a DNS provider into a zone file
+    #[clap(about = "Import a Route 53 or Cloudflare hosted zone through the provider's API into a zone file the server can serve with --zone-file")]
+    Import(ImportArgs),
+}
+
+/*
+Description:
+the arguments of the query subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct QueryArgs {
+    // The name to query, e.g. dice.mentisnovae.tech
+    pub name: String,
+
+    // The record type to query
+    // The default value is TXT, which every zone answers
+    #[clap(default_value = "TXT")]
+    pub record_type: RecordType,
+
+    // The server to send the query to
+    // The default value is the server's default UDP address on this machine
+    #[clap(long, short, default_value = "127.0.0.1:4200")]
+    pub server: SocketAddr,
+
+    // Whether to send the query over TCP instead of UDP
+    #[clap(long)]
+    pub tcp: bool,
+
+    // How long to wait for the answer, in milliseconds
+    #[clap(long, default_value = "2000")]
+    pub timeout_ms: u64,
+}
+
+/*
+Description:
+the arguments of the repl subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct ReplArgs {
+    // The server to send the queries to
+    #[clap(long, short, default_value = "127.0.0.1:4200")]
+    pub server: SocketAddr,
+
+    // Whether to answer the queries with a handler in this process, built from the DNS_* environment variables, instead of a server
+    #[clap(long, conflicts_with_all = ["server", "tcp"])]
+    pub local: bool,
+
+    // Whether to send the queries over TCP instead of UDP
+    #[clap(long)]
+    pub tcp: bool,
+
+    // The domain appended to names without a trailing dot, so "dice" queries dice.<domain>.
+    #[clap(long, short, default_value = "mentisnovae.tech")]
+    pub domain: String,
+
+    // How long to wait for each answer, in milliseconds
+    #[clap(long, default_value = "2000")]
+    pub timeout_ms: u64,
+}
+
+/*
+Description:
+the arguments of the check-config subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct CheckConfigArgs {
+    // The TOML configuration file to check
+    pub path: PathBuf,
+
+    // The server's flags (and DNS_* environment variables) to check with the file, e.g. its bind addresses
+    #[clap(flatten)]
+    pub serve: Box<Options>,
+}
+
+/*
+Description:
+the arguments of the explain subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct ExplainArgs {
+    // The name to explain, e.g. dice.mentisnovae.tech
+    pub name: String,
+
+    // The record type of the query
+    // The default value is TXT, which every zone answers
+    #[clap(default_value = "TXT")]
+    pub record_type: RecordType,
+
+    // The address the query comes from, for the access lists, country policies and blocklist exemptions
+    #[clap(long, default_value = "127.0.0.1")]
+    pub client: IpAddr,
+
+    // The server's flags (and DNS_* environment variables), including --config
+    #[clap(flatten)]
+    pub serve: Box<Options>,
+}
+
+/*
+Description:
+the arguments of the check-zone subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct CheckZoneArgs {
+    // The zone's origin, e.g. example.com
+    pub origin: String,
+
+    // The zone file, in BIND's master file format
+    pub path: PathBuf,
+}
+
+/*
+Description:
+the arguments of the bench subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    // The names to query, sent in turn, e.g. dice.mentisnovae.tech
+    #[clap(required = true)]
+    pub names: Vec<String>,
+
+    // The record type to query
+    #[clap(long, default_value = "TXT")]
+    pub record_type: RecordType,
+
+    // The server to send the queries to
+    #[clap(long, short, default_value = "127.0.0.1:4200")]
+    pub server: SocketAddr,
+
+    // Whether to send the queries over TCP, one connection per query, instead of UDP
+    #[clap(long)]
+    pub tcp: bool,
+
+    // The total number of queries to send
+    #[clap(long, short = 'n', default_value = "10000")]
+    pub queries: usize,
+
+    // The number of queries in flight at once
+    #[clap(long, short, default_value = "32")]
+    pub concurrency: usize,
+
+    // How long to wait for each answer, in milliseconds, before counting the query as timed out
+    #[clap(long, default_value = "2000")]
+    pub timeout_ms: u64,
+}
+
+/*
+Description:
+the arguments of the conformance subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct ConformanceArgs {
+    // The server to check, over both UDP and TCP
+    #[clap(long, short, default_value = "127.0.0.1:4200")]
+    pub server: SocketAddr,
+
+    // The domain the server answers for, as given to it with --domain
+    #[clap(long, short, default_value = "mentisnovae.tech")]
+    pub domain: String,
+
+    // The output format
+    #[clap(long, value_enum, default_value = "table")]
+    pub format: ConformanceFormat,
+
+    // How long to wait for each answer, in milliseconds, before failing the check
+    #[clap(long, default_value = "2000")]
+    pub timeout_ms: u64,
+}
+
+/*
+Description:
+the output formats of the conformance subcommand: a matrix of the checks against the transports with the reasons for the failures, or one JSON object per check and transport, for keeping the results of each release.
+*/
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConformanceFormat {
+    Table,
+    Json,
+}
+
+/*
+Description:
+the arguments of the blocklist subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct BlocklistArgs {
+    #[clap(subcommand)]
+    pub command: BlocklistCommand,
+}
+
+/*
+Description:
+the blocklist subcommand's own subcommands.
+*/
+#[derive(Subcommand, Debug)]
+pub enum BlocklistCommand {
+    // Download, merge and compile blocklists into one file
+    #[clap(about = "Download or read blocklists, remove duplicates and compile them into a file for the compiled setting of [blocklist]")]
+    Compile(BlocklistCompileArgs),
+}
+
+/*
+Description:
+the arguments of the blocklist compile subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct BlocklistCompileArgs {
+    // The blocklist files or http(s) URLs, in hosts or plain domain-list format
+    #[clap(required = true)]
+    pub sources: Vec<String>,
+
+    // The file to write the compiled list to
+    #[clap(long, short)]
+    pub output: PathBuf,
+}
+
+/*
+Description:
+the arguments of the keygen subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct KeygenArgs {
+    // The name of the TSIG key, as clients and the server refer to it, or the zone of the DNSSEC key
+    pub name: String,
+
+    // The kind of key to generate
+    #[clap(long = "type", value_enum, default_value = "tsig")]
+    pub key_type: KeyType,
+
+    // The length of the TSIG secret in bytes
+    // The default value is 32, the output size of HMAC-SHA256
+    #[clap(long, default_value = "32")]
+    pub bytes: usize,
+
+    // The file to write the TSIG key to, in BIND's key statement format; the key is printed if none is given
+    #[clap(long, short)]
+    pub output: Option<PathBuf>,
+
+    // The directory the DNSSEC key files are written to
+    #[clap(long, short = 'K', default_value = ".")]
+    pub directory: PathBuf,
+}
+
+/*
+Description:
+the kinds of key keygen generates: a TSIG secret for authenticating transfers and updates, or a DNSSEC zone-signing key (ZSK) or key-signing key (KSK).
+*/
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyType {
+    Tsig,
+    Zsk,
+    Ksk,
+}
+
+/*
+Description:
+the arguments of the stats subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    // The address of the server's admin API, as given to it with --admin
+    #[clap(long, short, env = "DNS_ADMIN")]
+    pub admin: SocketAddr,
+
+    // The output format
+    #[clap(long, value_enum, default_value = "json")]
+    pub format: StatsFormat,
+
+    // Whether to leave out the CSV header, e.g. when appending to a file from a cron job
+    #[clap(long)]
+    pub no_header: bool,
+
+    // How long to wait for the admin API, in milliseconds
+    #[clap(long, default_value = "5000")]
+    pub timeout_ms: u64,
+}
+
+/*
+Description:
+the output formats of the stats subcommand: the snapshot as pretty-printed JSON, or one CSV row per counter.
+*/
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsFormat {
+    Json,
+    Csv,
+}
+
+/*
+Description:
+the arguments of the completions subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    // The shell to print the completion script for
+    #[clap(value_enum)]
+    pub shell: clap_complete::Shell,
+}
+
+/*
+Description:
+the arguments of the manpage subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct ManpageArgs {
+    // The directory to write rusty-dns.1 and a page per subcommand (e.g. rusty-dns-query.1) to; the main page is printed if none is given
+    #[clap(long, short)]
+    pub output_dir: Option<PathBuf>,
+}
+
+/*
+Description:
+the arguments of the ddns subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct DdnsArgs {
+    #[clap(subcommand)]
+    pub command: DdnsCommand,
+}
+
+/*
+Description:
+the ddns subcommand's own subcommands.
+*/
+#[derive(Subcommand, Debug)]
+pub enum DdnsCommand {
+    // Add a host, or give an existing one a new token
+    #[clap(about = "Add a host to the dynamic zone, or give an existing host a new token, and print the token")]
+    Add(DdnsHostArgs),
+
+    // Remove a host
+    #[clap(about = "Remove a host from the dynamic zone")]
+    Remove(DdnsHostArgs),
+}
+
+/*
+Description:
+the arguments of the ddns add and remove subcommands.
+*/
+#[derive(Args, Debug)]
+pub struct DdnsHostArgs {
+    // The host's label, e.g. home for home.dyn.<domain>
+    pub label: String,
+
+    // The hosts file of the dynamic zone, as given to the server with --ddns-hosts
+    #[clap(long, default_value = "ddns-hosts.json", env = "DNS_DDNS_HOSTS")]
+    pub hosts: PathBuf,
+}
+
+/*
+Description:
+the arguments of the import subcommand.
+*/
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    // The provider hosting the zone
+    #[clap(value_enum)]
+    pub provider: Provider,
+
+    // The provider's ID of the zone: a Route 53 hosted zone ID (e.g. Z1D633PJN98FT9) or a Cloudflare zone ID
+    pub zone_id: String,
+
+    // The file to write the zone to; the zone is printed if none is given
+    #[clap(long, short)]
+    pub output: Option<PathBuf>,
+
+    // The Cloudflare API token, with the Zone:Read and DNS:Read permissions; Route 53 requests are signed with the AWS_* credentials instead
+    #[clap(long, env = "CLOUDFLARE_API_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+
+    // The provider's API endpoint, for compatible services
+    // The default value is the provider's public API
+    #[clap(long)]
+    pub endpoint: Option<String>,
+
+    // How long to wait for each API request, in milliseconds
+    #[clap(long, default_value = "30000")]
+    pub timeout_ms: u64,
+}
+
+/*
+Description:
+the DNS providers zones can be imported from.
+*/
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Provider {
+    Route53,
+    Cloudflare,
+}
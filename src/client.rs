@@ -0,0 +1,185 @@
+use crate::{cache::CaptureResponder, handlers::Handler, random};
+use rand::Rng;
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+};
+use trust_dns_server::{
+    authority::MessageRequest,
+    client::rr::{Name, RecordType},
+    proto::{
+        op::{Message, MessageType, OpCode, Query},
+        serialize::binary::BinDecodable,
+    },
+    server::{Protocol, Request, RequestHandler},
+};
+
+// The largest answer read over UDP.
+const MAX_UDP_ANSWER: usize = 65535;
+
+/*
+Description:
+sends one query to a DNS server and waits for its answer, as the query, bench and repl subcommands do. Over UDP, datagrams whose ID doesn't match the query are ignored; over TCP, a new connection is opened for the query.
+
+Parameters:
+server: the server's address.
+name: the name to query.
+record_type: the record type to query.
+tcp: whether to send the query over TCP instead of UDP.
+timeout: how long to wait for the answer.
+
+Returns:
+Ok(Message) with the answer, or an io::Error if it could not be sent, timed out or could not be decoded.
+*/
+pub async fn exchange(server: SocketAddr, name: &Name, record_type: RecordType, tcp: bool, timeout: Duration) -> io::Result<Message> {
+    let id = random::with_rng(|rng| rng.gen::<u16>());
+    let bytes = query(id, name, record_type, true)?;
+
+    let answer = exchange_bytes(server, &bytes, tcp, timeout).await?;
+    let answer = Message::from_vec(&answer).map_err(invalid_data)?;
+    if answer.id() != id {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the answer's ID doesn't match the query"));
+    }
+    Ok(answer)
+}
+
+/*
+Description:
+sends one non-recursive query to a DNS server and waits for its answer, as the trace zone does to ask each server on the way down from the root. The query goes over UDP and is repeated over TCP if the answer is truncated.
+
+Parameters:
+server: the server's address.
+name: the name to query.
+record_type: the record type to query.
+timeout: how long to wait for each answer.
+
+Returns:
+Ok(Message) with the answer, or an io::Error if it could not be sent, timed out or could not be decoded.
+*/
+pub async fn exchange_iterative(server: SocketAddr, name: &Name, record_type: RecordType, timeout: Duration) -> io::Result<Message> {
+    let id = random::with_rng(|rng| rng.gen::<u16>());
+    let bytes = query(id, name, record_type, false)?;
+
+    let mut answer = Message::from_vec(&exchange_bytes(server, &bytes, false, timeout).await?).map_err(invalid_data)?;
+    if answer.truncated() {
+        answer = Message::from_vec(&exchange_bytes(server, &bytes, true, timeout).await?).map_err(invalid_data)?;
+    }
+    if answer.id() != id {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the answer's ID doesn't match the query"));
+    }
+    Ok(answer)
+}
+
+/*
+Description:
+sends a query that is already in wire format to a DNS server and waits for its answer, without decoding either, as the conformance subcommand does to send queries the Message API won't build and to measure the answers' size. Over UDP, datagrams whose ID doesn't match the query's are ignored.
+
+Parameters:
+server: the server's address.
+query: the query in wire format, at least the two bytes of its ID long.
+tcp: whether to send the query over TCP instead of UDP.
+timeout: how long to wait for the answer.
+
+Returns:
+Ok(Vec<u8>) with the answer in wire format, or an io::Error if it could not be sent or timed out.
+*/
+pub async fn exchange_bytes(server: SocketAddr, query: &[u8], tcp: bool, timeout: Duration) -> io::Result<Vec<u8>> {
+    if query.len() < 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the query is too short to have an ID"));
+    }
+    let id = u16::from_be_bytes([query[0], query[1]]);
+
+    let exchange = async {
+        if tcp {
+            exchange_tcp(server, query).await
+        } else {
+            exchange_udp(server, query, id).await
+        }
+    };
+    tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("no answer from {server} within {timeout:?}")))?
+}
+
+/*
+Description:
+answers a query with a handler in this process instead of a server, as the repl does with --local and explain does, so the zones can be tried out without starting a server. The query comes over UDP.
+
+Parameters:
+handler: the handler.
+name: the name to query.
+record_type: the record type to query.
+client: the address the query comes from.
+
+Returns:
+Ok(Message) with the answer, or an io::Error if the handler sent none or it could not be decoded.
+*/
+pub async fn exchange_local(handler: &Handler, name: &Name, record_type: RecordType, client: IpAddr) -> io::Result<Message> {
+    let id = random::with_rng(|rng| rng.gen::<u16>());
+    let bytes = query(id, name, record_type, true)?;
+    let request = MessageRequest::from_bytes(&bytes).map_err(invalid_data)?;
+    let request = Request::new(request, SocketAddr::new(client, 0), Protocol::Udp);
+
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    let answer = responder
+        .take()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the handler sent no answer"))?;
+    Message::from_vec(&answer).map_err(invalid_data)
+}
+
+// Builds a query for a name in wire format, recursive or not
+fn query(id: u16, name: &Name, record_type: RecordType, recursion_desired: bool) -> io::Result<Vec<u8>> {
+    let mut query = Message::new();
+    query
+        .set_id(id)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(recursion_desired)
+        .add_query(Query::query(name.clone(), record_type));
+    query.to_vec().map_err(invalid_data)
+}
+
+// Sends a query over UDP and returns the first datagram with the query's ID
+async fn exchange_udp(server: SocketAddr, query: &[u8], id: u16) -> io::Result<Vec<u8>> {
+    let local = match server {
+        SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    socket.send(query).await?;
+
+    let mut buffer = vec![0; MAX_UDP_ANSWER];
+    loop {
+        let length = socket.recv(&mut buffer).await?;
+        if length >= 2 && u16::from_be_bytes([buffer[0], buffer[1]]) == id {
+            buffer.truncate(length);
+            return Ok(buffer);
+        }
+    }
+}
+
+// Sends a query over a new TCP connection and returns the answer
+async fn exchange_tcp(server: SocketAddr, query: &[u8]) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(server).await?;
+    let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(query);
+    stream.write_all(&framed).await?;
+
+    let mut length = [0; 2];
+    stream.read_exact(&mut length).await?;
+    let mut answer = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut answer).await?;
+    Ok(answer)
+}
+
+// Wraps a protocol error as an io::Error
+fn invalid_data(error: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
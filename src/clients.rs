@@ -0,0 +1,170 @@
+use lru::LruCache;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    num::NonZeroUsize,
+    sync::Mutex,
+};
+
+// The largest number of distinct query names remembered per client.
+// When a client's map is full, its least queried name is forgotten to make room.
+const MAX_QNAMES_PER_CLIENT: usize = 32;
+
+// The number of query names reported per client.
+const TOP_QNAMES: usize = 5;
+
+/*
+Description:
+what is known about a single client: how many queries it sent, when it was last seen, and which names it queries most.
+*/
+#[derive(Clone, Debug, Default)]
+struct ClientEntry {
+    // The number of queries received from the client
+    queries: u64,
+
+    // Unix timestamp of the client's latest query
+    last_seen: i64,
+
+    // How often the client queried each name, bounded by MAX_QNAMES_PER_CLIENT
+    qnames: HashMap<String, u64>,
+}
+
+/*
+Description:
+a summary of a single client, as reported by the admin API and the top zone.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct ClientReport {
+    // The client's IP address
+    pub address: IpAddr,
+
+    // The number of queries received from the client
+    pub queries: u64,
+
+    // Unix timestamp of the client's latest query
+    pub last_seen: i64,
+
+    // The client's most queried names with their query counts, busiest first
+    pub top_qnames: Vec<(String, u64)>,
+}
+
+impl fmt::Display for ClientReport {
+    // Formats the report as a single line for a TXT answer
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let age = (chrono::Utc::now().timestamp() - self.last_seen).max(0);
+        write!(f, "{} {} queries, last seen {age}s ago", self.address, self.queries)?;
+        for (i, (qname, count)) in self.top_qnames.iter().enumerate() {
+            let separator = if i == 0 { ", top: " } else { ", " };
+            write!(f, "{separator}{qname} ({count})")?;
+        }
+        Ok(())
+    }
+}
+
+/*
+Description:
+per-client query statistics keyed by source IP address, used to spot abusive clients. The map holds a bounded number of clients and forgets the least recently seen one when it is full, so a flood of spoofed source addresses cannot grow it without limit.
+*/
+#[derive(Debug)]
+pub struct ClientStats {
+    clients: Mutex<LruCache<IpAddr, ClientEntry>>,
+}
+
+impl ClientStats {
+    /*
+    Description:
+    creates an empty statistics map.
+
+    Parameters:
+    capacity: the largest number of clients tracked at once; 0 is treated as 1.
+
+    Returns:
+    A new ClientStats instance.
+    */
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity.max(1)).unwrap();
+        ClientStats {
+            clients: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /*
+    Description:
+    records a query from a client.
+
+    Parameters:
+    client: the IP address of the client.
+    qname: the name that was queried.
+
+    Returns:
+    None
+    */
+    pub fn record(&self, client: IpAddr, qname: &str) {
+        let mut clients = self.clients.lock().unwrap();
+
+        // Look the client up, making it the most recently seen; new clients push out the least recently seen one
+        let entry = clients.get_or_insert_mut(client, ClientEntry::default);
+        entry.queries += 1;
+        entry.last_seen = chrono::Utc::now().timestamp();
+
+        // Make room for a new name by forgetting the client's least queried one
+        if entry.qnames.len() >= MAX_QNAMES_PER_CLIENT && !entry.qnames.contains_key(qname) {
+            if let Some(least_queried) = entry
+                .qnames
+                .iter()
+                .min_by_key(|(_, count)| **count)
+                .map(|(name, _)| name.clone())
+            {
+                entry.qnames.remove(&least_queried);
+            }
+        }
+
+        *entry.qnames.entry(qname.to_string()).or_default() += 1;
+    }
+
+    // Returns the number of clients currently tracked
+    pub fn tracked(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /*
+    Description:
+    returns the busiest clients.
+
+    Parameters:
+    limit: the largest number of clients to return.
+
+    Returns:
+    A Vec of ClientReport, sorted by query count with the busiest client first.
+    */
+    pub fn top(&self, limit: usize) -> Vec<ClientReport> {
+        let clients = self.clients.lock().unwrap();
+
+        let mut reports: Vec<ClientReport> = clients
+            .iter()
+            .map(|(address, entry)| {
+                // Sort the client's names, busiest first
+                let mut top_qnames: Vec<(String, u64)> = entry
+                    .qnames
+                    .iter()
+                    .map(|(name, count)| (name.clone(), *count))
+                    .collect();
+                top_qnames.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                top_qnames.truncate(TOP_QNAMES);
+
+                ClientReport {
+                    address: *address,
+                    queries: entry.queries,
+                    last_seen: entry.last_seen,
+                    top_qnames,
+                }
+            })
+            .collect();
+
+        reports.sort_by_key(|report| std::cmp::Reverse(report.queries));
+        reports.truncate(limit);
+        reports
+    }
+}
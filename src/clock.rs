@@ -0,0 +1,37 @@
+use chrono::{DateTime, Utc};
+use std::fmt;
+
+/*
+Description:
+where the zones that answer with dates and times (moon, sun, week, age and new ULIDs) get the current time. The server reads the system clock; a fixed clock (--clock) makes their answers reproducible in tests and demos.
+*/
+pub trait Clock: fmt::Debug + Send + Sync {
+    // Returns the current time
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/*
+Description:
+the system clock.
+*/
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/*
+Description:
+a clock stopped at a given time, which it always returns.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
@@ -0,0 +1,944 @@
+use crate::{
+    blocklist::{self, CompiledList},
+    cli::{BenchArgs, BlocklistArgs, BlocklistCommand, BlocklistCompileArgs, CheckConfigArgs, CheckZoneArgs, Cli, Command, CompletionsArgs, ConformanceArgs, ConformanceFormat, DdnsArgs, DdnsCommand, ExplainArgs, ImportArgs, KeyType, KeygenArgs, ManpageArgs, Provider, QueryArgs, ReplArgs, StatsArgs, StatsFormat},
+    client,
+    config::Config,
+    conformance::{self, Check, Transport},
+    aws, ddns, dnssec,
+    handlers::Handler,
+    import::{self, ImportedZone},
+    options::Options,
+    zonefile::{self, Severity},
+};
+use base64::Engine;
+use clap::{CommandFactory, Parser};
+use rand::RngCore;
+use rustyline::error::ReadlineError;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fs::OpenOptions,
+    io::{self, Write},
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use trust_dns_server::{
+    client::rr::{LowerName, Name, RecordType},
+    proto::op::{Message, MessageType, ResponseCode},
+};
+
+// The result of a subcommand; errors are printed by main, which exits non-zero.
+type CommandResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+// The repl's help text.
+const REPL_HELP: &str = "\
+Type a name, optionally followed by a record type (TXT by default), e.g.:
+  dice                     dice.<domain>, like every name without a trailing dot
+  time.1700000000 TXT
+  example.com. A           a name with a trailing dot is taken as it is
+  @                        the domain itself
+Commands:
+  :type <type>             change the default record type
+  :server <address>        query a server (instead of the handler in this process with --local)
+  :tcp, :udp               switch the transport to the server
+  :help, :quit             this help, and leaving (or Ctrl-D)";
+
+/*
+Description:
+runs one of the tools' subcommands; serve is run by main itself, since it sets up logging and the runtime for the server.
+
+Parameters:
+command: the subcommand.
+
+Returns:
+Ok(()) if the subcommand succeeded, or the error to report.
+*/
+pub fn run(command: Command) -> CommandResult {
+    match command {
+        Command::Serve(_) => unreachable!("main runs the server itself"),
+        Command::Query(args) => query(args),
+        Command::Repl(args) => repl(args),
+        Command::CheckConfig(args) => check_config(args),
+        Command::Explain(args) => explain(args),
+        Command::CheckZone(args) => check_zone(args),
+        Command::Bench(args) => bench(args),
+        Command::Conformance(args) => conformance(args),
+        Command::Stats(args) => stats(args),
+        Command::Completions(args) => completions(args),
+        Command::Manpage(args) => manpage(args),
+        Command::Blocklist(args) => blocklist(args),
+        Command::Keygen(args) => keygen(args),
+        Command::Ddns(args) => ddns(args),
+        Command::Import(args) => import(args),
+    }
+}
+
+/*
+Description:
+the query subcommand: sends one query and prints the answer in the style of dig, with the header, the records of each section and the round-trip time.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) once the answer is printed, or an error if the name is invalid or no answer came back.
+*/
+pub fn query(args: QueryArgs) -> CommandResult {
+    let name = parse_name(&args.name)?;
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+
+    let started = Instant::now();
+    let answer = runtime.block_on(client::exchange(args.server, &name, args.record_type, args.tcp, Duration::from_millis(args.timeout_ms)))?;
+    let elapsed = started.elapsed();
+
+    print!("{}", format_answer(&answer));
+    println!(
+        ";; Query time: {:.2} ms, server: {} ({})",
+        elapsed.as_secs_f64() * 1000.0,
+        args.server,
+        if args.tcp { "tcp" } else { "udp" }
+    );
+    Ok(())
+}
+
+/*
+Description:
+the repl subcommand: an interactive prompt that reads queries and prints their answers like the query subcommand, with line editing and a history kept across sessions in ~/.rusty-dns-history. Names without a trailing dot are taken to be below the domain, so the zones can be tried out by keyword ("dice", "fx.100.usd.eur"). With --local, the queries are answered by a handler in this process, so no server is needed.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) when the prompt is left, or an error if the terminal could not be read.
+*/
+pub fn repl(args: ReplArgs) -> CommandResult {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let domain = args.domain.trim_end_matches('.').to_string();
+    let timeout = Duration::from_millis(args.timeout_ms);
+    let mut handler = match args.local {
+        true => {
+            let _guard = runtime.enter();
+            Some(Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", &domain])))
+        }
+        false => None,
+    };
+    let (mut server, mut tcp, mut record_type) = (args.server, args.tcp, RecordType::TXT);
+
+    let mut editor = rustyline::DefaultEditor::new()?;
+    let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".rusty-dns-history"));
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+    match handler {
+        Some(_) => println!("Answering queries for {domain} in this process; type :help for help"),
+        None => println!("Querying {server} for {domain}; type :help for help"),
+    }
+
+    loop {
+        let line = match editor.readline("rusty-dns> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error.into()),
+        };
+        let mut words = line.split_whitespace();
+        let Some(first) = words.next() else {
+            continue;
+        };
+        let _ = editor.add_history_entry(line.trim());
+
+        match first {
+            ":quit" | ":q" | ":exit" => break,
+            ":help" | ":h" => println!("{REPL_HELP}"),
+            ":tcp" | ":udp" => {
+                tcp = first == ":tcp";
+                if handler.is_some() {
+                    println!("queries are answered in this process; use :server to query a server");
+                }
+            }
+            ":type" => match words.next().map(|text| text.to_ascii_uppercase().parse::<RecordType>()) {
+                Some(Ok(parsed)) => record_type = parsed,
+                _ => println!("usage: :type <record type>, e.g. :type A"),
+            },
+            ":server" => match words.next().map(str::parse::<SocketAddr>) {
+                Some(Ok(address)) => {
+                    server = address;
+                    handler = None;
+                }
+                _ => println!("usage: :server <address:port>"),
+            },
+            command if command.starts_with(':') => println!("unknown command {command}; type :help for help"),
+            name => {
+                let query_type = match words.next().map(|text| text.to_ascii_uppercase().parse::<RecordType>()) {
+                    None => record_type,
+                    Some(Ok(parsed)) => parsed,
+                    Some(Err(_)) => {
+                        println!("unknown record type; type :help for help");
+                        continue;
+                    }
+                };
+                let name = match repl_name(name, &domain) {
+                    Ok(name) => name,
+                    Err(error) => {
+                        println!("{error}");
+                        continue;
+                    }
+                };
+
+                let started = Instant::now();
+                let answer = runtime.block_on(async {
+                    match &handler {
+                        Some(handler) => client::exchange_local(handler, &name, query_type, Ipv4Addr::LOCALHOST.into()).await,
+                        None => client::exchange(server, &name, query_type, tcp, timeout).await,
+                    }
+                });
+                let elapsed = started.elapsed().as_secs_f64() * 1000.0;
+                match answer {
+                    Ok(answer) => {
+                        print!("{}", format_answer(&answer));
+                        match handler {
+                            Some(_) => println!(";; Query time: {elapsed:.2} ms (in process)"),
+                            None => println!(";; Query time: {elapsed:.2} ms, server: {server} ({})", if tcp { "tcp" } else { "udp" }),
+                        }
+                    }
+                    Err(error) => println!("error: {error}"),
+                }
+            }
+        }
+    }
+
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+    Ok(())
+}
+
+/*
+Description:
+the check-config subcommand: loads a configuration file with the same checks the server applies at startup and on SIGHUP, then checks what the server would only find out when using it: that its bind addresses can be bound, and that its blocklist sources, alert webhook and webhooks are well-formed URLs or existing files. The effective configuration, with every default filled in, is printed as TOML; problems are reported on stderr, and warnings (e.g. an address already in use, perhaps by the running server) don't fail the check.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) if the configuration is valid, or an error saying how many problems were found.
+*/
+pub fn check_config(args: CheckConfigArgs) -> CommandResult {
+    let config = Config::load(&args.path)?;
+    let options = &args.serve;
+    let (mut errors, mut warnings) = (Vec::new(), Vec::new());
+
+    // Every address must be bound once, and by this machine
+    let mut bound = BTreeMap::new();
+    let listeners = options.udp.iter().map(|address| ("udp", *address))
+        .chain(options.tcp.iter().map(|address| ("tcp", *address)))
+        .chain(options.admin.map(|address| ("admin", address)));
+    for (listener, address) in listeners {
+        let protocol = if listener == "udp" { "udp" } else { "tcp" };
+        if let Some(other) = bound.insert((protocol, address), listener) {
+            errors.push(match other == listener {
+                true => format!("{listener} address {address} is listed twice"),
+                false => format!("{address} is used by both the {other} and the {listener} listener"),
+            });
+            continue;
+        }
+        match check_bind(protocol, address) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::AddrInUse => {
+                warnings.push(format!("{listener} address {address} is in use, perhaps by the running server"))
+            }
+            Err(error) if error.kind() == io::ErrorKind::PermissionDenied => {
+                warnings.push(format!("{listener} address {address} needs root or CAP_NET_BIND_SERVICE to bind"))
+            }
+            Err(error) => errors.push(format!("{listener} address {address} can't be bound: {error}")),
+        }
+    }
+
+    // Blocklist sources are fetched or read in the background, so a typo would only show up in the log; hosted domains' blocklists too
+    let tenant_blocklists = config
+        .tenants
+        .iter()
+        .filter_map(|tenant| Some((format!("{} blocklist", tenant.domain), tenant.blocklist.as_ref()?)));
+    for (name, blocklist) in config.blocklist.iter().map(|blocklist| ("blocklist".to_string(), blocklist)).chain(tenant_blocklists) {
+        for source in &blocklist.sources {
+            if let Err(error) = check_source(source) {
+                errors.push(format!("{name} source {source}: {error}"));
+            }
+        }
+        if let Some(path) = &blocklist.compiled {
+            if let Err(error) = CompiledList::open(path) {
+                errors.push(format!("compiled {name} {}: {error}", path.display()));
+            }
+        }
+        for group in blocklist.exempt.iter().filter(|(_, networks)| networks.is_empty()).map(|(group, _)| group) {
+            warnings.push(format!("{name} exempt group {group} has no networks"));
+        }
+    }
+
+    // A hosted domain holding the main domain would take its queries, so the server leaves it out
+    let domain = options.domain.trim_end_matches('.').to_ascii_lowercase();
+    for tenant in &config.tenants {
+        let hosted = tenant.domain.trim_end_matches('.').to_ascii_lowercase();
+        if domain == hosted || domain.ends_with(&format!(".{hosted}")) {
+            errors.push(format!("the tenant {} holds the main domain {}", tenant.domain, options.domain));
+        }
+    }
+    if let Some(alerts) = &config.alerts {
+        if let Err(error) = check_url(&alerts.webhook) {
+            errors.push(format!("alert webhook: {error}"));
+        }
+    }
+    for webhook in &config.webhooks {
+        if let Err(error) = check_url(&webhook.url) {
+            errors.push(format!("webhook: {error}"));
+        }
+    }
+
+    println!("# Effective configuration of {}", args.path.display());
+    println!("# udp = {:?}", options.udp.iter().map(ToString::to_string).collect::<Vec<_>>());
+    println!("# tcp = {:?}", options.tcp.iter().map(ToString::to_string).collect::<Vec<_>>());
+    if let Some(admin) = options.admin {
+        println!("# admin = \"{admin}\"");
+    }
+    print!("{}", toml::to_string(&config)?);
+
+    for warning in &warnings {
+        eprintln!("warning: {warning}");
+    }
+    for error in &errors {
+        eprintln!("error: {error}");
+    }
+    if !errors.is_empty() {
+        return Err(format!("{}: {} problem(s) found", args.path.display(), errors.len()).into());
+    }
+    eprintln!("{}: OK", args.path.display());
+    Ok(())
+}
+
+/*
+Description:
+the explain subcommand: builds the server's handler from its flags and configuration file without binding any sockets, shows the checks a query from the given client would go through and where it would be answered, then prints the answer the handler gives it. The counter file, query and audit logs, dnstap and Redis are left out, so explaining a query leaves no trace in the server's files or shared state.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) once the explanation is printed, or an error if the name or configuration file is invalid.
+*/
+pub fn explain(args: ExplainArgs) -> CommandResult {
+    let name = parse_name(&args.name)?;
+    let mut options = *args.serve;
+    if let Some(path) = &options.config_path {
+        options.config = Config::load(path)?;
+    }
+    options.counter_file = None;
+    options.audit_log = None;
+    options.dnstap_file = None;
+    options.dnstap_socket = None;
+    #[cfg(feature = "sqlite")]
+    {
+        options.query_log = None;
+    }
+    #[cfg(feature = "redis")]
+    {
+        options.redis_url = None;
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let handler = {
+        let _guard = runtime.enter();
+        Handler::from_options(&options)
+    };
+
+    // The blocklists are loaded in the background, so wait for them before checking the name against them
+    let tenants = handler.tenants.iter().flat_map(|tenants| tenants.iter().map(|tenant| &tenant.handler));
+    for blocklist in std::iter::once(&handler).chain(tenants).filter_map(|handler| handler.blocklist.as_ref()) {
+        runtime.block_on(blocklist.wait_loaded());
+    }
+
+    println!("{name} {} from {}", args.record_type, args.client);
+    for (check, outcome) in handler.explain(&LowerName::new(&name), args.record_type, args.client) {
+        println!("  {check:<10} {outcome}");
+    }
+    println!();
+
+    match runtime.block_on(client::exchange_local(&handler, &name, args.record_type, args.client)) {
+        Ok(answer) => print!("{}", format_answer(&answer)),
+        Err(error) => println!(";; {error}"),
+    }
+    Ok(())
+}
+
+/*
+Description:
+the check-zone subcommand: reads a zone file and lints its records, reporting each problem with its line on stderr.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) if the zone has no errors (it may have warnings), or an error if it could not be read or has errors.
+*/
+pub fn check_zone(args: CheckZoneArgs) -> CommandResult {
+    let origin = Name::parse(&args.origin, Some(&Name::root())).map_err(|error| format!("invalid origin {}: {error}", args.origin))?;
+    let text = std::fs::read_to_string(&args.path).map_err(|error| format!("Failed to read {}: {error}", args.path.display()))?;
+    let records = zonefile::parse(&text, &origin).map_err(|error| format!("{}: {error}", args.path.display()))?;
+
+    let problems = zonefile::lint(&origin, &records);
+    for problem in &problems {
+        match problem.line {
+            Some(line) => eprintln!("{}:{line}: {}: {}", args.path.display(), problem.severity, problem.message),
+            None => eprintln!("{}: {}: {}", args.path.display(), problem.severity, problem.message),
+        }
+    }
+
+    let errors = problems.iter().filter(|problem| problem.severity == Severity::Error).count();
+    if errors > 0 {
+        return Err(format!("{}: {errors} error(s) in {} records", args.path.display(), records.len()).into());
+    }
+    eprintln!("{}: OK ({} records, {} warning(s))", args.path.display(), records.len(), problems.len());
+    Ok(())
+}
+
+/*
+Description:
+the bench subcommand: sends the given number of queries, cycling through the names, keeping a fixed number in flight, and reports the rate, the outcome of the queries and the latency percentiles of the answered ones.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) once the report is printed, or an error if a name is invalid.
+*/
+pub fn bench(args: BenchArgs) -> CommandResult {
+    let names = args.names.iter().map(|name| parse_name(name)).collect::<Result<Vec<_>, _>>()?;
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    let timeout = Duration::from_millis(args.timeout_ms);
+
+    // Each worker takes the next query number until all have been sent, recording the latency and outcome of each
+    let next = Arc::new(AtomicUsize::new(0));
+    let results = Arc::new(Mutex::new((Vec::with_capacity(args.queries), BTreeMap::<String, usize>::new())));
+    let names = Arc::new(names);
+    let started = Instant::now();
+    runtime.block_on(async {
+        let workers: Vec<_> = (0..args.concurrency.max(1))
+            .map(|_| {
+                let (next, results, names) = (next.clone(), results.clone(), names.clone());
+                let (server, record_type, tcp, queries) = (args.server, args.record_type, args.tcp, args.queries);
+                tokio::spawn(async move {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= queries {
+                            break;
+                        }
+                        let sent = Instant::now();
+                        let outcome = client::exchange(server, &names[index % names.len()], record_type, tcp, timeout).await;
+                        let latency = sent.elapsed();
+
+                        let mut results = results.lock().unwrap();
+                        let outcome = match outcome {
+                            Ok(answer) => {
+                                results.0.push(latency);
+                                rcode_name(answer.response_code())
+                            }
+                            Err(error) if error.kind() == io::ErrorKind::TimedOut => "timed out".to_string(),
+                            Err(_) => "failed".to_string(),
+                        };
+                        *results.1.entry(outcome).or_default() += 1;
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            let _ = worker.await;
+        }
+    });
+    let elapsed = started.elapsed();
+
+    let mut results = results.lock().unwrap();
+    let (latencies, outcomes) = &mut *results;
+    latencies.sort_unstable();
+    println!(
+        "{} queries in {:.2} s: {:.0} queries/s",
+        args.queries,
+        elapsed.as_secs_f64(),
+        args.queries as f64 / elapsed.as_secs_f64()
+    );
+    let outcomes: Vec<String> = outcomes.iter().map(|(outcome, count)| format!("{outcome} {count}")).collect();
+    println!("outcomes: {}", outcomes.join(", "));
+    if !latencies.is_empty() {
+        let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p).round() as usize].as_secs_f64() * 1000.0;
+        println!(
+            "latency: p50 {:.2} ms, p95 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+            percentile(0.5),
+            percentile(0.95),
+            percentile(0.99),
+            percentile(1.0)
+        );
+    }
+    Ok(())
+}
+
+/*
+Description:
+the conformance subcommand: runs the conformance checks against a running server over UDP and TCP and prints a matrix of the checks against the transports, followed by why each failed check failed, or the outcomes as JSON.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) if the server passed every check, or an error saying how many it failed.
+*/
+pub fn conformance(args: ConformanceArgs) -> CommandResult {
+    let domain = parse_name(&args.domain)?;
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let outcomes = runtime.block_on(conformance::run(args.server, &domain, Duration::from_millis(args.timeout_ms)));
+
+    match args.format {
+        ConformanceFormat::Json => println!("{}", serde_json::to_string_pretty(&outcomes)?),
+        ConformanceFormat::Table => {
+            let width = Check::ALL.iter().map(|check| check.name().len()).max().unwrap_or_default();
+            let transports: Vec<String> = Transport::ALL.iter().map(|transport| format!("{transport:<4}")).collect();
+            println!("{:<width$}  {:<16}  {}", "check", "reference", transports.join("  "));
+            for check in Check::ALL {
+                let results: Vec<&str> = outcomes
+                    .iter()
+                    .filter(|outcome| outcome.check == check)
+                    .map(|outcome| if outcome.passed { "pass" } else { "FAIL" })
+                    .collect();
+                println!("{:<width$}  {:<16}  {}", check.name(), check.reference(), results.join("  "));
+            }
+
+            let failures: Vec<_> = outcomes.iter().filter(|outcome| !outcome.passed).collect();
+            if !failures.is_empty() {
+                println!();
+                for outcome in failures {
+                    println!("{} ({}): {}", outcome.check, outcome.transport, outcome.failure.as_deref().unwrap_or_default());
+                }
+            }
+        }
+    }
+
+    let failed = outcomes.iter().filter(|outcome| !outcome.passed).count();
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{failed} of {} checks failed", outcomes.len()).into()),
+    }
+}
+
+/*
+Description:
+the stats subcommand: fetches the stats snapshot from a running server's admin API and prints it as pretty-printed JSON, or as CSV with one timestamp,metric,value row per counter. Nested counters are flattened into dotted names, with the entries of per-zone and per-transport lists named after their zone or transport (e.g. zones.dice.queries) rather than their position, so the names stay the same from one snapshot to the next.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) once the snapshot is printed, or an error if the admin API could not be reached or answered with an error.
+*/
+pub fn stats(args: StatsArgs) -> CommandResult {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let snapshot: serde_json::Value = runtime.block_on(async {
+        reqwest::Client::builder()
+            .timeout(Duration::from_millis(args.timeout_ms))
+            .build()?
+            .get(format!("http://{}/stats", args.admin))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    })?;
+
+    match args.format {
+        StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&snapshot)?),
+        StatsFormat::Csv => {
+            let timestamp = snapshot.get("timestamp").and_then(serde_json::Value::as_i64).unwrap_or_default();
+            let mut rows = Vec::new();
+            flatten("", &snapshot, &mut rows);
+            if !args.no_header {
+                println!("timestamp,metric,value");
+            }
+            for (metric, value) in rows.iter().filter(|(metric, _)| metric != "timestamp") {
+                println!("{timestamp},{},{}", csv_field(metric), csv_field(value));
+            }
+        }
+    }
+    Ok(())
+}
+
+/*
+Description:
+the completions subcommand: prints a shell's completion script, generated from the command line's definition so it always matches the flags of the binary it came from, e.g. for packages to install in /usr/share/bash-completion/completions.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) once the script is printed.
+*/
+pub fn completions(args: CompletionsArgs) -> CommandResult {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(args.shell, &mut command, name, &mut io::stdout());
+    Ok(())
+}
+
+/*
+Description:
+the manpage subcommand: renders the man page of the command line, generated from its definition like the completions. With an output directory, it also writes a page for every subcommand, named the way man expects for git-style subcommands (e.g. rusty-dns-query.1).
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) once the pages are written or printed, or an io::Error if one could not be written.
+*/
+pub fn manpage(args: ManpageArgs) -> CommandResult {
+    let command = Cli::command();
+    let Some(directory) = &args.output_dir else {
+        clap_mangen::Man::new(command).render(&mut io::stdout())?;
+        return Ok(());
+    };
+
+    std::fs::create_dir_all(directory)?;
+    let name = command.get_name().to_string();
+    for subcommand in command.get_subcommands() {
+        let page = subcommand
+            .clone()
+            .name(format!("{name}-{}", subcommand.get_name()))
+            .bin_name(format!("{name} {}", subcommand.get_name()));
+        let path = directory.join(format!("{}.1", page.get_name()));
+        clap_mangen::Man::new(page).render(&mut std::fs::File::create(&path)?)?;
+        println!("Wrote {}", path.display());
+    }
+    let path = directory.join(format!("{name}.1"));
+    clap_mangen::Man::new(command).render(&mut std::fs::File::create(&path)?)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/*
+Description:
+the blocklist subcommand: runs its own subcommand.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) if the subcommand succeeded, or the error to report.
+*/
+pub fn blocklist(args: BlocklistArgs) -> CommandResult {
+    match args.command {
+        BlocklistCommand::Compile(args) => blocklist_compile(args),
+    }
+}
+
+/*
+Description:
+the blocklist compile subcommand: loads every source like the server would, merges them without duplicates and compiles them into a file the server memory-maps at startup, so a list of a million domains neither has to be parsed nor kept on the heap. It fails if any source can't be loaded rather than writing a list with holes in it.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) once the file is written, or an error if a source failed to load or the file could not be written.
+*/
+fn blocklist_compile(args: BlocklistCompileArgs) -> CommandResult {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let loaded = runtime.block_on(blocklist::load_sources(&args.sources))?;
+
+    let mut domains = BTreeSet::new();
+    for (source, listed) in loaded {
+        eprintln!("{source}: {} domains", listed.len());
+        domains.extend(listed);
+    }
+
+    let size = blocklist::compile(&domains, &args.output).map_err(|error| format!("Failed to write {}: {error}", args.output.display()))?;
+    println!("Wrote {} unique domains to {} ({size} bytes)", domains.len(), args.output.display());
+    Ok(())
+}
+
+/*
+Description:
+the keygen subcommand: generates a TSIG key (HMAC-SHA256) with a secret from the operating system's random number generator, as a BIND key statement that BIND, nsupdate and the server can load, or a DNSSEC key.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) once the key is written or printed, or an error if it could not be generated or written.
+*/
+pub fn keygen(args: KeygenArgs) -> CommandResult {
+    if args.key_type != KeyType::Tsig {
+        return dnssec_keygen(&args);
+    }
+
+    let mut secret = vec![0; args.bytes.max(16)];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    let key = format!(
+        "key \"{}\" {{\n    algorithm hmac-sha256;\n    secret \"{}\";\n}};\n",
+        args.name,
+        base64::engine::general_purpose::STANDARD.encode(&secret)
+    );
+
+    match &args.output {
+        Some(path) => {
+            write_private(path, &key)?;
+            println!("Wrote key {} to {}", args.name, path.display());
+        }
+        None => print!("{key}"),
+    }
+    Ok(())
+}
+
+// Generates a DNSSEC key pair, writes its files the way dnssec-keygen does and prints its DNSKEY record, and for a key-signing key the DS record for the parent zone
+fn dnssec_keygen(args: &KeygenArgs) -> CommandResult {
+    let zone = Name::parse(&args.name, Some(&Name::root())).map_err(|error| format!("invalid zone {}: {error}", args.name))?;
+    let key = dnssec::KeyPair::generate(&zone, args.key_type == KeyType::Ksk)?;
+    let created = chrono::Utc::now().format("%Y%m%d%H%M%S").to_string();
+
+    std::fs::create_dir_all(&args.directory)?;
+    let public = args.directory.join(format!("{}.key", key.file_stem()));
+    let private = args.directory.join(format!("{}.private", key.file_stem()));
+    std::fs::write(&public, key.public_file(&created))?;
+    write_private(&private, &key.private_file(&created))?;
+    println!("Wrote {} and {}", public.display(), private.display());
+
+    println!("{}", key.dnskey_record());
+    if args.key_type == KeyType::Ksk {
+        println!("{}", key.ds_record());
+    }
+    Ok(())
+}
+
+/*
+Description:
+the ddns subcommand: adds a host to the dynamic zone's hosts file and prints its token, or removes one. A running server sees the change at its next update, since it reads the hosts file again for every update.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) once the hosts file is written, or an error if the label is invalid, the host to remove doesn't exist or the file could not be read or written.
+*/
+pub fn ddns(args: DdnsArgs) -> CommandResult {
+    match args.command {
+        DdnsCommand::Add(args) => {
+            let token = ddns::add_host(&args.hosts, &args.label).map_err(|error| format!("Failed to add {}: {error}", args.label))?;
+            println!("Added {} to {}; its DynDNS client updates it with the password {token}", args.label, args.hosts.display());
+            Ok(())
+        }
+        DdnsCommand::Remove(args) => match ddns::remove_host(&args.hosts, &args.label) {
+            Ok(true) => {
+                println!("Removed {} from {}", args.label, args.hosts.display());
+                Ok(())
+            }
+            Ok(false) => Err(format!("{} has no host {}", args.hosts.display(), args.label).into()),
+            Err(error) => Err(format!("Failed to remove {}: {error}", args.label).into()),
+        },
+    }
+}
+
+/*
+Description:
+the import subcommand: reads a hosted zone through its provider's API and writes it as a zone file, checked the way check-zone checks one. The records that can't be copied (e.g. Route 53 aliases) are listed on stderr, so nothing is left out silently.
+
+Parameters:
+args: the subcommand's arguments.
+
+Returns:
+Ok(()) once the zone is written or printed, or an error if the API failed, the credentials are missing or the zone file has errors.
+*/
+pub fn import(args: ImportArgs) -> CommandResult {
+    let client = import::http_client(Duration::from_millis(args.timeout_ms));
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let (zone, source): (ImportedZone, String) = match args.provider {
+        Provider::Route53 => {
+            let credentials = aws::Credentials::from_env().ok_or("Route 53 requests are signed with AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, which are not set")?;
+            let endpoint = args.endpoint.as_deref().unwrap_or(import::ROUTE53_ENDPOINT);
+            let zone = runtime.block_on(import::route53(&client, endpoint, &args.zone_id, &credentials))?;
+            (zone, format!("Route 53 zone {}", args.zone_id))
+        }
+        Provider::Cloudflare => {
+            let token = args.token.as_deref().ok_or("Cloudflare requests need an API token, given with --token or CLOUDFLARE_API_TOKEN")?;
+            let endpoint = args.endpoint.as_deref().unwrap_or(import::CLOUDFLARE_ENDPOINT);
+            let zone = runtime.block_on(import::cloudflare(&client, endpoint, &args.zone_id, token))?;
+            (zone, format!("Cloudflare zone {}", args.zone_id))
+        }
+    };
+    for skipped in &zone.skipped {
+        eprintln!("skipped {skipped}");
+    }
+
+    let text = import::zone_file(&zone, &source);
+    let origin = Name::parse(&zone.origin, Some(&Name::root())).map_err(|error| format!("invalid zone name {}: {error}", zone.origin))?;
+    let records = zonefile::parse(&text, &origin).map_err(|error| format!("The imported zone can't be read back: {error}"))?;
+    let problems = zonefile::lint(&origin, &records);
+    for problem in &problems {
+        eprintln!("{}: {}", problem.severity, problem.message);
+    }
+    if problems.iter().any(|problem| problem.severity == Severity::Error) {
+        return Err(format!("The imported zone {} has errors", zone.origin).into());
+    }
+
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, &text).map_err(|error| format!("Failed to write {}: {error}", path.display()))?;
+            println!("Wrote {} records of {} to {} ({} skipped)", records.len(), zone.origin, path.display(), zone.skipped.len());
+        }
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+// Writes a file that only its owner may read, for secrets
+fn write_private(path: &std::path::Path, contents: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+// Binds an address the way the server would and releases it again
+fn check_bind(protocol: &str, address: SocketAddr) -> io::Result<()> {
+    match protocol {
+        "udp" => std::net::UdpSocket::bind(address).map(drop),
+        _ => std::net::TcpListener::bind(address).map(drop),
+    }
+}
+
+// Checks a blocklist source: a URL if it starts with http:// or https://, as the blocklist loads it, and a readable file otherwise
+fn check_source(source: &str) -> Result<(), String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return check_url(source);
+    }
+    match std::fs::metadata(source) {
+        Ok(metadata) if metadata.is_file() => Ok(()),
+        Ok(_) => Err("not a file".to_string()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+// Checks that a URL is a well-formed http(s) URL with a host
+fn check_url(url: &str) -> Result<(), String> {
+    let url = reqwest::Url::parse(url).map_err(|error| format!("invalid URL: {error}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme {}", url.scheme()));
+    }
+    if url.host_str().unwrap_or_default().is_empty() {
+        return Err("the URL has no host".to_string());
+    }
+    Ok(())
+}
+
+// Parses a name typed on the command line; the labels are taken as they are, since some zones take arguments that aren't valid hostnames (e.g. sun's "+0530")
+fn parse_name(text: &str) -> Result<Name, Box<dyn Error + Send + Sync>> {
+    let mut name = Name::from_labels(text.split('.').filter(|label| !label.is_empty()).map(str::as_bytes))
+        .map_err(|error| format!("invalid name {text}: {error}"))?;
+    name.set_fqdn(true);
+    Ok(name)
+}
+
+// Flattens a JSON value into (dotted name, value) pairs, naming list entries after their zone, transport or first element if they have one and their position otherwise; nulls are left out
+fn flatten(name: &str, value: &serde_json::Value, rows: &mut Vec<(String, String)>) {
+    use serde_json::Value;
+
+    let join = |key: &str| if name.is_empty() { key.to_string() } else { format!("{name}.{key}") };
+    match value {
+        Value::Null => {}
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                flatten(&join(key), value, rows);
+            }
+        }
+        Value::Array(entries) => {
+            for (index, entry) in entries.iter().enumerate() {
+                match entry {
+                    // A pair such as a blocklist client and its count
+                    Value::Array(pair) if pair.len() == 2 && pair[0].is_string() => {
+                        flatten(&join(pair[0].as_str().unwrap_or_default()), &pair[1], rows)
+                    }
+                    // A zone's or transport's counters, named after it
+                    Value::Object(fields) if ["zone", "transport"].iter().any(|key| fields.get(*key).is_some_and(Value::is_string)) => {
+                        let key = if fields.contains_key("zone") { "zone" } else { "transport" };
+                        let prefix = join(fields[key].as_str().unwrap_or_default());
+                        for (field, value) in fields.iter().filter(|(field, _)| *field != key) {
+                            flatten(&format!("{prefix}.{field}"), value, rows);
+                        }
+                    }
+                    entry => flatten(&join(&index.to_string()), entry, rows),
+                }
+            }
+        }
+        Value::String(text) => rows.push((name.to_string(), text.clone())),
+        value => rows.push((name.to_string(), value.to_string())),
+    }
+}
+
+// Quotes a CSV field if it contains a comma, a quote or a line break
+fn csv_field(text: &str) -> String {
+    match text.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
+
+// Resolves a name typed at the repl's prompt: "@" is the domain, names with a trailing dot or already below the domain are taken as they are, and other names are below the domain
+fn repl_name(text: &str, domain: &str) -> Result<Name, Box<dyn Error + Send + Sync>> {
+    let lower = text.to_ascii_lowercase();
+    let domain_lower = domain.to_ascii_lowercase();
+    match text {
+        "@" => parse_name(domain),
+        _ if text.ends_with('.') || lower == domain_lower || lower.ends_with(&format!(".{domain_lower}")) => parse_name(text),
+        _ => parse_name(&format!("{text}.{domain}")),
+    }
+}
+
+// Formats an answer like dig: the header line, then each non-empty section with one record per line
+fn format_answer(answer: &Message) -> String {
+    let mut flags = Vec::new();
+    for (set, flag) in [
+        (answer.message_type() == MessageType::Response, "qr"),
+        (answer.authoritative(), "aa"),
+        (answer.truncated(), "tc"),
+        (answer.recursion_desired(), "rd"),
+        (answer.recursion_available(), "ra"),
+    ] {
+        if set {
+            flags.push(flag);
+        }
+    }
+
+    let mut text = format!(
+        ";; status: {}, id: {}, flags: {}\n",
+        rcode_name(answer.response_code()),
+        answer.id(),
+        flags.join(" ")
+    );
+    for (section, records) in [
+        ("ANSWER", answer.answers()),
+        ("AUTHORITY", answer.name_servers()),
+        ("ADDITIONAL", answer.additionals()),
+    ] {
+        if records.is_empty() {
+            continue;
+        }
+        text.push_str(&format!(";; {section} SECTION:\n"));
+        for record in records {
+            text.push_str(&format!("{record}\n"));
+        }
+    }
+    text
+}
+
+// Returns a response code's name as dig writes it, e.g. NOERROR or NXDOMAIN
+fn rcode_name(code: ResponseCode) -> String {
+    format!("{code:?}").to_uppercase()
+}
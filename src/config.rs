@@ -0,0 +1,1141 @@
+use crate::{acl::Cidr, delegation::Delegation, e164::EnumZone, failover::Failover, geodns::GeoDns, geoip::{GeoIp, Location}, records, rotation::Rotation};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use trust_dns_server::client::rr::Name;
+
+/*
+Description:
+defines the errors that can occur while loading the configuration file.
+*/
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file {}: {1:}", .0.display())]
+    Io(PathBuf, std::io::Error),
+    #[error("Invalid config file {}: {1:}", .0.display())]
+    Parse(PathBuf, toml::de::Error),
+    #[error("Invalid config file {}: {1:}", .0.display())]
+    Invalid(PathBuf, String),
+    #[error("Failed to open GeoIP database {}: {1:}", .0.display())]
+    GeoIp(PathBuf, maxminddb::MaxMindDBError),
+}
+
+/*
+Description:
+the optional TOML configuration file, for settings that don't fit on the command line, such as per-zone policies. Every section is optional, so an empty file is a valid configuration. It serializes back to TOML with the defaults filled in, which is how check-config prints the effective configuration.
+
+Example:
+[geoip]
+database = "/usr/share/GeoIP/GeoLite2-Country.mmdb"
+
+[blocklist]
+sources = ["https://example.com/hosts.txt", "/etc/rusty-dns/blocked.txt"]
+response = "null"
+allow = ["*.github.com"]
+
+[blocklist.exempt]
+admin-laptop = ["192.168.1.10"]
+
+[tunneling]
+action = "block"
+exempt = ["10.0.0.0/8"]
+
+[padding]
+encrypted_from = ["127.0.0.1"]
+
+[alerts]
+webhook = "https://hooks.slack.com/services/T000/B000/XXXX"
+servfail_rate_above = 0.05
+
+[[webhooks]]
+url = "https://example.com/canary"
+events = ["query"]
+watch = ["*.canary.mentisnovae.tech"]
+
+[[geo]]
+name = "www.example.com"
+fallback = ["203.0.113.10"]
+countries = { US = ["198.51.100.10"] }
+continents = { EU = ["192.0.2.10", "192.0.2.11"] }
+
+[[failover]]
+name = "api.example.com"
+addresses = ["192.0.2.20", "192.0.2.21"]
+check = "http"
+path = "/healthz"
+
+[[delegation]]
+zone = "svc.mentisnovae.tech"
+url = "https://inventory.example.com/dns"
+headers = { Authorization = "Bearer 5f2b..." }
+
+[[tenants]]
+domain = "example.org"
+admin_token = "c81e728d..."
+rate_limit = 500
+counter_file = "/var/lib/rusty-dns/example.org.counter"
+
+[tenants.blocklist]
+sources = ["/etc/rusty-dns/example.org-blocked.txt"]
+
+[rotation]
+policy = "round-robin"
+
+[rotation.names."api.example.com"]
+policy = "weighted"
+weights = { "192.0.2.20" = 3, "192.0.2.21" = 1 }
+
+[enum]
+zone = "e164.arpa"
+numbers = { "+44 20 7946 0000" = ["sip:reception@pbx.example.com"] }
+prefixes = { "+44 20 7946 01" = ["sip:{number}@pbx.example.com"] }
+
+[soa]
+primary = "ns1.mentisnovae.tech"
+hostmaster = "hostmaster@mentisnovae.tech"
+serial = 2024022901
+minimum = 60
+
+[counter]
+operators = ["127.0.0.1", "10.0.0.0/8"]
+max_names = 500
+
+[zones.myip]
+append_country = true
+
+[zones.fx]
+allow_countries = ["DE", "FR"]
+priority = "low"
+*/
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // The GeoIP database settings
+    pub geoip: Option<GeoIpConfig>,
+
+    // The ad/tracker blocklist settings
+    pub blocklist: Option<BlocklistConfig>,
+
+    // The DNS tunneling detector settings
+    pub tunneling: Option<TunnelingConfig>,
+
+    // How answers carried over encrypted transports are padded
+    pub padding: Option<PaddingConfig>,
+
+    // The threshold alerting settings
+    pub alerts: Option<AlertsConfig>,
+
+    // The webhooks notified of query events, each with its own events and batching
+    pub webhooks: Vec<WebhookConfig>,
+
+    // The names answered by the client's location, each with its answer sets
+    pub geo: Vec<GeoConfig>,
+
+    // The names answered with those of their addresses that pass health checks
+    pub failover: Vec<FailoverConfig>,
+
+    // The subzones answered by HTTP backends
+    pub delegation: Vec<DelegationConfig>,
+
+    // The further domains hosted, each with its own counters, rate limit, blocklist and admin token
+    pub tenants: Vec<TenantConfig>,
+
+    // How the addresses of names with several are ordered in each answer
+    pub rotation: Option<RotationConfig>,
+
+    // The telephone numbers served as an ENUM zone, if any
+    #[serde(rename = "enum")]
+    pub enum_zone: Option<EnumConfig>,
+
+    // The SOA record of the domain, sent with negative answers so resolvers can cache them
+    pub soa: SoaConfig,
+
+    // Who may read and reset the named counters of the counter zone, and how many there can be
+    pub counter: CounterConfig,
+
+    // Per-zone settings, keyed by the zone's keyword (e.g. "myip" or "fx")
+    pub zones: BTreeMap<String, ZoneConfig>,
+
+    // The GeoIP database opened from the [geoip] section when the file was loaded
+    #[serde(skip)]
+    pub geoip_db: Option<Arc<GeoIp>>,
+}
+
+/*
+Description:
+the [geoip] section, locating the MaxMind database used for country lookups.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GeoIpConfig {
+    // Path to a GeoLite2/GeoIP2 Country or City database in MaxMind DB format
+    pub database: PathBuf,
+}
+
+/*
+Description:
+the [blocklist] section, configuring Pi-hole-style blocking of ad and tracker domains.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BlocklistConfig {
+    // Blocklist files or http(s) URLs, in hosts or plain domain-list format
+    #[serde(default)]
+    pub sources: Vec<String>,
+
+    // A blocklist compiled with `rusty-dns blocklist compile`, memory-mapped instead of loaded
+    #[serde(default)]
+    pub compiled: Option<PathBuf>,
+
+    // How often (in seconds) the sources are reloaded
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u64,
+
+    // How blocked names are answered
+    #[serde(default)]
+    pub response: BlockResponse,
+
+    // Names that are never blocked: "example.com" allows just that name, "*.example.com" allows it and everything below it
+    #[serde(default)]
+    pub allow: Vec<String>,
+
+    // Named groups of clients (networks in CIDR notation) that bypass blocking, e.g. admin-laptop = ["192.168.1.10"]
+    #[serde(default)]
+    pub exempt: BTreeMap<String, Vec<Cidr>>,
+}
+
+// Blocklists are refreshed once a day unless configured otherwise
+fn default_refresh_interval() -> u64 {
+    86400
+}
+
+/*
+Description:
+how blocked names are answered: with NXDOMAIN, or with the null address (0.0.0.0 or ::) so clients fail fast without retrying other resolvers.
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockResponse {
+    #[default]
+    Nxdomain,
+    Null,
+}
+
+/*
+Description:
+the [tunneling] section, turning on the detector that scores clients for signs of DNS tunneling (data smuggled in and out through query names and TXT answers) and logs or blocks those that look like it.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TunnelingConfig {
+    // What is done with clients suspected of tunneling
+    pub action: TunnelingAction,
+
+    // How long (in seconds) each client's queries are scored over before its counts start over
+    pub window: u64,
+
+    // The fewest queries in a window for a client to be scored, so a handful of odd names doesn't flag it
+    pub min_queries: u64,
+
+    // The score (from 0 to 1) above which a client is suspected of tunneling
+    pub score_above: f64,
+
+    // How long (in seconds) a client stays suspected, and blocked if the action is "block", once flagged
+    pub block_for: u64,
+
+    // Networks (in CIDR notation) whose clients are never scored, e.g. monitoring that queries random names on purpose
+    pub exempt: Vec<Cidr>,
+
+    // The most clients scored at once; the least recently seen are forgotten when there are more
+    pub max_clients: usize,
+}
+
+impl Default for TunnelingConfig {
+    fn default() -> Self {
+        TunnelingConfig {
+            action: TunnelingAction::default(),
+            window: 60,
+            min_queries: 20,
+            score_above: 0.8,
+            block_for: 600,
+            exempt: Vec::new(),
+            max_clients: 10000,
+        }
+    }
+}
+
+/*
+Description:
+what is done with a client suspected of tunneling: its suspicion is logged and its queries answered as usual, or its queries are refused until the suspicion lapses.
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelingAction {
+    #[default]
+    Log,
+    Block,
+}
+
+/*
+Description:
+the [padding] section: EDNS padding (RFC 7830) of the answers that travel encrypted, so their length gives away less of what was asked. The server speaks plain UDP and TCP, so DNS over TLS, HTTPS or QUIC is served by a proxy in front of it that terminates the encryption; queries from the proxy's addresses count as encrypted, and their answers are padded if the query asked for it with a Padding option.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaddingConfig {
+    // How the padded length is chosen
+    pub policy: PaddingPolicy,
+
+    // The block size answers are padded to a multiple of, or the most octets of random padding
+    pub block_size: u16,
+
+    // The addresses (in CIDR notation) of the proxies that terminate DoT, DoH or DoQ in front of the server
+    pub encrypted_from: Vec<Cidr>,
+}
+
+impl Default for PaddingConfig {
+    fn default() -> Self {
+        // 468 octets is the block size RFC 8467 recommends for responses
+        PaddingConfig { policy: PaddingPolicy::default(), block_size: 468, encrypted_from: Vec::new() }
+    }
+}
+
+/*
+Description:
+the padding policies of RFC 8467: padding to the next multiple of a block size, or with a random number of octets up to it.
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PaddingPolicy {
+    #[default]
+    Block,
+    Random,
+}
+
+/*
+Description:
+the [alerts] section: thresholds that are checked periodically and a webhook that is called when one is crossed and again when it clears. Thresholds left out are not checked.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AlertsConfig {
+    // The URL the alerts are POSTed to
+    pub webhook: String,
+
+    // The payload format the webhook expects
+    #[serde(default)]
+    pub format: WebhookFormat,
+
+    // The PagerDuty integration key (required by the "pagerduty" format)
+    #[serde(serialize_with = "redacted")]
+    pub routing_key: Option<String>,
+
+    // How often (in seconds) the thresholds are checked; rates are measured over this interval
+    #[serde(default = "default_alert_interval")]
+    pub interval: u64,
+
+    // How long (in seconds) to wait before repeating an alert that is still firing
+    #[serde(default = "default_alert_cooldown")]
+    pub cooldown: u64,
+
+    // Alert when the average queries per second over the interval exceeds this
+    pub qps_above: Option<f64>,
+
+    // Alert when the share of queries answered with SERVFAIL over the interval exceeds this, e.g. 0.05 for 5%
+    pub servfail_rate_above: Option<f64>,
+
+    // The fewest queries in an interval for the SERVFAIL rate to be checked, so a handful of failures on an idle server doesn't alert
+    #[serde(default = "default_alert_min_queries")]
+    pub min_queries: u64,
+
+    // Alert when more than this many upstream lookups (the fx zone's exchange-rate API) fail in an interval
+    pub upstream_failures_above: Option<u64>,
+}
+
+// Thresholds are checked every minute unless configured otherwise
+fn default_alert_interval() -> u64 {
+    60
+}
+
+// Alerts that keep firing are repeated every 15 minutes unless configured otherwise
+fn default_alert_cooldown() -> u64 {
+    900
+}
+
+// The SERVFAIL rate is only checked over at least 100 queries unless configured otherwise
+fn default_alert_min_queries() -> u64 {
+    100
+}
+
+// Writes a secret as asterisks, so printing the configuration doesn't leak it
+fn redacted<S: serde::Serializer>(secret: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match secret {
+        Some(_) => serializer.serialize_some("********"),
+        None => serializer.serialize_none(),
+    }
+}
+
+/*
+Description:
+the payload sent to the alert webhook: a Slack-style {"text": ...} message, which most chat tools accept, or a PagerDuty Events API v2 event.
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    #[default]
+    Slack,
+    Pagerduty,
+}
+
+/*
+Description:
+a [[webhooks]] entry: a URL that is POSTed batches of JSON events as queries happen, retried with backoff if it fails.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    // The URL the batches are POSTed to
+    pub url: String,
+
+    // The events sent to the webhook
+    pub events: Vec<WebhookEvent>,
+
+    // The names whose queries are "query" events: "example.com" watches just that name, "*.example.com" it and everything below it
+    #[serde(default)]
+    pub watch: Vec<String>,
+
+    // The number of NXDOMAIN answers per batch interval above which an "nxdomain_spike" event is sent
+    #[serde(default)]
+    pub nxdomain_spike_above: Option<u64>,
+
+    // A secret the body of each batch is signed with (HMAC-SHA256, in the X-Rusty-DNS-Signature header), so the receiver can tell it came from the server
+    #[serde(default, serialize_with = "redacted")]
+    pub secret: Option<String>,
+
+    // How often (in seconds) pending events are sent
+    #[serde(default = "default_webhook_batch_interval")]
+    pub batch_interval: u64,
+
+    // The most events sent in one batch; events beyond it wait for the next
+    #[serde(default = "default_webhook_batch_size")]
+    pub batch_size: usize,
+
+    // The most events kept waiting while the webhook is failing; newer events are dropped beyond it
+    #[serde(default = "default_webhook_queue_size")]
+    pub queue_size: usize,
+
+    // How many times a failed batch is retried, waiting twice as long each time, before it is dropped
+    #[serde(default = "default_webhook_retries")]
+    pub retries: u32,
+}
+
+/*
+Description:
+the events a webhook can be sent: a query for a watched name, a query answered from the blocklist, and more NXDOMAIN answers in a batch interval than the webhook's threshold.
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Query,
+    Blocked,
+    NxdomainSpike,
+}
+
+// Webhook events are sent every 10 seconds unless configured otherwise
+fn default_webhook_batch_interval() -> u64 {
+    10
+}
+
+// Batches hold up to 100 events unless configured otherwise
+fn default_webhook_batch_size() -> usize {
+    100
+}
+
+// Up to 10000 events wait for a failing webhook unless configured otherwise
+fn default_webhook_queue_size() -> usize {
+    10000
+}
+
+// Failed batches are retried 5 times unless configured otherwise
+fn default_webhook_retries() -> u32 {
+    5
+}
+
+/*
+Description:
+a [[geo]] section: the answer sets of a name and type, tagged by country or continent, of which clients get the one matching their location. A country's set takes precedence over its continent's, and clients whose location has no set, or is unknown, get the fallback set.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GeoConfig {
+    // The name answered, e.g. "www.example.com"
+    pub name: String,
+
+    // The type of the records answered
+    #[serde(rename = "type", default = "default_geo_type")]
+    pub record_type: String,
+
+    // The TTL of the records answered, kept short so clients that move pick up their new set
+    #[serde(default = "default_geo_ttl")]
+    pub ttl: u32,
+
+    // The answer sets of ISO country codes, each record's data written as in a zone file
+    #[serde(default)]
+    pub countries: BTreeMap<String, Vec<String>>,
+
+    // The answer sets of continent codes (AF, AN, AS, EU, NA, OC, SA)
+    #[serde(default)]
+    pub continents: BTreeMap<String, Vec<String>>,
+
+    // The answer set of every other client
+    pub fallback: Vec<String>,
+}
+
+// GeoDNS answers A records unless configured otherwise
+fn default_geo_type() -> String {
+    "A".to_string()
+}
+
+// GeoDNS answers have a TTL of 60 seconds unless configured otherwise
+fn default_geo_ttl() -> u32 {
+    60
+}
+
+/*
+Description:
+a [[failover]] section: a name answered with the addresses of its targets that pass active health checks, so clients are steered away from targets that are down. A target is marked down after unhealthy_threshold failed checks in a row and up again after healthy_threshold passed ones; every target starts up.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FailoverConfig {
+    // The name answered, e.g. "api.example.com"
+    pub name: String,
+
+    // The targets' addresses, answered as A or AAAA records
+    pub addresses: Vec<IpAddr>,
+
+    // How the targets are checked
+    #[serde(default)]
+    pub check: HealthCheck,
+
+    // The port checked, 80 for http and 443 for https checks unless given; tcp checks need one
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    // The path requested by http and https checks
+    #[serde(default = "default_failover_path")]
+    pub path: String,
+
+    // How often (in seconds) the targets are checked
+    #[serde(default = "default_failover_interval")]
+    pub interval: u64,
+
+    // How long (in seconds) a check may take before it fails
+    #[serde(default = "default_failover_timeout")]
+    pub timeout: u64,
+
+    // The checks in a row a target that is down has to pass to be answered again
+    #[serde(default = "default_failover_healthy_threshold")]
+    pub healthy_threshold: u32,
+
+    // The checks in a row a target that is up has to fail to stop being answered
+    #[serde(default = "default_failover_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+
+    // The TTL of the records answered, kept short so clients move off a failed target quickly
+    #[serde(default = "default_failover_ttl")]
+    pub ttl: u32,
+
+    // Whether every address is answered when all the targets are down, as a client trying a dead address is no worse off than one without any
+    #[serde(default = "default_failover_fail_open")]
+    pub fail_open: bool,
+}
+
+/*
+Description:
+how failover targets are checked: by connecting to a TCP port, or by a GET request over http or https that has to be answered with a 2xx or 3xx status. https checks verify the certificate against the record's name.
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheck {
+    #[default]
+    Tcp,
+    Http,
+    Https,
+}
+
+// Failover checks request / unless configured otherwise
+fn default_failover_path() -> String {
+    "/".to_string()
+}
+
+// Failover targets are checked every 10 seconds unless configured otherwise
+fn default_failover_interval() -> u64 {
+    10
+}
+
+// Failover checks time out after 2 seconds unless configured otherwise
+fn default_failover_timeout() -> u64 {
+    2
+}
+
+// Failover targets are answered again after 2 passed checks unless configured otherwise
+fn default_failover_healthy_threshold() -> u32 {
+    2
+}
+
+// Failover targets stop being answered after 3 failed checks unless configured otherwise
+fn default_failover_unhealthy_threshold() -> u32 {
+    3
+}
+
+// Failover answers have a TTL of 30 seconds unless configured otherwise
+fn default_failover_ttl() -> u32 {
+    30
+}
+
+// Failover answers every address when all targets are down unless configured otherwise
+fn default_failover_fail_open() -> bool {
+    true
+}
+
+/*
+Description:
+a [[delegation]] section: a subzone whose names are answered by an HTTP backend, which is sent each query as JSON in a POST request and answers with the records as JSON. Answers are cached for the lowest TTL among their records, and once the backend has failed failure_threshold times in a row it isn't asked for open_for seconds (the circuit is open), so a backend that is down doesn't hold every query for the timeout.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DelegationConfig {
+    // The subzone delegated, e.g. "svc.mentisnovae.tech"; every name below it is answered by the backend
+    pub zone: String,
+
+    // The URL queries are POSTed to
+    pub url: String,
+
+    // Headers sent with every request, e.g. { Authorization = "Bearer ..." }
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    // How long (in milliseconds) the backend has to answer before the query gets SERVFAIL
+    #[serde(default = "default_delegation_timeout_ms")]
+    pub timeout_ms: u64,
+
+    // The most answers cached, forgetting the least recently used beyond it
+    #[serde(default = "default_delegation_cache_size")]
+    pub cache_size: usize,
+
+    // The longest (in seconds) an answer is cached, whatever the TTLs of its records
+    #[serde(default = "default_delegation_max_cache_ttl")]
+    pub max_cache_ttl: u32,
+
+    // How long (in seconds) answers without records, and NXDOMAIN answers, are cached
+    #[serde(default = "default_delegation_negative_ttl")]
+    pub negative_ttl: u32,
+
+    // The TTL of records the backend gives none for
+    #[serde(default = "default_delegation_ttl")]
+    pub ttl: u32,
+
+    // The failures in a row after which the backend is no longer asked
+    #[serde(default = "default_delegation_failure_threshold")]
+    pub failure_threshold: u32,
+
+    // How long (in seconds) the backend is left alone once it has failed too often, before a single query tries it again
+    #[serde(default = "default_delegation_open_for")]
+    pub open_for: u64,
+}
+
+// Delegated queries wait a second for the backend unless configured otherwise, well within resolvers' own timeouts
+fn default_delegation_timeout_ms() -> u64 {
+    1000
+}
+
+// Delegations cache 1000 answers unless configured otherwise
+fn default_delegation_cache_size() -> usize {
+    1000
+}
+
+// Delegated answers are cached for at most 5 minutes unless configured otherwise
+fn default_delegation_max_cache_ttl() -> u32 {
+    300
+}
+
+// Negative delegated answers are cached for 30 seconds unless configured otherwise
+fn default_delegation_negative_ttl() -> u32 {
+    30
+}
+
+// Delegated records without a TTL have one of 60 seconds unless configured otherwise
+fn default_delegation_ttl() -> u32 {
+    60
+}
+
+// A backend is left alone after 5 failures in a row unless configured otherwise
+fn default_delegation_failure_threshold() -> u32 {
+    5
+}
+
+// A failing backend is left alone for 30 seconds unless configured otherwise
+fn default_delegation_open_for() -> u64 {
+    30
+}
+
+/*
+Description:
+a [[tenants]] section: a further domain the server hosts, answered by the same zones as the main domain but with state of its own, so one domain's traffic can't show in or eat into another's: its own request and named counters, statistics, rate limit and blocklist, and an admin API token that only sees them. The policies of the main domain, such as the [zones] settings, the [soa] record and the [tunneling] detector, apply to it too.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TenantConfig {
+    // The hosted domain, e.g. "example.org"
+    pub domain: String,
+
+    // The token the domain's owner sends to the admin API, as "Authorization: Bearer <token>", to see the domain's state and no other
+    #[serde(default, serialize_with = "redacted")]
+    pub admin_token: Option<String>,
+
+    // The most queries per second the domain answers, 0 for no limit
+    #[serde(default)]
+    pub rate_limit: u32,
+
+    // The file the domain's counters are kept in across restarts
+    #[serde(default)]
+    pub counter_file: Option<PathBuf>,
+
+    // The domain's blocklist; the main domain's [blocklist] doesn't apply to it
+    #[serde(default)]
+    pub blocklist: Option<BlocklistConfig>,
+}
+
+/*
+Description:
+the [rotation] section: how the A and AAAA records of names with several addresses are ordered in each answer, for basic load distribution between backends, since most clients use the first address. The policy applies to every name, and the names table gives single names their own policy.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RotationConfig {
+    // The policy of every name without its own
+    pub policy: RotationPolicy,
+
+    // The policies of single names, e.g. "api.example.com" = { policy = "weighted", weights = { "192.0.2.20" = 3 } }
+    pub names: BTreeMap<String, NameRotationConfig>,
+}
+
+/*
+Description:
+the rotation policy of a single name, with the weights of its addresses if it is weighted and how many of them each answer has.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NameRotationConfig {
+    // How the name's addresses are ordered
+    pub policy: RotationPolicy,
+
+    // The weights of the name's addresses under the weighted policy; addresses left out weigh 1 and those weighing 0 come last
+    #[serde(default)]
+    pub weights: BTreeMap<String, u32>,
+
+    // How many addresses each answer has, all of them if not given
+    #[serde(default)]
+    pub count: Option<usize>,
+}
+
+/*
+Description:
+how the addresses of a name are ordered in each answer: as the backend returns them, rotated by one place per answer, shuffled, or shuffled so that each address comes first in proportion to its weight.
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RotationPolicy {
+    #[default]
+    Fixed,
+    RoundRobin,
+    Shuffle,
+    Weighted,
+}
+
+/*
+Description:
+the [enum] section: an ENUM zone (RFC 6116) mapping telephone numbers to URIs, such as the SIP addresses of a lab's phones, for VoIP equipment that looks numbers up in e164.arpa. Numbers are written in E.164 form, with or without the + and separators.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EnumConfig {
+    // The zone the numbers are served under, e.g. "e164.arpa" or "e164.lab.example.com"
+    #[serde(default = "default_enum_zone")]
+    pub zone: String,
+
+    // The TTL of the NAPTR records
+    #[serde(default = "default_enum_ttl")]
+    pub ttl: u32,
+
+    // The URIs of single numbers, e.g. "+44 20 7946 0000" = ["sip:reception@pbx.example.com"]
+    #[serde(default)]
+    pub numbers: BTreeMap<String, Vec<String>>,
+
+    // The URI templates of every number starting with a prefix, in which {number} stands for the number in E.164 form
+    #[serde(default)]
+    pub prefixes: BTreeMap<String, Vec<String>>,
+}
+
+// ENUM numbers are served under e164.arpa unless configured otherwise
+fn default_enum_zone() -> String {
+    "e164.arpa".to_string()
+}
+
+// ENUM records have a TTL of 300 seconds unless configured otherwise
+fn default_enum_ttl() -> u32 {
+    300
+}
+
+/*
+Description:
+the [soa] section: the SOA record of the domain, which the authority section of NXDOMAIN and NODATA answers carries so that resolvers cache them for its negative TTL (RFC 2308) instead of asking again at once. Every field has a default, so negative answers carry an SOA record without the section.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SoaConfig {
+    // The primary name server of the domain; ns1.<domain> if not given
+    pub primary: Option<String>,
+
+    // The mailbox of the domain's administrator, as a name or an address; hostmaster.<domain> if not given
+    pub hostmaster: Option<String>,
+
+    // The serial number of the zone
+    pub serial: u32,
+
+    // How often (in seconds) secondaries check the serial, and retry if that fails
+    pub refresh: u32,
+    pub retry: u32,
+
+    // How long (in seconds) secondaries keep serving the zone without reaching the primary
+    pub expire: u32,
+
+    // How long (in seconds) resolvers cache negative answers
+    pub minimum: u32,
+
+    // The TTL of the SOA record itself; negative answers carry it with the lower of this and the minimum
+    pub ttl: u32,
+}
+
+impl Default for SoaConfig {
+    fn default() -> Self {
+        SoaConfig {
+            primary: None,
+            hostmaster: None,
+            serial: 1,
+            refresh: 3600,
+            retry: 900,
+            expire: 604800,
+            minimum: 300,
+            ttl: 3600,
+        }
+    }
+}
+
+/*
+Description:
+the [counter] section: the clients allowed to read the named counters of the counter zone without incrementing them and to reset them, and how many named counters are kept. Without it nobody may, and up to 1000 counters are kept.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CounterConfig {
+    // The networks whose clients may peek at and reset counters, e.g. "10.0.0.0/8"
+    pub operators: Vec<Cidr>,
+
+    // The most named counters kept; increments of a new name are refused once there are this many
+    pub max_names: usize,
+}
+
+impl Default for CounterConfig {
+    fn default() -> Self {
+        CounterConfig { operators: Vec::new(), max_names: 1000 }
+    }
+}
+
+impl CounterConfig {
+    // Returns whether a client may peek at and reset counters
+    pub fn is_operator(&self, ip: IpAddr) -> bool {
+        self.operators.iter().any(|network| network.contains(ip))
+    }
+}
+
+/*
+Description:
+the settings of a single zone, from its [zones.<keyword>] section.
+*/
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZoneConfig {
+    // ISO country codes the zone answers; queries from anywhere else are refused. Empty means every country.
+    pub allow_countries: Vec<String>,
+
+    // ISO country codes whose queries are refused, taking precedence over allow_countries
+    pub deny_countries: Vec<String>,
+
+    // Whether to append the client's country code to the answer (only used by the myip zone)
+    pub append_country: bool,
+
+    // How readily the zone's queries are shed when the server is overloaded
+    pub priority: Priority,
+
+    // Log one in every log_sample successful queries at debug level, overriding --log-sample
+    pub log_sample: Option<u64>,
+
+    // Log one in every log_error_sample failed queries at debug level; every failure is logged by default
+    pub log_error_sample: Option<u64>,
+
+    // The TTL of the records the zone computes, instead of its default (60 seconds for most zones)
+    pub ttl: Option<u32>,
+}
+
+/*
+Description:
+the priority of a zone under overload: low-priority zones are shed first, then normal ones, and critical zones are always answered.
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Critical,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl ZoneConfig {
+    /*
+    Description:
+    checks whether the zone may answer a client from a given country. Clients whose country is unknown (private addresses, addresses missing from the database) are only refused when an allow list is set.
+
+    Parameters:
+    country: the client's ISO country code, if known.
+
+    Returns:
+    true if the zone may answer the client.
+    */
+    pub fn permits_country(&self, country: Option<&str>) -> bool {
+        match country {
+            Some(country) => {
+                !self.deny_countries.iter().any(|denied| denied.eq_ignore_ascii_case(country))
+                    && (self.allow_countries.is_empty()
+                        || self.allow_countries.iter().any(|allowed| allowed.eq_ignore_ascii_case(country)))
+            }
+            None => self.allow_countries.is_empty(),
+        }
+    }
+
+    // Returns true if any setting needs the client's country
+    pub fn uses_geoip(&self) -> bool {
+        !self.allow_countries.is_empty() || !self.deny_countries.is_empty() || self.append_country
+    }
+}
+
+impl Config {
+    /*
+    Description:
+    loads and validates the configuration file, opening the GeoIP database if one is configured so a missing or broken database is reported at startup.
+
+    Parameters:
+    path: the TOML configuration file.
+
+    Returns:
+    Ok(Config) if the file was read and is valid, or a ConfigError describing the problem.
+    */
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        // Read and parse the file
+        let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Io(path.to_path_buf(), error))?;
+        let mut config: Config = toml::from_str(&text).map_err(|error| ConfigError::Parse(path.to_path_buf(), error))?;
+
+        // Open the GeoIP database
+        if let Some(geoip) = &config.geoip {
+            let database = GeoIp::open(&geoip.database)
+                .map_err(|error| ConfigError::GeoIp(geoip.database.clone(), error))?;
+            config.geoip_db = Some(Arc::new(database));
+        }
+
+        // Country policies are useless without a database to look countries up in
+        if config.geoip.is_none() {
+            if let Some((zone, _)) = config.zones.iter().find(|(_, zone)| zone.uses_geoip()) {
+                return Err(ConfigError::Invalid(
+                    path.to_path_buf(),
+                    format!("zone {zone} uses GeoIP but there is no [geoip] database"),
+                ));
+            }
+        }
+
+        // GeoDNS needs a database to locate clients in, and answer sets that are valid records
+        if !config.geo.is_empty() {
+            if config.geoip.is_none() {
+                return Err(ConfigError::Invalid(path.to_path_buf(), "[[geo]] answers need a [geoip] database".to_string()));
+            }
+            GeoDns::new(&config.geo).map_err(|error| ConfigError::Invalid(path.to_path_buf(), error))?;
+        }
+
+        // Failover names need targets and a way to check them
+        if !config.failover.is_empty() {
+            Failover::new(&config.failover).map_err(|error| ConfigError::Invalid(path.to_path_buf(), error))?;
+        }
+
+        // Delegated subzones need valid names, given once, and backends that can be reached
+        if !config.delegation.is_empty() {
+            Delegation::new(&config.delegation).map_err(|error| ConfigError::Invalid(path.to_path_buf(), error))?;
+        }
+
+        // Hosted domains need valid names, given once, admin tokens that tell them apart, and blocklists with something to block
+        let mut domains = HashSet::new();
+        let mut tokens = HashSet::new();
+        for tenant in &config.tenants {
+            let problem = match () {
+                _ if tenant.domain.trim_end_matches('.').is_empty() || Name::parse(&tenant.domain, Some(&Name::root())).is_err() => {
+                    Some("is not a valid domain")
+                }
+                _ if !domains.insert(tenant.domain.trim_end_matches('.').to_ascii_lowercase()) => Some("is given more than once"),
+                _ if tenant.admin_token.as_deref().is_some_and(str::is_empty) => Some("has an empty admin_token"),
+                _ if tenant.admin_token.as_ref().is_some_and(|token| !tokens.insert(token.clone())) => Some("shares its admin_token with another tenant"),
+                _ if tenant.blocklist.as_ref().is_some_and(|blocklist| blocklist.sources.is_empty() && blocklist.compiled.is_none()) => {
+                    Some("has a blocklist without sources or a compiled list")
+                }
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                return Err(ConfigError::Invalid(path.to_path_buf(), format!("the tenant {} {problem}", tenant.domain)));
+            }
+        }
+
+        // Rotation weights need to be given to addresses
+        if let Some(rotation) = &config.rotation {
+            Rotation::new(rotation).map_err(|error| ConfigError::Invalid(path.to_path_buf(), error))?;
+        }
+
+        // ENUM numbers need to be numbers, mapped to URIs ENUM has services for
+        if let Some(enum_zone) = &config.enum_zone {
+            EnumZone::new(enum_zone).map_err(|error| ConfigError::Invalid(path.to_path_buf(), error))?;
+        }
+
+        // The SOA record needs valid names
+        records::soa_record(&Name::root(), &config.soa).map_err(|error| ConfigError::Invalid(path.to_path_buf(), error))?;
+
+        // A blocklist needs something to block
+        if let Some(blocklist) = &config.blocklist {
+            if blocklist.sources.is_empty() && blocklist.compiled.is_none() {
+                return Err(ConfigError::Invalid(
+                    path.to_path_buf(),
+                    "the blocklist needs sources or a compiled list".to_string(),
+                ));
+            }
+        }
+
+        // Tunneling scores run from 0 to 1 and are measured over at least a second and a query
+        if let Some(tunneling) = &config.tunneling {
+            let problem = match () {
+                _ if !(0.0..1.0).contains(&tunneling.score_above) => Some("score_above has to be from 0 to below 1"),
+                _ if tunneling.window == 0 => Some("window has to be at least 1 second"),
+                _ if tunneling.min_queries == 0 => Some("min_queries has to be at least 1"),
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                return Err(ConfigError::Invalid(path.to_path_buf(), format!("[tunneling] {problem}")));
+            }
+        }
+
+        // Padding needs a block size and proxies whose queries come encrypted
+        if let Some(padding) = &config.padding {
+            let problem = match () {
+                _ if padding.block_size == 0 => Some("block_size has to be at least 1"),
+                _ if padding.encrypted_from.is_empty() => Some("needs the addresses of the proxies in encrypted_from"),
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                return Err(ConfigError::Invalid(path.to_path_buf(), format!("[padding] {problem}")));
+            }
+        }
+
+        // PagerDuty events can't be routed without an integration key
+        if let Some(alerts) = &config.alerts {
+            if alerts.format == WebhookFormat::Pagerduty && alerts.routing_key.is_none() {
+                return Err(ConfigError::Invalid(
+                    path.to_path_buf(),
+                    "the pagerduty alert format needs a routing_key".to_string(),
+                ));
+            }
+        }
+
+        // Webhooks need something to notify of, and query events need names to watch
+        for webhook in &config.webhooks {
+            let problem = match () {
+                _ if webhook.events.is_empty() => Some("needs events"),
+                _ if webhook.events.contains(&WebhookEvent::Query) && webhook.watch.is_empty() => Some("has query events but no names to watch"),
+                _ if webhook.events.contains(&WebhookEvent::NxdomainSpike) && webhook.nxdomain_spike_above.is_none() => {
+                    Some("has nxdomain_spike events but no nxdomain_spike_above threshold")
+                }
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                return Err(ConfigError::Invalid(path.to_path_buf(), format!("the webhook {} {problem}", webhook.url)));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /*
+    Description:
+    returns the settings of a zone.
+
+    Parameters:
+    zone: the zone's keyword.
+
+    Returns:
+    Some(&ZoneConfig) if the zone has a section in the file, None otherwise.
+    */
+    pub fn zone(&self, zone: &str) -> Option<&ZoneConfig> {
+        self.zones.get(zone)
+    }
+
+    /*
+    Description:
+    looks up the country of a client in the GeoIP database.
+
+    Parameters:
+    ip: the client's address.
+
+    Returns:
+    Some(String) with the ISO country code, or None if there is no database or the address is not in it.
+    */
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        self.geoip_db.as_ref()?.country(ip)
+    }
+
+    /*
+    Description:
+    returns the configuration a hosted domain is served with: the main domain's policies, which aren't tied to its names or state, and the tenant's own blocklist. The sections that answer names of the main domain, such as [[geo]] or [[delegation]], are left out.
+
+    Parameters:
+    tenant: the [[tenants]] section of the domain.
+
+    Returns:
+    The Config the domain's handler is built from.
+    */
+    pub fn for_tenant(&self, tenant: &TenantConfig) -> Config {
+        Config {
+            geoip: self.geoip.clone(),
+            geoip_db: self.geoip_db.clone(),
+            blocklist: tenant.blocklist.clone(),
+            tunneling: self.tunneling.clone(),
+            padding: self.padding.clone(),
+            soa: self.soa.clone(),
+            counter: self.counter.clone(),
+            zones: self.zones.clone(),
+            ..Default::default()
+        }
+    }
+
+    // Returns the country and continent of an address, neither known if there is no GeoIP database
+    pub fn locate(&self, ip: IpAddr) -> Location {
+        self.geoip_db.as_ref().map(|geoip| geoip.locate(ip)).unwrap_or_default()
+    }
+}
@@ -0,0 +1,353 @@
+use crate::{client, random};
+use rand::Rng;
+use serde::Serialize;
+use std::{fmt, net::SocketAddr, time::Duration};
+use trust_dns_server::{
+    client::rr::{Name, RecordType},
+    proto::op::{Edns, Message, MessageType, OpCode, Query, ResponseCode},
+};
+
+// The largest answer a client without EDNS accepts over UDP (RFC 1035 section 4.2.1).
+const MAX_PLAIN_UDP: usize = 512;
+
+// The payload size advertised in queries with EDNS, the size recommended by DNS Flag Day 2020.
+const EDNS_PAYLOAD: u16 = 1232;
+
+// A record type no server knows, from the private use range (RFC 6895 section 3.1).
+const UNKNOWN_TYPE: u16 = 65280;
+
+// The name below the domain whose answer is too long for a UDP answer without EDNS: the 3000th Fibonacci number has 627 digits.
+const LONG_ANSWER: &str = "fib.3000";
+
+/*
+Description:
+the protocol behaviors the conformance suite checks, each one required of an authoritative server by an RFC.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    // The answer is a response to the query: same ID, QR and RD, the query op code and the question echoed
+    Header,
+
+    // The question is echoed with the case it was asked in
+    CasePreservation,
+
+    // A query for a type the server doesn't know gets no records of other types
+    UnknownType,
+
+    // A query with EDNS gets an answer with EDNS
+    Edns,
+
+    // A query with an EDNS version the server doesn't support gets BADVERS
+    EdnsVersion,
+
+    // An answer too long for UDP is truncated with TC set, and arrives whole over TCP
+    Truncation,
+
+    // A query with an op code the server doesn't implement gets NOTIMP
+    UnknownOpCode,
+
+    // A dynamic update, which the server doesn't support, gets NOTIMP or REFUSED
+    Update,
+}
+
+impl Check {
+    // Every check, in the order they are run and reported
+    pub const ALL: [Check; 8] = [
+        Check::Header,
+        Check::CasePreservation,
+        Check::UnknownType,
+        Check::Edns,
+        Check::EdnsVersion,
+        Check::Truncation,
+        Check::UnknownOpCode,
+        Check::Update,
+    ];
+
+    // Returns the check's name, as it is reported
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::Header => "header",
+            Check::CasePreservation => "case-preservation",
+            Check::UnknownType => "unknown-type",
+            Check::Edns => "edns",
+            Check::EdnsVersion => "edns-version",
+            Check::Truncation => "truncation",
+            Check::UnknownOpCode => "unknown-opcode",
+            Check::Update => "update",
+        }
+    }
+
+    // Returns the RFC section that requires the behavior
+    pub fn reference(self) -> &'static str {
+        match self {
+            Check::Header => "RFC 1035 4.1.1",
+            Check::CasePreservation => "RFC 4343 4.1",
+            Check::UnknownType => "RFC 3597 3",
+            Check::Edns => "RFC 6891 7",
+            Check::EdnsVersion => "RFC 6891 6.1.3",
+            Check::Truncation => "RFC 2181 9",
+            Check::UnknownOpCode => "RFC 1035 4.1.1",
+            Check::Update => "RFC 2136 3",
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.name())
+    }
+}
+
+/*
+Description:
+the transports each check is run over.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+impl Transport {
+    // Both transports, in the order they are reported
+    pub const ALL: [Transport; 2] = [Transport::Udp, Transport::Tcp];
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            Transport::Udp => "udp",
+            Transport::Tcp => "tcp",
+        })
+    }
+}
+
+/*
+Description:
+the result of one check over one transport: whether the server passed it, and if not, what it did instead.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct Outcome {
+    // The check
+    pub check: Check,
+
+    // The RFC section that requires the behavior
+    pub reference: &'static str,
+
+    // The transport the check was run over
+    pub transport: Transport,
+
+    // Whether the server behaved as required
+    pub passed: bool,
+
+    // What the server did wrong, if it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+}
+
+/*
+Description:
+runs every check over UDP and TCP against a running server, one query at a time, for the conformance subcommand and the conformance tests. The queries go to names below the domain that every instance answers, so the server needs no configuration beyond its domain.
+
+Parameters:
+server: the server's address, for both UDP and TCP.
+domain: the domain the server answers for.
+timeout: how long to wait for each answer; a query that gets no answer fails its check.
+
+Returns:
+The outcomes, in the order of Check::ALL, each over UDP then TCP.
+*/
+pub async fn run(server: SocketAddr, domain: &Name, timeout: Duration) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    for check in Check::ALL {
+        for transport in Transport::ALL {
+            let result = run_check(check, server, domain, transport == Transport::Tcp, timeout).await;
+            outcomes.push(Outcome {
+                check,
+                reference: check.reference(),
+                transport,
+                passed: result.is_ok(),
+                failure: result.err(),
+            });
+        }
+    }
+    outcomes
+}
+
+// Runs one check over one transport, returning what the server did wrong if it failed
+async fn run_check(check: Check, server: SocketAddr, domain: &Name, tcp: bool, timeout: Duration) -> Result<(), String> {
+    let dice = below(domain, "dice")?;
+    match check {
+        Check::Header => {
+            let query = query(OpCode::Query, dice, RecordType::TXT);
+            let answer = exchange(server, &query, tcp, timeout).await?;
+            expect_response(&query, &answer)?;
+            expect_code(&answer, &[ResponseCode::NoError])?;
+            if answer.recursion_desired() != query.recursion_desired() {
+                return Err("RD was not copied from the query".to_string());
+            }
+            if answer.answers().is_empty() {
+                return Err("no answer records".to_string());
+            }
+            Ok(())
+        }
+        Check::CasePreservation => {
+            let name = Name::from_ascii(mixed_case(&dice.to_ascii())).map_err(|error| error.to_string())?;
+            let query = query(OpCode::Query, name.clone(), RecordType::TXT);
+            let answer = exchange(server, &query, tcp, timeout).await?;
+            expect_response(&query, &answer)?;
+            expect_code(&answer, &[ResponseCode::NoError])?;
+            match answer.queries().first() {
+                Some(echoed) if echoed.name().to_ascii() == name.to_ascii() => Ok(()),
+                Some(echoed) => Err(format!("the question was echoed as {}", echoed.name())),
+                None => Err("the question was not echoed".to_string()),
+            }
+        }
+        Check::UnknownType => {
+            let query = query(OpCode::Query, dice, RecordType::Unknown(UNKNOWN_TYPE));
+            let answer = exchange(server, &query, tcp, timeout).await?;
+            expect_response(&query, &answer)?;
+            expect_code(&answer, &[ResponseCode::NoError, ResponseCode::NXDomain])?;
+            match answer.answers().iter().find(|record| record.record_type() != RecordType::Unknown(UNKNOWN_TYPE)) {
+                Some(record) => Err(format!("answered with a {} record", record.record_type())),
+                None => Ok(()),
+            }
+        }
+        Check::Edns => {
+            let mut query = query(OpCode::Query, dice, RecordType::TXT);
+            query.set_edns(edns(0));
+            let answer = exchange(server, &query, tcp, timeout).await?;
+            expect_response(&query, &answer)?;
+            expect_code(&answer, &[ResponseCode::NoError])?;
+            match answer.extensions() {
+                Some(edns) if edns.version() == 0 => Ok(()),
+                Some(edns) => Err(format!("the answer has EDNS version {}", edns.version())),
+                None => Err("the answer has no OPT record".to_string()),
+            }
+        }
+        Check::EdnsVersion => {
+            let mut query = query(OpCode::Query, dice, RecordType::TXT);
+            query.set_edns(edns(1));
+            let answer = exchange(server, &query, tcp, timeout).await?;
+            expect_response(&query, &answer)?;
+            expect_code(&answer, &[ResponseCode::BADVERS])?;
+            match answer.extensions() {
+                Some(edns) if edns.version() == 0 => Ok(()),
+                Some(edns) => Err(format!("the answer has EDNS version {}", edns.version())),
+                None => Err("the answer has no OPT record".to_string()),
+            }
+        }
+        Check::Truncation => {
+            let query = query(OpCode::Query, below(domain, LONG_ANSWER)?, RecordType::TXT);
+            let bytes = exchange_bytes(server, &query, tcp, timeout).await?;
+            let answer = decode(&bytes)?;
+            expect_response(&query, &answer)?;
+            expect_code(&answer, &[ResponseCode::NoError])?;
+            match (tcp, answer.truncated()) {
+                (false, _) if bytes.len() > MAX_PLAIN_UDP => Err(format!("a {} byte answer was sent over UDP to a client without EDNS", bytes.len())),
+                (false, false) => Err("the answer doesn't fit in 512 bytes but TC is not set".to_string()),
+                (true, true) => Err("TC is set over TCP".to_string()),
+                (true, false) if answer.answers().is_empty() => Err("no answer records".to_string()),
+                _ => Ok(()),
+            }
+        }
+        Check::UnknownOpCode => {
+            let query = query(OpCode::Status, dice, RecordType::TXT);
+            let answer = exchange(server, &query, tcp, timeout).await?;
+            expect_response(&query, &answer)?;
+            expect_code(&answer, &[ResponseCode::NotImp])
+        }
+        Check::Update => {
+            // The zone section of an update takes the place of the question, with the zone's SOA type (RFC 2136 section 2.3)
+            let query = query(OpCode::Update, domain.clone(), RecordType::SOA);
+            let answer = exchange(server, &query, tcp, timeout).await?;
+            expect_response(&query, &answer)?;
+            expect_code(&answer, &[ResponseCode::NotImp, ResponseCode::Refused])
+        }
+    }
+}
+
+// Returns the name of a label below the domain
+fn below(domain: &Name, label: &str) -> Result<Name, String> {
+    Name::from_ascii(label)
+        .and_then(|name| name.append_domain(domain))
+        .map_err(|error| error.to_string())
+}
+
+// Builds a query with a random ID and RD set
+fn query(op_code: OpCode, name: Name, record_type: RecordType) -> Message {
+    let mut query = Message::new();
+    query
+        .set_id(random::with_rng(|rng| rng.gen::<u16>()))
+        .set_message_type(MessageType::Query)
+        .set_op_code(op_code)
+        .set_recursion_desired(true)
+        .add_query(Query::query(name, record_type));
+    query
+}
+
+// Returns an OPT record with the given version and the suite's payload size
+fn edns(version: u8) -> Edns {
+    let mut edns = Edns::new();
+    edns.set_version(version).set_max_payload(EDNS_PAYLOAD);
+    edns
+}
+
+// Alternates the case of a name's letters, e.g. DiCe.MeNtIsNoVaE.TeCh.
+fn mixed_case(name: &str) -> String {
+    let mut upper = true;
+    name.chars()
+        .map(|character| {
+            if !character.is_ascii_alphabetic() {
+                return character;
+            }
+            upper = !upper;
+            if upper { character.to_ascii_lowercase() } else { character.to_ascii_uppercase() }
+        })
+        .collect()
+}
+
+// Sends a query and returns the answer in wire format
+async fn exchange_bytes(server: SocketAddr, query: &Message, tcp: bool, timeout: Duration) -> Result<Vec<u8>, String> {
+    let bytes = query.to_vec().map_err(|error| error.to_string())?;
+    client::exchange_bytes(server, &bytes, tcp, timeout)
+        .await
+        .map_err(|error| error.to_string())
+}
+
+// Sends a query and returns the decoded answer
+async fn exchange(server: SocketAddr, query: &Message, tcp: bool, timeout: Duration) -> Result<Message, String> {
+    decode(&exchange_bytes(server, query, tcp, timeout).await?)
+}
+
+// Decodes an answer
+fn decode(bytes: &[u8]) -> Result<Message, String> {
+    Message::from_vec(bytes).map_err(|error| format!("the answer could not be decoded: {error}"))
+}
+
+// Fails unless the answer is a response to the query: the same ID and op code, QR set and the question echoed
+fn expect_response(query: &Message, answer: &Message) -> Result<(), String> {
+    if answer.id() != query.id() {
+        return Err("the answer's ID doesn't match the query".to_string());
+    }
+    if answer.message_type() != MessageType::Response {
+        return Err("QR is not set".to_string());
+    }
+    if answer.op_code() != query.op_code() {
+        return Err(format!("the answer's op code is {:?}", answer.op_code()));
+    }
+    if answer.queries() != query.queries() {
+        return Err("the question was not echoed".to_string());
+    }
+    Ok(())
+}
+
+// Fails unless the answer's response code is one of the expected ones
+fn expect_code(answer: &Message, expected: &[ResponseCode]) -> Result<(), String> {
+    match expected.contains(&answer.response_code()) {
+        true => Ok(()),
+        false => Err(format!("answered with {}", format!("{:?}", answer.response_code()).to_uppercase())),
+    }
+}
@@ -0,0 +1,389 @@
+use crate::tasks;
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::io::AsyncWriteExt;
+use tracing::*;
+
+// The longest name a named counter can have: a single DNS label.
+pub const MAX_NAME_LENGTH: usize = 63;
+
+// How long Redis remembers the last sync an instance applied, and so how long a sync whose reply was lost can be retried without counting it twice.
+#[cfg(feature = "redis")]
+const REDIS_SYNC_MEMORY: Duration = Duration::from_secs(86400);
+
+// Adds a sync's requests to the shared count unless the instance's last applied sync has the same number, which means the reply to it was lost and this is a retry, and returns the shared count.
+// KEYS[1] is the shared count and KEYS[2] the number of the instance's last applied sync; ARGV holds the sync's number, its requests and how long to remember it.
+#[cfg(feature = "redis")]
+const REDIS_SYNC_SCRIPT: &str = r"
+if redis.call('GET', KEYS[2]) == ARGV[1] then
+    return tonumber(redis.call('GET', KEYS[1]) or '0')
+end
+redis.call('SET', KEYS[2], ARGV[1], 'EX', ARGV[3])
+return redis.call('INCRBY', KEYS[1], ARGV[2])
+";
+
+// The slot of the next thread that counts into a counter without a shard.
+static NEXT_THREAD_SLOT: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // The slot this thread counts into, handed out round-robin the first time it counts
+    static THREAD_SLOT: usize = NEXT_THREAD_SLOT.fetch_add(1, Ordering::Relaxed);
+}
+
+/*
+Description:
+the request counter, split into slots on separate cache lines so cores counting queries at the same time never write to the same line. A shard's handle counts into the shard's slot, and any other handle counts into a slot picked per thread, so the worker threads of a multi-threaded runtime are spread over the slots too. Reading adds the slots up, which only happens when the count is asked for.
+
+Every access is relaxed: the count is a statistic and nothing is synchronized through it, so the stronger orderings would only cost time on every query.
+*/
+#[derive(Clone, Debug)]
+pub struct Counter {
+    // The slots, at least one
+    slots: Arc<[Slot]>,
+
+    // The slot of the shard this handle counts into, or None to pick one per thread
+    shard: Option<usize>,
+}
+
+// A counter slot, aligned to its own cache line
+#[repr(align(64))]
+#[derive(Debug, Default)]
+struct Slot(AtomicU64);
+
+impl Counter {
+    /*
+    Description:
+    creates a counter.
+
+    Parameters:
+    initial: the starting value, e.g. the persisted count.
+    slots: the number of slots, e.g. the number of shards or worker threads; 0 is treated as 1.
+
+    Returns:
+    A handle counting into a slot picked per thread.
+    */
+    pub fn new(initial: u64, slots: usize) -> Self {
+        let slots: Arc<[Slot]> = (0..slots.max(1)).map(|_| Slot::default()).collect();
+        slots[0].0.store(initial, Ordering::Relaxed);
+        Counter { slots, shard: None }
+    }
+
+    // Returns a handle to the same counter that counts into the slot of the given shard
+    pub fn for_shard(&self, shard: usize) -> Self {
+        Counter {
+            slots: self.slots.clone(),
+            shard: Some(shard % self.slots.len()),
+        }
+    }
+
+    // Adds to the counter, wrapping around on overflow
+    pub fn add(&self, value: u64) {
+        let slot = self.shard.unwrap_or_else(|| THREAD_SLOT.with(|slot| *slot) % self.slots.len());
+        self.slots[slot].0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    // Returns the counter's value, the sum of every slot
+    pub fn load(&self) -> u64 {
+        self.slots
+            .iter()
+            .fold(0, |total: u64, slot| total.wrapping_add(slot.0.load(Ordering::Relaxed)))
+    }
+}
+
+/*
+Description:
+the named counters of the counter zone: <name>.counter.<domain> increments the counter of that name and answers its new value, which makes the zone a lightweight metrics endpoint for scripts that can make a DNS query but not an HTTP request. A counter is created by its first increment, up to a number of counters so clients can't fill the memory with names, and removed again by a reset.
+*/
+#[derive(Debug)]
+pub struct NamedCounters {
+    // The value of each counter, by name
+    values: Mutex<BTreeMap<String, u64>>,
+
+    // The most counters kept
+    capacity: usize,
+
+    // Bumped on every change, so the flush task knows when there is something new to persist
+    generation: AtomicU64,
+}
+
+impl NamedCounters {
+    /*
+    Description:
+    creates the named counters.
+
+    Parameters:
+    initial: the starting values, e.g. the persisted counters.
+    capacity: the most counters kept.
+
+    Returns:
+    The NamedCounters.
+    */
+    pub fn new(initial: BTreeMap<String, u64>, capacity: usize) -> Self {
+        NamedCounters {
+            values: Mutex::new(initial),
+            capacity,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    // Adds one to a counter, creating it if there is room, and returns its new value, or None if it doesn't exist and there is no room for it
+    pub fn increment(&self, name: &str) -> Option<u64> {
+        let mut values = self.values.lock().unwrap();
+        if !values.contains_key(name) && values.len() >= self.capacity {
+            return None;
+        }
+        let value = values.entry(name.to_string()).or_default();
+        *value = value.wrapping_add(1);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        Some(*value)
+    }
+
+    // Returns the value of a counter without changing it; a counter that doesn't exist is 0
+    pub fn get(&self, name: &str) -> u64 {
+        self.values.lock().unwrap().get(name).copied().unwrap_or(0)
+    }
+
+    // Removes a counter, so it starts again from 0, and returns the value it had
+    pub fn reset(&self, name: &str) -> u64 {
+        let value = self.values.lock().unwrap().remove(name);
+        if value.is_some() {
+            self.generation.fetch_add(1, Ordering::Relaxed);
+        }
+        value.unwrap_or(0)
+    }
+
+    // Returns every counter with its value
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        self.values.lock().unwrap().clone()
+    }
+
+    // Returns a number that changes whenever a counter does
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+}
+
+// Returns whether a name can be that of a named counter: lowercase letters, digits and dashes, not starting with one, in a single label
+pub fn valid_name(name: &str) -> bool {
+    (1..=MAX_NAME_LENGTH).contains(&name.len())
+        && !name.starts_with('-')
+        && name.bytes().all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-')
+}
+
+/*
+Description:
+loads the persisted request counter, the first line of the counter file. A missing file means the server has never run with persistence before and starts from zero; a corrupt file is reported and also starts from zero rather than keeping the server down.
+
+Parameters:
+path: the counter file.
+
+Returns:
+The persisted counter value, or 0 if there is none.
+*/
+pub fn load(path: &Path) -> u64 {
+    let Some(contents) = read(path) else {
+        return 0;
+    };
+    contents.lines().next().unwrap_or_default().trim().parse().unwrap_or_else(|error| {
+        warn!("Ignoring corrupt counter file {}: {error}", path.display());
+        0
+    })
+}
+
+/*
+Description:
+loads the persisted named counters, the lines after the first of the counter file, each a name and its value separated by a space. Lines that can't be read are reported and skipped.
+
+Parameters:
+path: the counter file.
+
+Returns:
+The persisted named counters, or none if there are none.
+*/
+pub fn load_named(path: &Path) -> BTreeMap<String, u64> {
+    let mut counters = BTreeMap::new();
+    let Some(contents) = read(path) else {
+        return counters;
+    };
+    for line in contents.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+        let counter = line.trim().split_once(' ').and_then(|(name, value)| Some((name, value.trim().parse::<u64>().ok()?)));
+        match counter {
+            Some((name, value)) if valid_name(name) => {
+                counters.insert(name.to_string(), value);
+            }
+            _ => warn!("Ignoring corrupt named counter in {}: {line}", path.display()),
+        }
+    }
+    counters
+}
+
+// Reads the counter file, returning None if there is none or it can't be read
+fn read(path: &Path) -> Option<String> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Some(contents),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => {
+            warn!("Failed to read counter file {}: {error}", path.display());
+            None
+        }
+    }
+}
+
+/*
+Description:
+spawns a background task that writes the request counter and the named counters to a file every interval, skipping the write when none of them has changed. Must be called from within the tokio runtime. At most one interval's worth of requests is lost if the process dies.
+
+Parameters:
+counter: the shared request counter.
+named: the named counters.
+path: the counter file.
+interval: how often to flush the counters.
+
+Returns:
+None
+*/
+pub fn spawn_flush(counter: Counter, named: Arc<NamedCounters>, path: PathBuf, interval: Duration) {
+    tasks::spawn("counter-flush", async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut flushed = (counter.load(), named.generation());
+
+        loop {
+            ticker.tick().await;
+
+            // Only write when there is something new to persist
+            let current = (counter.load(), named.generation());
+            if current == flushed {
+                continue;
+            }
+
+            match store(&path, current.0, &named.snapshot()).await {
+                Ok(()) => flushed = current,
+                Err(error) => warn!("Failed to flush counter to {}: {error}", path.display()),
+            }
+        }
+    });
+}
+
+/*
+Description:
+writes the counters to a file crash-safely: the request counter on the first line, followed by a line per named counter. The values are written to a temporary file and synced to disk before it is renamed over the old file, so a crash leaves either the old or the new values behind, never a torn write.
+
+Parameters:
+path: the counter file.
+value: the request counter value to store.
+named: the named counters to store.
+
+Returns:
+Ok(()) if the values were stored, or an io::Error if writing failed.
+*/
+async fn store(path: &Path, value: u64, named: &BTreeMap<String, u64>) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut contents = value.to_string();
+    for (name, value) in named {
+        contents.push_str(&format!("\n{name} {value}"));
+    }
+
+    // Write and sync the temporary file
+    let mut file = tokio::fs::File::create(&tmp).await?;
+    file.write_all(contents.as_bytes()).await?;
+    file.sync_all().await?;
+
+    // Atomically move it into place
+    tokio::fs::rename(&tmp, path).await
+}
+
+/*
+Description:
+spawns a background task that shares the request counter with other Rusty-DNS instances through Redis. Every interval, the requests counted locally since the last sync are added to a Redis key, and the local counter is moved to the shared total that comes back, so every instance behind an anycast address reports the same count. If Redis is unreachable, requests keep being counted locally and are added on the next successful sync. A sync that got no reply is sent again unchanged, under the same number, and a Lua script adds it only if the instance's last applied sync had another number, so a sync that reached Redis but whose reply was lost is not counted twice. Must be called from within the tokio runtime.
+
+Parameters:
+counter: the shared request counter.
+url: the Redis URL, e.g. "redis://127.0.0.1/".
+key: the Redis key holding the shared count.
+interval: how often to sync the counter.
+
+Returns:
+Ok(()) if the task was started, or a redis::RedisError if the URL is invalid.
+*/
+#[cfg(feature = "redis")]
+pub fn spawn_redis_sync(
+    counter: Counter,
+    url: &str,
+    key: String,
+    interval: Duration,
+) -> redis::RedisResult<()> {
+    let client = redis::Client::open(url)?;
+
+    tasks::spawn("counter-sync", async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut connection = None;
+
+        // The local counter value that corresponds to the shared total after the last sync
+        let mut synced = counter.load();
+
+        // The key remembering this instance's last applied sync, and the number of the last sync taken
+        let applied_key = format!("{key}:sync:{:016x}", rand::random::<u64>());
+        let mut sequence: u64 = 0;
+
+        // A sync that got no reply: its number, the requests it adds and the local count they were taken from
+        let mut pending: Option<(u64, u64, u64)> = None;
+
+        loop {
+            ticker.tick().await;
+
+            // (Re)connect if needed
+            if connection.is_none() {
+                match client.get_multiplexed_tokio_connection().await {
+                    Ok(established) => connection = Some(established),
+                    Err(error) => {
+                        warn!("Failed to connect to Redis: {error}");
+                        continue;
+                    }
+                }
+            }
+            let Some(redis) = connection.as_mut() else {
+                continue;
+            };
+
+            // Add the requests counted locally since the last sync to the shared total, or retry the sync that got no reply as it was
+            let (number, delta, local) = *pending.get_or_insert_with(|| {
+                sequence += 1;
+                let local = counter.load();
+                (sequence, local.wrapping_sub(synced), local)
+            });
+            let total: redis::RedisResult<u64> = redis::cmd("EVAL")
+                .arg(REDIS_SYNC_SCRIPT)
+                .arg(2)
+                .arg(&key)
+                .arg(&applied_key)
+                .arg(number)
+                .arg(delta)
+                .arg(REDIS_SYNC_MEMORY.as_secs())
+                .query_async(redis)
+                .await;
+
+            match total {
+                Ok(total) => {
+                    // Move the local counter to the shared total, keeping requests counted since the sync was taken
+                    pending = None;
+                    counter.add(total.wrapping_sub(local));
+                    synced = total;
+                }
+                Err(error) => {
+                    warn!("Failed to sync counter with Redis: {error}");
+                    connection = None;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
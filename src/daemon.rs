@@ -0,0 +1,91 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    os::unix::io::AsRawFd,
+    path::Path,
+};
+
+/*
+Description:
+detaches the server from its terminal the classic way, for init scripts that expect the server to background itself: fork, start a new session, fork again so the server can never reacquire a terminal, and point stdin at /dev/null and stdout/stderr at the log file (or /dev/null). The working directory is kept, since relative paths such as the default fx cache directory are resolved against it. This has to run before the tokio runtime is built, since only the forking thread survives a fork.
+
+Parameters:
+log_file: the file that receives the server's output, appended to; None discards it.
+
+Returns:
+Ok(()) in the daemon process, or an io::Error if a step failed. The original process exits once the daemon is running.
+*/
+pub fn daemonize(log_file: Option<&Path>) -> io::Result<()> {
+    // Open the output files first, so errors are still reported to the terminal
+    let null = File::open("/dev/null")?;
+    let output = match log_file {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path)?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+
+    // Fork, leaving the parent to exit, and become the leader of a new session without a terminal
+    fork()?;
+    if unsafe { libc::setsid() } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Fork again so the daemon is not a session leader and can't acquire a terminal
+    fork()?;
+
+    // Files the daemon creates get the usual permissions, whatever umask it was started with
+    unsafe { libc::umask(0o022) };
+
+    // Redirect the standard streams
+    redirect(&null, libc::STDIN_FILENO)?;
+    redirect(&output, libc::STDOUT_FILENO)?;
+    redirect(&output, libc::STDERR_FILENO)
+}
+
+/*
+Description:
+sends the server's output (stdout and stderr) to a log file instead of the terminal, for servers that log to a file without daemonizing.
+
+Parameters:
+path: the log file, appended to.
+
+Returns:
+Ok(()) once the output is redirected, or an io::Error.
+*/
+pub fn log_to(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    redirect(&file, libc::STDOUT_FILENO)?;
+    redirect(&file, libc::STDERR_FILENO)
+}
+
+// Makes a standard stream refer to a file
+fn redirect(file: &File, fd: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::dup2(file.as_raw_fd(), fd) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Forks, exiting in the parent and returning in the child
+fn fork() -> io::Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+/*
+Description:
+writes the server's process ID to a pid file, for init scripts that stop or signal the server with it. An existing file is overwritten, since a leftover pid file from a crashed server must not keep it from starting.
+
+Parameters:
+path: the pid file.
+
+Returns:
+Ok(()) once the file is written, or an io::Error.
+*/
+pub fn write_pidfile(path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", std::process::id())?;
+    file.sync_all()
+}
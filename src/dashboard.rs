@@ -0,0 +1,108 @@
+use crate::{
+    blocklist::BlocklistStats,
+    clients::ClientReport,
+    handlers::Handler,
+    metrics::{RecentQuery, TransportLatency, ZoneLatency, ZoneMetrics},
+};
+use serde::Serialize;
+use std::{io, time::Duration};
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+
+// The dashboard page, a single self-contained HTML file.
+pub const INDEX_HTML: &str = include_str!("dashboard.html");
+
+// How often the event stream sends a new snapshot.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+// The number of clients shown on the dashboard.
+const TOP_CLIENTS: usize = 10;
+
+/*
+Description:
+everything the dashboard shows, sent as one JSON object per update.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct DashboardSnapshot {
+    // Seconds since the server started
+    pub uptime: u64,
+
+    // The total number of requests served
+    pub queries: u64,
+
+    // Queries per second, averaged over the last few seconds
+    pub qps: f64,
+
+    // Query counts for each of the last 60 seconds, oldest first
+    pub qps_history: Vec<u64>,
+
+    // Query and error counts per zone, busiest first
+    pub zones: Vec<(String, ZoneMetrics)>,
+
+    // Latency percentiles per zone, busiest first
+    pub zone_latency: Vec<ZoneLatency>,
+
+    // Latency percentiles per transport
+    pub transport_latency: Vec<TransportLatency>,
+
+    // The busiest clients
+    pub top_clients: Vec<ClientReport>,
+
+    // The most recent queries, newest first
+    pub recent: Vec<RecentQuery>,
+
+    // Blocklist size and hits, if a blocklist is configured
+    pub blocklist: Option<BlocklistStats>,
+}
+
+/*
+Description:
+collects a dashboard snapshot from the handler's metrics registry, client statistics and blocklist.
+
+Parameters:
+handler: the DNS handler whose state is shown.
+
+Returns:
+A DashboardSnapshot.
+*/
+pub fn snapshot(handler: &Handler) -> DashboardSnapshot {
+    let metrics = handler.metrics.snapshot();
+
+    DashboardSnapshot {
+        uptime: handler.health.uptime().as_secs(),
+        queries: handler.counter.load(),
+        qps: metrics.qps,
+        qps_history: metrics.qps_history,
+        zones: metrics.zones,
+        zone_latency: metrics.zone_latency,
+        transport_latency: metrics.transport_latency,
+        top_clients: handler.clients.top(TOP_CLIENTS),
+        recent: metrics.recent,
+        blocklist: handler.blocklist.as_ref().map(|blocklist| blocklist.stats(TOP_CLIENTS)),
+    }
+}
+
+/*
+Description:
+serves the dashboard's Server-Sent Events stream: a new snapshot every second until the browser disconnects.
+
+Parameters:
+stream: the client connection, whose request has already been read.
+handler: the DNS handler whose state is shown.
+
+Returns:
+Ok(()) never in practice; an io::Error once the client goes away.
+*/
+pub async fn stream(mut stream: TcpStream, handler: &Handler) -> io::Result<()> {
+    stream
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n")
+        .await?;
+
+    let mut ticker = tokio::time::interval(UPDATE_INTERVAL);
+    loop {
+        ticker.tick().await;
+
+        // Send the snapshot as a single "data:" event
+        let data = serde_json::to_string(&snapshot(handler)).unwrap_or_default();
+        stream.write_all(format!("data: {data}\n\n").as_bytes()).await?;
+    }
+}
@@ -0,0 +1,421 @@
+use crate::{
+    audit::AuditEntry,
+    handlers::Handler,
+    httpd::{self, query_param},
+    tasks,
+};
+use base64::Engine;
+use http::{header, Method, Request, Response, StatusCode};
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::*;
+
+// The keyword of the zone the hosts are served in, as <host>.dyn.<domain>.
+pub const ZONE: &str = "dyn";
+
+// The TTL of the hosts' A and AAAA records, short so that a new address is seen within a minute.
+pub const TTL: u32 = 60;
+
+// The length of the tokens handed out for hosts.
+const TOKEN_LENGTH: usize = 32;
+
+// The most hostnames a single update may change, as DynDNS allows.
+const MAX_HOSTNAMES: usize = 20;
+
+// The largest request body the API accepts; updates are GET requests, so there shouldn't be one.
+const MAX_BODY_SIZE: usize = 1024;
+
+// How long a client gets to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/*
+Description:
+a host in the dynamic zone: the digest of the token its DDNS client updates it with, and the addresses it was last updated to.
+*/
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Host {
+    // The SHA-256 digest of the host's token, in hexadecimal; the token itself is never stored
+    token_hash: String,
+
+    // The IPv4 address served as its A record, if it has one
+    #[serde(default)]
+    ipv4: Option<Ipv4Addr>,
+
+    // The IPv6 address served as its AAAA record, if it has one
+    #[serde(default)]
+    ipv6: Option<Ipv6Addr>,
+
+    // When the host's addresses last changed, in seconds since the Unix epoch
+    #[serde(default)]
+    updated: Option<i64>,
+}
+
+/*
+Description:
+the ways an update can fail, named by the DynDNS return codes the client is answered with.
+*/
+#[derive(Debug, PartialEq, Eq)]
+pub enum DdnsError {
+    // The token is wrong for the host
+    BadAuth,
+
+    // The hostname is not a name in the dynamic zone
+    NotFqdn,
+
+    // The host does not exist
+    NoHost,
+
+    // More hostnames were given than an update may change
+    NumHost,
+
+    // The hosts file could not be read or written
+    DnsErr,
+}
+
+impl DdnsError {
+    // Returns the DynDNS return code of the failure
+    pub fn code(&self) -> &'static str {
+        match self {
+            DdnsError::BadAuth => "badauth",
+            DdnsError::NotFqdn => "notfqdn",
+            DdnsError::NoHost => "nohost",
+            DdnsError::NumHost => "numhost",
+            DdnsError::DnsErr => "dnserr",
+        }
+    }
+}
+
+/*
+Description:
+the dynamic zone: hosts whose A and AAAA records their owners' routers keep up to date through the DynDNS update API, served as <host>.dyn.<domain>. The hosts and their addresses are kept in a JSON file, so they survive restarts; hosts are added and removed with the ddns subcommand, which hands out each host's token.
+*/
+#[derive(Debug)]
+pub struct Ddns {
+    // The domain the hosts are served under
+    domain: String,
+
+    // The hosts, by label
+    hosts: RwLock<BTreeMap<String, Host>>,
+
+    // The file the hosts are kept in
+    hosts_file: PathBuf,
+
+    // Held while the hosts file is read and written, so updates don't undo each other
+    saving: Mutex<()>,
+}
+
+impl Ddns {
+    /*
+    Description:
+    creates the dynamic zone from its hosts file.
+
+    Parameters:
+    domain: the domain the hosts are served under.
+    hosts_file: the JSON file the hosts are kept in. A missing file is an empty zone.
+
+    Returns:
+    Ok(Ddns), or an io::Error if the hosts file exists but could not be read.
+    */
+    pub fn open(domain: &str, hosts_file: &Path) -> io::Result<Self> {
+        Ok(Ddns {
+            domain: domain.trim_end_matches('.').to_ascii_lowercase(),
+            hosts: RwLock::new(load(hosts_file)?),
+            hosts_file: hosts_file.to_path_buf(),
+            saving: Mutex::new(()),
+        })
+    }
+
+    // Returns the full name a host is served at
+    pub fn hostname(&self, label: &str) -> String {
+        format!("{label}.{ZONE}.{}", self.domain)
+    }
+
+    // Returns the number of hosts
+    pub fn hosts(&self) -> usize {
+        self.hosts.read().unwrap().len()
+    }
+
+    // Returns the addresses of a host, or None if there is no such host
+    pub fn addresses(&self, label: &str) -> Option<(Option<Ipv4Addr>, Option<Ipv6Addr>)> {
+        let hosts = self.hosts.read().unwrap();
+        hosts.get(&label.to_ascii_lowercase()).map(|host| (host.ipv4, host.ipv6))
+    }
+
+    /*
+    Description:
+    updates a host's addresses and saves them. The hosts file is read again first, so hosts added or removed with the ddns subcommand since the server started are seen. An update with only an IPv4 or only an IPv6 address leaves the host's other address alone.
+
+    Parameters:
+    hostname: the host's full name, e.g. home.dyn.mentisnovae.tech.
+    token: the host's token.
+    addresses: the host's new addresses; the last IPv4 and the last IPv6 address are used.
+
+    Returns:
+    Ok(true) if the addresses changed, Ok(false) if they were already current, or a DdnsError if the name, the host, the token or the hosts file is wrong.
+    */
+    pub fn update(&self, hostname: &str, token: &str, addresses: &[IpAddr]) -> Result<bool, DdnsError> {
+        let label = self.label(hostname).ok_or(DdnsError::NotFqdn)?;
+
+        let _saving = self.saving.lock().unwrap();
+        let mut hosts = load(&self.hosts_file).map_err(|error| {
+            error!("Failed to read the DDNS hosts from {}: {error}", self.hosts_file.display());
+            DdnsError::DnsErr
+        })?;
+        let host = hosts.get_mut(&label).ok_or(DdnsError::NoHost)?;
+        if host.token_hash != hash(token) {
+            return Err(DdnsError::BadAuth);
+        }
+
+        let ipv4 = addresses.iter().rev().find_map(|address| match address {
+            IpAddr::V4(ipv4) => Some(*ipv4),
+            IpAddr::V6(_) => None,
+        });
+        let ipv6 = addresses.iter().rev().find_map(|address| match address {
+            IpAddr::V6(ipv6) => Some(*ipv6),
+            IpAddr::V4(_) => None,
+        });
+        let changed = (ipv4.is_some() && ipv4 != host.ipv4) || (ipv6.is_some() && ipv6 != host.ipv6);
+        if changed {
+            host.ipv4 = ipv4.or(host.ipv4);
+            host.ipv6 = ipv6.or(host.ipv6);
+            host.updated = Some(chrono::Utc::now().timestamp());
+            store(&self.hosts_file, &hosts).map_err(|error| {
+                error!("Failed to save the DDNS hosts to {}: {error}", self.hosts_file.display());
+                DdnsError::DnsErr
+            })?;
+        }
+
+        *self.hosts.write().unwrap() = hosts;
+        Ok(changed)
+    }
+
+    // Returns the label of a host from its full name, or None if the name is not a host of the dynamic zone
+    fn label(&self, hostname: &str) -> Option<String> {
+        let hostname = hostname.trim_end_matches('.').to_ascii_lowercase();
+        let label = hostname.strip_suffix(&format!(".{ZONE}.{}", self.domain))?;
+        valid_label(label).then(|| label.to_string())
+    }
+}
+
+// Returns whether a label can name a host: letters, digits and hyphens, not at either end
+pub fn valid_label(label: &str) -> bool {
+    (1..=63).contains(&label.len())
+        && label.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+}
+
+/*
+Description:
+adds a host to a hosts file, or gives an existing host a new token, for the ddns subcommand. A host that already exists keeps its addresses.
+
+Parameters:
+hosts_file: the JSON file the hosts are kept in; it is created if it is missing.
+label: the host's label, e.g. home for home.dyn.<domain>.
+
+Returns:
+Ok(String) with the host's new token, the only time it is seen, or an io::Error if the label is invalid or the file could not be read or written.
+*/
+pub fn add_host(hosts_file: &Path, label: &str) -> io::Result<String> {
+    let label = label.to_ascii_lowercase();
+    if !valid_label(&label) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{label} is not a valid host label")));
+    }
+
+    // Tokens are secrets, so they come from the OS
+    let token: String = OsRng.sample_iter(&Alphanumeric).take(TOKEN_LENGTH).map(char::from).collect();
+    let mut hosts = load(hosts_file)?;
+    hosts.entry(label).or_default().token_hash = hash(&token);
+    store(hosts_file, &hosts)?;
+    Ok(token)
+}
+
+/*
+Description:
+removes a host from a hosts file, for the ddns subcommand.
+
+Parameters:
+hosts_file: the JSON file the hosts are kept in.
+label: the host's label.
+
+Returns:
+Ok(true) if the host was removed, Ok(false) if there was no such host, or an io::Error if the file could not be read or written.
+*/
+pub fn remove_host(hosts_file: &Path, label: &str) -> io::Result<bool> {
+    let mut hosts = load(hosts_file)?;
+    let removed = hosts.remove(&label.to_ascii_lowercase()).is_some();
+    if removed {
+        store(hosts_file, &hosts)?;
+    }
+    Ok(removed)
+}
+
+// Reads the hosts from a hosts file, of which a missing one has none
+fn load(path: &Path) -> io::Result<BTreeMap<String, Host>> {
+    match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(error) => Err(error),
+    }
+}
+
+// Writes the hosts to a hosts file through a temporary file, so a crash never leaves half of them behind
+fn store(path: &Path, hosts: &BTreeMap<String, Host>) -> io::Result<()> {
+    let data = serde_json::to_vec_pretty(hosts).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)
+}
+
+// Returns the SHA-256 digest of a token in hexadecimal
+fn hash(token: &str) -> String {
+    let digest = digest::digest(&digest::SHA256, token.as_bytes());
+    digest.as_ref().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/*
+Description:
+serves the DynDNS update API, which home routers' and ddclient's DynDNS clients speak: GET /nic/update?hostname=<names>&myip=<addresses> with the host's token as the password of HTTP basic authentication (the user name is ignored). hostname takes up to 20 comma-separated names, and myip up to one IPv4 and one IPv6 address; without it, the client's own address is used. The answer is one DynDNS return code per name, e.g. "good 192.0.2.7" or "nochg 192.0.2.7". Every connection carries one request. Serve it over HTTPS through a reverse proxy if clients reach it across a network, since the tokens travel in the clear.
+
+Parameters:
+listener: the TCP listener to accept API connections on.
+handler: the DNS handler, whose dynamic zone the API updates and whose audit log records the updates.
+
+Returns:
+None; runs until the process exits.
+*/
+pub async fn serve(listener: TcpListener, handler: Handler) {
+    loop {
+        // Accept the next connection, carrying on if a single accept fails
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(error) => {
+                warn!("Failed to accept DDNS API connection: {error}");
+                continue;
+            }
+        };
+
+        // Serve each connection in its own task
+        let handler = handler.clone();
+        tasks::spawn("ddns-connection", async move {
+            if let Err(error) = serve_connection(stream, peer, &handler).await {
+                debug!("DDNS API connection from {peer} failed: {error}");
+            }
+        });
+    }
+}
+
+// Reads a request from a connection, answers it and records it in the audit log
+async fn serve_connection(mut stream: TcpStream, peer: SocketAddr, handler: &Handler) -> io::Result<()> {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, httpd::read_request(&mut stream, MAX_BODY_SIZE))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request timed out"))??;
+
+    let response = match &handler.ddns {
+        Some(ddns) => route(ddns.clone(), peer.ip(), &request).await,
+        None => text(StatusCode::NOT_FOUND, "911"),
+    };
+
+    if let Some(audit) = &handler.audit {
+        let hostname = query_param(&request, "hostname").map(httpd::percent_decode).unwrap_or_else(|| "-".to_string());
+        audit.record(AuditEntry::now(
+            format!("ddns-api/{hostname}"),
+            peer.to_string(),
+            request.method().as_str(),
+            request.uri().path().to_string(),
+            String::from_utf8_lossy(response.body()).trim().replace('\n', ","),
+        ));
+    }
+    httpd::write_response(&mut stream, &response).await
+}
+
+// Routes an API request to the update endpoint
+async fn route(ddns: Arc<Ddns>, client: IpAddr, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    match (request.method(), request.uri().path()) {
+        (&Method::GET, "/nic/update") => {}
+        (_, "/nic/update") => return httpd::error(StatusCode::METHOD_NOT_ALLOWED),
+        _ => return httpd::error(StatusCode::NOT_FOUND),
+    }
+
+    // Ask for credentials if there are none, as browsers and some clients only send them when asked
+    let Some(token) = password(request) else {
+        let mut response = text(StatusCode::UNAUTHORIZED, DdnsError::BadAuth.code());
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Basic realm=\"rusty-dns\""));
+        return response;
+    };
+
+    let hostnames: Vec<String> = query_param(request, "hostname")
+        .map(httpd::percent_decode)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|hostname| !hostname.is_empty())
+        .map(str::to_string)
+        .collect();
+    if hostnames.is_empty() {
+        return text(StatusCode::OK, DdnsError::NotFqdn.code());
+    }
+    if hostnames.len() > MAX_HOSTNAMES {
+        return text(StatusCode::OK, DdnsError::NumHost.code());
+    }
+
+    // The addresses to set, or the client's own address if none are given
+    let addresses = match query_param(request, "myip").map(httpd::percent_decode) {
+        Some(myip) if !myip.is_empty() => match myip.split(',').map(|address| address.trim().parse::<IpAddr>()).collect() {
+            Ok(addresses) => addresses,
+            Err(_) => return httpd::error(StatusCode::BAD_REQUEST),
+        },
+        _ => vec![client],
+    };
+    let shown = addresses.iter().map(IpAddr::to_string).collect::<Vec<_>>().join(",");
+
+    // The hosts file is read and written synchronously, so keep it off the runtime's threads
+    let result = tokio::task::spawn_blocking(move || {
+        hostnames
+            .iter()
+            .map(|hostname| match ddns.update(hostname, &token, &addresses) {
+                Ok(true) => format!("good {shown}"),
+                Ok(false) => format!("nochg {shown}"),
+                Err(error) => error.code().to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+    .await;
+
+    match result {
+        Ok(lines) => text(StatusCode::OK, &lines),
+        Err(_) => text(StatusCode::INTERNAL_SERVER_ERROR, "911"),
+    }
+}
+
+// Returns the password of a request's basic authentication
+fn password(request: &Request<Vec<u8>>) -> Option<String> {
+    let value = request.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let encoded = value.strip_prefix("Basic ").or_else(|| value.strip_prefix("basic "))?;
+    let decoded = base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    credentials.split_once(':').map(|(_, password)| password.to_string())
+}
+
+// Builds a plain text response, the format DynDNS clients expect
+fn text(status: StatusCode, body: &str) -> Response<Vec<u8>> {
+    let mut response = Response::new(format!("{body}\n").into_bytes());
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, header::HeaderValue::from_static("text/plain; charset=utf-8"));
+    response
+}
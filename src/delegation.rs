@@ -0,0 +1,305 @@
+use crate::{config::DelegationConfig, records};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::{
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::*;
+use trust_dns_server::client::rr::{LowerName, Name, Record, RecordType};
+
+/*
+Description:
+defines the errors that can occur while asking an HTTP backend for the answer to a delegated query. It covers failures talking to the backend, statuses other than 2xx, answers that could not be understood, and backends left alone after failing too often.
+*/
+#[derive(thiserror::Error, Debug)]
+pub enum DelegationError {
+    #[error("HTTP error: {0:}")]
+    Http(#[from] reqwest::Error),
+    #[error("The backend answered {0:}")]
+    Status(reqwest::StatusCode),
+    #[error("Malformed backend response: {0:}")]
+    MalformedResponse(String),
+    #[error("The backend failed {0:} times in a row and is left alone for now")]
+    CircuitOpen(u32),
+}
+
+// The query as it is POSTed to a backend, e.g. {"name": "web.svc.mentisnovae.tech.", "type": "A", "zone": "svc.mentisnovae.tech."}
+#[derive(Serialize)]
+struct BackendQuery {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    zone: String,
+}
+
+// A backend's answer, e.g. {"answers": [{"type": "A", "data": "192.0.2.10", "ttl": 30}]}, or {"rcode": "NXDOMAIN"} for a name that doesn't exist
+#[derive(Deserialize)]
+struct BackendAnswer {
+    #[serde(default)]
+    rcode: Option<String>,
+    #[serde(default)]
+    answers: Vec<BackendRecord>,
+}
+
+// A record of a backend's answer: its name is the query name unless given, and its TTL the delegation's unless given
+#[derive(Deserialize)]
+struct BackendRecord {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(rename = "type")]
+    record_type: String,
+    #[serde(default)]
+    ttl: Option<u32>,
+    data: String,
+}
+
+/*
+Description:
+what a backend answered a delegated query with: the records, none if the name has none of the type, or that the name doesn't exist.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DelegatedAnswer {
+    Records(Vec<Record>),
+    NxDomain,
+}
+
+// An answer in the cache, with when it was stored and when it stops being fresh
+#[derive(Debug)]
+struct Cached {
+    answer: DelegatedAnswer,
+    stored: Instant,
+    expires: Instant,
+}
+
+impl Cached {
+    // Returns the answer as served now, its TTLs cut by the time it has been cached
+    fn answer(&self, now: Instant) -> DelegatedAnswer {
+        let age = now.duration_since(self.stored).as_secs() as u32;
+        match &self.answer {
+            DelegatedAnswer::Records(records) => DelegatedAnswer::Records(
+                records
+                    .iter()
+                    .map(|record| {
+                        let mut record = record.clone();
+                        record.set_ttl(record.ttl().saturating_sub(age));
+                        record
+                    })
+                    .collect(),
+            ),
+            DelegatedAnswer::NxDomain => DelegatedAnswer::NxDomain,
+        }
+    }
+}
+
+// How a backend has been doing: its failures in a row, and until when it is left alone
+#[derive(Debug, Default)]
+struct Circuit {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+// A delegated subzone: its backend, the client it is asked with, its cached answers and its circuit
+#[derive(Debug)]
+struct Backend {
+    config: DelegationConfig,
+    zone: LowerName,
+    client: reqwest::Client,
+    cache: Mutex<LruCache<(LowerName, RecordType), Cached>>,
+    circuit: Mutex<Circuit>,
+}
+
+impl Backend {
+    // Asks the backend for the answer to a query, checking that its records are valid and within the subzone
+    async fn fetch(&self, name: &LowerName, record_type: RecordType) -> Result<DelegatedAnswer, DelegationError> {
+        let query = BackendQuery { name: name.to_string(), record_type: record_type.to_string(), zone: self.zone.to_string() };
+        let mut request = self.client.post(&self.config.url).json(&query);
+        for (header, value) in &self.config.headers {
+            request = request.header(header, value);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(DelegationError::Status(response.status()));
+        }
+        let answer: BackendAnswer = response.json().await?;
+
+        match answer.rcode.as_deref().map(str::to_ascii_uppercase).as_deref() {
+            None | Some("NOERROR") => {}
+            Some("NXDOMAIN") => return Ok(DelegatedAnswer::NxDomain),
+            Some(rcode) => return Err(DelegationError::MalformedResponse(format!("unsupported rcode {rcode}, expected NOERROR or NXDOMAIN"))),
+        }
+        let mut records = Vec::with_capacity(answer.answers.len());
+        for record in &answer.answers {
+            let owner = record.name.clone().unwrap_or_else(|| name.to_string());
+            let record = records::record(&owner, &record.record_type, record.ttl.unwrap_or(self.config.ttl), &record.data)
+                .map_err(DelegationError::MalformedResponse)?;
+            if !self.zone.zone_of(&LowerName::new(record.name())) {
+                return Err(DelegationError::MalformedResponse(format!("{} is outside {}", record.name(), self.zone)));
+            }
+            records.push(record);
+        }
+        Ok(DelegatedAnswer::Records(records))
+    }
+
+    // Returns whether the backend may be asked now; once its circuit has been open for long enough, a single query is let through to try it again
+    fn admit(&self, now: Instant) -> Result<(), DelegationError> {
+        let mut circuit = self.circuit.lock().unwrap();
+        match circuit.open_until {
+            Some(until) if until > now => Err(DelegationError::CircuitOpen(circuit.failures)),
+            Some(_) => {
+                circuit.open_until = Some(now + Duration::from_secs(self.config.open_for));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Counts the outcome of asking the backend, opening its circuit after failure_threshold failures in a row and closing it after a success
+    fn record(&self, passed: bool, now: Instant) {
+        let mut circuit = self.circuit.lock().unwrap();
+        if passed {
+            if circuit.open_until.take().is_some() {
+                info!("The delegation backend of {} answers again", self.zone);
+            }
+            circuit.failures = 0;
+            return;
+        }
+        circuit.failures += 1;
+        if circuit.failures >= self.config.failure_threshold.max(1) {
+            if circuit.open_until.is_none() {
+                warn!("The delegation backend of {} failed {} times in a row, leaving it alone for {}s", self.zone, circuit.failures, self.config.open_for);
+            }
+            circuit.open_until = Some(now + Duration::from_secs(self.config.open_for));
+        }
+    }
+
+    // Returns how long an answer is cached: the lowest TTL of its records, or the negative TTL if it has none, up to max_cache_ttl
+    fn cache_ttl(&self, answer: &DelegatedAnswer) -> u32 {
+        let ttl = match answer {
+            DelegatedAnswer::Records(records) if !records.is_empty() => records.iter().map(Record::ttl).min().unwrap_or_default(),
+            _ => self.config.negative_ttl,
+        };
+        ttl.min(self.config.max_cache_ttl)
+    }
+}
+
+/*
+Description:
+subzones delegated to HTTP backends from the [[delegation]] sections of the configuration file: each query for a name in a subzone is POSTed to its backend as JSON, and the JSON answer turned into records. Answers are cached for their lowest TTL. A backend that fails failure_threshold times in a row is left alone for open_for seconds, after which a single query tries it again; while it is failing, answers it gave before keep being served from the cache after they expire, and names it never answered get SERVFAIL.
+*/
+#[derive(Debug, Default)]
+pub struct Delegation {
+    // The subzones, the longest first so a subzone delegated within another is matched before it
+    backends: Vec<Backend>,
+}
+
+impl Delegation {
+    /*
+    Description:
+    builds the subzones of [[delegation]] sections, checking that each is a valid name given once with an http(s) URL.
+
+    Parameters:
+    configs: the [[delegation]] sections.
+
+    Returns:
+    Ok(Delegation), or an error message saying which section is wrong and why.
+    */
+    pub fn new(configs: &[DelegationConfig]) -> Result<Self, String> {
+        let mut delegation = Delegation::default();
+        for config in configs {
+            let context = |error: String| format!("[[delegation]] {}: {error}", config.zone);
+            let zone = Name::parse(&config.zone, Some(&Name::root())).map_err(|error| context(format!("invalid zone: {error}")))?;
+            let zone = LowerName::new(&zone);
+            if delegation.backends.iter().any(|backend| backend.zone == zone) {
+                return Err(context("the zone is given more than once".to_string()));
+            }
+            let url = reqwest::Url::parse(&config.url).map_err(|error| context(format!("invalid URL: {error}")))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(context(format!("the URL {url} is not http(s)")));
+            }
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_millis(config.timeout_ms.max(1)))
+                .build()
+                .map_err(|error| context(format!("failed to set up the backend's client: {error}")))?;
+            let capacity = NonZeroUsize::new(config.cache_size.max(1)).unwrap();
+            delegation.backends.push(Backend {
+                config: config.clone(),
+                zone,
+                client,
+                cache: Mutex::new(LruCache::new(capacity)),
+                circuit: Mutex::new(Circuit::default()),
+            });
+        }
+        delegation.backends.sort_by_key(|backend| std::cmp::Reverse(backend.zone.num_labels()));
+        Ok(delegation)
+    }
+
+    // Returns the subzone a name is delegated in, if any
+    fn backend(&self, name: &LowerName) -> Option<&Backend> {
+        self.backends.iter().find(|backend| backend.zone.zone_of(name))
+    }
+
+    // Returns whether a name is in a delegated subzone
+    pub fn serves(&self, name: &LowerName) -> bool {
+        self.backend(name).is_some()
+    }
+
+    // Returns the subzone a name is delegated in, its backend's URL and whether the backend is being left alone, if the name is delegated
+    pub fn status(&self, name: &LowerName) -> Option<(String, String, bool)> {
+        let backend = self.backend(name)?;
+        let open = backend.circuit.lock().unwrap().open_until.is_some_and(|until| until > Instant::now());
+        Some((backend.zone.to_string(), backend.config.url.clone(), open))
+    }
+
+    /*
+    Description:
+    answers a query for a delegated name, from the cache while its answer is fresh and from the backend otherwise.
+
+    Parameters:
+    name: the query name.
+    record_type: the type asked for.
+
+    Returns:
+    Some(Ok(DelegatedAnswer)), Some(Err(DelegationError)) if the backend can't be asked or fails and the answer isn't cached, or None if the name isn't delegated.
+    */
+    pub async fn answer(&self, name: &LowerName, record_type: RecordType) -> Option<Result<DelegatedAnswer, DelegationError>> {
+        let backend = self.backend(name)?;
+        let key = (name.clone(), record_type);
+        let now = Instant::now();
+        let stale = {
+            let mut cache = backend.cache.lock().unwrap();
+            match cache.get(&key) {
+                Some(cached) if cached.expires > now => return Some(Ok(cached.answer(now))),
+                Some(cached) => Some(cached.answer(now)),
+                None => None,
+            }
+        };
+
+        // Ask the backend unless it is being left alone, serving the stale answer if it can't be asked or fails
+        let fetched = match backend.admit(now) {
+            Ok(()) => {
+                let fetched = backend.fetch(name, record_type).await;
+                backend.record(fetched.is_ok(), Instant::now());
+                fetched
+            }
+            Err(error) => Err(error),
+        };
+        let answer = match (fetched, stale) {
+            (Ok(answer), _) => answer,
+            (Err(error), Some(stale)) => {
+                debug!("Failed to ask the delegation backend of {} about {name}, serving its cached answer: {error}", backend.zone);
+                return Some(Ok(stale));
+            }
+            (Err(error), None) => return Some(Err(error)),
+        };
+
+        // Cache the answer for its lowest TTL
+        let now = Instant::now();
+        let ttl = Duration::from_secs(u64::from(backend.cache_ttl(&answer)));
+        if !ttl.is_zero() {
+            backend.cache.lock().unwrap().put(key, Cached { answer: answer.clone(), stored: now, expires: now + ttl });
+        }
+        Some(Ok(answer))
+    }
+}
@@ -0,0 +1,174 @@
+use base64::Engine;
+use ring::{
+    digest,
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair as _},
+};
+use trust_dns_server::client::rr::Name;
+
+// The DNSSEC algorithm number of Ed25519 (RFC 8080).
+const ED25519: u8 = 15;
+
+// The DNSKEY flags: a zone key, and the secure entry point bit that marks a key-signing key (RFC 4034 section 2.1.1).
+const ZONE_KEY: u16 = 0x0100;
+const SECURE_ENTRY_POINT: u16 = 0x0001;
+
+// The digest type of SHA-256 in DS records (RFC 4509).
+const DS_SHA256: u8 = 2;
+
+// The length of the PKCS#8 prefix before the private key's seed in ring's Ed25519 documents.
+const PKCS8_SEED_OFFSET: usize = 16;
+
+/*
+Description:
+a DNSSEC key pair for a zone, either a zone-signing key (ZSK) or a key-signing key (KSK), using Ed25519, which is short, fast and supported by every current validator. It is written in BIND's key file format, so dnssec-signzone, BIND and the server can use it.
+*/
+pub struct KeyPair {
+    // The zone the key belongs to
+    zone: Name,
+
+    // Whether this is a key-signing key, whose DS record goes in the parent zone
+    ksk: bool,
+
+    // The public key
+    public: Vec<u8>,
+
+    // The private key's 32-byte seed
+    seed: Vec<u8>,
+}
+
+impl KeyPair {
+    /*
+    Description:
+    generates a new key pair with the operating system's random number generator.
+
+    Parameters:
+    zone: the zone the key belongs to.
+    ksk: true for a key-signing key, false for a zone-signing key.
+
+    Returns:
+    Ok(KeyPair), or an error if the random number generator failed.
+    */
+    pub fn generate(zone: &Name, ksk: bool) -> Result<Self, String> {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).map_err(|_| "failed to generate a key".to_string())?;
+        let pair = Ed25519KeyPair::from_pkcs8(document.as_ref()).map_err(|_| "failed to generate a key".to_string())?;
+        let public = pair.public_key().as_ref().to_vec();
+
+        // BIND stores the seed rather than the PKCS#8 document, so take it out and make sure it is the key's
+        let seed = document.as_ref()[PKCS8_SEED_OFFSET..PKCS8_SEED_OFFSET + 32].to_vec();
+        Ed25519KeyPair::from_seed_and_public_key(&seed, &public).map_err(|_| "failed to extract the private key".to_string())?;
+
+        Ok(KeyPair {
+            zone: zone.to_lowercase(),
+            ksk,
+            public,
+            seed,
+        })
+    }
+
+    // Returns the data of the key's DNSKEY record: the flags, the protocol (always 3), the algorithm and the public key
+    fn dnskey_rdata(&self) -> Vec<u8> {
+        let flags = if self.ksk { ZONE_KEY | SECURE_ENTRY_POINT } else { ZONE_KEY };
+        let mut rdata = flags.to_be_bytes().to_vec();
+        rdata.extend([3, ED25519]);
+        rdata.extend(&self.public);
+        rdata
+    }
+
+    /*
+    Description:
+    computes the key tag that identifies the key in RRSIG and DS records and in its file names (RFC 4034 appendix B).
+
+    Parameters:
+    None
+
+    Returns:
+    The key tag.
+    */
+    pub fn key_tag(&self) -> u16 {
+        let mut sum: u32 = 0;
+        for (index, byte) in self.dnskey_rdata().iter().enumerate() {
+            sum += if index % 2 == 0 { (*byte as u32) << 8 } else { *byte as u32 };
+        }
+        sum += (sum >> 16) & 0xFFFF;
+        (sum & 0xFFFF) as u16
+    }
+
+    // Returns the base name of the key's files, as dnssec-keygen names them (e.g. "Kexample.com.+015+12345")
+    pub fn file_stem(&self) -> String {
+        format!("K{}+{ED25519:03}+{:05}", self.zone, self.key_tag())
+    }
+
+    // Returns the key's DNSKEY record in zone file format
+    pub fn dnskey_record(&self) -> String {
+        let rdata = self.dnskey_rdata();
+        format!(
+            "{} IN DNSKEY {} 3 {ED25519} {}",
+            self.zone,
+            u16::from_be_bytes([rdata[0], rdata[1]]),
+            base64::engine::general_purpose::STANDARD.encode(&self.public)
+        )
+    }
+
+    /*
+    Description:
+    returns the DS record that the parent zone publishes to delegate trust to this key, with a SHA-256 digest of the zone name and the DNSKEY record's data (RFC 4034 section 5.1.4).
+
+    Parameters:
+    None
+
+    Returns:
+    The DS record in zone file format.
+    */
+    pub fn ds_record(&self) -> String {
+        // The owner name in canonical wire format: lower-case labels, each preceded by its length
+        let mut data = Vec::new();
+        for label in self.zone.iter() {
+            data.push(label.len() as u8);
+            data.extend(label.to_ascii_lowercase());
+        }
+        data.push(0);
+        data.extend(self.dnskey_rdata());
+
+        let digest = digest::digest(&digest::SHA256, &data);
+        let hex: String = digest.as_ref().iter().map(|byte| format!("{byte:02X}")).collect();
+        format!("{} IN DS {} {ED25519} {DS_SHA256} {hex}", self.zone, self.key_tag())
+    }
+
+    /*
+    Description:
+    returns the contents of the key's public file (.key): a comment saying what the key is and the DNSKEY record.
+
+    Parameters:
+    created: when the key was created, as YYYYMMDDHHMMSS in UTC.
+
+    Returns:
+    The file's contents.
+    */
+    pub fn public_file(&self, created: &str) -> String {
+        let kind = if self.ksk { "key-signing" } else { "zone-signing" };
+        format!(
+            "; This is a {kind} key, keyid {}, for {}\n; Created: {created}\n{}\n",
+            self.key_tag(),
+            self.zone,
+            self.dnskey_record()
+        )
+    }
+
+    /*
+    Description:
+    returns the contents of the key's private file (.private), in BIND's v1.3 private key format, with the key published and active from its creation.
+
+    Parameters:
+    created: when the key was created, as YYYYMMDDHHMMSS in UTC.
+
+    Returns:
+    The file's contents, which must only be readable by the key's owner.
+    */
+    pub fn private_file(&self, created: &str) -> String {
+        format!(
+            "Private-key-format: v1.3\nAlgorithm: {ED25519} (ED25519)\nPrivateKey: {}\nCreated: {created}\nPublish: {created}\nActivate: {created}\n",
+            base64::engine::general_purpose::STANDARD.encode(&self.seed)
+        )
+    }
+}
@@ -0,0 +1,486 @@
+use crate::{anonymize::Anonymizer, tasks};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter},
+    net::UnixStream,
+    sync::mpsc,
+};
+use tracing::*;
+use trust_dns_server::{
+    authority::{MessageRequest, MessageResponse, MessageResponseBuilder},
+    client::rr::Record,
+    proto::serialize::binary::{BinDecodable, BinEncodable, BinEncoder},
+    server::{Protocol, Request, ResponseHandler, ResponseInfo},
+};
+
+// The content type announced in the frame stream control frames.
+const CONTENT_TYPE: &[u8] = b"protobuf:dnstap.Dnstap";
+
+// Frame stream control frame types.
+const CONTROL_ACCEPT: u32 = 0x01;
+const CONTROL_START: u32 = 0x02;
+const CONTROL_READY: u32 = 0x04;
+
+// The control frame field carrying the content type.
+const CONTROL_FIELD_CONTENT_TYPE: u32 = 0x01;
+
+// How long to wait before reconnecting to the dnstap socket after a failure.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// dnstap Message.Type values for an authoritative server.
+const AUTH_QUERY: u64 = 1;
+const AUTH_RESPONSE: u64 = 2;
+
+/*
+Description:
+where dnstap frames are written: a file, or the Unix socket of a collector such as dnstap-receiver or fstrm_capture.
+*/
+#[derive(Clone, Debug)]
+pub enum Sink {
+    File(PathBuf),
+    Socket(PathBuf),
+}
+
+/*
+Description:
+a dnstap logger. Queries and responses are encoded as dnstap protobuf messages and handed to a background task that writes them out as a Frame Streams stream. The hand-off goes through a bounded buffer, and frames are dropped (and counted) rather than slowing down query handling when the sink cannot keep up. Only one in every sample_rate queries is logged.
+*/
+#[derive(Debug)]
+pub struct Dnstap {
+    // Channel to the background writer task
+    frames: mpsc::Sender<Vec<u8>>,
+
+    // Log one in every sample_rate queries
+    sample_rate: u64,
+
+    // The number of queries seen, used for sampling
+    seen: AtomicU64,
+
+    // The number of frames dropped because the buffer was full
+    dropped: AtomicU64,
+
+    // The server identity reported in every message
+    identity: String,
+
+    // Strips personal data from messages before they are written, if anonymization is enabled
+    anonymizer: Option<Arc<Anonymizer>>,
+}
+
+impl Dnstap {
+    /*
+    Description:
+    creates a dnstap logger and spawns the background task that writes to the sink. Must be called from within the tokio runtime.
+
+    Parameters:
+    sink: where to write the frames.
+    identity: the server identity reported in every message, e.g. the served domain.
+    sample_rate: log one in every sample_rate queries; 0 is treated as 1.
+    buffer_size: the number of frames that can be queued for the writer before new frames are dropped.
+    anonymizer: strips personal data from every message if given.
+
+    Returns:
+    A new Dnstap instance.
+    */
+    pub fn spawn(
+        sink: Sink,
+        identity: &str,
+        sample_rate: u64,
+        buffer_size: usize,
+        anonymizer: Option<Arc<Anonymizer>>,
+    ) -> Self {
+        // Queue frames for the writer task
+        let (frames, receiver) = mpsc::channel(buffer_size.max(1));
+        tasks::spawn("dnstap-writer", write_frames(sink, receiver));
+
+        Dnstap {
+            frames,
+            sample_rate: sample_rate.max(1),
+            seen: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            identity: identity.to_string(),
+            anonymizer,
+        }
+    }
+
+    // Returns the number of frames dropped because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /*
+    Description:
+    decides whether the next query should be logged.
+
+    Parameters:
+    None
+
+    Returns:
+    true if the query (and its response) should be logged.
+    */
+    pub fn sample(&self) -> bool {
+        self.seen.fetch_add(1, Ordering::Relaxed).is_multiple_of(self.sample_rate)
+    }
+
+    /*
+    Description:
+    logs a query received from a client.
+
+    Parameters:
+    request: the query.
+    query_time: when the query was received.
+
+    Returns:
+    None
+    */
+    pub fn log_query(&self, request: &Request, query_time: SystemTime) {
+        // Re-encode the query; it was decoded on the way in and re-encoding is lossless
+        let query_message = match request.to_bytes() {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                warn!("Failed to encode query for dnstap: {error}");
+                return;
+            }
+        };
+
+        self.send(Message {
+            kind: AUTH_QUERY,
+            client: request.src(),
+            protocol: request.protocol(),
+            query_time,
+            query_message: Some(query_message),
+            response_time: None,
+            response_message: None,
+        });
+    }
+
+    // Anonymizes and encodes a message and queues it for the writer, dropping it if the buffer is full
+    fn send(&self, mut message: Message) {
+        if let Some(anonymizer) = &self.anonymizer {
+            message.client = SocketAddr::new(anonymizer.ip(message.client.ip()), 0);
+            message.query_message = message.query_message.and_then(|bytes| anonymizer.message(&bytes));
+            message.response_message = message.response_message.and_then(|bytes| anonymizer.message(&bytes));
+        }
+
+        let frame = message.encode(&self.identity);
+        if self.frames.try_send(frame).is_err() {
+            // Only warn on the first drop and then every 1000th, so a slow sink doesn't flood the log
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed);
+            if dropped.is_multiple_of(1000) {
+                warn!("dnstap buffer full, {} frames dropped so far", dropped + 1);
+            }
+        }
+    }
+}
+
+/*
+Description:
+a ResponseHandler that logs the response to dnstap before handing it to the wrapped handler. A MessageResponse can only be serialized once, so the response is serialized for dnstap, decoded again and rebuilt for the wrapped handler. This costs an extra round trip through the wire format, but only for sampled queries.
+*/
+#[derive(Clone)]
+pub struct DnstapResponder<R: ResponseHandler> {
+    // The handler that sends the response to the client
+    inner: R,
+
+    // The dnstap logger
+    dnstap: Arc<Dnstap>,
+
+    // The client the response is for
+    client: SocketAddr,
+
+    // The transport the query arrived over
+    protocol: Protocol,
+
+    // When the query was received
+    query_time: SystemTime,
+}
+
+impl<R: ResponseHandler> DnstapResponder<R> {
+    /*
+    Description:
+    wraps a response handler so the response to a request is logged.
+
+    Parameters:
+    inner: the handler that sends the response to the client.
+    dnstap: the dnstap logger.
+    request: the query being answered.
+    query_time: when the query was received.
+
+    Returns:
+    A new DnstapResponder.
+    */
+    pub fn new(inner: R, dnstap: Arc<Dnstap>, request: &Request, query_time: SystemTime) -> Self {
+        DnstapResponder {
+            inner,
+            dnstap,
+            client: request.src(),
+            protocol: request.protocol(),
+            query_time,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: ResponseHandler> ResponseHandler for DnstapResponder<R> {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        // Serialize the response for dnstap
+        let mut bytes = Vec::with_capacity(512);
+        response
+            .destructive_emit(&mut BinEncoder::new(&mut bytes))
+            .map_err(io::Error::other)?;
+
+        // Decode it again so it can be rebuilt for the wrapped handler
+        let message = MessageRequest::from_bytes(&bytes)
+            .map_err(io::Error::other)?;
+
+        self.dnstap.send(Message {
+            kind: AUTH_RESPONSE,
+            client: self.client,
+            protocol: self.protocol,
+            query_time: self.query_time,
+            query_message: None,
+            response_time: Some(SystemTime::now()),
+            response_message: Some(bytes),
+        });
+
+        // Rebuild the response and send it
+        let mut builder = MessageResponseBuilder::from_message_request(&message);
+        if let Some(edns) = message.edns() {
+            builder.edns(edns.clone());
+        }
+        let response = builder.build(
+            *message.header(),
+            message.answers().iter(),
+            message.name_servers().iter(),
+            &[],
+            message.additionals().iter(),
+        );
+        self.inner.send_response(response).await
+    }
+}
+
+// A single dnstap message, before encoding.
+struct Message {
+    kind: u64,
+    client: SocketAddr,
+    protocol: Protocol,
+    query_time: SystemTime,
+    query_message: Option<Vec<u8>>,
+    response_time: Option<SystemTime>,
+    response_message: Option<Vec<u8>>,
+}
+
+impl Message {
+    /*
+    Description:
+    encodes the message as a dnstap protobuf (a Dnstap wrapper of type MESSAGE around a Message).
+
+    Parameters:
+    identity: the server identity to report.
+
+    Returns:
+    The encoded Dnstap protobuf.
+    */
+    fn encode(&self, identity: &str) -> Vec<u8> {
+        // The Message
+        let mut message = Vec::with_capacity(256);
+        put_varint_field(&mut message, 1, self.kind);
+        let (family, address) = match self.client.ip() {
+            IpAddr::V4(ip) => (1, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (2, ip.octets().to_vec()),
+        };
+        put_varint_field(&mut message, 2, family);
+        put_varint_field(&mut message, 3, if matches!(self.protocol, Protocol::Udp) { 1 } else { 2 });
+        put_bytes_field(&mut message, 4, &address);
+        if self.client.port() != 0 {
+            put_varint_field(&mut message, 6, self.client.port() as u64);
+        }
+        put_time_fields(&mut message, 8, self.query_time);
+        if let Some(query) = &self.query_message {
+            put_bytes_field(&mut message, 10, query);
+        }
+        if let Some(response_time) = self.response_time {
+            put_time_fields(&mut message, 12, response_time);
+        }
+        if let Some(response) = &self.response_message {
+            put_bytes_field(&mut message, 14, response);
+        }
+
+        // The Dnstap wrapper
+        let mut dnstap = Vec::with_capacity(message.len() + 64);
+        put_bytes_field(&mut dnstap, 1, identity.as_bytes());
+        put_bytes_field(
+            &mut dnstap,
+            2,
+            concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")).as_bytes(),
+        );
+        put_bytes_field(&mut dnstap, 14, &message);
+        put_varint_field(&mut dnstap, 15, 1);
+        dnstap
+    }
+}
+
+// Appends a protobuf varint
+fn put_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+// Appends a varint field (wire type 0)
+fn put_varint_field(buffer: &mut Vec<u8>, field: u64, value: u64) {
+    put_varint(buffer, field << 3);
+    put_varint(buffer, value);
+}
+
+// Appends a length-delimited field (wire type 2)
+fn put_bytes_field(buffer: &mut Vec<u8>, field: u64, value: &[u8]) {
+    put_varint(buffer, field << 3 | 2);
+    put_varint(buffer, value.len() as u64);
+    buffer.extend_from_slice(value);
+}
+
+// Appends a timestamp as a uint64 seconds field followed by a fixed32 nanoseconds field
+fn put_time_fields(buffer: &mut Vec<u8>, seconds_field: u64, time: SystemTime) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    put_varint_field(buffer, seconds_field, since_epoch.as_secs());
+    put_varint(buffer, (seconds_field + 1) << 3 | 5);
+    buffer.extend_from_slice(&since_epoch.subsec_nanos().to_le_bytes());
+}
+
+/*
+Description:
+the background writer task. It opens the sink, writes the Frame Streams handshake and then every queued frame, batching flushes while frames keep arriving. If the sink fails it is reopened after a short delay; frames queued in the meantime are kept up to the buffer size. The task ends when the Dnstap logger is dropped.
+
+Parameters:
+sink: where to write the frames.
+frames: the receiving end of the frame buffer.
+
+Returns:
+None
+*/
+async fn write_frames(sink: Sink, mut frames: mpsc::Receiver<Vec<u8>>) {
+    loop {
+        // Open the sink and perform the handshake
+        let mut writer = match open(&sink).await {
+            Ok(writer) => writer,
+            Err(error) => {
+                warn!("Failed to open dnstap sink {sink:?}: {error}");
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+
+        // Write frames until the sink fails or the logger goes away
+        loop {
+            let Some(frame) = frames.recv().await else {
+                return;
+            };
+            if let Err(error) = write_batch(&mut writer, frame, &mut frames).await {
+                warn!("Failed to write to dnstap sink {sink:?}: {error}");
+                break;
+            }
+        }
+
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+// Writes a frame plus any others already queued, then flushes
+async fn write_batch(
+    writer: &mut (impl AsyncWrite + Unpin),
+    frame: Vec<u8>,
+    frames: &mut mpsc::Receiver<Vec<u8>>,
+) -> io::Result<()> {
+    write_data_frame(writer, &frame).await?;
+    while let Ok(frame) = frames.try_recv() {
+        write_data_frame(writer, &frame).await?;
+    }
+    writer.flush().await
+}
+
+/*
+Description:
+opens a sink and writes the Frame Streams start of stream. Files get a unidirectional stream (a START frame), while sockets get the bidirectional handshake (READY, wait for ACCEPT, then START) that collectors expect.
+
+Parameters:
+sink: the sink to open.
+
+Returns:
+Ok(writer) ready for data frames, or an io::Error if opening or the handshake failed.
+*/
+async fn open(sink: &Sink) -> io::Result<Box<dyn AsyncWrite + Unpin + Send>> {
+    match sink {
+        Sink::File(path) => {
+            let mut file = BufWriter::new(tokio::fs::File::create(path).await?);
+            write_control_frame(&mut file, CONTROL_START).await?;
+            file.flush().await?;
+            Ok(Box::new(file))
+        }
+        Sink::Socket(path) => {
+            let mut socket = UnixStream::connect(path).await?;
+            write_control_frame(&mut socket, CONTROL_READY).await?;
+            read_accept(&mut socket).await?;
+            write_control_frame(&mut socket, CONTROL_START).await?;
+            Ok(Box::new(BufWriter::new(socket)))
+        }
+    }
+}
+
+// Writes a control frame announcing the dnstap content type
+async fn write_control_frame(writer: &mut (impl AsyncWrite + Unpin), kind: u32) -> io::Result<()> {
+    let length = 4 + 4 + 4 + CONTENT_TYPE.len() as u32;
+    writer.write_u32(0).await?;
+    writer.write_u32(length).await?;
+    writer.write_u32(kind).await?;
+    writer.write_u32(CONTROL_FIELD_CONTENT_TYPE).await?;
+    writer.write_u32(CONTENT_TYPE.len() as u32).await?;
+    writer.write_all(CONTENT_TYPE).await
+}
+
+// Writes a data frame
+async fn write_data_frame(writer: &mut (impl AsyncWrite + Unpin), frame: &[u8]) -> io::Result<()> {
+    writer.write_u32(frame.len() as u32).await?;
+    writer.write_all(frame).await
+}
+
+// Reads the collector's ACCEPT control frame
+async fn read_accept(socket: &mut UnixStream) -> io::Result<()> {
+    // Control frames start with a zero-length escape
+    if socket.read_u32().await? != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "expected a control frame"));
+    }
+
+    // Read the control frame and check its type
+    let length = socket.read_u32().await? as usize;
+    if !(4..=512).contains(&length) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid control frame length"));
+    }
+    let mut control = vec![0; length];
+    socket.read_exact(&mut control).await?;
+    if control[..4] != CONTROL_ACCEPT.to_be_bytes() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "expected an ACCEPT control frame"));
+    }
+
+    Ok(())
+}
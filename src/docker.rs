@@ -0,0 +1,208 @@
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+use tracing::*;
+
+// The keyword of the zone containers are served in, as <name>.docker.<domain>.
+pub const ZONE: &str = "docker";
+
+// The TTL of the containers' A and AAAA records, short since containers come and go.
+pub const TTL: u32 = 5;
+
+// The label Docker Compose gives each container with the name of its service.
+const COMPOSE_SERVICE: &str = "com.docker.compose.service";
+
+// The label Docker Compose gives each container with the name of its project.
+const COMPOSE_PROJECT: &str = "com.docker.compose.project";
+
+// The events that change which containers run or where, URL-encoded for the filters parameter of /events.
+const EVENT_FILTERS: &str = "%7B%22type%22%3A%5B%22container%22%2C%22network%22%5D%2C%22event%22%3A%5B%22start%22%2C%22die%22%2C%22rename%22%2C%22connect%22%2C%22disconnect%22%5D%7D";
+
+// How long to wait before reconnecting to the Docker socket after losing it.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// A container as /containers/json lists it, with just the fields the zone needs
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Container {
+    #[serde(default)]
+    names: Vec<String>,
+
+    #[serde(default)]
+    labels: HashMap<String, String>,
+
+    #[serde(default)]
+    network_settings: NetworkSettings,
+}
+
+// The networks a container is attached to
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "PascalCase")]
+struct NetworkSettings {
+    #[serde(default)]
+    networks: BTreeMap<String, Network>,
+}
+
+// A container's addresses on one network, which are empty strings if it has none
+#[derive(Deserialize, Default)]
+struct Network {
+    #[serde(rename = "IPAddress", default)]
+    ipv4: String,
+
+    #[serde(rename = "GlobalIPv6Address", default)]
+    ipv6: String,
+}
+
+/*
+Description:
+the docker zone: the addresses of the running containers of the local Docker engine, served as <name>.docker.<domain> under each container's name, its Compose service (<service>.docker.<domain>, with every replica's addresses) and its Compose service and project (<service>.<project>.docker.<domain>). The names are refreshed from the Docker socket whenever a container starts, stops, is renamed or changes networks.
+*/
+#[derive(Debug, Default)]
+pub struct Docker {
+    // The addresses of each name below the zone, lower-case
+    names: RwLock<HashMap<String, Vec<IpAddr>>>,
+}
+
+impl Docker {
+    // Returns the addresses served for a name below the zone, e.g. "web" or "web.shop", or none if no running container has it
+    pub fn addresses(&self, name: &str) -> Vec<IpAddr> {
+        let names = self.names.read().unwrap();
+        names.get(&name.to_ascii_lowercase()).cloned().unwrap_or_default()
+    }
+
+    // Returns the number of names served
+    pub fn names(&self) -> usize {
+        self.names.read().unwrap().len()
+    }
+
+    /*
+    Description:
+    replaces the names served with those of a container list, as the Docker API's /containers/json returns it.
+
+    Parameters:
+    containers: the JSON container list.
+
+    Returns:
+    Ok(usize) with the number of names now served, or an io::Error if the list is not valid JSON, in which case the names are kept.
+    */
+    pub fn load(&self, containers: &[u8]) -> io::Result<usize> {
+        let containers: Vec<Container> = serde_json::from_slice(containers).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        let mut names: HashMap<String, Vec<IpAddr>> = HashMap::new();
+        for container in containers {
+            let addresses: Vec<IpAddr> = container
+                .network_settings
+                .networks
+                .values()
+                .flat_map(|network| [network.ipv4.parse().ok(), network.ipv6.parse().ok()])
+                .flatten()
+                .collect();
+            if addresses.is_empty() {
+                continue;
+            }
+
+            // Docker lists names with a leading slash, e.g. "/shop-web-1"
+            let mut container_names: Vec<String> = container.names.iter().map(|name| name.trim_start_matches('/').to_string()).collect();
+            if let Some(service) = container.labels.get(COMPOSE_SERVICE) {
+                container_names.push(service.clone());
+                if let Some(project) = container.labels.get(COMPOSE_PROJECT) {
+                    container_names.push(format!("{service}.{project}"));
+                }
+            }
+            for name in container_names {
+                let served = names.entry(name.to_ascii_lowercase()).or_default();
+                for address in &addresses {
+                    if !served.contains(address) {
+                        served.push(*address);
+                    }
+                }
+            }
+        }
+
+        let count = names.len();
+        *self.names.write().unwrap() = names;
+        Ok(count)
+    }
+}
+
+/*
+Description:
+keeps the docker zone in step with the Docker engine: loads the running containers, then follows the engine's event stream and loads them again after every event that changes them. If the socket can't be reached or the stream ends, it retries every few seconds, serving the last names it loaded meanwhile.
+
+Parameters:
+docker: the zone to keep up to date.
+socket: the path of the Docker socket, e.g. /var/run/docker.sock.
+
+Returns:
+None; runs until the process exits.
+*/
+pub async fn watch(docker: Arc<Docker>, socket: PathBuf) {
+    loop {
+        if let Err(error) = follow(&docker, &socket).await {
+            warn!("Lost the Docker socket {}, reconnecting in {}s: {error}", socket.display(), RECONNECT_DELAY.as_secs());
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+// Loads the containers, then loads them again after each event until the event stream ends
+async fn follow(docker: &Docker, socket: &Path) -> io::Result<()> {
+    // Subscribe before listing, so no change between the two is missed
+    let mut events = BufReader::new(request(socket, &format!("/events?filters={EVENT_FILTERS}")).await?);
+    skip_head(&mut events).await?;
+
+    let count = docker.load(&get(socket, "/containers/json").await?)?;
+    info!("Serving {count} Docker container names");
+
+    // Each event is a line of JSON; what changed doesn't matter, since the whole list is loaded again
+    let mut line = String::new();
+    while events.read_line(&mut line).await? > 0 {
+        if !line.trim().is_empty() {
+            let count = docker.load(&get(socket, "/containers/json").await?)?;
+            debug!("Docker event, now serving {count} container names");
+        }
+        line.clear();
+    }
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the event stream ended"))
+}
+
+// Sends a GET request to the Docker API over its socket. HTTP/1.0 keeps the engine from chunking the answer, so the body is just the bytes up to the end of the connection.
+async fn request(socket: &Path, path: &str) -> io::Result<UnixStream> {
+    let mut stream = UnixStream::connect(socket).await?;
+    stream.write_all(format!("GET {path} HTTP/1.0\r\nHost: docker\r\n\r\n").as_bytes()).await?;
+    Ok(stream)
+}
+
+// Sends a GET request to the Docker API and returns the body of its answer
+async fn get(socket: &Path, path: &str) -> io::Result<Vec<u8>> {
+    let mut response = BufReader::new(request(socket, path).await?);
+    skip_head(&mut response).await?;
+    let mut body = Vec::new();
+    response.read_to_end(&mut body).await?;
+    Ok(body)
+}
+
+// Reads the status line and headers of an answer, failing unless its status is 200
+async fn skip_head(response: &mut BufReader<UnixStream>) -> io::Result<()> {
+    let mut line = String::new();
+    response.read_line(&mut line).await?;
+    if line.split_whitespace().nth(1) != Some("200") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("the Docker API answered {}", line.trim())));
+    }
+    loop {
+        line.clear();
+        if response.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            return Ok(());
+        }
+    }
+}
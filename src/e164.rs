@@ -0,0 +1,202 @@
+use crate::config::EnumConfig;
+use std::{collections::BTreeMap, str::FromStr};
+use trust_dns_server::client::rr::{rdata::NAPTR, LowerName, Name, RData, Record};
+
+// The most digits an E.164 number has, country code included.
+pub const MAX_DIGITS: usize = 15;
+
+// The order of every NAPTR record answered; records of a number differ only in their preference (RFC 6116).
+const ORDER: u16 = 100;
+
+/*
+Description:
+reads a telephone number written in E.164 form, with or without the leading + and with spaces, dashes, dots or parentheses between its digits.
+
+Parameters:
+number: the number, e.g. "+44 20 7946 0000".
+
+Returns:
+Some(String) with its digits, e.g. "442079460000", or None if it isn't a number of 1 to 15 digits.
+*/
+pub fn digits(number: &str) -> Option<String> {
+    let digits: String = number
+        .strip_prefix('+')
+        .unwrap_or(number)
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+        .collect();
+    let valid = (1..=MAX_DIGITS).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit());
+    valid.then_some(digits)
+}
+
+/*
+Description:
+builds the ENUM name of a number: its digits reversed, one label each, under a zone, as RFC 6116 names them.
+
+Parameters:
+digits: the number's digits, as digits returns them.
+zone: the zone, e.g. "e164.arpa".
+
+Returns:
+The name, e.g. 0.0.0.0.6.4.9.7.0.2.4.4.e164.arpa. for 442079460000.
+*/
+pub fn reverse_name(digits: &str, zone: &str) -> String {
+    let labels: Vec<String> = digits.chars().rev().map(String::from).collect();
+    format!("{}.{}.", labels.join("."), zone.trim_end_matches('.'))
+}
+
+/*
+Description:
+the ENUM service of a URI, from its scheme: the service field of the NAPTR records a number's URIs are answered with (RFC 3764, RFC 4355, RFC 4415, RFC 4002).
+
+Parameters:
+uri: the URI, e.g. "sip:alice@pbx.example.com".
+
+Returns:
+Some(&str) with the service, e.g. "E2U+sip", or None if ENUM has no service for the scheme.
+*/
+pub fn service(uri: &str) -> Option<&'static str> {
+    let (scheme, rest) = uri.split_once(':')?;
+    if rest.is_empty() {
+        return None;
+    }
+    match scheme.to_ascii_lowercase().as_str() {
+        "sip" | "sips" => Some("E2U+sip"),
+        "tel" => Some("E2U+voice:tel"),
+        "mailto" => Some("E2U+email:mailto"),
+        "http" => Some("E2U+web:http"),
+        "https" => Some("E2U+web:https"),
+        "h323" => Some("E2U+h323"),
+        "xmpp" => Some("E2U+xmpp"),
+        _ => None,
+    }
+}
+
+/*
+Description:
+an ENUM zone (RFC 6116), from the [enum] section of the configuration file: telephone numbers served under the names of their reversed digits, each answered with a NAPTR record per URI it maps to, such as the SIP address of a lab phone. Numbers are listed one by one, or served by the URI templates of a prefix they start with, in which {number} stands for the number in E.164 form; a listed number takes precedence over prefixes, and the longest prefix over shorter ones.
+*/
+#[derive(Debug)]
+pub struct EnumZone {
+    // The zone the numbers are served under
+    zone: LowerName,
+
+    // The TTL of the NAPTR records
+    ttl: u32,
+
+    // The URIs of each listed number, by its digits
+    numbers: BTreeMap<String, Vec<String>>,
+
+    // The URI templates of each prefix, by its digits
+    prefixes: BTreeMap<String, Vec<String>>,
+}
+
+impl EnumZone {
+    /*
+    Description:
+    builds an ENUM zone from the [enum] section, checking that its zone is a name, its numbers and prefixes are E.164 numbers and its URIs have a scheme ENUM has a service for.
+
+    Parameters:
+    config: the [enum] section.
+
+    Returns:
+    Ok(EnumZone), or an error message saying which number is wrong and why.
+    */
+    pub fn new(config: &EnumConfig) -> Result<Self, String> {
+        let zone = Name::from_str(&config.zone).map_err(|error| format!("[enum] invalid zone {}: {error}", config.zone))?;
+        let read = |entries: &BTreeMap<String, Vec<String>>, kind: &str| -> Result<BTreeMap<String, Vec<String>>, String> {
+            let mut read = BTreeMap::new();
+            for (number, uris) in entries {
+                let context = |error: String| format!("[enum] {kind} {number}: {error}");
+                let digits = digits(number).ok_or_else(|| context(format!("not an E.164 number of up to {MAX_DIGITS} digits")))?;
+                if uris.is_empty() {
+                    return Err(context("has no URIs".to_string()));
+                }
+                if let Some(uri) = uris.iter().find(|uri| service(uri).is_none()) {
+                    return Err(context(format!("{uri} has no ENUM service (sip, sips, tel, mailto, http, https, h323 or xmpp)")));
+                }
+                if read.insert(digits, uris.clone()).is_some() {
+                    return Err(context("is given more than once".to_string()));
+                }
+            }
+            Ok(read)
+        };
+        Ok(EnumZone {
+            zone: LowerName::from(zone),
+            ttl: config.ttl,
+            numbers: read(&config.numbers, "number")?,
+            prefixes: read(&config.prefixes, "prefix")?,
+        })
+    }
+
+    // Returns whether a name is in the ENUM zone
+    pub fn serves(&self, name: &LowerName) -> bool {
+        self.zone.zone_of(name)
+    }
+
+    // Returns the digits a name in the zone stands for (e.g. "4321" for 1.2.3.4.<zone>, "" for the zone itself), or None if its labels aren't single digits or there are too many
+    pub fn number(&self, name: &LowerName) -> Option<String> {
+        let below = usize::from(name.num_labels().checked_sub(self.zone.num_labels())?);
+        if below > MAX_DIGITS {
+            return None;
+        }
+        let name = Name::from(name);
+        let labels: Vec<&[u8]> = name.iter().take(below).collect();
+        if labels.iter().any(|label| label.len() != 1 || !label[0].is_ascii_digit()) {
+            return None;
+        }
+        Some(labels.iter().rev().map(|label| char::from(label[0])).collect())
+    }
+
+    // Returns the URIs of a number: its own if it is listed, else the templates of its longest prefix filled in
+    pub fn uris(&self, digits: &str) -> Option<Vec<String>> {
+        if let Some(uris) = self.numbers.get(digits) {
+            return Some(uris.clone());
+        }
+        let templates = (1..=digits.len()).rev().find_map(|length| self.prefixes.get(&digits[..length]))?;
+        Some(templates.iter().map(|template| template.replace("{number}", &format!("+{digits}"))).collect())
+    }
+
+    /*
+    Description:
+    looks up the NAPTR records of a name in the zone.
+
+    Parameters:
+    name: the name, in the zone.
+
+    Returns:
+    Some(Vec<Record>) with a NAPTR record per URI of the number the name stands for, in the order the URIs are given; an empty Vec for the zone itself and the names on the way to a number; or None if the name doesn't exist.
+    */
+    pub fn lookup(&self, name: &LowerName) -> Option<Vec<Record>> {
+        let digits = self.number(name)?;
+        if let Some(uris) = self.uris(&digits) {
+            let owner = Name::from(name);
+            let records = uris
+                .iter()
+                .enumerate()
+                .filter_map(|(index, uri)| {
+                    let preference = u16::try_from((index + 1) * 10).ok()?;
+                    Some(Record::from_rdata(owner.clone(), self.ttl, RData::NAPTR(naptr(preference, uri)?)))
+                })
+                .collect();
+            return Some(records);
+        }
+
+        // Names above a listed number exist, without records of their own
+        let on_the_way = self.numbers.keys().chain(self.prefixes.keys()).any(|number| number.starts_with(&digits));
+        on_the_way.then(Vec::new)
+    }
+}
+
+// Builds the NAPTR record of a URI, which rewrites any number to the URI and ends the lookup (the "u" flag)
+fn naptr(preference: u16, uri: &str) -> Option<NAPTR> {
+    let regexp = format!("!^.*$!{uri}!");
+    Some(NAPTR::new(
+        ORDER,
+        preference,
+        b"u".to_vec().into_boxed_slice(),
+        service(uri)?.as_bytes().to_vec().into_boxed_slice(),
+        regexp.into_bytes().into_boxed_slice(),
+        Name::root(),
+    ))
+}
@@ -0,0 +1,188 @@
+use crate::{handlers::Handler, stats, tasks};
+use std::{collections::HashMap, io, net::SocketAddr, time::Duration};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, UdpSocket},
+};
+use tracing::*;
+
+// The largest StatsD packet sent, small enough to avoid IP fragmentation on common networks.
+const STATSD_PACKET_SIZE: usize = 1432;
+
+/*
+Description:
+where metrics are pushed to: a StatsD server over UDP, or a Graphite (Carbon plaintext) server over TCP.
+*/
+#[derive(Clone, Copy, Debug)]
+pub enum Target {
+    Statsd(SocketAddr),
+    Graphite(SocketAddr),
+}
+
+// Whether a metric only ever grows (a counter) or is a point-in-time value (a gauge)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Counter,
+    Gauge,
+}
+
+/*
+Description:
+spawns the task that pushes the server's metrics every flush interval. StatsD receives counters as the increase since the last flush, and Graphite receives their running totals, as each expects. Must be called from within the tokio runtime.
+
+Parameters:
+handler: the DNS handler whose metrics are exported.
+target: the StatsD or Graphite server.
+prefix: the prefix of every metric name, e.g. "rusty_dns".
+interval: how often the metrics are pushed.
+
+Returns:
+None
+*/
+pub fn spawn(handler: Handler, target: Target, prefix: String, interval: Duration) {
+    tasks::spawn("metrics-export", async move {
+        let mut exporter = Exporter {
+            target,
+            prefix,
+            previous: HashMap::new(),
+            graphite: None,
+        };
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            if let Err(error) = exporter.flush(&handler).await {
+                warn!("Failed to export metrics to {target:?}: {error}");
+            }
+        }
+    });
+}
+
+// The exporter's state between flushes
+struct Exporter {
+    // Where the metrics go
+    target: Target,
+
+    // The prefix of every metric name
+    prefix: String,
+
+    // The counter values sent at the previous flush, for StatsD deltas
+    previous: HashMap<String, u64>,
+
+    // The open Graphite connection, reopened after a failure
+    graphite: Option<TcpStream>,
+}
+
+impl Exporter {
+    // Collects the metrics and sends them to the target
+    async fn flush(&mut self, handler: &Handler) -> io::Result<()> {
+        let samples = samples(&self.prefix, handler);
+
+        match self.target {
+            Target::Statsd(address) => {
+                // Send counters as the increase since the previous flush
+                let mut lines = Vec::with_capacity(samples.len());
+                for (name, value, kind) in samples {
+                    match kind {
+                        Kind::Counter => {
+                            let previous = self.previous.insert(name.clone(), value).unwrap_or(0);
+                            lines.push(format!("{name}:{}|c", value.saturating_sub(previous)));
+                        }
+                        Kind::Gauge => lines.push(format!("{name}:{value}|g")),
+                    }
+                }
+
+                // Pack the lines into as few packets as possible
+                let socket = UdpSocket::bind(if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await?;
+                let mut packet = String::new();
+                for line in lines {
+                    if !packet.is_empty() && packet.len() + line.len() + 1 > STATSD_PACKET_SIZE {
+                        socket.send_to(packet.as_bytes(), address).await?;
+                        packet.clear();
+                    }
+                    if !packet.is_empty() {
+                        packet.push('\n');
+                    }
+                    packet.push_str(&line);
+                }
+                if !packet.is_empty() {
+                    socket.send_to(packet.as_bytes(), address).await?;
+                }
+                Ok(())
+            }
+            Target::Graphite(address) => {
+                let timestamp = chrono::Utc::now().timestamp();
+                let text: String = samples
+                    .into_iter()
+                    .map(|(name, value, _)| format!("{name} {value} {timestamp}\n"))
+                    .collect();
+
+                // Reuse the connection, reconnecting if it was lost
+                if self.graphite.is_none() {
+                    self.graphite = Some(TcpStream::connect(address).await?);
+                }
+                let result = match &mut self.graphite {
+                    Some(stream) => stream.write_all(text.as_bytes()).await,
+                    None => Ok(()),
+                };
+                if result.is_err() {
+                    self.graphite = None;
+                }
+                result
+            }
+        }
+    }
+}
+
+// Turns the stats snapshot into (name, value, kind) samples
+fn samples(prefix: &str, handler: &Handler) -> Vec<(String, u64, Kind)> {
+    let stats = stats::collect(handler);
+    let mut samples = vec![
+        (format!("{prefix}.queries"), stats.queries, Kind::Counter),
+        (format!("{prefix}.qps"), stats.qps.round() as u64, Kind::Gauge),
+        (format!("{prefix}.uptime"), stats.uptime, Kind::Gauge),
+        (format!("{prefix}.clients_tracked"), stats.clients_tracked as u64, Kind::Gauge),
+        (format!("{prefix}.overload.shed"), stats.shed, Kind::Counter),
+        (format!("{prefix}.overload.in_flight"), handler.overload.in_flight() as u64, Kind::Gauge),
+    ];
+
+    // Traffic per zone
+    for zone in &stats.zones {
+        let zone_prefix = format!("{prefix}.zones.{}", sanitize(&zone.zone));
+        samples.push((format!("{zone_prefix}.queries"), zone.queries, Kind::Counter));
+        samples.push((format!("{zone_prefix}.errors"), zone.errors, Kind::Counter));
+    }
+
+    // Latency percentiles per zone and per transport
+    for zone in &stats.zone_latency {
+        let zone_prefix = format!("{prefix}.zones.{}.latency_us", sanitize(&zone.zone));
+        samples.push((format!("{zone_prefix}.p50"), zone.total.p50, Kind::Gauge));
+        samples.push((format!("{zone_prefix}.p95"), zone.total.p95, Kind::Gauge));
+        samples.push((format!("{zone_prefix}.p99"), zone.total.p99, Kind::Gauge));
+    }
+    for transport in &stats.transport_latency {
+        let transport_prefix = format!("{prefix}.transports.{}.latency_us", sanitize(&transport.transport));
+        samples.push((format!("{transport_prefix}.p50"), transport.total.p50, Kind::Gauge));
+        samples.push((format!("{transport_prefix}.p95"), transport.total.p95, Kind::Gauge));
+        samples.push((format!("{transport_prefix}.p99"), transport.total.p99, Kind::Gauge));
+    }
+
+    // Optional subsystems
+    if let Some(dropped) = stats.dnstap_dropped {
+        samples.push((format!("{prefix}.dnstap.dropped"), dropped, Kind::Counter));
+    }
+    if let Some(blocklist) = &stats.blocklist {
+        samples.push((format!("{prefix}.blocklist.blocked"), blocklist.blocked, Kind::Counter));
+        samples.push((format!("{prefix}.blocklist.domains"), blocklist.domains as u64, Kind::Gauge));
+    }
+
+    samples
+}
+
+// Makes a zone or transport name safe to use as a metric path segment
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
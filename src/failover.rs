@@ -0,0 +1,267 @@
+use crate::{
+    config::{FailoverConfig, HealthCheck},
+    tasks,
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{net::TcpStream, task::JoinSet};
+use tracing::*;
+use trust_dns_server::client::rr::{LowerName, Name, RData, Record, RecordType};
+
+// Whether a target is answered, and how many checks in a row have disagreed with that
+#[derive(Debug)]
+struct TargetState {
+    healthy: bool,
+    streak: u32,
+}
+
+// A target of a name: its address, the client its http(s) checks are made with, and its state
+#[derive(Debug)]
+struct Target {
+    address: IpAddr,
+    client: Option<reqwest::Client>,
+    state: Mutex<TargetState>,
+}
+
+impl Target {
+    // Counts the outcome of a check, returning the target's new state if the outcome changes it
+    fn record(&self, passed: bool, healthy_threshold: u32, unhealthy_threshold: u32) -> Option<bool> {
+        let mut state = self.state.lock().unwrap();
+        if passed == state.healthy {
+            state.streak = 0;
+            return None;
+        }
+        state.streak += 1;
+        let threshold = if passed { healthy_threshold } else { unhealthy_threshold };
+        if state.streak < threshold.max(1) {
+            return None;
+        }
+        state.healthy = passed;
+        state.streak = 0;
+        Some(passed)
+    }
+}
+
+// A name's targets and how they are checked
+#[derive(Debug)]
+struct TargetSet {
+    config: FailoverConfig,
+    owner: Name,
+    targets: Vec<Arc<Target>>,
+}
+
+impl TargetSet {
+    // Checks every target at once, updating their states
+    async fn check(&self) {
+        let mut checks = JoinSet::new();
+        for (index, target) in self.targets.iter().enumerate() {
+            let target = target.clone();
+            let (check, port, timeout) = (self.config.check, self.port(), Duration::from_secs(self.config.timeout.max(1)));
+            let url = self.url();
+            checks.spawn(async move { (index, probe(&target, check, port, &url, timeout).await) });
+        }
+        while let Some(Ok((index, outcome))) = checks.join_next().await {
+            let target = &self.targets[index];
+            let passed = outcome.is_ok();
+            match target.record(passed, self.config.healthy_threshold, self.config.unhealthy_threshold) {
+                Some(true) => info!("Failover target {} of {} is up again", target.address, self.config.name),
+                Some(false) => warn!(
+                    "Failover target {} of {} is down, no longer answering it: {}",
+                    target.address,
+                    self.config.name,
+                    outcome.err().unwrap_or_default()
+                ),
+                None if !passed => debug!("Failover check of {} for {} failed: {}", target.address, self.config.name, outcome.err().unwrap_or_default()),
+                None => {}
+            }
+        }
+    }
+
+    // Returns the port the targets are checked on
+    fn port(&self) -> u16 {
+        self.config.port.unwrap_or(match self.config.check {
+            HealthCheck::Https => 443,
+            _ => 80,
+        })
+    }
+
+    // Returns the URL http(s) checks request, which every target's client resolves to the target itself
+    fn url(&self) -> String {
+        let scheme = match self.config.check {
+            HealthCheck::Https => "https",
+            _ => "http",
+        };
+        let host = self.config.name.trim_end_matches('.');
+        format!("{scheme}://{host}:{}/{}", self.port(), self.config.path.trim_start_matches('/'))
+    }
+}
+
+// Checks a target once, returning why the check failed if it did
+async fn probe(target: &Target, check: HealthCheck, port: u16, url: &str, timeout: Duration) -> Result<(), String> {
+    match (check, &target.client) {
+        (HealthCheck::Tcp, _) | (_, None) => match tokio::time::timeout(timeout, TcpStream::connect(SocketAddr::new(target.address, port))).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(error)) => Err(format!("failed to connect to port {port}: {error}")),
+            Err(_) => Err(format!("connecting to port {port} timed out")),
+        },
+        (_, Some(client)) => {
+            let response = client.get(url).timeout(timeout).send().await.map_err(|error| format!("GET {url} failed: {error}"))?;
+            let status = response.status();
+            match status.is_success() || status.is_redirection() {
+                true => Ok(()),
+                false => Err(format!("GET {url} answered {status}")),
+            }
+        }
+    }
+}
+
+/*
+Description:
+health-checked failover: names from the [[failover]] sections of the configuration file, answered with the addresses of their targets that pass active health checks (a TCP connection, or an http(s) GET request), so clients stop being sent to a target that is down. Targets start up, and change state only after a number of checks in a row disagree with it, so a single lost check doesn't flap the answer. If every target of a type is down, all of them are answered unless the name is configured not to fail open.
+*/
+#[derive(Debug, Default)]
+pub struct Failover {
+    // The targets of each name
+    names: HashMap<LowerName, Arc<TargetSet>>,
+}
+
+impl Failover {
+    /*
+    Description:
+    builds the targets of [[failover]] sections, without checking them yet, checking that each name is valid and given once, has targets, and can be checked.
+
+    Parameters:
+    configs: the [[failover]] sections.
+
+    Returns:
+    Ok(Failover), or an error message saying which section is wrong and why.
+    */
+    pub fn new(configs: &[FailoverConfig]) -> Result<Self, String> {
+        let mut failover = Failover::default();
+        for config in configs {
+            let context = |error: String| format!("[[failover]] {}: {error}", config.name);
+            let owner = Name::parse(&config.name, Some(&Name::root())).map_err(|error| context(format!("invalid name: {error}")))?;
+            if config.addresses.is_empty() {
+                return Err(context("has no addresses".to_string()));
+            }
+            if config.check == HealthCheck::Tcp && config.port.is_none() {
+                return Err(context("tcp checks need a port".to_string()));
+            }
+
+            let mut set = TargetSet {
+                config: config.clone(),
+                owner: owner.clone(),
+                targets: Vec::new(),
+            };
+            for &address in &config.addresses {
+                // http(s) checks ask for the record's name, so virtual hosts and certificates match, on the target's own address
+                let client = match config.check {
+                    HealthCheck::Tcp => None,
+                    HealthCheck::Http | HealthCheck::Https => Some(
+                        reqwest::Client::builder()
+                            .resolve(config.name.trim_end_matches('.'), SocketAddr::new(address, set.port()))
+                            .redirect(reqwest::redirect::Policy::none())
+                            .build()
+                            .map_err(|error| context(format!("failed to set up the checks of {address}: {error}")))?,
+                    ),
+                };
+                let state = Mutex::new(TargetState { healthy: true, streak: 0 });
+                set.targets.push(Arc::new(Target { address, client, state }));
+            }
+
+            if failover.names.insert(LowerName::new(&owner), Arc::new(set)).is_some() {
+                return Err(context("the name is given more than once".to_string()));
+            }
+        }
+        Ok(failover)
+    }
+
+    // Returns whether a name is answered by failover
+    pub fn serves(&self, name: &LowerName) -> bool {
+        self.names.contains_key(name)
+    }
+
+    /*
+    Description:
+    answers a name with the addresses of its targets that are up.
+
+    Parameters:
+    name: the name.
+    record_type: the type asked for.
+
+    Returns:
+    Some(Vec<Record>) with an A or AAAA record per target of the type that is up, or per target of the type if all are down and the name fails open; or None if the name has no targets of the type.
+    */
+    pub fn answer(&self, name: &LowerName, record_type: RecordType) -> Option<Vec<Record>> {
+        let set = self.names.get(name)?;
+        let targets: Vec<&Arc<Target>> = set
+            .targets
+            .iter()
+            .filter(|target| match record_type {
+                RecordType::A => target.address.is_ipv4(),
+                RecordType::AAAA => target.address.is_ipv6(),
+                _ => false,
+            })
+            .collect();
+        if targets.is_empty() {
+            return None;
+        }
+
+        let healthy: Vec<IpAddr> = targets.iter().filter(|target| target.state.lock().unwrap().healthy).map(|target| target.address).collect();
+        let addresses = match healthy.is_empty() && set.config.fail_open {
+            true => targets.iter().map(|target| target.address).collect(),
+            false => healthy,
+        };
+        let records = addresses
+            .into_iter()
+            .map(|address| {
+                let rdata = match address {
+                    IpAddr::V4(address) => RData::A(address),
+                    IpAddr::V6(address) => RData::AAAA(address),
+                };
+                Record::from_rdata(set.owner.clone(), set.config.ttl, rdata)
+            })
+            .collect();
+        Some(records)
+    }
+
+    // Returns the targets of a name with whether each is up, or None if failover doesn't answer the name
+    pub fn status(&self, name: &LowerName) -> Option<Vec<(IpAddr, bool)>> {
+        let set = self.names.get(name)?;
+        Some(set.targets.iter().map(|target| (target.address, target.state.lock().unwrap().healthy)).collect())
+    }
+
+    // Checks every target of every name once
+    pub async fn check_once(&self) {
+        for set in self.names.values() {
+            set.check().await;
+        }
+    }
+}
+
+/*
+Description:
+spawns the health checks: a background task per name that checks its targets every interval, starting at once. Must be called from within the tokio runtime.
+
+Parameters:
+failover: the names and their targets.
+
+Returns:
+None
+*/
+pub fn spawn(failover: &Failover) {
+    for set in failover.names.values() {
+        let set = set.clone();
+        tasks::spawn("failover-check", async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(set.config.interval.max(1)));
+            loop {
+                ticker.tick().await;
+                set.check().await;
+            }
+        });
+    }
+}
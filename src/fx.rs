@@ -0,0 +1,444 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+// How long a failed fetch is remembered, so queries for a currency the API doesn't know or can't serve don't each hit it again
+const NEGATIVE_TTL: Duration = Duration::from_secs(30);
+
+/*
+Description:
+defines the errors that can occur while converting between currencies. It covers unknown currency codes, failures talking to the exchange-rate API, API responses that could not be understood, a recent failure that is not retried yet, and I/O errors on the on-disk cache.
+*/
+#[derive(thiserror::Error, Debug)]
+pub enum FxError {
+    #[error("Unknown currency {0:}")]
+    UnknownCurrency(String),
+    #[error("HTTP error: {0:}")]
+    Http(#[from] reqwest::Error),
+    #[error("Malformed exchange rate response: {0:}")]
+    MalformedResponse(String),
+    #[error("Exchange rates unavailable: {0:}")]
+    Unavailable(String),
+    #[error("I/O error: {0:}")]
+    Io(#[from] std::io::Error),
+}
+
+/*
+Description:
+a table of exchange rates quoted against a single base currency. The same struct is kept in memory and written to the on-disk cache as JSON, so a restart does not have to hit the API again while the rates are still fresh.
+*/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RateTable {
+    // The currency the rates are quoted against, e.g. "USD"
+    pub base: String,
+
+    // Unix timestamp of the moment the rates were fetched from the API
+    pub fetched_at: i64,
+
+    // How many units of each currency one unit of the base currency buys
+    pub rates: HashMap<String, f64>,
+}
+
+// The subset of the exchange-rate API response that we care about.
+// open.er-api.com and exchangerate-api.com compatible APIs both return a "rates" object.
+#[derive(Deserialize)]
+struct ApiResponse {
+    rates: HashMap<String, f64>,
+}
+
+/*
+Description:
+the result of a single conversion, including how old the rates that produced it are so the answer can tell the client how fresh it is.
+*/
+#[derive(Clone, Debug)]
+pub struct Conversion {
+    // The converted amount in the target currency
+    pub converted: f64,
+
+    // The exchange rate that was applied
+    pub rate: f64,
+
+    // Age of the rate table in seconds
+    pub age: i64,
+
+    // Whether the rates are older than the configured maximum age (the API could not be reached to refresh them)
+    pub stale: bool,
+}
+
+// A fetch that failed recently, remembered so it isn't retried until NEGATIVE_TTL has passed
+#[derive(Debug)]
+struct Failure {
+    // When the fetch failed
+    at: Instant,
+
+    // Whether the API said it doesn't know the currency, rather than failing to answer
+    unknown: bool,
+
+    // The error the fetch failed with
+    message: String,
+}
+
+/*
+Description:
+an exchange-rate client with a two level cache. Rate tables are kept in memory and mirrored to one JSON file per base currency in the cache directory. Tables older than max_age are refreshed from the API, and if the API is unreachable the stale table is used instead of failing the query. Only one query per base currency fetches at a time, the others wait for its result, and a failed fetch is not retried for NEGATIVE_TTL.
+*/
+#[derive(Debug)]
+pub struct FxRates {
+    // HTTP client used to talk to the exchange-rate API
+    client: reqwest::Client,
+
+    // URL of the API, with "{base}" standing in for the base currency code
+    api_url: String,
+
+    // Directory holding the cached rate tables
+    cache_dir: PathBuf,
+
+    // How long a rate table is considered fresh
+    max_age: Duration,
+
+    // In-memory copy of the rate tables, keyed by base currency
+    tables: Mutex<HashMap<String, RateTable>>,
+
+    // Recently failed fetches, keyed by base currency
+    failed: Mutex<HashMap<String, Failure>>,
+
+    // One lock per base currency, held while its rates are refreshed; base currencies are three letters, so there are at most 26^3
+    refreshing: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+
+    // The number of failed requests to the API
+    failures: AtomicU64,
+}
+
+impl FxRates {
+    /*
+    Description:
+    creates a new exchange-rate client.
+
+    Parameters:
+    api_url: the API URL, where "{base}" is replaced by the base currency code.
+    cache_dir: the directory the rate tables are cached in.
+    max_age: how long fetched rates are considered fresh.
+
+    Returns:
+    A new FxRates instance with an empty in-memory cache.
+    */
+    pub fn new(api_url: &str, cache_dir: impl Into<PathBuf>, max_age: Duration) -> Self {
+        // Never let a slow API hold a DNS query for longer than a few seconds
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+
+        FxRates {
+            client,
+            api_url: api_url.to_string(),
+            cache_dir: cache_dir.into(),
+            max_age,
+            tables: Mutex::new(HashMap::new()),
+            failed: Mutex::new(HashMap::new()),
+            refreshing: Mutex::new(HashMap::new()),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    // Returns the number of rate tables held in the in-memory cache
+    pub fn cached_tables(&self) -> usize {
+        self.tables.lock().unwrap().len()
+    }
+
+    // Returns the number of failed requests to the API
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /*
+    Description:
+    converts an amount from one currency to another.
+
+    Parameters:
+    amount: the amount to convert.
+    from: the three letter code of the source currency.
+    to: the three letter code of the target currency.
+
+    Returns:
+    Ok(Conversion) with the converted amount and the freshness of the rates, or an FxError if either currency is unknown or no rates could be obtained.
+    */
+    pub async fn convert(&self, amount: f64, from: &str, to: &str) -> Result<Conversion, FxError> {
+        // Currency codes are always three ASCII letters; reject anything else before it reaches the API URL
+        let from = normalize_code(from)?;
+        let to = normalize_code(to)?;
+
+        // Look up (or fetch) the rate table for the source currency
+        let table = self.rates(&from).await?;
+
+        // Find the rate for the target currency
+        let rate = *table
+            .rates
+            .get(&to)
+            .ok_or_else(|| FxError::UnknownCurrency(to.clone()))?;
+
+        // Work out how old the rates are
+        let age = (chrono::Utc::now().timestamp() - table.fetched_at).max(0);
+
+        Ok(Conversion {
+            converted: amount * rate,
+            rate,
+            age,
+            stale: age > self.max_age.as_secs() as i64,
+        })
+    }
+
+    /*
+    Description:
+    returns the rate table for a base currency, consulting the in-memory cache, then the on-disk cache, and finally the API. Queries for the same base currency wait for each other's fetch rather than all asking the API at once, and a fetch that failed within NEGATIVE_TTL is not tried again.
+
+    Parameters:
+    base: the normalized base currency code.
+
+    Returns:
+    Ok(RateTable) if fresh or stale rates are available, or an FxError if none could be obtained.
+    */
+    async fn rates(&self, base: &str) -> Result<RateTable, FxError> {
+        // Try the in-memory cache first
+        let cached = self.tables.lock().unwrap().get(base).cloned();
+
+        // Fall back to the on-disk cache
+        let cached = match cached {
+            Some(table) => Some(table),
+            None => self.load(base).await,
+        };
+
+        // Use the cached table as long as it is fresh
+        if let Some(table) = cached.as_ref().filter(|table| self.is_fresh(table)) {
+            return Ok(table.clone());
+        }
+
+        // Let one query at a time refresh this base currency; the others wait here for its result
+        let lock = self.refreshing.lock().unwrap().entry(base.to_string()).or_default().clone();
+        let _refreshing = lock.lock().await;
+
+        // The query that held the lock may have refreshed the rates in the meantime
+        let cached = self.tables.lock().unwrap().get(base).cloned().or(cached);
+        if let Some(table) = cached.as_ref().filter(|table| self.is_fresh(table)) {
+            return Ok(table.clone());
+        }
+
+        // Refresh the rates from the API, unless that failed a moment ago, falling back to stale rates if there are none
+        let result = match self.recent_failure(base) {
+            Some(error) => Err(error),
+            None => {
+                let result = self.fetch(base).await;
+                match &result {
+                    Ok(_) => {
+                        self.failed.lock().unwrap().remove(base);
+                    }
+                    Err(error) => {
+                        self.remember_failure(base, error);
+
+                        // Count failures to reach the API, but not queries for currencies it doesn't know
+                        if !matches!(error, FxError::UnknownCurrency(_)) {
+                            self.failures.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+                result
+            }
+        };
+
+        match result {
+            Ok(table) => Ok(table),
+            Err(error) => match cached {
+                Some(table) => {
+                    warn!("Failed to refresh {base} exchange rates, using stale rates: {error}");
+                    Ok(table)
+                }
+                None => Err(error),
+            },
+        }
+    }
+
+    /*
+    Description:
+    fetches a fresh rate table from the API and stores it in both caches.
+
+    Parameters:
+    base: the normalized base currency code.
+
+    Returns:
+    Ok(RateTable) with the fetched rates, or an FxError if the request failed.
+    */
+    async fn fetch(&self, base: &str) -> Result<RateTable, FxError> {
+        // Build the request URL for this base currency
+        let url = self.api_url.replace("{base}", base);
+
+        // Fetch and decode the rates
+        let response = self.client.get(&url).send().await?.error_for_status()?;
+        let body: ApiResponse = response
+            .json()
+            .await
+            .map_err(|error| FxError::MalformedResponse(error.to_string()))?;
+
+        // An unknown base currency shows up as an empty (or missing) rate table
+        if body.rates.is_empty() {
+            return Err(FxError::UnknownCurrency(base.to_string()));
+        }
+
+        let table = RateTable {
+            base: base.to_string(),
+            fetched_at: chrono::Utc::now().timestamp(),
+            rates: body.rates,
+        };
+
+        // Remember the table in memory
+        self.tables
+            .lock()
+            .unwrap()
+            .insert(base.to_string(), table.clone());
+
+        // Persist the table to disk; a failure here only costs us an extra API call after a restart
+        if let Err(error) = self.store(&table).await {
+            warn!("Failed to cache {base} exchange rates: {error}");
+        }
+
+        Ok(table)
+    }
+
+    /*
+    Description:
+    loads a rate table from the on-disk cache.
+
+    Parameters:
+    base: the normalized base currency code.
+
+    Returns:
+    Some(RateTable) if a readable cache file exists, None otherwise.
+    */
+    async fn load(&self, base: &str) -> Option<RateTable> {
+        // Read and decode the cache file, ignoring missing or corrupt files
+        let data = tokio::fs::read(self.cache_path(base)).await.ok()?;
+        let table: RateTable = serde_json::from_slice(&data).ok()?;
+
+        // Keep a copy in memory so the disk is only read once
+        self.tables
+            .lock()
+            .unwrap()
+            .insert(base.to_string(), table.clone());
+
+        Some(table)
+    }
+
+    /*
+    Description:
+    writes a rate table to the on-disk cache. The table is written to a temporary file first and then renamed into place, so a crash never leaves a half-written cache file behind.
+
+    Parameters:
+    table: the rate table to store.
+
+    Returns:
+    Ok(()) if the table was written, or an FxError if writing failed.
+    */
+    async fn store(&self, table: &RateTable) -> Result<(), FxError> {
+        // Make sure the cache directory exists
+        tokio::fs::create_dir_all(&self.cache_dir).await?;
+
+        // Serialize the table
+        let data = serde_json::to_vec(table)
+            .map_err(|error| FxError::MalformedResponse(error.to_string()))?;
+
+        // Write to a temporary file and atomically move it into place
+        let path = self.cache_path(&table.base);
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, data).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+
+        Ok(())
+    }
+
+    // Returns whether a rate table is younger than the configured maximum age
+    fn is_fresh(&self, table: &RateTable) -> bool {
+        chrono::Utc::now().timestamp() - table.fetched_at <= self.max_age.as_secs() as i64
+    }
+
+    // Returns the error of a fetch for a base currency that failed less than NEGATIVE_TTL ago, if any
+    fn recent_failure(&self, base: &str) -> Option<FxError> {
+        let mut failed = self.failed.lock().unwrap();
+        match failed.get(base) {
+            Some(failure) if failure.at.elapsed() < NEGATIVE_TTL => Some(match failure.unknown {
+                true => FxError::UnknownCurrency(base.to_string()),
+                false => FxError::Unavailable(failure.message.clone()),
+            }),
+            Some(_) => {
+                failed.remove(base);
+                None
+            }
+            None => None,
+        }
+    }
+
+    // Remembers that a fetch for a base currency failed, so it isn't retried for NEGATIVE_TTL
+    fn remember_failure(&self, base: &str, error: &FxError) {
+        let failure = Failure {
+            at: Instant::now(),
+            unknown: matches!(error, FxError::UnknownCurrency(_)),
+            message: error.to_string(),
+        };
+        self.failed.lock().unwrap().insert(base.to_string(), failure);
+    }
+
+    // Returns the path of the cache file for a base currency
+    fn cache_path(&self, base: &str) -> PathBuf {
+        self.cache_dir.join(format!("{base}.json"))
+    }
+}
+
+/*
+Description:
+validates a currency code and converts it to upper case.
+
+Parameters:
+code: the currency code as it appeared in the query.
+
+Returns:
+Ok(String) with the upper-cased code, or FxError::UnknownCurrency if it is not three ASCII letters.
+*/
+pub fn normalize_code(code: &str) -> Result<String, FxError> {
+    if code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code.to_ascii_uppercase())
+    } else {
+        Err(FxError::UnknownCurrency(code.to_string()))
+    }
+}
+
+/*
+Description:
+formats the age of a rate table as a short freshness indicator for the TXT answer, e.g. "updated 5m ago" or "stale, updated 3h ago".
+
+Parameters:
+conversion: the conversion whose rates should be described.
+
+Returns:
+A String describing how fresh the rates are.
+*/
+pub fn freshness(conversion: &Conversion) -> String {
+    // Pick the largest sensible unit for the age
+    let age = match conversion.age {
+        age if age < 60 => format!("{age}s"),
+        age if age < 3600 => format!("{}m", age / 60),
+        age if age < 86400 => format!("{}h", age / 3600),
+        age => format!("{}d", age / 86400),
+    };
+
+    if conversion.stale {
+        format!("stale, updated {age} ago")
+    } else {
+        format!("updated {age} ago")
+    }
+}
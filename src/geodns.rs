@@ -0,0 +1,169 @@
+use crate::{config::GeoConfig, geoip::Location, records};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+use trust_dns_server::{
+    client::rr::{LowerName, Record, RecordType},
+    proto::rr::rdata::opt::{EdnsCode, EdnsOption},
+    server::Request,
+};
+
+// The continent codes of the GeoIP databases.
+pub const CONTINENTS: [&str; 7] = ["AF", "AN", "AS", "EU", "NA", "OC", "SA"];
+
+// The answer sets of one name and type
+#[derive(Debug, Default)]
+struct AnswerSets {
+    // The records of each country, by upper-case ISO code
+    countries: HashMap<String, Vec<Record>>,
+
+    // The records of each continent, by upper-case code
+    continents: HashMap<String, Vec<Record>>,
+
+    // The records of every other client
+    fallback: Vec<Record>,
+}
+
+/*
+Description:
+location-aware answers: names whose records depend on where the client is, from the [[geo]] sections of the configuration file. Each name and type has answer sets tagged by country or continent and a fallback set, and a query gets the set of its client's country, else that of its continent, else the fallback.
+*/
+#[derive(Debug, Default)]
+pub struct GeoDns {
+    // The answer sets of each name, by type
+    names: HashMap<LowerName, HashMap<RecordType, AnswerSets>>,
+}
+
+impl GeoDns {
+    /*
+    Description:
+    builds the answer sets of [[geo]] sections, checking that their records are valid, their tags are country or continent codes and that each name and type is given once.
+
+    Parameters:
+    configs: the [[geo]] sections.
+
+    Returns:
+    Ok(GeoDns), or an error message saying which section is wrong and why.
+    */
+    pub fn new(configs: &[GeoConfig]) -> Result<Self, String> {
+        let mut geo = GeoDns::default();
+        for config in configs {
+            let context = |error: String| format!("[[geo]] {} {}: {error}", config.name, config.record_type);
+            let records = |set: &[String]| -> Result<Vec<Record>, String> {
+                set.iter()
+                    .map(|data| records::record(&config.name, &config.record_type, config.ttl, data))
+                    .collect::<Result<_, _>>()
+                    .map_err(context)
+            };
+
+            if config.fallback.is_empty() {
+                return Err(context("the fallback set is empty".to_string()));
+            }
+            let mut sets = AnswerSets {
+                fallback: records(&config.fallback)?,
+                ..AnswerSets::default()
+            };
+            for (country, set) in &config.countries {
+                if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+                    return Err(context(format!("{country} is not an ISO country code")));
+                }
+                sets.countries.insert(country.to_ascii_uppercase(), records(set)?);
+            }
+            for (continent, set) in &config.continents {
+                let code = continent.to_ascii_uppercase();
+                if !CONTINENTS.contains(&code.as_str()) {
+                    return Err(context(format!("{continent} is not a continent code (one of {})", CONTINENTS.join(", "))));
+                }
+                sets.continents.insert(code, records(set)?);
+            }
+
+            let owner = sets.fallback[0].name().clone();
+            let record_type = sets.fallback[0].record_type();
+            if geo.names.entry(LowerName::new(&owner)).or_default().insert(record_type, sets).is_some() {
+                return Err(context("the name and type are given more than once".to_string()));
+            }
+        }
+        Ok(geo)
+    }
+
+    // Returns whether any answer sets are given for a name
+    pub fn serves(&self, name: &LowerName) -> bool {
+        self.names.contains_key(name)
+    }
+
+    /*
+    Description:
+    picks the answer of a name and type for a client's location.
+
+    Parameters:
+    name: the name.
+    record_type: the type.
+    location: where the client is.
+
+    Returns:
+    Some(Vec<Record>) with the set of the client's country, else its continent's, else the fallback set; or None if no answer sets are given for the name and type.
+    */
+    pub fn answer(&self, name: &LowerName, record_type: RecordType, location: &Location) -> Option<Vec<Record>> {
+        let sets = self.names.get(name)?.get(&record_type)?;
+        let country = location.country.as_ref().and_then(|country| sets.countries.get(&country.to_ascii_uppercase()));
+        let continent = location.continent.as_ref().and_then(|continent| sets.continents.get(&continent.to_ascii_uppercase()));
+        Some(country.or(continent).unwrap_or(&sets.fallback).clone())
+    }
+}
+
+/*
+Description:
+reads the EDNS Client Subnet option of a query (RFC 7871), with which a resolver tells the server which network the query is made for.
+
+Parameters:
+request: the query.
+
+Returns:
+Some((IpAddr, u8)) with the subnet's address, zero beyond its prefix, and its prefix length, or None if the query has no valid option.
+*/
+pub fn client_subnet(request: &Request) -> Option<(IpAddr, u8)> {
+    let option = request.edns()?.options().get(EdnsCode::Subnet)?;
+    let data = Vec::<u8>::from(option);
+    let family = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+    let prefix = *data.get(2)?;
+    let address = data.get(4..)?;
+    let length = match family {
+        1 if prefix <= 32 => 4,
+        2 if prefix <= 128 => 16,
+        _ => return None,
+    };
+    if address.len() != usize::from(prefix).div_ceil(8) {
+        return None;
+    }
+
+    let mut bytes = [0; 16];
+    bytes[..address.len()].copy_from_slice(address);
+    let address = match length {
+        4 => IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])),
+        _ => IpAddr::V6(Ipv6Addr::from(bytes)),
+    };
+    Some((address, prefix))
+}
+
+/*
+Description:
+builds the EDNS Client Subnet option of an answer made for a subnet, whose scope is the whole subnet, so resolvers only reuse the answer for clients in it.
+
+Parameters:
+address: the subnet's address, as client_subnet returned it.
+prefix: the subnet's prefix length.
+
+Returns:
+The EdnsOption.
+*/
+pub fn subnet_option(address: IpAddr, prefix: u8) -> EdnsOption {
+    let (family, bytes): (u16, Vec<u8>) = match address {
+        IpAddr::V4(address) => (1, address.octets().to_vec()),
+        IpAddr::V6(address) => (2, address.octets().to_vec()),
+    };
+    let mut data = family.to_be_bytes().to_vec();
+    data.extend([prefix, prefix]);
+    data.extend(&bytes[..usize::from(prefix).div_ceil(8).min(bytes.len())]);
+    EdnsOption::from((EdnsCode::Subnet, data.as_slice()))
+}
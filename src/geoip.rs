@@ -0,0 +1,84 @@
+use maxminddb::{geoip2, Reader};
+use std::{fmt, net::IpAddr, path::Path};
+
+/*
+Description:
+where an address is, as far as the database knows.
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    // The upper-case ISO 3166-1 country code
+    pub country: Option<String>,
+
+    // The upper-case continent code: AF, AN, AS, EU, NA, OC or SA
+    pub continent: Option<String>,
+}
+
+/*
+Description:
+country lookups against a MaxMind DB (GeoLite2/GeoIP2 Country or City) database. The whole database is read into memory at startup, so lookups never touch the disk.
+*/
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeoIp")
+            .field("database_type", &self.reader.metadata.database_type)
+            .finish()
+    }
+}
+
+impl GeoIp {
+    /*
+    Description:
+    opens a GeoIP database.
+
+    Parameters:
+    path: the MaxMind DB file.
+
+    Returns:
+    Ok(GeoIp) if the database could be read, or a MaxMindDBError otherwise.
+    */
+    pub fn open(path: &Path) -> Result<Self, maxminddb::MaxMindDBError> {
+        Ok(GeoIp {
+            reader: Reader::open_readfile(path)?,
+        })
+    }
+
+    /*
+    Description:
+    looks up the country of an address.
+
+    Parameters:
+    ip: the address to look up.
+
+    Returns:
+    Some(String) with the upper-case ISO 3166-1 country code, or None if the address is not in the database.
+    */
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let record: geoip2::Country = self.reader.lookup(ip).ok()?;
+        record.country?.iso_code.map(str::to_string)
+    }
+
+    /*
+    Description:
+    looks up the country and continent of an address.
+
+    Parameters:
+    ip: the address to look up.
+
+    Returns:
+    The Location, with neither known if the address is not in the database.
+    */
+    pub fn locate(&self, ip: IpAddr) -> Location {
+        let Ok(record) = self.reader.lookup::<geoip2::Country>(ip) else {
+            return Location::default();
+        };
+        Location {
+            country: record.country.and_then(|country| country.iso_code).map(str::to_string),
+            continent: record.continent.and_then(|continent| continent.code).map(str::to_string),
+        }
+    }
+}
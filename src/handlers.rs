@@ -1,34 +1,124 @@
-use crate::Options;
+use crate::{
+    acl::Acl,
+    acme::{self, Acme},
+    anonymize::Anonymizer,
+    astronomy,
+    audit::{AuditEntry, AuditLog},
+    blocklist::Blocklist,
+    chaos::{Chaos, Fault},
+    cache::{CachedResponse, CaptureResponder, NameCache, ParsedName, ResponseCache},
+    calendar::{self, EpochFormat},
+    checksum,
+    clock::{Clock, FixedClock, SystemClock},
+    clients::ClientStats,
+    config::{BlockResponse, Config, Priority, WebhookEvent, ZoneConfig},
+    counter::{self, Counter, NamedCounters},
+    ddns::{self, Ddns},
+    delegation::{DelegatedAnswer, Delegation, DelegationError},
+    docker::{self, Docker},
+    dnstap::{Dnstap, DnstapResponder, Sink},
+    e164::EnumZone,
+    failover::Failover,
+    fx::{self, FxRates},
+    geodns::{self, GeoDns},
+    health::Health,
+    hostsfile::{self, HostsFiles},
+    lan::{self, Lan},
+    limits::{QueryLimits, QueryViolation, RateLimit},
+    metrics::{Metrics, RecentQuery, TimedResponder},
+    names::{self, MAX_NAMES},
+    netcalc,
+    options::Options,
+    overload::Overload,
+    padding::{Padding, PaddingResponder},
+    random::{self, Random},
+    records::{self, Lookup, RecordStore},
+    registry::{self, Backend, Registry},
+    rotation::Rotation,
+    rps::{self, Move, Outcome, RpsStats},
+    sequences::{self, FACT_MAX_N, FIB_MAX_N},
+    templates::{RawQuery, Templates},
+    tenants::Tenants,
+    topk::{TopEntry, TopTalkers},
+    trace::{self, Tracer},
+    tunneling::{TunnelDetector, Verdict},
+    webhooks::{Event, Webhooks},
+    wireguard::{self, WireGuard},
+};
+#[cfg(feature = "ldap")]
+use crate::ldap::{Directory, LdapHosts};
+#[cfg(feature = "sqlite")]
+use crate::querylog::{QueryLog, QueryLogEntry};
 use std::{
-    net::{Ipv4Addr, Ipv6Addr, IpAddr},
+    net::{Ipv4Addr, Ipv6Addr, IpAddr, SocketAddr},
     str::FromStr,
-    sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
-    },
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
 use tracing::*;
 use trust_dns_server::{
     authority::MessageResponseBuilder,
-    client::rr::{rdata::TXT, LowerName, Name, RData, Record},
-    proto::op::{Header, MessageType, OpCode, ResponseCode},
-    server::{Request, RequestHandler, ResponseHandler, ResponseInfo},
+    client::rr::{rdata::{SRV, TXT}, LowerName, Name, RData, Record, RecordType},
+    proto::{
+        op::{Edns, Header, MessageType, OpCode, ResponseCode},
+        rr::rdata::opt::EdnsOption,
+    },
+    server::{Protocol, Request, RequestHandler, ResponseHandler, ResponseInfo},
 };
 use rand::Rng;
-use chrono::NaiveDateTime;
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+// The largest number of lottery numbers that can be drawn in one query.
+const LOTTO_MAX_COUNT: usize = 50;
+
+// The largest number a lottery draw can go up to.
+const LOTTO_MAX_NUMBER: usize = 1000;
+
+// The number of clients listed by the top zone unless a count is given.
+const TOP_DEFAULT_CLIENTS: usize = 10;
+
+// The largest number of clients the top zone lists in one query.
+const TOP_MAX_CLIENTS: usize = 20;
+
+// The number of clients and names listed by the stats zone unless a count is given.
+const STATS_DEFAULT_ENTRIES: usize = 5;
+
+// Whether the server offers recursion, which the RA flag of every answer tells clients. It answers only the zones it serves and neither resolves nor forwards other names.
+pub const RECURSION_AVAILABLE: bool = false;
+
+// The EDNS option code of Extended DNS Errors (RFC 8914).
+const EXTENDED_DNS_ERROR: u16 = 15;
+
+// The owner name and address of the record chaos mode answers malformed answers with, which match no question (RFC 6761 and RFC 5737).
+const CHAOS_NAME: &str = "chaos.invalid.";
+const CHAOS_ADDRESS: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
 
 /*
 Represents the DNS server's handler.
-has a total of eight fields, including seven zone-specific fields and a shared counter.
-The counter field is of type Arc<AtomicU64> and is used to track the number of requests received by the server.
-The root_zone, counter_zone, myip_zone, coin_zone, dice_zone, cidr_zone, and time_zone fields are all of type LowerName and represent different zones of the DNS server.
+has one field per zone plus the state shared between zones, such as the request counter and the exchange-rate client.
+The counter field is of type Counter and is used to track the number of requests received by the server.
+The *_zone fields are all of type LowerName and represent different zones of the DNS server.
 Each field is marked as public (pub) so that it can be accessed from outside the module.
 */
 
 #[derive(Clone, Debug)]
 pub struct Handler{
   // A shared counter to track the number of requests received
-  pub counter: Arc<AtomicU64>,
+  pub counter: Counter,
+
+  // The named counters of the counter zone, such as hits.counter.<domain>
+  pub named_counters: Arc<NamedCounters>,
+
+  // The limits every query is checked against before it reaches a zone
+  pub limits: QueryLimits,
+
+  // The most queries per second the domain answers, if limited
+  pub rate_limit: Option<Arc<RateLimit>>,
+
+  // The source-address access control lists for queries, zone transfers and dynamic updates
+  pub query_acl: Acl,
+  pub transfer_acl: Acl,
+  pub update_acl: Acl,
   
   // The root zone of the DNS server
   pub root_zone: LowerName,
@@ -50,6 +140,212 @@ pub struct Handler{
   
   // The time zone of the DNS server
   pub time_zone: LowerName,
+
+  // The dyn zone of the DNS server, serving the hosts kept up to date through the DynDNS update API
+  pub ddns_zone: LowerName,
+
+  // The docker zone of the DNS server, serving the addresses of the local Docker engine's containers
+  pub docker_zone: LowerName,
+
+  // The registry zone of the DNS server, serving the records of Consul or etcd under a configurable label
+  pub registry_zone: LowerName,
+
+  // The hosts zone of the DNS server, serving the entries of hosts-format files
+  pub hosts_zone: LowerName,
+
+  // The lan zone of the DNS server, serving the devices discovered on the local network
+  pub lan_zone: LowerName,
+
+  // The wg zone of the DNS server, serving the peers of a WireGuard interface
+  pub wireguard_zone: LowerName,
+
+  // The fx (currency conversion) zone of the DNS server
+  pub fx_zone: LowerName,
+
+  // The exchange-rate client used by the fx zone
+  pub fx: Arc<FxRates>,
+
+  // The lotto zone of the DNS server
+  pub lotto_zone: LowerName,
+
+  // The rps (rock-paper-scissors) zone of the DNS server
+  pub rps_zone: LowerName,
+
+  // The per-client win/loss tallies of the rps zone
+  pub rps_stats: Arc<RpsStats>,
+
+  // The moon (lunar phase) zone of the DNS server
+  pub moon_zone: LowerName,
+
+  // The sun (sunrise/sunset) zone of the DNS server
+  pub sun_zone: LowerName,
+
+  // The week (ISO week / day-of-year) zone of the DNS server
+  pub week_zone: LowerName,
+
+  // The str (string transform) zone of the DNS server
+  pub str_zone: LowerName,
+
+  // The puny (Unicode to punycode) zone of the DNS server
+  pub puny_zone: LowerName,
+
+  // The unpuny (punycode to Unicode) zone of the DNS server
+  pub unpuny_zone: LowerName,
+
+  // The luhn (Luhn check) zone of the DNS server
+  pub luhn_zone: LowerName,
+
+  // The crc32 zone of the DNS server
+  pub crc32_zone: LowerName,
+
+  // The fib (Fibonacci) zone of the DNS server
+  pub fib_zone: LowerName,
+
+  // The fact (factorial) zone of the DNS server
+  pub fact_zone: LowerName,
+
+  // The age (age calculator) zone of the DNS server
+  pub age_zone: LowerName,
+
+  // The ulid (ULID generator/decoder) zone of the DNS server
+  pub ulid_zone: LowerName,
+
+  // The name (random name generator) zone of the DNS server
+  pub name_zone: LowerName,
+
+  // The dnstap logger, if dnstap logging is enabled
+  pub dnstap: Option<Arc<Dnstap>>,
+
+  // The per-client query statistics, shared with the admin API
+  pub clients: Arc<ClientStats>,
+
+  // The top (busiest clients) zone of the DNS server
+  pub top_zone: LowerName,
+
+  // The SQLite query log, if query logging is enabled
+  #[cfg(feature = "sqlite")]
+  pub query_log: Option<Arc<QueryLog>>,
+
+  // The settings from the configuration file, such as per-zone policies
+  pub config: Arc<Config>,
+
+  // The ad/tracker blocklist, if blocking is configured
+  pub blocklist: Option<Arc<Blocklist>>,
+
+  // Scores clients for signs of DNS tunneling, if the detector is configured
+  pub tunneling: Option<Arc<TunnelDetector>>,
+
+  // Pads the answers to queries that came encrypted, if padding is configured
+  pub padding: Option<Arc<Padding>>,
+
+  // The health (liveness check) zone of the DNS server
+  pub health_zone: LowerName,
+
+  // The liveness and readiness state, shared with the admin API
+  pub health: Arc<Health>,
+
+  // The metrics registry behind the dashboard
+  pub metrics: Arc<Metrics>,
+
+  // The stats (top talkers and names) zone of the DNS server
+  pub stats_zone: LowerName,
+
+  // The acme zone of the DNS server, serving ACME DNS-01 challenge tokens
+  pub acme_zone: LowerName,
+
+  // The ACME challenge responder, if its API is enabled
+  pub acme: Option<Arc<Acme>>,
+
+  // The trace zone of the DNS server, tracing the lookup of names from the root servers down
+  pub trace_zone: LowerName,
+
+  // The tracer behind the trace zone, if it is enabled
+  pub tracer: Option<Arc<Tracer>>,
+
+  // The dynamic zone's hosts, if the DynDNS update API is enabled
+  pub ddns: Option<Arc<Ddns>>,
+
+  // The running containers' names and addresses, if a Docker socket is watched
+  pub docker: Option<Arc<Docker>>,
+
+  // The records read from Consul or etcd, if a backend is configured
+  pub registry: Option<Arc<Registry>>,
+
+  // The entries of the hosts files, if any are given
+  pub hosts: Option<Arc<HostsFiles>>,
+
+  // The devices discovered on the local network, if LAN discovery is enabled
+  pub lan: Option<Arc<Lan>>,
+
+  // The peers of a WireGuard interface, if a configuration file or interface is given
+  pub wireguard: Option<Arc<WireGuard>>,
+
+  // The records served as they are from backends such as PostgreSQL, Redis or zone files, if any is configured
+  pub records: Option<Arc<RecordStore>>,
+
+  // The names answered by the client's location, if the config file has [[geo]] sections
+  pub geo: Option<Arc<GeoDns>>,
+
+  // The names answered with their healthy targets, if the config file has [[failover]] sections
+  pub failover: Option<Arc<Failover>>,
+
+  // The subzones answered by HTTP backends, if the config file has [[delegation]] sections
+  pub delegation: Option<Arc<Delegation>>,
+
+  // Orders the addresses of names with several in each answer, if the config file has a [rotation] section
+  pub rotation: Option<Arc<Rotation>>,
+
+  // The telephone numbers of the ENUM zone, if the config file has an [enum] section
+  pub e164: Option<Arc<EnumZone>>,
+
+  // The SOA record of the domain, sent in the authority section of negative answers
+  pub soa: Option<Record>,
+
+  // The hosts of an LDAP directory, if one is configured
+  #[cfg(feature = "ldap")]
+  pub ldap: Option<Arc<LdapHosts>>,
+
+  // The webhooks notified of query events, if the config file has any
+  pub webhooks: Option<Arc<Webhooks>>,
+
+  // The busiest clients and most queried names over a sliding window
+  pub talkers: Arc<TopTalkers>,
+
+  // The audit log of administrative actions, if one is configured
+  pub audit: Option<Arc<AuditLog>>,
+
+  // Strips personal data from logged queries, if anonymization is enabled
+  pub anonymizer: Option<Arc<Anonymizer>>,
+
+  // Decides which queries to shed when the server is overloaded
+  pub overload: Arc<Overload>,
+
+  // Whether shed UDP queries are dropped instead of refused
+  pub shed_drop_udp: bool,
+
+  // Log one in every log_sample successful queries at debug level, unless the zone says otherwise
+  pub log_sample: u64,
+
+  // The pre-serialized answers for queries whose answer is fixed, used by the batched UDP transport
+  pub templates: Option<Arc<Templates>>,
+
+  // The cache of answers from zones whose answer depends only on the question, if enabled
+  pub response_cache: Option<Arc<ResponseCache>>,
+
+  // The cache of query names matched with their zones and taken apart, if enabled
+  pub name_cache: Option<Arc<NameCache>>,
+
+  // Where the random zones draw from, seeded if deterministic answers were asked for
+  pub random: Random,
+
+  // Where the date and time zones get the current time, stopped if a fixed time was given
+  pub clock: Arc<dyn Clock>,
+
+  // Injects faults into a share of the answers, if chaos mode is on
+  pub chaos: Option<Arc<Chaos>>,
+
+  // The domains hosted besides this one, each answered by a handler of its own, if the config file has [[tenants]] sections
+  pub tenants: Option<Arc<Tenants>>,
 }
 
 // Description:
@@ -80,6 +376,123 @@ pub enum Error {
     Io(#[from] std::io::Error),
 }
 
+/*
+Description:
+the checks a query goes through before it reaches its zone, run in the order of CHECKS by do_handle_request to answer it and by explain to describe how it would be answered.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Check {
+    // The access control list for the query's op code and type
+    Acl,
+    // The query limits on the name
+    Limits,
+    // The domain's rate limit
+    RateLimit,
+    // The DNS tunneling detector
+    Tunneling,
+    // The zone's country policy
+    Policy,
+    // Load shedding by the zone's priority
+    Overload,
+    // The op code and message type, which have to be those of a query
+    Message,
+    // Chaos mode's faults
+    Chaos,
+    // The blocklist
+    Blocklist,
+    // The response cache
+    Cache,
+}
+
+// The checks in the order they are made
+const CHECKS: [Check; 10] = [
+    Check::Acl,
+    Check::Limits,
+    Check::RateLimit,
+    Check::Tunneling,
+    Check::Policy,
+    Check::Overload,
+    Check::Message,
+    Check::Chaos,
+    Check::Blocklist,
+    Check::Cache,
+];
+
+impl Check {
+    // Returns the name explain shows the check under
+    fn name(self) -> &'static str {
+        match self {
+            Check::Acl => "acl",
+            Check::Limits => "limits",
+            Check::RateLimit => "ratelimit",
+            Check::Tunneling => "tunneling",
+            Check::Policy => "policy",
+            Check::Overload => "overload",
+            Check::Message => "message",
+            Check::Chaos => "chaos",
+            Check::Blocklist => "blocklist",
+            Check::Cache => "cache",
+        }
+    }
+}
+
+/*
+Description:
+the verdict of a check on a query: Pass and Delay let it go on to the next check, the others decide how it is answered.
+*/
+enum CheckVerdict<'a> {
+    // Go on to the next check
+    Pass,
+    // Go on to the next check after a delay injected by chaos mode
+    Delay(Duration),
+    // Answer REFUSED
+    Refuse,
+    // Send nothing, for queries shed over UDP
+    Drop,
+    // Answer FORMERR, saying why
+    Malformed(String),
+    // Fail with an error, answered SERVFAIL
+    Fail(Error),
+    // Answer with a fault injected by chaos mode
+    Fault(Fault),
+    // Answer as the blocklist says
+    Blocked(&'a Blocklist),
+    // Answer from the response cache
+    Cached(&'a ResponseCache),
+}
+
+/*
+Description:
+the parts of a query the checks look at, so explain can check a query without a Request.
+*/
+struct CheckedQuery<'a> {
+    // The query name
+    name: &'a LowerName,
+    // The query type
+    query_type: RecordType,
+    // The client's address
+    client: IpAddr,
+    // The protocol the query came over
+    protocol: Protocol,
+    // The op code of the message
+    op_code: OpCode,
+    // The type of the message
+    message_type: MessageType,
+}
+
+impl<'a> From<&'a Request> for CheckedQuery<'a> {
+    fn from(request: &'a Request) -> Self {
+        CheckedQuery {
+            name: request.query().name(),
+            query_type: request.query().query_type(),
+            client: request.src().ip(),
+            protocol: request.protocol(),
+            op_code: request.op_code(),
+            message_type: request.message_type(),
+        }
+    }
+}
+
 /*
 Description:
 This code is an implementation of the monolithic handler for the DNS server.
@@ -90,7 +503,7 @@ impl Handler {
   
 /*
 Description:
-This function creates a DNS server handler from a given set of options. It initializes several LowerName instances by parsing the domain name from the options and using it to construct various zone names for the DNS server. It also initializes an AtomicU64 counter.
+This function creates a DNS server handler from a given set of options. It initializes several LowerName instances by parsing the domain name from the options and using it to construct various zone names for the DNS server. It also initializes the request counter, with one slot per shard or core, restored from the counter file if one is configured.
 
 Parameters:
 options: a reference to an Options struct that contains information about the DNS server.
@@ -103,12 +516,54 @@ A new instance of the Handler struct, which contains the initialized zones and c
     
       // Get the domain name from the options struct.
       let domain = &options.domain;
+
+      // Start the counter from its persisted value and keep flushing it, if a counter file was given.
+      let slots = match options.shards {
+          0 => std::thread::available_parallelism().map_or(1, |cores| cores.get()),
+          shards => shards,
+      };
+      let counter = Counter::new(options.counter_file.as_deref().map_or(0, counter::load), slots);
+      let named = options.counter_file.as_deref().map_or_else(Default::default, counter::load_named);
+      let named_counters = Arc::new(NamedCounters::new(named, options.config.counter.max_names));
+      if let Some(path) = &options.counter_file {
+          counter::spawn_flush(counter.clone(), named_counters.clone(), path.clone(), Duration::from_secs(options.counter_flush_interval.max(1)));
+      }
+
+      // Share the counter with other instances through Redis, if a Redis URL was given.
+      #[cfg(feature = "redis")]
+      if let Some(url) = &options.redis_url {
+          let key = format!("{}counter", options.redis_prefix);
+          let interval = Duration::from_secs(options.counter_flush_interval.max(1));
+          if let Err(error) = counter::spawn_redis_sync(counter.clone(), url, key, interval) {
+              error!("Invalid Redis URL {url}: {error}");
+          }
+      }
+      // Truncate client addresses (and optionally hash queried names) in everything that logs queries, if anonymization is enabled.
+      let anonymizer = options.anonymize.then(|| {
+          Arc::new(Anonymizer::new(
+              domain,
+              options.anonymize_ipv4_prefix,
+              options.anonymize_ipv6_prefix,
+              options.hash_qnames,
+              Duration::from_secs(options.hash_salt_rotation.max(1)),
+          ))
+      });
+
       // Initialize a new Handler struct with the following fields:
-      Handler {
+      let handler = Handler {
         // Initialize the root zone with the LowerName instance created from the domain name.
         root_zone: LowerName::from(Name::from_str(domain).unwrap()), 
-        // Initialize a new AtomicU64 counter instance wrapped in an Arc smart pointer and initialize its value to 0.
-        counter: Arc::new(AtomicU64::new(0)),
+        // Use the counters created above, starting at 0 or their persisted values.
+        counter,
+        named_counters,
+        // Build the access control lists; transfers and updates are refused unless explicitly allowed.
+        query_acl: Acl::open(options.allow.clone(), options.deny.clone()),
+        transfer_acl: Acl::closed(options.transfer_allow.clone(), options.transfer_deny.clone()),
+        update_acl: Acl::closed(options.update_allow.clone(), options.update_deny.clone()),
+        // Check query names against the configured length and label count.
+        limits: QueryLimits { max_name_length: options.max_qname_length, max_labels: options.max_labels },
+        // Limit the queries per second if a rate limit was given.
+        rate_limit: (options.rate_limit > 0).then(|| Arc::new(RateLimit::new(options.rate_limit))),
         // Initialize the counter zone with the LowerName instance created from the domain name and the "counter" string.
         counter_zone: LowerName::from(Name::from_str(&format!("counter.{domain}")).unwrap()),
         // Initialize the myip zone with the LowerName instance created from the domain name and the "myip" string.
@@ -121,8 +576,225 @@ A new instance of the Handler struct, which contains the initialized zones and c
         cidr_zone: LowerName::from(Name::from_str(&format!("cidr.{domain}")).unwrap()),
         // Initialize the time zone with the LowerName instance created from the domain name and the "time" string.
         time_zone: LowerName::from(Name::from_str(&format!("time.{domain}")).unwrap()),
-        
-    }
+        // Initialize the dyn zone with the LowerName instance created from the domain name and the "dyn" string.
+        ddns_zone: LowerName::from(Name::from_str(&format!("{}.{domain}", ddns::ZONE)).unwrap()),
+        // Initialize the docker zone with the LowerName instance created from the domain name and the "docker" string.
+        docker_zone: LowerName::from(Name::from_str(&format!("{}.{domain}", docker::ZONE)).unwrap()),
+        // Initialize the registry zone with the LowerName instance created from the domain name and the configured label, falling back to "svc" if the label is not a valid name.
+        registry_zone: LowerName::from(
+            Name::from_str(&format!("{}.{domain}", options.registry_zone))
+                .unwrap_or_else(|_| Name::from_str(&format!("svc.{domain}")).unwrap()),
+        ),
+        // Initialize the hosts zone with the LowerName instance created from the domain name and the "hosts" string.
+        hosts_zone: LowerName::from(Name::from_str(&format!("{}.{domain}", hostsfile::ZONE)).unwrap()),
+        // Initialize the lan zone with the LowerName instance created from the domain name and the "lan" string.
+        lan_zone: LowerName::from(Name::from_str(&format!("{}.{domain}", lan::ZONE)).unwrap()),
+        // Initialize the wg zone with the LowerName instance created from the domain name and the "wg" string.
+        wireguard_zone: LowerName::from(Name::from_str(&format!("{}.{domain}", wireguard::ZONE)).unwrap()),
+        // Initialize the fx zone with the LowerName instance created from the domain name and the "fx" string.
+        fx_zone: LowerName::from(Name::from_str(&format!("fx.{domain}")).unwrap()),
+        // Initialize the exchange-rate client from the fx options, wrapped in an Arc so clones of the handler share its cache.
+        fx: Arc::new(FxRates::new(
+            &options.fx_api_url,
+            options.fx_cache_dir.clone(),
+            Duration::from_secs(options.fx_max_age),
+        )),
+        // Initialize the lotto zone with the LowerName instance created from the domain name and the "lotto" string.
+        lotto_zone: LowerName::from(Name::from_str(&format!("lotto.{domain}")).unwrap()),
+        // Initialize the rps zone with the LowerName instance created from the domain name and the "rps" string.
+        rps_zone: LowerName::from(Name::from_str(&format!("rps.{domain}")).unwrap()),
+        // Initialize an empty rock-paper-scissors stats map shared between clones of the handler.
+        rps_stats: Arc::new(RpsStats::default()),
+        // Initialize the moon zone with the LowerName instance created from the domain name and the "moon" string.
+        moon_zone: LowerName::from(Name::from_str(&format!("moon.{domain}")).unwrap()),
+        // Initialize the sun zone with the LowerName instance created from the domain name and the "sun" string.
+        sun_zone: LowerName::from(Name::from_str(&format!("sun.{domain}")).unwrap()),
+        // Initialize the week zone with the LowerName instance created from the domain name and the "week" string.
+        week_zone: LowerName::from(Name::from_str(&format!("week.{domain}")).unwrap()),
+        // Initialize the str zone with the LowerName instance created from the domain name and the "str" string.
+        str_zone: LowerName::from(Name::from_str(&format!("str.{domain}")).unwrap()),
+        // Initialize the puny zone with the LowerName instance created from the domain name and the "puny" string.
+        puny_zone: LowerName::from(Name::from_str(&format!("puny.{domain}")).unwrap()),
+        // Initialize the unpuny zone with the LowerName instance created from the domain name and the "unpuny" string.
+        unpuny_zone: LowerName::from(Name::from_str(&format!("unpuny.{domain}")).unwrap()),
+        // Initialize the luhn zone with the LowerName instance created from the domain name and the "luhn" string.
+        luhn_zone: LowerName::from(Name::from_str(&format!("luhn.{domain}")).unwrap()),
+        // Initialize the crc32 zone with the LowerName instance created from the domain name and the "crc32" string.
+        crc32_zone: LowerName::from(Name::from_str(&format!("crc32.{domain}")).unwrap()),
+        // Initialize the fib zone with the LowerName instance created from the domain name and the "fib" string.
+        fib_zone: LowerName::from(Name::from_str(&format!("fib.{domain}")).unwrap()),
+        // Initialize the fact zone with the LowerName instance created from the domain name and the "fact" string.
+        fact_zone: LowerName::from(Name::from_str(&format!("fact.{domain}")).unwrap()),
+        // Initialize the age zone with the LowerName instance created from the domain name and the "age" string.
+        age_zone: LowerName::from(Name::from_str(&format!("age.{domain}")).unwrap()),
+        // Initialize the ulid zone with the LowerName instance created from the domain name and the "ulid" string.
+        ulid_zone: LowerName::from(Name::from_str(&format!("ulid.{domain}")).unwrap()),
+        // Initialize the name zone with the LowerName instance created from the domain name and the "name" string.
+        name_zone: LowerName::from(Name::from_str(&format!("name.{domain}")).unwrap()),
+        // Start the dnstap logger if a dnstap file or socket was given.
+        dnstap: options
+            .dnstap_file
+            .clone()
+            .map(Sink::File)
+            .or_else(|| options.dnstap_socket.clone().map(Sink::Socket))
+            .map(|sink| {
+                let anonymizer = anonymizer.clone();
+                Arc::new(Dnstap::spawn(sink, domain, options.dnstap_sample, options.dnstap_buffer, anonymizer))
+            }),
+        // Initialize the per-client statistics with the configured capacity.
+        clients: Arc::new(ClientStats::new(options.client_stats_capacity)),
+        // Initialize the top zone with the LowerName instance created from the domain name and the "top" string.
+        top_zone: LowerName::from(Name::from_str(&format!("top.{domain}")).unwrap()),
+        // Open the SQLite query log if a database was given, carrying on without it if it cannot be opened.
+        #[cfg(feature = "sqlite")]
+        query_log: options.query_log.as_deref().and_then(|path| {
+            let retention = (options.query_log_retention_days > 0)
+                .then(|| Duration::from_secs(options.query_log_retention_days * 86400));
+            QueryLog::open(path, retention)
+                .map_err(|error| error!("Failed to open query log {}: {error}", path.display()))
+                .ok()
+                .map(Arc::new)
+        }),
+        // Keep the settings from the configuration file.
+        config: Arc::new(options.config.clone()),
+        // Start the blocklist if the config file has a [blocklist] section.
+        blocklist: options.config.blocklist.as_ref().map(Blocklist::spawn),
+        // Start scoring clients for DNS tunneling if the config file has a [tunneling] section.
+        tunneling: options.config.tunneling.as_ref().map(|config| Arc::new(TunnelDetector::new(config))),
+        // Pad answers sent back through encrypting proxies if the config file has a [padding] section.
+        padding: options.config.padding.as_ref().map(|config| Arc::new(Padding::new(config))),
+        // Initialize the health zone with the LowerName instance created from the domain name and the "health" string.
+        health_zone: LowerName::from(Name::from_str(&format!("health.{domain}")).unwrap()),
+        // Start out live but not ready; main marks the handler ready once the sockets are bound.
+        health: Arc::new(Health::new()),
+        // Initialize an empty metrics registry shared between clones of the handler.
+        metrics: Arc::new(Metrics::default()),
+        // Initialize the stats zone with the LowerName instance created from the domain name and the "stats" string.
+        stats_zone: LowerName::from(Name::from_str(&format!("stats.{domain}")).unwrap()),
+        // Initialize the acme zone with the LowerName instance created from the domain name and the "acme" string.
+        acme_zone: LowerName::from(Name::from_str(&format!("{}.{domain}", acme::ZONE)).unwrap()),
+        // Start the ACME challenge responder if its API is enabled, leaving it off if its accounts cannot be read rather than losing them.
+        acme: options.acme_api.and_then(|_| {
+            let lifetime = Duration::from_secs(options.acme_token_lifetime);
+            Acme::open(domain, options.acme_accounts.as_deref(), options.acme_register_allow.clone(), lifetime)
+                .map_err(|error| error!("Failed to load the ACME accounts, leaving the ACME API off: {error}"))
+                .ok()
+                .map(Arc::new)
+        }),
+        // Initialize the trace zone with the LowerName instance created from the domain name and the "trace" string.
+        trace_zone: LowerName::from(Name::from_str(&format!("{}.{domain}", trace::ZONE)).unwrap()),
+        // Create the tracer if the trace zone is enabled.
+        tracer: options.trace.then(|| Arc::new(Tracer::new(options.trace_roots.clone()))),
+        // Load the dynamic zone's hosts if the DynDNS update API is enabled, leaving it off if they cannot be read rather than overwriting them.
+        ddns: options.ddns_api.and_then(|_| {
+            Ddns::open(domain, &options.ddns_hosts)
+                .map_err(|error| error!("Failed to load the DDNS hosts from {}, leaving the DDNS API off: {error}", options.ddns_hosts.display()))
+                .ok()
+                .map(Arc::new)
+        }),
+        // Create the docker zone if a Docker socket is given; main starts watching the socket.
+        docker: options.docker_socket.as_ref().map(|_| Arc::new(Docker::default())),
+        // Create the registry zone if Consul or etcd is configured; main starts following the backend.
+        registry: Backend::from_options(options).map(|_| Arc::new(Registry::default())),
+        // Read the hosts files if any are given; main starts watching them for changes.
+        hosts: (!options.hosts_file.is_empty()).then(|| Arc::new(HostsFiles::new(domain, options.hosts_file.clone()))),
+        // Create the lan zone if LAN discovery is enabled; main starts discovering devices.
+        lan: options.lan_discovery.then(|| Arc::new(Lan::default())),
+        // Create the wg zone if a WireGuard configuration file or interface is given; main starts reading the peers.
+        wireguard: wireguard::Source::from_options(options).map(|_| Arc::new(WireGuard::default())),
+        // Create the record store if a backend serves records from it; main starts loading them.
+        records: records::configured(options).then(|| Arc::new(RecordStore::default())),
+        // Build the GeoDNS answer sets if the config file has any, leaving GeoDNS off if they are invalid.
+        geo: (!options.config.geo.is_empty())
+            .then(|| GeoDns::new(&options.config.geo).map_err(|error| error!("Invalid GeoDNS answers, leaving GeoDNS off: {error}")).ok())
+            .flatten()
+            .map(Arc::new),
+        // Set up the failover targets if the config file has any, leaving failover off if they are invalid; main starts checking them.
+        failover: (!options.config.failover.is_empty())
+            .then(|| Failover::new(&options.config.failover).map_err(|error| error!("Invalid failover targets, leaving failover off: {error}")).ok())
+            .flatten()
+            .map(Arc::new),
+        // Set up the delegated subzones if the config file has any, leaving delegation off if they are invalid.
+        delegation: (!options.config.delegation.is_empty())
+            .then(|| Delegation::new(&options.config.delegation).map_err(|error| error!("Invalid delegations, leaving delegation off: {error}")).ok())
+            .flatten()
+            .map(Arc::new),
+        // Read the rotation policies if the config file has any, leaving answers in their order if they are invalid.
+        rotation: options.config.rotation.as_ref().and_then(|config| {
+            Rotation::new(config)
+                .map_err(|error| error!("Invalid rotation policies, leaving answers in their order: {error}"))
+                .ok()
+                .map(Arc::new)
+        }),
+        // Build the ENUM zone if the config file has one, leaving it off if its numbers are invalid.
+        e164: options.config.enum_zone.as_ref().and_then(|config| {
+            EnumZone::new(config)
+                .map_err(|error| error!("Invalid ENUM numbers, leaving the ENUM zone off: {error}"))
+                .ok()
+                .map(Arc::new)
+        }),
+        // Build the SOA record of the domain from the [soa] section, leaving negative answers without one if its names are invalid.
+        soa: records::soa_record(&Name::parse(domain, Some(&Name::root())).unwrap(), &options.config.soa)
+            .map_err(|error| error!("Invalid SOA record, leaving it out of negative answers: {error}"))
+            .ok(),
+        // Set up the lookups of the LDAP directory's hosts if one is given, leaving the LDAP zone off if its settings are invalid.
+        #[cfg(feature = "ldap")]
+        ldap: Directory::from_options(options)
+            .and_then(|directory| LdapHosts::new(directory).map_err(|error| error!("Invalid LDAP settings, leaving the LDAP zone off: {error}")).ok())
+            .map(Arc::new),
+        // Start delivering to the webhooks if the config file has any.
+        webhooks: (!options.config.webhooks.is_empty()).then(|| Webhooks::spawn(&options.config.webhooks, domain.to_string())),
+        // Initialize the top-talker sketches with the configured window and capacity.
+        talkers: Arc::new(TopTalkers::new(Duration::from_secs(options.top_window.max(5)), options.top_capacity)),
+        // Open the audit log if one was given, carrying on without it if it cannot be opened.
+        audit: options.audit_log.as_deref().and_then(|path| {
+            AuditLog::open(path)
+                .map_err(|error| error!("Failed to open audit log {}: {error}", path.display()))
+                .ok()
+                .map(Arc::new)
+        }),
+        // Keep the anonymizer for the query log, dashboard and debug logs.
+        anonymizer,
+        // Start the overload detector with the configured limits.
+        overload: Arc::new(Overload::new(options.max_in_flight, options.max_latency_ms * 1000)),
+        shed_drop_udp: options.shed_drop_udp,
+        // Sample the debug query log as configured.
+        log_sample: options.log_sample,
+        // Filled in below, once the zones are known.
+        templates: None,
+        // Cache deterministic answers if a cache size was given.
+        response_cache: (options.response_cache_size > 0).then(|| {
+            Arc::new(ResponseCache::new(options.response_cache_size, Duration::from_secs(options.response_cache_ttl)))
+        }),
+        // Cache parsed query names if a cache size was given.
+        name_cache: (options.name_cache_size > 0).then(|| Arc::new(NameCache::new(options.name_cache_size))),
+        // Make the random zones deterministic if a seed was given.
+        random: options.rng_seed.map_or_else(Random::default, Random::seeded),
+        // Stop the clock of the date and time zones if a time was given.
+        clock: match options.clock {
+            Some(time) => Arc::new(FixedClock(time)),
+            None => Arc::new(SystemClock),
+        },
+        // Turn chaos mode on if a share of queries to inject faults into was given.
+        chaos: (options.chaos > 0.0 && !options.chaos_faults.is_empty()).then(|| {
+            Arc::new(Chaos::new(options.chaos, options.chaos_faults.clone(), Duration::from_millis(options.chaos_max_delay_ms)))
+        }),
+        // Filled in below, once the handler they share logs and health with exists.
+        tenants: None,
+    };
+
+      // Prepare the templates for answering names no zone serves without building a response.
+      let keyword_zones = handler.keyword_zones();
+      let fixed_zones = handler.fixed_zones().map(|(zone, _)| zone);
+      let templates = Templates::new(&handler.root_zone, &fixed_zones, &keyword_zones, handler.soa.as_ref());
+      let handler = Handler {
+          templates: Some(Arc::new(templates)),
+          ..handler
+      };
+
+      // Build the handlers of the hosted domains if the config file has any.
+      let tenants = (!options.config.tenants.is_empty()).then(|| Arc::new(Tenants::new(options, &handler)));
+      Handler { tenants, ..handler }
   }
 
 /*
@@ -146,421 +818,3378 @@ Result<ResponseInfo, Error>: a Result object that contains either a ResponseInfo
     response: R,
   ) -> Result<ResponseInfo, Error> {
 
-    // Check if the request's op code is a query. If not, return an error.
-    if request.op_code() != OpCode::Query {
-        return Err(Error::InvalidOpCode(request.op_code()));
+    // Run the checks in order, answering the request as the first one that doesn't pass it says.
+    let query = CheckedQuery::from(request);
+    for check in CHECKS {
+        match self.check(check, &query, None) {
+            CheckVerdict::Pass => {}
+            CheckVerdict::Delay(delay) => tokio::time::sleep(delay).await,
+            CheckVerdict::Refuse => return self.refuse(request, response).await,
+            CheckVerdict::Drop => {
+                let mut header = response_header(request.header());
+                header.set_response_code(ResponseCode::Refused);
+                return Ok(header.into());
+            }
+            CheckVerdict::Malformed(reason) => {
+                return self.do_handle_request_malformed(request, response, ResponseCode::FormErr, &reason).await;
+            }
+            CheckVerdict::Fail(error) => return Err(error),
+            CheckVerdict::Fault(fault) => return self.do_handle_request_chaos(fault, request, response).await,
+            CheckVerdict::Blocked(blocklist) => return self.do_handle_request_blocked(blocklist, request, response).await,
+            CheckVerdict::Cached(cache) => return self.do_handle_request_cached(cache, request, response).await,
+        }
+    }
+
+    self.dispatch(request, response).await
+  }
+
+/*
+Description:
+makes one of the checks a query goes through before it reaches its zone. Answering a query, it has the check's side effects: the audit log, the rate limit's and the tunneling detector's counts, the shed count and chaos mode's draws. Explaining one, it has none, and instead writes down how the check went; checks that depend on earlier queries or on chance pass, with a note of what they would do.
+
+Parameters:
+check: the check to make.
+query: the query to check.
+steps: None when answering the query; when explaining it, the (check, outcome) pairs so far, which the check's own is added to.
+
+Returns:
+CheckVerdict: whether the query goes on to the next check, and how it is answered if not.
+*/
+
+  fn check(&self, check: Check, query: &CheckedQuery, mut steps: Option<&mut Vec<(&'static str, String)>>) -> CheckVerdict<'_> {
+    let explaining = steps.is_some();
+    let mut note = |outcome: &dyn Fn() -> String| {
+        if let Some(steps) = steps.as_deref_mut() {
+            steps.push((check.name(), outcome()));
+        }
+    };
+    let client = query.client;
+
+    match check {
+        // Check the client's address against the matching access control list, recording every dynamic update attempt in the audit log; updates are not supported, so permitted ones fail later.
+        Check::Acl => {
+            let (acl, list) = self.acl_for(query.op_code, query.query_type);
+            let permitted = acl.permits(client);
+            if query.op_code == OpCode::Update && !explaining {
+                if let Some(audit) = &self.audit {
+                    let outcome = if permitted { "not supported" } else { "refused" };
+                    audit.record(AuditEntry::now(
+                        client.to_string(),
+                        format!("dns/{}", query.protocol.to_string().to_lowercase()),
+                        "update",
+                        query.name.to_string(),
+                        outcome,
+                    ));
+                }
+            }
+            if !permitted {
+                note(&|| format!("REFUSED: {client} is not allowed by the {list} access control list"));
+                return CheckVerdict::Refuse;
+            }
+            note(&|| format!("{client} is allowed by the {list} access control list"));
+            CheckVerdict::Pass
+        }
+
+        // Answer names that break the query limits with FORMERR, saying which, before any zone takes them apart.
+        Check::Limits => match self.limits.check_name(Name::from(query.name).iter()) {
+            Err(violation) => {
+                note(&|| format!("FORMERR: {violation}"));
+                CheckVerdict::Malformed(violation.to_string())
+            }
+            Ok(()) => {
+                note(&|| "the name is within the query limits".to_string());
+                CheckVerdict::Pass
+            }
+        },
+
+        // Refuse the query if the domain has answered as many queries this second as its rate limit allows; explaining one doesn't count it, so it is never over.
+        Check::RateLimit => match &self.rate_limit {
+            Some(rate_limit) if explaining => {
+                note(&|| format!("the domain answers up to {} queries per second and refuses the rest", rate_limit.rate()));
+                CheckVerdict::Pass
+            }
+            Some(rate_limit) if !rate_limit.allow(std::time::Instant::now()) => CheckVerdict::Refuse,
+            _ => CheckVerdict::Pass,
+        },
+
+        // Score the client for DNS tunneling on the labels in front of the domain, refusing it while it is suspected if the detector blocks; explaining a query doesn't score it, so the client isn't suspected.
+        Check::Tunneling => match &self.tunneling {
+            Some(tunneling) if explaining => {
+                let action = if tunneling.blocks() { "refused" } else { "logged" };
+                note(&|| format!("the client is scored for DNS tunneling and {action} once suspected"));
+                CheckVerdict::Pass
+            }
+            Some(tunneling) => {
+                let trailing = if self.root_zone.zone_of(query.name) { self.root_zone.num_labels() } else { 0 };
+                let subdomain = zone_labels(&Name::from(query.name), 0, trailing).join(".");
+                match tunneling.observe(client, &subdomain, query.query_type, std::time::Instant::now()) {
+                    Verdict::Clear => CheckVerdict::Pass,
+                    verdict => {
+                        if let Verdict::Flagged(score) = verdict {
+                            warn!("Client {} is suspected of DNS tunneling, {score}", self.logged_ip(client));
+                        }
+                        if tunneling.blocks() { CheckVerdict::Refuse } else { CheckVerdict::Pass }
+                    }
+                }
+            }
+            None => CheckVerdict::Pass,
+        },
+
+        // Refuse the query if the zone's country policy doesn't allow the client's country.
+        Check::Policy => match self.zone_config(query.name) {
+            Some(zone) if zone.uses_geoip() => {
+                let country = self.config.country(client);
+                if !zone.permits_country(country.as_deref()) {
+                    note(&|| format!("REFUSED: the zone's country policy doesn't allow {}", country.as_deref().unwrap_or("an unknown country")));
+                    return CheckVerdict::Refuse;
+                }
+                note(&|| format!("the zone's country policy allows {}", country.as_deref().unwrap_or("an unknown country")));
+                CheckVerdict::Pass
+            }
+            Some(_) => {
+                note(&|| "the zone has no country policy".to_string());
+                CheckVerdict::Pass
+            }
+            None => {
+                note(&|| "the zone has no [zones] section, so the defaults apply".to_string());
+                CheckVerdict::Pass
+            }
+        },
+
+        // Shed the query if the server is overloaded and the zone's priority is too low, dropping it instead of refusing it over UDP if configured; explaining one doesn't count it as shed.
+        Check::Overload => {
+            let priority = self.zone_config(query.name).map_or(Priority::Normal, |zone| zone.priority);
+            let shed = if explaining { self.overload.would_shed(priority) } else { self.overload.should_shed(priority) };
+            let drop = self.shed_drop_udp && matches!(query.protocol, Protocol::Udp);
+            if shed {
+                let action = if drop { "DROPPED" } else { "REFUSED" };
+                note(&|| format!("{action}: {priority:?} priority zones are shed at the current load of {:.2}", self.overload.load()));
+                return if drop { CheckVerdict::Drop } else { CheckVerdict::Refuse };
+            }
+            note(&|| format!("{priority:?} priority, not shed at the current load of {:.2}", self.overload.load()));
+            CheckVerdict::Pass
+        }
+
+        // Fail the query if its op code or message type isn't that of a query.
+        Check::Message => {
+            if query.op_code != OpCode::Query {
+                return CheckVerdict::Fail(Error::InvalidOpCode(query.op_code));
+            }
+            if query.message_type != MessageType::Query {
+                return CheckVerdict::Fail(Error::InvalidMessageType(query.message_type));
+            }
+            CheckVerdict::Pass
+        }
+
+        // Inject a fault if chaos mode picks this query, leaving health checks alone; truncation only applies over UDP. Explaining a query draws nothing, so only the odds are given.
+        Check::Chaos => match &self.chaos {
+            Some(_) if self.health_zone.zone_of(query.name) => {
+                note(&|| "health checks never get a fault injected".to_string());
+                CheckVerdict::Pass
+            }
+            Some(chaos) if explaining => {
+                let faults: Vec<String> = chaos.faults().iter().map(Fault::to_string).collect();
+                note(&|| format!("a fault ({}) is injected into {:.0}% of queries", faults.join(", "), chaos.probability() * 100.0));
+                CheckVerdict::Pass
+            }
+            Some(chaos) => match chaos.draw(&self.random) {
+                Some(Fault::Delay) => CheckVerdict::Delay(chaos.delay(&self.random)),
+                Some(Fault::Truncate) if !matches!(query.protocol, Protocol::Udp) => CheckVerdict::Pass,
+                Some(fault) => CheckVerdict::Fault(fault),
+                None => CheckVerdict::Pass,
+            },
+            None => CheckVerdict::Pass,
+        },
+
+        // Answer blocked names before they reach any zone.
+        Check::Blocklist => match &self.blocklist {
+            Some(blocklist) if blocklist.is_blocked(&query.name.to_string(), client) => {
+                note(&|| format!("BLOCKED: answered with {:?}", blocklist.response()));
+                CheckVerdict::Blocked(blocklist)
+            }
+            Some(_) => {
+                note(&|| "not blocked".to_string());
+                CheckVerdict::Pass
+            }
+            None => CheckVerdict::Pass,
+        },
+
+        // Answer repeated questions to zones whose answer depends only on the question from the response cache.
+        Check::Cache => match &self.response_cache {
+            Some(cache) if self.cacheable(query.name) => {
+                note(&|| "the answer depends only on the question, so it is served from the response cache".to_string());
+                CheckVerdict::Cached(cache)
+            }
+            Some(_) => {
+                note(&|| "the zone's answers are not cached".to_string());
+                CheckVerdict::Pass
+            }
+            None => CheckVerdict::Pass,
+        },
+    }
+  }
+
+  // Returns the [zones] section of the zone a name belongs to, if the config file has one
+  fn zone_config(&self, name: &LowerName) -> Option<&ZoneConfig> {
+    self.zone_name(name).and_then(|zone| self.config.zone(&zone))
+  }
+
+/*
+Description:
+explains how a query would be answered, for the explain subcommand: makes the checks of do_handle_request in the same order through check, without answering, logging or counting anything, stopping at the first one that would refuse or block the query, then names the handler that would answer it.
+
+Parameters:
+name: the query name.
+query_type: the query type.
+client: the address of the client asking.
+
+Returns:
+A Vec of (check, outcome) pairs in the order the checks are made.
+*/
+
+  pub fn explain(&self, name: &LowerName, query_type: RecordType, client: IpAddr) -> Vec<(&'static str, String)> {
+    let mut steps = Vec::new();
+
+    // The hosted domain the name belongs to, whose handler makes the checks in its place.
+    if let Some(tenant) = self.tenants.as_ref().and_then(|tenants| tenants.for_name(name)) {
+        steps.push(("tenant", format!("the hosted domain {}, answered by its own handler", tenant.domain)));
+        steps.extend(tenant.handler.explain(name, query_type, client));
+        return steps;
+    }
+
+    // The checks, as for a query over UDP, with the zone named before its policy is checked.
+    let query = CheckedQuery { name, query_type, client, protocol: Protocol::Udp, op_code: OpCode::Query, message_type: MessageType::Query };
+    let (zone, handler) = self.explain_zone(name, client);
+    for check in CHECKS {
+        if check == Check::Policy {
+            steps.push(("zone", zone.clone()));
+        }
+        match self.check(check, &query, Some(&mut steps)) {
+            CheckVerdict::Pass | CheckVerdict::Cached(_) => {}
+            _ => return steps,
+        }
+    }
+
+    // The order of the name's addresses.
+    if let Some(rotation) = &self.rotation {
+        steps.push(("rotation", format!("the addresses of names with several are ordered by the {:?} policy", rotation.policy(name))));
+    }
+
+    steps.push(("handler", handler));
+    steps
+  }
+
+  // Returns which zone a name belongs to, as dispatch matches it, and what answers it there, for explain
+  fn explain_zone(&self, name: &LowerName, client: IpAddr) -> (String, String) {
+    let parsed = self.parsed_name(name);
+    let served = parsed.fixed
+        || parsed.zone.as_deref().is_some_and(|keyword| {
+            self.keyword_zones().into_iter().any(|zone| Name::from(zone).iter().next() == Some(keyword.as_bytes()))
+        });
+    match parsed.zone.as_deref() {
+        _ if self.delegation.as_ref().is_some_and(|delegation| delegation.serves(name)) => {
+            let (zone, url, open) = self.delegation.as_ref().and_then(|delegation| delegation.status(name)).unwrap_or_default();
+            let state = if open { "failing, so only cached answers are served" } else { "being asked" };
+            (format!("the subzone {zone}, delegated to the HTTP backend {url}, which is {state}"), "answered with the backend's records".to_string())
+        }
+        Some(zone) if served => {
+            let kind = if parsed.fixed { "fixed" } else { "keyword" };
+            (format!("the {zone} {kind} zone, with the arguments {:?}", parsed.args), format!("answered by the {zone} zone's handler"))
+        }
+        _ if self.e164.as_ref().is_some_and(|e164| e164.serves(name)) => {
+            let number = self.e164.as_ref().and_then(|e164| e164.number(name));
+            let number = match number.as_deref() {
+                Some("") => "the zone itself".to_string(),
+                Some(digits) => format!("the number +{digits}"),
+                None => "no number (its labels aren't up to 15 single digits)".to_string(),
+            };
+            (format!("the ENUM zone, for {number}"), "answered with the number's NAPTR records".to_string())
+        }
+        #[cfg(feature = "ldap")]
+        _ if self.ldap.as_ref().is_some_and(|ldap| ldap.serves(name)) => {
+            let host = self.ldap.as_ref().and_then(|ldap| ldap.host(name).map(|host| ldap.filter(&host)));
+            (
+                format!("the LDAP zone, searching the directory with {}", host.as_deref().unwrap_or("no filter (the zone itself)")),
+                "answered with the addresses of the host's directory entries".to_string(),
+            )
+        }
+        _ if self.failover.as_ref().is_some_and(|failover| failover.serves(name)) => {
+            let status = self.failover.as_ref().and_then(|failover| failover.status(name)).unwrap_or_default();
+            let targets: Vec<String> = status.iter().map(|(address, up)| format!("{address} ({})", if *up { "up" } else { "down" })).collect();
+            (format!("health-checked failover, with the targets {}", targets.join(", ")), "answered with the targets that are up".to_string())
+        }
+        _ if self.geo.as_ref().is_some_and(|geo| geo.serves(name)) => {
+            let location = self.config.locate(client);
+            let place = match (&location.country, &location.continent) {
+                (Some(country), Some(continent)) => format!("{country} in {continent}"),
+                _ => "an unknown location".to_string(),
+            };
+            (format!("GeoDNS, answering a client in {place} (before any EDNS Client Subnet)"), "answered with the answer set of the client's location".to_string())
+        }
+        _ if self.records.as_ref().is_some_and(|records| records.serves(name)) => {
+            ("the record store, from the configured record backends".to_string(), "answered from the record store".to_string())
+        }
+        Some(zone) => {
+            (format!("no zone has the keyword {zone}, so the root zone {} answers", self.root_zone), "answered by the root zone's default handler".to_string())
+        }
+        None if self.root_zone.zone_of(name) => {
+            (format!("the root zone {}", self.root_zone), "answered by the root zone's default handler".to_string())
+        }
+        None if self.serves_reverse(name) => {
+            ("the reverse zone, from the hosts files and discovered LAN devices".to_string(), "answered by the reverse zone's handler".to_string())
+        }
+        None => {
+            (format!("none, {name} is not in the root zone {}", self.root_zone), "no handler, so the query fails and is not answered".to_string())
+        }
     }
+  }
+
+/*
+Description:
+hands a request to the zone its query name belongs to.
+
+Parameters:
+request: the request, which has passed the access, policy and blocklist checks.
+response: the ResponseHandler the zone sends its answer to.
+
+Returns:
+Result<ResponseInfo, Error>: the ResponseInfo of the zone's answer, or an Error if the zone failed or the name belongs to no zone.
+*/
+
+  async fn dispatch<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    response: R,
+  ) -> Result<ResponseInfo, Error> {
+    // Match the query name with a zone, from the name cache if possible, and call the appropriate function to handle the request.
+    let name = request.query().name();
 
-    // Check if the request's message type is a query. If not, return an error.
-    if request.message_type() != MessageType::Query {
-        return Err(Error::InvalidMessageType(request.message_type()));
+    // A delegated subzone is answered by its backend, whatever zone its names would otherwise belong to.
+    if self.delegation.as_ref().is_some_and(|delegation| delegation.serves(name)) {
+        return self.do_handle_request_delegation(request, response).await;
     }
+    let parsed = self.parsed_name(name);
 
-    // Match the query name with a zone and call the appropriate function to handle the request.
-    match request.query().name() {
+    // A zone that only answers some types gets an empty answer for the others, rather than records of a type that wasn't asked for.
+    if let Some(zone) = parsed.zone.as_deref() {
+        if !serves_type(parsed.fixed, zone, request.query().query_type()) {
+            return self.do_handle_request_nodata(request, response).await;
+        }
+    }
+    match (parsed.fixed, parsed.zone.as_deref()) {
         // If the query name is in the myip_zone, call the do_handle_request_myip function.
-        name if self.myip_zone.zone_of(name) => {
+        (true, Some("myip")) => {
             self.do_handle_request_myip(request, response).await
         }
         // If the query name is in the counter_zone, call the do_handle_request_counter function.
-        name if self.counter_zone.zone_of(name) => {
+        (true, Some("counter")) => {
             self.do_handle_request_counter(request, response).await
         }
         // If the query name is in the coin_zone, call the do_handle_request_coin function.
-        name if self.coin_zone.zone_of(name) => {
+        (true, Some("coin")) => {
             self.do_handle_request_coin(request, response).await
         }
         // If the query name is in the dice_zone, call the do_handle_request_dice function.
-        name if self.dice_zone.zone_of(name) => {
+        (true, Some("dice")) => {
             self.do_handle_request_dice(request, response).await
         }
         // If the query name is in the cidr_zone, call the do_handle_request_cidr function.
-        name if self.cidr_zone.zone_of(name) => {
+        (true, Some("cidr")) => {
             self.do_handle_request_cidr(request, response).await
         }
         // If the query name is in the time_zone, call the handle_epoch_request function.
-        name if self.time_zone.zone_of(name) => {
+        (true, Some("time")) => {
             self.handle_epoch_request(request, response).await
         }
+        // If the query name is in the ddns_zone, call the do_handle_request_ddns function.
+        (true, Some(ddns::ZONE)) => {
+            self.do_handle_request_ddns(request, response).await
+        }
+        // If the query name is in the docker_zone, call the do_handle_request_docker function.
+        (true, Some(docker::ZONE)) => {
+            self.do_handle_request_docker(request, response).await
+        }
+        // If the query name is in the registry_zone, call the do_handle_request_registry function.
+        (true, Some(registry::ZONE)) => {
+            self.do_handle_request_registry(request, response).await
+        }
+        // If the query name is in the hosts_zone, call the do_handle_request_hosts function.
+        (true, Some(hostsfile::ZONE)) => {
+            self.do_handle_request_hosts(request, response).await
+        }
+        // If the query name is in the lan_zone, call the do_handle_request_lan function.
+        (true, Some(lan::ZONE)) => {
+            self.do_handle_request_lan(request, response).await
+        }
+        // If the query name is in the wireguard_zone, call the do_handle_request_wireguard function.
+        (true, Some(wireguard::ZONE)) => {
+            self.do_handle_request_wireguard(request, response).await
+        }
+        // If the query name starts with the fx keyword, call the do_handle_request_fx function.
+        (false, Some("fx")) => {
+            self.do_handle_request_fx(request, response).await
+        }
+        // If the query name starts with the lotto keyword, call the do_handle_request_lotto function.
+        (false, Some("lotto")) => {
+            self.do_handle_request_lotto(request, response).await
+        }
+        // If the query name starts with the rps keyword, call the do_handle_request_rps function.
+        (false, Some("rps")) => {
+            self.do_handle_request_rps(request, response).await
+        }
+        // If the query name starts with the moon keyword, call the do_handle_request_moon function.
+        (false, Some("moon")) => {
+            self.do_handle_request_moon(request, response).await
+        }
+        // If the query name starts with the sun keyword, call the do_handle_request_sun function.
+        (false, Some("sun")) => {
+            self.do_handle_request_sun(request, response).await
+        }
+        // If the query name starts with the week keyword, call the do_handle_request_week function.
+        (false, Some("week")) => {
+            self.do_handle_request_week(request, response).await
+        }
+        // If the query name starts with the str keyword, call the do_handle_request_str function from the strings module.
+        (false, Some("str")) => {
+            self.do_handle_request_str(request, response).await
+        }
+        // If the query name starts with the puny keyword, call the do_handle_request_puny function.
+        (false, Some("puny")) => {
+            self.do_handle_request_puny(request, response, true).await
+        }
+        // If the query name starts with the unpuny keyword, call the do_handle_request_puny function.
+        (false, Some("unpuny")) => {
+            self.do_handle_request_puny(request, response, false).await
+        }
+        // If the query name starts with the luhn keyword, call the do_handle_request_luhn function.
+        (false, Some("luhn")) => {
+            self.do_handle_request_luhn(request, response).await
+        }
+        // If the query name starts with the crc32 keyword, call the do_handle_request_crc32 function.
+        (false, Some("crc32")) => {
+            self.do_handle_request_crc32(request, response).await
+        }
+        // If the query name starts with the fib keyword, call the do_handle_request_sequence function.
+        (false, Some("fib")) => {
+            self.do_handle_request_sequence(request, response, "fib", FIB_MAX_N, sequences::fibonacci).await
+        }
+        // If the query name starts with the fact keyword, call the do_handle_request_sequence function.
+        (false, Some("fact")) => {
+            self.do_handle_request_sequence(request, response, "fact", FACT_MAX_N, sequences::factorial).await
+        }
+        // If the query name starts with the age keyword, call the do_handle_request_age function.
+        (false, Some("age")) => {
+            self.do_handle_request_age(request, response).await
+        }
+        // If the query name starts with the ulid keyword, call the do_handle_request_ulid function.
+        (false, Some("ulid")) => {
+            self.do_handle_request_ulid(request, response).await
+        }
+        // If the query name starts with the name keyword, call the do_handle_request_name function.
+        (false, Some("name")) => {
+            self.do_handle_request_name(request, response).await
+        }
+        // If the query name starts with the top keyword, call the do_handle_request_top function.
+        (false, Some("top")) => {
+            self.do_handle_request_top(request, response).await
+        }
+        // If the query name starts with the health keyword, call the do_handle_request_health function.
+        (false, Some("health")) => {
+            self.do_handle_request_health(request, response).await
+        }
+        // If the query name starts with the stats keyword, call the do_handle_request_stats function.
+        (false, Some("stats")) => {
+            self.do_handle_request_stats(request, response).await
+        }
+        // If the query name starts with the acme keyword, call the do_handle_request_acme function.
+        (false, Some(acme::ZONE)) => {
+            self.do_handle_request_acme(request, response).await
+        }
+        // If the query name starts with the trace keyword, call the do_handle_request_trace function.
+        (false, Some(trace::ZONE)) => {
+            self.do_handle_request_trace(request, response).await
+        }
+        // If the query name is in the ENUM zone, call the do_handle_request_enum function.
+        _ if self.e164.as_ref().is_some_and(|e164| e164.serves(name)) => {
+            self.do_handle_request_enum(request, response).await
+        }
+        // If the query name is in the LDAP zone, call the do_handle_request_ldap function.
+        #[cfg(feature = "ldap")]
+        _ if self.ldap.as_ref().is_some_and(|ldap| ldap.serves(name)) => {
+            self.do_handle_request_ldap(request, response).await
+        }
+        // If failover answers the query name, call the do_handle_request_failover function.
+        _ if self.failover.as_ref().is_some_and(|failover| failover.serves(name)) => {
+            self.do_handle_request_failover(request, response).await
+        }
+        // If GeoDNS answers the query name, call the do_handle_request_geo function.
+        _ if self.geo.as_ref().is_some_and(|geo| geo.serves(name)) => {
+            self.do_handle_request_geo(request, response).await
+        }
+        // If the record store serves the query name, call the do_handle_request_records function.
+        _ if self.records.as_ref().is_some_and(|records| records.serves(name)) => {
+            self.do_handle_request_records(request, response).await
+        }
         // If the query name is in the root_zone, call the do_handle_request_default function.
-        name if self.root_zone.zone_of(name) => {
+        _ if self.root_zone.zone_of(name) => {
             self.do_handle_request_default(request, response).await
         }
+        // If the query name is the reverse name of an address and the hosts files or LAN devices are served, call the do_handle_request_reverse function.
+        _ if self.serves_reverse(name) => {
+            self.do_handle_request_reverse(request, response).await
+        }
         // If the query name is not in any zone, return an error.
-        name => Err(Error::InvalidZone(name.clone())),
+        _ => Err(Error::InvalidZone(name.clone())),
     }
   }
 
+  // Returns whether the answer to a name depends only on the question, so it can be served from the response cache
+  fn cacheable(&self, name: &LowerName) -> bool {
+    let delegated = self.delegation.as_ref().is_some_and(|delegation| delegation.serves(name));
+    !delegated && matches!(
+        self.zone_name(name).as_deref(),
+        Some("cidr" | "time" | "str" | "puny" | "unpuny" | "luhn" | "crc32" | "fib" | "fact")
+    )
+  }
+
 /*
 Description:
-This function handles a DNS request for retrieving the IP address of the client. It takes in a reference to a Request struct, a mutable reference to a ResponseHandler trait object, and returns a Result object containing a ResponseInfo struct or an Error object.
+answers a request from the response cache, or computes the answer with the zone's handler and caches it. On a miss the handler answers into a CaptureResponder, and the captured answer is decoded once and kept; either way the client is sent the cached records under a header built from its own request, so repeated questions skip the zone's work.
 
 Parameters:
-&self: a reference to the current instance of the DNS server object
-request: a reference to the Request struct that contains the DNS request information
-mut responder: a mutable reference to a ResponseHandler trait object that will handle the DNS response
+cache: the response cache.
+request: the request, whose zone is cacheable.
+responder: the ResponseHandler the answer is sent to.
 
 Returns:
-Ok(responder.send_response(response).await?): if the response is successfully sent, returns a Result object containing a ResponseInfo struct with the client IP address.
+Result<ResponseInfo, Error>: the ResponseInfo of the answer, or the zone's Error if it failed, which is not cached.
+*/
+
+  async fn do_handle_request_cached<R: ResponseHandler>(
+    &self,
+    cache: &ResponseCache,
+    request: &Request,
+    responder: R,
+  ) -> Result<ResponseInfo, Error> {
+    let name = request.query().original().name().to_string();
+    let query_type = request.query().query_type();
+    let edns = request.edns().is_some();
+
+    let cached = match cache.get(&name, query_type, edns) {
+        Some(cached) => {
+            // Count the query as the zone's handler would have.
+            self.counter.add(1);
+            cached
+        }
+        None => {
+            // Let the zone answer into memory, then cache and send what it answered.
+            let capture = CaptureResponder::default();
+            self.dispatch(request, capture.clone()).await?;
+            match capture.take().and_then(|bytes| cache.insert(&name, query_type, edns, &bytes)) {
+                Some(cached) => cached,
+                None => return Err(Error::Io(std::io::Error::other("the zone's answer could not be decoded"))),
+            }
+        }
+    };
+
+    self.send_cached(request, responder, &cached).await
+  }
+
+  // Sends a cached answer in reply to a request
+  async fn send_cached<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    mut responder: R,
+    cached: &CachedResponse,
+  ) -> Result<ResponseInfo, Error> {
+    let mut builder = MessageResponseBuilder::from_message_request(request);
+    let mut header = response_header(request.header());
+    header.set_authoritative(cached.authoritative);
+    header.set_response_code(cached.response_code);
+    // Carry the answer's EDNS options over, with the payload size this client offers.
+    if let (Some(cached_edns), Some(edns)) = (&cached.edns, request.edns()) {
+        let mut response_edns = cached_edns.clone();
+        response_edns.set_max_payload(edns.max_payload().max(512));
+        builder.edns(response_edns);
+    }
+    let response = builder.build(header, cached.answers.iter(), cached.name_servers.iter(), &[], cached.additionals.iter());
+    Ok(responder.send_response(response).await?)
+  }
+
+/*
+Description:
+matches a query name with its zone and takes it apart for the zone handlers, which all read their arguments from it rather than from the name. The fixed zones (myip, counter, coin, dice, cidr, time, dyn, docker, registry, hosts, lan, wg) match on their suffix and take the labels below them as arguments (e.g. 10.0.0.0.24.cidr.mentisnovae.tech); keyword zones such as fx take their arguments between the keyword and the root domain (e.g. fx.100.usd.eur.mentisnovae.tech), so a name below the root zone that no fixed zone serves is matched by its first label instead. The name is lowercased, so the zone and its arguments are too.
+
+Parameters:
+name: the query name.
+
+Returns:
+The ParsedName, with no zone if the name is not below the root zone.
+*/
+
+  fn parse_name(&self, name: &LowerName) -> ParsedName {
+    // Only names below the root zone belong to a zone, and the fixed zones also match names below them, so check those by suffix first.
+    let below_root = self.root_zone.zone_of(name) && name.num_labels() > self.root_zone.num_labels();
+    let fixed = self.fixed_zones().into_iter().find(|(zone, _)| zone.zone_of(name));
+    let (zone, args) = match fixed {
+        _ if !below_root => (None, Vec::new()),
+        Some((zone, keyword)) => (Some(keyword.to_string()), zone_labels(&Name::from(name), 0, zone.num_labels())),
+        None => (Name::from(name).iter().next().map(|label| String::from_utf8_lossy(label).into_owned()), self.keyword_args(name)),
+    };
+    ParsedName::new(zone, fixed.is_some(), name.to_string(), args)
+  }
+
+  // Returns a query name matched with its zone and taken apart, from the name cache if it is enabled
+  pub(crate) fn parsed_name(&self, name: &LowerName) -> Arc<ParsedName> {
+    match &self.name_cache {
+        Some(cache) => cache.get_or_parse(name, || self.parse_name(name)),
+        None => Arc::new(self.parse_name(name)),
+    }
+  }
+
+/*
+Description:
+works out which zone a query name belongs to, for looking up per-zone settings. The fixed zones (myip, counter, coin, dice, cidr, time, dyn, docker, registry, hosts, lan, wg) match on their suffix as in do_handle_request, and every other zone is a keyword zone named by the first label.
+
+Parameters:
+name: the query name.
+
+Returns:
+Some(String) with the zone's keyword, e.g. "myip" or "fx", or None if the name is not below the root zone.
+*/
+
+  pub(crate) fn zone_name(&self, name: &LowerName) -> Option<String> {
+    self.parsed_name(name).zone.clone()
+  }
+
+  // Returns the zones that also serve every name below them, with their keywords, in the order do_handle_request checks them
+  fn fixed_zones(&self) -> [(&LowerName, &'static str); 12] {
+    [
+        (&self.myip_zone, "myip"),
+        (&self.counter_zone, "counter"),
+        (&self.coin_zone, "coin"),
+        (&self.dice_zone, "dice"),
+        (&self.cidr_zone, "cidr"),
+        (&self.time_zone, "time"),
+        (&self.ddns_zone, ddns::ZONE),
+        (&self.docker_zone, docker::ZONE),
+        (&self.registry_zone, registry::ZONE),
+        (&self.hosts_zone, hostsfile::ZONE),
+        (&self.lan_zone, lan::ZONE),
+        (&self.wireguard_zone, wireguard::ZONE),
+    ]
+  }
+
+  // Returns whether a name outside the root zone is a reverse name (in in-addr.arpa or ip6.arpa) the reverse zone answers
+  fn serves_reverse(&self, name: &LowerName) -> bool {
+    (self.hosts.is_some() || self.lan.is_some()) && hostsfile::reverse_address(&name.to_string()).is_some()
+  }
+
+  // Returns the zones that serve names starting with their keyword, in the order do_handle_request checks them
+  fn keyword_zones(&self) -> Vec<&LowerName> {
+    vec![
+        &self.fx_zone,
+        &self.lotto_zone,
+        &self.rps_zone,
+        &self.moon_zone,
+        &self.sun_zone,
+        &self.week_zone,
+        &self.str_zone,
+        &self.puny_zone,
+        &self.unpuny_zone,
+        &self.luhn_zone,
+        &self.crc32_zone,
+        &self.fib_zone,
+        &self.fact_zone,
+        &self.age_zone,
+        &self.ulid_zone,
+        &self.name_zone,
+        &self.top_zone,
+        &self.health_zone,
+        &self.stats_zone,
+        &self.acme_zone,
+        &self.trace_zone,
+    ]
+  }
+
+/*
+Description:
+answers a UDP query straight from the wire if a template applies to it, skipping the decoding of the query and the building and encoding of the response. Today that is a query for a name no zone serves, which the default zone would answer with NXDOMAIN. The client is checked against the access control list, the zone's country policy and the overload state first, and the query is counted in the same statistics as any other; a query that would be refused, shed, blocked, logged to dnstap, the query log or the debug log or reported to a webhook is left to the full path, as are all queries while the record store or a delegated subzone may serve names the default zone would not.
+
+Parameters:
+src: the client's address.
+query: the query datagram.
+out: the buffer the answer is written to.
+
+Returns:
+true if the answer was written to out, false if the query has to take the full path.
+*/
+
+  pub fn answer_from_template(&self, src: SocketAddr, query: &[u8], out: &mut Vec<u8>) -> bool {
+    let received = std::time::Instant::now();
+    let Some(templates) = &self.templates else {
+        return false;
+    };
+
+    // Queries that may be blocked, rate limited, scored for tunneling or belong to a hosted domain, have to be logged in full, may be reported to a webhook, may be served from the record store or may get a fault injected take the full path.
+    if self.blocklist.is_some() || self.rate_limit.is_some() || self.tenants.is_some() || self.tunneling.is_some() || self.dnstap.is_some() || self.webhooks.is_some() || self.records.is_some() || self.geo.is_some() || self.failover.is_some() || self.delegation.is_some() || self.e164.is_some() || self.chaos.is_some() || enabled!(Level::DEBUG) {
+        return false;
+    }
+    #[cfg(feature = "sqlite")]
+    if self.query_log.is_some() {
+        return false;
+    }
+    #[cfg(feature = "ldap")]
+    if self.ldap.is_some() {
+        return false;
+    }
+    // Answers to encrypting proxies may have to be padded.
+    if self.padding.as_ref().is_some_and(|padding| padding.encrypted(src.ip())) {
+        return false;
+    }
+
+    // Only plain queries for names no zone serves, within the query limits, have a template.
+    let Some(query) = RawQuery::parse(query) else {
+        return false;
+    };
+    if self.limits.check_name(query.labels().iter().copied()).is_err() {
+        return false;
+    }
+    let Some(keyword) = templates.unserved(&query) else {
+        return false;
+    };
+    if matches!(query.query_type(), RecordType::AXFR | RecordType::IXFR) {
+        return false;
+    }
+
+    // Leave refusals and shedding to the full path.
+    let ip = src.ip();
+    let zone = keyword.map(|keyword| String::from_utf8_lossy(keyword).to_lowercase());
+    let zone_config = zone.as_deref().and_then(|zone| self.config.zone(zone));
+    if !self.query_acl.permits(ip) || self.overload.load() >= 1.0 || zone_config.is_some_and(|zone| zone.uses_geoip()) {
+        return false;
+    }
+
+    // Answer from the template and count the query like any other.
+    templates.nxdomain.render(&query, out);
+    self.counter.add(1);
+    let qname = query.name();
+    self.clients.record(ip, &qname);
+    self.talkers.record(ip, &qname);
+
+    let latency_us = received.elapsed().as_micros() as u64;
+    self.overload.record_latency(latency_us);
+    self.metrics.record(
+        RecentQuery {
+            timestamp: Utc::now().timestamp_millis(),
+            client: self.logged_ip(ip),
+            qname: self.logged_qname(&qname),
+            qtype: query.query_type().to_string(),
+            zone: zone.unwrap_or_else(|| "root".to_string()),
+            rcode: ResponseCode::NXDomain.to_string(),
+            latency_us,
+        },
+        "udp",
+        latency_us,
+        ResponseCode::NXDomain,
+    );
+    true
+  }
+
+/*
+Description:
+returns what the authority section of an answer carries: the SOA record of the domain if the answer is negative, NXDOMAIN or NOERROR without answers (NODATA), for a name in the domain, so resolvers can cache that the name or type doesn't exist (RFC 2308).
+
+Parameters:
+request: the request answered.
+header: the header of the answer, with its response code.
+answers: the records of the answer section.
+
+Returns:
+Some(Record) with the SOA record for a negative answer, or None.
+*/
+
+  fn negative_soa(&self, request: &Request, header: &Header, answers: &[Record]) -> Option<Record> {
+    let negative = match header.response_code() {
+        ResponseCode::NXDomain => true,
+        ResponseCode::NoError => answers.is_empty(),
+        _ => false,
+    };
+    match negative && self.root_zone.zone_of(request.query().name()) {
+        true => self.soa.clone(),
+        false => None,
+    }
+  }
+
+/*
+Description:
+starts the answer to a request, for a zone to add its records to and send. Its records get the TTL of the zone's [zones.<keyword>] section if it sets one, and the zone's default otherwise.
+
+Parameters:
+request: a reference to the Request struct that contains the DNS request information
+ttl: the zone's default TTL.
+
+Returns:
+The ResponseBuilder of an authoritative NOERROR answer without records.
+*/
+
+  pub(crate) fn respond<'a>(&'a self, request: &'a Request, ttl: u32) -> ResponseBuilder<'a> {
+    let ttl = self
+        .zone_name(request.query().name())
+        .and_then(|zone| self.config.zone(&zone).and_then(|zone| zone.ttl))
+        .unwrap_or(ttl);
+    let mut header = response_header(request.header());
+    header.set_authoritative(true);
+    ResponseBuilder {
+        handler: self,
+        request,
+        header,
+        ttl,
+        answers: Vec::new(),
+        additionals: Vec::new(),
+        authority: None,
+        edns: None,
+    }
+  }
+
+/*
+Description:
+orders the addresses of an answer from a backend of operator-managed record sets (the record store, GeoDNS, failover and LDAP) by the rotation policies, if there are any.
+
+Parameters:
+records: the records of the answer.
+
+Returns:
+The records, in the order they are answered in.
+*/
+
+  fn rotate(&self, records: Vec<Record>) -> Vec<Record> {
+    match &self.rotation {
+        Some(rotation) => rotation.apply(records, &self.random),
+        None => records,
+    }
+  }
+
+/*
+Description:
+returns a handle to this handler for one shard of a sharded server. Everything is shared with the other shards except the request counter slot, which is the shard's own so shards on different cores don't contend on it.
+
+Parameters:
+shard: the shard's number.
+
+Returns:
+A Handler for the shard.
+*/
+
+  pub fn for_shard(&self, shard: usize) -> Self {
+    Handler {
+        counter: self.counter.for_shard(shard),
+        ..self.clone()
+    }
+  }
+
+/*
+Description:
+returns a client address as it may be logged: truncated to its network if anonymization is enabled.
+
+Parameters:
+ip: the client address.
+
+Returns:
+The address to log.
+*/
+
+  pub(crate) fn logged_ip(&self, ip: IpAddr) -> IpAddr {
+    self.anonymizer.as_ref().map_or(ip, |anonymizer| anonymizer.ip(ip))
+  }
+
+/*
+Description:
+returns a queried name as it may be logged: with its arguments hashed if qname hashing is enabled.
+
+Parameters:
+qname: the queried name.
+
+Returns:
+The name to log.
+*/
+
+  pub(crate) fn logged_qname(&self, qname: &str) -> String {
+    self.anonymizer.as_ref().map_or_else(|| qname.to_string(), |anonymizer| anonymizer.qname(qname))
+  }
+
+/*
+Description:
+decides whether a query should be written to the debug query log. Each zone logs one in every log_sample successful queries and one in every log_error_sample failed ones, falling back to --log-sample and logging every failure; the choice is random so busy zones don't fall into step with the sampling.
+
+Parameters:
+zone: the zone's keyword, "root" or "other".
+failed: whether the query was answered with an error.
+
+Returns:
+true if the query should be logged.
+*/
+
+  pub(crate) fn log_sampled(&self, zone: &str, failed: bool) -> bool {
+    let zone = self.config.zone(zone);
+    let rate = if failed {
+        zone.and_then(|zone| zone.log_error_sample).unwrap_or(1)
+    } else {
+        zone.and_then(|zone| zone.log_sample).unwrap_or(self.log_sample)
+    };
+    rate <= 1 || random::with_rng(|rng| rng.gen_ratio(1, rate.min(u32::MAX as u64) as u32))
+  }
+
+/*
+Description:
+extracts the arguments of a keyword zone query, i.e. the labels between the keyword and the root domain. Zones read the lowercased arguments from parsed_name; this is for the few that echo text back and so need the original name's case.
+
+Parameters:
+name: the query name, which must belong to a keyword zone: the lower-cased name, or the original name where case matters.
+
+Returns:
+A vector of the argument labels as strings, e.g. ["100", "usd", "eur"] for fx.100.usd.eur.mentisnovae.tech.
+*/
+
+  pub(crate) fn keyword_args(&self, name: impl Into<Name>) -> Vec<String> {
+    // Drop the keyword at the front and the labels of the root zone at the back.
+    zone_labels(&name.into(), 1, self.root_zone.num_labels())
+  }
+
+/*
+Description:
+handles a DNS request for the fx zone, converting an amount between two currencies. The query has the form fx.<amount>.<from>.<to>.<domain>, where "_" can be used as the decimal separator in the amount (e.g. fx.12_50.usd.eur). The rates come from the configured exchange-rate API and are cached on disk, and the answer says how fresh they are.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the conversion result, a FORMERR answer explaining a malformed query or an unknown currency, or a SERVFAIL answer if no exchange rates could be obtained.
+*/
+
+  async fn do_handle_request_fx<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Extract the amount, source currency and target currency from the query name, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let (amount, from, to) = match parse_fx_query(request.query().name(), &parsed.args) {
+        Ok(query) => query,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Convert the amount using the cached exchange rates. An unknown currency is a malformed question, while rates that can't be had are the server's failure.
+    let conversion = match self.fx.convert(amount, from, to).await {
+        Ok(conversion) => conversion,
+        Err(error @ fx::FxError::UnknownCurrency(_)) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &error.to_string()).await,
+        Err(error) => return self.do_handle_request_malformed(request, responder, ResponseCode::ServFail, &error.to_string()).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Create a TXT record with the conversion result and the freshness of the rates.
+    let rdata = RData::TXT(TXT::new(vec![format!(
+        "{:.2} {} = {:.2} {} (rate {:.6}, {})",
+        amount,
+        from.to_uppercase(),
+        conversion.converted,
+        to.to_uppercase(),
+        conversion.rate,
+        fx::freshness(&conversion),
+    )]));
+
+    // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+    self.respond(request, 60).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the lotto zone, drawing lottery numbers. The query has the form lotto.<count>.<max>.<domain> (e.g. lotto.6.49 for "6 of 49"), and the answer is a TXT record with count unique random numbers between 1 and max, sorted in ascending order.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the drawn numbers, or a FORMERR answer explaining why if the count or maximum are missing, out of range, or the count exceeds the maximum.
+*/
+
+  async fn do_handle_request_lotto<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Extract the count and maximum from the query name, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let (count, max) = match parse_lotto_query(request.query().name(), &parsed.args) {
+        Ok(query) => query,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Draw count unique numbers from 0..max, shift them to 1..=max and sort them.
+    let mut numbers: Vec<usize> = self.random.with_rng(|rng| rand::seq::index::sample(rng, max, count))
+        .into_iter()
+        .map(|number| number + 1)
+        .collect();
+    numbers.sort_unstable();
+
+    // Create a TXT record with the numbers separated by spaces.
+    let numbers: Vec<String> = numbers.iter().map(|number| number.to_string()).collect();
+    let rdata = RData::TXT(TXT::new(vec![numbers.join(" ")]));
+
+    // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+    self.respond(request, 60).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the rps zone, playing a game of rock-paper-scissors. The query has the form rps.<rock|paper|scissors>.<domain>. The server plays a random move, and the answer reports both moves, the winner, and the client's running tally, which is kept per source IP address.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the result of the game, or a FORMERR answer explaining why if the query does not contain a valid move.
+*/
+
+  async fn do_handle_request_rps<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Extract the client's move from the query name, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let client_move = match parse_rps_query(request.query().name(), &parsed.args) {
+        Ok(client_move) => client_move,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Play a random move and work out who won.
+    let server_move = Move::random(&self.random);
+    let outcome = rps::play(client_move, server_move);
+
+    // Record the outcome in the client's tally.
+    let tally = self.rps_stats.record(request.src().ip(), outcome);
+
+    // Describe the winner from the client's point of view.
+    let winner = match outcome {
+        Outcome::Win => "you",
+        Outcome::Loss => "me",
+        Outcome::Draw => "nobody",
+    };
+
+    // Create a TXT record with both moves, the winner, and the client's tally.
+    let rdata = RData::TXT(TXT::new(vec![format!(
+        "you: {client_move}, me: {server_move}, winner: {winner} (wins {}, losses {}, draws {})",
+        tally.wins, tally.losses, tally.draws,
+    )]));
+
+    // Answer with the record, which has a TTL of 0 seconds so every game is played afresh.
+    self.respond(request, 0).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the moon zone, returning the phase of the moon. A query for moon.<domain> returns the current phase, and moon.<YYYY-MM-DD>.<domain> returns the phase at noon UTC on the given date. The answer names the phase and gives the illuminated percentage of the disc.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the moon phase, or a FORMERR answer explaining why if the date cannot be parsed.
+*/
+
+  async fn do_handle_request_moon<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Work out the moment to compute the phase for: now, or noon on the requested date, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let at = match parse_moon_query(request.query().name(), &parsed.args) {
+        Ok(at) => at.unwrap_or_else(|| self.clock.now().naive_utc()),
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Compute the phase of the moon.
+    let phase = astronomy::moon_phase(at);
+
+    // Create a TXT record with the phase name, illumination and age of the moon.
+    let rdata = RData::TXT(TXT::new(vec![format!(
+        "{}, {:.1}% illuminated (day {:.1} of the lunar cycle)",
+        phase.name, phase.illumination, phase.age,
+    )]));
+
+    // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+    self.respond(request, 60).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the sun zone, returning today's sunrise and sunset for a location. The query has the form sun.<lat>.<lon>.<domain>, optionally followed by a UTC offset label to also get local times (e.g. sun.12_97.77_59.+0530). Coordinates use "_" as the decimal separator and are either signed or suffixed with n/s/e/w (e.g. sun.33_87s.151_21e). The times are computed locally from solar position formulas.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the sunrise and sunset times, or a FORMERR answer explaining why if the coordinates or offset cannot be parsed.
+*/
+
+  async fn do_handle_request_sun<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Extract the latitude, longitude and optional UTC offset from the query name, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let (latitude, longitude, offset) = match parse_sun_query(request.query().name(), &parsed.args) {
+        Ok(query) => query,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Compute today's sunrise and sunset and describe them.
+    let text = match astronomy::sun_times(self.clock.now().date_naive(), latitude, longitude) {
+        astronomy::SunTimes::RiseAndSet(sunrise, sunset) => {
+            let mut text = format!("sunrise {} UTC, sunset {} UTC", sunrise.format("%H:%M"), sunset.format("%H:%M"));
+            // Add the local times if an offset was given.
+            if let Some(offset) = offset {
+                let local = |time: NaiveDateTime| (time + chrono::Duration::seconds(offset.local_minus_utc() as i64)).format("%H:%M");
+                text.push_str(&format!(" (local {offset}: sunrise {}, sunset {})", local(sunrise), local(sunset)));
+            }
+            text
+        }
+        astronomy::SunTimes::PolarDay => "the sun does not set today (polar day)".to_string(),
+        astronomy::SunTimes::PolarNight => "the sun does not rise today (polar night)".to_string(),
+    };
+
+    // Create a TXT record with the sunrise and sunset times.
+    let rdata = RData::TXT(TXT::new(vec![text]));
+
+    // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+    self.respond(request, 60).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the week zone, returning the ISO week number, day of year and quarter of a date. A query for week.<domain> describes today (UTC), and week.<YYYY-MM-DD>.<domain> describes the given date.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the calendar facts, or a FORMERR answer explaining why if the date cannot be parsed.
+*/
+
+  async fn do_handle_request_week<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Work out the date to describe: today, or the date given in the query, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let date = match parse_week_query(request.query().name(), &parsed.args) {
+        Ok(date) => date.unwrap_or_else(|| self.clock.now().date_naive()),
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Compute the calendar facts for the date.
+    let info = calendar::date_info(date);
+
+    // Create a TXT record with the ISO week, day of year and quarter.
+    let rdata = RData::TXT(TXT::new(vec![format!(
+        "{}: ISO week {} of {} ({}), day {} of {}, Q{}",
+        date.format("%Y-%m-%d"),
+        info.iso_week,
+        info.iso_year,
+        date.format("%A"),
+        info.day_of_year,
+        info.days_in_year,
+        info.quarter,
+    )]));
+
+    // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+    self.respond(request, 60).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the puny and unpuny zones, converting internationalized domain names (IDNs) between their Unicode and ASCII (xn--) forms with the idna crate. puny.<unicode-label>.<domain> returns the xn-- form, and unpuny.<xn--label>.<domain> returns the Unicode form, which makes it easy to see what a suspicious homograph name really says. Names spanning several labels are converted label by label.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+to_ascii: true to convert to the xn-- form (puny), false to convert to Unicode (unpuny)
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the converted name, or a FORMERR answer explaining why if no name was given or it is not a valid IDN.
+*/
+
+  async fn do_handle_request_puny<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    to_ascii: bool,
+    ) -> Result<ResponseInfo, Error> {
+    // Extract the name to convert from the query, joining multiple labels back together.
+    let parsed = self.parsed_name(request.query().name());
+    let args = &parsed.args;
+    if args.is_empty() {
+        let explanation = format!("expected puny.<label> or unpuny.<label>, got {}", request.query().name());
+        return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await;
+    }
+    let text = args.join(".");
+
+    // Convert the name in the requested direction, explaining why a name that isn't a valid IDN can't be.
+    let converted = if to_ascii {
+        idna::domain_to_ascii(&text).map_err(|error| format!("cannot convert {text} to punycode: {error:?}"))
+    } else {
+        match idna::domain_to_unicode(&text) {
+            (unicode, Ok(())) => Ok(unicode),
+            (_, Err(error)) => Err(format!("cannot decode {text}: {error:?}")),
+        }
+    };
+    let converted = match converted {
+        Ok(converted) => converted,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Create a TXT record with the converted name.
+    let rdata = RData::TXT(TXT::new(vec![converted]));
+
+    // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+    self.respond(request, 60).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the luhn zone, validating a number with the Luhn algorithm. The query has the form luhn.<digits>.<domain>, and the answer says whether the number is valid and which check digit it should end with.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the validation result, or a FORMERR answer explaining why if the query does not contain a number.
+*/
+
+  async fn do_handle_request_luhn<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Extract the number from the query name and validate it, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let result = match parsed.args.as_slice() {
+        [digits] => checksum::luhn(digits).ok_or_else(|| format!("{digits} is not a number with at least two digits")),
+        _ => Err(format!("expected luhn.<digits>, got {}", request.query().name())),
+    };
+    let result = match result {
+        Ok(result) => result,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Describe the result.
+    let text = if result.valid {
+        format!("valid (check digit {})", result.expected)
+    } else {
+        format!("invalid (expected check digit {})", result.expected)
+    };
+
+    // Create a TXT record with the validation result.
+    let rdata = RData::TXT(TXT::new(vec![text]));
+
+    // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+    self.respond(request, 60).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the crc32 zone, returning the CRC-32 checksum of some text. The query has the form crc32.<text>.<domain>; text spanning several labels is joined back together with dots, and its case is preserved since it changes the checksum.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the checksum, or a FORMERR answer explaining why if no text was given.
+*/
+
+  async fn do_handle_request_crc32<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Extract the text from the query name as the client sent it.
+    let args = self.keyword_args(request.query().original().name().clone());
+    if args.is_empty() {
+        let explanation = format!("expected crc32.<text>, got {}", request.query().name());
+        return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await;
+    }
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Compute the checksum of the text, joining multiple labels back together.
+    let crc = checksum::crc32(&args.join("."));
+
+    // Create a TXT record with the checksum in hexadecimal and decimal.
+    let rdata = RData::TXT(TXT::new(vec![format!("{crc:08x} ({crc})")]));
+
+    // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+    self.respond(request, 60).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the fib and fact zones, which compute the n-th Fibonacci number and n factorial with big integers. The query has the form fib.<n>.<domain> or fact.<n>.<domain>. n is capped so that a query cannot tie up the server, and long results are split across several strings of the TXT record, since a single TXT string holds at most 255 characters.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+keyword: the keyword of the zone, used in error messages
+max_n: the largest n the zone accepts
+compute: the function computing the n-th value of the sequence
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the computed number, or a FORMERR answer explaining why if n is missing, not a number, or above the cap.
+*/
+
+  async fn do_handle_request_sequence<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    keyword: &str,
+    max_n: u32,
+    compute: fn(u32) -> num_bigint::BigUint,
+    ) -> Result<ResponseInfo, Error> {
+    // Extract n from the query name and check it against the cap, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let n = match parsed.args.as_slice() {
+        [n] => n
+            .parse::<u32>()
+            .ok()
+            .filter(|n| *n <= max_n)
+            .ok_or_else(|| format!("{keyword}: n must be a number between 0 and {max_n}")),
+        _ => Err(format!("expected {keyword}.<n>, got {}", request.query().name())),
+    };
+    let n = match n {
+        Ok(n) => n,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Compute the number and split its digits into TXT-sized strings.
+    let digits = compute(n).to_string();
+    let strings = txt_strings(&digits);
+
+    // Create a TXT record holding all the strings; clients concatenate them to get the full number.
+    let rdata = RData::TXT(TXT::new(strings));
+
+    // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+    self.respond(request, 60).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the age zone, returning the exact age of someone born on the given date. The query has the form age.<YYYY-MM-DD>.<domain>, and the answer gives the age in years, months and days (as of today, UTC) and the number of days until the next birthday.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the age, or a FORMERR answer explaining why if the date cannot be parsed or is in the future.
+*/
+
+  async fn do_handle_request_age<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Extract the birth date from the query name and compute the age as of today, explaining what is wrong with a malformed query or a date in the future.
+    let parsed = self.parsed_name(request.query().name());
+    let age = match parsed.args.as_slice() {
+        [date] => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| format!("invalid date {date}, expected YYYY-MM-DD"))
+            .and_then(|birth| calendar::age(birth, self.clock.now().date_naive()).ok_or_else(|| format!("{birth} is in the future"))),
+        _ => Err(format!("expected age.<YYYY-MM-DD>, got {}", request.query().name())),
+    };
+    let age = match age {
+        Ok(age) => age,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Describe the age and the next birthday.
+    let birthday = match age.days_until_birthday {
+        0 => "happy birthday!".to_string(),
+        1 => "next birthday tomorrow".to_string(),
+        days => format!("next birthday in {days} days"),
+    };
+    let text = format!("{} years, {} months, {} days ({birthday})", age.years, age.months, age.days);
+
+    // Create a TXT record with the age.
+    let rdata = RData::TXT(TXT::new(vec![text]));
+
+    // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+    self.respond(request, 60).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the ulid zone. A query for ulid.<domain> returns a freshly generated ULID (a sortable, timestamp-prefixed unique identifier), and ulid.<existing-ulid>.<domain> decodes the timestamp embedded in the given ULID. Generated ULIDs are answered with a TTL of 0 so resolvers never hand out the same one twice.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the new ULID or decoded timestamp, or a FORMERR answer explaining why if the given ULID is invalid.
+*/
+
+  async fn do_handle_request_ulid<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Generate a new ULID, or decode the timestamp of the one in the query, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let answer = match parsed.args.as_slice() {
+        [] => {
+            // Build the ULID from the current time and 80 random bits, drawn like the other random zones.
+            let millis = self.clock.now().timestamp_millis().max(0) as u64;
+            let bits = self.random.with_rng(|rng| (u128::from(rng.next_u64()) << 64) | u128::from(rng.next_u64()));
+            Ok((ulid::Ulid::from_parts(millis, bits).to_string(), 0))
+        }
+        [encoded] => decode_ulid(encoded).map(|text| (text, 60)),
+        _ => Err(format!("expected ulid or ulid.<ulid>, got {}", request.query().name())),
+    };
+    let (text, ttl) = match answer {
+        Ok(answer) => answer,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Create a TXT record with the ULID or decoded timestamp.
+    let rdata = RData::TXT(TXT::new(vec![text]));
+
+    // Answer with the record, which has the TTL picked above unless the zone is configured with another.
+    self.respond(request, ttl).answer(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the health zone, so monitoring can check the server with a plain DNS query. health.<domain> answers "ok" followed by the uptime, the number of requests served and the server version, one TXT record each, with a TTL of 0 so resolvers never answer a probe from their cache.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the health records, or a FORMERR answer explaining why if the query has arguments.
+*/
+
+  async fn do_handle_request_health<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // The health zone takes no arguments.
+    if !self.parsed_name(request.query().name()).args.is_empty() {
+        let explanation = format!("expected health, got {}", request.query().name());
+        return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await;
+    }
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+    let queries = self.counter.load();
+
+    // Create one TXT record for the status and each piece of liveness information.
+    let report = self.health.report(queries);
+    let rdata = [
+        "ok".to_string(),
+        format!("uptime={}s", report.uptime),
+        format!("queries={}", report.queries),
+        format!("version={}", report.version),
+    ]
+    .into_iter()
+    .map(|text| RData::TXT(TXT::new(vec![text])));
+
+    // Answer with the records.
+    self.respond(request, 0).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the name zone, generating Docker-style random names such as "brave-turing" from embedded word lists. name.<domain> returns one name, and name.<n>.<domain> returns n names, one TXT record each, which makes it easy to name ephemeral machines from provisioning scripts. Names are answered with a TTL of 0 so resolvers don't hand out the same names twice.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the generated names, or a FORMERR answer explaining why if the requested count is not a number between 1 and MAX_NAMES.
+*/
+
+  async fn do_handle_request_name<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Work out how many names to generate, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let count = match parsed.args.as_slice() {
+        [] => Ok(1),
+        [count] => count
+            .parse::<usize>()
+            .ok()
+            .filter(|count| (1..=MAX_NAMES).contains(count))
+            .ok_or_else(|| format!("name count must be between 1 and {MAX_NAMES}")),
+        _ => Err(format!("expected name or name.<n>, got {}", request.query().name())),
+    };
+    let count = match count {
+        Ok(count) => count,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Create one TXT record per generated name.
+    let rdata = (0..count).map(|_| RData::TXT(TXT::new(vec![names::random_name(&self.random)])));
+
+    // Answer with the records.
+    self.respond(request, 0).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the top zone, listing the busiest clients to help spot abusive ones. top.<domain> returns the 10 busiest clients and top.<n>.<domain> returns the n busiest (up to 20), one TXT record each with the client's query count, when it was last seen and its most queried names.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the busiest clients, or a FORMERR answer explaining why if the requested count is not a number between 1 and TOP_MAX_CLIENTS.
+*/
+
+  async fn do_handle_request_top<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Work out how many clients to list, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let count = match parsed.args.as_slice() {
+        [] => Ok(TOP_DEFAULT_CLIENTS),
+        [count] => count
+            .parse::<usize>()
+            .ok()
+            .filter(|count| (1..=TOP_MAX_CLIENTS).contains(count))
+            .ok_or_else(|| format!("client count must be between 1 and {TOP_MAX_CLIENTS}")),
+        _ => Err(format!("expected top or top.<n>, got {}", request.query().name())),
+    };
+    let count = match count {
+        Ok(count) => count,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Create one TXT record per client; the statistics change with every query, so they are not cached.
+    let clients = self.clients.top(count);
+    let rdata = clients.iter().map(|client| RData::TXT(TXT::new(txt_strings(&client.to_string()))));
+
+    // Answer with the records.
+    self.respond(request, 0).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the stats zone, listing the heavy hitters of the last few minutes from the top-talker sketches. stats.<domain> returns the 5 busiest clients and the 5 most queried names, stats.clients.<domain> and stats.qnames.<domain> return just one list, and stats.clients.<n>.<domain> or stats.qnames.<n>.<domain> return the top n (up to 20). The first TXT record gives the window, and each further record an item with its estimated count and the possible overestimate.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the top talkers, or a FORMERR answer explaining why if the list or count is invalid.
+*/
+
+  async fn do_handle_request_stats<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Formats a top-k entry as a TXT line, e.g. "clients: 192.0.2.7 1532 (+-12)".
+    fn line<K: std::fmt::Display>(list: &str, entry: &TopEntry<K>) -> String {
+        format!("{list}: {} {} (+-{})", entry.item, entry.count, entry.error)
+    }
+
+    // Work out which lists to return and how long they are, explaining what is wrong with a malformed query.
+    let parsed = self.parsed_name(request.query().name());
+    let parse_count = |count: &String| {
+        count
+            .parse::<usize>()
+            .ok()
+            .filter(|count| (1..=TOP_MAX_CLIENTS).contains(count))
+            .ok_or_else(|| format!("stats count must be between 1 and {TOP_MAX_CLIENTS}"))
+    };
+    let query = match parsed.args.as_slice() {
+        [] => Ok((None, STATS_DEFAULT_ENTRIES)),
+        [list] => Ok((Some(list.as_str()), STATS_DEFAULT_ENTRIES)),
+        [list, count] => parse_count(count).map(|count| (Some(list.as_str()), count)),
+        _ => Err(format!("expected stats[.clients|.qnames[.<n>]], got {}", request.query().name())),
+    };
+    let query = query.and_then(|(list, count)| match list {
+        None | Some("clients") | Some("qnames") => Ok((list, count)),
+        Some(list) => Err(format!("unknown stats list {list}, expected clients or qnames")),
+    });
+    let (list, count) = match query {
+        Ok(query) => query,
+        Err(explanation) => return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await,
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Collect the lines, starting with the window.
+    let mut lines = vec![format!("window={}s", self.talkers.window().as_secs())];
+    match list {
+        None => {
+            lines.extend(self.talkers.top_clients(count).iter().map(|entry| line("clients", entry)));
+            lines.extend(self.talkers.top_qnames(count).iter().map(|entry| line("qnames", entry)));
+        }
+        Some("clients") => lines.extend(self.talkers.top_clients(count).iter().map(|entry| line("clients", entry))),
+        // The list was checked above, so anything else is the qnames list.
+        Some(_) => lines.extend(self.talkers.top_qnames(count).iter().map(|entry| line("qnames", entry))),
+    }
+
+    // Create one TXT record per line; the sketches change with every query, so they are not cached.
+    let rdata = lines.iter().map(|line| RData::TXT(TXT::new(txt_strings(line))));
+
+    // Answer with the records.
+    self.respond(request, 0).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the acme zone, serving the ACME DNS-01 challenge tokens published through the ACME API: acme.<subdomain>.<domain> returns one TXT record per live token of the account owning <subdomain>, and no records between challenges. Names of unregistered subdomains, and every name while the ACME API is off, do not exist.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the tokens if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_acme<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Look up the tokens of the subdomain, if it belongs to an account.
+    let parsed = self.parsed_name(request.query().name());
+    let tokens = match (&self.acme, parsed.args.as_slice()) {
+        (Some(acme), [subdomain]) => acme.tokens(subdomain),
+        _ => None,
+    };
+    let Some(tokens) = tokens else {
+        return self.do_handle_request_default(request, responder).await;
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Create one TXT record per token, with a TTL short enough that a new token is seen by the next validation.
+    let rdata = tokens.iter().map(|token| RData::TXT(TXT::new(vec![token.clone()])));
+
+    // Answer with the records.
+    self.respond(request, acme::TOKEN_TTL).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the trace zone, tracing the lookup of a name the way dig +trace does: trace.<name>.<domain> asks the root servers for the A records of <name>, follows their referrals down to the name's own servers and answers with one TXT string per query sent, giving the zone asked about, the server, how long it took and what it answered, after a summary. Each trace sends queries across the Internet and may take seconds, so the zone is off unless enabled, only a few traces run at once and further ones are refused, and the answer is never cached. Every name while the zone is off does not exist.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the trace if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_trace<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    let Some(tracer) = &self.tracer else {
+        return self.do_handle_request_default(request, responder).await;
+    };
+
+    // The labels between the keyword and the domain are the name to trace.
+    let parsed = self.parsed_name(request.query().name());
+    let name = match parsed.args.is_empty() {
+        true => None,
+        false => Name::from_str(&format!("{}.", parsed.args.join("."))).ok(),
+    };
+    let Some(name) = name else {
+        return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, "expected trace.<name>.<domain>").await;
+    };
+
+    // Refuse the query if too many traces are running already.
+    let Some(trace) = tracer.trace(&name, RecordType::A).await else {
+        return self.refuse(request, responder).await;
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Create one TXT record per line of the trace.
+    let rdata = trace.lines(&name, RecordType::A).into_iter().map(|line| RData::TXT(TXT::new(vec![line])));
+
+    // Answer with the records, which are stale as soon as they are sent.
+    self.respond(request, 0).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the dyn zone, serving the hosts kept up to date through the DynDNS update API: <host>.dyn.<domain> returns the host's IPv4 address for A queries and its IPv6 address for AAAA queries, and no records for other types or an address the host hasn't been given. Names of unknown hosts, and every name while the DynDNS API is off, do not exist.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the host's address if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_ddns<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Look up the host named by the label right below the zone.
+    let name = request.query().name();
+    let parsed = self.parsed_name(name);
+    let addresses = match (&self.ddns, parsed.args.as_slice()) {
+        (Some(ddns), [host]) => ddns.addresses(host),
+        _ => None,
+    };
+    let Some(addresses) = addresses else {
+        return self.do_handle_request_default(request, responder).await;
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Answer the address of the type asked for, if the host has one.
+    let rdata = match (request.query().query_type(), addresses) {
+        (RecordType::A, (Some(ipv4), _)) => Some(RData::A(ipv4)),
+        (RecordType::AAAA, (_, Some(ipv6))) => Some(RData::AAAA(ipv6)),
+        _ => None,
+    };
+
+    // Answer with the record, if there is one.
+    self.respond(request, ddns::TTL).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the docker zone, serving the addresses of the local Docker engine's running containers: <name>.docker.<domain> returns one A record per IPv4 address and one AAAA record per IPv6 address of the containers with that container name, Compose service name or <service>.<project>, and no records for other types. Names no running container has, and every name while no Docker socket is watched, do not exist.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the containers' addresses if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_docker<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Look up the containers named by the labels below the zone.
+    let name = request.query().name();
+    let parsed = self.parsed_name(name);
+    let labels = &parsed.args;
+    let addresses = match &self.docker {
+        Some(docker) if !labels.is_empty() => docker.addresses(&labels.join(".")),
+        _ => Vec::new(),
+    };
+    if addresses.is_empty() {
+        return self.do_handle_request_default(request, responder).await;
+    }
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Answer the addresses of the type asked for.
+    let query_type = request.query().query_type();
+    let rdata = addresses.into_iter().filter_map(|address| match (query_type, address) {
+        (RecordType::A, IpAddr::V4(ipv4)) => Some(RData::A(ipv4)),
+        (RecordType::AAAA, IpAddr::V6(ipv6)) => Some(RData::AAAA(ipv6)),
+        _ => None,
+    });
+
+    // Answer with the records.
+    self.respond(request, docker::TTL).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the registry zone, serving the records read from Consul or etcd: <name>.<label>.<domain> returns one A or AAAA record per address of the name's instances, and for SRV queries one SRV record per instance with a port, whose targets' addresses are added to the additional section. Names the backend doesn't have, and every name while no backend is configured, do not exist.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the name's records if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_registry<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Look up the instances of the labels below the zone.
+    let name = request.query().name();
+    let parsed = self.parsed_name(name);
+    let labels = &parsed.args;
+    let instances = match &self.registry {
+        Some(registry) if !labels.is_empty() => registry.instances(&labels.join(".")),
+        _ => Vec::new(),
+    };
+    if instances.is_empty() {
+        return self.do_handle_request_default(request, responder).await;
+    }
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Turns an address into the data of an A or AAAA record, if it is of the type asked for (or of either type for SRV targets).
+    let address_rdata = |address: IpAddr, query_type: RecordType| match (query_type, address) {
+        (RecordType::A | RecordType::SRV, IpAddr::V4(ipv4)) => Some(RData::A(ipv4)),
+        (RecordType::AAAA | RecordType::SRV, IpAddr::V6(ipv6)) => Some(RData::AAAA(ipv6)),
+        _ => None,
+    };
+
+    // Answer the addresses, or the SRV records and their targets' addresses.
+    let query_type = request.query().query_type();
+    let zone = Name::from(&self.registry_zone);
+    let mut response = self.respond(request, registry::TTL);
+    let mut additionals = Vec::new();
+    for instance in &instances {
+        if query_type != RecordType::SRV {
+            response = response.answers(address_rdata(instance.address, query_type));
+            continue;
+        }
+        let (Some(port), Ok(target)) = (instance.port, Name::from_str(&instance.target).and_then(|target| target.append_domain(&zone))) else {
+            continue;
+        };
+        response = response.answer(RData::SRV(SRV::new(1, 1, port, target.clone())));
+        let registry = self.registry.as_ref().unwrap();
+        for target_instance in registry.instances(&instance.target) {
+            let Some(rdata) = address_rdata(target_instance.address, RecordType::SRV) else {
+                continue;
+            };
+            if !additionals.contains(&(target.clone(), rdata.clone())) {
+                additionals.push((target.clone(), rdata));
+            }
+        }
+    }
+    for (target, rdata) in additionals {
+        response = response.additional(target, rdata);
+    }
+
+    // Send the answer.
+    response.send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the hosts zone, serving the entries of the hosts files: <name>.hosts.<domain> returns one A record per IPv4 address and one AAAA record per IPv6 address the files give the name, and no records for other types. Names the files don't have, and every name while no hosts file is given, do not exist.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the name's addresses if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_hosts<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Look up the addresses of the labels below the zone.
+    let name = request.query().name();
+    let parsed = self.parsed_name(name);
+    let labels = &parsed.args;
+    let addresses = match &self.hosts {
+        Some(hosts) if !labels.is_empty() => hosts.addresses(&labels.join(".")),
+        _ => Vec::new(),
+    };
+    if addresses.is_empty() {
+        return self.do_handle_request_default(request, responder).await;
+    }
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Answer the addresses of the type asked for.
+    let query_type = request.query().query_type();
+    let rdata = addresses.into_iter().filter_map(|address| match (query_type, address) {
+        (RecordType::A, IpAddr::V4(ipv4)) => Some(RData::A(ipv4)),
+        (RecordType::AAAA, IpAddr::V6(ipv6)) => Some(RData::AAAA(ipv6)),
+        _ => None,
+    });
+
+    // Answer with the records.
+    self.respond(request, hostsfile::TTL).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the wg zone, serving the peers of a WireGuard interface: <peer>.wg.<domain> returns one A record per IPv4 address and one AAAA record per IPv6 address among the peer's single-host allowed IPs, and no records for other types. Names no peer has, and every name while no WireGuard configuration or interface is given, do not exist.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the peer's addresses if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_wireguard<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Look up the addresses of the peer named by the labels below the zone.
+    let name = request.query().name();
+    let parsed = self.parsed_name(name);
+    let labels = &parsed.args;
+    let addresses = match &self.wireguard {
+        Some(wireguard) if !labels.is_empty() => wireguard.addresses(&labels.join(".")),
+        _ => Vec::new(),
+    };
+    if addresses.is_empty() {
+        return self.do_handle_request_default(request, responder).await;
+    }
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Answer the addresses of the type asked for.
+    let query_type = request.query().query_type();
+    let rdata = addresses.into_iter().filter_map(|address| match (query_type, address) {
+        (RecordType::A, IpAddr::V4(ipv4)) => Some(RData::A(ipv4)),
+        (RecordType::AAAA, IpAddr::V6(ipv6)) => Some(RData::AAAA(ipv6)),
+        _ => None,
+    });
+
+    // Answer with the records.
+    self.respond(request, wireguard::TTL).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the lan zone, serving the devices discovered on the local network: <name>.lan.<domain> returns one A record per IPv4 address and one AAAA record per IPv6 address of the device announcing <name>.local over mDNS, or of the device with the MAC address <name> in the ARP table, and no records for other types. Names no device has, and every name while LAN discovery is off, do not exist.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the device's addresses if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_lan<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Look up the addresses of the device named by the labels below the zone.
+    let name = request.query().name();
+    let parsed = self.parsed_name(name);
+    let labels = &parsed.args;
+    let addresses = match &self.lan {
+        Some(lan) if !labels.is_empty() => lan.addresses(&labels.join(".")),
+        _ => Vec::new(),
+    };
+    if addresses.is_empty() {
+        return self.do_handle_request_default(request, responder).await;
+    }
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Answer the addresses of the type asked for.
+    let query_type = request.query().query_type();
+    let rdata = addresses.into_iter().filter_map(|address| match (query_type, address) {
+        (RecordType::A, IpAddr::V4(ipv4)) => Some(RData::A(ipv4)),
+        (RecordType::AAAA, IpAddr::V6(ipv6)) => Some(RData::AAAA(ipv6)),
+        _ => None,
+    });
+
+    // Answer with the records.
+    self.respond(request, lan::TTL).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for a reverse name in in-addr.arpa or ip6.arpa, answering PTR queries for the addresses of the hosts files with the names they give the address, canonical name first, as <name>.hosts.<domain>, followed by the names of the LAN devices discovered with the address, as <name>.lan.<domain>. Other types get no records, and addresses neither has do not exist.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the address's names if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_reverse<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Look up the names of the address the query name is the reverse name of, with the zone each is served in.
+    let name = request.query().name();
+    let mut names = Vec::new();
+    if let Some(address) = hostsfile::reverse_address(&name.to_string()) {
+        if let Some(hosts) = &self.hosts {
+            names.extend(hosts.names_of(address).into_iter().map(|host| (host, &self.hosts_zone)));
+        }
+        if let Some(lan) = &self.lan {
+            names.extend(lan.names_of(address).into_iter().map(|device| (device, &self.lan_zone)));
+        }
+    }
+    if names.is_empty() {
+        return self.do_handle_request_default(request, responder).await;
+    }
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Answer the names below their zones, if PTR records were asked for.
+    let rdata: Vec<RData> = match request.query().query_type() {
+        RecordType::PTR => names
+            .iter()
+            .filter_map(|(host, zone)| Name::from_str(host).and_then(|host| host.append_domain(&Name::from(*zone))).ok())
+            .map(RData::PTR)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    // Answer with the records.
+    self.respond(request, hostsfile::TTL).answers(rdata).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for a name the record store serves, answering the records backends such as PostgreSQL hold for it as they are, after following CNAME records within the store. A name without records of the type gets an empty answer, and a name that doesn't exist in a zone of the store gets NXDOMAIN, both with the zone's SOA record in the authority section.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the name's records if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_records<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Look up the records, leaving names the store stopped serving since dispatch to the default zone.
+    let name = request.query().name();
+    let lookup = self.records.as_ref().and_then(|records| records.lookup(name, request.query().query_type()));
+    let Some(lookup) = lookup else {
+        return self.do_handle_request_default(request, responder).await;
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Answer the records as they are stored, or the zone's SOA record if there are none.
+    let response = self.respond(request, 0);
+    let response = match lookup {
+        Lookup::Answer(records) => response.records(self.rotate(records)),
+        Lookup::NoData(soa) => response.authority(soa),
+        Lookup::NxDomain(soa) => response.response_code(ResponseCode::NXDomain).authority(Some(soa)),
+    };
+
+    // Send the answer.
+    response.send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for a name GeoDNS answers, with the answer set of the client's location: that of the subnet in the query's EDNS Client Subnet option if it has one, as resolvers send on behalf of their clients, else that of the client's own address. The answer then carries the option back with the subnet as its scope. Types without answer sets are left to the record store if it serves the name, and get an empty answer otherwise.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the answer set if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_geo<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Locate the client, or the subnet the query is made for.
+    let subnet = geodns::client_subnet(request);
+    let location = self.config.locate(subnet.map_or(request.src().ip(), |(address, _)| address));
+
+    // Pick the answer set, leaving types without one to the record store.
+    let name = request.query().name();
+    let answer = self.geo.as_ref().and_then(|geo| geo.answer(name, request.query().query_type(), &location));
+    if answer.is_none() && self.records.as_ref().is_some_and(|records| records.serves(name)) {
+        return self.do_handle_request_records(request, responder).await;
+    }
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Answer the records as they are configured, echoing the client subnet with its scope.
+    let mut response = self.respond(request, 0).records(self.rotate(answer.unwrap_or_default()));
+    if let (Some((address, prefix)), Some(edns)) = (subnet, request.edns()) {
+        let mut response_edns = Edns::new();
+        response_edns.set_max_payload(edns.max_payload().max(512));
+        response_edns.options_mut().insert(geodns::subnet_option(address, prefix));
+        response = response.edns(response_edns);
+    }
+
+    // Send the answer.
+    response.send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for a name in a subzone delegated to an HTTP backend, with the records the backend answers for the name and type, from the delegation's cache while they are fresh. A name the backend says doesn't exist gets NXDOMAIN, and a backend that can't be reached, fails or answers something that isn't valid records gets SERVFAIL, unless it answered the question before.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the backend's records if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_delegation<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Ask the backend, leaving names no longer delegated since dispatch to the default zone.
+    let name = request.query().name();
+    let answer = match &self.delegation {
+        Some(delegation) => delegation.answer(name, request.query().query_type()).await,
+        None => None,
+    };
+    let Some(answer) = answer else {
+        return self.do_handle_request_default(request, responder).await;
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Answer the backend's records as they are, or say why there are none.
+    let response = self.respond(request, 0);
+    let response = match answer {
+        Ok(DelegatedAnswer::Records(records)) => response.records(self.rotate(records)),
+        Ok(DelegatedAnswer::NxDomain) => response.response_code(ResponseCode::NXDomain),
+        Err(error) => {
+            // A backend being left alone was warned about once, when it was.
+            match error {
+                DelegationError::CircuitOpen(_) => debug!("Not asking the delegation backend about {name}: {error}"),
+                _ => warn!("Failed to ask the delegation backend about {name}: {error}"),
+            }
+            response.authoritative(false).response_code(ResponseCode::ServFail)
+        }
+    };
+
+    // Send the answer.
+    response.send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for a name failover answers, with the addresses of its targets that pass their health checks. Types without targets are left to the record store if it serves the name, and get an empty answer otherwise.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the healthy targets if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_failover<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Pick the targets that are up, leaving types without targets to the record store.
+    let name = request.query().name();
+    let answer = self.failover.as_ref().and_then(|failover| failover.answer(name, request.query().query_type()));
+    if answer.is_none() && self.records.as_ref().is_some_and(|records| records.serves(name)) {
+        return self.do_handle_request_records(request, responder).await;
+    }
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Answer the targets' records as they are configured.
+    self.respond(request, 0).records(self.rotate(answer.unwrap_or_default())).send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the ENUM zone, serving telephone numbers under the names of their reversed digits: a NAPTR query for a number's name returns a NAPTR record per URI it maps to, and other types get an empty answer. The zone itself and the names on the way to a number exist without records, and every other name gets NXDOMAIN.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the number's records if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_enum<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    let Some(e164) = &self.e164 else {
+        return self.do_handle_request_default(request, responder).await;
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Look the number up, answering its records only to NAPTR queries.
+    let response = self.respond(request, 0);
+    let response = match e164.lookup(request.query().name()) {
+        Some(records) if request.query().query_type() == RecordType::NAPTR => response.records(records),
+        Some(_) => response,
+        None => response.response_code(ResponseCode::NXDomain),
+    };
+
+    // Send the answer.
+    response.send(responder).await
+  }
+
+/*
+Description:
+handles a DNS request for the LDAP zone, serving the hosts of the directory: <host>.<zone> returns one A record per IPv4 address and one AAAA record per IPv6 address of the host's entries, and no records for other types. Hosts the directory has no entry for do not exist, and a directory that can't be searched gets SERVFAIL unless the host's addresses are cached.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the host's records if the response is sent, or an Error if sending it fails.
+*/
+
+  #[cfg(feature = "ldap")]
+  async fn do_handle_request_ldap<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    let Some(ldap) = &self.ldap else {
+        return self.do_handle_request_default(request, responder).await;
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Look the host up, the zone itself being a name without addresses.
+    let name = request.query().name();
+    let addresses = match ldap.host(name) {
+        Some(host) => ldap.addresses(&host).await,
+        None => Ok(Some(Vec::new())),
+    };
+    let response = self.respond(request, 0);
+    let (response, addresses) = match addresses {
+        Ok(Some(addresses)) => (response, addresses),
+        Ok(None) => (response.response_code(ResponseCode::NXDomain), Vec::new()),
+        Err(error) => {
+            warn!("Failed to look {name} up in LDAP: {error}");
+            (response.authoritative(false).response_code(ResponseCode::ServFail), Vec::new())
+        }
+    };
+
+    // Answer the addresses of the type asked for, with the TTL of the directory's hosts.
+    let query_type = request.query().query_type();
+    let records: Vec<Record> = addresses
+        .into_iter()
+        .filter_map(|address| match (query_type, address) {
+            (RecordType::A, IpAddr::V4(ipv4)) => Some(RData::A(ipv4)),
+            (RecordType::AAAA, IpAddr::V6(ipv6)) => Some(RData::AAAA(ipv6)),
+            _ => None,
+        })
+        .map(|rdata| Record::from_rdata(Name::from(name), ldap.ttl(), rdata))
+        .collect();
+    response.records(self.rotate(records)).send(responder).await
+  }
+
+/*
+Description:
+picks the access control list that applies to a query: dynamic updates and zone transfers have their own lists, everything else is an ordinary query.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+op_code: the op code of the message
+query_type: the query type
+
+Returns:
+(&Acl, &'static str): the access control list to check the client against, and what it is the list for.
+*/
+
+  fn acl_for(&self, op_code: OpCode, query_type: RecordType) -> (&Acl, &'static str) {
+    if op_code == OpCode::Update {
+        (&self.update_acl, "update")
+    } else if matches!(query_type, RecordType::AXFR | RecordType::IXFR) {
+        (&self.transfer_acl, "zone transfer")
+    } else {
+        (&self.query_acl, "query")
+    }
+  }
+
+/*
+Description:
+answers a query that doesn't have exactly one question with FORMERR and nothing else, since there is no single question to explain the error under. Queries read off the wire never get here: trust-dns fails to parse them and answers them with FORMERR itself.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+mut responder: a mutable reference to a ResponseHandler trait object that will handle the DNS response
+violation: how the query breaks the limits, for the log
+
+Returns:
+ResponseInfo: the ResponseInfo of the answer, or of a SERVFAIL header if sending it failed.
+*/
+
+  async fn reject_question_count<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    mut responder: R,
+    violation: QueryViolation,
+    ) -> ResponseInfo {
+    debug!("Rejecting query from {}: {violation}", self.logged_ip(request.src().ip()));
+    self.counter.add(1);
+
+    // Create a header for the response message with the FORMERR response code, and send it without any records.
+    let builder = MessageResponseBuilder::from_message_request(request);
+    let mut header = response_header(request.header());
+    header.set_response_code(ResponseCode::FormErr);
+    match responder.send_response(builder.build_no_records(header)).await {
+        Ok(info) => info,
+        Err(error) => {
+            error!("Error in RequestHandler: {error}");
+            header.set_response_code(ResponseCode::ServFail);
+            header.into()
+        }
+    }
+  }
+
+/*
+Description:
+answers a request with REFUSED, used when the client is not allowed to make it.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct if the response is sent, or an Error if sending failed.
+*/
+
+  async fn refuse<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    debug!("Refusing {:?} request from {}", request.op_code(), self.logged_ip(request.src().ip()));
+
+    // Send an answer with the REFUSED response code and without any records.
+    self.respond(request, 0).authoritative(false).response_code(ResponseCode::Refused).send(responder).await
+  }
+
+/*
+Description:
+answers a query with a fault picked by chaos mode instead of its answer: SERVFAIL, an empty answer with TC set, or an authoritative NOERROR answer holding an A record for a name that matches no question. Delays are injected by do_handle_request, which answers normally after them.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+fault: the fault to answer with
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct if the response is sent, or an Error if sending failed.
+*/
+
+  async fn do_handle_request_chaos<R: ResponseHandler>(
+    &self,
+    fault: Fault,
+    request: &Request,
+    responder: R,
+  ) -> Result<ResponseInfo, Error> {
+    debug!("Chaos mode: answering {} with {fault}", self.logged_ip(request.src().ip()));
+
+    let response = self.respond(request, 0).authoritative(false);
+    let response = match fault {
+        Fault::Truncate => response.truncated(true),
+        Fault::Malformed => {
+            let record = Record::from_rdata(Name::from_ascii(CHAOS_NAME).unwrap(), 0, RData::A(CHAOS_ADDRESS));
+            response.authoritative(true).records([record])
+        }
+        Fault::Servfail | Fault::Delay => response.response_code(ResponseCode::ServFail),
+    };
+    response.send(responder).await
+  }
+
+/*
+Description:
+answers a query for a blocked name, either with NXDOMAIN or with the null address (0.0.0.0 for A queries, :: for AAAA queries and an empty answer for anything else), depending on the blocklist configuration. The block is counted in the per-client block statistics.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+blocklist: the blocklist that matched the name
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct if the response is sent, or an Error if sending failed.
+*/
+
+  async fn do_handle_request_blocked<R: ResponseHandler>(
+    &self,
+    blocklist: &Blocklist,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Count the block for the client.
+    blocklist.record(request.src().ip());
+
+    // Answer with NXDOMAIN, or with the null address for address queries, without claiming authority over the name.
+    let response = self.respond(request, 60).authoritative(false);
+    let (response, response_code) = match blocklist.response() {
+        BlockResponse::Nxdomain => (response.response_code(ResponseCode::NXDomain), ResponseCode::NXDomain),
+        BlockResponse::Null => {
+            let rdata = match request.query().query_type() {
+                RecordType::A => Some(RData::A(Ipv4Addr::UNSPECIFIED)),
+                RecordType::AAAA => Some(RData::AAAA(Ipv6Addr::UNSPECIFIED)),
+                _ => None,
+            };
+            (response.answers(rdata), ResponseCode::NoError)
+        }
+    };
+
+    // Report the block to the webhooks that want blocked events.
+    if let Some(webhooks) = &self.webhooks {
+        let qname = request.query().name().to_string();
+        webhooks.notify(WebhookEvent::Blocked, qname.trim_end_matches('.'), || {
+            Event::query(
+                self.logged_ip(request.src().ip()).to_string(),
+                self.logged_qname(&qname),
+                request.query().query_type().to_string(),
+                response_code.to_string(),
+            )
+        });
+    }
+
+    // Send the answer.
+    response.send(responder).await
+  }
+
+/*
+Description:
+This function handles a DNS request for retrieving the IP address of the client. It takes in a reference to a Request struct, a mutable reference to a ResponseHandler trait object, and returns a Result object containing a ResponseInfo struct or an Error object.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Ok(responder.send_response(response).await?): if the response is successfully sent, returns a Result object containing a ResponseInfo struct with the client IP address.
 Error: if an error occurs during the execution of the function, returns an Error object.
 */
-  
-  async fn do_handle_request_myip<R: ResponseHandler>(
-    &self, // reference to the current instance of the DNS server object
-    request: &Request, // reference to the Request struct that contains the DNS request information
-    mut responder: R, // mutable reference to a ResponseHandler trait object that will handle the DNS response
+  
+  async fn do_handle_request_myip<R: ResponseHandler>(
+    &self, // reference to the current instance of the DNS server object
+    request: &Request, // reference to the Request struct that contains the DNS request information
+    responder: R, // mutable reference to a ResponseHandler trait object that will handle the DNS response
+    ) -> Result<ResponseInfo, Error> {
+    // Increments the counter for the number of requests received.
+    self.counter.add(1);
+    
+    
+    // Answers the source of the request as an A or AAAA record, whichever is asked for and matches its address type (both for ANY), or as text for TXT; other types get no records.
+    let rdata = match (request.query().query_type(), request.src().ip()) {
+        (RecordType::A | RecordType::ANY, IpAddr::V4(ipv4)) => Some(RData::A(ipv4)),
+        (RecordType::AAAA | RecordType::ANY, IpAddr::V6(ipv6)) => Some(RData::AAAA(ipv6)),
+        (RecordType::TXT, ip) => Some(RData::TXT(TXT::new(vec![ip.to_string()]))),
+        _ => None,
+    };
+    
+    // Starts the answer with the record, if there is one, with a TTL of 60 seconds unless the zone is configured with another.
+    let mut response = self.respond(request, 60).answers(rdata);
+
+    // If the config file asks for it, add the client's country code as a TXT record in the additional section.
+    if self.config.zone("myip").is_some_and(|zone| zone.append_country) {
+        let country = self.config.country(request.src().ip()).unwrap_or_else(|| "unknown".to_string());
+        response = response.additional(request.query().name().into(), RData::TXT(TXT::new(vec![country])));
+    }
+    
+    // Sends the answer and awaits for it to be sent.
+    response.send(responder).await
+}
+  
+/*
+Description:
+asynchronous function that handles DNS requests for the counter zone. counter.<domain> answers the number of requests processed and <name>.counter.<domain> increments the named counter <name> and answers its new value. Operators, the clients listed in the [counter] section, can also read a named counter without incrementing it with _peek.<name>.counter.<domain> and reset it with _reset.<name>.counter.<domain>, which answers the value it had; everyone else is refused.
+
+Parameters:
+self: A reference to the DNS server instance calling this function.
+request: A reference to the DNS request being processed.
+responder: A mutable reference to an object that will handle the response.
+
+Returns:
+Ok(ResponseInfo): If the DNS request is successfully processed, a Result object containing a ResponseInfo object with the counter's value is returned.
+Err(Error): If there is an error processing the DNS request, an Error object is returned.
+*/
+  
+  async fn do_handle_request_counter<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Take the labels below the counter zone: none for the request counter, the name of a named counter, or an operation and the name.
+    let name = request.query().name();
+    let parsed = self.parsed_name(name);
+    let (operation, counter_name) = match parsed.args.as_slice() {
+        [] => (None, None),
+        [counter_name] => (None, Some(counter_name.as_str())),
+        [operation, counter_name] if matches!(operation.as_str(), "_peek" | "_reset") => (Some(operation.as_str()), Some(counter_name.as_str())),
+        _ => {
+            let explanation = format!("expected [_peek.|_reset.]<name>.{} with a counter name of letters, digits and dashes, got {name}", self.counter_zone);
+            return self.do_handle_request_malformed(request, responder, ResponseCode::NXDomain, &explanation).await;
+        }
+    };
+    if let Some(counter_name) = counter_name.filter(|counter_name| !counter::valid_name(counter_name)) {
+        let explanation = format!("{counter_name} is not a counter name: up to {} letters, digits and dashes, not starting with a dash", counter::MAX_NAME_LENGTH);
+        return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await;
+    }
+
+    // Only operators may peek at and reset counters.
+    if operation.is_some() && !self.config.counter.is_operator(request.src().ip()) {
+        return self.refuse(request, responder).await;
+    }
+
+    // Increment the counter for each request processed
+    let total = self.counter.load();
+    self.counter.add(1);
+
+    // Read, increment or reset the counter asked for, refusing to create a counter once there are as many as allowed.
+    let value = match (operation, counter_name) {
+        (_, None) => total,
+        (None, Some(counter_name)) => match self.named_counters.increment(counter_name) {
+            Some(value) => value,
+            None => {
+                let explanation = format!("there are already {} counters, the most kept", self.config.counter.max_names);
+                return self.do_handle_request_malformed(request, responder, ResponseCode::Refused, &explanation).await;
+            }
+        },
+        (Some("_reset"), Some(counter_name)) => {
+            let value = self.named_counters.reset(counter_name);
+            if let Some(audit) = &self.audit {
+                audit.record(AuditEntry::now(
+                    request.src().ip().to_string(),
+                    format!("dns/{}", request.protocol().to_string().to_lowercase()),
+                    "counter-reset",
+                    counter_name,
+                    value.to_string(),
+                ));
+            }
+            value
+        }
+        (Some(_), Some(counter_name)) => self.named_counters.get(counter_name),
+    };
+    
+    
+    // Create a TXT record containing the counter value as a string
+    let rdata = RData::TXT(TXT::new(vec![value.to_string()]));
+    
+    // Answer with the record and await the response being sent
+    self.respond(request, 60).answer(rdata).send(responder).await
+}
+
+/*
+Description:
+asynchronous function that handles a DNS request and returns a response with a coin toss result. It increments a counter each time it's called, builds a response with a TXT record containing the result of a coin toss, and sends the response back to the client using the provided response handler.
+
+Parameters:
+&self: A reference to the instance of the DNS server.
+request: &Request: A reference to the DNS request being handled.
+responder: R: A response handler, which is used to send the response back to the client.
+
+Returns:
+Result<ResponseInfo, Error>: A result that contains a ResponseInfo struct with the coin toss result and an Error if there was a problem sending the response back to the client.
+*/
+  async fn do_handle_request_coin<R: ResponseHandler>(
+    &self, // Reference to instance of DNS server
+    request: &Request, // Reference to the DNS request being handled
+    responder: R, // Mutable reference to a response handler
+    ) -> Result<ResponseInfo, Error> { // Returns a result that contains a ResponseInfo struct and an Error if there was a problem sending the       response back to the client
+    // Increment a counter each time the function is called
+    self.counter.add(1);
+
+
+    // Generate a random coin toss result
+    let result = if self.random.with_rng(|rng| rng.gen_bool(0.5)) { "heads" } else { "tails" };
+
+    // Create a TXT record with the result of the coin toss
+    let rdata = RData::TXT(TXT::new(vec![result.to_string()]));
+
+    // Answer with the record and send it back to the client using the provided response handler
+    self.respond(request, 60).answer(rdata).send(responder).await // Return a Result containing a ResponseInfo struct and an Error if there was a problem sending the response back to the client
+}
+
+/*
+Description:
+an asynchronous function do_handle_request_dice that handles a DNS request for a dice roll. The function takes three parameters - self, request, and responder - and returns a Result that can either contain a ResponseInfo or an Error.
+
+Parameters:
+self: a reference to the do_handle_request_dice method's receiver object.
+request: a reference to a Request object, which represents the DNS request being handled by the function.
+responder: a mutable reference to a type R implementing the ResponseHandler trait, which will be used to send the DNS response back to the client.
+
+Returns:
+The function returns a Result<ResponseInfo, Error>. The Ok variant contains a ResponseInfo object that represents the response sent to the client with die roll result. The Err variant contains an Error object indicating what went wrong.
+*/
+  // Define an asynchronous function do_handle_request_dice that takes a reference to the method's receiver object, a reference to a Request object, and a mutable reference to a type R implementing the ResponseHandler trait, and returns a Result containing either a ResponseInfo or an Error.
+async fn do_handle_request_dice<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Increment a counter stored in the method's receiver object by 1.
+    self.counter.add(1);
+    
+    
+    // Generate a random integer between 1 and 6 (inclusive) to use as the result of the dice roll.
+    let result = self.random.with_rng(|rng| rng.gen_range(1..7));
+
+    // Create an RData object representing the text record containing the dice roll result.
+    let rdata = RData::TXT(TXT::new(vec![result.to_string()]));
+    
+    // Answer with a record holding it, which has a TTL of 60 seconds unless the zone is configured with another, and return the Result object containing either the ResponseInfo object representing the response or an Error object if there was an error sending the response.
+    self.respond(request, 60).answer(rdata).send(responder).await
+}
+  
+/*
+Description:
+handles a DNS request for the CIDR domain to return usable IP range for a given IP Address prefix. The function takes a reference to a Request object, a mutable reference to a ResponseHandler object, and returns a Result<ResponseInfo, Error>. The function increments a counter, constructs a message response builder from the given request, sets some header fields, and processes the query. The labels below the cidr zone are expected to hold an address followed by a prefix length, in one of the encodings netcalc::parse_query accepts (e.g. 10.0.0.0.24.cidr.mentisnovae.tech, 10-0-0-0.24.cidr.mentisnovae.tech or 2001-db8--.32.cidr.mentisnovae.tech). A query that doesn't have that layout is answered with NXDOMAIN, and one whose address or prefix length can't be read, or whose prefix is longer than the address, with FORMERR, both explaining what is wrong. Otherwise, it parses the IP address and prefix length from the query parts, describes the network that corresponds to that prefix, constructs a TXT record for each of its network address, broadcast address (IPv4 only), usable IP range and host count, and constructs a response using the message response builder. Finally, it sends the response using the given responder object and returns a ResponseInfo object.
+
+Parameters:
+request: A reference to a Request object containing the DNS request to be handled.
+responder: A mutable reference to a ResponseHandler object that will handle the response.
+
+Returns:
+Result<ResponseInfo, Error>: Returns a ResponseInfo object if the function succeeds, or an Error if it encounters an error.
+*/
+  
+  async fn do_handle_request_cidr<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Take the labels below the cidr zone, whatever the domain is.
+    let name = request.query().name();
+    let parsed = self.parsed_name(name);
+
+    // Take the query apart into the address and prefix length, explaining what is wrong with a malformed query: a name without the layout doesn't exist, and a bad address or prefix length is a malformed question.
+    let (ip_addr, prefix_len) = match netcalc::parse_query(&parsed.args) {
+        Ok(query) => query,
+        Err(error) => {
+            let response_code = match error {
+                netcalc::QueryError::Layout(_) => ResponseCode::NXDomain,
+                netcalc::QueryError::Address(_) | netcalc::QueryError::PrefixLength(_) | netcalc::QueryError::PrefixRange(..) => ResponseCode::FormErr,
+            };
+            return self.do_handle_request_malformed(request, responder, response_code, &error.to_string()).await;
+        }
+    };
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+
+    // Describe the network the address belongs to, based on the IP address and prefix length.
+    let network = netcalc::network(ip_addr, prefix_len);
+  // Create a TXT record for each fact about the network: its address, its broadcast address if it has one, the usable IP range and the number of hosts.
+  let mut facts = vec![format!("Network: {}/{}", network.network, prefix_len)];
+  if let Some(broadcast) = network.broadcast {
+      facts.push(format!("Broadcast: {broadcast}"));
+  }
+  facts.push(format!("Usable IP Range: {} - {}", network.usable.0, network.usable.1));
+  facts.push(format!("Hosts: {}", network.hosts.map_or_else(|| "2^128".to_string(), |hosts| hosts.to_string())));
+
+  // Answer with a TXT record for each fact, with a TTL of 60 seconds unless the zone is configured with another, and return the Result object containing either the ResponseInfo object representing the response or an Error object if there was an error sending the response.
+  let rdata = facts.into_iter().map(|fact| RData::TXT(TXT::new(vec![fact])));
+  self.respond(request, 60).answers(rdata).send(responder).await
+}
+
+/*
+Description:
+handles a request to convert an epoch/unix timestamp to a human readable form. The timestamp is the label below the time zone, e.g. 1700000000.time.mentisnovae.tech, optionally preceded by a label choosing the format (human, iso, rfc2822 or date), e.g. iso.1700000000.time.mentisnovae.tech. A name that can't be read that way is answered with FORMERR and an explanation of the layout.
+
+Parameters:
+&self: A reference to the instance of the DNS server that this function is a part of.
+request: &Request: A reference to the incoming DNS request that needs to be processed.
+responder: R: The object that will be used to send the response back to the client.
+
+Returns:
+Result<ResponseInfo, Error>: A Result object that can either be an Ok with a ResponseInfo object or an Err with an Error object.
+*/
+  async fn handle_epoch_request<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+) -> Result<ResponseInfo, Error> {
+    // Take the labels below the time zone, whatever the domain is
+    let name = request.query().name();
+    let parsed = self.parsed_name(name);
+
+    // Read the timestamp and the format it is written in, explaining the layout if the name doesn't follow it
+    let expected = format!("expected [<format>.]<timestamp>.{}, with a format of {}", self.time_zone, EpochFormat::LABELS);
+    let query = match parsed.args.as_slice() {
+        [timestamp] => Ok((timestamp, EpochFormat::default())),
+        [format, timestamp] => format.parse::<EpochFormat>().map(|format| (timestamp, format)),
+        _ => Err(format!("{expected}, got {name}")),
+    };
+    let formatted = query.and_then(|(timestamp, format)| {
+        let seconds = timestamp.parse::<i64>().map_err(|_| format!("{timestamp} is not a timestamp in seconds; {expected}"))?;
+        calendar::format_epoch(seconds, format).ok_or_else(|| format!("{timestamp} is out of range"))
+    });
+    let formatted = match formatted {
+        Ok(formatted) => formatted,
+        Err(explanation) => {
+            return self.do_handle_request_malformed(request, responder, ResponseCode::FormErr, &explanation).await;
+        }
+    };
+
+    // Increment a counter for the number of times this function has been called
+    self.counter.add(1);
+
+
+    // Create a TXT record with the formatted date string as its value
+    let rdata = RData::TXT(TXT::new(vec![formatted]));
+
+    // Answer with the record, with a TTL of 60 seconds unless the zone is configured with another, back to the client using the responder object
+    self.respond(request, 60).answer(rdata).send(responder).await
+}
+
+/*
+Description:
+answers a query for a type the zone doesn't serve with an empty NOERROR answer (NODATA), with the domain's SOA record in the authority section, so resolvers know the name exists but has no records of the type.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the empty answer if the response is sent, or an Error if sending it fails.
+*/
+
+  async fn do_handle_request_nodata<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    ) -> Result<ResponseInfo, Error> {
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Send an authoritative answer without records, which carries the SOA record.
+    self.respond(request, 0).send(responder).await
+  }
+
+/*
+Description:
+answers a query a zone can't make sense of with an error code and an explanation of what is wrong with it: a TXT record in the additional section, named like the query, and an Extended DNS Error (RFC 8914) with the same text if the client sent EDNS.
+
+Parameters:
+&self: a reference to the current instance of the DNS server object
+request: a reference to the Request struct that contains the DNS request information
+responder: a ResponseHandler trait object that will handle the DNS response
+response_code: the error code answered, such as NXDOMAIN or FORMERR
+explanation: what is wrong with the query
+
+Returns:
+Result<ResponseInfo, Error>: a ResponseInfo struct with the error if the response is sent, or an Error if sending it fails.
+*/
+
+  pub(crate) async fn do_handle_request_malformed<R: ResponseHandler>(
+    &self,
+    request: &Request,
+    responder: R,
+    response_code: ResponseCode,
+    explanation: &str,
+    ) -> Result<ResponseInfo, Error> {
+    debug!("Answering {} from {} with {response_code}: {explanation}", request.query().name(), self.logged_ip(request.src().ip()));
+
+    // Increment the counter for the number of requests handled by this DNS server instance.
+    self.counter.add(1);
+
+    // Start an authoritative answer with the error code, explaining the error in a TXT record in the additional section.
+    let rdata = RData::TXT(TXT::new(vec![explanation.to_string()]));
+    let mut response = self.respond(request, 60).response_code(response_code).additional(request.query().name().into(), rdata);
+
+    // Add the explanation as an Extended DNS Error ("Other", code 0) if the client speaks EDNS.
+    if let Some(edns) = request.edns() {
+        let mut response_edns = Edns::new();
+        response_edns.set_max_payload(edns.max_payload().max(512));
+        let mut data = 0u16.to_be_bytes().to_vec();
+        data.extend(explanation.as_bytes());
+        response_edns.options_mut().insert(EdnsOption::Unknown(EXTENDED_DNS_ERROR, data));
+        response = response.edns(response_edns);
+    }
+
+    // Send the answer and return the result as a ResponseInfo object.
+    response.send(responder).await
+  }
+
+/*
+Description: 
+asynchronous function that handles default DNS requests. The function increments a counter, creates a response message for a given request, sets the header fields of the response message, and sends the response message back to the client using a given response handler.
+
+Parameters:
+&self: A reference to the DNS server object.
+request: A reference to the DNS request message.
+responder: A response handler object.
+
+Returns: 
+A Result containing a ResponseInfo object if the operation is successful, or an Error object if an error occurs.
+*/
+  async fn do_handle_request_default<R: ResponseHandler>(
+    &self, // A reference to self, the DNS server
+    request: &Request, // A reference to the request object
+    responder: R, // A mutable reference to a ResponseHandler object
     ) -> Result<ResponseInfo, Error> {
-    // Increments the counter for the number of requests received.
-    self.counter.fetch_add(1, Ordering::SeqCst);
-    
-    // Creates a new MessageResponseBuilder object from the request.
-    let builder = MessageResponseBuilder::from_message_request(request);
-    
-    // Creates a new Header object for the response, and sets it to be authoritative.
-    let mut header = Header::response_from_request(request.header());
-    header.set_authoritative(true);
-    
-    // Determines the IP address type of the source of the request and creates a new RData object
-    // with the appropriate type (A or AAAA).
-    let rdata = match request.src().ip() {
-        IpAddr::V4(ipv4) => RData::A(ipv4),
-        IpAddr::V6(ipv6) => RData::AAAA(ipv6),
-    };
+    // Increment the value of the counter by 1.
+    self.counter.add(1);
     
-    // Creates a new vector of Record objects with a single record containing the name and RData.
-    let records = vec![Record::from_rdata(request.query().name().into(), 60, rdata)];
-    
-    // Builds the response using the MessageResponseBuilder object, header, and records vector,
-    // along with empty vectors for additional records, nameservers, and resolvers.
-    let response = builder.build(header, records.iter(), &[], &[], &[]);
-    
-    // Sends the response using the responder object and awaits for the response to be sent.
-    // Returns a Result object containing a ResponseInfo struct if the response is successfully sent.
-    Ok(responder.send_response(response).await?)
+    // Answer authoritatively with the response code NXDomain (Non-Existent Domain), no answers, and the domain's SOA record in the authority section.
+    self.respond(request, 0).response_code(ResponseCode::NXDomain).send(responder).await
+  }
 }
-  
 /*
 Description:
-asynchronous function that handles DNS requests and increments a counter for each request processed. The function takes in three arguments - a reference to the DNS server instance that called it, a reference to the DNS request being processed, and a mutable reference to an object that will handle the response. The function returns a Result object containing a ResponseInfo object or an Error object.
+tells whether a zone answers a record type. The zones that compute their answer as text (fx, lotto, coin, dice, cidr, time and the like) serve TXT, and ANY, which they answer with their TXT records; the myip zone, the zones that serve addresses or other records and names no zone serves are left to their handlers, which look at the type themselves.
 
 Parameters:
-self: A reference to the DNS server instance calling this function.
-request: A reference to the DNS request being processed.
-responder: A mutable reference to an object that will handle the response.
+fixed: whether the zone is a fixed zone, matched on its suffix, rather than a keyword zone.
+zone: the zone's keyword, as ParsedName holds it.
+query_type: the type asked for.
 
 Returns:
-Ok(ResponseInfo): If the DNS request is successfully processed, a Result object containing a ResponseInfo object with the number of processed requests is returned.
-Err(Error): If there is an error processing the DNS request, an Error object is returned.
+true if the zone's handler should answer the query, false if it gets an empty answer.
 */
-  
-  async fn do_handle_request_counter<R: ResponseHandler>(
-    &self,
-    request: &Request,
-    mut responder: R,
-    ) -> Result<ResponseInfo, Error> {
-    // Increment the counter for each request processed
-    let counter = self.counter.fetch_add(1, Ordering::SeqCst);
-    
-    // Create a builder object from the DNS message request
-    let builder = MessageResponseBuilder::from_message_request(request);
-    
-    // Create a response header object and set it as authoritative
-    let mut header = Header::response_from_request(request.header());
-    header.set_authoritative(true);
-    
-    // Create a TXT record containing the counter value as a string
-    let rdata = RData::TXT(TXT::new(vec![counter.to_string()]));
-    
-    // Create a vector of records containing the TXT record and its associated information
-    let records = vec![Record::from_rdata(request.query().name().into(), 60, rdata)];
-    
-    // Build the response message using the message builder, header, and record vector
-    let response = builder.build(header, records.iter(), &[], &[], &[]);
-    
-    // Send the response message using the responder object and await the response
-    Ok(responder.send_response(response).await?)
+fn serves_type(fixed: bool, zone: &str, query_type: RecordType) -> bool {
+    let text_zone = match fixed {
+        true => matches!(zone, "counter" | "coin" | "dice" | "cidr" | "time"),
+        false => matches!(
+            zone,
+            "fx" | "lotto" | "rps" | "moon" | "sun" | "week" | "str" | "puny" | "unpuny" | "luhn" | "crc32" | "fib" | "fact" | "age" | "ulid" | "name" | "top" | "health" | "stats" | acme::ZONE | trace::ZONE
+        ),
+    };
+    !text_zone || matches!(query_type, RecordType::TXT | RecordType::ANY)
 }
 
 /*
 Description:
-asynchronous function that handles a DNS request and returns a response with a coin toss result. It increments a counter each time it's called, builds a response with a TXT record containing the result of a coin toss, and sends the response back to the client using the provided response handler.
+builds the header of an answer, which every answer takes its flags from: the query's ID and op code, its RD and CD flags echoed, and RA set only if the server offers recursion. AA and the response code are left to the answer.
 
 Parameters:
-&self: A reference to the instance of the DNS server.
-request: &Request: A reference to the DNS request being handled.
-mut responder: R: A mutable reference to a response handler, which is used to send the response back to the client.
+query: the header of the query being answered.
 
 Returns:
-Result<ResponseInfo, Error>: A result that contains a ResponseInfo struct with the coin toss result and an Error if there was a problem sending the response back to the client.
+The Header of the answer, with no records counted yet.
 */
-  async fn do_handle_request_coin<R: ResponseHandler>(
-    &self, // Reference to instance of DNS server
-    request: &Request, // Reference to the DNS request being handled
-    mut responder: R, // Mutable reference to a response handler
-    ) -> Result<ResponseInfo, Error> { // Returns a result that contains a ResponseInfo struct and an Error if there was a problem sending the       response back to the client
-    // Increment a counter each time the function is called
-    self.counter.fetch_add(1, Ordering::SeqCst);
+pub fn response_header(query: &Header) -> Header {
+    let mut header = Header::response_from_request(query);
+    header.set_recursion_available(RECURSION_AVAILABLE);
+    header
+}
 
-    // Build a response using the MessageResponseBuilder from the request
-    let builder = MessageResponseBuilder::from_message_request(request);
-    let mut header = Header::response_from_request(request.header());
+/*
+Description:
+an answer being put together, which every zone sends its answer through so that answers are built in one place: its header echoes the query as response_header builds it and is authoritative unless the zone says otherwise, the records a zone adds by their data are owned by the query name and given the zone's TTL, and an answer without records carries the domain's SOA record in its authority section. Handler::respond starts one.
+*/
+pub(crate) struct ResponseBuilder<'a> {
+    // The handler answering, which knows the domain's SOA record
+    handler: &'a Handler,
 
-    // Set the Authoritative bit in the header to true
-    header.set_authoritative(true);
+    // The request being answered
+    request: &'a Request,
 
-    // Generate a random coin toss result
-    let result = if rand::random() { "heads" } else { "tails" };
+    // The header of the answer
+    header: Header,
 
-    // Create a TXT record with the result of the coin toss
-    let rdata = RData::TXT(TXT::new(vec![result.to_string()]));
+    // The TTL of the records added by their data
+    ttl: u32,
+
+    // The records of the answer section
+    answers: Vec<Record>,
+
+    // The records of the additional section
+    additionals: Vec<Record>,
+
+    // The authority section, if the zone gives one instead of the SOA record of negative answers
+    authority: Option<Vec<Record>>,
 
-    // Create a vector of records containing the TXT record
-    let records = vec![Record::from_rdata(request.query().name().into(), 60, rdata)];
+    // The EDNS record of the answer, for zones that answer with options
+    edns: Option<Edns>,
+}
+
+impl<'a> ResponseBuilder<'a> {
+    // Sets the response code of the answer
+    pub fn response_code(mut self, response_code: ResponseCode) -> Self {
+        self.header.set_response_code(response_code);
+        self
+    }
+
+    // Sets whether the answer is authoritative
+    pub fn authoritative(mut self, authoritative: bool) -> Self {
+        self.header.set_authoritative(authoritative);
+        self
+    }
+
+    // Sets whether the answer is truncated
+    pub fn truncated(mut self, truncated: bool) -> Self {
+        self.header.set_truncated(truncated);
+        self
+    }
+
+    // Adds a record of the query name holding the data to the answer section
+    pub fn answer(self, rdata: RData) -> Self {
+        self.answers([rdata])
+    }
+
+    // Adds a record of the query name per data to the answer section
+    pub fn answers(mut self, rdata: impl IntoIterator<Item = RData>) -> Self {
+        let (name, ttl) = (Name::from(self.request.query().name()), self.ttl);
+        self.answers.extend(rdata.into_iter().map(|rdata| Record::from_rdata(name.clone(), ttl, rdata)));
+        self
+    }
+
+    // Adds records to the answer section as they are, with their own owners and TTLs, such as those of the record store
+    pub fn records(mut self, records: impl IntoIterator<Item = Record>) -> Self {
+        self.answers.extend(records);
+        self
+    }
+
+    // Adds a record of a name holding the data to the additional section
+    pub fn additional(mut self, owner: Name, rdata: RData) -> Self {
+        self.additionals.push(Record::from_rdata(owner, self.ttl, rdata));
+        self
+    }
+
+    // Sets the authority section, instead of leaving it to the SOA record of negative answers
+    pub fn authority(mut self, records: impl IntoIterator<Item = Record>) -> Self {
+        self.authority = Some(records.into_iter().collect());
+        self
+    }
+
+    // Sets the EDNS record of the answer
+    pub fn edns(mut self, edns: Edns) -> Self {
+        self.edns = Some(edns);
+        self
+    }
+
+    /*
+    Description:
+    builds the answer and sends it.
 
-    // Build the response using the MessageResponseBuilder and send it back to the client using the provided response handler
-    let response = builder.build(header, records.iter(), &[], &[], &[]);
-    Ok(responder.send_response(response).await?) // Return a Result containing a ResponseInfo struct and an Error if there was a problem sending the response back to the client
+    Parameters:
+    responder: the ResponseHandler the answer is sent to.
+
+    Returns:
+    Result<ResponseInfo, Error>: a ResponseInfo struct if the answer is sent, or an Error if sending it fails.
+    */
+    pub async fn send<R: ResponseHandler>(self, mut responder: R) -> Result<ResponseInfo, Error> {
+        let mut builder = MessageResponseBuilder::from_message_request(self.request);
+        if let Some(edns) = self.edns {
+            builder.edns(edns);
+        }
+        let authority = match self.authority {
+            Some(authority) => authority,
+            None => self.handler.negative_soa(self.request, &self.header, &self.answers).into_iter().collect(),
+        };
+        let response = builder.build(self.header, self.answers.iter(), &[], authority.iter(), self.additionals.iter());
+        Ok(responder.send_response(response).await?)
+    }
 }
 
 /*
 Description:
-an asynchronous function do_handle_request_dice that handles a DNS request for a dice roll. The function takes three parameters - self, request, and responder - and returns a Result that can either contain a ResponseInfo or an Error.
+takes the labels of a name as text, from a number of labels in to those of a zone at the end, which are left off; the zone handlers' arguments are taken out this way, so they all see them the same way.
 
 Parameters:
-self: a reference to the do_handle_request_dice method's receiver object.
-request: a reference to a Request object, which represents the DNS request being handled by the function.
-responder: a mutable reference to a type R implementing the ResponseHandler trait, which will be used to send the DNS response back to the client.
+name: the name.
+skip: the number of labels at the front to leave off, e.g. 1 for the keyword of a keyword zone.
+trailing: the number of labels at the end to leave off, those of the zone or root domain.
 
 Returns:
-The function returns a Result<ResponseInfo, Error>. The Ok variant contains a ResponseInfo object that represents the response sent to the client with die roll result. The Err variant contains an Error object indicating what went wrong.
+A vector of the labels in between, e.g. ["10", "0", "0", "0", "24"] for 10.0.0.0.24.cidr.mentisnovae.tech with the 3 labels of cidr.mentisnovae.tech left off, or an empty vector if there are none.
 */
-  // Define an asynchronous function do_handle_request_dice that takes a reference to the method's receiver object, a reference to a Request object, and a mutable reference to a type R implementing the ResponseHandler trait, and returns a Result containing either a ResponseInfo or an Error.
-async fn do_handle_request_dice<R: ResponseHandler>(
-    &self,
-    request: &Request,
-    mut responder: R,
-    ) -> Result<ResponseInfo, Error> {
-    // Increment a counter stored in the method's receiver object by 1, using a sequentially consistent memory ordering.
-    self.counter.fetch_add(1, Ordering::SeqCst);
-    
-    // Use the MessageResponseBuilder to construct a response to the DNS request.
-    let builder = MessageResponseBuilder::from_message_request(request);
-    
-    // Create a Header object representing the response header, initialized with values from the request header and set the Authoritative flag to true.
-    let mut header = Header::response_from_request(request.header());
-    header.set_authoritative(true);
-    
-    // Generate a random integer between 1 and 6 (inclusive) to use as the result of the dice roll.
-    let result = rand::thread_rng().gen_range(1..7);
+fn zone_labels(name: &Name, skip: usize, trailing: u8) -> Vec<String> {
+    let end = usize::from(name.num_labels().saturating_sub(trailing));
+    name.iter()
+        .take(end)
+        .skip(skip)
+        .map(|label| String::from_utf8_lossy(label).into_owned())
+        .collect()
+}
 
-    // Create an RData object representing the text record containing the dice roll result.
-    let rdata = RData::TXT(TXT::new(vec![result.to_string()]));
-    
-    // Create a Record object representing the answer to the DNS query, using the query name, a TTL of 60 seconds, and the RData object created above.
-    let records = vec![Record::from_rdata(request.query().name().into(), 60, rdata)];
-    
-    // Use the MessageResponseBuilder to construct the final response, passing in the response header and the answer record(s) created above, as well as empty vectors for additional records, nameservers, and additional data.
-    let response = builder.build(header, records.iter(), &[], &[], &[]);
-    
-    // Use the responder object to send the response to the client, and return the Result object containing either the ResponseInfo object representing the response or an Error object if there was an error sending the response.
-    Ok(responder.send_response(response).await?)
+/*
+Description:
+splits a long ASCII text into strings of at most 255 characters, the most a single TXT character-string can hold.
+
+Parameters:
+text: the ASCII text to split.
+
+Returns:
+A vector of strings that concatenate back to the text.
+*/
+fn txt_strings(text: &str) -> Vec<String> {
+    text.as_bytes()
+        .chunks(255)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect()
 }
-  
+
 /*
 Description:
-handles a DNS request for the CIDR domain to return usable IP range for a given IP Address prefix. The function takes a reference to a Request object, a mutable reference to a ResponseHandler object, and returns a Result<ResponseInfo, Error>. The function increments a counter, constructs a message response builder from the given request, sets some header fields, and processes the query. The query is expected to have four parts, and the first part should be the string "cidr". If the query does not conform to this format, the function returns a todo!() macro, indicating that the implementation for that case is incomplete. Otherwise, it parses the IP address and prefix length from the query parts, calculates the IP range that corresponds to that prefix, constructs a TXT record with the IP range as a string, creates a vector of records, and constructs a response using the message response builder. Finally, it sends the response using the given responder object and returns a ResponseInfo object.
+takes an fx query apart into the amount and the two currency codes. The amount may use "_" as the decimal separator, since "." separates labels.
 
 Parameters:
-request: A reference to a Request object containing the DNS request to be handled.
-responder: A mutable reference to a ResponseHandler object that will handle the response.
+name: the query name, quoted in the explanation of a malformed query.
+args: the labels between the fx keyword and the domain.
 
 Returns:
-Result<ResponseInfo, Error>: Returns a ResponseInfo object if the function succeeds, or an Error if it encounters an error.
+Ok((amount, from, to)), or Err with an explanation of what is wrong with the query.
 */
-  
-  async fn do_handle_request_cidr<R: ResponseHandler>(
-    &self,
-    request: &Request,
-    mut responder: R,
-    ) -> Result<ResponseInfo, Error> {
-    // Increment the counter for the number of requests handled by this DNS server instance.
-    self.counter.fetch_add(1, Ordering::SeqCst);
+fn parse_fx_query<'a>(name: &LowerName, args: &'a [String]) -> Result<(f64, &'a str, &'a str), String> {
+    let [amount, from, to] = args else {
+        return Err(format!("expected fx.<amount>.<from>.<to>, got {name}"));
+    };
 
-    // Create a builder to build the response message.
-    let builder = MessageResponseBuilder::from_message_request(request);
+    let amount = amount
+        .replace('_', ".")
+        .parse::<f64>()
+        .ok()
+        .filter(|amount| amount.is_finite() && *amount >= 0.0)
+        .ok_or_else(|| format!("invalid amount {amount}"))?;
 
-    // Create a header for the response message and set the authoritative flag to true.
-    let mut header = Header::response_from_request(request.header());
-    header.set_authoritative(true);
+    Ok((amount, from, to))
+}
+
+/*
+Description:
+takes a lotto query apart into the count and maximum. The limits keep the answer within a single TXT string.
 
-    // Extract the query name from the request and convert it to lowercase.
-    let query_name =  request
-        .query()
-        .name()
-        .to_string()
-        .to_lowercase();
+Parameters:
+name: the query name, quoted in the explanation of a malformed query.
+args: the labels between the lotto keyword and the domain.
 
-    // Split the query name into parts using "." as the delimiter.
-    let query_parts: Vec<&str> = query_name.split('.').collect();
+Returns:
+Ok((count, max)), or Err with an explanation of what is wrong with the query.
+*/
+fn parse_lotto_query(name: &LowerName, args: &[String]) -> Result<(usize, usize), String> {
+    let [count, max] = args else {
+        return Err(format!("expected lotto.<count>.<max>, got {name}"));
+    };
 
-    // Check if the query is valid (i.e., contains exactly four parts and the first part is "cidr").
-    if query_parts.len() != 4 || query_parts[0] != "cidr" {
-        // If the query is not valid, return a "todo" error.
-        return todo!();
+    match (count.parse::<usize>(), max.parse::<usize>()) {
+        (Ok(count), Ok(max)) if (1..=LOTTO_MAX_COUNT).contains(&count) && (1..=LOTTO_MAX_NUMBER).contains(&max) && count <= max => Ok((count, max)),
+        _ => Err(format!(
+            "lotto count must be between 1 and {LOTTO_MAX_COUNT}, max between 1 and {LOTTO_MAX_NUMBER}, and count <= max"
+        )),
     }
+}
 
-    // Parse the second part of the query as an IP address.
-    let ip_addr = match query_parts[1].parse::<IpAddr>() {
-        Ok(addr) => addr,
-        Err(_) => {
-            // If the IP address cannot be parsed, return a "todo" error.
-            return todo!();
-        }
-    };
+/*
+Description:
+takes an rps query apart into the client's move.
 
-    // Parse the third part of the query as a prefix length.
-    let prefix_len = match query_parts[2].parse::<u8>() {
-        Ok(len) => len,
-        Err(_) => {
-            // If the prefix length cannot be parsed, return a "todo" error.
-            return todo!();
-        }
+Parameters:
+name: the query name, quoted in the explanation of a malformed query.
+args: the labels between the rps keyword and the domain.
+
+Returns:
+Ok(Move), or Err with an explanation of what is wrong with the query.
+*/
+fn parse_rps_query(name: &LowerName, args: &[String]) -> Result<Move, String> {
+    match args {
+        [label] => label.parse::<Move>(),
+        _ => Err(format!("expected rps.<rock|paper|scissors>, got {name}")),
+    }
+}
+
+/*
+Description:
+takes a moon query apart into the moment to compute the phase for.
+
+Parameters:
+name: the query name, quoted in the explanation of a malformed query.
+args: the labels between the moon keyword and the domain.
+
+Returns:
+Ok(Some(NaiveDateTime)) with noon UTC on the requested date, Ok(None) if no date was given, or Err with an explanation of what is wrong with the query.
+*/
+fn parse_moon_query(name: &LowerName, args: &[String]) -> Result<Option<NaiveDateTime>, String> {
+    match args {
+        [] => Ok(None),
+        [date] => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(12, 0, 0))
+            .map(Some)
+            .ok_or_else(|| format!("invalid date {date}, expected YYYY-MM-DD")),
+        _ => Err(format!("expected moon or moon.<YYYY-MM-DD>, got {name}")),
+    }
+}
+
+/*
+Description:
+takes a sun query apart into the latitude, longitude and optional UTC offset, checking the coordinates are on the globe.
+
+Parameters:
+name: the query name, quoted in the explanation of a malformed query.
+args: the labels between the sun keyword and the domain.
+
+Returns:
+Ok((latitude, longitude, offset)), or Err with an explanation of what is wrong with the query.
+*/
+fn parse_sun_query(name: &LowerName, args: &[String]) -> Result<(f64, f64, Option<FixedOffset>), String> {
+    let (latitude, longitude, offset) = match args {
+        [lat, lon] => (lat, lon, None),
+        [lat, lon, offset] => (lat, lon, Some(offset)),
+        _ => return Err(format!("expected sun.<lat>.<lon>[.<utc offset>], got {name}")),
     };
 
-    // Calculate the start and end IP addresses of the range based on the IP address and prefix length.
-    let ip_range = match ip_addr {
-        // If the IP address is IPv4, calculate the range using a 32-bit netmask.
-        IpAddr::V4(ipv4) => {
-            let netmask = !((1u32 << (32 - prefix_len)) - 1);
-            let start_ip = u128::from(std::net::Ipv4Addr::from(ipv4.clone().into())) & netmask;
-            let end_ip = start_ip | !netmask;
-            (
-                IpAddr::V4(Ipv4Addr::from(start_ip.to_be())),
-                IpAddr::V4(Ipv4Addr::from(end_ip.to_be())),
-            )
-        }
-        // If the IP address is IPv6, calculate the range using a 128-bit netmask.
-        IpAddr::V6(ipv6) => {
-            let netmask = !((1u128 << (128 - prefix_len)) - 1);
-            let start_ip = u128::from(std::net::Ipv6Addr::from(ipv6.clone().into())) & netmask;
-            let end_ip = start_ip | !netmask;
-            (
-                IpAddr::V6(Ipv6Addr::from(start_ip.to_be_bytes())),
-                IpAddr::V6(Ipv6Addr::from(end_ip.to_be_bytes())),
-            )
-        }
+    let latitude = parse_coordinate(latitude, 'n', 's')
+        .filter(|latitude| latitude.abs() <= 90.0)
+        .ok_or_else(|| format!("invalid latitude {latitude}"))?;
+    let longitude = parse_coordinate(longitude, 'e', 'w')
+        .filter(|longitude| longitude.abs() <= 180.0)
+        .ok_or_else(|| format!("invalid longitude {longitude}"))?;
+    let offset = match offset {
+        Some(offset) => Some(parse_utc_offset(offset).ok_or_else(|| format!("invalid UTC offset {offset}, expected e.g. +0530 or -08"))?),
+        None => None,
     };
-  // Create a TXT record containing the IP range as a string.
-  let rdata = RData::TXT(TXT::new(vec![format!("Usable IP Range: {} - {}", ip_range.0, ip_range.1)]));
-    
-  // Create a Record object representing the answer to the DNS query, using the query name, a TTL of 60 seconds, and the RData object created above.
-  let records = vec![Record::from_rdata(request.query().name().into(), 60, rdata)];
-  
-  // Use the MessageResponseBuilder to construct the final response, passing in the response header and the answer record(s) created above, as well as empty vectors for additional records, nameservers, and additional data.
-  let response = builder.build(header, records.iter(), &[], &[], &[]);
-  
-  // Use the responder object to send the response to the client, and return the Result object containing either the ResponseInfo object representing the response or an Error object if there was an error sending the response.
-  Ok(responder.send_response(response).await?)
+
+    Ok((latitude, longitude, offset))
 }
 
 /*
 Description:
-handles a request to convert an epoch/unix timestamp to a human readable form . The function takes in three parameters: a reference to self, which represents the instance of the DNS server, a reference to request, which represents the incoming DNS request, and a mutable reference to responder, which is the object that will be used to send the response back to the client. The function returns a Result that can either be an Ok with a ResponseInfo object or an Err with an Error object.
+takes a week query apart into the date to describe.
 
 Parameters:
-&self: A reference to the instance of the DNS server that this function is a part of.
-request: &Request: A reference to the incoming DNS request that needs to be processed.
-mut responder: R: A mutable reference to the object that will be used to send the response back to the client.
+name: the query name, quoted in the explanation of a malformed query.
+args: the labels between the week keyword and the domain.
 
 Returns:
-Result<ResponseInfo, Error>: A Result object that can either be an Ok with a ResponseInfo object or an Err with an Error object.
+Ok(Some(NaiveDate)) with the requested date, Ok(None) if no date was given, or Err with an explanation of what is wrong with the query.
 */
-  async fn handle_epoch_request<R: ResponseHandler>(
-    &self,
-    request: &Request,
-    mut responder: R,
-) -> Result<ResponseInfo, Error> {
-    // Increment a counter for the number of times this function has been called
-    self.counter.fetch_add(1, Ordering::SeqCst);
-
-    // Get the query name from the incoming request
-    let query_name = request.query().name().to_string();
+fn parse_week_query(name: &LowerName, args: &[String]) -> Result<Option<NaiveDate>, String> {
+    match args {
+        [] => Ok(None),
+        [date] => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| format!("invalid date {date}, expected YYYY-MM-DD")),
+        _ => Err(format!("expected week or week.<YYYY-MM-DD>, got {name}")),
+    }
+}
 
-    // Extract the epoch timestamp from the query name
-    let timestamp = query_name
-        .strip_prefix("epoch.")
-        .and_then(|s| s.strip_suffix(".mentisnovae.tech"))
-        .and_then(|s| s.parse::<u64>().ok())
-        .ok_or_else(|| format_args!("Invalid query name"))?;
+/*
+Description:
+decodes the timestamp embedded in a ULID. The base32 alphabet is case-insensitive, so the lower-cased query name is fine.
 
-    // Convert the epoch timestamp to a DateTime object
-    let date_time = NaiveDateTime::from_timestamp_opt(timestamp as i64, 0);
+Parameters:
+encoded: the ULID as it appeared in the query.
 
-    // Format the DateTime object as a string
-    let formatted_date = date_time.format("%Y-%m-%d %H:%M:%S").to_string();
+Returns:
+Ok(String) describing the timestamp, or Err with an explanation of why the ULID can't be decoded.
+*/
+fn decode_ulid(encoded: &str) -> Result<String, String> {
+    let decoded = ulid::Ulid::from_string(encoded).map_err(|error| format!("invalid ULID {encoded}: {error}"))?;
 
-    // Create a builder for the DNS response
-    let builder = MessageResponseBuilder::from_message_request(request);
+    // Convert the embedded millisecond timestamp to a date and time.
+    let millis = decoded.timestamp_ms();
+    let time = Utc
+        .timestamp_millis_opt(millis as i64)
+        .single()
+        .ok_or_else(|| format!("ULID {encoded} has an out of range timestamp"))?;
 
-    // Create a response header and mark it as authoritative
-    let mut header = Header::response_from_request(request.header());
-    header.set_authoritative(true);
+    Ok(format!("{} UTC ({millis} ms since the epoch)", time.format("%Y-%m-%d %H:%M:%S%.3f")))
+}
 
-    // Create a TXT record with the formatted date string as its value
-    let rdata = RData::TXT(TXT::new(vec![formatted_date]));
+/*
+Description:
+parses a coordinate from a query label. Since "." separates labels, "_" is used as the decimal separator. The sign is given either with a leading "-" or with a hemisphere suffix, e.g. "-33_87" and "33_87s" are the same latitude.
 
-    // Create a DNS record with the query name, a TTL of 60 seconds, and the TXT record
-    let records = vec![Record::from_rdata(request.query().name().into(), 60, rdata)];
+Parameters:
+label: the label to parse.
+positive: the hemisphere suffix for positive values ('n' or 'e').
+negative: the hemisphere suffix for negative values ('s' or 'w').
 
-    // Build the DNS response using the builder, header, and record information
-    let response = builder.build(header, records.iter(), &[], &[], &[]);
+Returns:
+Some(f64) with the coordinate in degrees, or None if the label is not a valid coordinate.
+*/
+fn parse_coordinate(label: &str, positive: char, negative: char) -> Option<f64> {
+    // Strip the hemisphere suffix, if any, remembering the sign it implies.
+    let (value, sign) = if let Some(value) = label.strip_suffix(positive) {
+        (value, 1.0)
+    } else if let Some(value) = label.strip_suffix(negative) {
+        (value, -1.0)
+    } else {
+        (label, 1.0)
+    };
 
-    // Send the response back to the client using the responder object
-    Ok(responder.send_response(response).await?)
+    // Parse the number with "_" as the decimal separator.
+    value
+        .replace('_', ".")
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .map(|value| value * sign)
 }
 
 /*
-Description: 
-asynchronous function that handles default DNS requests. The function increments a counter, creates a response message for a given request, sets the header fields of the response message, and sends the response message back to the client using a given response handler.
+Description:
+parses a UTC offset from a query label, e.g. "+0530", "-08" or "+5_30".
 
 Parameters:
-&self: A reference to the DNS server object.
-request: A reference to the DNS request message.
-mut responder: A mutable reference to a response handler object.
+label: the label to parse.
 
-Returns: 
-A Result containing a ResponseInfo object if the operation is successful, or an Error object if an error occurs.
+Returns:
+Some(FixedOffset) if the label is a valid offset between -14 and +14 hours, None otherwise.
 */
-  async fn do_handle_request_default<R: ResponseHandler>(
-    &self, // A reference to self, the DNS server
-    request: &Request, // A reference to the request object
-    mut responder: R, // A mutable reference to a ResponseHandler object
-    ) -> Result<ResponseInfo, Error> {
-    // Increment the value of the counter by 1 atomically (sequentially consistent order).
-    self.counter.fetch_add(1, Ordering::SeqCst);
-    
-    // Create a new MessageResponseBuilder object from the request object.
-    let builder = MessageResponseBuilder::from_message_request(request);
-    
-    // Create a new Header object as a response from the request header.
-    let mut header = Header::response_from_request(request.header());
-    
-    // Set the Authoritative flag in the header to true.
-    header.set_authoritative(true);
-    
-    // Set the response code to NXDomain (Non-Existent Domain).
-    header.set_response_code(ResponseCode::NXDomain);
-    
-    // Build a response with no resource records using the builder and header objects.
-    let response = builder.build_no_records(header);
-    
-    // Send the response using the responder object and return the result as a ResponseInfo object.
-    Ok(responder.send_response(response).await?)
-  }
+fn parse_utc_offset(label: &str) -> Option<FixedOffset> {
+    // Offsets are plain ASCII, which also keeps the slicing below on character boundaries.
+    if !label.is_ascii() {
+        return None;
+    }
+
+    // Split off the sign.
+    let (sign, rest) = match label.chars().next()? {
+        '+' => (1, &label[1..]),
+        '-' => (-1, &label[1..]),
+        _ => return None,
+    };
+
+    // Split the hours from the minutes, which are either separated by "_" or given as HHMM.
+    let (hours, minutes) = match rest.split_once('_') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let hours = hours.parse::<i32>().ok().filter(|hours| *hours <= 14)?;
+    let minutes = minutes.parse::<i32>().ok().filter(|minutes| *minutes < 60)?;
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
 }
+
 /*
 Description:
-implementation of a RequestHandler trait for the DNS server. The RequestHandler trait defines a method for handling incoming DNS requests, and this implementation defines that method, which handles the request by calling a do_handle_request method and returning a ResponseInfo struct.
+implementation of a RequestHandler trait for the DNS server. The RequestHandler trait defines a method for handling incoming DNS requests, and this implementation defines that method, which handles the request by calling a do_handle_request method and returning a ResponseInfo struct. Requests for a hosted domain are handed to the domain's handler instead. Sampled requests are logged to dnstap on the way in and out when dnstap is enabled.
 
 Parameters:
 &self: A reference to the instance of the Handler struct that implements the RequestHandler trait.
@@ -578,20 +4207,123 @@ impl RequestHandler for Handler {
         request: &Request,
         response: R,
     ) -> ResponseInfo {
-        // Call the do_handle_request method and handle any errors that occur
-        match self.do_handle_request(request, response).await {
+        // Answer queries without exactly one question with FORMERR before anything looks at the question. trust-dns already answers those it reads off the wire itself, so this only catches requests built some other way
+        if let Err(violation) = self.limits.check_question_count(request.header().query_count()) {
+            return self.reject_question_count(request, response, violation).await;
+        }
+
+        // Hand queries for a hosted domain to its handler, which counts and answers them on its own
+        if let Some(tenant) = self.tenants.as_ref().and_then(|tenants| tenants.for_name(request.query().name())) {
+            return tenant.handler.handle_request(request, response).await;
+        }
+
+        // Note when the query arrived, for the metrics and the query log, and count it as in flight until it is answered
+        let received = std::time::Instant::now();
+        let _in_flight = self.overload.enter();
+
+        // Record the query in the per-client statistics and the top-talker sketches
+        let qname = request.query().name().to_string();
+        self.clients.record(request.src().ip(), &qname);
+        self.talkers.record(request.src().ip(), &qname);
+
+        // Pad the answer if the query came through an encrypting proxy and asked for padding
+        let response = PaddingResponder::new(response, self.padding.clone(), request, self.random.clone());
+
+        // Note when the handler starts sending its answer, for the latency histograms
+        let (response, sent) = TimedResponder::new(response);
+
+        // Call the do_handle_request method, logging the query and response to dnstap if this query is sampled
+        let result = match &self.dnstap {
+            Some(dnstap) if dnstap.sample() => {
+                let query_time = SystemTime::now();
+                dnstap.log_query(request, query_time);
+                let response = DnstapResponder::new(response, dnstap.clone(), request, query_time);
+                self.do_handle_request(request, response).await
+            }
+            _ => self.do_handle_request(request, response).await,
+        };
+
+        // Handle any errors that occurred
+        let info = match result {
             Ok(info) => info, // Return the ResponseInfo struct if the call to do_handle_request succeeds
             Err(error) => {
                 // Log the error
                 error!("Error in RequestHandler: {error}");
                 
-                // Create a new Header struct and set the response code to ServFail
-                let mut header = Header::new();
+                // Create a header answering the request and set the response code to ServFail
+                let mut header = response_header(request.header());
                 header.set_response_code(ResponseCode::ServFail);
                 
                 // Convert the Header struct into a ResponseInfo struct and return it
                 header.into()
             }
+        };
+
+        // Record the query, its outcome and latency in the metrics registry
+        let total_latency = received.elapsed();
+        let handler_latency = sent.lock().unwrap().map_or(total_latency, |sent| sent.duration_since(received));
+        self.overload.record_latency(handler_latency.as_micros() as u64);
+        let name = request.query().name();
+        let zone = self.zone_name(name).unwrap_or_else(|| {
+            if self.root_zone.zone_of(name) { "root" } else { "other" }.to_string()
+        });
+        let failed = info.response_code() != ResponseCode::NoError && info.response_code() != ResponseCode::NXDomain;
+
+        // Log the query at debug level, sampled per zone so busy servers can afford it
+        if enabled!(Level::DEBUG) && self.log_sampled(&zone, failed) {
+            debug!(
+                client = %self.logged_ip(request.src().ip()),
+                qname = %self.logged_qname(&name.to_string()),
+                qtype = %request.query().query_type(),
+                zone = %zone,
+                rcode = %info.response_code(),
+                latency_us = total_latency.as_micros() as u64,
+                "query"
+            );
+        }
+
+        self.metrics.record(
+            RecentQuery {
+                timestamp: Utc::now().timestamp_millis(),
+                client: self.logged_ip(request.src().ip()),
+                qname: self.logged_qname(&name.to_string()),
+                qtype: request.query().query_type().to_string(),
+                zone,
+                rcode: info.response_code().to_string(),
+                latency_us: total_latency.as_micros() as u64,
+            },
+            &request.protocol().to_string().to_lowercase(),
+            handler_latency.as_micros() as u64,
+            info.response_code(),
+        );
+
+        // Count NXDOMAIN answers towards the webhooks' spikes and report queries for watched names
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.answered(info.response_code());
+            let qname = name.to_string();
+            webhooks.notify(WebhookEvent::Query, qname.trim_end_matches('.'), || {
+                Event::query(
+                    self.logged_ip(request.src().ip()).to_string(),
+                    self.logged_qname(&qname),
+                    request.query().query_type().to_string(),
+                    info.response_code().to_string(),
+                )
+            });
+        }
+
+        // Log the query and its outcome to the SQLite query log
+        #[cfg(feature = "sqlite")]
+        if let Some(query_log) = &self.query_log {
+            query_log.record(QueryLogEntry {
+                timestamp: Utc::now().timestamp_millis(),
+                client: self.logged_ip(request.src().ip()),
+                qname: self.logged_qname(&request.query().name().to_string()),
+                qtype: request.query().query_type().to_string(),
+                rcode: info.response_code().into(),
+                latency_us: total_latency.as_micros() as u64,
+            });
         }
+
+        info
     }
 }
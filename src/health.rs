@@ -0,0 +1,96 @@
+use serde::Serialize;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+/*
+Description:
+the liveness and readiness state of the server, reported by the health zone and the admin API's /healthz and /readyz endpoints. The server is live as soon as the handler exists, and ready once its DNS sockets are bound and it can answer queries.
+*/
+#[derive(Debug)]
+pub struct Health {
+    // When the server started
+    started: Instant,
+
+    // Whether the DNS sockets are bound
+    ready: AtomicBool,
+}
+
+/*
+Description:
+a snapshot of the health state for the admin API.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthReport {
+    // "ok" when ready, "starting" otherwise
+    pub status: &'static str,
+
+    // Seconds since the server started
+    pub uptime: u64,
+
+    // The total number of requests served
+    pub queries: u64,
+
+    // The server version
+    pub version: &'static str,
+}
+
+impl Health {
+    /*
+    Description:
+    creates the health state of a server that is starting up.
+
+    Parameters:
+    None
+
+    Returns:
+    A new Health that is live but not yet ready.
+    */
+    pub fn new() -> Self {
+        Health {
+            started: Instant::now(),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    // Marks the server as ready to answer queries
+    pub fn set_ready(&self) {
+        self.ready.store(true, Ordering::SeqCst);
+    }
+
+    // Returns true once the server can answer queries
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    // Returns how long the server has been running
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /*
+    Description:
+    takes a snapshot of the health state.
+
+    Parameters:
+    queries: the total number of requests served, from the request counter.
+
+    Returns:
+    A HealthReport.
+    */
+    pub fn report(&self, queries: u64) -> HealthReport {
+        HealthReport {
+            status: if self.is_ready() { "ok" } else { "starting" },
+            uptime: self.uptime().as_secs(),
+            queries,
+            version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
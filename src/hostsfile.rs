@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use tracing::*;
+
+// The keyword of the zone the hosts files' names are served in, as <name>.hosts.<domain>.
+pub const ZONE: &str = "hosts";
+
+// The TTL of the hosts files' records.
+pub const TTL: u32 = 60;
+
+// How often the files' modification times are checked where inotify isn't available.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// How long to wait after a change before reloading, so an editor's several writes are read as one.
+const SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+// The names and addresses of the hosts files, both ways round
+#[derive(Debug, Default)]
+struct Entries {
+    // The addresses of each name below the zone, lower-case, in the order the files give them
+    names: HashMap<String, Vec<IpAddr>>,
+
+    // The names of each address, the first of which is the address's canonical name
+    addresses: HashMap<IpAddr, Vec<String>>,
+}
+
+/*
+Description:
+the hosts zone: the entries of one or more files in the format of /etc/hosts, served as A and AAAA records at <name>.hosts.<domain> and as PTR records for their addresses. Names that end with the domain are served without it, so "nas.mentisnovae.tech" and "nas" both become nas.hosts.<domain>. The files are read again whenever they change.
+*/
+#[derive(Debug)]
+pub struct HostsFiles {
+    // The domain the zone is below, stripped from names that end with it
+    domain: String,
+
+    // The files the entries are read from
+    paths: Vec<PathBuf>,
+
+    // The entries of the files as they were last read
+    entries: RwLock<Entries>,
+}
+
+impl HostsFiles {
+    /*
+    Description:
+    creates the hosts zone and reads its files. A file that can't be read is logged and the zone starts empty, to be filled in once the file appears.
+
+    Parameters:
+    domain: the domain the zone is below.
+    paths: the hosts-format files to serve.
+
+    Returns:
+    The HostsFiles.
+    */
+    pub fn new(domain: &str, paths: Vec<PathBuf>) -> Self {
+        let hosts = HostsFiles {
+            domain: domain.trim_end_matches('.').to_ascii_lowercase(),
+            paths,
+            entries: RwLock::default(),
+        };
+        match hosts.reload() {
+            Ok(count) => info!("Serving {count} names from the hosts files"),
+            Err(error) => warn!("Failed to read the hosts files, serving none until they change: {error}"),
+        }
+        hosts
+    }
+
+    // Returns the files the entries are read from
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /*
+    Description:
+    reads the files again and replaces the entries served with theirs.
+
+    Returns:
+    Ok(usize) with the number of names now served, or an io::Error if a file can't be read, in which case the entries are kept.
+    */
+    pub fn reload(&self) -> io::Result<usize> {
+        let mut entries = Entries::default();
+        for path in &self.paths {
+            let text = std::fs::read_to_string(path).map_err(|error| io::Error::new(error.kind(), format!("{}: {error}", path.display())))?;
+            self.parse(&text, &mut entries);
+        }
+        let count = entries.names.len();
+        *self.entries.write().unwrap() = entries;
+        Ok(count)
+    }
+
+    // Returns the addresses of a name below the zone, e.g. "nas", or none if no file has it
+    pub fn addresses(&self, name: &str) -> Vec<IpAddr> {
+        let entries = self.entries.read().unwrap();
+        entries.names.get(&name.to_ascii_lowercase()).cloned().unwrap_or_default()
+    }
+
+    // Returns the names below the zone of an address, canonical name first, or none if no file has it
+    pub fn names_of(&self, address: IpAddr) -> Vec<String> {
+        let entries = self.entries.read().unwrap();
+        entries.addresses.get(&address).cloned().unwrap_or_default()
+    }
+
+    // Returns the number of names served
+    pub fn names(&self) -> usize {
+        self.entries.read().unwrap().names.len()
+    }
+
+    // Adds the entries of a hosts file: an address and its names on each line, with # starting a comment. Lines whose address doesn't parse are left out.
+    fn parse(&self, text: &str, entries: &mut Entries) {
+        let suffix = format!(".{}", self.domain);
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(Ok(address)) = fields.next().map(str::parse::<IpAddr>) else {
+                continue;
+            };
+            for name in fields {
+                let name = name.trim_end_matches('.').to_ascii_lowercase();
+                let name = name.strip_suffix(&suffix).unwrap_or(&name).to_string();
+                if name.is_empty() || name == self.domain {
+                    continue;
+                }
+                let addresses = entries.names.entry(name.clone()).or_default();
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+                let names = entries.addresses.entry(address).or_default();
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+}
+
+/*
+Description:
+returns the address a reverse name stands for: a.b.c.d.in-addr.arpa for the IPv4 address d.c.b.a, or the 32 nibbles of an IPv6 address in reverse order under ip6.arpa. Names of networks rather than addresses, such as 2.0.192.in-addr.arpa, stand for none.
+
+Parameters:
+name: the reverse name, with or without its trailing dot.
+
+Returns:
+Some(IpAddr) with the address, or None if the name is not the reverse name of an address.
+*/
+pub fn reverse_address(name: &str) -> Option<IpAddr> {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    if let Some(octets) = name.strip_suffix(".in-addr.arpa") {
+        let octets: Vec<u8> = octets.rsplit('.').map(|octet| octet.parse().ok()).collect::<Option<_>>()?;
+        let octets: [u8; 4] = octets.try_into().ok()?;
+        return Some(IpAddr::from(octets));
+    }
+
+    let nibbles = name.strip_suffix(".ip6.arpa")?;
+    let nibbles: Vec<u8> = nibbles
+        .rsplit('.')
+        .map(|nibble| match nibble.len() {
+            1 => u8::from_str_radix(nibble, 16).ok(),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    if nibbles.len() != 32 {
+        return None;
+    }
+    let bytes: Vec<u8> = nibbles.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect();
+    let bytes: [u8; 16] = bytes.try_into().ok()?;
+    Some(IpAddr::from(bytes))
+}
+
+/*
+Description:
+keeps the hosts zone in step with its files, reading them again whenever one changes. On Linux the files' directories are watched with inotify, so files replaced by renaming (as most editors save them) are seen too; elsewhere, or if inotify fails, the files' modification times are checked every few seconds. A file that can't be read after a change leaves the entries as they were.
+
+Parameters:
+hosts: the zone to keep up to date.
+
+Returns:
+None; runs until the process exits.
+*/
+pub async fn watch(hosts: Arc<HostsFiles>) {
+    #[cfg(target_os = "linux")]
+    match inotify::Inotify::watch(hosts.paths()) {
+        Ok(inotify) => loop {
+            match inotify.changed().await {
+                Ok(true) => {
+                    tokio::time::sleep(SETTLE_DELAY).await;
+                    reload(&hosts);
+                }
+                Ok(false) => {}
+                Err(error) => {
+                    warn!("Lost the inotify watch of the hosts files, checking them every {}s instead: {error}", POLL_INTERVAL.as_secs());
+                    break;
+                }
+            }
+        },
+        Err(error) => warn!("Failed to watch the hosts files with inotify, checking them every {}s instead: {error}", POLL_INTERVAL.as_secs()),
+    }
+
+    let mut modified = modification_times(hosts.paths());
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let now = modification_times(hosts.paths());
+        if now != modified {
+            modified = now;
+            reload(&hosts);
+        }
+    }
+}
+
+// Reads the hosts files again, logging the outcome
+fn reload(hosts: &HostsFiles) {
+    match hosts.reload() {
+        Ok(count) => info!("Reloaded the hosts files, serving {count} names"),
+        Err(error) => warn!("Failed to reload the hosts files, keeping the previous entries: {error}"),
+    }
+}
+
+// Returns the modification time of each file, or None for those that can't be read
+fn modification_times(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths.iter().map(|path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()).collect()
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::{
+        ffi::{CString, OsStr, OsString},
+        io,
+        os::{
+            fd::{AsRawFd, FromRawFd, OwnedFd},
+            unix::ffi::OsStrExt,
+        },
+        path::{Path, PathBuf},
+    };
+    use tokio::io::unix::AsyncFd;
+
+    // The events that mean a file in a watched directory was written, replaced or removed.
+    const EVENTS: u32 = libc::IN_CLOSE_WRITE | libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+
+    // The size of an inotify event before its name: the watch descriptor, mask, cookie and name length.
+    const HEADER: usize = 16;
+
+    // An inotify instance watching the directories of the hosts files
+    pub(super) struct Inotify {
+        // The inotify file descriptor, registered with the runtime
+        fd: AsyncFd<OwnedFd>,
+
+        // The names of the hosts files within their directories
+        files: Vec<OsString>,
+    }
+
+    // Returns the directory a file is in, which is what inotify watches
+    fn directory(path: &Path) -> PathBuf {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+
+    impl Inotify {
+        // Watches the directories of the files given
+        pub(super) fn watch(paths: &[PathBuf]) -> io::Result<Self> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            let mut directories: Vec<PathBuf> = paths.iter().map(|path| directory(path)).collect();
+            directories.sort();
+            directories.dedup();
+            for directory in directories {
+                let path = CString::new(directory.as_os_str().as_bytes()).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+                if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), EVENTS) } < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            Ok(Inotify {
+                fd: AsyncFd::new(fd)?,
+                files: paths.iter().filter_map(|path| path.file_name().map(OsStr::to_os_string)).collect(),
+            })
+        }
+
+        // Waits for the next batch of events and returns whether any of them was about a hosts file
+        pub(super) async fn changed(&self) -> io::Result<bool> {
+            let mut buffer = [0u8; 4096];
+            let read = loop {
+                let mut guard = self.fd.readable().await?;
+                let result = guard.try_io(|fd| {
+                    let read = unsafe { libc::read(fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) };
+                    if read < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(read as usize)
+                });
+                if let Ok(result) = result {
+                    break result?;
+                }
+            };
+
+            // Each event is its header followed by the name of the file, padded with NULs; an overflow means events were lost, so anything may have changed
+            let mut offset = 0;
+            let mut changed = false;
+            while offset + HEADER <= read {
+                let mask = u32::from_ne_bytes(buffer[offset + 4..offset + 8].try_into().unwrap());
+                let length = u32::from_ne_bytes(buffer[offset + 12..offset + HEADER].try_into().unwrap()) as usize;
+                let name = &buffer[offset + HEADER..(offset + HEADER + length).min(read)];
+                let name = OsStr::from_bytes(name.split(|&byte| byte == 0).next().unwrap_or_default());
+                changed |= mask & libc::IN_Q_OVERFLOW != 0 || self.files.iter().any(|file| file == name);
+                offset += HEADER + length;
+            }
+            Ok(changed)
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use http::{header, Request, Response, StatusCode};
+use serde::Serialize;
+use std::io;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+// The largest request head (request line and headers) accepted.
+const MAX_HEAD_SIZE: usize = 8192;
+
+/*
+Description:
+reads one HTTP/1.1 request from a connection, for the server's small HTTP APIs, which answer one request per connection and close it: the request line, the headers and, if there is a Content-Length, the body. Chunked bodies are not supported.
+
+Parameters:
+stream: the client connection.
+max_body: the largest body accepted.
+
+Returns:
+Ok(Request) with the body, or an io::Error if the connection failed or the request was malformed or too large.
+*/
+pub async fn read_request(stream: &mut TcpStream, max_body: usize) -> io::Result<Request<Vec<u8>>> {
+    let mut data = Vec::with_capacity(1024);
+    let mut buffer = [0; 1024];
+
+    // Read until the blank line that ends the head
+    let head_end = loop {
+        if let Some(end) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || data.len() + read > MAX_HEAD_SIZE + max_body {
+            return Err(invalid("incomplete or oversized request"));
+        }
+        data.extend_from_slice(&buffer[..read]);
+    };
+    if head_end > MAX_HEAD_SIZE {
+        return Err(invalid("oversized request head"));
+    }
+
+    // Parse the request line, e.g. "GET /clients?limit=10 HTTP/1.1", and the headers after it
+    let head = String::from_utf8_lossy(&data[..head_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut parts = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(uri)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let mut builder = Request::builder().method(method).uri(uri);
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid("malformed header"))?;
+        builder = builder.header(name.trim(), value.trim());
+    }
+
+    // Read the rest of the body, as long as the Content-Length says
+    let length = match builder.headers_ref().and_then(|headers| headers.get(header::CONTENT_LENGTH)) {
+        Some(length) => length
+            .to_str()
+            .ok()
+            .and_then(|length| length.parse::<usize>().ok())
+            .ok_or_else(|| invalid("malformed Content-Length"))?,
+        None => 0,
+    };
+    if length > max_body {
+        return Err(invalid("oversized request body"));
+    }
+    let mut body = data.split_off(head_end + 4);
+    if body.len() < length {
+        let start = body.len();
+        body.resize(length, 0);
+        stream.read_exact(&mut body[start..]).await?;
+    }
+    body.truncate(length);
+
+    builder.body(body).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/*
+Description:
+writes a response and closes the connection. The Content-Type is taken from the response's headers, and is JSON if it has none; the response's other headers are written as they are.
+
+Parameters:
+stream: the client connection.
+response: the response.
+
+Returns:
+Ok(()) once the response is written, or an io::Error if the connection failed.
+*/
+pub async fn write_response(stream: &mut TcpStream, response: &Response<Vec<u8>>) -> io::Result<()> {
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/json");
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status().as_u16(),
+        response.status().canonical_reason().unwrap_or(""),
+        response.body().len(),
+    );
+    for (name, value) in response.headers() {
+        if ![header::CONTENT_TYPE, header::CONTENT_LENGTH, header::CONNECTION].contains(name) {
+            head.push_str(&format!("{name}: {}\r\n", String::from_utf8_lossy(value.as_bytes())));
+        }
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body()).await?;
+    stream.shutdown().await
+}
+
+// Returns the value of a query string parameter
+pub fn query_param<'a, B>(request: &'a Request<B>, name: &str) -> Option<&'a str> {
+    request
+        .uri()
+        .query()?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+// Decodes the %-escapes and pluses of a query string value, e.g. "2001%3Adb8%3A%3A1" to "2001:db8::1"
+pub fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = match (byte, tail) {
+            (b'%', [high, low, ..]) => std::str::from_utf8(&[*high, *low]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match (byte, escaped) {
+            (_, Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            (b'+', None) => {
+                bytes.push(b' ');
+                rest = tail;
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// Builds a response with a JSON body
+pub fn json(status: StatusCode, body: &impl Serialize) -> Response<Vec<u8>> {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response
+}
+
+// Builds an error response whose JSON body names the status
+pub fn error(status: StatusCode) -> Response<Vec<u8>> {
+    let reason = status.canonical_reason().unwrap_or("error");
+    json(status, &serde_json::json!({ "error": reason }))
+}
+
+// Returns the error of a malformed request
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
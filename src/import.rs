@@ -0,0 +1,410 @@
+use crate::aws::{self, Credentials};
+use chrono::Utc;
+use serde_json::Value;
+use std::{fmt::Write, time::Duration};
+
+// The endpoint of the Route 53 API.
+pub const ROUTE53_ENDPOINT: &str = "https://route53.amazonaws.com";
+
+// The endpoint of the Cloudflare API.
+pub const CLOUDFLARE_ENDPOINT: &str = "https://api.cloudflare.com/client/v4";
+
+// The TTL of Cloudflare records whose TTL is automatic (1), which Cloudflare serves as 300 seconds.
+const CLOUDFLARE_AUTOMATIC_TTL: u32 = 300;
+
+// The number of records asked for per page.
+const PAGE_SIZE: u32 = 100;
+
+/*
+Description:
+defines the errors that can occur while importing a zone.
+*/
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("HTTP error: {0:}")]
+    Http(#[from] reqwest::Error),
+    #[error("The API answered {0}: {1}")]
+    Api(reqwest::StatusCode, String),
+    #[error("Unexpected answer from the API: {0}")]
+    Malformed(String),
+}
+
+/*
+Description:
+a record of an imported zone, as it is written to the zone file.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportedRecord {
+    // The owner name, absolute
+    pub name: String,
+
+    // The TTL in seconds
+    pub ttl: u32,
+
+    // The record type, e.g. "MX"
+    pub record_type: String,
+
+    // The record's data, written as in a zone file with absolute names
+    pub data: String,
+
+    // A note written above the record, e.g. that the provider proxies it
+    pub comment: Option<String>,
+}
+
+/*
+Description:
+a zone read from a provider: its origin, its records, and the records that couldn't be imported with the reason for each.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct ImportedZone {
+    // The zone's origin, absolute
+    pub origin: String,
+
+    // The zone's records, in the order the provider listed them
+    pub records: Vec<ImportedRecord>,
+
+    // The records left out, each described with the reason why
+    pub skipped: Vec<String>,
+}
+
+/*
+Description:
+imports a Route 53 hosted zone, reading its record sets page by page. Alias records and record sets with a routing policy other than simple have no plain records to copy, so they are skipped.
+
+Parameters:
+client: the HTTP client to call the API with.
+endpoint: the API's endpoint, normally ROUTE53_ENDPOINT.
+zone_id: the hosted zone's ID, e.g. Z1D633PJN98FT9, with or without the /hostedzone/ prefix.
+credentials: the AWS credentials to sign the requests with.
+
+Returns:
+Ok(ImportedZone), or an ImportError if the API could not be reached or refused a request.
+*/
+pub async fn route53(client: &reqwest::Client, endpoint: &str, zone_id: &str, credentials: &Credentials) -> Result<ImportedZone, ImportError> {
+    let zone_id = zone_id.trim_start_matches("/hostedzone/");
+    let zone = route53_get(client, endpoint, &format!("/2013-04-01/hostedzone/{zone_id}"), &[], credentials).await?;
+    let origin = xml_elements(&zone, "HostedZone")
+        .first()
+        .and_then(|hosted_zone| xml_text(hosted_zone, "Name"))
+        .ok_or_else(|| ImportError::Malformed("the hosted zone has no name".to_string()))?;
+    let mut imported = ImportedZone {
+        origin: absolute(&route53_name(&origin)),
+        ..ImportedZone::default()
+    };
+
+    let path = format!("/2013-04-01/hostedzone/{zone_id}/rrset");
+    let mut next: Option<(String, String, Option<String>)> = None;
+    loop {
+        let page_size = PAGE_SIZE.to_string();
+        let mut query = vec![("maxitems", page_size.as_str())];
+        if let Some((name, record_type, identifier)) = &next {
+            query.push(("name", name));
+            query.push(("type", record_type));
+            if let Some(identifier) = identifier {
+                query.push(("identifier", identifier));
+            }
+        }
+        let page = route53_get(client, endpoint, &path, &query, credentials).await?;
+        let (records, skipped) = route53_records(&page);
+        imported.records.extend(records);
+        imported.skipped.extend(skipped);
+
+        if xml_text(&page, "IsTruncated").as_deref() != Some("true") {
+            return Ok(imported);
+        }
+        next = match (xml_text(&page, "NextRecordName"), xml_text(&page, "NextRecordType")) {
+            (Some(name), Some(record_type)) => Some((name, record_type, xml_text(&page, "NextRecordIdentifier"))),
+            _ => return Err(ImportError::Malformed("a truncated page doesn't say where the next one starts".to_string())),
+        };
+    }
+}
+
+// Sends a signed GET request to the Route 53 API, returning the XML it answers
+async fn route53_get(client: &reqwest::Client, endpoint: &str, path: &str, query: &[(&str, &str)], credentials: &Credentials) -> Result<String, ImportError> {
+    let mut url = reqwest::Url::parse(&format!("{}{path}", endpoint.trim_end_matches('/'))).map_err(|error| ImportError::Malformed(error.to_string()))?;
+    if !query.is_empty() {
+        url.query_pairs_mut().extend_pairs(query);
+    }
+    // Route 53 is a global service, signed for us-east-1
+    let response = aws::sign(client, url, credentials, "us-east-1", "route53", Utc::now()).send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(ImportError::Api(status, xml_text(&body, "Message").unwrap_or(body)));
+    }
+    Ok(body)
+}
+
+/*
+Description:
+reads the records of a page of Route 53's ListResourceRecordSets answer.
+
+Parameters:
+xml: the page.
+
+Returns:
+The page's records, and a description of each record set that was skipped and why.
+*/
+pub fn route53_records(xml: &str) -> (Vec<ImportedRecord>, Vec<String>) {
+    let (mut records, mut skipped) = (Vec::new(), Vec::new());
+    for set in xml_elements(xml, "ResourceRecordSet") {
+        let name = absolute(&route53_name(&xml_text(set, "Name").unwrap_or_default()));
+        let record_type = xml_text(set, "Type").unwrap_or_default();
+        if let Some(target) = xml_elements(set, "AliasTarget").first() {
+            let target = xml_text(target, "DNSName").unwrap_or_default();
+            skipped.push(format!("{name} {record_type}: an alias of {target}, which has no records to copy"));
+            continue;
+        }
+        if let Some(identifier) = xml_text(set, "SetIdentifier") {
+            skipped.push(format!("{name} {record_type}: the record set {identifier} of a routing policy"));
+            continue;
+        }
+
+        let ttl = xml_text(set, "TTL").and_then(|ttl| ttl.parse().ok()).unwrap_or(300);
+        for record in xml_elements(set, "ResourceRecord") {
+            records.push(ImportedRecord {
+                name: name.clone(),
+                ttl,
+                record_type: record_type.clone(),
+                data: xml_text(record, "Value").unwrap_or_default(),
+                comment: None,
+            });
+        }
+    }
+    (records, skipped)
+}
+
+/*
+Description:
+imports a Cloudflare zone, reading its DNS records page by page. Cloudflare doesn't list the zone's SOA and NS records, so its name servers are added as the NS records of the apex. Records Cloudflare proxies are imported with their origin's address, which clients of the imported zone connect to directly.
+
+Parameters:
+client: the HTTP client to call the API with.
+endpoint: the API's endpoint, normally CLOUDFLARE_ENDPOINT.
+zone_id: the zone's ID.
+token: an API token with the Zone:Read and DNS:Read permissions.
+
+Returns:
+Ok(ImportedZone), or an ImportError if the API could not be reached or refused a request.
+*/
+pub async fn cloudflare(client: &reqwest::Client, endpoint: &str, zone_id: &str, token: &str) -> Result<ImportedZone, ImportError> {
+    let endpoint = endpoint.trim_end_matches('/');
+    let zone = cloudflare_get(client, &format!("{endpoint}/zones/{zone_id}"), token).await?;
+    let origin = zone["result"]["name"]
+        .as_str()
+        .map(absolute)
+        .ok_or_else(|| ImportError::Malformed("the zone has no name".to_string()))?;
+    let name_servers = zone["result"]["name_servers"].as_array().cloned().unwrap_or_default();
+    let mut imported = ImportedZone {
+        records: name_servers
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|server| ImportedRecord {
+                name: origin.clone(),
+                ttl: 86400,
+                record_type: "NS".to_string(),
+                data: absolute(server),
+                comment: None,
+            })
+            .collect(),
+        origin,
+        ..ImportedZone::default()
+    };
+
+    for page in 1.. {
+        let url = format!("{endpoint}/zones/{zone_id}/dns_records?page={page}&per_page={PAGE_SIZE}");
+        let records = cloudflare_get(client, &url, token).await?;
+        let (found, skipped) = cloudflare_records(&records);
+        imported.records.extend(found);
+        imported.skipped.extend(skipped);
+
+        let total_pages = records["result_info"]["total_pages"].as_u64().unwrap_or(1);
+        if page >= total_pages {
+            break;
+        }
+    }
+    Ok(imported)
+}
+
+// Sends a GET request to the Cloudflare API, returning the JSON it answers
+async fn cloudflare_get(client: &reqwest::Client, url: &str, token: &str) -> Result<Value, ImportError> {
+    let response = client.get(url).bearer_auth(token).send().await?;
+    let status = response.status();
+    let body: Value = response.json().await?;
+    if !status.is_success() || body["success"] == Value::Bool(false) {
+        let errors: Vec<&str> = body["errors"].as_array().into_iter().flatten().filter_map(|error| error["message"].as_str()).collect();
+        return Err(ImportError::Api(status, errors.join("; ")));
+    }
+    Ok(body)
+}
+
+/*
+Description:
+reads the records of a page of Cloudflare's DNS records answer, turning the fields Cloudflare keeps apart (e.g. an MX record's priority) into the data of a zone file.
+
+Parameters:
+page: the page.
+
+Returns:
+The page's records, and a description of each record that was skipped and why.
+*/
+pub fn cloudflare_records(page: &Value) -> (Vec<ImportedRecord>, Vec<String>) {
+    let (mut records, mut skipped) = (Vec::new(), Vec::new());
+    for record in page["result"].as_array().into_iter().flatten() {
+        let (Some(name), Some(record_type), Some(content)) = (record["name"].as_str(), record["type"].as_str(), record["content"].as_str()) else {
+            skipped.push(format!("{record}: not a record"));
+            continue;
+        };
+        let name = absolute(name);
+        let priority = record["priority"].as_u64().unwrap_or(0);
+        let data = &record["data"];
+        let data = match record_type {
+            "CNAME" | "NS" | "PTR" => absolute(content),
+            "MX" => format!("{priority} {}", absolute(content)),
+            "SRV" if data.is_object() => format!(
+                "{} {} {} {}",
+                data["priority"].as_u64().unwrap_or(priority),
+                data["weight"].as_u64().unwrap_or(0),
+                data["port"].as_u64().unwrap_or(0),
+                absolute(data["target"].as_str().unwrap_or_default())
+            ),
+            "SRV" => format!("{priority} {content}"),
+            "TXT" if content.starts_with('"') => content.to_string(),
+            "TXT" => quote(content),
+            "CAA" if data.is_object() => format!(
+                "{} {} {}",
+                data["flags"].as_u64().unwrap_or(0),
+                data["tag"].as_str().unwrap_or_default(),
+                quote(data["value"].as_str().unwrap_or_default())
+            ),
+            _ => content.to_string(),
+        };
+        let ttl = match record["ttl"].as_u64() {
+            Some(1) | None => CLOUDFLARE_AUTOMATIC_TTL,
+            Some(ttl) => u32::try_from(ttl).unwrap_or(u32::MAX),
+        };
+        let comment = (record["proxied"] == Value::Bool(true)).then(|| "proxied by Cloudflare; this is the origin's address".to_string());
+        records.push(ImportedRecord {
+            name,
+            ttl,
+            record_type: record_type.to_string(),
+            data,
+            comment,
+        });
+    }
+    (records, skipped)
+}
+
+/*
+Description:
+writes an imported zone as a zone file the server can serve with --zone-file: the SOA record first, then the NS records of the apex, then the rest in the provider's order. A zone the provider doesn't export an SOA record for gets one, naming its first name server and with today's date as its serial.
+
+Parameters:
+zone: the imported zone.
+source: where the zone came from, for the file's header, e.g. "Route 53 zone Z1D633PJN98FT9".
+
+Returns:
+The zone file.
+*/
+pub fn zone_file(zone: &ImportedZone, source: &str) -> String {
+    let mut text = format!("; {} imported from {source} on {}\n$ORIGIN {}\n", zone.origin, Utc::now().format("%Y-%m-%d"), zone.origin);
+    let is_apex_type = |record: &&ImportedRecord, record_type: &str| record.record_type == record_type && record.name.eq_ignore_ascii_case(&zone.origin);
+
+    let soa: Vec<&ImportedRecord> = zone.records.iter().filter(|record| is_apex_type(record, "SOA")).collect();
+    let ns: Vec<&ImportedRecord> = zone.records.iter().filter(|record| is_apex_type(record, "NS")).collect();
+    if soa.is_empty() {
+        let primary = ns.first().map_or_else(|| format!("ns1.{}", zone.origin), |ns| ns.data.clone());
+        text.push_str("; The provider has no SOA record to export, so this one was made up\n");
+        let _ = writeln!(text, "{} 3600 IN SOA {primary} hostmaster.{} {}01 3600 900 604800 300", zone.origin, zone.origin, Utc::now().format("%Y%m%d"));
+    }
+
+    let rest = zone.records.iter().filter(|record| !is_apex_type(record, "SOA") && !is_apex_type(record, "NS"));
+    for record in soa.into_iter().chain(ns).chain(rest) {
+        if let Some(comment) = &record.comment {
+            let _ = writeln!(text, "; {comment}");
+        }
+        let _ = writeln!(text, "{} {} IN {} {}", record.name, record.ttl, record.record_type, record.data);
+    }
+    text
+}
+
+// Returns an HTTP client for calling the providers' APIs
+pub fn http_client(timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+// Returns a name with a trailing dot and in lower case
+fn absolute(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.').to_ascii_lowercase())
+}
+
+// Turns the octal escapes Route 53 writes names with (e.g. \052 for the * of a wildcard) back into characters
+fn route53_name(name: &str) -> String {
+    let mut text = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(index) = rest.find('\\') {
+        text.push_str(&rest[..index]);
+        let escape = rest.get(index + 1..index + 4).filter(|digits| digits.bytes().all(|byte| byte.is_ascii_digit()));
+        match escape.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            Some(byte) => {
+                text.push(char::from(byte));
+                rest = &rest[index + 4..];
+            }
+            None => {
+                text.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+// Quotes text as a zone file's character strings, split into strings of at most 255 bytes
+fn quote(text: &str) -> String {
+    let mut strings = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        if current.len() + c.len_utf8() > 255 {
+            strings.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    strings.push(current);
+    strings
+        .iter()
+        .map(|string| format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Returns the contents of each <tag> element of XML text, in order; the answers read don't nest an element in one of the same name
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    let mut elements = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let contents = &rest[start + open.len()..];
+        let Some(end) = contents.find(&close) else {
+            break;
+        };
+        elements.push(&contents[..end]);
+        rest = &contents[end + close.len()..];
+    }
+    elements
+}
+
+// Returns the text of the first <tag> element of XML text, with its entities replaced
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let text = xml_elements(xml, tag).into_iter().next()?;
+    Some(
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
@@ -0,0 +1,372 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::net::UdpSocket;
+use tracing::*;
+
+// The keyword of the zone discovered devices are served in, as <name>.lan.<domain>.
+pub const ZONE: &str = "lan";
+
+// The TTL of the lan zone's records, short since devices come and go.
+pub const TTL: u32 = 60;
+
+// The multicast group and port of mDNS.
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+
+// The name DNS-SD answers with the service types announced on the link.
+const SERVICE_TYPES: &str = "_services._dns-sd._udp.local";
+
+// The kernel's ARP table on Linux.
+const ARP_TABLE: &str = "/proc/net/arp";
+
+// The most questions sent in one mDNS query, which keeps queries well within a datagram.
+const MAX_QUESTIONS: usize = 32;
+
+// The record types the lan zone reads from mDNS messages.
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+
+/*
+Description:
+a record of an mDNS message that the lan zone uses: an address of a host name, or a pointer from one name to another, as DNS-SD lists services with.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MdnsData {
+    // An A or AAAA record
+    Address(IpAddr),
+
+    // A PTR record, with the name it points to
+    Pointer(String),
+}
+
+/*
+Description:
+a record read from an mDNS message, with its name lower-cased and without its trailing dot.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MdnsRecord {
+    // The owner name, e.g. "printer.local"
+    pub name: String,
+
+    // The record's TTL in seconds; 0 means the record is withdrawn
+    pub ttl: u32,
+
+    // The record's data
+    pub data: MdnsData,
+}
+
+/*
+Description:
+the lan zone: the devices of the local network, discovered from the A and AAAA records they announce over mDNS (served as <host>.lan.<domain> for <host>.local) and from the kernel's ARP table (served as <mac>.lan.<domain>, e.g. 00-11-22-aa-bb-cc, for devices that announce no name). Both kinds are also served as PTR records for their addresses.
+*/
+#[derive(Debug, Default)]
+pub struct Lan {
+    // The addresses announced over mDNS for each host name, with when each of them expires
+    mdns: RwLock<HashMap<String, Vec<(IpAddr, Instant)>>>,
+
+    // The address of each device in the ARP table, by its MAC address
+    arp: RwLock<HashMap<String, IpAddr>>,
+}
+
+impl Lan {
+    // Returns the addresses of a name below the zone, e.g. "printer", or none if no device has it
+    pub fn addresses(&self, name: &str) -> Vec<IpAddr> {
+        let name = name.to_ascii_lowercase();
+        let now = Instant::now();
+        let mut addresses: Vec<IpAddr> = self.mdns.read().unwrap().get(&name).map_or_else(Vec::new, |learned| {
+            learned.iter().filter(|(_, expires)| *expires > now).map(|(address, _)| *address).collect()
+        });
+        addresses.extend(self.arp.read().unwrap().get(&name));
+        addresses
+    }
+
+    // Returns the names below the zone of an address, those announced over mDNS first, or none if no device has it
+    pub fn names_of(&self, address: IpAddr) -> Vec<String> {
+        let now = Instant::now();
+        let mut names: Vec<String> = self
+            .mdns
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, learned)| learned.iter().any(|(learned, expires)| *learned == address && *expires > now))
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        let arp = self.arp.read().unwrap();
+        names.extend(arp.iter().filter(|(_, learned)| **learned == address).map(|(name, _)| name.clone()));
+        names
+    }
+
+    // Returns the host names announced over mDNS that haven't expired, to ask for again before they do
+    pub fn hosts(&self) -> Vec<String> {
+        let now = Instant::now();
+        let mdns = self.mdns.read().unwrap();
+        mdns.iter().filter(|(_, learned)| learned.iter().any(|(_, expires)| *expires > now)).map(|(name, _)| name.clone()).collect()
+    }
+
+    /*
+    Description:
+    learns the addresses of the host names in mDNS records: an address is served until its TTL runs out, and a record with a TTL of 0 withdraws it at once. Records for names outside .local are ignored.
+
+    Parameters:
+    records: the records of an mDNS message.
+
+    Returns:
+    The number of addresses learned or withdrawn.
+    */
+    pub fn learn(&self, records: &[MdnsRecord]) -> usize {
+        let now = Instant::now();
+        let mut mdns = self.mdns.write().unwrap();
+        let mut count = 0;
+        for record in records {
+            let (MdnsData::Address(address), Some(host)) = (&record.data, record.name.strip_suffix(".local")) else {
+                continue;
+            };
+            let learned = mdns.entry(host.to_string()).or_default();
+            learned.retain(|(learned, expires)| learned != address && *expires > now);
+            if record.ttl > 0 {
+                learned.push((*address, now + Duration::from_secs(record.ttl.into())));
+            }
+            count += 1;
+        }
+        mdns.retain(|_, learned| !learned.is_empty());
+        count
+    }
+
+    /*
+    Description:
+    replaces the devices read from the ARP table with those of a table in the format of /proc/net/arp. Incomplete entries, which are devices that didn't answer, are left out.
+
+    Parameters:
+    table: the text of the table, with its header line.
+
+    Returns:
+    The number of devices now served from the ARP table.
+    */
+    pub fn load_arp(&self, table: &str) -> usize {
+        let mut arp = HashMap::new();
+        for line in table.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [address, _, flags, mac, ..] = fields[..] else {
+                continue;
+            };
+            // The ATF_COM flag marks a complete entry
+            let complete = u32::from_str_radix(flags.trim_start_matches("0x"), 16).is_ok_and(|flags| flags & 0x2 != 0);
+            let (Ok(address), true) = (address.parse::<IpAddr>(), complete) else {
+                continue;
+            };
+            if mac == "00:00:00:00:00:00" {
+                continue;
+            }
+            arp.insert(mac.to_ascii_lowercase().replace(':', "-"), address);
+        }
+        let count = arp.len();
+        *self.arp.write().unwrap() = arp;
+        count
+    }
+}
+
+/*
+Description:
+reads the A, AAAA and PTR records of an mDNS message, from its answer, authority and additional sections. The top bit of each record's class, which mDNS uses as the cache-flush flag, is ignored.
+
+Parameters:
+message: the message as it was received.
+
+Returns:
+Some(Vec) with the records, or None if the message is malformed.
+*/
+pub fn parse_mdns(message: &[u8]) -> Option<Vec<MdnsRecord>> {
+    let count = |offset: usize| Some(u16::from_be_bytes(message.get(offset..offset + 2)?.try_into().ok()?) as usize);
+    let questions = count(4)?;
+    let records = count(6)? + count(8)? + count(10)?;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        (_, offset) = read_name(message, offset)?;
+        offset += 4;
+    }
+
+    let mut parsed = Vec::new();
+    for _ in 0..records {
+        let (name, end) = read_name(message, offset)?;
+        let fixed = message.get(end..end + 10)?;
+        let record_type = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+        let length = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let rdata = message.get(end + 10..end + 10 + length)?;
+        let data = match record_type {
+            TYPE_A => <[u8; 4]>::try_from(rdata).ok().map(|octets| MdnsData::Address(Ipv4Addr::from(octets).into())),
+            TYPE_AAAA => <[u8; 16]>::try_from(rdata).ok().map(|octets| MdnsData::Address(Ipv6Addr::from(octets).into())),
+            TYPE_PTR => Some(MdnsData::Pointer(read_name(message, end + 10)?.0)),
+            _ => None,
+        };
+        parsed.extend(data.map(|data| MdnsRecord { name, ttl, data }));
+        offset = end + 10 + length;
+    }
+    Some(parsed)
+}
+
+// Reads a possibly compressed name at an offset of a message, returning it lower-cased without its trailing dot and the offset after it
+fn read_name(message: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Each pointer has to point backwards, so following them always ends; the limit only guards against malformed messages
+    for _ in 0..128 {
+        let length = *message.get(offset)? as usize;
+        match length {
+            0 => {
+                let name = labels.join(".").to_ascii_lowercase();
+                return Some((name, end.unwrap_or(offset + 1)));
+            }
+            _ if length & 0xc0 == 0xc0 => {
+                let pointer = (length & 0x3f) << 8 | *message.get(offset + 1)? as usize;
+                if pointer >= offset {
+                    return None;
+                }
+                end.get_or_insert(offset + 2);
+                offset = pointer;
+            }
+            _ if length < 64 => {
+                let label = message.get(offset + 1..offset + 1 + length)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + length;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/*
+Description:
+builds an mDNS query asking for records of names, to be sent to the mDNS group so that the answers are multicast for every listener to see.
+
+Parameters:
+questions: the names and the types of record asked for.
+
+Returns:
+The query as it is sent.
+*/
+pub fn mdns_query(questions: &[(&str, u16)]) -> Vec<u8> {
+    let mut query = vec![0; 12];
+    query[4..6].copy_from_slice(&(questions.len() as u16).to_be_bytes());
+    for (name, record_type) in questions {
+        for label in name.trim_end_matches('.').split('.').filter(|label| !label.is_empty()) {
+            let label = &label.as_bytes()[..label.len().min(63)];
+            query.push(label.len() as u8);
+            query.extend_from_slice(label);
+        }
+        query.push(0);
+        query.extend_from_slice(&record_type.to_be_bytes());
+        // Class IN
+        query.extend_from_slice(&1u16.to_be_bytes());
+    }
+    query
+}
+
+/*
+Description:
+keeps the lan zone up to date: listens to the mDNS group for the addresses devices announce, and every interval asks for the service types on the link, the instances of each, and the host names already known before they expire, so devices that announce only when asked are found too. The ARP table is read again at the same interval. If the mDNS port can't be bound (e.g. another responder holds it without sharing it), only the ARP table is used.
+
+Parameters:
+lan: the zone to keep up to date.
+interval: how often to scan the network.
+
+Returns:
+None; runs until the process exits.
+*/
+pub async fn discover(lan: Arc<Lan>, interval: Duration) {
+    let socket = match bind_mdns() {
+        Ok(socket) => Some(socket),
+        Err(error) => {
+            warn!("Failed to join the mDNS group, discovering LAN devices from the ARP table only: {error}");
+            None
+        }
+    };
+
+    let mut scan = tokio::time::interval(interval);
+    let mut service_types: HashSet<String> = HashSet::new();
+    let mut buffer = vec![0u8; 9000];
+    loop {
+        let received = async {
+            match &socket {
+                Some(socket) => socket.recv_from(&mut buffer).await.map(|(length, _)| length),
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = scan.tick() => {
+                match tokio::fs::read_to_string(ARP_TABLE).await {
+                    Ok(table) => debug!("Serving {} LAN devices from the ARP table", lan.load_arp(&table)),
+                    Err(error) => debug!("Failed to read the ARP table {ARP_TABLE}: {error}"),
+                }
+                if let Some(socket) = &socket {
+                    let mut questions: Vec<(String, u16)> = vec![(SERVICE_TYPES.to_string(), TYPE_PTR)];
+                    questions.extend(service_types.iter().map(|service_type| (service_type.clone(), TYPE_PTR)));
+                    for host in lan.hosts() {
+                        questions.push((format!("{host}.local"), TYPE_A));
+                        questions.push((format!("{host}.local"), TYPE_AAAA));
+                    }
+                    send_queries(socket, &questions).await;
+                }
+            }
+            received = received => {
+                let length = match received {
+                    Ok(length) => length,
+                    Err(error) => {
+                        warn!("Failed to receive an mDNS message: {error}");
+                        continue;
+                    }
+                };
+                let Some(records) = parse_mdns(&buffer[..length]) else {
+                    continue;
+                };
+                lan.learn(&records);
+
+                // Ask for the instances of service types seen for the first time straight away, rather than at the next scan
+                let new_types: Vec<(String, u16)> = records
+                    .iter()
+                    .filter(|record| record.name == SERVICE_TYPES)
+                    .filter_map(|record| match &record.data {
+                        MdnsData::Pointer(service_type) if service_types.insert(service_type.clone()) => Some((service_type.clone(), TYPE_PTR)),
+                        _ => None,
+                    })
+                    .collect();
+                if let (Some(socket), false) = (&socket, new_types.is_empty()) {
+                    send_queries(socket, &new_types).await;
+                }
+            }
+        }
+    }
+}
+
+// Sends queries for a list of questions to the mDNS group, a few questions per query
+async fn send_queries(socket: &UdpSocket, questions: &[(String, u16)]) {
+    for chunk in questions.chunks(MAX_QUESTIONS) {
+        let chunk: Vec<(&str, u16)> = chunk.iter().map(|(name, record_type)| (name.as_str(), *record_type)).collect();
+        if let Err(error) = socket.send_to(&mdns_query(&chunk), (MDNS_GROUP, MDNS_PORT)).await {
+            warn!("Failed to send an mDNS query: {error}");
+        }
+    }
+}
+
+// Binds the mDNS port, sharing it with any other responder on the host, and joins the mDNS group on the default interface
+fn bind_mdns() -> io::Result<UdpSocket> {
+    let address = SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT));
+    #[cfg(target_os = "linux")]
+    let socket = crate::shard::bind_udp(address)?;
+    #[cfg(not(target_os = "linux"))]
+    let socket = std::net::UdpSocket::bind(address)?;
+
+    socket.set_nonblocking(true)?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    UdpSocket::from_std(socket)
+}
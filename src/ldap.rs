@@ -0,0 +1,276 @@
+use crate::{options::Options, tasks};
+use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::*;
+use trust_dns_server::client::rr::{LowerName, Name};
+
+// How long connecting, binding or searching may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The largest number of hosts kept in the cache; expired hosts are dropped when it is full.
+const CACHE_CAPACITY: usize = 10000;
+
+/*
+Description:
+defines the errors that can occur while looking hosts up in the directory.
+*/
+#[derive(thiserror::Error, Debug)]
+pub enum LdapError {
+    #[error("LDAP error: {0:}")]
+    Ldap(#[from] ldap3::LdapError),
+}
+
+/*
+Description:
+the directory the hosts are looked up in: where it is, how to bind to it, how to find a host's entry and which of its attributes hold addresses, and the zone its hosts are served under.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Directory {
+    // The directory's URL, ldap:// or ldaps://
+    pub url: String,
+
+    // The DN to bind as and its password, or None to search anonymously
+    pub bind: Option<(String, String)>,
+
+    // The DN below which hosts are searched for
+    pub base: String,
+
+    // The search filter, in which {host} is replaced by the host's name below the zone and {fqdn} by its full name
+    pub filter: String,
+
+    // The attributes holding the hosts' IPv4 and IPv6 addresses
+    pub attributes: Vec<String>,
+
+    // The zone the hosts are served under, e.g. hosts.example.com
+    pub zone: String,
+
+    // The number of connections kept open
+    pub pool_size: usize,
+
+    // How long a host's addresses are cached and served for
+    pub ttl: u32,
+}
+
+impl Directory {
+    // Returns the directory the options choose, if any, serving its hosts under ldap.<domain> unless another zone is given
+    pub fn from_options(options: &Options) -> Option<Self> {
+        let url = options.ldap.clone()?;
+        Some(Directory {
+            url,
+            bind: options.ldap_bind_dn.clone().map(|dn| (dn, options.ldap_password.clone().unwrap_or_default())),
+            base: options.ldap_base.clone().unwrap_or_default(),
+            filter: options.ldap_filter.clone(),
+            attributes: options.ldap_attributes.clone(),
+            zone: options.ldap_zone.clone().unwrap_or_else(|| format!("ldap.{}", options.domain)),
+            pool_size: options.ldap_pool_size.max(1),
+            ttl: options.ldap_ttl,
+        })
+    }
+}
+
+// The addresses of a host as last looked up, or None if the directory had no entry for it
+#[derive(Clone, Debug)]
+struct Cached {
+    addresses: Option<Vec<IpAddr>>,
+    expires: Instant,
+}
+
+/*
+Description:
+the hosts of an LDAP directory, such as FreeIPA's or Active Directory's computer objects, served as A and AAAA records under a zone. Hosts are looked up as they are queried, over a small pool of connections that are opened when first needed and opened again when the directory closes them, and their addresses (or their absence) are cached for the records' TTL. If the directory can't be reached, hosts looked up before keep being served from the cache.
+*/
+#[derive(Debug)]
+pub struct LdapHosts {
+    // The directory's settings
+    directory: Directory,
+
+    // The zone the hosts are served under
+    zone: LowerName,
+
+    // The connections, each opened when it is first used
+    pool: Vec<tokio::sync::Mutex<Option<Ldap>>>,
+
+    // The connection the next search goes out on
+    next: AtomicUsize,
+
+    // The hosts looked up, by name below the zone
+    cache: Mutex<HashMap<String, Cached>>,
+}
+
+impl LdapHosts {
+    /*
+    Description:
+    sets up the lookups of a directory's hosts, without connecting to it yet.
+
+    Parameters:
+    directory: the directory.
+
+    Returns:
+    Ok(LdapHosts), or an error message if the zone isn't a valid name or the filter doesn't name the host.
+    */
+    pub fn new(directory: Directory) -> Result<Self, String> {
+        let zone = Name::from_str(&directory.zone).map_err(|error| format!("invalid zone {}: {error}", directory.zone))?;
+        if !directory.filter.contains("{host}") && !directory.filter.contains("{fqdn}") {
+            return Err(format!("the filter {} has neither {{host}} nor {{fqdn}} in it", directory.filter));
+        }
+        if directory.attributes.is_empty() {
+            return Err("no attributes are given to read addresses from".to_string());
+        }
+        Ok(LdapHosts {
+            zone: LowerName::from(zone),
+            pool: (0..directory.pool_size.max(1)).map(|_| tokio::sync::Mutex::new(None)).collect(),
+            next: AtomicUsize::new(0),
+            cache: Mutex::new(HashMap::new()),
+            directory,
+        })
+    }
+
+    // Returns whether a name is in the zone the hosts are served under
+    pub fn serves(&self, name: &LowerName) -> bool {
+        self.zone.zone_of(name)
+    }
+
+    // Returns the TTL of the hosts' records
+    pub fn ttl(&self) -> u32 {
+        self.directory.ttl
+    }
+
+    // Returns the name of a host below the zone, in lower case (e.g. "web01" for web01.<zone>), or None for the zone itself
+    pub fn host(&self, name: &LowerName) -> Option<String> {
+        let below = name.num_labels().checked_sub(self.zone.num_labels()).filter(|below| *below > 0)?;
+        let labels: Vec<String> = Name::from(name).iter().take(usize::from(below)).map(|label| String::from_utf8_lossy(label).to_ascii_lowercase()).collect();
+        Some(labels.join("."))
+    }
+
+    // Returns the search filter of a host, with its names escaped as RFC 4515 requires
+    pub fn filter(&self, host: &str) -> String {
+        let zone = self.zone.to_string();
+        let fqdn = format!("{host}.{}", zone.trim_end_matches('.'));
+        self.directory
+            .filter
+            .replace("{host}", &ldap3::ldap_escape(host))
+            .replace("{fqdn}", &ldap3::ldap_escape(fqdn.as_str()))
+    }
+
+    /*
+    Description:
+    looks up the addresses of a host, from the cache while they are fresh and from the directory otherwise.
+
+    Parameters:
+    host: the host's name below the zone, as host returns it.
+
+    Returns:
+    Ok(Some(Vec<IpAddr>)) with the addresses of the host's entries, Ok(None) if the directory has no entry for it, or an LdapError if the directory can't be searched and the host isn't cached.
+    */
+    pub async fn addresses(&self, host: &str) -> Result<Option<Vec<IpAddr>>, LdapError> {
+        let cached = self.cache.lock().unwrap().get(host).cloned();
+        if let Some(cached) = &cached {
+            if cached.expires > Instant::now() {
+                return Ok(cached.addresses.clone());
+            }
+        }
+
+        let addresses = match self.search(host).await {
+            Ok(addresses) => addresses,
+            Err(error) => match cached {
+                Some(cached) => {
+                    warn!("Failed to look {host} up in LDAP, serving its cached addresses: {error}");
+                    return Ok(cached.addresses);
+                }
+                None => return Err(error),
+            },
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        let now = Instant::now();
+        if cache.len() >= CACHE_CAPACITY {
+            cache.retain(|_, cached| cached.expires > now);
+        }
+        if cache.len() < CACHE_CAPACITY {
+            let expires = now + Duration::from_secs(u64::from(self.directory.ttl));
+            cache.insert(host.to_string(), Cached { addresses: addresses.clone(), expires });
+        }
+        Ok(addresses)
+    }
+
+    // Searches the directory for a host's entries, trying again on a new connection if the pooled one fails, as it does once the directory closes idle connections
+    async fn search(&self, host: &str) -> Result<Option<Vec<IpAddr>>, LdapError> {
+        let slot = &self.pool[self.next.fetch_add(1, Ordering::Relaxed) % self.pool.len()];
+        let filter = self.filter(host);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let result = match self.connection(slot).await {
+                Ok(mut ldap) => ldap
+                    .with_timeout(REQUEST_TIMEOUT)
+                    .search(&self.directory.base, Scope::Subtree, &filter, &self.directory.attributes)
+                    .await
+                    .and_then(|result| result.success()),
+                Err(error) => Err(error),
+            };
+            match result {
+                Ok((entries, _)) if entries.is_empty() => return Ok(None),
+                Ok((entries, _)) => {
+                    let entries: Vec<SearchEntry> = entries.into_iter().map(SearchEntry::construct).collect();
+                    return Ok(Some(addresses(&entries, &self.directory.attributes)));
+                }
+                Err(error) => {
+                    *slot.lock().await = None;
+                    if attempts >= 2 {
+                        return Err(error.into());
+                    }
+                    debug!("LDAP search failed, trying again on a new connection: {error}");
+                }
+            }
+        }
+    }
+
+    // Returns the connection of a slot of the pool, connecting and binding it if it isn't open
+    async fn connection(&self, slot: &tokio::sync::Mutex<Option<Ldap>>) -> Result<Ldap, ldap3::LdapError> {
+        let mut slot = slot.lock().await;
+        if let Some(ldap) = slot.as_mut() {
+            if !ldap.is_closed() {
+                return Ok(ldap.clone());
+            }
+        }
+
+        let settings = LdapConnSettings::new().set_conn_timeout(REQUEST_TIMEOUT);
+        let (connection, mut ldap) = LdapConnAsync::with_settings(settings, &self.directory.url).await?;
+        tasks::spawn("ldap-connection", async move {
+            if let Err(error) = connection.drive().await {
+                debug!("LDAP connection closed: {error}");
+            }
+        });
+        if let Some((dn, password)) = &self.directory.bind {
+            ldap.with_timeout(REQUEST_TIMEOUT).simple_bind(dn, password).await?.success()?;
+        }
+        *slot = Some(ldap.clone());
+        Ok(ldap)
+    }
+}
+
+// Returns the addresses in the attributes of entries, skipping (and logging) values that aren't addresses
+fn addresses(entries: &[SearchEntry], attributes: &[String]) -> Vec<IpAddr> {
+    let mut addresses = Vec::new();
+    for entry in entries {
+        // Attribute names are case-insensitive, and directories return them as they define them
+        let values = entry.attrs.iter().filter(|(name, _)| attributes.iter().any(|attribute| attribute.eq_ignore_ascii_case(name)));
+        for value in values.flat_map(|(_, values)| values) {
+            match value.trim().parse::<IpAddr>() {
+                Ok(address) if !addresses.contains(&address) => addresses.push(address),
+                Ok(_) => {}
+                Err(_) => debug!("Skipping {value} in the LDAP entry {}: not an address", entry.dn),
+            }
+        }
+    }
+    addresses
+}
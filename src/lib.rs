@@ -0,0 +1,97 @@
+pub mod acl;
+pub mod acme;
+pub mod admin;
+#[cfg(target_os = "linux")]
+pub mod affinity;
+pub mod alerts;
+pub mod anonymize;
+pub mod astronomy;
+pub mod audit;
+pub mod aws;
+pub mod blocklist;
+pub mod cache;
+pub mod calendar;
+pub mod chaos;
+pub mod checksum;
+pub mod cli;
+pub mod client;
+pub mod clients;
+pub mod clock;
+pub mod commands;
+pub mod config;
+pub mod conformance;
+pub mod counter;
+#[cfg(unix)]
+pub mod daemon;
+pub mod dashboard;
+pub mod delegation;
+pub mod ddns;
+pub mod docker;
+pub mod dnssec;
+pub mod dnstap;
+pub mod e164;
+pub mod export;
+pub mod failover;
+pub mod fx;
+pub mod geodns;
+pub mod geoip;
+pub mod handlers;
+pub mod health;
+pub mod hostsfile;
+pub mod httpd;
+pub mod import;
+pub mod lan;
+pub mod limits;
+#[cfg(feature = "ldap")]
+pub mod ldap;
+pub mod metrics;
+pub mod names;
+pub mod netcalc;
+pub mod options;
+pub mod overload;
+pub mod padding;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(unix)]
+pub mod privileges;
+#[cfg(feature = "sqlite")]
+pub mod querylog;
+pub mod random;
+pub mod records;
+#[cfg(feature = "redis")]
+pub mod redis_records;
+pub mod registry;
+pub mod rotation;
+pub mod rps;
+#[cfg(unix)]
+pub mod sandbox;
+pub mod sequences;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(target_os = "linux")]
+pub mod shard;
+pub mod stats;
+pub mod strings;
+pub mod tasks;
+pub mod tcp;
+pub mod templates;
+pub mod tenants;
+pub mod testing;
+pub mod topk;
+pub mod trace;
+pub mod tunneling;
+#[cfg(target_os = "linux")]
+pub mod udp_batch;
+pub mod webhooks;
+pub mod wireguard;
+pub mod zonefile;
+pub mod zonesync;
+
+// Swap the global allocator of the server and the benchmarks if an allocator feature is enabled
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static ALLOCATOR: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+#[global_allocator]
+static ALLOCATOR: mimalloc::MiMalloc = mimalloc::MiMalloc;
@@ -0,0 +1,177 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+// The longest a name may be written, without its trailing dot (RFC 1035).
+pub const MAX_NAME_LENGTH: usize = 253;
+
+// The most labels a name can have on the wire.
+pub const MAX_LABELS: u8 = 127;
+
+/*
+Description:
+the ways a query can break the limits, each saying what is wrong so the client can be told.
+*/
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum QueryViolation {
+    #[error("the query has {0} questions; exactly one is answered")]
+    QuestionCount(u16),
+    #[error("the name is {0} characters long, more than the {1} allowed")]
+    NameLength(usize, usize),
+    #[error("the name has {0} labels, more than the {1} allowed")]
+    LabelCount(usize, u8),
+    #[error("the label {0:?} holds {1}; labels are printable text")]
+    Character(String, &'static str),
+}
+
+/*
+Description:
+the limits every query is checked against before it reaches a zone: a single question, whose name is no longer and has no more labels than configured, written in printable characters. Labels may hold any printable ASCII character and UTF-8 text, which zones such as puny and sun take as arguments, but no control characters, spaces or dots, which would only confuse the zones that join labels back into names. The zones bound their own work on top of this, such as the most numbers a lotto draw or the largest n fib computes; the name limits bound the input every zone is handed.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct QueryLimits {
+    // The longest a query name may be written, without its trailing dot
+    pub max_name_length: usize,
+
+    // The most labels a query name may have
+    pub max_labels: u8,
+}
+
+impl QueryLimits {
+    // Checks that a query has exactly one question
+    pub fn check_question_count(&self, count: u16) -> Result<(), QueryViolation> {
+        match count {
+            1 => Ok(()),
+            count => Err(QueryViolation::QuestionCount(count)),
+        }
+    }
+
+    /*
+    Description:
+    checks a query name against the limits.
+
+    Parameters:
+    labels: the labels of the name, as they are on the wire.
+
+    Returns:
+    Ok(()) if the name is within the limits, or the first QueryViolation found.
+    */
+    pub fn check_name<'a>(&self, labels: impl IntoIterator<Item = &'a [u8]>) -> Result<(), QueryViolation> {
+        let labels: Vec<&[u8]> = labels.into_iter().collect();
+        if labels.len() > usize::from(self.max_labels) {
+            return Err(QueryViolation::LabelCount(labels.len(), self.max_labels));
+        }
+        let length = labels.iter().map(|label| label.len() + 1).sum::<usize>().saturating_sub(1);
+        if length > self.max_name_length {
+            return Err(QueryViolation::NameLength(length, self.max_name_length));
+        }
+        for label in labels {
+            let problem = match std::str::from_utf8(label) {
+                Err(_) => Some("invalid UTF-8"),
+                Ok(text) if text.contains(' ') => Some("a space"),
+                Ok(text) if text.contains('.') => Some("a dot"),
+                Ok(text) if text.chars().any(char::is_control) => Some("a control character"),
+                Ok(_) => None,
+            };
+            if let Some(problem) = problem {
+                return Err(QueryViolation::Character(String::from_utf8_lossy(label).into_owned(), problem));
+            }
+        }
+        Ok(())
+    }
+}
+
+/*
+Description:
+parses the --max-qname-length option: a length from 1 to the longest a name can be.
+
+Parameters:
+text: the option's value.
+
+Returns:
+Ok(usize), or an error if it is not a number from 1 to 253.
+*/
+pub fn parse_max_name_length(text: &str) -> Result<usize, String> {
+    match text.parse::<usize>() {
+        Ok(length) if (1..=MAX_NAME_LENGTH).contains(&length) => Ok(length),
+        _ => Err(format!("{text} is not a name length from 1 to {MAX_NAME_LENGTH}")),
+    }
+}
+
+/*
+Description:
+parses the --max-labels option: a label count from 1 to the most a name can have.
+
+Parameters:
+text: the option's value.
+
+Returns:
+Ok(u8), or an error if it is not a number from 1 to 127.
+*/
+pub fn parse_max_labels(text: &str) -> Result<u8, String> {
+    match text.parse::<u8>() {
+        Ok(labels) if (1..=MAX_LABELS).contains(&labels) => Ok(labels),
+        _ => Err(format!("{text} is not a label count from 1 to {MAX_LABELS}")),
+    }
+}
+
+/*
+Description:
+a token bucket limiting how many queries per second a domain answers: it holds a second's worth of queries and refills at the rate, so short bursts pass and sustained floods are refused. The queries it refuses are counted for the statistics.
+*/
+#[derive(Debug)]
+pub struct RateLimit {
+    // The most queries per second answered
+    rate: u32,
+
+    // The queries that may still be answered, and when the bucket was last refilled
+    bucket: Mutex<(f64, Instant)>,
+
+    // The queries refused so far
+    limited: AtomicU64,
+}
+
+impl RateLimit {
+    // Starts a full bucket for the given queries per second
+    pub fn new(rate: u32) -> Self {
+        RateLimit { rate, bucket: Mutex::new((f64::from(rate), Instant::now())), limited: AtomicU64::new(0) }
+    }
+
+    // Returns the most queries per second answered
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /*
+    Description:
+    takes a query out of the bucket after refilling it for the time since it was last refilled.
+
+    Parameters:
+    now: the time the query arrived.
+
+    Returns:
+    true if the query may be answered, or false if the bucket is empty, counting the query as refused.
+    */
+    pub fn allow(&self, now: Instant) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        let (tokens, last) = &mut *bucket;
+        let rate = f64::from(self.rate);
+        *tokens = (*tokens + now.saturating_duration_since(*last).as_secs_f64() * rate).min(rate);
+        *last = now.max(*last);
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            return true;
+        }
+        self.limited.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    // Returns the queries refused so far
+    pub fn limited(&self) -> u64 {
+        self.limited.load(Ordering::Relaxed)
+    }
+}
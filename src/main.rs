@@ -1,13 +1,34 @@
 use anyhow::Result;
 use clap::Parser;
-use handlers::Handler;
-use options::Options;
+use my_project::{
+    acme, admin, alerts,
+    audit::AuditEntry,
+    cli::{Cli, Command},
+    commands,
+    config::Config,
+    ddns, docker, export, failover,
+    hostsfile, lan,
+    handlers::Handler,
+    options::Options,
+    registry::{self, Backend},
+    stats, tasks, tcp,
+    wireguard::{self, Source},
+    zonesync,
+};
+#[cfg(feature = "postgres")]
+use my_project::postgres;
+#[cfg(feature = "redis")]
+use my_project::redis_records;
+#[cfg(unix)]
+use my_project::{daemon, privileges, sandbox};
+#[cfg(target_os = "linux")]
+use my_project::{affinity, shard, udp_batch};
 use std::time::Duration;
 use tokio::net::{TcpListener, UdpSocket};
-use trust_dns_server::ServerFuture;
-
-mod handlers;
-mod options;
+use trust_dns_server::{
+    client::rr::{LowerName, Name},
+    ServerFuture,
+};
 
 // This constant is used to set the timeout duration for TCP connections in the DNS server.
 // If a TCP connection takes longer than 10 seconds to complete, it will be closed.
@@ -17,46 +38,425 @@ const TCP_TIMEOUT: Duration = Duration::from_secs(10);
 
 /*
 Description:
-represents the core DNS server that listens to UDP and TCP connections and responds to DNS queries. The server uses the tokio runtime to asynchronously handle incoming connections. The code initializes a tracing_subscriber for logging and reads in command-line options using the Options struct. It then creates a Handler struct from the Options and initializes a ServerFuture with it. The server registers the UDP sockets and TCP listeners from the options, and then blocks until the server is done processing incoming connections.
+the entry point. It parses the command line and runs the subcommand: the DNS server for serve, or for the server's flags given without a subcommand, and otherwise one of the tools that go with it.
 
 Parameters:
 None
 
+Returns:
+Result<()>: A Result indicating whether the subcommand completed successfully or not; an error makes the process exit non-zero.
+*/
+
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Parse the command line
+    let cli = Cli::parse();
+
+    let options = match cli.command {
+        None => cli.serve,
+        Some(Command::Serve(options)) => *options,
+        Some(command) => {
+            // Report a tool's failure as a plain message, e.g. for check-config in a deployment pipeline
+            if let Err(error) = commands::run(command) {
+                eprintln!("Error: {error}");
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+    };
+
+    // Report the server's failure as a plain message too, e.g. a port it has no right to bind
+    if let Err(error) = run(options) {
+        eprintln!("Error: {error}");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/*
+Description:
+represents the core DNS server that listens to UDP and TCP connections and responds to DNS queries. The code takes the command-line options of the serve subcommand, initializes a tracing_subscriber for logging and loads the configuration file. It then detaches into the background and writes the pid file if requested, applies the Landlock sandbox if requested and sets no_new_privs if privileges will be dropped, both of which have to happen before the tokio runtime starts its worker threads, and runs the server on a new tokio runtime, sized and pinned to cores as requested.
+
+Parameters:
+options: the server's command-line options.
+
 Returns:
 Result<()>: A Result indicating whether the server completed successfully or not.
 */
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Initialize the logging framework
-    tracing_subscriber::fmt::init();
+fn run(mut options: Options) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+
+    // Initialize the logging framework at the chosen level, without colors when logging to a file
+    #[cfg(not(feature = "console"))]
+    tracing_subscriber::fmt()
+        .with_max_level(options.log_level)
+        .with_ansi(options.log_file.is_none() && !options.daemon)
+        .init();
+
+    // With the console feature, record the runtime's task instrumentation next to the log, which keeps its own level; the
+    // server that sends it to tokio-console starts on the runtime, since no thread may be started before daemonizing
+    #[cfg(feature = "console")]
+    let console = {
+        use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, Layer};
+        let (layer, server) = console_subscriber::ConsoleLayer::builder().server_addr(options.console_addr).build();
+        let log = tracing_subscriber::fmt::layer()
+            .with_ansi(options.log_file.is_none() && !options.daemon)
+            .with_filter(LevelFilter::from_level(options.log_level));
+        tracing_subscriber::registry().with(layer).with(log).init();
+        server
+    };
+
+    // Load the configuration file, if one was given
+    if let Some(path) = &options.config_path {
+        options.config = Config::load(path)?;
+    }
+
+    // Detach from the terminal, sending output to the log file, or just redirect the output to the log file
+    #[cfg(unix)]
+    if options.daemon {
+        daemon::daemonize(options.log_file.as_deref())?;
+    } else if let Some(path) = &options.log_file {
+        daemon::log_to(path)?;
+    }
+    #[cfg(not(unix))]
+    if options.daemon || options.log_file.is_some() {
+        return Err("--daemon and --log-file are only supported on Unix".into());
+    }
+
+    // Write the pid file, after daemonizing so it holds the daemon's process ID
+    #[cfg(unix)]
+    if let Some(path) = &options.pidfile {
+        daemon::write_pidfile(path)?;
+    }
+
+    // Restrict file system access before any other thread exists, so every thread inherits the restriction
+    if options.landlock {
+        #[cfg(target_os = "linux")]
+        sandbox::landlock(&options)?;
+        #[cfg(not(target_os = "linux"))]
+        return Err("--landlock is only supported on Linux".into());
+    }
+
+    // Forbid gaining privileges before any other thread exists, since the flag is per-thread and only new threads inherit it
+    #[cfg(target_os = "linux")]
+    if options.user.is_some() {
+        privileges::forbid_new_privileges()?;
+    }
+
+    // Size the tokio runtime as requested; with shards, which bring their own runtimes, it only runs the background tasks
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.max_blocking_threads(options.max_blocking_threads.max(1));
+    if options.shards > 0 {
+        runtime.worker_threads(1);
+    } else if options.worker_threads > 0 {
+        runtime.worker_threads(options.worker_threads);
+    }
+
+    // Pin the worker threads to their own cores; shards pin their own threads instead
+    if options.pin_workers && options.shards == 0 {
+        #[cfg(target_os = "linux")]
+        {
+            let cpus = affinity::allowed_cpus()?;
+            let workers = match options.worker_threads {
+                0 => std::thread::available_parallelism().map_or(1, |cores| cores.get()),
+                workers => workers,
+            };
+            let started = std::sync::atomic::AtomicUsize::new(0);
+            runtime.on_thread_start(move || {
+                // The runtime starts its workers before any other thread, so only the first threads are pinned; later ones run blocking work
+                let index = started.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                if index >= workers || cpus.is_empty() {
+                    return;
+                }
+                if let Err(error) = affinity::pin_current_thread(cpus[index % cpus.len()]) {
+                    tracing::warn!("Failed to pin worker thread {index} to CPU {}: {error}", cpus[index % cpus.len()]);
+                }
+            });
+        }
+        #[cfg(not(target_os = "linux"))]
+        return Err("--pin-workers is only supported on Linux".into());
+    }
+
+    let runtime = runtime.enable_all().build()?;
+
+    // Serve the task instrumentation to tokio-console
+    #[cfg(feature = "console")]
+    runtime.spawn(async move {
+        if let Err(error) = console.serve().await {
+            tracing::warn!("Failed to serve tokio-console: {error}");
+        }
+    });
+
+    runtime.block_on(serve(options))
+}
+
+/*
+Description:
+runs the DNS server. It creates a Handler struct from the Options and initializes a ServerFuture with it. The server registers the UDP sockets and TCP listeners from the options, confines and de-privileges itself if requested, and then blocks until the server is done processing incoming connections.
+
+Parameters:
+options: the command-line options, with the configuration file loaded.
 
-    // Parse the command-line options
-    let options = Options::parse();
+Returns:
+Result<()>: A Result indicating whether the server completed successfully or not.
+*/
 
+async fn serve(options: Options) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Create a handler for the DNS server based on the options
     let handler = Handler::from_options(&options);
 
+    // Start the admin HTTP API if an address was given
+    if let Some(admin) = options.admin {
+        let listener = TcpListener::bind(admin).await.map_err(|error| bind_error(admin, error))?;
+        tasks::spawn("admin", admin::serve(listener, handler.clone()));
+    }
+
+    // Start the ACME API if an address was given and its accounts could be loaded
+    if let (Some(address), Some(_)) = (options.acme_api, &handler.acme) {
+        let listener = TcpListener::bind(address).await.map_err(|error| bind_error(address, error))?;
+        tasks::spawn("acme", acme::serve(listener, handler.clone()));
+    }
+
+    // Start the DynDNS update API if an address was given and the hosts file could be read
+    if let (Some(address), Some(_)) = (options.ddns_api, &handler.ddns) {
+        let listener = TcpListener::bind(address).await.map_err(|error| bind_error(address, error))?;
+        tasks::spawn("ddns", ddns::serve(listener, handler.clone()));
+    }
+
+    // Follow the Docker engine's containers if a Docker socket was given
+    if let (Some(socket), Some(docker)) = (options.docker_socket.clone(), &handler.docker) {
+        tasks::spawn("docker-watch", docker::watch(docker.clone(), socket));
+    }
+
+    // Read the hosts files again whenever they change, if any were given
+    if let Some(hosts) = &handler.hosts {
+        tasks::spawn("hosts-watch", hostsfile::watch(hosts.clone()));
+    }
+
+    // Discover the LAN's devices if LAN discovery is enabled
+    if let Some(lan) = &handler.lan {
+        tasks::spawn("lan-discovery", lan::discover(lan.clone(), Duration::from_secs(options.lan_scan_interval.max(1))));
+    }
+
+    // Read the WireGuard peers periodically if a configuration file or interface was given
+    if let (Some(source), Some(wg)) = (Source::from_options(&options), &handler.wireguard) {
+        let interval = Duration::from_secs(options.wireguard_refresh.max(1));
+        tasks::spawn("wireguard-refresh", wireguard::refresh(wg.clone(), source, interval));
+    }
+
+    // Load the records from PostgreSQL and follow its notifications if a database was given
+    #[cfg(feature = "postgres")]
+    if let (Some(source), Some(records)) = (postgres::Source::from_options(&options), &handler.records) {
+        tasks::spawn("postgres", postgres::follow(records.clone(), source));
+    }
+
+    // Read the records published in Redis periodically if asked to
+    #[cfg(feature = "redis")]
+    if let (Some(url), Some(records), true) = (&options.redis_url, &handler.records, options.redis_records) {
+        let interval = Duration::from_secs(options.redis_records_refresh.max(1));
+        tasks::spawn("redis-records", redis_records::follow(records.clone(), url.clone(), options.redis_records_prefix.clone(), interval));
+    }
+
+    // Fetch the zone files and keep them up to date, if any were given
+    if let Some(records) = &handler.records {
+        let interval = Duration::from_secs(options.zone_refresh.max(1));
+        for source in &options.zone_file {
+            tasks::spawn("zone-refresh", zonesync::follow(records.clone(), source.clone(), options.zone_cache_dir.clone(), interval));
+        }
+    }
+
+    // Check the failover targets if the config file has any
+    if let Some(failover) = &handler.failover {
+        failover::spawn(failover);
+    }
+
+    // Follow Consul or etcd if one was given
+    if let (Some(backend), Some(registry)) = (Backend::from_options(&options), &handler.registry) {
+        tasks::spawn("registry-watch", registry::watch(registry.clone(), backend));
+    }
+
+    // Reload the runtime-reloadable parts of the configuration file on SIGHUP
+    #[cfg(unix)]
+    if let Some(path) = options.config_path.clone() {
+        tasks::spawn("config-reload", reload_on_sighup(path, handler.clone()));
+    }
+
+    // Dump the server's counters on SIGUSR1
+    #[cfg(unix)]
+    tasks::spawn("stats-dump", stats::dump_on_sigusr1(handler.clone(), options.stats_file.clone()));
+
+    // Push metrics to StatsD or Graphite if a server was given
+    let export_target = options
+        .statsd
+        .map(export::Target::Statsd)
+        .or(options.graphite.map(export::Target::Graphite));
+    if let Some(target) = export_target {
+        let interval = Duration::from_secs(options.metrics_flush_interval.max(1));
+        export::spawn(handler.clone(), target, options.metrics_prefix.clone(), interval);
+    }
+
+    // Watch the alert thresholds if the config file has an [alerts] section
+    if let Some(alerts) = &options.config.alerts {
+        alerts::spawn(handler.clone(), alerts.clone(), options.domain.clone());
+    }
+
+    // Keep the health state to mark the server ready once its sockets are bound
+    let health = handler.health.clone();
+
+    // Keep a handler for the sockets served outside ServerFuture: batched UDP sockets and shards
+    let outside_handler = handler.clone();
+    let mut outside = tokio::task::JoinSet::new();
+
     // Create a new DNS server
     let mut server = ServerFuture::new(handler);
+    let mut uses_server = false;
+
+    // The budget of every TCP listener
+    let tcp_limits = tcp::Limits {
+        idle_timeout: TCP_TIMEOUT,
+        max_connections: options.tcp_max_connections,
+        max_per_client: options.tcp_max_per_client,
+        max_in_flight: options.tcp_max_in_flight,
+    };
+
+    if options.shards > 0 {
+        // Give every shard its own sockets on the same ports, its own runtime and its own slot of the counter
+        #[cfg(target_os = "linux")]
+        let cpus = if options.pin_workers { affinity::allowed_cpus()? } else { Vec::new() };
+        #[cfg(target_os = "linux")]
+        for index in 0..options.shards {
+            let udp = options.udp.iter().map(|address| shard::bind_udp(*address).map_err(|error| bind_error(*address, error))).collect::<Result<_, _>>()?;
+            let tcp = options.tcp.iter().map(|address| shard::bind_tcp(*address).map_err(|error| bind_error(*address, error))).collect::<Result<_, _>>()?;
+            let handler = outside_handler.for_shard(index);
+            let cpu = (!cpus.is_empty()).then(|| cpus[index % cpus.len()]);
+            outside.spawn(shard::spawn(index, handler, udp, tcp, tcp_limits, options.udp_batch, cpu)?);
+        }
+        #[cfg(not(target_os = "linux"))]
+        return Err("--shards is only supported on Linux".into());
+    } else {
+        // Register UDP sockets with the server, or serve them with batched system calls if requested
+        for udp in &options.udp {
+            let socket = UdpSocket::bind(udp).await.map_err(|error| bind_error(*udp, error))?;
+            if options.udp_batch > 0 {
+                #[cfg(target_os = "linux")]
+                {
+                    outside.spawn(udp_batch::serve(socket, outside_handler.clone(), options.udp_batch));
+                    continue;
+                }
+                #[cfg(not(target_os = "linux"))]
+                return Err("--udp-batch is only supported on Linux".into());
+            }
+            server.register_socket(socket);
+            uses_server = true;
+        }
+
+        // Register TCP listeners with the server, or serve them within their budget if one is set
+        for tcp in &options.tcp {
+            let listener = TcpListener::bind(tcp).await.map_err(|error| bind_error(*tcp, error))?;
+            if tcp_limits.enabled() {
+                outside.spawn(tcp::serve(listener, outside_handler.clone(), tcp_limits));
+                continue;
+            }
+            server.register_listener(listener, TCP_TIMEOUT);
+            uses_server = true;
+        }
+    }
 
-    // Register UDP sockets with the server
-    for udp in &options.udp {
-        let socket_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), *udp);
-        let socket = UdpSocket::bind(socket_addr).await?;
-        server.register_socket(socket);
+    // Every socket is bound, so confine the server to the chroot directory if requested
+    if let Some(dir) = &options.chroot {
+        #[cfg(unix)]
+        sandbox::chroot(dir)?;
+        #[cfg(not(unix))]
+        return Err(format!("--chroot {} is only supported on Unix", dir.display()).into());
     }
 
-    // Register TCP listeners with the server
-    for tcp in &options.tcp {
-        let listener_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), *tcp);
-        let listener = TcpListener::bind(&listener_addr).await?;
-        server.register_listener(listener, TCP_TIMEOUT);
+    // Switch to the unprivileged user and group if requested
+    if options.user.is_some() || options.group.is_some() {
+        #[cfg(unix)]
+        privileges::drop_privileges(options.user.as_deref(), options.group.as_deref())?;
+        #[cfg(not(unix))]
+        return Err("--user and --group are only supported on Unix".into());
     }
 
-    // Block until the server is done processing incoming connections
-    server.block_until_done().await?;
+    // Every socket is bound, so the server can answer queries now
+    health.set_ready();
+
+    // Block until the server is done processing incoming connections, then until the sockets served outside it are
+    if uses_server {
+        server.block_until_done().await?;
+    }
+    while let Some(result) = outside.join_next().await {
+        result??;
+    }
 
     // The server completed successfully
     Ok(())
-}
\ No newline at end of file
+}
+
+// Explains a failure to bind an address, such as a privileged port the server has no right to bind
+fn bind_error(address: std::net::SocketAddr, error: std::io::Error) -> Box<dyn std::error::Error + Send + Sync> {
+    #[cfg(unix)]
+    return privileges::bind_error(address, error);
+    #[cfg(not(unix))]
+    return Box::new(error);
+}
+
+/*
+Description:
+waits for SIGHUP and reloads the parts of the configuration file that can change at runtime, currently the blocklist settings (sources, compiled list, response, allowlist and exemptions). A file that fails to load is reported and the running configuration is kept.
+
+Parameters:
+path: the configuration file.
+handler: the DNS handler whose settings are reloaded.
+
+Returns:
+None; runs until the process exits.
+*/
+#[cfg(unix)]
+async fn reload_on_sighup(path: std::path::PathBuf, handler: Handler) {
+    use tokio::signal::unix::{signal, SignalKind};
+    use tracing::*;
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(error) => {
+            warn!("Failed to listen for SIGHUP, config reloading is disabled: {error}");
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        let outcome = match Config::load(&path) {
+            Ok(config) => {
+                match (&handler.blocklist, &config.blocklist) {
+                    (Some(blocklist), Some(settings)) => blocklist.reload(settings),
+                    (None, Some(_)) => warn!("A [blocklist] section was added; restart the server to enable blocking"),
+                    _ => {}
+                }
+
+                // Reload the hosted domains' blocklists from their [[tenants]] sections
+                for tenant in handler.tenants.iter().flat_map(|tenants| tenants.iter()) {
+                    let section = config
+                        .tenants
+                        .iter()
+                        .find(|section| Name::parse(&section.domain, Some(&Name::root())).is_ok_and(|domain| LowerName::new(&domain) == tenant.domain));
+                    match (&tenant.handler.blocklist, section.and_then(|section| section.blocklist.as_ref())) {
+                        (Some(blocklist), Some(settings)) => blocklist.reload(settings),
+                        (None, Some(_)) => warn!("A blocklist was added for {}; restart the server to enable blocking", tenant.domain),
+                        _ => {}
+                    }
+                }
+                info!("Reloaded {}", path.display());
+                "ok".to_string()
+            }
+            Err(error) => {
+                error!("Failed to reload config, keeping the current one: {error}");
+                format!("failed: {error}")
+            }
+        };
+
+        // Record the reload in the audit log
+        if let Some(audit) = &handler.audit {
+            audit.record(AuditEntry::now("SIGHUP", "local", "config-reload", path.display().to_string(), outcome));
+        }
+    }
+}
@@ -0,0 +1,458 @@
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use trust_dns_server::{
+    authority::MessageResponse,
+    client::rr::Record,
+    proto::op::ResponseCode,
+    server::{ResponseHandler, ResponseInfo},
+};
+
+// The number of seconds of per-second query counts kept for the QPS history.
+const QPS_WINDOW: usize = 60;
+
+// The number of most recent full seconds averaged for the current QPS.
+const QPS_AVERAGE: usize = 10;
+
+// The number of recent queries kept for the dashboard.
+const RECENT_QUERIES: usize = 50;
+
+// The number of histogram buckets: 16 exact ones for 0-15, then 8 per power of two up to 2^64.
+const HISTOGRAM_BUCKETS: usize = 16 + 8 * 60;
+
+// The largest number of zones counted separately. Zones are named after the first label of the query name,
+// so without a limit a client could grow the map with made-up labels; queries beyond it are counted under "other".
+const MAX_ZONES: usize = 256;
+
+/*
+Description:
+the in-memory metrics registry: per-second query counts for the QPS graph, query and error counts per zone, and the most recent queries. It is updated once per query by the handler and read by the admin API's dashboard.
+*/
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+// The registry's state, behind a single lock since every query touches all of it
+#[derive(Debug, Default)]
+struct Inner {
+    // Query counts for the last QPS_WINDOW seconds, oldest first, and the Unix second of the last entry
+    per_second: VecDeque<u64>,
+    current_second: u64,
+
+    // Query and error counts per zone
+    zones: HashMap<String, ZoneMetrics>,
+
+    // Handler and total latency histograms per zone
+    zone_latency: HashMap<String, (Histogram, Histogram)>,
+
+    // Total latency histograms per transport (udp, tcp, ...)
+    transport_latency: HashMap<String, Histogram>,
+
+    // The most recent queries, newest last
+    recent: VecDeque<RecentQuery>,
+}
+
+/*
+Description:
+the traffic counters of a single zone.
+*/
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ZoneMetrics {
+    // The number of queries answered by the zone
+    pub queries: u64,
+
+    // The number of those queries that were answered with an error (SERVFAIL, REFUSED, ...)
+    pub errors: u64,
+
+    // The number of those errors that were SERVFAIL
+    pub servfails: u64,
+
+    // The time spent answering the zone's queries, in microseconds
+    pub total_latency_us: u64,
+}
+
+/*
+Description:
+an HDR-style latency histogram in microseconds. Values are counted in log-linear buckets, eight per power of two, so every recorded value is known to within 12.5% in fixed memory, whatever the range of latencies.
+*/
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    // The number of values per bucket
+    counts: Vec<u64>,
+
+    // The number of values recorded
+    count: u64,
+
+    // The largest value recorded
+    max: u64,
+}
+
+/*
+Description:
+the percentiles of a latency histogram, in microseconds.
+*/
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Percentiles {
+    // The number of values the percentiles are based on
+    pub count: u64,
+
+    // The median, 95th and 99th percentiles
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+
+    // The largest value recorded
+    pub max: u64,
+}
+
+impl Histogram {
+    // Creates an empty histogram
+    pub fn new() -> Self {
+        Histogram {
+            counts: vec![0; HISTOGRAM_BUCKETS],
+            count: 0,
+            max: 0,
+        }
+    }
+
+    // Records a value
+    pub fn record(&mut self, value: u64) {
+        self.counts[bucket(value)] += 1;
+        self.count += 1;
+        self.max = self.max.max(value);
+    }
+
+    /*
+    Description:
+    computes the usual percentiles. Each percentile is reported as the upper bound of the bucket it falls in, capped at the largest value recorded, so it never understates the latency.
+
+    Parameters:
+    None
+
+    Returns:
+    The Percentiles, all zero for an empty histogram.
+    */
+    pub fn percentiles(&self) -> Percentiles {
+        let percentile = |fraction: f64| {
+            let rank = ((self.count as f64 * fraction).ceil() as u64).max(1);
+            let mut seen = 0;
+            for (index, count) in self.counts.iter().enumerate() {
+                seen += count;
+                if seen >= rank {
+                    return bucket_upper_bound(index).min(self.max);
+                }
+            }
+            self.max
+        };
+
+        if self.count == 0 {
+            return Percentiles::default();
+        }
+        Percentiles {
+            count: self.count,
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max: self.max,
+        }
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Returns the bucket of a value: values below 16 have their own bucket, larger ones share a bucket with the values that agree in their top four bits
+fn bucket(value: u64) -> usize {
+    if value < 16 {
+        return value as usize;
+    }
+    let shift = 63 - value.leading_zeros() as usize - 3;
+    8 * shift + (value >> shift) as usize
+}
+
+// Returns the largest value that falls in a bucket
+fn bucket_upper_bound(index: usize) -> u64 {
+    if index < 16 {
+        return index as u64;
+    }
+    let shift = index / 8 - 1;
+    let mantissa = (index % 8 + 8) as u64;
+    ((mantissa + 1) << shift).saturating_sub(1)
+}
+
+/*
+Description:
+the latency percentiles of a zone: the time until the handler started sending its answer, and the total time until the answer was sent.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct ZoneLatency {
+    // The zone's keyword, "root" or "other"
+    pub zone: String,
+
+    // The time until the handler started sending its answer
+    pub handler: Percentiles,
+
+    // The time until the answer was sent
+    pub total: Percentiles,
+}
+
+/*
+Description:
+the total latency percentiles of a transport.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct TransportLatency {
+    // The transport, e.g. "udp" or "tcp"
+    pub transport: String,
+
+    // The time until the answer was sent
+    pub total: Percentiles,
+}
+
+/*
+Description:
+a single query as shown in the dashboard's recent queries list.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct RecentQuery {
+    // Unix timestamp of the query in milliseconds
+    pub timestamp: i64,
+
+    // The client's IP address
+    pub client: IpAddr,
+
+    // The query name
+    pub qname: String,
+
+    // The query type, e.g. "TXT"
+    pub qtype: String,
+
+    // The zone that answered, or "other" for names outside every zone
+    pub zone: String,
+
+    // The response code, e.g. "No Error"
+    pub rcode: String,
+
+    // How long the query took to answer, in microseconds
+    pub latency_us: u64,
+}
+
+/*
+Description:
+a snapshot of the metrics for the admin API.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct MetricsSnapshot {
+    // Queries per second, averaged over the last few full seconds
+    pub qps: f64,
+
+    // Query counts for each of the last QPS_WINDOW seconds, oldest first
+    pub qps_history: Vec<u64>,
+
+    // Query and error counts per zone, busiest first
+    pub zones: Vec<(String, ZoneMetrics)>,
+
+    // The most recent queries, newest first
+    pub recent: Vec<RecentQuery>,
+
+    // Latency percentiles per zone, busiest first
+    pub zone_latency: Vec<ZoneLatency>,
+
+    // Latency percentiles per transport
+    pub transport_latency: Vec<TransportLatency>,
+}
+
+impl Metrics {
+    /*
+    Description:
+    records an answered query.
+
+    Parameters:
+    query: the query, its zone, outcome and total latency.
+    transport: the transport the query arrived over, e.g. "udp".
+    handler_us: how long the handler took before it started sending the answer, in microseconds.
+    rcode: the response code of the answer; anything but NOERROR and NXDOMAIN counts as an error.
+
+    Returns:
+    None
+    */
+    pub fn record(&self, query: RecentQuery, transport: &str, handler_us: u64, rcode: ResponseCode) {
+        let mut inner = self.inner.lock().unwrap();
+
+        // Count the query in the current second
+        let now = unix_seconds();
+        inner.advance(now);
+        if let Some(count) = inner.per_second.back_mut() {
+            *count += 1;
+        }
+
+        // Count the query for its zone, lumping zones beyond the limit together
+        let zone = if inner.zones.len() < MAX_ZONES || inner.zones.contains_key(&query.zone) {
+            query.zone.clone()
+        } else {
+            "other".to_string()
+        };
+        let counters = inner.zones.entry(zone.clone()).or_default();
+        counters.queries += 1;
+        counters.errors += u64::from(rcode != ResponseCode::NoError && rcode != ResponseCode::NXDomain);
+        counters.servfails += u64::from(rcode == ResponseCode::ServFail);
+        counters.total_latency_us += query.latency_us;
+
+        // Record the latencies for the zone and the transport
+        let (handler, total) = inner.zone_latency.entry(zone).or_default();
+        handler.record(handler_us);
+        total.record(query.latency_us);
+        match inner.transport_latency.get_mut(transport) {
+            Some(histogram) => histogram.record(query.latency_us),
+            None => {
+                let mut histogram = Histogram::new();
+                histogram.record(query.latency_us);
+                inner.transport_latency.insert(transport.to_string(), histogram);
+            }
+        }
+
+        // Remember the query, forgetting the oldest one
+        if inner.recent.len() >= RECENT_QUERIES {
+            inner.recent.pop_front();
+        }
+        inner.recent.push_back(query);
+    }
+
+    /*
+    Description:
+    takes a snapshot of the metrics.
+
+    Parameters:
+    None
+
+    Returns:
+    A MetricsSnapshot.
+    */
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut inner = self.inner.lock().unwrap();
+        inner.advance(unix_seconds());
+
+        // Average the full seconds before the current one
+        let full_seconds: Vec<u64> = inner.per_second.iter().rev().skip(1).take(QPS_AVERAGE).copied().collect();
+        let qps = if full_seconds.is_empty() {
+            0.0
+        } else {
+            full_seconds.iter().sum::<u64>() as f64 / full_seconds.len() as f64
+        };
+
+        let mut zones: Vec<(String, ZoneMetrics)> = inner.zones.iter().map(|(zone, counters)| (zone.clone(), counters.clone())).collect();
+        zones.sort_by_key(|(_, counters)| std::cmp::Reverse(counters.queries));
+
+        let zone_latency = zones
+            .iter()
+            .filter_map(|(zone, _)| {
+                let (handler, total) = inner.zone_latency.get(zone)?;
+                Some(ZoneLatency {
+                    zone: zone.clone(),
+                    handler: handler.percentiles(),
+                    total: total.percentiles(),
+                })
+            })
+            .collect();
+        let mut transport_latency: Vec<TransportLatency> = inner
+            .transport_latency
+            .iter()
+            .map(|(transport, total)| TransportLatency {
+                transport: transport.clone(),
+                total: total.percentiles(),
+            })
+            .collect();
+        transport_latency.sort_by(|a, b| a.transport.cmp(&b.transport));
+
+        MetricsSnapshot {
+            qps,
+            qps_history: inner.per_second.iter().copied().collect(),
+            zones,
+            recent: inner.recent.iter().rev().cloned().collect(),
+            zone_latency,
+            transport_latency,
+        }
+    }
+}
+
+impl Inner {
+    // Moves the per-second counts forward to the given second, adding empty seconds for any gap
+    fn advance(&mut self, now: u64) {
+        if self.per_second.is_empty() {
+            self.per_second.push_back(0);
+            self.current_second = now;
+            return;
+        }
+
+        let elapsed = now.saturating_sub(self.current_second).min(QPS_WINDOW as u64);
+        for _ in 0..elapsed {
+            if self.per_second.len() >= QPS_WINDOW {
+                self.per_second.pop_front();
+            }
+            self.per_second.push_back(0);
+        }
+        self.current_second = self.current_second.max(now);
+    }
+}
+
+// Returns the current Unix time in whole seconds
+fn unix_seconds() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/*
+Description:
+a response handler that notes when the handler starts sending its answer, so the time spent in the handler can be told apart from the time spent sending.
+*/
+#[derive(Clone)]
+pub struct TimedResponder<R: ResponseHandler> {
+    // The wrapped response handler
+    inner: R,
+
+    // When send_response was called, shared with the caller
+    sent: Arc<Mutex<Option<Instant>>>,
+}
+
+impl<R: ResponseHandler> TimedResponder<R> {
+    /*
+    Description:
+    wraps a response handler.
+
+    Parameters:
+    inner: the handler that sends the response to the client.
+
+    Returns:
+    The TimedResponder and the slot that receives the time the answer started being sent.
+    */
+    pub fn new(inner: R) -> (Self, Arc<Mutex<Option<Instant>>>) {
+        let sent = Arc::new(Mutex::new(None));
+        (TimedResponder { inner, sent: sent.clone() }, sent)
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: ResponseHandler> ResponseHandler for TimedResponder<R> {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        *self.sent.lock().unwrap() = Some(Instant::now());
+        self.inner.send_response(response).await
+    }
+}
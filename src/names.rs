@@ -0,0 +1,55 @@
+use crate::random::Random;
+use rand::seq::SliceRandom;
+
+// The largest number of names that can be generated in one query.
+pub const MAX_NAMES: usize = 20;
+
+// Adjectives for the first half of a generated name, in the spirit of Docker's container names.
+const ADJECTIVES: &[&str] = &[
+    "admiring", "adoring", "affectionate", "agitated", "amazing", "angry", "awesome", "beautiful",
+    "blissful", "bold", "boring", "brave", "busy", "charming", "clever", "cool", "compassionate",
+    "competent", "condescending", "confident", "cranky", "crazy", "dazzling", "determined",
+    "distracted", "dreamy", "eager", "ecstatic", "elastic", "elated", "elegant", "eloquent", "epic",
+    "exciting", "fervent", "festive", "flamboyant", "focused", "friendly", "frosty", "funny",
+    "gallant", "gifted", "goofy", "gracious", "great", "happy", "hardcore", "heuristic", "hopeful",
+    "hungry", "infallible", "inspiring", "intelligent", "interesting", "jolly", "jovial", "keen",
+    "kind", "laughing", "loving", "lucid", "magical", "modest", "musing", "mystifying", "naughty",
+    "nervous", "nice", "nifty", "nostalgic", "objective", "optimistic", "peaceful", "pedantic",
+    "pensive", "practical", "priceless", "quirky", "quizzical", "recursing", "relaxed", "reverent",
+    "romantic", "sad", "serene", "sharp", "silly", "sleepy", "stoic", "strange", "stupefied",
+    "suspicious", "sweet", "tender", "thirsty", "trusting", "unruffled", "upbeat", "vibrant",
+    "vigilant", "vigorous", "wizardly", "wonderful", "xenodochial", "youthful", "zealous", "zen",
+];
+
+// Surnames of notable scientists and engineers for the second half of a generated name.
+const NOUNS: &[&str] = &[
+    "albattani", "allen", "archimedes", "babbage", "banach", "bardeen", "bartik", "bell", "bhabha",
+    "bohr", "booth", "bose", "cannon", "carson", "cerf", "chandrasekhar", "clarke", "curie",
+    "darwin", "dijkstra", "einstein", "euclid", "euler", "faraday", "fermat", "fermi", "feynman",
+    "franklin", "galileo", "gauss", "goldberg", "goodall", "hamilton", "hawking", "heisenberg",
+    "hertz", "hodgkin", "hopper", "hypatia", "jackson", "johnson", "kalam", "kepler", "khorana",
+    "knuth", "kowalevski", "lamarr", "lamport", "leakey", "liskov", "lovelace", "lumiere",
+    "mayer", "mccarthy", "mcclintock", "meitner", "mendel", "mirzakhani", "morse", "napier",
+    "newton", "nightingale", "nobel", "noether", "pascal", "pasteur", "payne", "perlman", "pike",
+    "poincare", "ptolemy", "raman", "ramanujan", "ride", "ritchie", "rosalind", "saha", "shannon",
+    "shockley", "sinoussi", "stallman", "swartz", "tesla", "thompson", "torvalds", "turing",
+    "varahamihira", "villani", "wescoff", "wiles", "williams", "wilson", "wing", "wozniak",
+    "wright", "yalow", "yonath",
+];
+
+/*
+Description:
+generates a random Docker-style name made of an adjective and a scientist's surname, e.g. "brave-turing".
+
+Parameters:
+random: the source to draw from.
+
+Returns:
+A String with the generated name.
+*/
+pub fn random_name(random: &Random) -> String {
+    // Both lists are non-empty, so choose always succeeds
+    let (adjective, noun) = random.with_rng(|rng| (ADJECTIVES.choose(rng).unwrap(), NOUNS.choose(rng).unwrap()));
+
+    format!("{adjective}-{noun}")
+}
@@ -0,0 +1,164 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/*
+Description:
+defines the ways a cidr query can be malformed, each with the explanation its answer carries.
+*/
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum QueryError {
+    #[error("expected <address>.<prefix length> below the cidr zone, e.g. 10.0.0.0.24, 10-0-0-0.24 or 2001-db8--.32, got {0}")]
+    Layout(String),
+    #[error("{0} is not an IP address")]
+    Address(String),
+    #[error("{0} is not a prefix length")]
+    PrefixLength(String),
+    #[error("prefix length {0} is out of range: {1} prefixes go from 0 to {2}")]
+    PrefixRange(String, &'static str, u8),
+}
+
+/*
+Description:
+takes a cidr query apart into the address and prefix length it asks about. The prefix length is the last label, without a slash, and the address comes before it: as four dotted labels (10.0.0.0.24), as one label with dashes for dots (10-0-0-0.24), or as an IPv6 address in one label, with dashes or colons between its groups (2001-db8--.32 or 2001:db8::.32).
+
+Parameters:
+labels: the labels of the query name below the cidr zone.
+
+Returns:
+Ok((IpAddr, u8)) with the address and a prefix length no longer than the address, or the QueryError saying what is wrong with the query.
+*/
+pub fn parse_query(labels: &[String]) -> Result<(IpAddr, u8), QueryError> {
+    let (prefix_label, address_labels) = match labels.split_last() {
+        Some((prefix_label, address_labels)) if !address_labels.is_empty() => (prefix_label, address_labels),
+        _ => return Err(QueryError::Layout(if labels.is_empty() { "the zone itself".to_string() } else { labels.join(".") })),
+    };
+    let ip_addr = parse_address(address_labels).ok_or_else(|| QueryError::Address(address_labels.join(".")))?;
+    let prefix_len = prefix_label.parse::<u32>().map_err(|_| QueryError::PrefixLength(prefix_label.clone()))?;
+
+    // The prefix can't be longer than the address
+    let (family, max) = match ip_addr {
+        IpAddr::V4(_) => ("IPv4", 32),
+        IpAddr::V6(_) => ("IPv6", 128),
+    };
+    match u8::try_from(prefix_len) {
+        Ok(prefix_len) if prefix_len <= max => Ok((ip_addr, prefix_len)),
+        _ => Err(QueryError::PrefixRange(prefix_label.clone(), family, max)),
+    }
+}
+
+// Reads the address of a cidr query from its labels, in any of the encodings parse_query accepts
+fn parse_address(labels: &[String]) -> Option<IpAddr> {
+    match labels {
+        [_, _, _, _] => labels.join(".").parse::<Ipv4Addr>().ok().map(IpAddr::V4),
+        [label] if label.contains(':') => label.parse::<Ipv6Addr>().ok().map(IpAddr::V6),
+        [label] => {
+            // Four dash-separated numbers are an IPv4 address, anything else with dashes an IPv6 address
+            let parts: Vec<&str> = label.split('-').collect();
+            if parts.len() == 4 && parts.iter().all(|part| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit())) {
+                parts.join(".").parse::<Ipv4Addr>().ok().map(IpAddr::V4)
+            } else {
+                label.replace('-', ":").parse::<Ipv6Addr>().ok().map(IpAddr::V6)
+            }
+        }
+        _ => None,
+    }
+}
+
+/*
+Description:
+the facts the cidr zone answers about a network: where it starts and ends, which of its addresses can be given to hosts, and how many there are.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Network {
+    // The address with every host bit cleared
+    pub network: IpAddr,
+
+    // The IPv4 broadcast address, with every host bit set; IPv6 has none
+    pub broadcast: Option<IpAddr>,
+
+    // The first and last address that can be given to a host
+    pub usable: (IpAddr, IpAddr),
+
+    // The number of addresses that can be given to hosts, or None if it is too large for a u128 (2^128, the whole IPv6 space)
+    pub hosts: Option<u128>,
+}
+
+/*
+Description:
+computes the range of addresses in the network an address belongs to, for the cidr zone: the address with every host bit cleared, and with every host bit set.
+
+Parameters:
+ip_addr: an address in the network.
+prefix_len: the length of the network prefix in bits, at most the width of the address; a /0 covers every address.
+
+Returns:
+A tuple of the first and last address of the range.
+*/
+pub fn range(ip_addr: IpAddr, prefix_len: u8) -> (IpAddr, IpAddr) {
+    match ip_addr {
+        // If the IP address is IPv4, calculate the range using a 32-bit netmask.
+        IpAddr::V4(ipv4) => {
+            let (first, last) = ipv4_range(ipv4, prefix_len);
+            (IpAddr::V4(first), IpAddr::V4(last))
+        }
+        // If the IP address is IPv6, calculate the range using a 128-bit netmask.
+        IpAddr::V6(ipv6) => {
+            let (first, last) = ipv6_range(ipv6, prefix_len);
+            (IpAddr::V6(first), IpAddr::V6(last))
+        }
+    }
+}
+
+/*
+Description:
+describes the network an address belongs to. An IPv4 network's usable addresses leave out the network and broadcast address, except in /31 point-to-point links (RFC 3021) and /32 single hosts, which use every address; IPv6 networks have no broadcast address and use every address.
+
+Parameters:
+ip_addr: an address in the network.
+prefix_len: the length of the network prefix in bits, at most the width of the address.
+
+Returns:
+The Network the address belongs to.
+*/
+pub fn network(ip_addr: IpAddr, prefix_len: u8) -> Network {
+    match ip_addr {
+        IpAddr::V4(ipv4) => {
+            let (first, last) = ipv4_range(ipv4, prefix_len);
+            let size = u128::from(u32::from(last) - u32::from(first)) + 1;
+            let usable = match prefix_len {
+                31 | 32 => (first, last),
+                _ => (Ipv4Addr::from(u32::from(first) + 1), Ipv4Addr::from(u32::from(last) - 1)),
+            };
+            Network {
+                network: IpAddr::V4(first),
+                broadcast: (prefix_len < 31).then_some(IpAddr::V4(last)),
+                usable: (IpAddr::V4(usable.0), IpAddr::V4(usable.1)),
+                hosts: Some(if prefix_len < 31 { size - 2 } else { size }),
+            }
+        }
+        IpAddr::V6(ipv6) => {
+            let (first, last) = ipv6_range(ipv6, prefix_len);
+            Network {
+                network: IpAddr::V6(first),
+                broadcast: None,
+                usable: (IpAddr::V6(first), IpAddr::V6(last)),
+                hosts: (u128::from(last) - u128::from(first)).checked_add(1),
+            }
+        }
+    }
+}
+
+// Computes the first and last address of an IPv4 network, on the address as a host-order u32; the shift is checked since a /0 shifts the mask out entirely
+fn ipv4_range(ipv4: Ipv4Addr, prefix_len: u8) -> (Ipv4Addr, Ipv4Addr) {
+    let netmask = u32::MAX.checked_shl(32 - u32::from(prefix_len)).unwrap_or(0);
+    let start_ip = u32::from(ipv4) & netmask;
+    let end_ip = start_ip | !netmask;
+    (Ipv4Addr::from(start_ip), Ipv4Addr::from(end_ip))
+}
+
+// Computes the first and last address of an IPv6 network, on the address as a host-order u128; the shift is checked since a /0 shifts the mask out entirely
+fn ipv6_range(ipv6: Ipv6Addr, prefix_len: u8) -> (Ipv6Addr, Ipv6Addr) {
+    let netmask = u128::MAX.checked_shl(128 - u32::from(prefix_len)).unwrap_or(0);
+    let start_ip = u128::from(ipv6) & netmask;
+    let end_ip = start_ip | !netmask;
+    (Ipv6Addr::from(start_ip), Ipv6Addr::from(end_ip))
+}
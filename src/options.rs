@@ -1,9 +1,11 @@
+use chrono::{DateTime, Utc};
 use clap::Parser;
-use std::net::SocketAddr;
+use crate::{acl::Cidr, chaos::{self, Fault}, config::Config, limits, zonesync::ZoneSource};
+use std::{net::SocketAddr, path::PathBuf};
 
 /*
 Description:
-defines a struct Options that holds various options related to a DNS server. The struct has three fields: udp, tcp, and domain. The udp and tcp fields are vectors of SocketAddr structs, representing the IP addresses and port numbers on which the DNS server will listen for UDP and TCP requests. The domain field is a string that represents the domain name that the DNS server is responsible for.
+defines a struct Options that holds various options related to a DNS server. The udp and tcp fields are vectors of SocketAddr structs, representing the IP addresses and port numbers on which the DNS server will listen for UDP and TCP requests. The domain field is a string that represents the domain name that the DNS server is responsible for. The remaining fields configure individual zones.
 
 Parameters:
 NONE
@@ -30,4 +32,575 @@ pub struct Options {
     // The default value is "mentisnovae.tech" and can be overridden by setting the DNS_DOMAIN environment variable
     #[clap(long, short, default_value = "mentisnovae.tech", env = "DNS_DOMAIN")]
     pub domain: String,
+
+    // The TOML configuration file holding settings such as per-zone policies
+    // The default value is none (no configuration file) and can be overridden by setting the DNS_CONFIG environment variable
+    #[clap(long = "config", env = "DNS_CONFIG")]
+    pub config_path: Option<PathBuf>,
+
+    // The contents of the configuration file, loaded after the command line is parsed
+    #[clap(skip)]
+    pub config: Config,
+
+    // The networks (in CIDR notation, comma separated) allowed to query the server
+    // The default value is an empty list (everyone not denied may query) and can be overridden by setting the DNS_ALLOW environment variable
+    #[clap(long, value_delimiter = ',', env = "DNS_ALLOW")]
+    pub allow: Vec<Cidr>,
+
+    // The networks (in CIDR notation, comma separated) refused service, taking precedence over --allow
+    // The default value is an empty list and can be overridden by setting the DNS_DENY environment variable
+    #[clap(long, value_delimiter = ',', env = "DNS_DENY")]
+    pub deny: Vec<Cidr>,
+
+    // The networks (in CIDR notation, comma separated) allowed to request zone transfers (AXFR/IXFR)
+    // The default value is an empty list (no one may transfer) and can be overridden by setting the DNS_TRANSFER_ALLOW environment variable
+    #[clap(long, value_delimiter = ',', env = "DNS_TRANSFER_ALLOW")]
+    pub transfer_allow: Vec<Cidr>,
+
+    // The networks (in CIDR notation, comma separated) refused zone transfers, taking precedence over --transfer-allow
+    // The default value is an empty list and can be overridden by setting the DNS_TRANSFER_DENY environment variable
+    #[clap(long, value_delimiter = ',', env = "DNS_TRANSFER_DENY")]
+    pub transfer_deny: Vec<Cidr>,
+
+    // The networks (in CIDR notation, comma separated) allowed to send dynamic updates
+    // The default value is an empty list (no one may update) and can be overridden by setting the DNS_UPDATE_ALLOW environment variable
+    #[clap(long, value_delimiter = ',', env = "DNS_UPDATE_ALLOW")]
+    pub update_allow: Vec<Cidr>,
+
+    // The networks (in CIDR notation, comma separated) refused dynamic updates, taking precedence over --update-allow
+    // The default value is an empty list and can be overridden by setting the DNS_UPDATE_DENY environment variable
+    #[clap(long, value_delimiter = ',', env = "DNS_UPDATE_DENY")]
+    pub update_deny: Vec<Cidr>,
+
+    // The URL of the exchange-rate API used by the fx zone
+    // "{base}" is replaced by the code of the currency being converted from
+    // The default value is "https://open.er-api.com/v6/latest/{base}" and can be overridden by setting the DNS_FX_API_URL environment variable
+    #[clap(long, default_value = "https://open.er-api.com/v6/latest/{base}", env = "DNS_FX_API_URL")]
+    pub fx_api_url: String,
+
+    // The directory in which fetched exchange rates are cached between restarts
+    // The default value is "fx-cache" and can be overridden by setting the DNS_FX_CACHE_DIR environment variable
+    #[clap(long, default_value = "fx-cache", env = "DNS_FX_CACHE_DIR")]
+    pub fx_cache_dir: PathBuf,
+
+    // How long (in seconds) fetched exchange rates are considered fresh
+    // The default value is 3600 and can be overridden by setting the DNS_FX_MAX_AGE environment variable
+    #[clap(long, default_value = "3600", env = "DNS_FX_MAX_AGE")]
+    pub fx_max_age: u64,
+
+    // The file to which dnstap logs of all queries and responses are written
+    // The default value is none (disabled) and can be overridden by setting the DNS_DNSTAP_FILE environment variable
+    #[clap(long, env = "DNS_DNSTAP_FILE", conflicts_with = "dnstap_socket")]
+    pub dnstap_file: Option<PathBuf>,
+
+    // The Unix socket of a dnstap collector to which all queries and responses are logged
+    // The default value is none (disabled) and can be overridden by setting the DNS_DNSTAP_SOCKET environment variable
+    #[clap(long, env = "DNS_DNSTAP_SOCKET")]
+    pub dnstap_socket: Option<PathBuf>,
+
+    // Log only one in every N queries to dnstap
+    // The default value is 1 (every query) and can be overridden by setting the DNS_DNSTAP_SAMPLE environment variable
+    #[clap(long, default_value = "1", env = "DNS_DNSTAP_SAMPLE")]
+    pub dnstap_sample: u64,
+
+    // The number of dnstap frames buffered for the writer before new frames are dropped
+    // The default value is 4096 and can be overridden by setting the DNS_DNSTAP_BUFFER environment variable
+    #[clap(long, default_value = "4096", env = "DNS_DNSTAP_BUFFER")]
+    pub dnstap_buffer: usize,
+
+    // The address on which the admin HTTP API listens
+    // The default value is none (disabled) and can be overridden by setting the DNS_ADMIN environment variable
+    #[clap(long, env = "DNS_ADMIN")]
+    pub admin: Option<SocketAddr>,
+
+    // The address on which the ACME DNS-01 API (compatible with acme-dns) listens, for certbot and lego to publish challenge tokens served at acme.<subdomain>.<domain>
+    // The default value is none (disabled) and can be overridden by setting the DNS_ACME_API environment variable
+    #[clap(long, env = "DNS_ACME_API")]
+    pub acme_api: Option<SocketAddr>,
+
+    // The JSON file in which the ACME API's accounts are kept, so they survive restarts
+    // The default value is none (accounts are lost on restart) and can be overridden by setting the DNS_ACME_ACCOUNTS environment variable
+    #[clap(long, env = "DNS_ACME_ACCOUNTS")]
+    pub acme_accounts: Option<PathBuf>,
+
+    // The networks allowed to register ACME accounts, comma-separated
+    // The default value is the loopback addresses and can be overridden by setting the DNS_ACME_REGISTER_ALLOW environment variable
+    #[clap(long, value_delimiter = ',', default_value = "127.0.0.0/8,::1", env = "DNS_ACME_REGISTER_ALLOW")]
+    pub acme_register_allow: Vec<Cidr>,
+
+    // How long (in seconds) a published ACME challenge token is served
+    // The default value is 3600 and can be overridden by setting the DNS_ACME_TOKEN_LIFETIME environment variable
+    #[clap(long, default_value = "3600", env = "DNS_ACME_TOKEN_LIFETIME")]
+    pub acme_token_lifetime: u64,
+
+    // The address on which the DynDNS update API (/nic/update) listens, for routers to keep their hosts in the dyn zone up to date
+    // The default value is none (disabled) and can be overridden by setting the DNS_DDNS_API environment variable
+    #[clap(long, env = "DNS_DDNS_API")]
+    pub ddns_api: Option<SocketAddr>,
+
+    // The JSON file in which the dyn zone's hosts, their token digests and their addresses are kept; hosts are added with the ddns subcommand
+    // The default value is ddns-hosts.json and can be overridden by setting the DNS_DDNS_HOSTS environment variable
+    #[clap(long, default_value = "ddns-hosts.json", env = "DNS_DDNS_HOSTS")]
+    pub ddns_hosts: PathBuf,
+
+    // The Docker socket to watch, serving the running containers' addresses at <name>.docker.<domain> (e.g. /var/run/docker.sock)
+    // The default value is none (disabled) and can be overridden by setting the DNS_DOCKER_SOCKET environment variable
+    #[clap(long, env = "DNS_DOCKER_SOCKET")]
+    pub docker_socket: Option<PathBuf>,
+
+    // A file in the format of /etc/hosts whose entries are served at <name>.hosts.<domain> and as PTR records for their addresses, read again whenever it changes; may be given more than once
+    // The default value is none (disabled) and can be overridden by setting the DNS_HOSTS_FILE environment variable to a comma-separated list
+    #[clap(long, value_delimiter = ',', env = "DNS_HOSTS_FILE")]
+    pub hosts_file: Vec<PathBuf>,
+
+    // Whether to discover the LAN's devices from their mDNS announcements and the ARP table, serving them at <name>.lan.<domain> and as PTR records for their addresses
+    // The default value is false and can be overridden by setting the DNS_LAN_DISCOVERY environment variable
+    #[clap(long, env = "DNS_LAN_DISCOVERY")]
+    pub lan_discovery: bool,
+
+    // How often (in seconds) the LAN is scanned for devices with mDNS queries and the ARP table is read again
+    // The default value is 60 and can be overridden by setting the DNS_LAN_SCAN_INTERVAL environment variable
+    #[clap(long, default_value = "60", env = "DNS_LAN_SCAN_INTERVAL")]
+    pub lan_scan_interval: u64,
+
+    // Whether to serve the trace zone, which looks up trace.<name>.<domain> from the root servers down and answers with every server's reply, sending queries across the Internet
+    // The default value is false and can be overridden by setting the DNS_TRACE environment variable
+    #[clap(long, env = "DNS_TRACE")]
+    pub trace: bool,
+
+    // The servers traces start from instead of the root servers (e.g. 198.41.0.4:53)
+    // The default value is none (the root servers) and can be overridden by setting the DNS_TRACE_ROOTS environment variable to a comma-separated list
+    #[clap(long, value_delimiter = ',', env = "DNS_TRACE_ROOTS")]
+    pub trace_roots: Vec<SocketAddr>,
+
+    // The WireGuard configuration file whose peers are served at <peer>.wg.<domain>, named by their "# Name = " comments (e.g. /etc/wireguard/wg0.conf)
+    // The default value is none and can be overridden by setting the DNS_WIREGUARD_CONFIG environment variable
+    #[clap(long, env = "DNS_WIREGUARD_CONFIG")]
+    pub wireguard_config: Option<PathBuf>,
+
+    // The WireGuard interface whose peers are served at <peer>.wg.<domain>, read with "wg show" (which needs CAP_NET_ADMIN); names come from --wireguard-config if given
+    // The default value is none and can be overridden by setting the DNS_WIREGUARD_INTERFACE environment variable
+    #[clap(long, env = "DNS_WIREGUARD_INTERFACE")]
+    pub wireguard_interface: Option<String>,
+
+    // How often (in seconds) the WireGuard peers are read again
+    // The default value is 30 and can be overridden by setting the DNS_WIREGUARD_REFRESH environment variable
+    #[clap(long, default_value = "30", env = "DNS_WIREGUARD_REFRESH")]
+    pub wireguard_refresh: u64,
+
+    // The URL of the Consul HTTP API whose catalog is served in the registry zone (e.g. http://127.0.0.1:8500)
+    // The default value is none (disabled) and can be overridden by setting the DNS_CONSUL environment variable
+    #[clap(long, env = "DNS_CONSUL")]
+    pub consul: Option<String>,
+
+    // The ACL token sent to Consul
+    // The default value is none and can be overridden by setting the DNS_CONSUL_TOKEN environment variable
+    #[clap(long, env = "DNS_CONSUL_TOKEN")]
+    pub consul_token: Option<String>,
+
+    // The URL of the etcd v3 JSON gateway whose keys below --etcd-prefix are served in the registry zone (e.g. http://127.0.0.1:2379)
+    // The default value is none (disabled) and can be overridden by setting the DNS_ETCD environment variable
+    #[clap(long, env = "DNS_ETCD", conflicts_with = "consul")]
+    pub etcd: Option<String>,
+
+    // The etcd prefix the registry zone's keys are below
+    // The default value is /rusty-dns/ and can be overridden by setting the DNS_ETCD_PREFIX environment variable
+    #[clap(long, default_value = "/rusty-dns/", env = "DNS_ETCD_PREFIX")]
+    pub etcd_prefix: String,
+
+    // The label of the subzone Consul's or etcd's records are served under, as <name>.<label>.<domain>
+    // The default value is svc and can be overridden by setting the DNS_REGISTRY_ZONE environment variable
+    #[clap(long, default_value = "svc", env = "DNS_REGISTRY_ZONE")]
+    pub registry_zone: String,
+
+    // The PostgreSQL database whose records are served, as a URL (e.g. postgres://dns@localhost/app) or key=value pairs; TLS is not supported
+    // Only available when built with the "postgres" feature
+    // The default value is none (disabled) and can be overridden by setting the DNS_POSTGRES environment variable
+    #[cfg(feature = "postgres")]
+    #[clap(long, env = "DNS_POSTGRES")]
+    pub postgres: Option<String>,
+
+    // The query returning the records served from PostgreSQL, as rows of name, type, content and ttl (e.g. a view over a web app's tables)
+    // Only available when built with the "postgres" feature
+    // The default value is "SELECT name, type, content, ttl FROM records" and can be overridden by setting the DNS_POSTGRES_QUERY environment variable
+    #[cfg(feature = "postgres")]
+    #[clap(long, default_value = "SELECT name, type, content, ttl FROM records", env = "DNS_POSTGRES_QUERY")]
+    pub postgres_query: String,
+
+    // The channel on which a NOTIFY makes the records load again from PostgreSQL
+    // Only available when built with the "postgres" feature
+    // The default value is rusty_dns and can be overridden by setting the DNS_POSTGRES_CHANNEL environment variable
+    #[cfg(feature = "postgres")]
+    #[clap(long, default_value = "rusty_dns", env = "DNS_POSTGRES_CHANNEL")]
+    pub postgres_channel: String,
+
+    // A zone served from a zone file, as <origin>:<location> where the location is a path, an http(s) URL or an s3://bucket/key URL (e.g. example.com:https://zones.example.net/db.example.com); may be given more than once
+    // The default value is none and can be overridden by setting the DNS_ZONE_FILE environment variable to a comma-separated list
+    #[clap(long, value_delimiter = ',', env = "DNS_ZONE_FILE")]
+    pub zone_file: Vec<ZoneSource>,
+
+    // The directory in which fetched zone files are cached, and served from at startup until they are fetched again
+    // The default value is "zone-cache" and can be overridden by setting the DNS_ZONE_CACHE_DIR environment variable
+    #[clap(long, default_value = "zone-cache", env = "DNS_ZONE_CACHE_DIR")]
+    pub zone_cache_dir: PathBuf,
+
+    // How often (in seconds) the zone files are fetched again; remote files are only downloaded again when their ETag has changed
+    // The default value is 300 and can be overridden by setting the DNS_ZONE_REFRESH environment variable
+    #[clap(long, default_value = "300", env = "DNS_ZONE_REFRESH")]
+    pub zone_refresh: u64,
+
+    // The URL of the LDAP directory whose hosts' addresses are served (e.g. ldaps://ipa.example.com)
+    // Only available when built with the "ldap" feature
+    // The default value is none (disabled) and can be overridden by setting the DNS_LDAP environment variable
+    #[cfg(feature = "ldap")]
+    #[clap(long, env = "DNS_LDAP", requires = "ldap_base")]
+    pub ldap: Option<String>,
+
+    // The zone the directory's hosts are served under, as <host>.<zone>
+    // Only available when built with the "ldap" feature
+    // The default value is ldap.<domain> and can be overridden by setting the DNS_LDAP_ZONE environment variable
+    #[cfg(feature = "ldap")]
+    #[clap(long, env = "DNS_LDAP_ZONE")]
+    pub ldap_zone: Option<String>,
+
+    // The DN below which hosts are searched for (e.g. cn=computers,cn=accounts,dc=example,dc=com)
+    // Only available when built with the "ldap" feature
+    // The default value is none and can be overridden by setting the DNS_LDAP_BASE environment variable
+    #[cfg(feature = "ldap")]
+    #[clap(long, env = "DNS_LDAP_BASE")]
+    pub ldap_base: Option<String>,
+
+    // The DN the server binds to the directory as; it searches anonymously if none is given
+    // Only available when built with the "ldap" feature
+    // The default value is none and can be overridden by setting the DNS_LDAP_BIND_DN environment variable
+    #[cfg(feature = "ldap")]
+    #[clap(long, env = "DNS_LDAP_BIND_DN")]
+    pub ldap_bind_dn: Option<String>,
+
+    // The password of the bind DN, best given through the environment rather than on the command line
+    // Only available when built with the "ldap" feature
+    // The default value is none and can be overridden by setting the DNS_LDAP_PASSWORD environment variable
+    #[cfg(feature = "ldap")]
+    #[clap(long, env = "DNS_LDAP_PASSWORD", hide_env_values = true, requires = "ldap_bind_dn")]
+    pub ldap_password: Option<String>,
+
+    // The filter a host's entries are searched for with, in which {host} stands for the host's name below the zone and {fqdn} for its full name
+    // Only available when built with the "ldap" feature
+    // The default value is "(cn={host})" and can be overridden by setting the DNS_LDAP_FILTER environment variable
+    #[cfg(feature = "ldap")]
+    #[clap(long, default_value = "(cn={host})", env = "DNS_LDAP_FILTER")]
+    pub ldap_filter: String,
+
+    // The attributes of a host's entries holding its IPv4 and IPv6 addresses
+    // Only available when built with the "ldap" feature
+    // The default value is ipHostNumber and can be overridden by setting the DNS_LDAP_ATTRIBUTES environment variable to a comma-separated list
+    #[cfg(feature = "ldap")]
+    #[clap(long, value_delimiter = ',', default_value = "ipHostNumber", env = "DNS_LDAP_ATTRIBUTES")]
+    pub ldap_attributes: Vec<String>,
+
+    // The number of connections to the directory that lookups are spread over
+    // Only available when built with the "ldap" feature
+    // The default value is 4 and can be overridden by setting the DNS_LDAP_POOL_SIZE environment variable
+    #[cfg(feature = "ldap")]
+    #[clap(long, default_value = "4", env = "DNS_LDAP_POOL_SIZE")]
+    pub ldap_pool_size: usize,
+
+    // The TTL (in seconds) of the hosts' records, for which their lookups are also cached
+    // Only available when built with the "ldap" feature
+    // The default value is 60 and can be overridden by setting the DNS_LDAP_TTL environment variable
+    #[cfg(feature = "ldap")]
+    #[clap(long, default_value = "60", env = "DNS_LDAP_TTL")]
+    pub ldap_ttl: u32,
+
+    // The largest number of clients whose query statistics are tracked at once
+    // The default value is 10000 and can be overridden by setting the DNS_CLIENT_STATS_CAPACITY environment variable
+    #[clap(long, default_value = "10000", env = "DNS_CLIENT_STATS_CAPACITY")]
+    pub client_stats_capacity: usize,
+
+    // The length (in seconds) of the sliding window over which the busiest clients and most queried names are tracked
+    // The default value is 300 and can be overridden by setting the DNS_TOP_WINDOW environment variable
+    #[clap(long, default_value = "300", env = "DNS_TOP_WINDOW")]
+    pub top_window: u64,
+
+    // The number of counters kept per top-talker sketch; the busiest clients and names up to this many are tracked reliably
+    // The default value is 100 and can be overridden by setting the DNS_TOP_CAPACITY environment variable
+    #[clap(long, default_value = "100", env = "DNS_TOP_CAPACITY")]
+    pub top_capacity: usize,
+
+    // The file in which the request counter is persisted across restarts
+    // The default value is none (the counter starts from zero on every start) and can be overridden by setting the DNS_COUNTER_FILE environment variable
+    #[clap(long, env = "DNS_COUNTER_FILE")]
+    #[cfg_attr(feature = "redis", clap(conflicts_with = "redis_url"))]
+    pub counter_file: Option<PathBuf>,
+
+    // How often (in seconds) the request counter is written to the counter file
+    // The default value is 10 and can be overridden by setting the DNS_COUNTER_FLUSH_INTERVAL environment variable
+    #[clap(long, default_value = "10", env = "DNS_COUNTER_FLUSH_INTERVAL")]
+    pub counter_flush_interval: u64,
+
+    // The URL of a Redis server through which state such as the request counter is shared with other instances
+    // Only available when built with the "redis" feature
+    // The default value is none (state is kept locally) and can be overridden by setting the DNS_REDIS_URL environment variable
+    #[cfg(feature = "redis")]
+    #[clap(long, env = "DNS_REDIS_URL")]
+    pub redis_url: Option<String>,
+
+    // The prefix of every Redis key used by the server, so several deployments can share a Redis server
+    // Only available when built with the "redis" feature
+    // The default value is "rusty-dns:" and can be overridden by setting the DNS_REDIS_PREFIX environment variable
+    #[cfg(feature = "redis")]
+    #[clap(long, default_value = "rusty-dns:", env = "DNS_REDIS_PREFIX")]
+    pub redis_prefix: String,
+
+    // Whether to serve the records other services publish in Redis, as keys named <prefix><name>:<type> (e.g. dns:www.example.com:A) whose TTL is the records' TTL
+    // Only available when built with the "redis" feature
+    // The default value is false and can be overridden by setting the DNS_REDIS_RECORDS environment variable
+    #[cfg(feature = "redis")]
+    #[clap(long, env = "DNS_REDIS_RECORDS", requires = "redis_url")]
+    pub redis_records: bool,
+
+    // The prefix of the Redis keys holding records; unlike --redis-prefix it is shared with the services publishing them
+    // Only available when built with the "redis" feature
+    // The default value is "dns:" and can be overridden by setting the DNS_REDIS_RECORDS_PREFIX environment variable
+    #[cfg(feature = "redis")]
+    #[clap(long, default_value = "dns:", env = "DNS_REDIS_RECORDS_PREFIX")]
+    pub redis_records_prefix: String,
+
+    // How often (in seconds) the Redis keys holding records are read again; keys that expire stop being served when they do
+    // Only available when built with the "redis" feature
+    // The default value is 5 and can be overridden by setting the DNS_REDIS_RECORDS_REFRESH environment variable
+    #[cfg(feature = "redis")]
+    #[clap(long, default_value = "5", env = "DNS_REDIS_RECORDS_REFRESH")]
+    pub redis_records_refresh: u64,
+
+    // The address on which the runtime's task instrumentation is served to tokio-console
+    // Only available when built with the "console" feature
+    // The default value is 127.0.0.1:6669 and can be overridden by setting the DNS_CONSOLE_ADDR environment variable
+    #[cfg(feature = "console")]
+    #[clap(long, default_value = "127.0.0.1:6669", env = "DNS_CONSOLE_ADDR")]
+    pub console_addr: SocketAddr,
+
+    // The SQLite database in which every query is logged
+    // Only available when built with the "sqlite" feature
+    // The default value is none (disabled) and can be overridden by setting the DNS_QUERY_LOG environment variable
+    #[cfg(feature = "sqlite")]
+    #[clap(long, env = "DNS_QUERY_LOG")]
+    pub query_log: Option<PathBuf>,
+
+    // How many days queries are kept in the query log, 0 keeps them forever
+    // Only available when built with the "sqlite" feature
+    // The default value is 7 and can be overridden by setting the DNS_QUERY_LOG_RETENTION_DAYS environment variable
+    #[cfg(feature = "sqlite")]
+    #[clap(long, default_value = "7", env = "DNS_QUERY_LOG_RETENTION_DAYS")]
+    pub query_log_retention_days: u64,
+
+    // Truncate client addresses in the query log, dnstap output, dashboard and debug logs
+    // The default value is false and can be overridden by setting the DNS_ANONYMIZE environment variable
+    #[clap(long, env = "DNS_ANONYMIZE")]
+    pub anonymize: bool,
+
+    // The number of leading bits kept of IPv4 client addresses when anonymizing
+    // The default value is 24 and can be overridden by setting the DNS_ANONYMIZE_IPV4_PREFIX environment variable
+    #[clap(long, default_value = "24", env = "DNS_ANONYMIZE_IPV4_PREFIX")]
+    pub anonymize_ipv4_prefix: u8,
+
+    // The number of leading bits kept of IPv6 client addresses when anonymizing
+    // The default value is 48 and can be overridden by setting the DNS_ANONYMIZE_IPV6_PREFIX environment variable
+    #[clap(long, default_value = "48", env = "DNS_ANONYMIZE_IPV6_PREFIX")]
+    pub anonymize_ipv6_prefix: u8,
+
+    // Also replace the arguments of queried names with a salted hash when anonymizing
+    // The default value is false and can be overridden by setting the DNS_HASH_QNAMES environment variable
+    #[clap(long, env = "DNS_HASH_QNAMES", requires = "anonymize")]
+    pub hash_qnames: bool,
+
+    // How often (in seconds) the salt used to hash queried names is replaced
+    // The default value is 86400 (a day) and can be overridden by setting the DNS_HASH_SALT_ROTATION environment variable
+    #[clap(long, default_value = "86400", env = "DNS_HASH_SALT_ROTATION")]
+    pub hash_salt_rotation: u64,
+
+    // The user to switch to once the sockets are bound, by name or numeric ID (requires starting as root)
+    // The default value is none (keep running as the starting user) and can be overridden by setting the DNS_USER environment variable
+    #[clap(long, env = "DNS_USER")]
+    pub user: Option<String>,
+
+    // The group to switch to once the sockets are bound, by name or numeric ID (requires starting as root)
+    // The default value is none (the user's primary group, if --user is given) and can be overridden by setting the DNS_GROUP environment variable
+    #[clap(long, env = "DNS_GROUP")]
+    pub group: Option<String>,
+
+    // The directory to chroot into once the sockets are bound (requires starting as root)
+    // The default value is none (no chroot) and can be overridden by setting the DNS_CHROOT environment variable
+    #[clap(long, env = "DNS_CHROOT")]
+    pub chroot: Option<PathBuf>,
+
+    // Whether to restrict file system access to the files the server needs with Landlock (Linux only)
+    // The default value is false and can be overridden by setting the DNS_LANDLOCK environment variable
+    #[clap(long, env = "DNS_LANDLOCK")]
+    pub landlock: bool,
+
+    // Whether to detach from the terminal and run in the background (Unix only)
+    // The default value is false and can be overridden by setting the DNS_DAEMON environment variable
+    #[clap(long, env = "DNS_DAEMON")]
+    pub daemon: bool,
+
+    // The file to which the server's process ID is written at startup
+    // The default value is none (no pid file) and can be overridden by setting the DNS_PIDFILE environment variable
+    #[clap(long, env = "DNS_PIDFILE")]
+    pub pidfile: Option<PathBuf>,
+
+    // The file to which log output is appended instead of the terminal
+    // The default value is none (log to stdout, or discard the output with --daemon) and can be overridden by setting the DNS_LOG_FILE environment variable
+    #[clap(long, env = "DNS_LOG_FILE")]
+    pub log_file: Option<PathBuf>,
+
+    // The file to which a JSON snapshot of the server's counters is written on SIGUSR1
+    // The default value is none (the snapshot is logged instead) and can be overridden by setting the DNS_STATS_FILE environment variable
+    #[clap(long, env = "DNS_STATS_FILE")]
+    pub stats_file: Option<PathBuf>,
+
+    // The append-only file in which admin API requests, config reloads and dynamic update attempts are recorded
+    // The default value is none (no audit log) and can be overridden by setting the DNS_AUDIT_LOG environment variable
+    #[clap(long, env = "DNS_AUDIT_LOG")]
+    pub audit_log: Option<PathBuf>,
+
+    // The number of requests that may be handled at once before low-priority zones are shed, 0 for no limit
+    // The default value is 0 and can be overridden by setting the DNS_MAX_IN_FLIGHT environment variable
+    #[clap(long, default_value = "0", env = "DNS_MAX_IN_FLIGHT")]
+    pub max_in_flight: usize,
+
+    // The average handler latency (in milliseconds) above which low-priority zones are shed, 0 for no limit
+    // The default value is 0 and can be overridden by setting the DNS_MAX_LATENCY_MS environment variable
+    #[clap(long, default_value = "0", env = "DNS_MAX_LATENCY_MS")]
+    pub max_latency_ms: u64,
+
+    // The longest a query name may be written, without its trailing dot; longer names are answered with FORMERR
+    // The default value is 253 (any valid name) and can be overridden by setting the DNS_MAX_QNAME_LENGTH environment variable
+    #[clap(long, default_value = "253", value_parser = limits::parse_max_name_length, env = "DNS_MAX_QNAME_LENGTH")]
+    pub max_qname_length: usize,
+
+    // The most labels a query name may have; names with more are answered with FORMERR
+    // The default value is 40 and can be overridden by setting the DNS_MAX_LABELS environment variable
+    #[clap(long, default_value = "40", value_parser = limits::parse_max_labels, env = "DNS_MAX_LABELS")]
+    pub max_labels: u8,
+
+    // The most queries per second the domain answers; queries over the limit are refused, 0 for no limit
+    // The default value is 0 and can be overridden by setting the DNS_RATE_LIMIT environment variable
+    #[clap(long, default_value = "0", env = "DNS_RATE_LIMIT")]
+    pub rate_limit: u32,
+
+    // Drop shed UDP queries without an answer instead of answering REFUSED (TCP queries are always refused)
+    // The default value is false and can be overridden by setting the DNS_SHED_DROP_UDP environment variable
+    #[clap(long, env = "DNS_SHED_DROP_UDP")]
+    pub shed_drop_udp: bool,
+
+    // The most verbose level logged: error, warn, info, debug or trace ("debug" logs every query, subject to sampling)
+    // The default value is info and can be overridden by setting the DNS_LOG_LEVEL environment variable
+    #[clap(long, default_value = "info", env = "DNS_LOG_LEVEL")]
+    pub log_level: tracing::Level,
+
+    // Log only one in every N successful queries at debug level, for zones without their own log_sample (failures are always logged)
+    // The default value is 1 (every query) and can be overridden by setting the DNS_LOG_SAMPLE environment variable
+    #[clap(long, default_value = "1", env = "DNS_LOG_SAMPLE")]
+    pub log_sample: u64,
+
+    // Read and write up to N UDP datagrams per system call with recvmmsg/sendmmsg, 0 to use one call per datagram (Linux only)
+    // The default value is 0 and can be overridden by setting the DNS_UDP_BATCH environment variable
+    #[clap(long, default_value = "0", env = "DNS_UDP_BATCH")]
+    pub udp_batch: usize,
+
+    // Run N shards, each a thread with its own single-threaded runtime and its own SO_REUSEPORT sockets, 0 to serve every socket from one multi-threaded runtime (Linux only)
+    // The default value is 0 and can be overridden by setting the DNS_SHARDS environment variable
+    #[clap(long, default_value = "0", env = "DNS_SHARDS")]
+    pub shards: usize,
+
+    // The number of worker threads of the tokio runtime, 0 for one per CPU core (ignored with --shards, which brings its own threads)
+    // The default value is 0 and can be overridden by setting the DNS_WORKER_THREADS environment variable
+    #[clap(long, default_value = "0", env = "DNS_WORKER_THREADS")]
+    pub worker_threads: usize,
+
+    // The most threads the tokio runtime starts for blocking work such as file I/O and query log writes
+    // The default value is 512 and can be overridden by setting the DNS_MAX_BLOCKING_THREADS environment variable
+    #[clap(long, default_value = "512", env = "DNS_MAX_BLOCKING_THREADS")]
+    pub max_blocking_threads: usize,
+
+    // Whether to pin each worker thread (or each shard) to its own CPU core (Linux only)
+    // The default value is false and can be overridden by setting the DNS_PIN_WORKERS environment variable
+    #[clap(long, env = "DNS_PIN_WORKERS")]
+    pub pin_workers: bool,
+
+    // Seed the random zones (coin, dice, lotto, rps, name, ulid) so they give the same answers on every run, for tests and demos
+    // The default value is none (draw from the OS) and can be overridden by setting the DNS_RNG_SEED environment variable
+    #[clap(long, visible_alias = "seed", env = "DNS_RNG_SEED")]
+    pub rng_seed: Option<u64>,
+
+    // Stop the clock of the date and time zones (moon, sun, week, age, ulid) at this time, in RFC 3339 format (e.g. 2024-02-29T12:00:00Z), for tests and demos
+    // The default value is none (the system clock) and can be overridden by setting the DNS_CLOCK environment variable
+    #[clap(long, env = "DNS_CLOCK")]
+    pub clock: Option<DateTime<Utc>>,
+
+    // Inject faults into this share of queries, from 0 to 1, so client developers can test their retries against a real server; never use it in production
+    // The default value is 0 (off) and can be overridden by setting the DNS_CHAOS environment variable
+    #[clap(long, default_value = "0", value_parser = chaos::parse_probability, env = "DNS_CHAOS")]
+    pub chaos: f64,
+
+    // The faults chaos mode picks from, comma-separated: servfail, delay, truncate (over UDP) and malformed
+    // The default value is all of them and can be overridden by setting the DNS_CHAOS_FAULTS environment variable
+    #[clap(long, value_enum, value_delimiter = ',', default_value = "servfail,delay,truncate,malformed", env = "DNS_CHAOS_FAULTS")]
+    pub chaos_faults: Vec<Fault>,
+
+    // The longest delay (in milliseconds) chaos mode adds to an answer
+    // The default value is 3000 and can be overridden by setting the DNS_CHAOS_MAX_DELAY_MS environment variable
+    #[clap(long, default_value = "3000", env = "DNS_CHAOS_MAX_DELAY_MS")]
+    pub chaos_max_delay_ms: u64,
+
+    // The most TCP connections open at once on each TCP listener (and each shard's), 0 for no limit
+    // The default value is 0 and can be overridden by setting the DNS_TCP_MAX_CONNECTIONS environment variable
+    #[clap(long, default_value = "0", env = "DNS_TCP_MAX_CONNECTIONS")]
+    pub tcp_max_connections: usize,
+
+    // The most TCP connections one client address may hold open on each TCP listener, 0 for no limit
+    // The default value is 0 and can be overridden by setting the DNS_TCP_MAX_PER_CLIENT environment variable
+    #[clap(long, default_value = "0", env = "DNS_TCP_MAX_PER_CLIENT")]
+    pub tcp_max_per_client: usize,
+
+    // The most queries of one TCP connection handled at once when a TCP connection limit is set
+    // The default value is 16 and can be overridden by setting the DNS_TCP_MAX_IN_FLIGHT environment variable
+    #[clap(long, default_value = "16", env = "DNS_TCP_MAX_IN_FLIGHT")]
+    pub tcp_max_in_flight: usize,
+
+    // The number of answers kept in the cache for zones whose answer depends only on the question (cidr, time, str, puny, unpuny, luhn, crc32, fib, fact), 0 to disable the cache
+    // The default value is 10000 and can be overridden by setting the DNS_RESPONSE_CACHE_SIZE environment variable
+    #[clap(long, default_value = "10000", env = "DNS_RESPONSE_CACHE_SIZE")]
+    pub response_cache_size: usize,
+
+    // The longest (in seconds) a cached answer is served, however long its TTL
+    // The default value is 60 and can be overridden by setting the DNS_RESPONSE_CACHE_TTL environment variable
+    #[clap(long, default_value = "60", env = "DNS_RESPONSE_CACHE_TTL")]
+    pub response_cache_ttl: u64,
+
+    // The number of query names kept parsed and matched to their zone, 0 to parse every name on every query
+    // The default value is 10000 and can be overridden by setting the DNS_NAME_CACHE_SIZE environment variable
+    #[clap(long, default_value = "10000", env = "DNS_NAME_CACHE_SIZE")]
+    pub name_cache_size: usize,
+
+    // The StatsD server (UDP) to which metrics are pushed
+    // The default value is none (disabled) and can be overridden by setting the DNS_STATSD environment variable
+    #[clap(long, env = "DNS_STATSD", conflicts_with = "graphite")]
+    pub statsd: Option<SocketAddr>,
+
+    // The Graphite server (Carbon plaintext protocol over TCP) to which metrics are pushed
+    // The default value is none (disabled) and can be overridden by setting the DNS_GRAPHITE environment variable
+    #[clap(long, env = "DNS_GRAPHITE")]
+    pub graphite: Option<SocketAddr>,
+
+    // The prefix of every metric name pushed to StatsD or Graphite
+    // The default value is "rusty_dns" and can be overridden by setting the DNS_METRICS_PREFIX environment variable
+    #[clap(long, default_value = "rusty_dns", env = "DNS_METRICS_PREFIX")]
+    pub metrics_prefix: String,
+
+    // How often (in seconds) metrics are pushed to StatsD or Graphite
+    // The default value is 10 and can be overridden by setting the DNS_METRICS_FLUSH_INTERVAL environment variable
+    #[clap(long, default_value = "10", env = "DNS_METRICS_FLUSH_INTERVAL")]
+    pub metrics_flush_interval: u64,
 }
\ No newline at end of file
@@ -0,0 +1,135 @@
+use crate::config::Priority;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+// The load at which normal-priority zones are shed as well as low-priority ones.
+const SEVERE_LOAD: f64 = 1.5;
+
+// The weight of each new sample in the latency moving average, as a power of two (1/16).
+const LATENCY_SMOOTHING: u32 = 4;
+
+/*
+Description:
+detects when the server is overloaded and decides which queries to shed. The load is the larger of two ratios: the number of requests being handled against the in-flight limit, and the moving average of handler latency against the latency limit. At a load of 1 low-priority zones are shed, at 1.5 normal-priority zones too; critical zones are always answered. A limit of 0 disables that half of the check.
+*/
+#[derive(Debug, Default)]
+pub struct Overload {
+    // The number of requests that may be handled at once before the server counts as overloaded, 0 for no limit
+    max_in_flight: usize,
+
+    // The average handler latency (in microseconds) above which the server counts as overloaded, 0 for no limit
+    max_latency_us: u64,
+
+    // The number of requests being handled right now
+    in_flight: AtomicUsize,
+
+    // The moving average of handler latency, in microseconds
+    latency_us: AtomicU64,
+
+    // The number of queries shed so far
+    shed: AtomicU64,
+}
+
+/*
+Description:
+marks a request as in flight until it is dropped.
+*/
+pub struct InFlight<'a>(&'a Overload);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Overload {
+    /*
+    Description:
+    creates an overload detector.
+
+    Parameters:
+    max_in_flight: the number of requests that may be handled at once, 0 for no limit.
+    max_latency_us: the highest acceptable average handler latency in microseconds, 0 for no limit.
+
+    Returns:
+    A new Overload.
+    */
+    pub fn new(max_in_flight: usize, max_latency_us: u64) -> Self {
+        Overload {
+            max_in_flight,
+            max_latency_us,
+            ..Default::default()
+        }
+    }
+
+    // Counts a request as in flight until the returned guard is dropped
+    pub fn enter(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(self)
+    }
+
+    /*
+    Description:
+    adds a handler latency to the moving average. Concurrent updates may overwrite each other, which only makes the average slightly less smooth.
+
+    Parameters:
+    latency_us: the time a request took to handle, in microseconds.
+
+    Returns:
+    None
+    */
+    pub fn record_latency(&self, latency_us: u64) {
+        let average = self.latency_us.load(Ordering::Relaxed);
+        let average = average - (average >> LATENCY_SMOOTHING) + (latency_us >> LATENCY_SMOOTHING);
+        self.latency_us.store(average, Ordering::Relaxed);
+    }
+
+    // Returns the current load, where 1 or more means overloaded
+    pub fn load(&self) -> f64 {
+        let queue = match self.max_in_flight {
+            0 => 0.0,
+            max => self.in_flight.load(Ordering::Relaxed) as f64 / max as f64,
+        };
+        let latency = match self.max_latency_us {
+            0 => 0.0,
+            max => self.latency_us.load(Ordering::Relaxed) as f64 / max as f64,
+        };
+        queue.max(latency)
+    }
+
+    // Returns whether a query for a zone of the given priority would be shed at the current load, without counting it
+    pub fn would_shed(&self, priority: Priority) -> bool {
+        match priority {
+            Priority::Critical => false,
+            Priority::Normal => self.load() >= SEVERE_LOAD,
+            Priority::Low => self.load() >= 1.0,
+        }
+    }
+
+    /*
+    Description:
+    decides whether a query for a zone of the given priority should be shed, counting it if so.
+
+    Parameters:
+    priority: the priority of the queried zone.
+
+    Returns:
+    true if the query should be refused or dropped instead of answered.
+    */
+    pub fn should_shed(&self, priority: Priority) -> bool {
+        let shed = self.would_shed(priority);
+        if shed {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+        }
+        shed
+    }
+
+    // Returns the number of queries shed so far
+    pub fn shed(&self) -> u64 {
+        self.shed.load(Ordering::Relaxed)
+    }
+
+    // Returns the number of requests being handled right now
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
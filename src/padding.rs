@@ -0,0 +1,176 @@
+use crate::{
+    config::{PaddingConfig, PaddingPolicy},
+    random::Random,
+};
+use rand::Rng;
+use std::{io, net::IpAddr, sync::Arc};
+use trust_dns_server::{
+    authority::{MessageRequest, MessageResponse, MessageResponseBuilder},
+    client::rr::Record,
+    proto::{
+        op::Edns,
+        rr::rdata::opt::{EdnsCode, EdnsOption},
+        serialize::binary::{BinDecodable, BinEncoder},
+    },
+    server::{Protocol, Request, ResponseHandler, ResponseInfo},
+};
+
+// The EDNS option code of Padding (RFC 7830).
+const PADDING: u16 = 12;
+
+// The length of an OPT record without options: a root name, its type, class, TTL and data length.
+const OPT_RECORD_LENGTH: usize = 11;
+
+// The length of an EDNS option's code and length.
+const OPTION_HEADER_LENGTH: usize = 4;
+
+// The longest message TCP can carry, behind its two length octets.
+const MAX_TCP_LENGTH: usize = 65535;
+
+/*
+Description:
+decides which answers are padded and by how much, by the [padding] section.
+*/
+#[derive(Debug)]
+pub struct Padding {
+    // The [padding] section
+    config: PaddingConfig,
+}
+
+impl Padding {
+    // Creates the padding of the [padding] section
+    pub fn new(config: &PaddingConfig) -> Self {
+        Padding { config: config.clone() }
+    }
+
+    // Returns whether a client is a proxy that terminates an encrypted transport
+    pub fn encrypted(&self, client: IpAddr) -> bool {
+        self.config.encrypted_from.iter().any(|network| network.contains(client))
+    }
+
+    // Returns whether the answer to a request is padded: the request came encrypted and asked for padding
+    pub fn applies(&self, request: &Request) -> bool {
+        let asked = request.edns().is_some_and(|edns| edns.options().get(EdnsCode::Padding).is_some());
+        self.encrypted(request.src().ip()) && asked
+    }
+
+    /*
+    Description:
+    chooses the length of an answer's padding.
+
+    Parameters:
+    length: the length of the answer with an empty Padding option.
+    limit: the longest the answer may be.
+    random: where random padding draws from.
+
+    Returns:
+    The number of octets of padding, which never takes the answer past the limit.
+    */
+    pub fn padding_length(&self, length: usize, limit: usize, random: &Random) -> usize {
+        let block = usize::from(self.config.block_size.max(1));
+        let padding = match self.config.policy {
+            PaddingPolicy::Block => (block - length % block) % block,
+            PaddingPolicy::Random => random.with_rng(|rng| rng.gen_range(0..=block)),
+        };
+        padding.min(limit.saturating_sub(length))
+    }
+}
+
+/*
+Description:
+a response handler that pads the answer to a request the Padding applies to with a Padding option, adding an OPT record if the answer has none. Like the dnstap responder, it serializes the answer to measure it and rebuilds it for the wrapped handler; answers to other requests pass through untouched.
+*/
+#[derive(Clone)]
+pub struct PaddingResponder<R: ResponseHandler> {
+    // The handler that sends the response to the client
+    inner: R,
+
+    // The padding, if the answer is padded
+    padding: Option<Arc<Padding>>,
+
+    // Where random padding draws from
+    random: Random,
+
+    // The query's UDP payload size, at least 512
+    payload: u16,
+
+    // The longest the answer may be: the query's payload size over UDP, the most TCP can carry otherwise
+    limit: usize,
+}
+
+impl<R: ResponseHandler> PaddingResponder<R> {
+    /*
+    Description:
+    wraps a response handler so the answer to a request is padded if the padding applies to it.
+
+    Parameters:
+    inner: the handler that sends the response to the client.
+    padding: the server's padding, if it is configured.
+    request: the query being answered.
+    random: where random padding draws from.
+
+    Returns:
+    A new PaddingResponder.
+    */
+    pub fn new(inner: R, padding: Option<Arc<Padding>>, request: &Request, random: Random) -> Self {
+        let payload = request.edns().map_or(512, |edns| edns.max_payload().max(512));
+        let limit = match request.protocol() {
+            Protocol::Udp => usize::from(payload),
+            _ => MAX_TCP_LENGTH,
+        };
+        PaddingResponder { inner, padding: padding.filter(|padding| padding.applies(request)), random, payload, limit }
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: ResponseHandler> ResponseHandler for PaddingResponder<R> {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        let Some(padding) = &self.padding else {
+            return self.inner.send_response(response).await;
+        };
+
+        // Serialize the answer to measure it, and decode it again so it can be rebuilt with the padding
+        let mut bytes = Vec::with_capacity(512);
+        response
+            .destructive_emit(&mut BinEncoder::new(&mut bytes))
+            .map_err(io::Error::other)?;
+        let message = MessageRequest::from_bytes(&bytes).map_err(io::Error::other)?;
+
+        // Pad the answer by what it is short of the padded length once it carries an empty Padding option, unless even that doesn't fit
+        let mut edns = message.edns().cloned();
+        let length = bytes.len() + OPTION_HEADER_LENGTH + if edns.is_some() { 0 } else { OPT_RECORD_LENGTH };
+        if length <= self.limit {
+            let edns = edns.get_or_insert_with(|| {
+                let mut edns = Edns::new();
+                edns.set_max_payload(self.payload);
+                edns
+            });
+            let padding = padding.padding_length(length, self.limit, &self.random);
+            edns.options_mut().insert(EdnsOption::Unknown(PADDING, vec![0; padding]));
+        }
+
+        // Rebuild the answer and send it
+        let mut builder = MessageResponseBuilder::from_message_request(&message);
+        if let Some(edns) = edns {
+            builder.edns(edns);
+        }
+        let response = builder.build(
+            *message.header(),
+            message.answers().iter(),
+            message.name_servers().iter(),
+            &[],
+            message.additionals().iter(),
+        );
+        self.inner.send_response(response).await
+    }
+}
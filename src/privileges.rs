@@ -0,0 +1,188 @@
+use std::{ffi::CString, io, net::SocketAddr};
+
+// The version of the capability structures passed to capget and capset, _LINUX_CAPABILITY_VERSION_3 (64-bit sets in two halves)
+#[cfg(target_os = "linux")]
+const CAPABILITY_VERSION: u32 = 0x2008_0522;
+
+// The header of capget and capset, struct __user_cap_header_struct
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct CapabilityHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+// One half of the capability sets of capget and capset, struct __user_cap_data_struct
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapabilityData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/*
+Description:
+defines the errors that can occur while dropping root privileges, or binding a port that needs them.
+*/
+#[derive(thiserror::Error, Debug)]
+pub enum PrivilegeError {
+    #[error("Unknown user {0:}")]
+    UnknownUser(String),
+    #[error("Unknown group {0:}")]
+    UnknownGroup(String),
+    #[error("--user and --group need the server to be started as root")]
+    NotRoot,
+    #[error("Failed to {0:}: {1:}")]
+    Failed(&'static str, io::Error),
+    #[error("Binding {0:} needs root or CAP_NET_BIND_SERVICE (e.g. setcap cap_net_bind_service=+ep on the binary), or a port of 1024 or above: {1:}")]
+    PrivilegedPort(SocketAddr, io::Error),
+}
+
+/*
+Description:
+drops root privileges by switching to an unprivileged user and group. This is meant to be called once every socket is bound, so the server can bind port 53 as root and then serve as, e.g., "nobody". Supplementary groups are cleared, and since the user ID changes away from root the kernel clears the capabilities of every thread. On Linux the calling thread's capability sets, ambient set included, are then cleared explicitly and checked to be empty, and no_new_privs is set so nothing the server runs can gain privileges through a setuid or file-capability binary; callers that start threads before this should call forbid_new_privileges first, since no_new_privs only reaches the calling thread and the threads it starts.
+
+Parameters:
+user: the user name or numeric ID to switch to, if any.
+group: the group name or numeric ID to switch to; defaults to the user's primary group.
+
+Returns:
+Ok(()) once the process runs as the given user and group, or a PrivilegeError if they don't exist, the process isn't root, or a switch failed.
+*/
+pub fn drop_privileges(user: Option<&str>, group: Option<&str>) -> Result<(), PrivilegeError> {
+    // Only root can change its user and group
+    if unsafe { libc::geteuid() } != 0 {
+        return Err(PrivilegeError::NotRoot);
+    }
+
+    // Look up the user, and the group if one was given or else the user's primary group
+    let user = user.map(lookup_user).transpose()?;
+    let gid = match group {
+        Some(group) => Some(lookup_group(group)?),
+        None => user.map(|(_, gid)| gid),
+    };
+
+    // Switch the group first, while we are still root
+    if let Some(gid) = gid {
+        if unsafe { libc::setgroups(1, &gid) } != 0 {
+            return Err(PrivilegeError::Failed("clear supplementary groups", io::Error::last_os_error()));
+        }
+        if unsafe { libc::setgid(gid) } != 0 {
+            return Err(PrivilegeError::Failed("change group", io::Error::last_os_error()));
+        }
+    }
+
+    // Then switch the user, and make sure there is no way back
+    if let Some((uid, _)) = user {
+        if unsafe { libc::setuid(uid) } != 0 {
+            return Err(PrivilegeError::Failed("change user", io::Error::last_os_error()));
+        }
+        if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+            return Err(PrivilegeError::Failed("drop root", io::Error::from(io::ErrorKind::PermissionDenied)));
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            clear_capabilities()?;
+            forbid_new_privileges()?;
+        }
+    }
+
+    Ok(())
+}
+
+/*
+Description:
+sets no_new_privs, so neither this thread nor any thread or program it starts can gain privileges by executing a setuid or file-capability binary. It is a per-thread flag that new threads inherit, so it is meant to be called before the runtime's threads are started.
+
+Parameters:
+None
+
+Returns:
+Ok(()) once the flag is set, or a PrivilegeError if the kernel refused.
+*/
+#[cfg(target_os = "linux")]
+pub fn forbid_new_privileges() -> Result<(), PrivilegeError> {
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(PrivilegeError::Failed("set no_new_privs", io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+// Clears the calling thread's ambient, effective, permitted and inheritable capabilities, and checks that none are left
+#[cfg(target_os = "linux")]
+fn clear_capabilities() -> Result<(), PrivilegeError> {
+    // Kernels before 4.3 have no ambient set, so there is nothing to clear
+    let cleared = unsafe { libc::prctl(libc::PR_CAP_AMBIENT, libc::PR_CAP_AMBIENT_CLEAR_ALL, 0, 0, 0) };
+    let error = io::Error::last_os_error();
+    if cleared != 0 && error.raw_os_error() != Some(libc::EINVAL) {
+        return Err(PrivilegeError::Failed("clear ambient capabilities", error));
+    }
+
+    let mut header = CapabilityHeader { version: CAPABILITY_VERSION, pid: 0 };
+    let mut data = [CapabilityData::default(); 2];
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header as *mut CapabilityHeader, data.as_ptr()) } != 0 {
+        return Err(PrivilegeError::Failed("clear capabilities", io::Error::last_os_error()));
+    }
+
+    if unsafe { libc::syscall(libc::SYS_capget, &mut header as *mut CapabilityHeader, data.as_mut_ptr()) } != 0 {
+        return Err(PrivilegeError::Failed("read capabilities", io::Error::last_os_error()));
+    }
+    if data.iter().any(|half| half.effective | half.permitted | half.inheritable != 0) {
+        return Err(PrivilegeError::Failed("clear capabilities", io::Error::from(io::ErrorKind::PermissionDenied)));
+    }
+    Ok(())
+}
+
+/*
+Description:
+explains a failure to bind a socket. Binding a port below 1024 is refused to processes that are neither root nor hold CAP_NET_BIND_SERVICE, which the bare "Permission denied" doesn't say.
+
+Parameters:
+address: the address that could not be bound.
+error: the error binding it.
+
+Returns:
+A PrivilegeError::PrivilegedPort for a privileged port that was refused, or the error as it was.
+*/
+pub fn bind_error(address: SocketAddr, error: io::Error) -> Box<dyn std::error::Error + Send + Sync> {
+    match error.kind() {
+        io::ErrorKind::PermissionDenied if address.port() < 1024 => Box::new(PrivilegeError::PrivilegedPort(address, error)),
+        _ => Box::new(error),
+    }
+}
+
+// Looks up a user by name or numeric ID, returning its user ID and primary group ID
+fn lookup_user(user: &str) -> Result<(libc::uid_t, libc::gid_t), PrivilegeError> {
+    let name = CString::new(user).map_err(|_| PrivilegeError::UnknownUser(user.to_string()))?;
+
+    // getpwnam isn't thread-safe, but this runs once at startup before anything else looks up users
+    let entry = match user.parse::<libc::uid_t>() {
+        Ok(uid) => unsafe { libc::getpwuid(uid) },
+        Err(_) => unsafe { libc::getpwnam(name.as_ptr()) },
+    };
+    if entry.is_null() {
+        return Err(PrivilegeError::UnknownUser(user.to_string()));
+    }
+
+    let entry = unsafe { &*entry };
+    Ok((entry.pw_uid, entry.pw_gid))
+}
+
+// Looks up a group by name or numeric ID, returning its group ID
+fn lookup_group(group: &str) -> Result<libc::gid_t, PrivilegeError> {
+    // Numeric IDs don't need to exist in /etc/group
+    if let Ok(gid) = group.parse::<libc::gid_t>() {
+        return Ok(gid);
+    }
+
+    let name = CString::new(group).map_err(|_| PrivilegeError::UnknownGroup(group.to_string()))?;
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(PrivilegeError::UnknownGroup(group.to_string()));
+    }
+
+    Ok(unsafe { (*entry).gr_gid })
+}
@@ -0,0 +1,182 @@
+use rusqlite::{params, Connection};
+use std::{
+    net::IpAddr,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use tracing::*;
+
+// The largest number of entries written in one transaction.
+const BATCH_SIZE: usize = 1000;
+
+// The number of entries that can be queued for the writer before new entries are dropped.
+const BUFFER_SIZE: usize = 10_000;
+
+// How often entries older than the retention period are deleted.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/*
+Description:
+a single logged query.
+*/
+#[derive(Clone, Debug)]
+pub struct QueryLogEntry {
+    // Unix timestamp of the query, in milliseconds
+    pub timestamp: i64,
+
+    // The IP address of the client
+    pub client: IpAddr,
+
+    // The queried name
+    pub qname: String,
+
+    // The queried record type, e.g. "TXT"
+    pub qtype: String,
+
+    // The numeric response code of the answer
+    pub rcode: u16,
+
+    // How long the query took to answer, in microseconds
+    pub latency_us: u64,
+}
+
+/*
+Description:
+a query log backed by a SQLite database, for offline analysis with plain SQL. Entries are handed to a dedicated writer thread through a bounded buffer and written in batches, one transaction per batch, so logging never blocks query handling; when the buffer is full, entries are dropped and counted. Entries older than the retention period are pruned periodically.
+*/
+#[derive(Debug)]
+pub struct QueryLog {
+    // Channel to the writer thread
+    entries: mpsc::Sender<QueryLogEntry>,
+
+    // The number of entries dropped because the buffer was full
+    dropped: AtomicU64,
+}
+
+impl QueryLog {
+    /*
+    Description:
+    opens (creating if needed) the query log database and starts the writer thread.
+
+    Parameters:
+    path: the SQLite database file.
+    retention: how long entries are kept; None keeps them forever.
+
+    Returns:
+    Ok(QueryLog) if the database could be opened, or a rusqlite::Error otherwise.
+    */
+    pub fn open(path: &Path, retention: Option<Duration>) -> rusqlite::Result<Self> {
+        // Open the database and create the schema
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS queries (
+                 timestamp INTEGER NOT NULL,
+                 client TEXT NOT NULL,
+                 qname TEXT NOT NULL,
+                 qtype TEXT NOT NULL,
+                 rcode INTEGER NOT NULL,
+                 latency_us INTEGER NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS queries_timestamp ON queries (timestamp);",
+        )?;
+
+        // SQLite is blocking, so the writer gets its own thread
+        let (entries, receiver) = mpsc::channel(BUFFER_SIZE);
+        std::thread::Builder::new()
+            .name("query-log".to_string())
+            .spawn(move || write_entries(connection, receiver, retention))
+            .expect("failed to spawn the query log thread");
+
+        Ok(QueryLog {
+            entries,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /*
+    Description:
+    queues an entry for writing, dropping it if the buffer is full.
+
+    Parameters:
+    entry: the entry to log.
+
+    Returns:
+    None
+    */
+    pub fn record(&self, entry: QueryLogEntry) {
+        if self.entries.try_send(entry).is_err() {
+            // Only warn on the first drop and then every 1000th, so a slow disk doesn't flood the log
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed);
+            if dropped % 1000 == 0 {
+                warn!("Query log buffer full, {} entries dropped so far", dropped + 1);
+            }
+        }
+    }
+}
+
+/*
+Description:
+the writer thread. It waits for an entry, gathers whatever else is queued (up to BATCH_SIZE) and writes the batch in a single transaction, pruning old entries every PRUNE_INTERVAL. The thread ends when the QueryLog is dropped.
+
+Parameters:
+connection: the open database.
+entries: the receiving end of the entry buffer.
+retention: how long entries are kept; None keeps them forever.
+
+Returns:
+None
+*/
+fn write_entries(mut connection: Connection, mut entries: mpsc::Receiver<QueryLogEntry>, retention: Option<Duration>) {
+    let mut last_prune: Option<Instant> = None;
+
+    while let Some(entry) = entries.blocking_recv() {
+        // Gather the batch
+        let mut batch = vec![entry];
+        while batch.len() < BATCH_SIZE {
+            match entries.try_recv() {
+                Ok(entry) => batch.push(entry),
+                Err(_) => break,
+            }
+        }
+
+        if let Err(error) = write_batch(&mut connection, &batch) {
+            warn!("Failed to write {} query log entries: {error}", batch.len());
+        }
+
+        // Prune old entries when it is due
+        if let Some(retention) = retention {
+            if last_prune.map_or(true, |last| last.elapsed() >= PRUNE_INTERVAL) {
+                last_prune = Some(Instant::now());
+                let cutoff = chrono::Utc::now().timestamp_millis() - retention.as_millis() as i64;
+                match connection.execute("DELETE FROM queries WHERE timestamp < ?1", params![cutoff]) {
+                    Ok(pruned) => debug!("Pruned {pruned} query log entries"),
+                    Err(error) => warn!("Failed to prune the query log: {error}"),
+                }
+            }
+        }
+    }
+}
+
+// Writes a batch of entries in a single transaction
+fn write_batch(connection: &mut Connection, batch: &[QueryLogEntry]) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    {
+        let mut insert = transaction.prepare_cached(
+            "INSERT INTO queries (timestamp, client, qname, qtype, rcode, latency_us) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for entry in batch {
+            insert.execute(params![
+                entry.timestamp,
+                entry.client.to_string(),
+                entry.qname,
+                entry.qtype,
+                entry.rcode,
+                entry.latency_us as i64,
+            ])?;
+        }
+    }
+    transaction.commit()
+}
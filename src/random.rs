@@ -0,0 +1,63 @@
+use rand::{
+    rngs::{SmallRng, StdRng},
+    RngCore, SeedableRng,
+};
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex},
+};
+
+thread_local! {
+    // Each thread's own generator, seeded from the OS the first time the thread draws.
+    static LOCAL: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+/*
+Description:
+where a handler's random zones (coin, dice, lotto, rps, name, ulid) draw from: each thread's own fast generator, or in deterministic mode one generator seeded with --seed and shared by every clone of the handler, so the same queries in the same order get the same answers on every run. The seeded generator is shared behind a lock, so it is meant for tests and demos rather than production.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct Random {
+    // The seeded generator, or None to draw from the calling thread's own
+    seeded: Option<Arc<Mutex<StdRng>>>,
+}
+
+impl Random {
+    // Returns a source that draws from a generator seeded with the given seed
+    pub fn seeded(seed: u64) -> Self {
+        Random {
+            seeded: Some(Arc::new(Mutex::new(StdRng::seed_from_u64(seed)))),
+        }
+    }
+
+    /*
+    Description:
+    runs a function with this source's generator: the seeded one in deterministic mode, and the calling thread's own otherwise.
+
+    Parameters:
+    f: the function, which draws from the generator it is given.
+
+    Returns:
+    What the function returns.
+    */
+    pub fn with_rng<T>(&self, f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        match &self.seeded {
+            Some(seeded) => f(&mut *seeded.lock().unwrap()),
+            None => with_rng(f),
+        }
+    }
+}
+
+/*
+Description:
+runs a function with the calling thread's own fast generator, which is set up once per thread rather than per draw, for randomness that never needs to repeat, such as query IDs and log sampling.
+
+Parameters:
+f: the function, which draws from the generator it is given.
+
+Returns:
+What the function returns.
+*/
+pub fn with_rng<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    LOCAL.with(|rng| f(&mut *rng.borrow_mut()))
+}
@@ -0,0 +1,337 @@
+use crate::{config::SoaConfig, options::Options, zonefile};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+use trust_dns_server::client::rr::{
+    rdata::{MX, SOA, SRV, TXT},
+    LowerName, Name, RData, Record, RecordType,
+};
+
+// The most CNAME records followed within the store for a single answer.
+const MAX_CNAME_CHAIN: usize = 8;
+
+/*
+Description:
+the store's answer for a name and type.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    // The records of the type, after the CNAME records that led to them if any
+    Answer(Vec<Record>),
+
+    // The name exists but has no records of the type; with the SOA record of its zone, if it is in one
+    NoData(Option<Record>),
+
+    // The name is in a zone of the store but doesn't exist; with the zone's SOA record
+    NxDomain(Record),
+}
+
+// A record with the time it expires, if it does
+#[derive(Debug)]
+struct Entry {
+    // The record, with its TTL when it was stored
+    record: Record,
+
+    // When the record stops being served
+    expires: Option<Instant>,
+}
+
+impl Entry {
+    // Returns the record as served now, its TTL cut to the time it has left, or None if it has expired
+    fn live(&self, now: Instant) -> Option<Record> {
+        let Some(expires) = self.expires else {
+            return Some(self.record.clone());
+        };
+        let left = expires.checked_duration_since(now).filter(|left| !left.is_zero())?;
+        let mut record = self.record.clone();
+        record.set_ttl(record.ttl().min(left.as_secs_f64().ceil() as u32));
+        Some(record)
+    }
+}
+
+// The records of one source, indexed for lookups
+#[derive(Debug, Default)]
+struct Layer {
+    // The records of each name
+    names: HashMap<LowerName, Vec<Entry>>,
+
+    // The SOA records of the zones the source is authoritative for
+    zones: Vec<Record>,
+
+    // The names within those zones that have no records but names below them that do (empty non-terminals)
+    parents: HashSet<LowerName>,
+}
+
+/*
+Description:
+records served as they are, from backends that hold records rather than compute them, such as a PostgreSQL table, Redis keys or zone files. Each backend replaces its own records whenever they change, and a name is served if a backend has records for it or if it is within a zone a backend has an SOA record for, in which case names without records don't exist.
+*/
+#[derive(Debug, Default)]
+pub struct RecordStore {
+    // The records of each source, by the source's name
+    layers: RwLock<BTreeMap<String, Arc<Layer>>>,
+}
+
+impl RecordStore {
+    /*
+    Description:
+    replaces the records of a source, e.g. after the backend reported a change. Names are compared without regard to case, and the owners of SOA records are the zones the source is authoritative for.
+
+    Parameters:
+    source: the source's name, e.g. "postgres".
+    records: the source's records.
+
+    Returns:
+    None
+    */
+    pub fn replace(&self, source: &str, records: Vec<Record>) {
+        self.replace_expiring(source, records.into_iter().map(|record| (record, None)).collect());
+    }
+
+    /*
+    Description:
+    replaces the records of a source like replace, for backends whose records expire on their own: a record is served with its TTL cut to the time it has left, and not at all once it has expired, even if the source hasn't replaced its records since.
+
+    Parameters:
+    source: the source's name, e.g. "redis".
+    records: the source's records, with the time each expires if it does.
+
+    Returns:
+    None
+    */
+    pub fn replace_expiring(&self, source: &str, records: Vec<(Record, Option<Instant>)>) {
+        let mut layer = Layer::default();
+        for (record, expires) in records {
+            if record.record_type() == RecordType::SOA {
+                layer.zones.push(record.clone());
+            }
+            layer.names.entry(LowerName::new(record.name())).or_default().push(Entry { record, expires });
+        }
+
+        // Note the names between each name and the apex of its zone, which exist although they have no records
+        for name in layer.names.keys() {
+            let Some(zone) = layer.zones.iter().map(|soa| LowerName::new(soa.name())).filter(|zone| zone.zone_of(name)).max_by_key(LowerName::num_labels) else {
+                continue;
+            };
+            let mut parent = name.base_name();
+            while parent.num_labels() > zone.num_labels() {
+                layer.parents.insert(parent.clone());
+                parent = parent.base_name();
+            }
+        }
+
+        self.layers.write().unwrap().insert(source.to_string(), Arc::new(layer));
+    }
+
+    // Returns the number of names with records, across the sources
+    pub fn names(&self) -> usize {
+        self.layers.read().unwrap().values().map(|layer| layer.names.len()).sum()
+    }
+
+    // Returns whether the store answers for a name: some source has records for it or a zone containing it
+    pub fn serves(&self, name: &LowerName) -> bool {
+        let layers = self.layers.read().unwrap();
+        let now = Instant::now();
+        layers.values().any(|layer| {
+            layer.names.get(name).is_some_and(|entries| entries.iter().any(|entry| entry.live(now).is_some()))
+                || layer.zones.iter().any(|soa| LowerName::new(soa.name()).zone_of(name))
+        })
+    }
+
+    /*
+    Description:
+    looks up the records of a type for a name, following CNAME records within the store. A CNAME whose target the store doesn't serve ends the answer, leaving the rest to the client.
+
+    Parameters:
+    name: the name.
+    record_type: the type; ANY returns every record of the name.
+
+    Returns:
+    Some(Lookup) with the answer, or None if the store doesn't serve the name.
+    */
+    pub fn lookup(&self, name: &LowerName, record_type: RecordType) -> Option<Lookup> {
+        let layers = self.layers.read().unwrap();
+        let now = Instant::now();
+        let mut answers = Vec::new();
+        let mut current = name.clone();
+
+        for _ in 0..MAX_CNAME_CHAIN {
+            let records: Vec<Record> = layers
+                .values()
+                .filter_map(|layer| layer.names.get(&current))
+                .flatten()
+                .filter_map(|entry| entry.live(now))
+                .collect();
+            if records.is_empty() {
+                if !answers.is_empty() {
+                    break;
+                }
+                let soa = zone_of(&layers, name);
+                if layers.values().any(|layer| layer.parents.contains(name)) {
+                    return Some(Lookup::NoData(soa));
+                }
+                return soa.map(Lookup::NxDomain);
+            }
+
+            // The records of the type, or else the CNAME to follow
+            let matching: Vec<Record> = records
+                .iter()
+                .filter(|record| record_type == RecordType::ANY || record.record_type() == record_type)
+                .cloned()
+                .collect();
+            if !matching.is_empty() {
+                answers.extend(matching);
+                break;
+            }
+            match records.iter().find_map(|record| match record.data() {
+                Some(RData::CNAME(target)) => Some((record, LowerName::new(target))),
+                _ => None,
+            }) {
+                Some((cname, target)) => {
+                    answers.push(cname.clone());
+                    current = target;
+                }
+                None if answers.is_empty() => return Some(Lookup::NoData(zone_of(&layers, name))),
+                None => break,
+            }
+        }
+
+        Some(Lookup::Answer(answers))
+    }
+}
+
+// Returns the SOA record of the closest zone containing a name, if any
+fn zone_of(layers: &BTreeMap<String, Arc<Layer>>, name: &LowerName) -> Option<Record> {
+    layers
+        .values()
+        .flat_map(|layer| &layer.zones)
+        .filter(|soa| LowerName::new(soa.name()).zone_of(name))
+        .max_by_key(|soa| soa.name().num_labels())
+        .cloned()
+}
+
+// Returns whether the options give a backend that serves its records from the record store
+pub fn configured(options: &Options) -> bool {
+    #[cfg(feature = "postgres")]
+    if options.postgres.is_some() {
+        return true;
+    }
+    #[cfg(feature = "redis")]
+    if options.redis_records {
+        return true;
+    }
+    !options.zone_file.is_empty()
+}
+
+/*
+Description:
+builds a record from its parts as a backend keeps them as text: a name, a type, a TTL and the data written as in a zone file (e.g. "10 mail.example.com" for an MX record). Names are absolute whether or not they end with a dot. The types A, AAAA, CNAME, NS, PTR, MX, TXT, SRV and SOA are supported.
+
+Parameters:
+name: the record's owner.
+record_type: the record's type, e.g. "MX".
+ttl: the record's TTL in seconds.
+data: the record's data.
+
+Returns:
+Ok(Record), or an error message saying what is wrong with the record.
+*/
+pub fn record(name: &str, record_type: &str, ttl: u32, data: &str) -> Result<Record, String> {
+    let owner = Name::parse(name, Some(&Name::root())).map_err(|error| format!("invalid name {name}: {error}"))?;
+    let record_type = RecordType::from_str(&record_type.to_ascii_uppercase()).map_err(|_| format!("unknown record type {record_type}"))?;
+    let fields = zonefile::parse_data(record_type, data, &Name::root())?;
+    Ok(Record::from_rdata(owner, ttl, rdata(record_type, &fields)?))
+}
+
+/*
+Description:
+builds the SOA record of a zone from the [soa] section, with the TTL negative answers carry it with: the lower of its own TTL and its minimum (RFC 2308 section 3). The hostmaster can be written as a mail address, whose @ becomes the first dot of the name.
+
+Parameters:
+zone: the zone, e.g. the domain.
+config: the [soa] section.
+
+Returns:
+Ok(Record), or an error message saying which name is invalid.
+*/
+pub fn soa_record(zone: &Name, config: &SoaConfig) -> Result<Record, String> {
+    let name = |field: &str, text: Option<&String>, default: &str| -> Result<Name, String> {
+        match text {
+            Some(text) => Name::parse(text, Some(&Name::root())).map_err(|error| format!("[soa] invalid {field} {text}: {error}")),
+            None => Name::from_ascii(default).and_then(|name| name.append_domain(zone)).map_err(|error| format!("[soa] invalid {field}: {error}")),
+        }
+    };
+    let primary = name("primary", config.primary.as_ref(), "ns1")?;
+    let mailbox = config.hostmaster.as_ref().map(|hostmaster| match hostmaster.split_once('@') {
+        Some((local, domain)) => format!("{}.{domain}", local.replace('.', "\\.")),
+        None => hostmaster.clone(),
+    });
+    let hostmaster = name("hostmaster", mailbox.as_ref(), "hostmaster")?;
+    let soa = SOA::new(
+        primary,
+        hostmaster,
+        config.serial,
+        config.refresh as i32,
+        config.retry as i32,
+        config.expire as i32,
+        config.minimum,
+    );
+    Ok(Record::from_rdata(zone.clone(), config.ttl.min(config.minimum), RData::SOA(soa)))
+}
+
+/*
+Description:
+turns the fields of a record's data, as zonefile::parse and zonefile::parse_data check them, into the record's data.
+
+Parameters:
+record_type: the record's type.
+fields: the fields of the data, with absolute names.
+
+Returns:
+Ok(RData), or an error message if the type is not supported or a field is invalid.
+*/
+pub fn rdata(record_type: RecordType, fields: &[String]) -> Result<RData, String> {
+    let invalid = || format!("invalid {record_type} data {}", fields.join(" "));
+    let name = |index: usize| fields.get(index).and_then(|field| Name::from_str(field).ok()).ok_or_else(invalid);
+    let number = |index: usize| fields.get(index).and_then(|field| field.parse::<u16>().ok()).ok_or_else(invalid);
+    let seconds = |index: usize| fields.get(index).and_then(|field| zonefile::parse_ttl(field)).ok_or_else(invalid);
+
+    Ok(match record_type {
+        RecordType::A => RData::A(fields[0].parse::<Ipv4Addr>().map_err(|_| invalid())?),
+        RecordType::AAAA => RData::AAAA(fields[0].parse::<Ipv6Addr>().map_err(|_| invalid())?),
+        RecordType::CNAME => RData::CNAME(name(0)?),
+        RecordType::NS => RData::NS(name(0)?),
+        RecordType::PTR => RData::PTR(name(0)?),
+        RecordType::MX => RData::MX(MX::new(number(0)?, name(1)?)),
+        RecordType::SRV => RData::SRV(SRV::new(number(0)?, number(1)?, number(2)?, name(3)?)),
+        RecordType::TXT => RData::TXT(TXT::new(fields.iter().map(|field| unquote(field)).collect())),
+        RecordType::SOA => RData::SOA(SOA::new(
+            name(0)?,
+            name(1)?,
+            fields[2].parse().map_err(|_| invalid())?,
+            seconds(3)? as i32,
+            seconds(4)? as i32,
+            seconds(5)? as i32,
+            seconds(6)?,
+        )),
+        _ => return Err(format!("{record_type} records are not supported")),
+    })
+}
+
+// Removes the quotes around a character string and its backslash escapes
+fn unquote(field: &str) -> String {
+    let inner = field.strip_prefix('"').and_then(|field| field.strip_suffix('"')).unwrap_or(field);
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            _ => text.push(c),
+        }
+    }
+    text
+}
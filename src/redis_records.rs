@@ -0,0 +1,170 @@
+use crate::records::{self, RecordStore};
+use redis::{aio::MultiplexedConnection, FromRedisValue, RedisResult};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::*;
+use trust_dns_server::client::rr::Record;
+
+// The name the Redis records are kept under in the record store.
+pub const SOURCE: &str = "redis";
+
+// The TTL of records whose keys don't expire.
+pub const PERSISTENT_TTL: u32 = 60;
+
+// The number of keys asked for per SCAN.
+const SCAN_COUNT: usize = 1000;
+
+/*
+Description:
+reads the records of a Redis key: the key is <prefix><name>:<type>, e.g. dns:www.example.com:A, and its value holds the data of one record per line, written as in a zone file; a set holds one record per member. Records of keys that expire take the time the key has left as their TTL, rounded up to a second.
+
+Parameters:
+prefix: the prefix of the record keys.
+key: the key.
+values: the key's value, or the members of its set.
+pttl: the key's time to live in milliseconds as PTTL gives it, negative if it doesn't expire.
+
+Returns:
+Ok(Vec<Record>) with the key's records, or an error message if the key or a record is invalid.
+*/
+pub fn records(prefix: &str, key: &str, values: &[String], pttl: i64) -> Result<Vec<Record>, String> {
+    let (name, record_type) = key
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.rsplit_once(':'))
+        .ok_or_else(|| format!("{key} is not of the form {prefix}<name>:<type>"))?;
+    let ttl = match pttl {
+        ..=0 => PERSISTENT_TTL,
+        _ => u32::try_from((pttl + 999) / 1000).unwrap_or(u32::MAX),
+    };
+    values
+        .iter()
+        .flat_map(|value| value.lines())
+        .map(str::trim)
+        .filter(|data| !data.is_empty())
+        .map(|data| records::record(name, record_type, ttl, data))
+        .collect()
+}
+
+/*
+Description:
+keeps the record store's Redis records up to date, reading every key below the prefix each interval. Records whose keys expire stop being served when they do, between reads too. If Redis can't be read, the last records read are served until they expire or Redis is back.
+
+Parameters:
+store: the record store to keep up to date.
+url: the URL of the Redis server.
+prefix: the prefix of the record keys, e.g. dns:.
+interval: how often to read the keys.
+
+Returns:
+None; runs until the process exits.
+*/
+pub async fn follow(store: Arc<RecordStore>, url: String, prefix: String, interval: Duration) {
+    let client = match redis::Client::open(url.as_str()) {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Invalid Redis URL, not serving records from Redis: {error}");
+            return;
+        }
+    };
+    let mut ticker = tokio::time::interval(interval);
+    let mut connection = None;
+
+    loop {
+        ticker.tick().await;
+
+        // (Re)connect if needed
+        if connection.is_none() {
+            match client.get_multiplexed_tokio_connection().await {
+                Ok(established) => connection = Some(established),
+                Err(error) => {
+                    warn!("Failed to connect to Redis: {error}");
+                    continue;
+                }
+            }
+        }
+        let Some(redis) = connection.as_mut() else {
+            continue;
+        };
+
+        match scan(redis, &prefix).await {
+            Ok(records) => {
+                debug!("Serving {} records from Redis", records.len());
+                store.replace_expiring(SOURCE, records);
+            }
+            Err(error) => {
+                warn!("Failed to read the records from Redis, serving the last ones read: {error}");
+                connection = None;
+            }
+        }
+    }
+}
+
+// Reads the records of every key below the prefix, with the time each expires
+async fn scan(redis: &mut MultiplexedConnection, prefix: &str) -> RedisResult<Vec<(Record, Option<Instant>)>> {
+    let pattern = format!("{}*", escape_pattern(prefix));
+    let mut records = Vec::new();
+    let mut cursor = 0u64;
+
+    loop {
+        let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(&pattern)
+            .arg("COUNT")
+            .arg(SCAN_COUNT)
+            .query_async(redis)
+            .await?;
+
+        if !keys.is_empty() {
+            // Find out what each key holds and when it expires, then read the strings and sets
+            let mut pipe = redis::pipe();
+            for key in &keys {
+                pipe.cmd("TYPE").arg(key).cmd("PTTL").arg(key);
+            }
+            let kinds: Vec<(String, i64)> = pipe.query_async(redis).await?;
+
+            let mut pipe = redis::pipe();
+            let mut read = Vec::new();
+            for (key, (kind, pttl)) in keys.iter().zip(kinds) {
+                match kind.as_str() {
+                    "string" => pipe.cmd("GET").arg(key),
+                    "set" => pipe.cmd("SMEMBERS").arg(key),
+                    _ => continue,
+                };
+                read.push((key, pttl));
+            }
+            let values: Vec<redis::Value> = if read.is_empty() { Vec::new() } else { pipe.query_async(redis).await? };
+
+            let now = Instant::now();
+            for ((key, pttl), value) in read.into_iter().zip(values) {
+                let values = Vec::<String>::from_redis_value(&value)?;
+                match self::records(prefix, key, &values, pttl) {
+                    Ok(found) => {
+                        let expires = (pttl > 0).then(|| now + Duration::from_millis(pttl as u64));
+                        records.extend(found.into_iter().map(|record| (record, expires)));
+                    }
+                    Err(error) => debug!("Skipping the Redis key {key}: {error}"),
+                }
+            }
+        }
+
+        if next == 0 {
+            return Ok(records);
+        }
+        cursor = next;
+    }
+}
+
+// Escapes the characters that are special in a Redis glob pattern
+fn escape_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
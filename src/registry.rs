@@ -0,0 +1,429 @@
+use crate::options::Options;
+use base64::Engine;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tracing::*;
+
+// The keyword the registry zone is known by in the configuration and metrics, whatever label it is served under.
+pub const ZONE: &str = "registry";
+
+// The TTL of the registry zone's records, short so that changes are seen within seconds.
+pub const TTL: u32 = 10;
+
+// How long a Consul blocking query waits for a change before it is sent again.
+const BLOCKING_WAIT: Duration = Duration::from_secs(300);
+
+// How long a request that isn't waiting for a change may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How long to wait before reconnecting after losing the backend.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/*
+Description:
+defines the errors that can occur while reading records from a backend.
+*/
+#[derive(thiserror::Error, Debug)]
+pub enum RegistryError {
+    #[error("HTTP error: {0:}")]
+    Http(#[from] reqwest::Error),
+    #[error("Malformed response: {0:}")]
+    MalformedResponse(String),
+}
+
+/*
+Description:
+the service registries the registry zone can serve records from.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    // Consul's catalog, at the URL of its HTTP API, with an ACL token if it needs one
+    Consul { url: String, token: Option<String> },
+
+    // The keys below a prefix of etcd, at the URL of its v3 JSON gateway
+    Etcd { url: String, prefix: String },
+}
+
+impl Backend {
+    // Returns the backend the options choose, if any
+    pub fn from_options(options: &Options) -> Option<Self> {
+        match (&options.consul, &options.etcd) {
+            (Some(url), _) => Some(Backend::Consul {
+                url: url.trim_end_matches('/').to_string(),
+                token: options.consul_token.clone(),
+            }),
+            (None, Some(url)) => Some(Backend::Etcd {
+                url: url.trim_end_matches('/').to_string(),
+                prefix: options.etcd_prefix.clone(),
+            }),
+            (None, None) => None,
+        }
+    }
+}
+
+/*
+Description:
+an address a name of the registry zone resolves to, with the port and target name its SRV record gives if it has a port.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instance {
+    // The address
+    pub address: IpAddr,
+
+    // The port of the service, if it is a service instance
+    pub port: Option<u16>,
+
+    // The name of the instance's host below the zone, the target of its SRV record
+    pub target: String,
+}
+
+/*
+Description:
+the registry zone: records read from Consul's catalog or an etcd prefix, served below a subzone of the domain and replaced whenever the backend reports a change.
+*/
+#[derive(Debug, Default)]
+pub struct Registry {
+    // The instances of each name below the zone, lower-case
+    names: RwLock<HashMap<String, Vec<Instance>>>,
+}
+
+impl Registry {
+    // Returns the instances of a name below the zone, e.g. "web.service", or none if there is no such name
+    pub fn instances(&self, name: &str) -> Vec<Instance> {
+        let names = self.names.read().unwrap();
+        names.get(&name.to_ascii_lowercase()).cloned().unwrap_or_default()
+    }
+
+    // Returns the number of names served
+    pub fn names(&self) -> usize {
+        self.names.read().unwrap().len()
+    }
+
+    // Replaces the names served
+    pub fn replace(&self, names: HashMap<String, Vec<Instance>>) {
+        *self.names.write().unwrap() = names;
+    }
+}
+
+// Adds an instance to a name, lower-casing the name and leaving out duplicates
+fn add(names: &mut HashMap<String, Vec<Instance>>, name: &str, instance: Instance) {
+    let instances = names.entry(name.to_ascii_lowercase()).or_default();
+    if !instances.contains(&instance) {
+        instances.push(instance);
+    }
+}
+
+// A node as Consul's /v1/catalog/nodes and /v1/health/service list it
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulNode {
+    node: String,
+    address: String,
+}
+
+// A service instance as Consul's /v1/health/service lists it
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulServiceEntry {
+    node: ConsulNode,
+    service: ConsulService,
+}
+
+// The service of a service instance, whose address is empty if it is the node's
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulService {
+    service: String,
+    #[serde(default)]
+    address: String,
+    port: u16,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+}
+
+/*
+Description:
+builds the registry zone's names from Consul's catalog, the way Consul's own DNS interface names them: <node>.node for each node, and <service>.service and <tag>.<service>.service for the healthy instances of each service, whose SRV records point at their nodes. Nodes and services whose addresses are hostnames rather than IP addresses are left out.
+
+Parameters:
+nodes: the JSON node list of /v1/catalog/nodes.
+services: the JSON instance lists of /v1/health/service/<service>?passing, one per service.
+
+Returns:
+Ok(HashMap) with the instances of each name, or a RegistryError if a list is malformed.
+*/
+pub fn consul_names(nodes: &[u8], services: &[Vec<u8>]) -> Result<HashMap<String, Vec<Instance>>, RegistryError> {
+    let malformed = |error: serde_json::Error| RegistryError::MalformedResponse(error.to_string());
+    let mut names = HashMap::new();
+
+    for node in serde_json::from_slice::<Vec<ConsulNode>>(nodes).map_err(malformed)? {
+        if let Ok(address) = node.address.parse() {
+            let target = format!("{}.node", node.node);
+            add(&mut names, &target, Instance { address, port: None, target: target.clone() });
+        }
+    }
+
+    for service in services {
+        for entry in serde_json::from_slice::<Vec<ConsulServiceEntry>>(service).map_err(malformed)? {
+            let address = match entry.service.address.is_empty() {
+                true => &entry.node.address,
+                false => &entry.service.address,
+            };
+            let Ok(address) = address.parse() else {
+                continue;
+            };
+            let instance = Instance {
+                address,
+                port: Some(entry.service.port),
+                target: format!("{}.node", entry.node.node),
+            };
+            add(&mut names, &format!("{}.service", entry.service.service), instance.clone());
+            for tag in entry.service.tags.unwrap_or_default() {
+                add(&mut names, &format!("{tag}.{}.service", entry.service.service), instance.clone());
+            }
+        }
+    }
+    Ok(names)
+}
+
+// A range response of etcd's JSON gateway, whose numbers are strings and whose keys and values are base64
+#[derive(Deserialize)]
+struct EtcdRange {
+    header: EtcdHeader,
+    #[serde(default)]
+    kvs: Vec<EtcdKeyValue>,
+}
+
+// The header of an etcd response, with the store's revision
+#[derive(Deserialize)]
+struct EtcdHeader {
+    revision: String,
+}
+
+// A key and its value
+#[derive(Deserialize)]
+struct EtcdKeyValue {
+    key: String,
+    #[serde(default)]
+    value: String,
+}
+
+// An instance written to etcd as JSON, in the format of CoreDNS's etcd plugin
+#[derive(Deserialize)]
+struct EtcdInstance {
+    host: String,
+    #[serde(default)]
+    port: Option<u16>,
+}
+
+/*
+Description:
+builds the registry zone's names from the keys below an etcd prefix. The key <prefix><name> serves <name>, and <prefix><name>/<id> serves one of several instances of <name> that is also served on its own as <id>.<name>. A value is either an IP address or JSON such as {"host": "10.0.0.5", "port": 8080}, with the port giving the instance an SRV record. Keys whose values are neither are left out.
+
+Parameters:
+prefix: the prefix the keys are below.
+range: the JSON response of the range request for the prefix.
+
+Returns:
+Ok((HashMap, i64)) with the instances of each name and the revision they were read at, or a RegistryError if the response is malformed.
+*/
+pub fn etcd_names(prefix: &str, range: &[u8]) -> Result<(HashMap<String, Vec<Instance>>, i64), RegistryError> {
+    let range: EtcdRange = serde_json::from_slice(range).map_err(|error| RegistryError::MalformedResponse(error.to_string()))?;
+    let revision = range
+        .header
+        .revision
+        .parse()
+        .map_err(|_| RegistryError::MalformedResponse(format!("invalid revision {}", range.header.revision)))?;
+
+    let decode = |text: &str| {
+        base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+    };
+    let mut names = HashMap::new();
+    for kv in range.kvs {
+        let (Some(key), Some(value)) = (decode(&kv.key), decode(&kv.value)) else {
+            continue;
+        };
+        let Some(path) = key.strip_prefix(prefix) else {
+            continue;
+        };
+        let (address, port) = match serde_json::from_str::<EtcdInstance>(&value) {
+            Ok(instance) => (instance.host, instance.port),
+            Err(_) => (value.trim().to_string(), None),
+        };
+        let Ok(address) = address.parse() else {
+            continue;
+        };
+
+        match path.trim_matches('/').split_once('/') {
+            Some((name, id)) => {
+                let target = format!("{}.{name}", id.replace('/', "."));
+                add(&mut names, name, Instance { address, port, target: target.clone() });
+                add(&mut names, &target, Instance { address, port, target: target.clone() });
+            }
+            None => {
+                let name = path.trim_matches('/');
+                add(&mut names, name, Instance { address, port, target: name.to_string() });
+            }
+        }
+    }
+    Ok((names, revision))
+}
+
+/*
+Description:
+keeps the registry zone in step with its backend: loads every record, then waits for the backend to report a change and loads them again, so changes are served within seconds. Consul is followed with blocking queries on its catalog and health checks, and etcd with a watch on the prefix. If the backend can't be reached, it retries every few seconds, serving the last records it loaded meanwhile.
+
+Parameters:
+registry: the zone to keep up to date.
+backend: the backend to read the records from.
+
+Returns:
+None; runs until the process exits.
+*/
+pub async fn watch(registry: Arc<Registry>, backend: Backend) {
+    let client = reqwest::Client::builder()
+        .connect_timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new());
+
+    loop {
+        let result = match &backend {
+            Backend::Consul { url, token } => follow_consul(&client, &registry, url, token.as_deref()).await,
+            Backend::Etcd { url, prefix } => follow_etcd(&client, &registry, url, prefix).await,
+        };
+        if let Err(error) = result {
+            warn!("Lost the registry backend, retrying in {}s: {error}", RETRY_DELAY.as_secs());
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+// Loads Consul's catalog, then loads it again whenever the services or any health check change
+async fn follow_consul(client: &reqwest::Client, registry: &Registry, url: &str, token: Option<&str>) -> Result<(), RegistryError> {
+    loop {
+        // Take the indexes first, so a change made while loading is waited for rather than missed
+        let (services, services_index) = consul_get(client, url, token, "/v1/catalog/services", None).await?;
+        let (_, health_index) = consul_get(client, url, token, "/v1/health/state/any", None).await?;
+
+        let (nodes, _) = consul_get(client, url, token, "/v1/catalog/nodes", None).await?;
+        let services: BTreeMap<String, serde_json::Value> =
+            serde_json::from_slice(&services).map_err(|error| RegistryError::MalformedResponse(error.to_string()))?;
+        let mut healthy = Vec::with_capacity(services.len());
+        for service in services.keys() {
+            let path = format!("/v1/health/service/{service}?passing=true");
+            healthy.push(consul_get(client, url, token, &path, None).await?.0);
+        }
+
+        let names = consul_names(&nodes, &healthy)?;
+        info!("Serving {} names from Consul", names.len());
+        registry.replace(names);
+
+        tokio::select! {
+            result = consul_wait(client, url, token, "/v1/catalog/services", services_index) => result?,
+            result = consul_wait(client, url, token, "/v1/health/state/any", health_index) => result?,
+        }
+    }
+}
+
+// Sends a GET request to Consul, as a blocking query if an index is given, and returns the body and the X-Consul-Index of the answer
+async fn consul_get(client: &reqwest::Client, url: &str, token: Option<&str>, path: &str, index: Option<u64>) -> Result<(Vec<u8>, u64), RegistryError> {
+    let mut request = match index {
+        Some(index) => {
+            let separator = if path.contains('?') { '&' } else { '?' };
+            client
+                .get(format!("{url}{path}{separator}index={index}&wait={}s", BLOCKING_WAIT.as_secs()))
+                .timeout(BLOCKING_WAIT + REQUEST_TIMEOUT)
+        }
+        None => client.get(format!("{url}{path}")).timeout(REQUEST_TIMEOUT),
+    };
+    if let Some(token) = token {
+        request = request.header("X-Consul-Token", token);
+    }
+
+    let response = request.send().await?.error_for_status()?;
+    let index = response
+        .headers()
+        .get("X-Consul-Index")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    Ok((response.bytes().await?.to_vec(), index))
+}
+
+// Waits with blocking queries until Consul's index for a path moves on from the one given
+async fn consul_wait(client: &reqwest::Client, url: &str, token: Option<&str>, path: &str, index: u64) -> Result<(), RegistryError> {
+    loop {
+        let (_, current) = consul_get(client, url, token, path, Some(index)).await?;
+        // A blocking query that times out answers with the same index; one that goes backwards means Consul was reset
+        if current != index {
+            return Ok(());
+        }
+    }
+}
+
+// Loads the keys below the etcd prefix, then watches the prefix and loads them again after every change
+async fn follow_etcd(client: &reqwest::Client, registry: &Registry, url: &str, prefix: &str) -> Result<(), RegistryError> {
+    let encode = |bytes: &[u8]| base64::engine::general_purpose::STANDARD.encode(bytes);
+    let key = encode(prefix.as_bytes());
+    let range_end = encode(&prefix_end(prefix.as_bytes()));
+
+    loop {
+        let range = client
+            .post(format!("{url}/v3/kv/range"))
+            .timeout(REQUEST_TIMEOUT)
+            .json(&serde_json::json!({ "key": key, "range_end": range_end }))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let (names, revision) = etcd_names(prefix, &range)?;
+        info!("Serving {} names from etcd", names.len());
+        registry.replace(names);
+
+        // Watch from the revision after the one loaded, so nothing written since is missed
+        let watch = serde_json::json!({
+            "create_request": { "key": key, "range_end": range_end, "start_revision": (revision + 1).to_string() }
+        });
+        let mut response = client.post(format!("{url}/v3/watch")).json(&watch).send().await?.error_for_status()?;
+
+        // The watch answers with a line of JSON per batch of events, the first of which only confirms it was created
+        let mut buffer = Vec::new();
+        'watching: loop {
+            let Some(chunk) = response.chunk().await? else {
+                return Err(RegistryError::MalformedResponse("the watch ended".to_string()));
+            };
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let message: serde_json::Value = serde_json::from_slice(&line).unwrap_or_default();
+                let events = message["result"]["events"].as_array().map_or(0, Vec::len);
+                if events > 0 {
+                    debug!("{events} etcd events, reloading the registry zone");
+                    break 'watching;
+                }
+            }
+        }
+    }
+}
+
+// Returns the end of the range of keys starting with a prefix: the prefix with its last byte that isn't 0xff incremented
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    // Every byte was 0xff, or there was no prefix at all: the range runs to the end of the keys
+    vec![0]
+}
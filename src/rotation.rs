@@ -0,0 +1,167 @@
+use crate::{
+    config::{RotationConfig, RotationPolicy},
+    random::Random,
+};
+use lru::LruCache;
+use rand::{seq::SliceRandom, Rng};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    num::NonZeroUsize,
+    sync::Mutex,
+};
+use trust_dns_server::client::rr::{LowerName, Name, RData, Record, RecordType};
+
+// The largest number of names whose round-robin turn is remembered; the least recently answered start over.
+const TURNS_CAPACITY: usize = 10000;
+
+// The policy of a single name, with its weights read
+#[derive(Debug)]
+struct NamePolicy {
+    policy: RotationPolicy,
+    weights: HashMap<IpAddr, u32>,
+    count: Option<usize>,
+}
+
+/*
+Description:
+answer rotation: orders the A and AAAA records of names with several addresses in each answer by the policy of the [rotation] section, so that clients, which mostly use the first address, are spread over the backends behind a name. Round-robin rotates each name's addresses by one place per answer, shuffle orders them at random, and weighted orders them at random with each address coming first in proportion to its weight. A name's own policy can also cut its answers down to a number of addresses.
+*/
+#[derive(Debug)]
+pub struct Rotation {
+    // The policy of every name without its own
+    policy: RotationPolicy,
+
+    // The policies of single names
+    names: HashMap<LowerName, NamePolicy>,
+
+    // The next round-robin turn of each name and type
+    turns: Mutex<LruCache<(LowerName, RecordType), usize>>,
+}
+
+impl Rotation {
+    /*
+    Description:
+    reads the [rotation] section, checking that its names are valid and its weights are given to addresses.
+
+    Parameters:
+    config: the [rotation] section.
+
+    Returns:
+    Ok(Rotation), or an error message saying which name is wrong and why.
+    */
+    pub fn new(config: &RotationConfig) -> Result<Self, String> {
+        let mut names = HashMap::new();
+        for (name, policy) in &config.names {
+            let context = |error: String| format!("[rotation.names] {name}: {error}");
+            let owner = Name::parse(name, Some(&Name::root())).map_err(|error| context(format!("invalid name: {error}")))?;
+            let mut weights = HashMap::new();
+            for (address, weight) in &policy.weights {
+                let address = address.parse::<IpAddr>().map_err(|_| context(format!("{address} is not an address")))?;
+                weights.insert(address, *weight);
+            }
+            if !weights.is_empty() && policy.policy != RotationPolicy::Weighted {
+                return Err(context("weights are only used by the weighted policy".to_string()));
+            }
+            if policy.count == Some(0) {
+                return Err(context("the count has to be at least 1".to_string()));
+            }
+            names.insert(LowerName::new(&owner), NamePolicy { policy: policy.policy, weights, count: policy.count });
+        }
+        Ok(Rotation {
+            policy: config.policy,
+            names,
+            turns: Mutex::new(LruCache::new(NonZeroUsize::new(TURNS_CAPACITY).unwrap())),
+        })
+    }
+
+    // Returns the policy a name's addresses are ordered by
+    pub fn policy(&self, name: &LowerName) -> RotationPolicy {
+        self.names.get(name).map_or(self.policy, |name| name.policy)
+    }
+
+    /*
+    Description:
+    orders the addresses of an answer. The A or AAAA records of each owner name are ordered among themselves, in the place of the first of them, and the other records, such as the CNAME records leading to them, are left as they are.
+
+    Parameters:
+    records: the records of the answer.
+    random: where shuffles draw from.
+
+    Returns:
+    The records, with the addresses of each name ordered by its policy and cut down to its count.
+    */
+    pub fn apply(&self, records: Vec<Record>, random: &Random) -> Vec<Record> {
+        // Gather the addresses of each name and type, in the order they are first seen
+        let mut groups: Vec<((LowerName, RecordType), Vec<Record>)> = Vec::new();
+        for record in &records {
+            if matches!(record.record_type(), RecordType::A | RecordType::AAAA) {
+                let key = (LowerName::new(record.name()), record.record_type());
+                match groups.iter_mut().find(|(group, _)| *group == key) {
+                    Some((_, group)) => group.push(record.clone()),
+                    None => groups.push((key, vec![record.clone()])),
+                }
+            }
+        }
+        if groups.iter().all(|(_, group)| group.len() < 2) {
+            return records;
+        }
+
+        let mut answer = Vec::with_capacity(records.len());
+        for record in records {
+            if !matches!(record.record_type(), RecordType::A | RecordType::AAAA) {
+                answer.push(record);
+                continue;
+            }
+            let key = (LowerName::new(record.name()), record.record_type());
+            if let Some(index) = groups.iter().position(|(group, _)| *group == key) {
+                let (key, group) = groups.swap_remove(index);
+                answer.extend(self.order(&key, group, random));
+            }
+        }
+        answer
+    }
+
+    // Orders the addresses of one name and type by the name's policy
+    fn order(&self, key: &(LowerName, RecordType), mut group: Vec<Record>, random: &Random) -> Vec<Record> {
+        let name = self.names.get(&key.0);
+        match name.map_or(self.policy, |name| name.policy) {
+            RotationPolicy::Fixed => {}
+            RotationPolicy::RoundRobin => {
+                let turn = {
+                    let mut turns = self.turns.lock().unwrap();
+                    let turn = turns.get_or_insert_mut(key.clone(), || 0);
+                    let current = *turn;
+                    *turn = turn.wrapping_add(1);
+                    current
+                };
+                let length = group.len();
+                group.rotate_left(turn % length);
+            }
+            RotationPolicy::Shuffle => random.with_rng(|rng| group.shuffle(rng)),
+            RotationPolicy::Weighted => {
+                // Each address draws a key of u^(1/weight) and the highest keys come first (Efraimidis and Spirakis), which puts an address first in proportion to its weight
+                let weight = |record: &Record| match record.data() {
+                    Some(RData::A(address)) => name.and_then(|name| name.weights.get(&IpAddr::V4(*address)).copied()).unwrap_or(1),
+                    Some(RData::AAAA(address)) => name.and_then(|name| name.weights.get(&IpAddr::V6(*address)).copied()).unwrap_or(1),
+                    _ => 1,
+                };
+                let mut keyed: Vec<(f64, Record)> = random.with_rng(|rng| {
+                    group
+                        .into_iter()
+                        .map(|record| match weight(&record) {
+                            0 => (-1.0, record),
+                            weight => (rng.gen::<f64>().powf(1.0 / f64::from(weight)), record),
+                        })
+                        .collect()
+                });
+                keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+                group = keyed.into_iter().map(|(_, record)| record).collect();
+            }
+        }
+        if let Some(count) = name.and_then(|name| name.count) {
+            group.truncate(count);
+        }
+        group
+    }
+}
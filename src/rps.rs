@@ -0,0 +1,174 @@
+use crate::random::Random;
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    str::FromStr,
+    sync::Mutex,
+};
+
+// The largest number of clients whose tallies are remembered at once.
+// When the map is full, the client that has played the fewest games is forgotten to make room.
+const MAX_CLIENTS: usize = 10_000;
+
+/*
+Description:
+a move in a game of rock-paper-scissors.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Move {
+    Rock,
+    Paper,
+    Scissors,
+}
+
+impl Move {
+    /*
+    Description:
+    picks a random move for the server.
+
+    Parameters:
+    random: the source to draw from.
+
+    Returns:
+    A uniformly random Move.
+    */
+    pub fn random(random: &Random) -> Self {
+        match random.with_rng(|rng| rng.gen_range(0..3)) {
+            0 => Move::Rock,
+            1 => Move::Paper,
+            _ => Move::Scissors,
+        }
+    }
+
+    // Returns true if this move beats the other move
+    fn beats(self, other: Move) -> bool {
+        matches!(
+            (self, other),
+            (Move::Rock, Move::Scissors) | (Move::Paper, Move::Rock) | (Move::Scissors, Move::Paper)
+        )
+    }
+}
+
+impl FromStr for Move {
+    type Err = String;
+
+    // Parses a move from a query label, e.g. "rock"
+    fn from_str(label: &str) -> Result<Self, Self::Err> {
+        match label.to_ascii_lowercase().as_str() {
+            "rock" => Ok(Move::Rock),
+            "paper" => Ok(Move::Paper),
+            "scissors" => Ok(Move::Scissors),
+            other => Err(format!("unknown move {other}, expected rock, paper or scissors")),
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Move::Rock => "rock",
+            Move::Paper => "paper",
+            Move::Scissors => "scissors",
+        };
+        f.write_str(name)
+    }
+}
+
+/*
+Description:
+the outcome of a game from the client's point of view.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+/*
+Description:
+decides the outcome of a game.
+
+Parameters:
+client: the move played by the client.
+server: the move played by the server.
+
+Returns:
+The Outcome of the game from the client's point of view.
+*/
+pub fn play(client: Move, server: Move) -> Outcome {
+    if client.beats(server) {
+        Outcome::Win
+    } else if server.beats(client) {
+        Outcome::Loss
+    } else {
+        Outcome::Draw
+    }
+}
+
+/*
+Description:
+the running win/loss/draw tally of a single client.
+*/
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tally {
+    pub wins: u64,
+    pub losses: u64,
+    pub draws: u64,
+}
+
+impl Tally {
+    // Returns the total number of games played
+    fn games(&self) -> u64 {
+        self.wins + self.losses + self.draws
+    }
+}
+
+/*
+Description:
+the per-client rock-paper-scissors statistics, keyed by the client's IP address. The map is bounded so that a flood of spoofed source addresses cannot grow it without limit.
+*/
+#[derive(Debug, Default)]
+pub struct RpsStats {
+    tallies: Mutex<HashMap<IpAddr, Tally>>,
+}
+
+impl RpsStats {
+    /*
+    Description:
+    records the outcome of a game for a client.
+
+    Parameters:
+    client: the IP address of the client that played.
+    outcome: the outcome of the game from the client's point of view.
+
+    Returns:
+    The client's updated Tally.
+    */
+    pub fn record(&self, client: IpAddr, outcome: Outcome) -> Tally {
+        let mut tallies = self.tallies.lock().unwrap();
+
+        // Make room for a new client by forgetting the least active one
+        if tallies.len() >= MAX_CLIENTS && !tallies.contains_key(&client) {
+            if let Some(least_active) = tallies
+                .iter()
+                .min_by_key(|(_, tally)| tally.games())
+                .map(|(addr, _)| *addr)
+            {
+                tallies.remove(&least_active);
+            }
+        }
+
+        // Update the client's tally
+        let tally = tallies.entry(client).or_default();
+        match outcome {
+            Outcome::Win => tally.wins += 1,
+            Outcome::Loss => tally.losses += 1,
+            Outcome::Draw => tally.draws += 1,
+        }
+
+        *tally
+    }
+}
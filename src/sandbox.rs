@@ -0,0 +1,208 @@
+use crate::options::Options;
+use std::{
+    ffi::CString,
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+use tracing::*;
+
+/*
+Description:
+defines the errors that can occur while sandboxing the server.
+*/
+#[derive(thiserror::Error, Debug)]
+pub enum SandboxError {
+    #[error("Failed to chroot into {}: {1:}", .0.display())]
+    Chroot(PathBuf, io::Error),
+    #[error("Failed to apply the Landlock policy: {0:}")]
+    Landlock(io::Error),
+}
+
+/*
+Description:
+confines the server to a directory with chroot. Meant to be called once every socket is bound and every file needed at startup is open, before privileges are dropped, since chroot needs root (or CAP_SYS_CHROOT). Files the server opens afterwards, such as the counter file or the fx cache, are looked up inside the new root.
+
+Parameters:
+dir: the new root directory, ideally empty and owned by root.
+
+Returns:
+Ok(()) once the process runs inside the directory, or a SandboxError.
+*/
+pub fn chroot(dir: &Path) -> Result<(), SandboxError> {
+    let error = |error| SandboxError::Chroot(dir.to_path_buf(), error);
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|_| error(io::Error::from(io::ErrorKind::InvalidInput)))?;
+
+    if unsafe { libc::chroot(path.as_ptr()) } != 0 {
+        return Err(error(io::Error::last_os_error()));
+    }
+
+    // Leave the old working directory, which would still reach outside the new root
+    std::env::set_current_dir("/").map_err(error)
+}
+
+// The Landlock system calls and constants, from <linux/landlock.h>
+#[cfg(target_os = "linux")]
+mod landlock {
+    // The file system access rights of the first Landlock ABI, from LANDLOCK_ACCESS_FS_EXECUTE (1 << 0) to LANDLOCK_ACCESS_FS_MAKE_SYM (1 << 12)
+    pub const ACCESS_FS_ALL: u64 = (1 << 13) - 1;
+
+    // The rights that apply to files rather than directories: executing, writing and reading
+    pub const ACCESS_FS_FILE: u64 = (1 << 0) | (1 << 1) | (1 << 2);
+
+    // Reading files and listing directories
+    pub const ACCESS_FS_READ: u64 = (1 << 2) | (1 << 3);
+
+    // Everything but executing files
+    pub const ACCESS_FS_READ_WRITE: u64 = ACCESS_FS_ALL & !1;
+
+    // LANDLOCK_RULE_PATH_BENEATH
+    pub const RULE_PATH_BENEATH: libc::c_int = 1;
+
+    // struct landlock_ruleset_attr
+    #[repr(C)]
+    pub struct RulesetAttr {
+        pub handled_access_fs: u64,
+    }
+
+    // struct landlock_path_beneath_attr, which the kernel declares as packed
+    #[repr(C, packed)]
+    pub struct PathBeneathAttr {
+        pub allowed_access: u64,
+        pub parent_fd: i32,
+    }
+}
+
+/*
+Description:
+restricts the server's file system access with Landlock (Linux 5.13 and later): everything is denied except reading the configuration and blocklist files and the system files needed for name resolution and time zones, and writing the directories of the files the server keeps (counter file, fx cache, query log, dnstap file, stats file, audit log). Landlock only applies to the calling thread and the threads it starts afterwards, so this has to run before the tokio runtime is built. On kernels without Landlock a warning is logged and the server runs unrestricted.
+
+Parameters:
+options: the command-line options, which name the files the server uses.
+
+Returns:
+Ok(()) if the policy was applied or Landlock is unavailable, or a SandboxError if applying it failed.
+*/
+#[cfg(target_os = "linux")]
+pub fn landlock(options: &Options) -> Result<(), SandboxError> {
+    use landlock::*;
+
+    // Create a ruleset that handles every file system access right
+    let attr = RulesetAttr { handled_access_fs: ACCESS_FS_ALL };
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0,
+        )
+    };
+    if ruleset < 0 {
+        let error = io::Error::last_os_error();
+        if matches!(error.raw_os_error(), Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP)) {
+            warn!("Landlock is not supported by this kernel, running without a file system sandbox");
+            return Ok(());
+        }
+        return Err(SandboxError::Landlock(error));
+    }
+    let ruleset = ruleset as libc::c_int;
+
+    // The fx cache directory is normally created on first use, which would be too late to allow it
+    if let Err(error) = std::fs::create_dir_all(&options.fx_cache_dir) {
+        warn!("Failed to create fx cache directory {}: {error}", options.fx_cache_dir.display());
+    }
+
+    // Allow the paths the server needs, skipping any that don't exist
+    for (path, access) in allowed_paths(options) {
+        let Ok(name) = CString::new(path.as_os_str().as_bytes()) else { continue };
+        let fd = unsafe { libc::open(name.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if fd < 0 {
+            debug!("Not adding {} to the Landlock policy: {}", path.display(), io::Error::last_os_error());
+            continue;
+        }
+
+        // Files can only be granted file rights, so drop the directory ones for them
+        let access = if path.is_dir() { access } else { access & ACCESS_FS_FILE };
+        let rule = PathBeneathAttr { allowed_access: access, parent_fd: fd };
+        let added = unsafe {
+            libc::syscall(libc::SYS_landlock_add_rule, ruleset, RULE_PATH_BENEATH, &rule as *const PathBeneathAttr, 0)
+        };
+        let error = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        if added != 0 {
+            unsafe { libc::close(ruleset) };
+            return Err(SandboxError::Landlock(error));
+        }
+    }
+
+    // Enforce the ruleset; no_new_privs is required for unprivileged processes
+    let restricted = unsafe {
+        libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0
+            && libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) == 0
+    };
+    let error = io::Error::last_os_error();
+    unsafe { libc::close(ruleset) };
+    if !restricted {
+        return Err(SandboxError::Landlock(error));
+    }
+
+    Ok(())
+}
+
+// Lists the paths the server needs after startup with the access it needs to them
+#[cfg(target_os = "linux")]
+fn allowed_paths(options: &Options) -> Vec<(PathBuf, u64)> {
+    use landlock::{ACCESS_FS_READ, ACCESS_FS_READ_WRITE};
+
+    // Name resolution (resolv.conf, hosts, nsswitch.conf) and time zones
+    let mut paths: Vec<(PathBuf, u64)> = ["/etc", "/usr/share/zoneinfo"]
+        .into_iter()
+        .map(|path| (PathBuf::from(path), ACCESS_FS_READ))
+        .collect();
+
+    // LAN discovery rereads the kernel's ARP table
+    if options.lan_discovery {
+        paths.push((PathBuf::from("/proc/net/arp"), ACCESS_FS_READ));
+    }
+
+    // The configuration file and the blocklist files it lists are reread at runtime
+    paths.extend(options.config_path.iter().map(|path| (path.clone(), ACCESS_FS_READ)));
+    paths.extend(options.wireguard_config.iter().map(|path| (path.clone(), ACCESS_FS_READ)));
+    if let Some(blocklist) = &options.config.blocklist {
+        paths.extend(
+            blocklist
+                .sources
+                .iter()
+                .filter(|source| !source.starts_with("http://") && !source.starts_with("https://"))
+                .map(|source| (PathBuf::from(source), ACCESS_FS_READ)),
+        );
+    }
+
+    // Files the server writes are replaced or created next to themselves, so their directories must be writable
+    let parent = |path: &Path| match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    paths.push((options.fx_cache_dir.clone(), ACCESS_FS_READ_WRITE));
+    paths.extend(options.counter_file.iter().map(|path| (parent(path), ACCESS_FS_READ_WRITE)));
+    paths.extend(options.dnstap_file.iter().map(|path| (parent(path), ACCESS_FS_READ_WRITE)));
+    paths.extend(options.stats_file.iter().map(|path| (parent(path), ACCESS_FS_READ_WRITE)));
+    paths.extend(options.audit_log.iter().map(|path| (parent(path), ACCESS_FS_READ_WRITE)));
+    paths.extend(options.acme_accounts.iter().map(|path| (parent(path), ACCESS_FS_READ_WRITE)));
+    if options.ddns_api.is_some() {
+        paths.push((parent(&options.ddns_hosts), ACCESS_FS_READ_WRITE));
+    }
+    #[cfg(feature = "sqlite")]
+    paths.extend(options.query_log.iter().map(|path| (parent(path), ACCESS_FS_READ_WRITE)));
+
+    // Hosts files are watched through their directories, since editors replace them rather than rewriting them
+    paths.extend(options.hosts_file.iter().map(|path| (parent(path), ACCESS_FS_READ)));
+
+    // A recompiled blocklist replaces the file rather than rewriting it, so its directory has to be readable
+    let compiled = options.config.blocklist.as_ref().and_then(|blocklist| blocklist.compiled.as_deref());
+    paths.extend(compiled.map(|path| (parent(path), ACCESS_FS_READ)));
+
+    paths
+}
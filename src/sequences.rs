@@ -0,0 +1,44 @@
+use num_bigint::BigUint;
+
+// The largest n accepted by the fib zone. F(4700) has 982 digits.
+pub const FIB_MAX_N: u32 = 4700;
+
+// The largest n accepted by the fact zone. 440! has 974 digits.
+pub const FACT_MAX_N: u32 = 440;
+
+/*
+Description:
+computes the n-th Fibonacci number, with F(0) = 0 and F(1) = 1. The numbers are computed iteratively with big integers; callers are expected to cap n (see FIB_MAX_N) since the work grows with the square of n.
+
+Parameters:
+n: the index of the Fibonacci number.
+
+Returns:
+The n-th Fibonacci number as a BigUint.
+*/
+pub fn fibonacci(n: u32) -> BigUint {
+    let mut current = BigUint::from(0u32);
+    let mut next = BigUint::from(1u32);
+
+    // Step through the sequence n times
+    for _ in 0..n {
+        let sum = &current + &next;
+        current = std::mem::replace(&mut next, sum);
+    }
+
+    current
+}
+
+/*
+Description:
+computes n factorial with big integers. Callers are expected to cap n (see FACT_MAX_N).
+
+Parameters:
+n: the number whose factorial to compute.
+
+Returns:
+n! as a BigUint, with 0! = 1.
+*/
+pub fn factorial(n: u32) -> BigUint {
+    (1..=n).fold(BigUint::from(1u32), |product, factor| product * factor)
+}
@@ -0,0 +1,180 @@
+use crate::{affinity, handlers::Handler, tcp, udp_batch};
+use std::{
+    future::Future,
+    io, mem,
+    net::{SocketAddr, TcpListener, UdpSocket},
+    os::unix::io::FromRawFd,
+    thread,
+};
+use tokio::{sync::oneshot, task::JoinSet};
+use tracing::*;
+use trust_dns_server::ServerFuture;
+
+// The length of the queue of TCP connections waiting to be accepted.
+const LISTEN_BACKLOG: libc::c_int = 1024;
+
+/*
+Description:
+binds a UDP socket with SO_REUSEPORT set, so every shard can bind its own socket to the same port and the kernel spreads the incoming queries over them.
+
+Parameters:
+address: the address to bind to.
+
+Returns:
+Ok(UdpSocket) in non-blocking mode, or an io::Error if the socket could not be created or bound.
+*/
+pub fn bind_udp(address: SocketAddr) -> io::Result<UdpSocket> {
+    let fd = reuseport_socket(address, libc::SOCK_DGRAM)?;
+
+    // SAFETY: the descriptor was just created and is owned by nothing else.
+    Ok(unsafe { UdpSocket::from_raw_fd(fd) })
+}
+
+/*
+Description:
+binds and listens on a TCP socket with SO_REUSEPORT set, so every shard can accept its own share of the connections to the same port.
+
+Parameters:
+address: the address to listen on.
+
+Returns:
+Ok(TcpListener) in non-blocking mode, or an io::Error if the socket could not be created, bound or put into listening mode.
+*/
+pub fn bind_tcp(address: SocketAddr) -> io::Result<TcpListener> {
+    let fd = reuseport_socket(address, libc::SOCK_STREAM)?;
+
+    // SAFETY: the descriptor was just created and is owned by nothing else.
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    if unsafe { libc::listen(fd, LISTEN_BACKLOG) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(listener)
+}
+
+// Creates a non-blocking socket with SO_REUSEADDR and SO_REUSEPORT set and binds it
+fn reuseport_socket(address: SocketAddr, kind: libc::c_int) -> io::Result<libc::c_int> {
+    let domain = if address.is_ipv4() { libc::AF_INET } else { libc::AF_INET6 };
+    let fd = unsafe { libc::socket(domain, kind | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let result = (|| {
+        let enable: libc::c_int = 1;
+        for option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT] {
+            let set = unsafe {
+                libc::setsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    option,
+                    (&enable as *const libc::c_int).cast(),
+                    mem::size_of::<libc::c_int>() as libc::socklen_t,
+                )
+            };
+            if set < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let (raw, length) = udp_batch::to_raw_addr(&address);
+        if unsafe { libc::bind(fd, (&raw as *const libc::sockaddr_storage).cast(), length) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => Ok(fd),
+        Err(error) => {
+            unsafe { libc::close(fd) };
+            Err(error)
+        }
+    }
+}
+
+/*
+Description:
+starts a shard: a thread running its own single-threaded tokio runtime that serves its own sockets with its own handler. Nothing on the query path is shared with other cores except the handler's shared state, and the request counter is counted per shard.
+
+Parameters:
+shard: the shard's number, used to name its thread.
+handler: the shard's handler, from Handler::for_shard.
+udp: the shard's UDP sockets, bound with bind_udp.
+tcp: the shard's TCP listeners, bound with bind_tcp.
+tcp_limits: the budget of each TCP listener, including how long an idle connection is kept open.
+udp_batch: the number of datagrams read and written per system call, 0 to serve UDP with ServerFuture.
+cpu: the CPU the shard's thread is pinned to, or None to let the scheduler place it.
+
+Returns:
+Ok(future) that completes when the shard stops, with the error that stopped it; or an io::Error if the thread could not be started.
+*/
+pub fn spawn(
+    shard: usize,
+    handler: Handler,
+    udp: Vec<UdpSocket>,
+    tcp: Vec<TcpListener>,
+    tcp_limits: tcp::Limits,
+    udp_batch: usize,
+    cpu: Option<usize>,
+) -> io::Result<impl Future<Output = io::Result<()>>> {
+    let (done, stopped) = oneshot::channel();
+
+    thread::Builder::new().name(format!("shard-{shard}")).spawn(move || {
+        if let Some(cpu) = cpu {
+            if let Err(error) = affinity::pin_current_thread(cpu) {
+                warn!("Failed to pin shard {shard} to CPU {cpu}: {error}");
+            }
+        }
+        let result = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .and_then(|runtime| runtime.block_on(serve(handler, udp, tcp, tcp_limits, udp_batch)));
+        let _ = done.send(result);
+    })?;
+
+    Ok(async move {
+        stopped
+            .await
+            .unwrap_or_else(|_| Err(io::Error::other("shard thread panicked")))
+    })
+}
+
+// Serves a shard's sockets on the shard's runtime until one of them fails
+async fn serve(
+    handler: Handler,
+    udp: Vec<UdpSocket>,
+    tcp: Vec<TcpListener>,
+    tcp_limits: tcp::Limits,
+    batch_size: usize,
+) -> io::Result<()> {
+    let mut batched = JoinSet::new();
+    let mut server = ServerFuture::new(handler.clone());
+    let uses_server = (!tcp_limits.enabled() && !tcp.is_empty()) || (batch_size == 0 && !udp.is_empty());
+
+    // Register the sockets, which have to be converted on the runtime they are served on
+    for socket in udp {
+        let socket = tokio::net::UdpSocket::from_std(socket)?;
+        if batch_size > 0 {
+            batched.spawn(udp_batch::serve(socket, handler.clone(), batch_size));
+        } else {
+            server.register_socket(socket);
+        }
+    }
+    for listener in tcp {
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        if tcp_limits.enabled() {
+            batched.spawn(tcp::serve(listener, handler.clone(), tcp_limits));
+        } else {
+            server.register_listener(listener, tcp_limits.idle_timeout);
+        }
+    }
+
+    // Serve until the sockets are closed or fail
+    if uses_server {
+        server.block_until_done().await.map_err(io::Error::other)?;
+    }
+    while let Some(result) = batched.join_next().await {
+        result.map_err(io::Error::other)??;
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use crate::{random::Random, tasks};
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{net::UdpSocket, task::JoinHandle};
+
+// The largest datagram relayed.
+const MAX_DATAGRAM: usize = 65535;
+
+/*
+Description:
+the faults a FaultyRelay injects. Each one is applied to every datagram on its own, in both directions, so a query and its answer can each be lost, duplicated or delayed.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct Faults {
+    // The probability that a datagram is dropped, from 0 to 1
+    pub loss: f64,
+
+    // The probability that a datagram is sent twice, from 0 to 1
+    pub duplicate: f64,
+
+    // The probability that a datagram is held back until the datagrams sent after it have gone, from 0 to 1
+    pub reorder: f64,
+
+    // The delay added to every datagram
+    pub latency: Duration,
+
+    // The most random delay added on top of the latency
+    pub jitter: Duration,
+
+    // The seed of the draws deciding which datagrams are hit, so a run can be repeated; None to seed from the OS
+    pub seed: Option<u64>,
+}
+
+/*
+Description:
+a UDP relay that forwards datagrams between clients and a server, dropping, duplicating, delaying and reordering them according to its Faults. Clients send to the relay's address instead of the server's; each client gets its own socket towards the server, so answers go back to the client that asked. It is meant for testing how clients and servers cope with a bad network, and stops when dropped.
+*/
+pub struct FaultyRelay {
+    // The address clients send to
+    local_addr: SocketAddr,
+
+    // The task receiving from clients
+    task: JoinHandle<()>,
+}
+
+impl FaultyRelay {
+    /*
+    Description:
+    binds the relay on an ephemeral port of the loopback address and starts relaying to the server. Must be called from within the tokio runtime.
+
+    Parameters:
+    server: the address of the server to relay to.
+    faults: the faults to inject.
+
+    Returns:
+    Ok(FaultyRelay), or an io::Error if the relay's socket could not be bound.
+    */
+    pub async fn start(server: SocketAddr, faults: Faults) -> io::Result<Self> {
+        let local = match server {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::LOCALHOST, 0)),
+        };
+        let front = Arc::new(UdpSocket::bind(local).await?);
+        let local_addr = front.local_addr()?;
+
+        let link = Arc::new(Link {
+            random: faults.seed.map_or_else(Random::default, Random::seeded),
+            faults,
+        });
+        let task = tasks::spawn("sim-relay", relay(front, server, link));
+        Ok(FaultyRelay { local_addr, task })
+    }
+
+    // Returns the address clients send to instead of the server's
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for FaultyRelay {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// The faults and the draws deciding which datagrams they hit, shared by both directions
+struct Link {
+    // The faults to inject
+    faults: Faults,
+
+    // Where the draws come from
+    random: Random,
+}
+
+impl Link {
+    // Sends a datagram through the faulty link: drops it, or sends it once or twice after its delay
+    fn send(self: &Arc<Self>, socket: Arc<UdpSocket>, datagram: Vec<u8>, to: SocketAddr) {
+        let faults = &self.faults;
+        let (lost, copies, delay) = self.random.with_rng(|rng| {
+            let lost = rng.gen_bool(faults.loss.clamp(0.0, 1.0));
+            let copies = if rng.gen_bool(faults.duplicate.clamp(0.0, 1.0)) { 2 } else { 1 };
+            let jitter = faults.jitter.mul_f64(rng.gen::<f64>());
+
+            // A held-back datagram waits out the longest delay any other datagram can get, and a little more
+            let held_back = match rng.gen_bool(faults.reorder.clamp(0.0, 1.0)) {
+                true => faults.latency + faults.jitter + Duration::from_millis(10),
+                false => Duration::ZERO,
+            };
+            (lost, copies, faults.latency + jitter + held_back)
+        });
+        if lost {
+            return;
+        }
+
+        tasks::spawn("sim-send", async move {
+            tokio::time::sleep(delay).await;
+            for _ in 0..copies {
+                let _ = socket.send_to(&datagram, to).await;
+            }
+        });
+    }
+}
+
+// The tasks relaying answers back to clients, stopped with the relay
+struct Relays(Vec<JoinHandle<()>>);
+
+impl Drop for Relays {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+// Receives datagrams from clients and sends them on to the server, each client through its own socket
+async fn relay(front: Arc<UdpSocket>, server: SocketAddr, link: Arc<Link>) {
+    let mut backs: HashMap<SocketAddr, Arc<UdpSocket>> = HashMap::new();
+    let mut relays = Relays(Vec::new());
+    let mut buffer = vec![0; MAX_DATAGRAM];
+
+    loop {
+        let (length, client) = match front.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(_) => continue,
+        };
+
+        // Open a socket towards the server the first time a client sends, and relay its answers back
+        let back = match backs.get(&client) {
+            Some(back) => back.clone(),
+            None => {
+                let local = match server {
+                    SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+                    SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+                };
+                let back = match UdpSocket::bind(local).await {
+                    Ok(back) => Arc::new(back),
+                    Err(_) => continue,
+                };
+                relays.0.push(tasks::spawn("sim-answers", answers(back.clone(), front.clone(), client, link.clone())));
+                backs.insert(client, back.clone());
+                back
+            }
+        };
+
+        link.send(back, buffer[..length].to_vec(), server);
+    }
+}
+
+// Relays what the server sends to a client's socket back to the client
+async fn answers(back: Arc<UdpSocket>, front: Arc<UdpSocket>, client: SocketAddr, link: Arc<Link>) {
+    let mut buffer = vec![0; MAX_DATAGRAM];
+    while let Ok((length, _)) = back.recv_from(&mut buffer).await {
+        link.send(front.clone(), buffer[..length].to_vec(), client);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    blocklist::BlocklistStats,
+    cache::CacheStats,
+    handlers::Handler,
+    metrics::{TransportLatency, ZoneLatency},
+    tunneling::TunnelingStats,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::PathBuf};
+use tracing::*;
+
+// The number of clients listed in the blocklist section of a dump.
+const DUMP_BLOCKLIST_CLIENTS: usize = 10;
+
+/*
+Description:
+a snapshot of every counter the server keeps, written out on SIGUSR1.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct StatsDump {
+    // Unix timestamp of the snapshot
+    pub timestamp: i64,
+
+    // Seconds since the server started
+    pub uptime: u64,
+
+    // The total number of requests served
+    pub queries: u64,
+
+    // Queries per second, averaged over the last few seconds
+    pub qps: f64,
+
+    // Traffic and latency per zone, busiest first
+    pub zones: Vec<ZoneStats>,
+
+    // Handler and total latency percentiles per zone, busiest first
+    pub zone_latency: Vec<ZoneLatency>,
+
+    // Total latency percentiles per transport
+    pub transport_latency: Vec<TransportLatency>,
+
+    // The number of clients whose statistics are tracked
+    pub clients_tracked: usize,
+
+    // The number of exchange-rate tables in the fx zone's in-memory cache
+    pub fx_cached_tables: usize,
+
+    // The number of dnstap frames dropped because the buffer was full, if dnstap is enabled
+    pub dnstap_dropped: Option<u64>,
+
+    // Blocklist size and hits, if a blocklist is configured
+    pub blocklist: Option<BlocklistStats>,
+
+    // Clients flagged, queries refused and clients suspected by the tunneling detector, if it is configured
+    pub tunneling: Option<TunnelingStats>,
+
+    // The number of queries refused over the rate limit, if one is set
+    pub rate_limited: Option<u64>,
+
+    // The number of queries shed because the server was overloaded
+    pub shed: u64,
+
+    // The current load, where 1 or more means low-priority zones are being shed
+    pub load: f64,
+
+    // The size and hit rate of the response cache, if it is enabled
+    pub response_cache: Option<CacheStats>,
+
+    // The size and hit rate of the parsed name cache, if it is enabled
+    pub name_cache: Option<CacheStats>,
+
+    // The counters of each hosted domain, kept apart from these, keyed by the domain
+    pub tenants: BTreeMap<String, StatsDump>,
+}
+
+/*
+Description:
+the traffic and latency of a single zone in a StatsDump.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct ZoneStats {
+    // The zone's keyword, "root" or "other"
+    pub zone: String,
+
+    // The number of queries answered by the zone
+    pub queries: u64,
+
+    // The number of those queries that were answered with an error
+    pub errors: u64,
+
+    // The average time spent answering a query, in microseconds
+    pub avg_latency_us: u64,
+}
+
+/*
+Description:
+collects a snapshot of the server's counters, with those of each hosted domain.
+
+Parameters:
+handler: the DNS handler whose counters are collected.
+
+Returns:
+A StatsDump.
+*/
+pub fn collect(handler: &Handler) -> StatsDump {
+    let metrics = handler.metrics.snapshot();
+
+    StatsDump {
+        timestamp: chrono::Utc::now().timestamp(),
+        uptime: handler.health.uptime().as_secs(),
+        queries: handler.counter.load(),
+        qps: metrics.qps,
+        zones: metrics
+            .zones
+            .into_iter()
+            .map(|(zone, counters)| ZoneStats {
+                zone,
+                queries: counters.queries,
+                errors: counters.errors,
+                avg_latency_us: counters.total_latency_us.checked_div(counters.queries).unwrap_or(0),
+            })
+            .collect(),
+        zone_latency: metrics.zone_latency,
+        transport_latency: metrics.transport_latency,
+        clients_tracked: handler.clients.tracked(),
+        fx_cached_tables: handler.fx.cached_tables(),
+        dnstap_dropped: handler.dnstap.as_ref().map(|dnstap| dnstap.dropped()),
+        blocklist: handler.blocklist.as_ref().map(|blocklist| blocklist.stats(DUMP_BLOCKLIST_CLIENTS)),
+        tunneling: handler.tunneling.as_ref().map(|tunneling| tunneling.stats()),
+        rate_limited: handler.rate_limit.as_ref().map(|rate_limit| rate_limit.limited()),
+        shed: handler.overload.shed(),
+        load: handler.overload.load(),
+        response_cache: handler.response_cache.as_ref().map(|cache| cache.stats()),
+        name_cache: handler.name_cache.as_ref().map(|cache| cache.stats()),
+        tenants: handler
+            .tenants
+            .iter()
+            .flat_map(|tenants| tenants.iter())
+            .map(|tenant| (tenant.domain.to_string(), collect(&tenant.handler)))
+            .collect(),
+    }
+}
+
+/*
+Description:
+waits for SIGUSR1 and dumps a snapshot of the server's counters, either to the log or, if a stats file was given, as pretty-printed JSON to that file. This gives operators a way to look inside a running server without the admin API.
+
+Parameters:
+handler: the DNS handler whose counters are dumped.
+file: the file the JSON snapshot is written to, replacing its previous contents; None logs the snapshot instead.
+
+Returns:
+None; runs until the process exits.
+*/
+#[cfg(unix)]
+pub async fn dump_on_sigusr1(handler: Handler, file: Option<PathBuf>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = match signal(SignalKind::user_defined1()) {
+        Ok(signals) => signals,
+        Err(error) => {
+            warn!("Failed to listen for SIGUSR1, stats dumps are disabled: {error}");
+            return;
+        }
+    };
+
+    while signals.recv().await.is_some() {
+        let dump = collect(&handler);
+        match &file {
+            Some(path) => {
+                let json = serde_json::to_vec_pretty(&dump).unwrap_or_default();
+                match tokio::fs::write(path, json).await {
+                    Ok(()) => info!("Wrote stats to {}", path.display()),
+                    Err(error) => error!("Failed to write stats to {}: {error}", path.display()),
+                }
+            }
+            None => info!("Stats: {}", serde_json::to_string(&dump).unwrap_or_default()),
+        }
+    }
+}
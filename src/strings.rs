@@ -0,0 +1,115 @@
+use crate::handlers::{Error, Handler};
+use std::str::FromStr;
+use trust_dns_server::{
+    client::rr::{rdata::TXT, RData},
+    proto::op::ResponseCode,
+    server::{Request, ResponseHandler, ResponseInfo},
+};
+
+/*
+Description:
+the operations supported by the str zone.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StringOp {
+    Reverse,
+    Upper,
+    Lower,
+    Length,
+}
+
+impl FromStr for StringOp {
+    type Err = String;
+
+    // Parses an operation from a query label, e.g. "reverse"
+    fn from_str(label: &str) -> Result<Self, Self::Err> {
+        match label.to_ascii_lowercase().as_str() {
+            "reverse" => Ok(StringOp::Reverse),
+            "upper" => Ok(StringOp::Upper),
+            "lower" => Ok(StringOp::Lower),
+            "length" | "len" => Ok(StringOp::Length),
+            other => Err(format!(
+                "unknown string operation {other}, expected reverse, upper, lower or length"
+            )),
+        }
+    }
+}
+
+impl StringOp {
+    /*
+    Description:
+    applies the operation to a piece of text.
+
+    Parameters:
+    text: the text to transform.
+
+    Returns:
+    A String with the transformed text, or the length in characters for the length operation.
+    */
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            StringOp::Reverse => text.chars().rev().collect(),
+            StringOp::Upper => text.to_uppercase(),
+            StringOp::Lower => text.to_lowercase(),
+            StringOp::Length => text.chars().count().to_string(),
+        }
+    }
+}
+
+/*
+Description:
+the str zone lives in its own module but is still part of the monolithic handler, so it extends Handler with its request handling function.
+*/
+impl Handler {
+    /*
+    Description:
+    handles a DNS request for the str zone, applying a text operation to the labels of the query. The query has the form str.<op>.<text>.<domain>, where op is one of reverse, upper, lower and length. Text spanning several labels is joined back together with dots. The text is taken from the query name as the client sent it, so its case is preserved (unless a resolver in between randomizes it).
+
+    Parameters:
+    &self: a reference to the current instance of the DNS server object
+    request: a reference to the Request struct that contains the DNS request information
+    responder: a ResponseHandler trait object that will handle the DNS response
+
+    Returns:
+    Result<ResponseInfo, Error>: a ResponseInfo struct with the transformed text, or a FORMERR answer explaining why if the operation is unknown or no text was given.
+    */
+    pub(crate) async fn do_handle_request_str<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        responder: R,
+    ) -> Result<ResponseInfo, Error> {
+        // Extract the operation and text from the query name, preserving the case the client used, and explain what is wrong with a malformed query.
+        let args = self.keyword_args(request.query().original().name().clone());
+        let parsed = match args.split_first() {
+            Some((op, text)) if !text.is_empty() => {
+                op.parse::<StringOp>().map(|op| (op, text.join(".")))
+            }
+            _ => Err(format!(
+                "expected str.<op>.<text>, got {}",
+                request.query().name()
+            )),
+        };
+        let (op, text) = match parsed {
+            Ok(query) => query,
+            Err(explanation) => {
+                return self
+                    .do_handle_request_malformed(
+                        request,
+                        responder,
+                        ResponseCode::FormErr,
+                        &explanation,
+                    )
+                    .await
+            }
+        };
+
+        // Increment the counter for the number of requests handled by this DNS server instance.
+        self.counter.add(1);
+
+        // Create a TXT record with the result of the operation.
+        let rdata = RData::TXT(TXT::new(vec![op.apply(&text)]));
+
+        // Answer with the record, which has a TTL of 60 seconds unless the zone is configured with another.
+        self.respond(request, 60).answer(rdata).send(responder).await
+    }
+}
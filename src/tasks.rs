@@ -0,0 +1,36 @@
+use std::future::Future;
+use tokio::task::JoinHandle;
+
+// The console feature relies on tokio's unstable task instrumentation.
+#[cfg(all(feature = "console", not(tokio_unstable)))]
+compile_error!("the console feature needs tokio's task instrumentation: build with RUSTFLAGS=\"--cfg tokio_unstable\"");
+
+/*
+Description:
+spawns a task on the current runtime like tokio::spawn, but named, so that when the server is built with the console feature the task can be told apart from the others in tokio-console (e.g. "udp-query" or "tcp-connection") and its polls traced back to the code that spawned it. Without the feature the name is ignored.
+
+Parameters:
+name: the name of the task.
+future: the future the task runs.
+
+Returns:
+The JoinHandle of the task.
+*/
+#[track_caller]
+pub fn spawn<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(feature = "console")]
+    return tokio::task::Builder::new()
+        .name(name)
+        .spawn(future)
+        .expect("spawning a task on a running runtime never fails");
+
+    #[cfg(not(feature = "console"))]
+    {
+        let _ = name;
+        tokio::spawn(future)
+    }
+}
@@ -0,0 +1,314 @@
+use crate::{handlers::Handler, tasks};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{tcp::OwnedReadHalf, TcpListener, TcpStream},
+    sync::{mpsc, Notify, Semaphore},
+    time::timeout,
+};
+use tracing::*;
+use trust_dns_server::{
+    authority::{MessageRequest, MessageResponse},
+    client::rr::Record,
+    proto::serialize::binary::{BinDecodable, BinEncoder},
+    server::{Protocol, Request, RequestHandler, ResponseHandler, ResponseInfo},
+};
+
+// The number of answers that can wait for a connection's writer before the handlers answering it wait too.
+const WRITE_QUEUE: usize = 64;
+
+// How long to wait before accepting again when accepting fails, e.g. because the process ran out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/*
+Description:
+the budget of a TCP listener: how many connections it keeps open, how many of them one client may hold, and how many queries of one connection are handled at once.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    // How long a connection may stay idle, or take to send a whole query, before it is closed
+    pub idle_timeout: Duration,
+
+    // The most connections open at once on the listener, 0 for no limit
+    pub max_connections: usize,
+
+    // The most connections one client address may hold open on the listener, 0 for no limit
+    pub max_per_client: usize,
+
+    // The most queries of one connection handled at once; the next query isn't read until one of them is answered
+    pub max_in_flight: usize,
+}
+
+impl Limits {
+    // Returns whether a connection limit is set, in which case the listener is served by serve rather than ServerFuture
+    pub fn enabled(&self) -> bool {
+        self.max_connections > 0 || self.max_per_client > 0
+    }
+}
+
+// An open connection in a listener's table
+struct Connection {
+    // The client's address
+    client: IpAddr,
+
+    // When the connection last read a query, in milliseconds since the listener started
+    last_active: Arc<AtomicU64>,
+
+    // Wakes the connection to close it when it is shed
+    close: Arc<Notify>,
+}
+
+// The open connections of a listener
+struct Table {
+    // The connections, by their number
+    connections: Mutex<HashMap<u64, Connection>>,
+
+    // The number of the next connection
+    next_id: AtomicU64,
+
+    // When the listener started, the origin of the activity timestamps
+    started: Instant,
+}
+
+impl Table {
+    // Admits a connection from a client, shedding another one if the listener is full; returns its number, activity timestamp and close signal, or None if it is refused
+    fn admit(&self, client: IpAddr, limits: &Limits) -> Option<(u64, Arc<AtomicU64>, Arc<Notify>)> {
+        let mut connections = self.connections.lock().unwrap();
+
+        // A client at its own limit is refused, whatever the others do
+        let mut per_client = HashMap::<IpAddr, usize>::new();
+        for connection in connections.values() {
+            *per_client.entry(connection.client).or_default() += 1;
+        }
+        let own = per_client.get(&client).copied().unwrap_or(0);
+        if limits.max_per_client > 0 && own >= limits.max_per_client {
+            debug!("Refusing a TCP connection from {client}, which already holds {own}");
+            return None;
+        }
+
+        // A full listener sheds the idlest connection of the client holding the most, unless that is the newcomer
+        if limits.max_connections > 0 && connections.len() >= limits.max_connections {
+            let (heaviest, count) = per_client.into_iter().max_by_key(|&(_, count)| count)?;
+            if count <= own {
+                debug!("Refusing a TCP connection from {client}, the listener is full");
+                return None;
+            }
+            let (&idlest, _) = connections
+                .iter()
+                .filter(|(_, connection)| connection.client == heaviest)
+                .min_by_key(|(_, connection)| connection.last_active.load(Ordering::Relaxed))?;
+            if let Some(shed) = connections.remove(&idlest) {
+                debug!("Shedding an idle TCP connection from {heaviest}, which holds {count}, to admit {client}");
+                shed.close.notify_one();
+            }
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let last_active = Arc::new(AtomicU64::new(self.now()));
+        let close = Arc::new(Notify::new());
+        connections.insert(
+            id,
+            Connection {
+                client,
+                last_active: last_active.clone(),
+                close: close.clone(),
+            },
+        );
+        Some((id, last_active, close))
+    }
+
+    // Removes a closed connection, if it wasn't already shed
+    fn remove(&self, id: u64) {
+        self.connections.lock().unwrap().remove(&id);
+    }
+
+    // Returns the time since the listener started, in milliseconds
+    fn now(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
+
+/*
+Description:
+serves DNS over a TCP listener within a connection budget, instead of ServerFuture which accepts every connection and reads every query it is sent. A client already holding its share of the connections is refused; when the listener is full, the idlest connection of the client holding the most is closed to make room, so one client opening many slow connections (slowloris) sheds its own connections rather than everyone else's. Each connection handles a bounded number of queries at once and stops reading while it is at that bound, leaving the client to TCP flow control, and is closed when it stays idle or takes longer than the idle timeout to send a query.
+
+Parameters:
+listener: the bound TCP listener.
+handler: the DNS handler that answers the queries.
+limits: the listener's budget.
+
+Returns:
+Never returns; failures to accept are logged and retried.
+*/
+pub async fn serve(listener: TcpListener, handler: Handler, limits: Limits) -> io::Result<()> {
+    let table = Arc::new(Table {
+        connections: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(0),
+        started: Instant::now(),
+    });
+
+    loop {
+        let (stream, src) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(error) => {
+                warn!("Failed to accept a TCP connection: {error}");
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+
+        // Dropping a refused stream closes it
+        let Some((id, last_active, close)) = table.admit(src.ip(), &limits) else {
+            continue;
+        };
+
+        let handler = handler.clone();
+        let table = table.clone();
+        tasks::spawn("tcp-connection", async move {
+            serve_connection(stream, src, handler, limits, &table, last_active, close).await;
+            table.remove(id);
+        });
+    }
+}
+
+// Reads and answers the queries of one connection until it is closed, idle or shed
+async fn serve_connection(
+    stream: TcpStream,
+    src: SocketAddr,
+    handler: Handler,
+    limits: Limits,
+    table: &Table,
+    last_active: Arc<AtomicU64>,
+    close: Arc<Notify>,
+) {
+    let (mut reader, mut writer) = stream.into_split();
+
+    // Write the answers from a separate task, in the order they are ready
+    let (replies, mut queue) = mpsc::channel::<Vec<u8>>(WRITE_QUEUE);
+    let writer_task = tasks::spawn("tcp-writer", async move {
+        let mut frame = Vec::new();
+        while let Some(bytes) = queue.recv().await {
+            // Write the length prefix and the answer in one go, so they leave in one segment
+            frame.clear();
+            frame.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+            frame.extend_from_slice(&bytes);
+            if writer.write_all(&frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let in_flight = Arc::new(Semaphore::new(limits.max_in_flight.max(1)));
+    let shed = loop {
+        // Wait for a free slot before reading the next query
+        let permit = tokio::select! {
+            permit = in_flight.clone().acquire_owned() => permit.expect("the semaphore is never closed"),
+            _ = close.notified() => break true,
+        };
+
+        let bytes = tokio::select! {
+            read = timeout(limits.idle_timeout, read_message(&mut reader)) => match read {
+                Ok(Ok(Some(bytes))) => bytes,
+                Ok(Ok(None)) => break false,
+                Ok(Err(error)) => {
+                    debug!("Closing the TCP connection from {src}: {error}");
+                    break false;
+                }
+                Err(_) => {
+                    debug!("Closing the idle TCP connection from {src}");
+                    break false;
+                }
+            },
+            _ = close.notified() => break true,
+        };
+        last_active.store(table.now(), Ordering::Relaxed);
+
+        let message = match MessageRequest::from_bytes(&bytes) {
+            Ok(message) => message,
+            Err(error) => {
+                debug!("Closing the TCP connection from {src} after a malformed query: {error}");
+                break false;
+            }
+        };
+
+        // Handle the query in its own task, holding the slot until it is answered
+        let responder = StreamResponder { replies: replies.clone() };
+        let handler = handler.clone();
+        tasks::spawn("tcp-query", async move {
+            let request = Request::new(message, src, Protocol::Tcp);
+            handler.handle_request(&request, responder).await;
+            drop(permit);
+        });
+    };
+
+    // Let the queries already read be answered, unless the connection was shed
+    drop(replies);
+    if shed {
+        writer_task.abort();
+    } else {
+        let _ = writer_task.await;
+    }
+}
+
+// Reads one length-prefixed message, returning None if the client closed the connection between messages
+async fn read_message(reader: &mut OwnedReadHalf) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 2];
+    match reader.read_exact(&mut length).await {
+        Ok(_) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+
+    let mut bytes = vec![0; u16::from_be_bytes(length) as usize];
+    reader.read_exact(&mut bytes).await?;
+    Ok(Some(bytes))
+}
+
+/*
+Description:
+a ResponseHandler that serializes the answer and queues it for the connection's writer, waiting while the writer is behind.
+*/
+#[derive(Clone)]
+struct StreamResponder {
+    // The queue of answers for the writer task
+    replies: mpsc::Sender<Vec<u8>>,
+}
+
+#[async_trait::async_trait]
+impl ResponseHandler for StreamResponder {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        let mut bytes = Vec::with_capacity(512);
+        let header = {
+            let mut encoder = BinEncoder::new(&mut bytes);
+            encoder.set_max_size(u16::MAX);
+            response
+                .destructive_emit(&mut encoder)
+                .map_err(io::Error::other)?
+        };
+
+        self.replies
+            .send(bytes)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the TCP connection is closed"))?;
+        Ok(header)
+    }
+}
@@ -0,0 +1,303 @@
+use crate::handlers::RECURSION_AVAILABLE;
+use trust_dns_server::{
+    client::rr::{LowerName, Name, Record, RecordType},
+    proto::{
+        op::ResponseCode,
+        serialize::binary::{BinEncodable, BinEncoder},
+    },
+};
+
+// The size of the fixed DNS header.
+const HEADER_LEN: usize = 12;
+
+// The most labels a name can have (255 bytes of one-character labels).
+const MAX_LABELS: usize = 128;
+
+// The header flags: response, opcode, authoritative, recursion desired, recursion available, checking disabled.
+const QR: u16 = 0x8000;
+const OPCODE: u16 = 0x7800;
+const AA: u16 = 0x0400;
+const RD: u16 = 0x0100;
+const RA: u16 = 0x0080;
+const CD: u16 = 0x0010;
+
+// The record type of the EDNS OPT pseudo-record.
+const OPT: u16 = 41;
+
+// The mark of a compression pointer in the first byte of a name.
+const POINTER: u16 = 0xC000;
+
+/*
+Description:
+a query parsed straight from the wire, just far enough to decide whether it can be answered from a template: the header has been checked and the question's labels located, without allocating. Only plain queries with one question, no records other than an optional EDNS OPT record, and an uncompressed name are accepted.
+*/
+pub struct RawQuery<'a> {
+    // The whole query
+    bytes: &'a [u8],
+
+    // The labels of the queried name, in the order they appear
+    labels: [&'a [u8]; MAX_LABELS],
+
+    // The number of labels in use
+    label_count: usize,
+
+    // The offset just past the question
+    question_end: usize,
+}
+
+impl<'a> RawQuery<'a> {
+    /*
+    Description:
+    parses a query datagram.
+
+    Parameters:
+    bytes: the datagram.
+
+    Returns:
+    Some(RawQuery), or None if the message is anything but a plain query that templates can answer; such messages take the full path.
+    */
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN {
+            return None;
+        }
+        let word = |offset: usize| u16::from_be_bytes([bytes[offset], bytes[offset + 1]]);
+
+        // A standard query with exactly one question and nothing but EDNS besides
+        let flags = word(2);
+        if flags & (QR | OPCODE) != 0 || word(4) != 1 || word(6) != 0 || word(8) != 0 || word(10) > 1 {
+            return None;
+        }
+
+        // Walk the name's labels, which must not use compression
+        let mut labels = [&bytes[..0]; MAX_LABELS];
+        let mut label_count = 0;
+        let mut offset = HEADER_LEN;
+        loop {
+            let length = *bytes.get(offset)? as usize;
+            offset += 1;
+            if length == 0 {
+                break;
+            }
+            if length > 63 || label_count == MAX_LABELS || offset - HEADER_LEN + length > 255 {
+                return None;
+            }
+            labels[label_count] = bytes.get(offset..offset + length)?;
+            label_count += 1;
+            offset += length;
+        }
+        let question_end = offset + 4;
+        if bytes.len() < question_end {
+            return None;
+        }
+
+        // The only record allowed after the question is a well-formed OPT record for the root
+        let rest = &bytes[question_end..];
+        if word(10) == 0 {
+            if !rest.is_empty() {
+                return None;
+            }
+        } else if rest.len() < 11
+            || rest[0] != 0
+            || u16::from_be_bytes([rest[1], rest[2]]) != OPT
+            || rest.len() != 11 + u16::from_be_bytes([rest[9], rest[10]]) as usize
+        {
+            return None;
+        }
+
+        Some(RawQuery {
+            bytes,
+            labels,
+            label_count,
+            question_end,
+        })
+    }
+
+    // Returns the labels of the queried name
+    pub fn labels(&self) -> &[&'a [u8]] {
+        &self.labels[..self.label_count]
+    }
+
+    // Returns the queried record type
+    pub fn query_type(&self) -> RecordType {
+        let offset = self.question_end - 4;
+        RecordType::from(u16::from_be_bytes([self.bytes[offset], self.bytes[offset + 1]]))
+    }
+
+    // Returns the queried name, lowercased as it is written in logs, e.g. "nosuchzone.mentisnovae.tech."
+    pub fn name(&self) -> String {
+        Name::from_labels(self.labels().iter().copied()).map_or_else(|_| ".".to_string(), |name| name.to_lowercase().to_string())
+    }
+}
+
+/*
+Description:
+a pre-serialized answer with no records in its answer section, such as the NXDOMAIN for names no zone serves, optionally with the domain's SOA record in its authority section. Rendering it copies the query's ID, its RD and CD flags and its question, byte for byte, behind the template's flags, which set RA as handlers::response_header does, and appends the SOA record as trust-dns encodes it after the question, moving its compression pointers to where it lands; nothing else about the answer depends on the query, so no response is built or encoded and the answer is the same, byte for byte, as the full path's.
+*/
+#[derive(Clone, Debug)]
+pub struct Template {
+    // The header flags of the answer, without the ones copied from the query
+    flags: u16,
+
+    // The SOA record of the authority section, serialized as if it started the message, and the offsets of its compression pointers
+    authority: Option<(Vec<u8>, Vec<usize>)>,
+}
+
+impl Template {
+    // Creates a template for an authoritative answer with the given response code and no records
+    pub fn new(response_code: ResponseCode) -> Self {
+        Template {
+            flags: QR | AA | (if RECURSION_AVAILABLE { RA } else { 0 }) | response_code.low() as u16,
+            authority: None,
+        }
+    }
+
+    /*
+    Description:
+    adds an SOA record to the authority section of the template's answer. trust-dns copies the question into a response without remembering its names, so the record is compressed against nothing but itself: its owner is written out and the names in its data point into it.
+
+    Parameters:
+    soa: the SOA record.
+
+    Returns:
+    The Template, or the one without the record if it can't be serialized.
+    */
+    pub fn with_authority(mut self, soa: &Record) -> Self {
+        // Serialize the record as the full path does, but at the start of a message, so its pointers are relative to the record
+        let mut bytes = Vec::new();
+        if soa.emit(&mut BinEncoder::new(&mut bytes)).is_err() || soa.record_type() != RecordType::SOA {
+            return self;
+        }
+
+        // Find the pointers among the owner and the two names starting the data, after the type, class, TTL and data length
+        let mut pointers = Vec::new();
+        let mut offset = 0;
+        for skip in [0, 10, 0] {
+            offset += skip;
+            loop {
+                let Some(&length) = bytes.get(offset) else {
+                    return self;
+                };
+                if u16::from(length) << 8 & POINTER == POINTER {
+                    pointers.push(offset);
+                    offset += 2;
+                    break;
+                }
+                offset += 1 + length as usize;
+                if length == 0 {
+                    break;
+                }
+            }
+        }
+        self.authority = Some((bytes, pointers));
+        self
+    }
+
+    /*
+    Description:
+    writes the answer to a query into a buffer, replacing what it held.
+
+    Parameters:
+    query: the query being answered.
+    out: the buffer the answer is written to.
+
+    Returns:
+    None
+    */
+    pub fn render(&self, query: &RawQuery, out: &mut Vec<u8>) {
+        let flags = self.flags | (u16::from_be_bytes([query.bytes[2], query.bytes[3]]) & (RD | CD));
+
+        out.clear();
+        out.extend_from_slice(&query.bytes[..2]);
+        out.extend_from_slice(&flags.to_be_bytes());
+        out.extend_from_slice(&[0, 1, 0, 0]);
+        out.extend_from_slice(&u16::from(self.authority.is_some()).to_be_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(&query.bytes[HEADER_LEN..query.question_end]);
+
+        // The SOA record's pointers lead into the record, so they move by as much as the record does
+        if let Some((record, pointers)) = &self.authority {
+            let start = out.len();
+            out.extend_from_slice(record);
+            for pointer in pointers {
+                let target = u16::from_be_bytes([out[start + pointer], out[start + pointer + 1]]) + start as u16;
+                out[start + pointer..start + pointer + 2].copy_from_slice(&target.to_be_bytes());
+            }
+        }
+    }
+}
+
+/*
+Description:
+the templates a handler answers from, and what is needed to tell which queries they apply to. Today that is the NXDOMAIN for names below the served domain that no zone serves, which the handler otherwise answers through the default zone.
+*/
+#[derive(Debug)]
+pub struct Templates {
+    // The labels of the served domain, lowercased
+    root: Vec<Vec<u8>>,
+
+    // The keywords of the zones that also serve every name below them, e.g. "dice" for x.dice.<domain>
+    fixed: Vec<Vec<u8>>,
+
+    // The keywords of the zones that serve names starting with them, e.g. "fx" for fx.100.usd.eur.<domain>
+    keywords: Vec<Vec<u8>>,
+
+    // The answer for names no zone serves
+    pub nxdomain: Template,
+}
+
+impl Templates {
+    /*
+    Description:
+    prepares the templates for a handler's zones.
+
+    Parameters:
+    root: the served domain.
+    fixed: the zones that serve every name below them.
+    keywords: the zones that serve names starting with their keyword.
+    soa: the SOA record of the served domain, which the NXDOMAIN carries in its authority section, if there is one.
+
+    Returns:
+    The Templates.
+    */
+    pub fn new(root: &LowerName, fixed: &[&LowerName], keywords: &[&LowerName], soa: Option<&Record>) -> Self {
+        let first_label = |zone: &&LowerName| Name::from(*zone).iter().next().unwrap_or_default().to_ascii_lowercase();
+        Templates {
+            root: Name::from(root).iter().map(|label| label.to_ascii_lowercase()).collect(),
+            fixed: fixed.iter().map(first_label).collect(),
+            keywords: keywords.iter().map(first_label).collect(),
+            nxdomain: match soa {
+                Some(soa) => Template::new(ResponseCode::NXDomain).with_authority(soa),
+                None => Template::new(ResponseCode::NXDomain),
+            },
+        }
+    }
+
+    /*
+    Description:
+    works out whether a query is for a name that no zone serves, matching names case-insensitively as the zones do.
+
+    Parameters:
+    query: the query.
+
+    Returns:
+    Some(Some(keyword)) for a name starting with an unknown keyword, Some(None) for the served domain itself, or None if the name is outside the served domain or served by a zone.
+    */
+    pub fn unserved<'q>(&self, query: &'q RawQuery) -> Option<Option<&'q [u8]>> {
+        let labels = query.labels();
+        let below = labels.len().checked_sub(self.root.len())?;
+        let in_domain = labels[below..].iter().zip(&self.root).all(|(label, root)| label.eq_ignore_ascii_case(root));
+        if !in_domain {
+            return None;
+        }
+        if below == 0 {
+            return Some(None);
+        }
+
+        // Neither a name below a fixed zone, nor one starting with a keyword
+        let matches = |zones: &[Vec<u8>], label: &[u8]| zones.iter().any(|zone| label.eq_ignore_ascii_case(zone));
+        if matches(&self.fixed, labels[below - 1]) || matches(&self.keywords, labels[0]) {
+            return None;
+        }
+        Some(Some(labels[0]))
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{config::TenantConfig, handlers::Handler, options::Options};
+use tracing::*;
+use trust_dns_server::client::rr::{LowerName, Name};
+
+/*
+Description:
+a hosted domain: its name, the token its owner reads its state with through the admin API, and the handler that answers it, with counters, statistics, rate limit and blocklist of its own.
+*/
+#[derive(Debug)]
+pub struct Tenant {
+    // The hosted domain
+    pub domain: LowerName,
+
+    // The token that scopes admin API requests to the domain, if it has one
+    pub admin_token: Option<String>,
+
+    // The handler that answers and counts the domain's queries
+    pub handler: Handler,
+}
+
+/*
+Description:
+the domains hosted besides the main one, from the [[tenants]] sections of the configuration file. Each gets a handler of its own, built as if the server were started for the domain alone, so one domain's queries don't show in another's counters or statistics and can't use up another's rate limit. What belongs to the server rather than a domain is shared with the main handler: the query, dnstap and audit logs, the health state, the exchange rates and the overload detector.
+*/
+#[derive(Debug)]
+pub struct Tenants {
+    // The hosted domains, the longest first so a domain hosted within another is matched before it
+    tenants: Vec<Tenant>,
+}
+
+// Returns the options a hosted domain's handler is built from: the server's, for the domain, with its own state and without the backends main follows for the main domain
+fn tenant_options(options: &Options, config: &TenantConfig) -> Options {
+    let mut tenant = options.clone();
+    tenant.domain = config.domain.trim_end_matches('.').to_string();
+    tenant.config = options.config.for_tenant(config);
+    tenant.counter_file = config.counter_file.clone();
+    tenant.rate_limit = config.rate_limit;
+    tenant.acme_api = None;
+    tenant.ddns_api = None;
+    tenant.docker_socket = None;
+    tenant.hosts_file.clear();
+    tenant.lan_discovery = false;
+    tenant.wireguard_config = None;
+    tenant.wireguard_interface = None;
+    tenant.consul = None;
+    tenant.etcd = None;
+    tenant.zone_file.clear();
+    tenant.dnstap_file = None;
+    tenant.dnstap_socket = None;
+    tenant.audit_log = None;
+    #[cfg(feature = "postgres")]
+    {
+        tenant.postgres = None;
+    }
+    #[cfg(feature = "ldap")]
+    {
+        tenant.ldap = None;
+    }
+    #[cfg(feature = "redis")]
+    {
+        tenant.redis_prefix = format!("{}{}:", options.redis_prefix, tenant.domain);
+        tenant.redis_records = false;
+    }
+    #[cfg(feature = "sqlite")]
+    {
+        tenant.query_log = None;
+    }
+    tenant.config.tenants.clear();
+    tenant
+}
+
+// Compares an admin token with the one a request gave in time that depends only on their length, so the time taken doesn't tell how much of a guess was right
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+impl Tenants {
+    /*
+    Description:
+    builds the handlers of the hosted domains, leaving out those that are not valid names or that hold the main domain, which would take its queries.
+
+    Parameters:
+    options: the server's options, with the [[tenants]] sections in options.config.
+    main: the handler of the main domain, whose logs, health, exchange rates and overload detector the domains share.
+
+    Returns:
+    The hosted domains.
+    */
+    pub fn new(options: &Options, main: &Handler) -> Self {
+        let mut tenants = Vec::new();
+        for config in &options.config.tenants {
+            let domain = match Name::parse(&config.domain, Some(&Name::root())) {
+                Ok(domain) => LowerName::new(&domain),
+                Err(error) => {
+                    error!("Invalid hosted domain {}, leaving it out: {error}", config.domain);
+                    continue;
+                }
+            };
+            if domain.zone_of(&main.root_zone) {
+                error!("The hosted domain {domain} holds the main domain {}, leaving it out", main.root_zone);
+                continue;
+            }
+
+            let handler = Handler::from_options(&tenant_options(options, config));
+            let handler = Handler {
+                dnstap: main.dnstap.clone(),
+                #[cfg(feature = "sqlite")]
+                query_log: main.query_log.clone(),
+                audit: main.audit.clone(),
+                health: main.health.clone(),
+                fx: main.fx.clone(),
+                overload: main.overload.clone(),
+                ..handler
+            };
+            info!("Hosting {domain}");
+            tenants.push(Tenant { domain, admin_token: config.admin_token.clone(), handler });
+        }
+        tenants.sort_by_key(|tenant| std::cmp::Reverse(tenant.domain.num_labels()));
+        Tenants { tenants }
+    }
+
+    // Returns the hosted domains
+    pub fn iter(&self) -> impl Iterator<Item = &Tenant> {
+        self.tenants.iter()
+    }
+
+    // Returns the hosted domain a name belongs to, if any
+    pub fn for_name(&self, name: &LowerName) -> Option<&Tenant> {
+        self.tenants.iter().find(|tenant| tenant.domain.zone_of(name))
+    }
+
+    // Returns the hosted domain whose admin token was given, if any
+    pub fn for_token(&self, token: &str) -> Option<&Tenant> {
+        self.tenants
+            .iter()
+            .find(|tenant| tenant.admin_token.as_deref().is_some_and(|admin_token| tokens_match(admin_token, token)))
+    }
+}
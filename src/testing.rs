@@ -0,0 +1,175 @@
+pub use crate::cache::CaptureResponder;
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    str::FromStr,
+};
+use trust_dns_server::{
+    authority::MessageRequest,
+    client::rr::{Name, RecordType},
+    proto::{
+        op::{Edns, Message, MessageType, OpCode, Query},
+        rr::rdata::opt::EdnsOption,
+        serialize::binary::BinDecodable,
+    },
+    server::{Protocol, Request, RequestHandler},
+};
+
+// The payload size of the OPT record added for an EDNS option when no other was set.
+const DEFAULT_EDNS_PAYLOAD: u16 = 1232;
+
+/*
+Description:
+builds the Request values the server hands to a RequestHandler, for unit-testing zone handlers without a socket: a query for a name and type, from a client address, over a transport, with or without EDNS. The query is encoded and decoded again, so the handler sees exactly what it would have seen off the wire. Pair it with a CaptureResponder to read the answer, e.g.
+
+    let request = RequestBuilder::new(name, RecordType::TXT).source("192.0.2.7:53000".parse()?).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    let answer = Message::from_vec(&responder.take().unwrap())?;
+*/
+#[derive(Clone, Debug)]
+pub struct RequestBuilder {
+    // The query's ID
+    id: u16,
+
+    // The query name
+    name: Name,
+
+    // The query type
+    query_type: RecordType,
+
+    // The query's op code
+    op_code: OpCode,
+
+    // Whether the query has RD set
+    recursion_desired: bool,
+
+    // The query's OPT record, if it has one
+    edns: Option<Edns>,
+
+    // The address the query comes from
+    source: SocketAddr,
+
+    // The transport the query comes over
+    protocol: Protocol,
+}
+
+impl RequestBuilder {
+    /*
+    Description:
+    starts a standard query with RD set and ID 0, without EDNS, over UDP from 127.0.0.1:53000; each of these can be changed with the builder's other methods.
+
+    Parameters:
+    name: the query name. Names built with Name::from_labels may hold any bytes, as names off the wire can.
+    query_type: the query type.
+
+    Returns:
+    The RequestBuilder.
+    */
+    pub fn new(name: Name, query_type: RecordType) -> Self {
+        RequestBuilder {
+            id: 0,
+            name,
+            query_type,
+            op_code: OpCode::Query,
+            recursion_desired: true,
+            edns: None,
+            source: SocketAddr::from((Ipv4Addr::LOCALHOST, 53000)),
+            protocol: Protocol::Udp,
+        }
+    }
+
+    // Sets the query's ID
+    pub fn id(mut self, id: u16) -> Self {
+        self.id = id;
+        self
+    }
+
+    // Sets the query's op code, e.g. OpCode::Update
+    pub fn op_code(mut self, op_code: OpCode) -> Self {
+        self.op_code = op_code;
+        self
+    }
+
+    // Sets whether the query has RD set
+    pub fn recursion_desired(mut self, recursion_desired: bool) -> Self {
+        self.recursion_desired = recursion_desired;
+        self
+    }
+
+    // Sets the address the query comes from, which the access lists, country policies and per-client statistics see
+    pub fn source(mut self, source: SocketAddr) -> Self {
+        self.source = source;
+        self
+    }
+
+    // Sets the transport the query comes over
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    // Gives the query an OPT record, replacing any options added before
+    pub fn edns(mut self, edns: Edns) -> Self {
+        self.edns = Some(edns);
+        self
+    }
+
+    // Adds an option to the query's OPT record, adding an EDNS version 0 record with a 1232-byte payload if there is none
+    pub fn edns_option(mut self, option: EdnsOption) -> Self {
+        self.edns
+            .get_or_insert_with(|| {
+                let mut edns = Edns::new();
+                edns.set_max_payload(DEFAULT_EDNS_PAYLOAD);
+                edns
+            })
+            .options_mut()
+            .insert(option);
+        self
+    }
+
+    /*
+    Description:
+    encodes the query and decodes it into a Request, as the server does with a query it receives.
+
+    Parameters:
+    None
+
+    Returns:
+    The Request. Panics if the query cannot be encoded, e.g. because an EDNS option is malformed, which is a mistake in the test.
+    */
+    pub fn build(&self) -> Request {
+        let mut message = Message::new();
+        message
+            .set_id(self.id)
+            .set_message_type(MessageType::Query)
+            .set_op_code(self.op_code)
+            .set_recursion_desired(self.recursion_desired)
+            .add_query(Query::query(self.name.clone(), self.query_type));
+        if let Some(edns) = &self.edns {
+            message.set_edns(edns.clone());
+        }
+
+        let bytes = message.to_vec().expect("the query could not be encoded");
+        let request = MessageRequest::from_bytes(&bytes).expect("the query could not be decoded");
+        Request::new(request, self.source, self.protocol)
+    }
+}
+
+/*
+Description:
+answers a query for a name over UDP from 127.0.0.1, for tests that only need the answer.
+
+Parameters:
+handler: the handler to ask, e.g. a Handler.
+name: the query name, e.g. "dice.mentisnovae.tech.".
+record_type: the query type.
+
+Returns:
+The answer. Panics if the name is invalid or the handler sends no answer.
+*/
+pub async fn answer(handler: &impl RequestHandler, name: &str, record_type: RecordType) -> Message {
+    let request = RequestBuilder::new(Name::from_str(name).expect("invalid query name"), record_type).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    Message::from_vec(&responder.take().expect("the handler sent no answer")).expect("the answer could not be decoded")
+}
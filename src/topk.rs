@@ -0,0 +1,214 @@
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// The number of sub-windows a sliding window is split into. The window slides by one sub-window at a time.
+const BUCKETS: u32 = 5;
+
+/*
+Description:
+a space-saving sketch (Metwally et al.) that finds the most frequent items of a stream in fixed memory. It keeps at most capacity counters; an untracked item takes over the smallest counter, inheriting its count as the possible overestimate (error). Any item seen more than n/capacity times in a stream of n items is guaranteed to be tracked.
+*/
+#[derive(Clone, Debug)]
+struct SpaceSaving<K> {
+    // The largest number of items tracked
+    capacity: usize,
+
+    // The tracked items with their (count, error)
+    counters: HashMap<K, (u64, u64)>,
+}
+
+impl<K: Clone + Eq + Hash> SpaceSaving<K> {
+    fn new(capacity: usize) -> Self {
+        SpaceSaving {
+            capacity: capacity.max(1),
+            counters: HashMap::new(),
+        }
+    }
+
+    // Counts one occurrence of an item
+    fn insert(&mut self, item: &K) {
+        if let Some((count, _)) = self.counters.get_mut(item) {
+            *count += 1;
+            return;
+        }
+
+        // Track the new item, replacing the smallest counter if the sketch is full
+        if self.counters.len() < self.capacity {
+            self.counters.insert(item.clone(), (1, 0));
+        } else if let Some((smallest, min)) = self
+            .counters
+            .iter()
+            .min_by_key(|(_, (count, _))| *count)
+            .map(|(key, (count, _))| (key.clone(), *count))
+        {
+            self.counters.remove(&smallest);
+            self.counters.insert(item.clone(), (min + 1, min));
+        }
+    }
+}
+
+/*
+Description:
+one entry of a top-k report.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct TopEntry<K> {
+    // The item, e.g. a client address or a query name
+    pub item: K,
+
+    // The estimated number of occurrences in the window; never an underestimate
+    pub count: u64,
+
+    // How much count may overestimate the true number of occurrences
+    pub error: u64,
+}
+
+/*
+Description:
+a space-saving top-k over a sliding window. The window is split into BUCKETS sub-windows with a sketch each; when the newest sub-window is full it is started afresh and the oldest one is forgotten, so reports cover between (BUCKETS - 1) / BUCKETS of the window and the whole window.
+*/
+#[derive(Debug)]
+struct SlidingTopK<K> {
+    // The sketches of the sub-windows, newest last
+    buckets: VecDeque<SpaceSaving<K>>,
+
+    // The length of a sub-window
+    bucket_span: Duration,
+
+    // When the newest sub-window started
+    bucket_started: Instant,
+
+    // The number of counters per sketch
+    capacity: usize,
+}
+
+impl<K: Clone + Eq + Hash> SlidingTopK<K> {
+    fn new(window: Duration, capacity: usize) -> Self {
+        SlidingTopK {
+            buckets: VecDeque::from([SpaceSaving::new(capacity)]),
+            bucket_span: (window / BUCKETS).max(Duration::from_secs(1)),
+            bucket_started: Instant::now(),
+            capacity,
+        }
+    }
+
+    // Starts new sub-windows for the time that has passed, forgetting those that left the window
+    fn advance(&mut self) {
+        while self.bucket_started.elapsed() >= self.bucket_span {
+            self.bucket_started += self.bucket_span;
+            if self.buckets.len() >= BUCKETS as usize {
+                self.buckets.pop_front();
+            }
+            self.buckets.push_back(SpaceSaving::new(self.capacity));
+
+            // After a long quiet spell, start over instead of stepping through every empty sub-window
+            if self.bucket_started.elapsed() >= self.bucket_span * BUCKETS {
+                self.bucket_started = Instant::now();
+                self.buckets = VecDeque::from([SpaceSaving::new(self.capacity)]);
+            }
+        }
+    }
+
+    fn insert(&mut self, item: &K) {
+        self.advance();
+        if let Some(bucket) = self.buckets.back_mut() {
+            bucket.insert(item);
+        }
+    }
+
+    // Merges the sub-windows and returns the limit most frequent items, most frequent first
+    fn top(&mut self, limit: usize) -> Vec<TopEntry<K>> {
+        self.advance();
+
+        let mut merged: HashMap<K, (u64, u64)> = HashMap::new();
+        for bucket in &self.buckets {
+            for (item, (count, error)) in &bucket.counters {
+                let entry = merged.entry(item.clone()).or_default();
+                entry.0 += count;
+                entry.1 += error;
+            }
+        }
+
+        let mut entries: Vec<TopEntry<K>> = merged
+            .into_iter()
+            .map(|(item, (count, error))| TopEntry { item, count, error })
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.count));
+        entries.truncate(limit);
+        entries
+    }
+}
+
+/*
+Description:
+the busiest clients and most queried names over a sliding window, tracked in fixed memory with space-saving sketches. Unlike the per-client statistics, which remember every client up to a capacity, this only keeps the heavy hitters, so it stays accurate for the top of the list under any traffic.
+*/
+#[derive(Debug)]
+pub struct TopTalkers {
+    // The length of the sliding window
+    window: Duration,
+
+    // The busiest client addresses
+    clients: Mutex<SlidingTopK<IpAddr>>,
+
+    // The most queried names
+    qnames: Mutex<SlidingTopK<String>>,
+}
+
+impl TopTalkers {
+    /*
+    Description:
+    creates empty top-talker tracking.
+
+    Parameters:
+    window: the length of the sliding window.
+    capacity: the number of counters per sketch; the top capacity items are tracked reliably.
+
+    Returns:
+    A new TopTalkers instance.
+    */
+    pub fn new(window: Duration, capacity: usize) -> Self {
+        TopTalkers {
+            window,
+            clients: Mutex::new(SlidingTopK::new(window, capacity)),
+            qnames: Mutex::new(SlidingTopK::new(window, capacity)),
+        }
+    }
+
+    /*
+    Description:
+    records a query.
+
+    Parameters:
+    client: the client's IP address.
+    qname: the query name, lower-case.
+
+    Returns:
+    None
+    */
+    pub fn record(&self, client: IpAddr, qname: &str) {
+        self.clients.lock().unwrap().insert(&client);
+        self.qnames.lock().unwrap().insert(&qname.to_string());
+    }
+
+    // Returns the length of the sliding window
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    // Returns the limit busiest clients in the window, busiest first
+    pub fn top_clients(&self, limit: usize) -> Vec<TopEntry<IpAddr>> {
+        self.clients.lock().unwrap().top(limit)
+    }
+
+    // Returns the limit most queried names in the window, most queried first
+    pub fn top_qnames(&self, limit: usize) -> Vec<TopEntry<String>> {
+        self.qnames.lock().unwrap().top(limit)
+    }
+}
@@ -0,0 +1,376 @@
+use crate::client;
+use std::{
+    fmt,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    pin::Pin,
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+use trust_dns_server::{
+    client::rr::{Name, RData, Record, RecordType},
+    proto::op::{Message, ResponseCode},
+};
+
+// The keyword of the trace zone, whose names are trace.<name>.<domain>.
+pub const ZONE: &str = "trace";
+
+// The IPv4 addresses of the root servers, a to m, which a trace starts from unless given others.
+pub const ROOT_SERVERS: [Ipv4Addr; 13] = [
+    Ipv4Addr::new(198, 41, 0, 4),
+    Ipv4Addr::new(170, 247, 170, 2),
+    Ipv4Addr::new(192, 33, 4, 12),
+    Ipv4Addr::new(199, 7, 91, 13),
+    Ipv4Addr::new(192, 203, 230, 10),
+    Ipv4Addr::new(192, 5, 5, 241),
+    Ipv4Addr::new(192, 112, 36, 4),
+    Ipv4Addr::new(198, 97, 190, 53),
+    Ipv4Addr::new(192, 36, 148, 17),
+    Ipv4Addr::new(192, 58, 128, 30),
+    Ipv4Addr::new(193, 0, 14, 129),
+    Ipv4Addr::new(199, 7, 83, 42),
+    Ipv4Addr::new(202, 12, 27, 33),
+];
+
+// How long each server is given to answer.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+// How long a whole trace may take before it gives up, short of the time resolvers wait for the answer.
+const TRACE_BUDGET: Duration = Duration::from_secs(8);
+
+// The most queries a trace sends, which also stops referral loops.
+const MAX_QUERIES: usize = 20;
+
+// The most servers of a zone asked before the zone is given up on.
+const MAX_TRIES: usize = 3;
+
+// The most CNAMEs a trace follows.
+const MAX_CNAMES: usize = 8;
+
+// How deep the lookups of name servers without glue may nest.
+const MAX_DEPTH: u8 = 2;
+
+// The most traces run at once, since each sends queries across the Internet.
+const MAX_CONCURRENT_TRACES: usize = 16;
+
+// A walk from the roots, boxed since lookups of name servers without glue walk again from within it.
+type Walk<'a> = Pin<Box<dyn Future<Output = Result<Vec<Record>, &'static str>> + Send + 'a>>;
+
+// The longest a TXT character-string may be.
+const MAX_TXT_LENGTH: usize = 255;
+
+/*
+Description:
+one query of a trace: the zone whose server was asked, the server, how long it took to answer and what it answered.
+*/
+#[derive(Clone, Debug)]
+pub struct Hop {
+    // The zone the server was asked as a server of, "." for the root servers
+    pub zone: Name,
+
+    // The name being looked up, which changes when a CNAME is followed
+    pub name: Name,
+
+    // The server's name, if it was learnt from an NS record
+    pub server_name: Option<Name>,
+
+    // The server's address
+    pub server: SocketAddr,
+
+    // How long the server took to answer
+    pub elapsed: Duration,
+
+    // What the server answered, or why it didn't
+    pub outcome: Outcome,
+}
+
+/*
+Description:
+what a server answered during a trace.
+*/
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    // The answer, with the records of the name
+    Answer(ResponseCode, Vec<Record>),
+
+    // A referral to the name servers of a zone closer to the name
+    Referral(Name, usize),
+
+    // An answer that is neither, such as NXDOMAIN, an empty answer or an error code
+    Final(ResponseCode, &'static str),
+
+    // No answer, and why
+    Failed(String),
+}
+
+impl fmt::Display for Hop {
+    // Formats the hop as a line of a TXT answer, e.g. "com. a.gtld-servers.net. 192.5.6.30 21ms NOERROR: referral to example.com. (2 servers)"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let server_name = self.server_name.as_ref().map_or(String::new(), |name| format!("{name} "));
+        write!(f, "{} {server_name}{} {}ms ", self.zone, self.server.ip(), self.elapsed.as_millis())?;
+        match &self.outcome {
+            Outcome::Answer(code, records) => {
+                let shown: Vec<String> = records.iter().take(3).map(Record::to_string).collect();
+                let more = match records.len().saturating_sub(shown.len()) {
+                    0 => String::new(),
+                    more => format!(" (+{more} more)"),
+                };
+                write!(f, "{}: {}{more}", rcode_name(*code), shown.join(", "))
+            }
+            Outcome::Referral(zone, servers) => write!(f, "NOERROR: referral to {zone} ({servers} servers)"),
+            Outcome::Final(code, what) => write!(f, "{}: {what}", rcode_name(*code)),
+            Outcome::Failed(error) => write!(f, "no answer: {error}"),
+        }
+    }
+}
+
+/*
+Description:
+a whole trace: the queries it sent and how long it took.
+*/
+#[derive(Clone, Debug)]
+pub struct Trace {
+    // The queries, in the order they were sent
+    pub hops: Vec<Hop>,
+
+    // How long the trace took
+    pub elapsed: Duration,
+
+    // Why the trace stopped short of an answer, if it did
+    pub stopped: Option<&'static str>,
+}
+
+impl Trace {
+    // Returns the trace as the strings of a TXT answer: one per query, after a summary, each cut to the 255 characters a string can hold
+    pub fn lines(&self, name: &Name, record_type: RecordType) -> Vec<String> {
+        let mut lines = vec![format!("trace of {name} {record_type}: {} queries in {}ms", self.hops.len(), self.elapsed.as_millis())];
+        lines.extend(self.hops.iter().enumerate().map(|(i, hop)| format!("{} {hop}", i + 1)));
+        lines.extend(self.stopped.map(|reason| format!("stopped: {reason}")));
+        lines.into_iter().map(|line| truncate(line, MAX_TXT_LENGTH)).collect()
+    }
+}
+
+// Returns a response code's name as dig writes it, e.g. NOERROR or NXDOMAIN
+fn rcode_name(code: ResponseCode) -> String {
+    format!("{code:?}").to_uppercase()
+}
+
+// Cuts a line to a length, on a character boundary
+fn truncate(mut line: String, length: usize) -> String {
+    if line.len() > length {
+        let mut end = length;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        line.truncate(end);
+    }
+    line
+}
+
+/*
+Description:
+looks names up the way an iterative resolver does, starting at the root servers and following referrals down to the servers of the name, and keeps every query it sent, which is what dig +trace shows. Name servers without glue are looked up the same way without being traced, and CNAMEs are followed from the root again. The tracer runs only a few traces at once, since each sends queries across the Internet.
+*/
+#[derive(Debug)]
+pub struct Tracer {
+    // The servers a trace starts from
+    roots: Vec<SocketAddr>,
+
+    // The traces that may still run at once
+    running: Semaphore,
+}
+
+impl Tracer {
+    /*
+    Description:
+    creates a tracer.
+
+    Parameters:
+    roots: the servers a trace starts from, the root servers if empty.
+
+    Returns:
+    A new Tracer.
+    */
+    pub fn new(roots: Vec<SocketAddr>) -> Self {
+        let roots = match roots.is_empty() {
+            true => ROOT_SERVERS.iter().map(|address| SocketAddr::new(IpAddr::V4(*address), 53)).collect(),
+            false => roots,
+        };
+        Tracer { roots, running: Semaphore::new(MAX_CONCURRENT_TRACES) }
+    }
+
+    /*
+    Description:
+    traces the lookup of a name.
+
+    Parameters:
+    name: the name to look up.
+    record_type: the type of records to look up.
+
+    Returns:
+    Some(Trace), or None if too many traces are running already.
+    */
+    pub async fn trace(&self, name: &Name, record_type: RecordType) -> Option<Trace> {
+        let _running = self.running.try_acquire().ok()?;
+        let started = Instant::now();
+        let mut hops = Vec::new();
+        let stopped = self.walk(name.clone(), record_type, 0, started, &mut hops).await.err();
+        Some(Trace { hops, elapsed: started.elapsed(), stopped })
+    }
+
+    // Follows the referrals from the roots to the servers of a name, adding each query to the hops, and returns the records answered or why it stopped
+    fn walk<'a>(
+        &'a self,
+        mut name: Name,
+        record_type: RecordType,
+        depth: u8,
+        started: Instant,
+        hops: &'a mut Vec<Hop>,
+    ) -> Walk<'a> {
+        Box::pin(async move {
+            let mut zone = Name::root();
+            let mut servers: Vec<(Option<Name>, Option<SocketAddr>)> = self.roots.iter().map(|address| (None, Some(*address))).collect();
+            let mut cnames = 0;
+            loop {
+                // Ask the zone's servers in turn until one answers, looking up the addresses of those without glue
+                let mut answer = None;
+                for (server_name, address) in servers.iter().take(MAX_TRIES) {
+                    if hops.len() >= MAX_QUERIES {
+                        return Err("too many queries");
+                    }
+                    if started.elapsed() >= TRACE_BUDGET {
+                        return Err("out of time");
+                    }
+                    let address = match (address, server_name) {
+                        (Some(address), _) => *address,
+                        (None, Some(server_name)) if depth < MAX_DEPTH => {
+                            let mut quiet = Vec::new();
+                            let records = self.walk(server_name.clone(), RecordType::A, depth + 1, started, &mut quiet).await;
+                            match records.ok().and_then(|records| first_address(&records)) {
+                                Some(address) => SocketAddr::new(address, 53),
+                                None => continue,
+                            }
+                        }
+                        _ => continue,
+                    };
+                    let sent = Instant::now();
+                    let result = client::exchange_iterative(address, &name, record_type, QUERY_TIMEOUT).await;
+                    let elapsed = sent.elapsed();
+                    let outcome = match &result {
+                        Ok(message) => outcome(message, &name, &zone),
+                        Err(error) => Outcome::Failed(error.to_string()),
+                    };
+                    hops.push(Hop { zone: zone.clone(), name: name.clone(), server_name: server_name.clone(), server: address, elapsed, outcome });
+                    if let Ok(message) = result {
+                        answer = Some(message);
+                        break;
+                    }
+                }
+                let Some(message) = answer else {
+                    return Err("no server of the zone answered");
+                };
+
+                match &hops.last().unwrap().outcome {
+                    Outcome::Answer(_, records) => {
+                        // Follow a CNAME from the root unless it is what was asked for or its target was answered too
+                        let target = cname_target(records, &name, record_type);
+                        match target {
+                            Some(target) if cnames < MAX_CNAMES => {
+                                cnames += 1;
+                                name = target;
+                                zone = Name::root();
+                                servers = self.roots.iter().map(|address| (None, Some(*address))).collect();
+                            }
+                            Some(_) => return Err("too many CNAMEs"),
+                            None => return Ok(records.clone()),
+                        }
+                    }
+                    Outcome::Referral(child, _) => {
+                        zone = child.clone();
+                        servers = referral_servers(&message, child);
+                    }
+                    Outcome::Final(_, what) => return Err(*what),
+                    Outcome::Failed(_) => unreachable!("failed queries are not answers"),
+                }
+            }
+        })
+    }
+}
+
+// Makes out what a server answered a name with, given the zone it was asked as a server of
+fn outcome(message: &Message, name: &Name, zone: &Name) -> Outcome {
+    let code = message.response_code();
+    if code != ResponseCode::NoError {
+        let what = if code == ResponseCode::NXDomain { "the name does not exist" } else { "the server failed" };
+        return Outcome::Final(code, what);
+    }
+    if !message.answers().is_empty() {
+        return Outcome::Answer(code, message.answers().to_vec());
+    }
+
+    // A referral delegates a zone below the one asked, which the name is in
+    let child = message
+        .name_servers()
+        .iter()
+        .filter(|record| record.record_type() == RecordType::NS)
+        .map(Record::name)
+        .find(|child| child.zone_of(name) && child.num_labels() > zone.num_labels());
+    match child {
+        Some(child) => {
+            let servers = message.name_servers().iter().filter(|record| record.record_type() == RecordType::NS && record.name() == child).count();
+            Outcome::Referral(child.clone(), servers)
+        }
+        None if message.authoritative() => Outcome::Final(code, "the name has no records of the type"),
+        None => Outcome::Final(code, "the server neither answered nor referred (lame delegation)"),
+    }
+}
+
+// Returns the name servers of a referral, with the addresses given as glue
+fn referral_servers(message: &Message, child: &Name) -> Vec<(Option<Name>, Option<SocketAddr>)> {
+    let mut servers: Vec<(Option<Name>, Option<SocketAddr>)> = message
+        .name_servers()
+        .iter()
+        .filter(|record| record.name() == child)
+        .filter_map(|record| match record.data() {
+            Some(RData::NS(server)) => {
+                let glue = message.additionals().iter().filter(|glue| glue.name() == server).find_map(|glue| match glue.data() {
+                    Some(RData::A(address)) => Some(SocketAddr::new(IpAddr::V4(*address), 53)),
+                    _ => None,
+                });
+                Some((Some(server.clone()), glue))
+            }
+            _ => None,
+        })
+        .collect();
+
+    // Ask the servers with glue first, since the others have to be looked up
+    servers.sort_by_key(|(_, address)| address.is_none());
+    servers
+}
+
+// Returns the name a CNAME chain in an answer ends at, if the answer doesn't also hold the records asked for there
+fn cname_target(records: &[Record], name: &Name, record_type: RecordType) -> Option<Name> {
+    if record_type == RecordType::CNAME {
+        return None;
+    }
+    let mut target = name.clone();
+    while let Some(next) = records.iter().filter(|record| record.name() == &target).find_map(|record| match record.data() {
+        Some(RData::CNAME(next)) => Some(next.clone()),
+        _ => None,
+    }) {
+        target = next;
+        if &target == name {
+            break;
+        }
+    }
+    let answered = records.iter().any(|record| record.name() == &target && record.record_type() == record_type);
+    (&target != name && !answered).then_some(target)
+}
+
+// Returns the first address in a set of records
+fn first_address(records: &[Record]) -> Option<IpAddr> {
+    records.iter().find_map(|record| match record.data() {
+        Some(RData::A(address)) => Some(IpAddr::V4(*address)),
+        _ => None,
+    })
+}
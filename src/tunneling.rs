@@ -0,0 +1,271 @@
+use crate::config::{TunnelingAction, TunnelingConfig};
+use lru::LruCache;
+use serde::Serialize;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    net::IpAddr,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use trust_dns_server::client::rr::RecordType;
+
+// The entropy (in bits per character) at or below which names score 0, about that of words.
+const LOW_ENTROPY: f64 = 2.5;
+
+// The entropy (in bits per character) at or above which names score 1, about that of hex or base32 encoded data.
+const HIGH_ENTROPY: f64 = 4.0;
+
+// The most distinct names remembered per client in a window; names beyond them count as new.
+const MAX_NAMES_PER_CLIENT: usize = 512;
+
+/*
+Description:
+how much a client's queries look like tunneling, from three signs that each run from 0 to 1: how random its names are (the average Shannon entropy of the labels in front of the domain, 0 for words and 1 for encoded data), how many of its names are new (distinct names over queries), and how many of its queries ask for TXT or NULL records, the types tunnels carry their data back in. A client using the zones as meant repeats names, which are words, so it scores low even though most zones answer TXT; a tunnel sends a new encoded name with every query.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Score {
+    // How random the names are, from 0 (words) to 1 (encoded data)
+    pub entropy: f64,
+
+    // The share of queries for names not seen before in the window
+    pub unique: f64,
+
+    // The share of queries for TXT or NULL records
+    pub txt: f64,
+}
+
+impl Score {
+    // Returns the overall score, the average of the three signs
+    pub fn total(&self) -> f64 {
+        (self.entropy + self.unique + self.txt) / 3.0
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "score {:.2} (entropy {:.2}, new names {:.2}, TXT/NULL {:.2})",
+            self.total(),
+            self.entropy,
+            self.unique,
+            self.txt
+        )
+    }
+}
+
+/*
+Description:
+what the detector makes of a query: the client isn't suspected, it has just been flagged with the given score, or it was flagged before and is still suspected.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Clear,
+    Flagged(Score),
+    Suspected,
+}
+
+/*
+Description:
+the counts of the detector reported in stats dumps.
+*/
+#[derive(Clone, Debug, Serialize)]
+pub struct TunnelingStats {
+    // The number of times a client was flagged
+    pub flagged: u64,
+
+    // The number of queries refused because their client was suspected
+    pub blocked: u64,
+
+    // The clients suspected right now with the score they were flagged with
+    pub suspected: HashMap<IpAddr, Score>,
+}
+
+/*
+Description:
+the queries of a single client in the current window.
+*/
+#[derive(Debug)]
+struct ClientWindow {
+    // When the window started
+    started: Instant,
+
+    // The number of queries in the window
+    queries: u64,
+
+    // The number of TXT and NULL queries in the window
+    txt: u64,
+
+    // The sum of the entropy scores of the queried names
+    entropy: f64,
+
+    // The hashes of the distinct names queried, bounded by MAX_NAMES_PER_CLIENT
+    names: HashSet<u64>,
+
+    // The number of queries for names not queried before in the window
+    unique: u64,
+
+    // Until when the client is suspected, and the score it was flagged with
+    suspected: Option<(Instant, Score)>,
+}
+
+impl ClientWindow {
+    fn new(now: Instant) -> Self {
+        ClientWindow { started: now, queries: 0, txt: 0, entropy: 0.0, names: HashSet::new(), unique: 0, suspected: None }
+    }
+
+    // Returns the score of the window's queries
+    fn score(&self) -> Score {
+        let queries = self.queries.max(1) as f64;
+        Score { entropy: self.entropy / queries, unique: self.unique as f64 / queries, txt: self.txt as f64 / queries }
+    }
+}
+
+/*
+Description:
+the DNS tunneling detector: scores each client over a window of its queries and flags those scoring above the threshold, which are then logged or refused for a while. Clients are kept in a bounded map that forgets the least recently seen, so a flood of spoofed addresses can't grow it.
+*/
+#[derive(Debug)]
+pub struct TunnelDetector {
+    // The [tunneling] section
+    config: TunnelingConfig,
+
+    // The windows of the clients seen lately
+    clients: Mutex<LruCache<IpAddr, ClientWindow>>,
+
+    // The number of times a client was flagged
+    flagged: AtomicU64,
+
+    // The number of queries refused because their client was suspected
+    blocked: AtomicU64,
+}
+
+impl TunnelDetector {
+    // Creates a detector with the settings of the [tunneling] section
+    pub fn new(config: &TunnelingConfig) -> Self {
+        TunnelDetector {
+            config: config.clone(),
+            clients: Mutex::new(LruCache::new(NonZeroUsize::new(config.max_clients.max(1)).unwrap())),
+            flagged: AtomicU64::new(0),
+            blocked: AtomicU64::new(0),
+        }
+    }
+
+    // Returns whether suspected clients are refused rather than only logged
+    pub fn blocks(&self) -> bool {
+        self.config.action == TunnelingAction::Block
+    }
+
+    /*
+    Description:
+    scores a query, adding it to its client's window, and says whether the client is suspected of tunneling. A client is only scored once it has sent min_queries queries in the window, and stays suspected for block_for seconds once flagged.
+
+    Parameters:
+    client: the client's address.
+    subdomain: the labels of the query name in front of the domain, or the whole name if it is outside it.
+    query_type: the type of records asked for.
+    now: the time of the query.
+
+    Returns:
+    The Verdict on the client, Clear for exempt clients.
+    */
+    pub fn observe(&self, client: IpAddr, subdomain: &str, query_type: RecordType, now: Instant) -> Verdict {
+        if self.config.exempt.iter().any(|network| network.contains(client)) {
+            return Verdict::Clear;
+        }
+        let subdomain = subdomain.to_lowercase();
+        let mut clients = self.clients.lock().unwrap();
+        let window = clients.get_or_insert_mut(client, || ClientWindow::new(now));
+
+        // Start the counts over once the window is over, keeping any suspicion that hasn't lapsed
+        if now.duration_since(window.started) >= Duration::from_secs(self.config.window) {
+            let suspected = window.suspected.filter(|(until, _)| *until > now);
+            *window = ClientWindow { suspected, ..ClientWindow::new(now) };
+        }
+
+        window.queries += 1;
+        if matches!(query_type, RecordType::TXT | RecordType::NULL) {
+            window.txt += 1;
+        }
+        window.entropy += entropy_score(entropy(&subdomain));
+        let mut hasher = DefaultHasher::new();
+        subdomain.hash(&mut hasher);
+        let hash = hasher.finish();
+        if !window.names.contains(&hash) {
+            window.unique += 1;
+            if window.names.len() < MAX_NAMES_PER_CLIENT {
+                window.names.insert(hash);
+            }
+        }
+
+        let verdict = match window.suspected {
+            Some((until, _)) if until > now => Verdict::Suspected,
+            _ if window.queries < self.config.min_queries => Verdict::Clear,
+            _ => {
+                let score = window.score();
+                match score.total() > self.config.score_above {
+                    true => {
+                        window.suspected = Some((now + Duration::from_secs(self.config.block_for), score));
+                        self.flagged.fetch_add(1, Ordering::Relaxed);
+                        Verdict::Flagged(score)
+                    }
+                    false => Verdict::Clear,
+                }
+            }
+        };
+        if verdict != Verdict::Clear && self.blocks() {
+            self.blocked.fetch_add(1, Ordering::Relaxed);
+        }
+        verdict
+    }
+
+    // Returns the detector's counts and the clients suspected right now
+    pub fn stats(&self) -> TunnelingStats {
+        let now = Instant::now();
+        let clients = self.clients.lock().unwrap();
+        TunnelingStats {
+            flagged: self.flagged.load(Ordering::Relaxed),
+            blocked: self.blocked.load(Ordering::Relaxed),
+            suspected: clients
+                .iter()
+                .filter_map(|(client, window)| window.suspected.filter(|(until, _)| *until > now).map(|(_, score)| (*client, score)))
+                .collect(),
+        }
+    }
+}
+
+/*
+Description:
+computes the Shannon entropy of a text.
+
+Parameters:
+text: the text, such as the labels of a name.
+
+Returns:
+The entropy in bits per character, 0 for an empty text.
+*/
+pub fn entropy(text: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in text.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let length = counts.values().sum::<usize>() as f64;
+    counts
+        .values()
+        .map(|count| {
+            let p = *count as f64 / length;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// Maps an entropy onto a score from 0, for words, to 1, for encoded data
+fn entropy_score(bits: f64) -> f64 {
+    ((bits - LOW_ENTROPY) / (HIGH_ENTROPY - LOW_ENTROPY)).clamp(0.0, 1.0)
+}
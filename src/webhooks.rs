@@ -0,0 +1,302 @@
+use crate::{
+    blocklist,
+    config::{WebhookConfig, WebhookEvent},
+    tasks,
+};
+use chrono::Utc;
+use ring::hmac;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tracing::*;
+use trust_dns_server::proto::op::ResponseCode;
+
+// How long to wait for a webhook to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How long to wait before the first retry of a failed batch; each further retry waits twice as long.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// The longest wait between two retries.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+// The header carrying the signature of a batch, if the webhook has a secret.
+pub const SIGNATURE_HEADER: &str = "X-Rusty-DNS-Signature";
+
+/*
+Description:
+an event sent to a webhook. Query and blocked events describe the query, with the client address and name as the query log would record them; NXDOMAIN spike events give the number of NXDOMAIN answers in the batch interval.
+*/
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    // What happened
+    pub event: WebhookEvent,
+
+    // When it happened, in milliseconds since the Unix epoch
+    pub timestamp: i64,
+
+    // The client's address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+
+    // The name queried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qname: Option<String>,
+
+    // The type queried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qtype: Option<String>,
+
+    // The response code of the answer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rcode: Option<String>,
+
+    // The number of NXDOMAIN answers, for NXDOMAIN spikes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+}
+
+impl Event {
+    // Returns an event describing a query, timestamped now
+    pub fn query(client: String, qname: String, qtype: String, rcode: String) -> Self {
+        Event {
+            event: WebhookEvent::Query,
+            timestamp: Utc::now().timestamp_millis(),
+            client: Some(client),
+            qname: Some(qname),
+            qtype: Some(qtype),
+            rcode: Some(rcode),
+            count: None,
+        }
+    }
+}
+
+// A webhook with the events waiting to be sent to it
+#[derive(Debug)]
+struct Hook {
+    // The webhook's [[webhooks]] entry
+    config: WebhookConfig,
+
+    // The events not sent yet, oldest first
+    pending: Mutex<VecDeque<Event>>,
+
+    // The events dropped because the queue was full, since the last batch
+    dropped: AtomicU64,
+
+    // The NXDOMAIN answers since the last batch
+    nxdomains: AtomicU64,
+}
+
+impl Hook {
+    // Whether the webhook is sent an event about a query for a name
+    fn wants(&self, kind: WebhookEvent, name: &str) -> bool {
+        self.config.events.contains(&kind)
+            && (kind != WebhookEvent::Query || self.config.watch.iter().any(|pattern| blocklist::allows(pattern, name)))
+    }
+
+    // Queues an event, dropping it if the queue is full
+    fn push(&self, event: Event) {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= self.config.queue_size {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        } else {
+            pending.push_back(event);
+        }
+    }
+}
+
+/*
+Description:
+the webhooks of the configuration file. Events are queued per webhook as queries are answered, and a task per webhook POSTs them in batches every batch interval, so a burst of queries costs a single request; a batch that fails is retried with exponential backoff while new events keep queueing.
+*/
+#[derive(Debug)]
+pub struct Webhooks {
+    // The webhooks, in the order of the configuration file
+    hooks: Vec<Arc<Hook>>,
+}
+
+impl Webhooks {
+    /*
+    Description:
+    creates the webhooks without starting their delivery, so events only queue up.
+
+    Parameters:
+    configs: the [[webhooks]] entries of the configuration file.
+
+    Returns:
+    The new Webhooks.
+    */
+    pub fn new(configs: &[WebhookConfig]) -> Self {
+        let hooks = configs
+            .iter()
+            .map(|config| {
+                Arc::new(Hook {
+                    config: config.clone(),
+                    pending: Mutex::new(VecDeque::new()),
+                    dropped: AtomicU64::new(0),
+                    nxdomains: AtomicU64::new(0),
+                })
+            })
+            .collect();
+        Webhooks { hooks }
+    }
+
+    /*
+    Description:
+    creates the webhooks and spawns a delivery task for each. Must be called from within the tokio runtime.
+
+    Parameters:
+    configs: the [[webhooks]] entries of the configuration file.
+    source: the name the server reports itself as in the batches, e.g. the served domain.
+
+    Returns:
+    An Arc holding the new Webhooks, shared with the delivery tasks.
+    */
+    pub fn spawn(configs: &[WebhookConfig], source: String) -> Arc<Self> {
+        let webhooks = Arc::new(Webhooks::new(configs));
+        let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build().unwrap_or_else(|_| reqwest::Client::new());
+        for hook in &webhooks.hooks {
+            tasks::spawn("webhook", deliver(client.clone(), hook.clone(), source.clone()));
+        }
+        webhooks
+    }
+
+    /*
+    Description:
+    queues an event for the webhooks that want it: those sent events of its kind and, for query events, watching the name queried. The event is only built if a webhook wants it.
+
+    Parameters:
+    kind: the kind of event.
+    name: the name queried, lower-case and without the trailing dot, matched against the watched names.
+    describe: builds the event; its kind is set to the given one.
+
+    Returns:
+    None
+    */
+    pub fn notify(&self, kind: WebhookEvent, name: &str, describe: impl FnOnce() -> Event) {
+        let hooks: Vec<&Arc<Hook>> = self.hooks.iter().filter(|hook| hook.wants(kind, name)).collect();
+        if hooks.is_empty() {
+            return;
+        }
+        let event = Event { event: kind, ..describe() };
+        for hook in hooks {
+            hook.push(event.clone());
+        }
+    }
+
+    // Counts an answer towards the NXDOMAIN spikes of the webhooks that watch for them
+    pub fn answered(&self, rcode: ResponseCode) {
+        if rcode == ResponseCode::NXDomain {
+            for hook in &self.hooks {
+                hook.nxdomains.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /*
+    Description:
+    returns the events waiting to be sent to a webhook, oldest first.
+
+    Parameters:
+    index: the webhook's position in the configuration file.
+
+    Returns:
+    The pending events, or none if there is no such webhook.
+    */
+    pub fn pending(&self, index: usize) -> Vec<Event> {
+        self.hooks.get(index).map_or_else(Vec::new, |hook| hook.pending.lock().unwrap().iter().cloned().collect())
+    }
+}
+
+/*
+Description:
+signs the body of a batch with a webhook's secret, as the receiver checks it: HMAC-SHA256 of the body, in hex, prefixed with "sha256=".
+
+Parameters:
+secret: the webhook's secret.
+body: the body of the batch.
+
+Returns:
+The value of the signature header.
+*/
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let signature: String = hmac::sign(&key, body).as_ref().iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("sha256={signature}")
+}
+
+// Sends a webhook its pending events every batch interval, adding an NXDOMAIN spike event when the threshold is first crossed
+async fn deliver(client: reqwest::Client, hook: Arc<Hook>, source: String) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(hook.config.batch_interval.max(1)));
+    ticker.tick().await;
+    let mut spiking = false;
+
+    loop {
+        ticker.tick().await;
+
+        // Note a spike when the NXDOMAIN answers first cross the threshold, and again only once they have fallen back below it
+        let nxdomains = hook.nxdomains.swap(0, Ordering::Relaxed);
+        if let Some(limit) = hook.config.nxdomain_spike_above.filter(|_| hook.config.events.contains(&WebhookEvent::NxdomainSpike)) {
+            let crossed = nxdomains > limit;
+            if crossed && !spiking {
+                hook.push(Event {
+                    event: WebhookEvent::NxdomainSpike,
+                    timestamp: Utc::now().timestamp_millis(),
+                    client: None,
+                    qname: None,
+                    qtype: None,
+                    rcode: None,
+                    count: Some(nxdomains),
+                });
+            }
+            spiking = crossed;
+        }
+
+        // Take the next batch
+        let events: Vec<Event> = {
+            let mut pending = hook.pending.lock().unwrap();
+            let count = pending.len().min(hook.config.batch_size.max(1));
+            pending.drain(..count).collect()
+        };
+        let dropped = hook.dropped.swap(0, Ordering::Relaxed);
+        if events.is_empty() && dropped == 0 {
+            continue;
+        }
+        if dropped > 0 {
+            warn!("Dropped {dropped} events for the webhook {}, which is not keeping up", hook.config.url);
+        }
+
+        let body = json!({ "source": source, "events": events, "dropped": dropped }).to_string();
+        send(&client, &hook.config, body, events.len()).await;
+    }
+}
+
+// POSTs a batch to a webhook, retrying with exponential backoff until it succeeds or the retries run out
+async fn send(client: &reqwest::Client, config: &WebhookConfig, body: String, count: usize) {
+    let mut delay = FIRST_RETRY_DELAY;
+    for attempt in 0..=config.retries {
+        let mut request = client.post(&config.url).header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = &config.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, body.as_bytes()));
+        }
+        match request.body(body.clone()).send().await.and_then(|response| response.error_for_status()) {
+            Ok(_) => {
+                debug!("Sent {count} events to the webhook {}", config.url);
+                return;
+            }
+            Err(error) if attempt < config.retries => {
+                debug!("Failed to send {count} events to the webhook {}, retrying in {delay:?}: {error}", config.url);
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+            Err(error) => warn!("Failed to send {count} events to the webhook {}, dropping them: {error}", config.url),
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use crate::options::Options;
+use base64::Engine;
+use std::{
+    collections::HashMap,
+    io,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tracing::*;
+
+// The keyword of the zone WireGuard peers are served in, as <peer>.wg.<domain>.
+pub const ZONE: &str = "wg";
+
+// The TTL of the wg zone's records.
+pub const TTL: u32 = 60;
+
+/*
+Description:
+a peer of a WireGuard interface, as its configuration or the kernel lists it.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Peer {
+    // The peer's name below the zone, from a comment of its configuration or else from its public key
+    pub name: String,
+
+    // The peer's public key, in base64
+    pub public_key: String,
+
+    // The peer's own addresses: those of its allowed IPs that are single hosts (/32 or /128)
+    pub addresses: Vec<IpAddr>,
+}
+
+/*
+Description:
+where the wg zone reads its peers from: a WireGuard configuration file, the kernel's interface (through the wg tool), or both, in which case the kernel gives the peers and their allowed IPs and the file gives their names.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Source {
+    // The configuration file, in the format of wg-quick and wg setconf
+    pub config: Option<PathBuf>,
+
+    // The name of the interface, e.g. wg0
+    pub interface: Option<String>,
+}
+
+impl Source {
+    // Returns the source the options choose, if any
+    pub fn from_options(options: &Options) -> Option<Self> {
+        (options.wireguard_config.is_some() || options.wireguard_interface.is_some()).then(|| Source {
+            config: options.wireguard_config.clone(),
+            interface: options.wireguard_interface.clone(),
+        })
+    }
+
+    /*
+    Description:
+    reads the peers from the source.
+
+    Returns:
+    Ok(Vec) with the peers, or an io::Error if the configuration file can't be read or the wg tool fails.
+    */
+    pub fn peers(&self) -> io::Result<Vec<Peer>> {
+        let configured = match &self.config {
+            Some(path) => parse_config(&std::fs::read_to_string(path).map_err(|error| io::Error::new(error.kind(), format!("{}: {error}", path.display())))?),
+            None => Vec::new(),
+        };
+        let Some(interface) = &self.interface else {
+            return Ok(configured);
+        };
+
+        let output = std::process::Command::new("wg").args(["show", interface, "allowed-ips"]).output()?;
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("wg show {interface} failed: {message}")));
+        }
+        let names: HashMap<&str, &str> = configured.iter().map(|peer| (peer.public_key.as_str(), peer.name.as_str())).collect();
+        let mut peers = parse_allowed_ips(&String::from_utf8_lossy(&output.stdout));
+        for peer in &mut peers {
+            if let Some(name) = names.get(peer.public_key.as_str()) {
+                peer.name = name.to_string();
+            }
+        }
+        Ok(peers)
+    }
+}
+
+/*
+Description:
+the wg zone: the peers of a WireGuard interface, served as <peer>.wg.<domain> with one A or AAAA record per single-host allowed IP, and read again periodically so added and removed peers are picked up.
+*/
+#[derive(Debug, Default)]
+pub struct WireGuard {
+    // The addresses of each peer, by its name below the zone
+    peers: RwLock<HashMap<String, Vec<IpAddr>>>,
+}
+
+impl WireGuard {
+    // Returns the addresses of a peer, or none if there is no such peer
+    pub fn addresses(&self, name: &str) -> Vec<IpAddr> {
+        let peers = self.peers.read().unwrap();
+        peers.get(&name.to_ascii_lowercase()).cloned().unwrap_or_default()
+    }
+
+    // Returns the number of peers served
+    pub fn names(&self) -> usize {
+        self.peers.read().unwrap().len()
+    }
+
+    // Replaces the peers served; peers with the same name have their addresses merged
+    pub fn replace(&self, peers: Vec<Peer>) {
+        let mut named: HashMap<String, Vec<IpAddr>> = HashMap::new();
+        for peer in peers {
+            let addresses = named.entry(peer.name).or_default();
+            for address in peer.addresses {
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+        }
+        *self.peers.write().unwrap() = named;
+    }
+}
+
+/*
+Description:
+reads the peers of a WireGuard configuration file. A peer is named by a "# Name = <name>" comment in its [Peer] section, as several configuration generators write, or by a comment on the line just before the section; peers without either are named after their public key. Names are lower-cased, and characters that can't be in a DNS label become hyphens.
+
+Parameters:
+text: the configuration.
+
+Returns:
+The peers in the order of the file.
+*/
+pub fn parse_config(text: &str) -> Vec<Peer> {
+    let mut peers = Vec::new();
+    let mut current: Option<(Option<String>, Option<String>, Vec<IpAddr>)> = None;
+    let mut last_comment: Option<String> = None;
+
+    // Adds the peer whose section just ended, if it had a public key
+    let finish = |current: Option<(Option<String>, Option<String>, Vec<IpAddr>)>, peers: &mut Vec<Peer>| {
+        if let Some((name, Some(public_key), addresses)) = current {
+            let name = name.map_or_else(|| key_name(&public_key), |name| label(&name));
+            peers.push(Peer { name, public_key, addresses });
+        }
+    };
+
+    for line in text.lines().map(str::trim) {
+        if let Some(comment) = line.strip_prefix('#') {
+            let comment = comment.trim();
+            let named = comment.split_once('=').filter(|(key, _)| key.trim().eq_ignore_ascii_case("name"));
+            match (&mut current, named) {
+                (Some((name, _, _)), Some((_, value))) => *name = Some(value.trim().to_string()),
+                _ => last_comment = Some(comment.to_string()).filter(|comment| !comment.is_empty()),
+            }
+            continue;
+        }
+        if line.starts_with('[') {
+            finish(current.take(), &mut peers);
+            if line.eq_ignore_ascii_case("[peer]") {
+                current = Some((last_comment.take(), None, Vec::new()));
+            }
+            last_comment = None;
+            continue;
+        }
+        last_comment = None;
+
+        let (Some((key, value)), Some((_, public_key, addresses))) = (line.split_once('='), &mut current) else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            // A base64 key ends with '=', so only the first '=' separates the key from the value
+            "publickey" => *public_key = Some(value.trim().to_string()),
+            "allowedips" => addresses.extend(value.split(',').filter_map(host_address)),
+            _ => {}
+        }
+    }
+    finish(current, &mut peers);
+    peers
+}
+
+/*
+Description:
+reads the output of "wg show <interface> allowed-ips": a line per peer with its public key and its allowed IPs separated by spaces, or "(none)". Peers are named after their public keys.
+
+Parameters:
+output: the output of the command.
+
+Returns:
+The peers in the order of the output.
+*/
+pub fn parse_allowed_ips(output: &str) -> Vec<Peer> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let public_key = fields.next()?.to_string();
+            let addresses = fields.filter_map(host_address).collect();
+            Some(Peer { name: key_name(&public_key), public_key, addresses })
+        })
+        .collect()
+}
+
+// Returns the address of an allowed IP that is a single host, such as 10.0.0.2/32 or fd00::2/128
+fn host_address(allowed: &str) -> Option<IpAddr> {
+    let (address, prefix) = allowed.trim().split_once('/')?;
+    let address: IpAddr = address.parse().ok()?;
+    let host_prefix = if address.is_ipv4() { "32" } else { "128" };
+    (prefix == host_prefix).then_some(address)
+}
+
+// Returns the name of a peer without one: the first five bytes of its public key, in hex
+fn key_name(public_key: &str) -> String {
+    match base64::engine::general_purpose::STANDARD.decode(public_key) {
+        Ok(key) if key.len() >= 5 => key[..5].iter().map(|byte| format!("{byte:02x}")).collect(),
+        _ => label(public_key),
+    }
+}
+
+// Turns a name into a DNS label, lower-casing it and replacing anything but letters, digits and hyphens with hyphens
+fn label(name: &str) -> String {
+    let label: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c.to_ascii_lowercase() } else { '-' })
+        .take(63)
+        .collect();
+    label.trim_matches('-').to_string()
+}
+
+/*
+Description:
+keeps the wg zone up to date, reading the peers from the source every interval. If they can't be read, the last peers read are served until they can.
+
+Parameters:
+wireguard: the zone to keep up to date.
+source: where to read the peers from.
+interval: how often to read them.
+
+Returns:
+None; runs until the process exits.
+*/
+pub async fn refresh(wireguard: Arc<WireGuard>, source: Source, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        let reading = source.clone();
+        match tokio::task::spawn_blocking(move || reading.peers()).await {
+            Ok(Ok(peers)) => {
+                wireguard.replace(peers);
+                debug!("Serving {} WireGuard peers", wireguard.names());
+            }
+            Ok(Err(error)) => warn!("Failed to read the WireGuard peers, serving the last ones read: {error}"),
+            Err(error) => warn!("Failed to read the WireGuard peers: {error}"),
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+use trust_dns_server::client::rr::{Name, RecordType};
+
+/*
+Description:
+defines the errors that can occur while reading a zone file. Reading stops at the first syntax error, since the lines after it can't be trusted to be read the way their author meant.
+*/
+#[derive(thiserror::Error, Debug)]
+pub enum ZoneFileError {
+    #[error("line {0}: {1}")]
+    Syntax(usize, String),
+}
+
+/*
+Description:
+a record read from a zone file in BIND's master file format (RFC 1035 section 5), with the inherited owner, TTL and class filled in and the names in its data made absolute, so records can be compared with each other.
+*/
+#[derive(Clone, Debug)]
+pub struct Record {
+    // The line the record starts on
+    pub line: usize,
+
+    // The owner name, absolute
+    pub name: Name,
+
+    // The TTL in seconds
+    pub ttl: u32,
+
+    // The class, upper-case (e.g. "IN")
+    pub class: String,
+
+    // The record type
+    pub record_type: RecordType,
+
+    // The fields of the record's data, with names made absolute and lower-case
+    pub rdata: Vec<String>,
+}
+
+/*
+Description:
+how serious a problem found by lint is: errors make the server refuse or misserve the zone, warnings are legal but almost certainly mistakes.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/*
+Description:
+a problem found by lint.
+*/
+#[derive(Clone, Debug)]
+pub struct Problem {
+    // The line of the offending record, or None for a problem with the zone as a whole (e.g. a missing SOA)
+    pub line: Option<usize>,
+
+    // How serious the problem is
+    pub severity: Severity,
+
+    // What is wrong
+    pub message: String,
+}
+
+// The tokens of one entry of a zone file, which may span several lines inside parentheses.
+struct Entry {
+    // The line the entry starts on
+    line: usize,
+
+    // Whether the entry starts with blank space, so the record has the previous record's owner
+    inherits_owner: bool,
+
+    // The entry's tokens; quoted strings keep their quotes
+    tokens: Vec<String>,
+}
+
+/*
+Description:
+reads a zone file. $ORIGIN and $TTL are supported; $INCLUDE is not, since a lint of one file shouldn't read others. A record without a TTL takes the $TTL, or else the TTL of the record before it. The data of common record types (A, AAAA, CNAME, NS, PTR, MX, SRV, SOA) is checked, and that of other types taken as it is.
+
+Parameters:
+text: the contents of the zone file.
+origin: the zone's origin, which relative names and "@" are relative to until an $ORIGIN changes it.
+
+Returns:
+Ok(Vec<Record>) with the records in the order of the file, or a ZoneFileError at the first syntax error.
+*/
+pub fn parse(text: &str, origin: &Name) -> Result<Vec<Record>, ZoneFileError> {
+    let mut origin = origin.clone();
+    let (mut default_ttl, mut last_ttl, mut last_owner) = (None, None, None);
+    let mut records = Vec::new();
+
+    for entry in entries(text)? {
+        let error = |message: String| ZoneFileError::Syntax(entry.line, message);
+        let mut tokens = entry.tokens.iter().map(String::as_str);
+
+        // Directives
+        if !entry.inherits_owner && entry.tokens[0].starts_with('$') {
+            let directive = tokens.next().unwrap_or_default().to_ascii_uppercase();
+            let argument = tokens.next().ok_or_else(|| error(format!("{directive} needs an argument")))?;
+            match directive.as_str() {
+                "$ORIGIN" => origin = parse_name(argument, &origin).map_err(error)?,
+                "$TTL" => default_ttl = Some(parse_ttl(argument).ok_or_else(|| error(format!("invalid TTL {argument}")))?),
+                "$INCLUDE" => return Err(error("$INCLUDE is not supported".to_string())),
+                _ => return Err(error(format!("unknown directive {directive}"))),
+            }
+            continue;
+        }
+
+        // The owner, then the TTL and class in either order, then the type
+        let name = match entry.inherits_owner {
+            true => last_owner.clone().ok_or_else(|| error("the first record has no owner".to_string()))?,
+            false => parse_name(tokens.next().unwrap_or_default(), &origin).map_err(error)?,
+        };
+        let (mut ttl, mut class) = (None, None);
+        let mut next = tokens.next();
+        for _ in 0..2 {
+            match next {
+                Some(token) if ttl.is_none() && token.starts_with(|c: char| c.is_ascii_digit()) => {
+                    ttl = Some(parse_ttl(token).ok_or_else(|| error(format!("invalid TTL {token}")))?);
+                }
+                Some(token) if class.is_none() && ["IN", "CH", "HS", "CS"].contains(&token.to_ascii_uppercase().as_str()) => {
+                    class = Some(token.to_ascii_uppercase());
+                }
+                _ => break,
+            }
+            next = tokens.next();
+        }
+        let record_type = next.ok_or_else(|| error("missing record type".to_string()))?;
+        let record_type = RecordType::from_str(&record_type.to_ascii_uppercase())
+            .map_err(|_| error(format!("unknown record type {record_type}")))?;
+
+        let ttl = ttl
+            .or(default_ttl)
+            .or(last_ttl)
+            .ok_or_else(|| error("the record has no TTL and there is no $TTL".to_string()))?;
+        let rdata = parse_rdata(record_type, tokens.map(str::to_string).collect(), &origin).map_err(error)?;
+
+        last_ttl = Some(ttl);
+        last_owner = Some(name.clone());
+        records.push(Record {
+            line: entry.line,
+            name,
+            ttl,
+            class: class.unwrap_or_else(|| "IN".to_string()),
+            record_type,
+            rdata,
+        });
+    }
+
+    Ok(records)
+}
+
+/*
+Description:
+reads the data of a single record, written as in a zone file after its type (e.g. "10 mail.example.com" for an MX record), for backends that keep each record's data as text.
+
+Parameters:
+record_type: the record's type.
+text: the record's data.
+origin: the name relative names in the data are relative to.
+
+Returns:
+Ok(Vec<String>) with the fields of the data, checked and with names made absolute as parse does, or an error message.
+*/
+pub fn parse_data(record_type: RecordType, text: &str, origin: &Name) -> Result<Vec<String>, String> {
+    let entries = entries(text).map_err(|error| error.to_string())?;
+    parse_rdata(record_type, entries.into_iter().flat_map(|entry| entry.tokens).collect(), origin)
+}
+
+/*
+Description:
+checks the records of a zone for the mistakes that make a server refuse or misserve it: a missing or misplaced SOA, no NS records at the apex, records outside the zone, and names that have a CNAME along with other data (RFC 1034 section 3.6.2) or more than one CNAME. Duplicate records and RRsets whose records have different TTLs (RFC 2181 section 5.2) are reported as warnings.
+
+Parameters:
+origin: the zone's origin.
+records: the zone's records, as read by parse.
+
+Returns:
+A Vec<Problem> sorted by line, with the problems of the zone as a whole first; empty if the zone is fine.
+*/
+pub fn lint(origin: &Name, records: &[Record]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut problem = |line: Option<usize>, severity: Severity, message: String| problems.push(Problem { line, severity, message });
+    let origin = origin.to_lowercase();
+    let at_apex = |record: &Record| record.name.to_lowercase() == origin;
+
+    // The apex must have exactly one SOA and some NS records, and nothing may lie outside the zone
+    let soas: Vec<&Record> = records.iter().filter(|record| record.record_type == RecordType::SOA).collect();
+    match soas.iter().filter(|record| at_apex(record)).count() {
+        0 => problem(None, Severity::Error, format!("there is no SOA record at the apex {origin}")),
+        1 => {}
+        _ => problem(None, Severity::Error, format!("there is more than one SOA record at the apex {origin}")),
+    }
+    for soa in soas.iter().filter(|record| !at_apex(record)) {
+        problem(Some(soa.line), Severity::Error, format!("SOA record for {}, which is not the apex", soa.name));
+    }
+    if !records.iter().any(|record| record.record_type == RecordType::NS && at_apex(record)) {
+        problem(None, Severity::Error, format!("there are no NS records at the apex {origin}"));
+    }
+    for record in records.iter().filter(|record| !origin.zone_of(&record.name.to_lowercase())) {
+        problem(Some(record.line), Severity::Error, format!("{} is outside the zone {origin}", record.name));
+    }
+
+    // A name with a CNAME may only have DNSSEC records besides it
+    let mut owners: BTreeMap<Name, Vec<&Record>> = BTreeMap::new();
+    for record in records {
+        owners.entry(record.name.to_lowercase()).or_default().push(record);
+    }
+    for (name, records) in &owners {
+        let cnames: Vec<&&Record> = records.iter().filter(|record| record.record_type == RecordType::CNAME).collect();
+        if cnames.is_empty() {
+            continue;
+        }
+        for cname in cnames.iter().skip(1) {
+            problem(Some(cname.line), Severity::Error, format!("{name} has more than one CNAME record"));
+        }
+        let others = records
+            .iter()
+            .filter(|record| !matches!(record.record_type, RecordType::CNAME | RecordType::RRSIG | RecordType::NSEC));
+        for other in others {
+            problem(
+                Some(other.line),
+                Severity::Error,
+                format!("{name} has a CNAME record (line {}) and other data ({})", cnames[0].line, other.record_type),
+            );
+        }
+    }
+
+    // Duplicates are merged by servers, and an RRset is served with a single TTL
+    let mut seen = HashMap::new();
+    let mut rrsets = HashMap::new();
+    for record in records {
+        let rrset = (record.name.to_lowercase(), record.class.clone(), record.record_type);
+        if let Some(line) = seen.insert((rrset.clone(), record.rdata.clone()), record.line) {
+            problem(Some(record.line), Severity::Warning, format!("duplicate of the record on line {line}"));
+            continue;
+        }
+        let (line, ttl) = *rrsets.entry(rrset).or_insert((record.line, record.ttl));
+        if ttl != record.ttl {
+            problem(
+                Some(record.line),
+                Severity::Warning,
+                format!("TTL {} differs from the TTL {ttl} of the same {} RRset on line {line}", record.ttl, record.record_type),
+            );
+        }
+    }
+
+    problems.sort_by_key(|problem| (problem.line, problem.severity));
+    problems
+}
+
+// Splits a zone file into entries, joining the lines inside parentheses and dropping comments and blank lines
+fn entries(text: &str) -> Result<Vec<Entry>, ZoneFileError> {
+    let mut entries = Vec::new();
+    let mut entry: Option<Entry> = None;
+    let mut depth = 0;
+
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let current = entry.get_or_insert_with(|| Entry {
+            line: number,
+            inherits_owner: line.starts_with([' ', '\t']),
+            tokens: Vec::new(),
+        });
+
+        let mut chars = line.chars();
+        let (mut token, mut quoted): (Option<String>, bool) = (None, false);
+        while let Some(c) = chars.next() {
+            match c {
+                // A backslash escapes the next character, in or out of quotes
+                '\\' => {
+                    let token = token.get_or_insert_with(String::new);
+                    token.push(c);
+                    token.extend(chars.next());
+                }
+                // Inside a quoted string everything but the closing quote is data
+                '"' if quoted => {
+                    token.get_or_insert_with(String::new).push(c);
+                    current.tokens.extend(token.take());
+                    quoted = false;
+                }
+                _ if quoted => token.get_or_insert_with(String::new).push(c),
+                '"' => {
+                    current.tokens.extend(token.take());
+                    token = Some(c.to_string());
+                    quoted = true;
+                }
+                ';' => break,
+                '(' | ')' | ' ' | '\t' => {
+                    current.tokens.extend(token.take());
+                    match c {
+                        '(' => depth += 1,
+                        ')' if depth == 0 => return Err(ZoneFileError::Syntax(number, "unbalanced ')'".to_string())),
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                }
+                _ => token.get_or_insert_with(String::new).push(c),
+            }
+        }
+        if quoted {
+            return Err(ZoneFileError::Syntax(number, "unterminated quoted string".to_string()));
+        }
+        current.tokens.extend(token);
+
+        // An entry ends with its line, unless a parenthesis is still open
+        if depth == 0 {
+            entries.extend(entry.take().filter(|entry| !entry.tokens.is_empty()));
+        }
+    }
+
+    match entry {
+        Some(entry) => Err(ZoneFileError::Syntax(entry.line, "unbalanced '('".to_string())),
+        None => Ok(entries),
+    }
+}
+
+// Parses a name as it appears in a zone file: "@" is the origin and names without a trailing dot are relative to it
+fn parse_name(text: &str, origin: &Name) -> Result<Name, String> {
+    match text {
+        "@" => Ok(origin.clone()),
+        _ => Name::parse(text, Some(origin)).map_err(|error| format!("invalid name {text}: {error}")),
+    }
+}
+
+// Parses a TTL in seconds, or with BIND's units (e.g. "1h30m" or "1w")
+pub(crate) fn parse_ttl(text: &str) -> Option<u32> {
+    if let Ok(seconds) = text.parse() {
+        return Some(seconds);
+    }
+    let (mut total, mut number) = (0u32, None::<u32>);
+    for c in text.chars() {
+        match c.to_ascii_lowercase() {
+            digit @ '0'..='9' => number = Some(number.unwrap_or(0).checked_mul(10)?.checked_add(digit.to_digit(10)?)?),
+            unit => {
+                let scale = match unit {
+                    's' => 1,
+                    'm' => 60,
+                    'h' => 3600,
+                    'd' => 86400,
+                    'w' => 604800,
+                    _ => return None,
+                };
+                total = total.checked_add(number.take()?.checked_mul(scale)?)?;
+            }
+        }
+    }
+    match number {
+        Some(_) => None,
+        None => Some(total),
+    }
+}
+
+// Checks the data of the common record types, making the names in it absolute and lower-case so equal records compare equal
+fn parse_rdata(record_type: RecordType, mut rdata: Vec<String>, origin: &Name) -> Result<Vec<String>, String> {
+    // The fields that are names, and the number of fields, of the types whose data is checked
+    let (names, fields): (&[usize], usize) = match record_type {
+        RecordType::A | RecordType::AAAA => (&[], 1),
+        RecordType::CNAME | RecordType::NS | RecordType::PTR => (&[0], 1),
+        RecordType::MX => (&[1], 2),
+        RecordType::SRV => (&[3], 4),
+        RecordType::SOA => (&[0, 1], 7),
+        _ if rdata.is_empty() => return Err(format!("the {record_type} record has no data")),
+        _ => return Ok(rdata),
+    };
+    if rdata.len() != fields {
+        return Err(format!("the {record_type} record has {} fields instead of {fields}", rdata.len()));
+    }
+
+    let valid = match record_type {
+        RecordType::A => rdata[0].parse::<Ipv4Addr>().is_ok(),
+        RecordType::AAAA => rdata[0].parse::<Ipv6Addr>().is_ok(),
+        RecordType::MX => rdata[0].parse::<u16>().is_ok(),
+        RecordType::SRV => rdata[..3].iter().all(|field| field.parse::<u16>().is_ok()),
+        RecordType::SOA => rdata[2].parse::<u32>().is_ok() && rdata[3..].iter().all(|field| parse_ttl(field).is_some()),
+        _ => true,
+    };
+    if !valid {
+        return Err(format!("invalid {record_type} data {}", rdata.join(" ")));
+    }
+    for &field in names {
+        rdata[field] = parse_name(&rdata[field], origin)?.to_lowercase().to_string();
+    }
+    Ok(rdata)
+}
@@ -0,0 +1,293 @@
+use crate::{
+    aws::{self, Credentials},
+    records::{self, RecordStore},
+    zonefile,
+};
+use chrono::Utc;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tracing::*;
+use trust_dns_server::client::rr::{LowerName, Name, Record, RecordType};
+
+// The region S3 requests are signed for when neither AWS_REGION nor AWS_DEFAULT_REGION is set.
+const DEFAULT_REGION: &str = "us-east-1";
+
+/*
+Description:
+defines the errors that can occur while fetching a zone file.
+*/
+#[derive(thiserror::Error, Debug)]
+pub enum ZoneSyncError {
+    #[error("HTTP error: {0:}")]
+    Http(#[from] reqwest::Error),
+    #[error("I/O error: {0:}")]
+    Io(#[from] std::io::Error),
+    #[error("The server answered {0}")]
+    Status(reqwest::StatusCode),
+    #[error("Invalid URL: {0}")]
+    Url(String),
+}
+
+/*
+Description:
+where a zone file is read from: a local path, an http(s) URL, or an object in an S3 bucket.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Location {
+    File(PathBuf),
+    Http(String),
+    S3 { bucket: String, key: String },
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::File(path) => write!(f, "{}", path.display()),
+            Location::Http(url) => f.write_str(url),
+            Location::S3 { bucket, key } => write!(f, "s3://{bucket}/{key}"),
+        }
+    }
+}
+
+/*
+Description:
+a zone served from a zone file, as given to --zone-file: the zone's origin and where its file is read from, e.g. example.com:https://zones.example.net/db.example.com or example.com:s3://bucket/db.example.com.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZoneSource {
+    // The zone's origin, which relative names in the file are relative to
+    pub origin: Name,
+
+    // Where the zone file is read from
+    pub location: Location,
+}
+
+impl FromStr for ZoneSource {
+    type Err = String;
+
+    // Parses <origin>:<location>, where the location is a path, an http(s) URL or an s3://bucket/key URL
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (origin, location) = text.split_once(':').ok_or_else(|| format!("{text} is not of the form <origin>:<path or URL>"))?;
+        let origin = Name::parse(origin, Some(&Name::root())).map_err(|error| format!("invalid origin {origin}: {error}"))?;
+        let location = if location.starts_with("http://") || location.starts_with("https://") {
+            reqwest::Url::parse(location).map_err(|error| format!("invalid URL {location}: {error}"))?;
+            Location::Http(location.to_string())
+        } else if let Some(object) = location.strip_prefix("s3://") {
+            match object.split_once('/') {
+                Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => Location::S3 {
+                    bucket: bucket.to_string(),
+                    key: key.to_string(),
+                },
+                _ => return Err(format!("{location} is not of the form s3://<bucket>/<key>")),
+            }
+        } else if location.is_empty() {
+            return Err(format!("{text} gives no zone file"));
+        } else {
+            Location::File(PathBuf::from(location))
+        };
+        Ok(ZoneSource { origin, location })
+    }
+}
+
+impl ZoneSource {
+    // Returns the name the zone's records are kept under in the record store
+    pub fn store_name(&self) -> String {
+        format!("zone:{}", self.origin)
+    }
+
+    // Returns the path of the zone's cached copy in a cache directory
+    fn cache_path(&self, cache_dir: &Path) -> PathBuf {
+        let origin = self.origin.to_string();
+        let origin = origin.trim_end_matches('.');
+        cache_dir.join(format!("{}.zone", if origin.is_empty() { "root" } else { origin }))
+    }
+}
+
+/*
+Description:
+turns the records of a zone file into the records served, checking that the file is a zone: it must have an SOA record at the origin. Records outside the zone and of types the record store doesn't support are skipped with a warning, so one odd record doesn't take the zone down.
+
+Parameters:
+origin: the zone's origin.
+text: the contents of the zone file.
+
+Returns:
+Ok(Vec<Record>) with the zone's records, or an error message if the file can't be read as the zone.
+*/
+pub fn records(origin: &Name, text: &str) -> Result<Vec<Record>, String> {
+    let parsed = zonefile::parse(text, origin).map_err(|error| error.to_string())?;
+    if !parsed.iter().any(|record| record.record_type == RecordType::SOA && record.name == *origin) {
+        return Err(format!("there is no SOA record at {origin}"));
+    }
+
+    let zone = LowerName::new(origin);
+    let mut records = Vec::with_capacity(parsed.len());
+    for record in parsed {
+        if !zone.zone_of(&LowerName::new(&record.name)) {
+            warn!("Skipping the {} record of {} on line {}: it is outside {origin}", record.record_type, record.name, record.line);
+            continue;
+        }
+        match records::rdata(record.record_type, &record.rdata) {
+            Ok(rdata) => records.push(Record::from_rdata(record.name, record.ttl, rdata)),
+            Err(error) => warn!("Skipping line {} of the zone file of {origin}: {error}", record.line),
+        }
+    }
+    Ok(records)
+}
+
+/*
+Description:
+keeps a zone's records in the record store up to date with its zone file. The cached copy from the last run is served first if there is one, then the file is fetched and fetched again every interval; an http(s) or S3 file is only downloaded again when its ETag changes. Each new version that reads as the zone replaces the zone's records and, for a remote file, the cached copy, and if the file can't be fetched or read, the last version is served.
+
+Parameters:
+store: the record store to keep up to date.
+source: the zone and its file.
+cache_dir: the directory the fetched files are cached in.
+interval: how often to fetch the file.
+
+Returns:
+None; runs until the process exits.
+*/
+pub async fn follow(store: Arc<RecordStore>, source: ZoneSource, cache_dir: PathBuf, interval: Duration) {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new());
+    let cache = source.cache_path(&cache_dir);
+    let etag_path = cache.with_extension("zone.etag");
+
+    // Serve the cached copy until the file is fetched, so a restart doesn't depend on the remote being up
+    let mut served = None;
+    let mut etag = None;
+    if let Ok(text) = tokio::fs::read_to_string(&cache).await {
+        if load(&store, &source, &text) {
+            served = Some(text);
+            etag = tokio::fs::read_to_string(&etag_path).await.ok();
+        }
+    }
+
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        let (text, new_etag) = match fetch(&client, &source.location, etag.as_deref()).await {
+            Ok(Some(fetched)) => fetched,
+            Ok(None) => {
+                debug!("The zone file of {} is unchanged", source.origin);
+                continue;
+            }
+            Err(error) => {
+                warn!("Failed to fetch the zone file of {} from {}, serving the last version: {error}", source.origin, source.location);
+                continue;
+            }
+        };
+        etag = new_etag;
+        if served.as_ref() == Some(&text) || !load(&store, &source, &text) {
+            continue;
+        }
+
+        if !matches!(source.location, Location::File(_)) {
+            if let Err(error) = store_cache(&cache, &etag_path, &text, etag.as_deref()).await {
+                warn!("Failed to cache the zone file of {}: {error}", source.origin);
+            }
+        }
+        served = Some(text);
+    }
+}
+
+// Replaces the zone's records with those of a version of its file, returning whether it could be read as the zone
+fn load(store: &RecordStore, source: &ZoneSource, text: &str) -> bool {
+    match records(&source.origin, text) {
+        Ok(records) => {
+            info!("Serving {} records of {} from {}", records.len(), source.origin, source.location);
+            store.replace(&source.store_name(), records);
+            true
+        }
+        Err(error) => {
+            warn!("The zone file of {} from {} is invalid, serving the last version: {error}", source.origin, source.location);
+            false
+        }
+    }
+}
+
+// Writes the cached copy of a zone file and its ETag, each through a temporary file renamed into place
+async fn store_cache(cache: &Path, etag_path: &Path, text: &str, etag: Option<&str>) -> std::io::Result<()> {
+    if let Some(dir) = cache.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let tmp = cache.with_extension("zone.tmp");
+    tokio::fs::write(&tmp, text).await?;
+    tokio::fs::rename(&tmp, cache).await?;
+    match etag {
+        Some(etag) => {
+            let tmp = etag_path.with_extension("etag.tmp");
+            tokio::fs::write(&tmp, etag).await?;
+            tokio::fs::rename(&tmp, etag_path).await
+        }
+        None => match tokio::fs::remove_file(etag_path).await {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        },
+    }
+}
+
+/*
+Description:
+fetches a zone file. Remote files are asked for with If-None-Match when their ETag is known, so an unchanged file isn't downloaded again. S3 objects are read from AWS_ENDPOINT_URL if it is set (for S3-compatible stores, with path-style URLs) or else from the bucket's virtual host in AWS_REGION, and the requests are signed when credentials are in the environment.
+
+Parameters:
+client: the HTTP client to fetch remote files with.
+location: where the file is.
+etag: the ETag of the version last fetched, if known.
+
+Returns:
+Ok(Some((text, etag))) with the file and its ETag if it has one, Ok(None) if it hasn't changed since the ETag, or a ZoneSyncError.
+*/
+pub async fn fetch(client: &reqwest::Client, location: &Location, etag: Option<&str>) -> Result<Option<(String, Option<String>)>, ZoneSyncError> {
+    let mut request = match location {
+        Location::File(path) => return Ok(Some((tokio::fs::read_to_string(path).await?, None))),
+        Location::Http(url) => client.get(url),
+        Location::S3 { bucket, key } => s3_request(client, bucket, key, Credentials::from_env().as_ref())?,
+    };
+    if let Some(etag) = etag {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(ZoneSyncError::Status(response.status()));
+    }
+    let etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(str::to_string);
+    Ok(Some((response.text().await?, etag)))
+}
+
+// Builds the GET request for an S3 object, signed if there are credentials
+fn s3_request(client: &reqwest::Client, bucket: &str, key: &str, credentials: Option<&Credentials>) -> Result<reqwest::RequestBuilder, ZoneSyncError> {
+    let region = std::env::var("AWS_REGION")
+        .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+        .unwrap_or_else(|_| DEFAULT_REGION.to_string());
+    let path = format!("/{}", key.split('/').map(aws::uri_encode).collect::<Vec<_>>().join("/"));
+
+    // Path-style URLs on a custom endpoint, virtual-hosted ones on AWS
+    let url = match std::env::var("AWS_ENDPOINT_URL") {
+        Ok(endpoint) => format!("{}/{}{path}", endpoint.trim_end_matches('/'), aws::uri_encode(bucket)),
+        Err(_) => format!("https://{bucket}.s3.{region}.amazonaws.com{path}"),
+    };
+    let url = reqwest::Url::parse(&url).map_err(|error| ZoneSyncError::Url(error.to_string()))?;
+    Ok(match credentials {
+        Some(credentials) => aws::sign(client, url, credentials, &region, "s3", Utc::now()),
+        None => client.get(url),
+    })
+}
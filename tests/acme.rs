@@ -0,0 +1,185 @@
+use clap::Parser;
+use my_project::{
+    acme::{self, Acme, AcmeError},
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use trust_dns_server::{
+    client::rr::{Name, RData, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// The domain the handler serves.
+const DOMAIN: &str = "mentisnovae.tech";
+
+// A well-formed DNS-01 token, and a second one.
+const TOKEN: &str = "LoqXcYV8q5ONbJQxbmR7SCTNo3tiAXDfowyjxAjEuX0";
+const OTHER_TOKEN: &str = "kd4pJ0j0jFHAzVqLSeTLq2Ec0ar8Qyj-fOEcLOQvqO_";
+
+// The address clients publish from.
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+// Creates a handler with the ACME API enabled
+fn handler() -> Handler {
+    let options = Options::parse_from(["rusty-dns", "--domain", DOMAIN, "--acme-api", "127.0.0.1:0"]);
+    Handler::from_options(&options)
+}
+
+// Answers a TXT query for a name over UDP
+async fn answer(handler: &Handler, name: &str) -> Message {
+    let request = RequestBuilder::new(Name::from_str(name).unwrap(), RecordType::TXT).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    Message::from_vec(&responder.take().expect("the handler sent no answer")).unwrap()
+}
+
+// Returns the texts of an answer's TXT records
+fn texts(answer: &Message) -> Vec<String> {
+    answer
+        .answers()
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(RData::TXT(txt)) => Some(txt.txt_data().iter().map(|data| String::from_utf8_lossy(data)).collect()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Sends one request to the ACME API and returns the status and body of its answer
+async fn http(address: std::net::SocketAddr, request: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+    (status, body)
+}
+
+#[tokio::test]
+async fn published_tokens_are_served_as_txt_records() {
+    let handler = handler();
+    let acme = handler.acme.clone().unwrap();
+    let account = acme.register(LOCALHOST, Vec::new()).await.unwrap();
+    assert_eq!(account.fulldomain, format!("acme.{}.{DOMAIN}", account.subdomain));
+
+    // Between challenges the name exists but has no records
+    let empty = answer(&handler, &format!("{}.", account.fulldomain)).await;
+    assert_eq!(empty.response_code(), ResponseCode::NoError);
+    assert!(empty.answers().is_empty());
+
+    acme.update(LOCALHOST, &account.username, &account.password, &account.subdomain, TOKEN).unwrap();
+    let answer = answer(&handler, &format!("{}.", account.fulldomain.to_uppercase())).await;
+    assert_eq!(answer.response_code(), ResponseCode::NoError);
+    assert_eq!(texts(&answer), [TOKEN]);
+    assert_eq!(answer.answers()[0].ttl(), acme::TOKEN_TTL);
+}
+
+#[tokio::test]
+async fn unregistered_subdomains_do_not_exist() {
+    let handler = handler();
+    let answer = answer(&handler, &format!("acme.c6b1a4a2-0000-4000-8000-000000000000.{DOMAIN}.")).await;
+    assert_eq!(answer.response_code(), ResponseCode::NXDomain);
+
+    let off = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", DOMAIN]));
+    assert!(off.acme.is_none());
+}
+
+#[tokio::test]
+async fn updates_need_the_accounts_credentials_and_subdomain() {
+    let acme = Acme::open(DOMAIN, None, vec!["127.0.0.0/8".parse().unwrap()], Duration::from_secs(60)).unwrap();
+    let account = acme.register(LOCALHOST, Vec::new()).await.unwrap();
+    let other = acme.register(LOCALHOST, Vec::new()).await.unwrap();
+
+    let update = |password: &str, subdomain: &str, txt: &str| acme.update(LOCALHOST, &account.username, password, subdomain, txt);
+    assert_eq!(update("wrong", &account.subdomain, TOKEN), Err(AcmeError::Forbidden));
+    assert_eq!(update(&account.password, &other.subdomain, TOKEN), Err(AcmeError::BadSubdomain));
+    assert_eq!(update(&account.password, &account.subdomain, "not a token"), Err(AcmeError::BadTxt));
+    assert_eq!(update(&account.password, &account.subdomain, TOKEN), Ok(()));
+    assert_eq!(acme.tokens(&other.subdomain), Some(Vec::new()));
+}
+
+#[tokio::test]
+async fn registration_and_updates_are_limited_to_allowed_networks() {
+    let acme = Acme::open(DOMAIN, None, vec!["127.0.0.0/8".parse().unwrap()], Duration::from_secs(60)).unwrap();
+    let outsider = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7));
+    assert!(matches!(acme.register(outsider, Vec::new()).await, Err(AcmeError::Forbidden)));
+
+    let account = acme.register(LOCALHOST, vec!["127.0.0.1/32".parse().unwrap()]).await.unwrap();
+    assert_eq!(
+        acme.update(outsider, &account.username, &account.password, &account.subdomain, TOKEN),
+        Err(AcmeError::Forbidden)
+    );
+    assert_eq!(acme.update(LOCALHOST, &account.username, &account.password, &account.subdomain, TOKEN), Ok(()));
+}
+
+#[tokio::test]
+async fn only_the_two_newest_live_tokens_are_served() {
+    let acme = Acme::open(DOMAIN, None, vec!["127.0.0.0/8".parse().unwrap()], Duration::from_secs(60)).unwrap();
+    let account = acme.register(LOCALHOST, Vec::new()).await.unwrap();
+    for token in [OTHER_TOKEN, TOKEN, OTHER_TOKEN] {
+        acme.update(LOCALHOST, &account.username, &account.password, &account.subdomain, token).unwrap();
+    }
+    assert_eq!(acme.tokens(&account.subdomain), Some(vec![TOKEN.to_string(), OTHER_TOKEN.to_string()]));
+
+    let expiring = Acme::open(DOMAIN, None, vec!["127.0.0.0/8".parse().unwrap()], Duration::ZERO).unwrap();
+    let account = expiring.register(LOCALHOST, Vec::new()).await.unwrap();
+    expiring.update(LOCALHOST, &account.username, &account.password, &account.subdomain, TOKEN).unwrap();
+    assert_eq!(expiring.tokens(&account.subdomain), Some(Vec::new()));
+}
+
+#[tokio::test]
+async fn accounts_survive_a_restart() {
+    let path = std::env::temp_dir().join(format!("rusty-dns-acme-{}.json", std::process::id()));
+    let allow = vec!["127.0.0.0/8".parse().unwrap()];
+    let account = {
+        let acme = Acme::open(DOMAIN, Some(&path), allow.clone(), Duration::from_secs(60)).unwrap();
+        acme.register(LOCALHOST, Vec::new()).await.unwrap()
+    };
+
+    let acme = Acme::open(DOMAIN, Some(&path), allow, Duration::from_secs(60)).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(acme.accounts(), 1);
+    assert_eq!(acme.update(LOCALHOST, &account.username, &account.password, &account.subdomain, TOKEN), Ok(()));
+}
+
+#[tokio::test]
+async fn the_api_speaks_the_acme_dns_protocol() {
+    let handler = handler();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(acme::serve(listener, handler.clone()));
+
+    let (status, body) = http(address, "POST /register HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+    assert_eq!(status, 201);
+    let account: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let (username, password, subdomain) = (&account["username"], &account["password"], &account["subdomain"]);
+
+    let update = serde_json::json!({ "subdomain": subdomain, "txt": TOKEN }).to_string();
+    let request = format!(
+        "POST /update HTTP/1.1\r\nX-Api-User: {}\r\nX-Api-Key: {}\r\nContent-Length: {}\r\n\r\n{update}",
+        username.as_str().unwrap(),
+        password.as_str().unwrap(),
+        update.len(),
+    );
+    let (status, body) = http(address, &request).await;
+    assert_eq!((status, body), (200, format!("{{\"txt\":\"{TOKEN}\"}}")));
+
+    let request = format!("POST /update HTTP/1.1\r\nX-Api-User: {}\r\nX-Api-Key: wrong\r\nContent-Length: {}\r\n\r\n{update}", username.as_str().unwrap(), update.len());
+    let (status, body) = http(address, &request).await;
+    assert_eq!((status, body.as_str()), (401, "{\"error\":\"forbidden\"}"));
+
+    let answer = answer(&handler, &format!("{}.", account["fulldomain"].as_str().unwrap())).await;
+    assert_eq!(texts(&answer), [TOKEN]);
+}
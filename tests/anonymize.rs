@@ -0,0 +1,104 @@
+use my_project::anonymize::Anonymizer;
+use std::{net::IpAddr, str::FromStr, time::Duration};
+use trust_dns_server::{
+    client::rr::{Name, RData, Record, RecordType},
+    proto::{
+        op::{Edns, Message, MessageType, Query},
+        rr::rdata::{opt::EdnsOption, TXT},
+    },
+};
+
+// An anonymizer for example.net with the default prefixes, hashing names with a salt kept for a day
+fn anonymizer(hash_qnames: bool) -> Anonymizer {
+    Anonymizer::new("Example.NET.", 24, 48, hash_qnames, Duration::from_secs(86400))
+}
+
+#[test]
+fn addresses_are_truncated_to_their_network() {
+    let anonymizer = anonymizer(false);
+    for (ip, network) in [
+        ("192.0.2.77", "192.0.2.0"),
+        ("198.51.100.255", "198.51.100.0"),
+        ("2001:db8:1234:5678::1", "2001:db8:1234::"),
+        ("::1", "::"),
+    ] {
+        assert_eq!(anonymizer.ip(IpAddr::from_str(ip).unwrap()), IpAddr::from_str(network).unwrap(), "{ip}");
+    }
+
+    // Prefixes past the address length keep it whole, and a zero prefix keeps nothing
+    let whole = Anonymizer::new("example.net", 40, 200, false, Duration::from_secs(86400));
+    assert_eq!(whole.ip(IpAddr::from_str("192.0.2.77").unwrap()), IpAddr::from_str("192.0.2.77").unwrap());
+    assert_eq!(whole.ip(IpAddr::from_str("2001:db8::1").unwrap()), IpAddr::from_str("2001:db8::1").unwrap());
+    let nothing = Anonymizer::new("example.net", 0, 0, false, Duration::from_secs(86400));
+    assert_eq!(nothing.ip(IpAddr::from_str("192.0.2.77").unwrap()), IpAddr::from_str("0.0.0.0").unwrap());
+    assert_eq!(nothing.ip(IpAddr::from_str("2001:db8::1").unwrap()), IpAddr::from_str("::").unwrap());
+}
+
+#[test]
+fn arguments_are_hashed_and_keywords_kept() {
+    let anonymizer = anonymizer(true);
+    let hashed = anonymizer.qname("age.1990-05-17.example.net.");
+    let labels: Vec<&str> = hashed.split('.').collect();
+    assert_eq!(labels.len(), 5, "{hashed}");
+    assert_eq!((labels[0], labels[2], labels[3], labels[4]), ("age", "example", "net", ""));
+    assert_eq!(labels[1].len(), 16);
+    assert!(labels[1].chars().all(|c| c.is_ascii_hexdigit()), "{hashed}");
+
+    // The same arguments hash the same within a salt period, whatever their case
+    assert_eq!(anonymizer.qname("AGE.1990-05-17.Example.Net."), hashed);
+    assert_ne!(anonymizer.qname("age.1990-05-18.example.net."), hashed);
+
+    // Names without arguments are kept, and names outside the domain are hashed whole
+    assert_eq!(anonymizer.qname("counter.example.net."), "counter.example.net.");
+    assert_eq!(anonymizer.qname("example.net."), "example.net.");
+    let outside = anonymizer.qname("www.example.org.");
+    assert_eq!(outside.len(), 17, "{outside}");
+    assert!(!outside.contains("example"), "{outside}");
+
+    // Names are left alone when hashing is off
+    assert_eq!(self::anonymizer(false).qname("age.1990-05-17.example.net."), "age.1990-05-17.example.net.");
+}
+
+#[test]
+fn hashes_change_with_the_salt() {
+    let rotating = Anonymizer::new("example.net", 24, 48, true, Duration::ZERO);
+    assert_ne!(rotating.qname("age.1990-05-17.example.net."), rotating.qname("age.1990-05-17.example.net."));
+
+    // Each anonymizer has a salt of its own
+    assert_ne!(anonymizer(true).qname("www.example.org."), anonymizer(true).qname("www.example.org."));
+}
+
+#[test]
+fn messages_keep_only_the_header_and_question() {
+    let mut message = Message::new();
+    message
+        .set_id(4242)
+        .set_message_type(MessageType::Response)
+        .add_query(Query::query(Name::from_str("age.1990-05-17.example.net.").unwrap(), RecordType::TXT))
+        .add_answer(Record::from_rdata(
+            Name::from_str("age.1990-05-17.example.net.").unwrap(),
+            60,
+            RData::TXT(TXT::new(vec!["36 years".to_string()])),
+        ));
+    let mut edns = Edns::new();
+    edns.options_mut().insert(EdnsOption::Unknown(8, vec![0, 1, 24, 0, 192, 0, 2]));
+    message.set_edns(edns);
+    let bytes = message.to_vec().unwrap();
+
+    let anonymizer = anonymizer(true);
+    let anonymized = Message::from_vec(&anonymizer.message(&bytes).unwrap()).unwrap();
+    assert_eq!(anonymized.header().id(), 4242);
+    assert_eq!(anonymized.message_type(), MessageType::Response);
+    assert_eq!(anonymized.queries().len(), 1);
+    assert_eq!(anonymized.queries()[0].name().to_string(), anonymizer.qname("age.1990-05-17.example.net."));
+    assert_eq!(anonymized.queries()[0].query_type(), RecordType::TXT);
+    assert!(anonymized.answers().is_empty());
+    assert!(anonymized.extensions().is_none());
+
+    // Without hashing the question is kept as asked
+    let anonymized = Message::from_vec(&self::anonymizer(false).message(&bytes).unwrap()).unwrap();
+    assert_eq!(anonymized.queries()[0].name().to_string(), "age.1990-05-17.example.net.");
+    assert!(anonymized.answers().is_empty());
+
+    assert_eq!(anonymizer.message(&[0, 1, 2]), None);
+}
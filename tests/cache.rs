@@ -0,0 +1,172 @@
+use clap::Parser;
+use my_project::{
+    cache::{CaptureResponder, NameCache, ParsedName, ResponseCache},
+    handlers::Handler,
+    options::Options,
+    testing::RequestBuilder,
+};
+use std::{cell::Cell, str::FromStr, time::Duration};
+use trust_dns_server::{
+    client::rr::{LowerName, Name, RData, Record, RecordType},
+    proto::{
+        op::{Edns, Message, MessageType},
+        rr::rdata::TXT,
+    },
+    server::RequestHandler,
+};
+
+// Encodes an answer with one TXT record of a given TTL, with EDNS or without
+fn answer(ttl: u32, edns: bool) -> Vec<u8> {
+    let mut message = Message::new();
+    message.set_message_type(MessageType::Response).add_answer(Record::from_rdata(
+        Name::from_str("fib.10.example.net.").unwrap(),
+        ttl,
+        RData::TXT(TXT::new(vec!["55".to_string()])),
+    ));
+    if edns {
+        message.set_edns(Edns::new());
+    }
+    message.to_vec().unwrap()
+}
+
+#[test]
+fn answers_are_kept_apart_by_edns() {
+    let cache = ResponseCache::new(10, Duration::from_secs(60));
+    assert!(cache.insert("fib.10.example.net.", RecordType::TXT, true, &answer(60, true)).unwrap().edns.is_some());
+
+    // A query without EDNS doesn't get the answer to one with it, nor one for another type or in another case
+    assert!(cache.get("fib.10.example.net.", RecordType::TXT, false).is_none());
+    assert!(cache.get("fib.10.example.net.", RecordType::A, true).is_none());
+    assert!(cache.get("FIB.10.example.net.", RecordType::TXT, true).is_none());
+    assert!(cache.get("fib.10.example.net.", RecordType::TXT, true).unwrap().edns.is_some());
+
+    cache.insert("fib.10.example.net.", RecordType::TXT, false, &answer(60, false));
+    assert!(cache.get("fib.10.example.net.", RecordType::TXT, false).unwrap().edns.is_none());
+    assert!(cache.get("fib.10.example.net.", RecordType::TXT, true).unwrap().edns.is_some());
+
+    let stats = cache.stats();
+    assert_eq!((stats.entries, stats.hits, stats.misses), (2, 3, 3));
+}
+
+#[test]
+fn answers_that_must_not_be_cached_are_not() {
+    let cache = ResponseCache::new(10, Duration::from_secs(60));
+    assert!(cache.insert("fib.10.example.net.", RecordType::TXT, false, &answer(0, false)).is_some());
+    assert!(cache.get("fib.10.example.net.", RecordType::TXT, false).is_none());
+    assert!(cache.insert("fib.10.example.net.", RecordType::TXT, false, &[0, 1, 2]).is_none());
+    assert_eq!(cache.stats().entries, 0);
+
+    // Entries expire after the shorter of their TTL and the limit
+    let cache = ResponseCache::new(10, Duration::ZERO);
+    cache.insert("fib.10.example.net.", RecordType::TXT, false, &answer(60, false));
+    assert!(cache.get("fib.10.example.net.", RecordType::TXT, false).is_none());
+}
+
+// Asks a handler for a name, with EDNS or without, and returns the answer as it is sent
+async fn ask(handler: &Handler, name: &str, edns: bool) -> Vec<u8> {
+    let mut request = RequestBuilder::new(Name::from_str(name).unwrap(), RecordType::TXT).id(7);
+    if edns {
+        let mut edns = Edns::new();
+        edns.set_max_payload(1232);
+        request = request.edns(edns);
+    }
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request.build(), responder.clone()).await;
+    responder.take().unwrap()
+}
+
+#[tokio::test]
+async fn cached_answers_are_those_the_zone_would_send() {
+    let cached = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", "example.net"]));
+    let uncached = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", "example.net", "--response-cache-size", "0"]));
+
+    // Answers to queries with and without EDNS, including a FORMERR with an Extended DNS Error, in both orders
+    for (name, first_edns, malformed) in [
+        ("fib.10.example.net.", true, false),
+        ("fib.11.example.net.", false, false),
+        ("fib.x.example.net.", true, true),
+        ("fib.y.example.net.", false, true),
+    ] {
+        for edns in [first_edns, !first_edns, first_edns, !first_edns] {
+            let answer = ask(&cached, name, edns).await;
+            assert_eq!(answer, ask(&uncached, name, edns).await, "{name} with EDNS {edns}");
+            // Only answers to EDNS queries may carry an OPT record, and FORMERRs carry their Extended DNS Error in one
+            let has_opt = Message::from_vec(&answer).unwrap().extensions().is_some();
+            if !edns {
+                assert!(!has_opt, "{name}");
+            } else if malformed {
+                assert!(has_opt, "{name}");
+            }
+        }
+    }
+
+    // The well-formed names were each computed once with EDNS and once without
+    let stats = cached.response_cache.as_ref().unwrap().stats();
+    assert!(stats.hits >= 4, "{stats:?}");
+    assert!(stats.entries >= 4, "{stats:?}");
+}
+
+#[test]
+fn names_are_parsed_once_until_evicted() {
+    let cache = NameCache::new(2);
+    let parses = Cell::new(0);
+    let lookup = |name: &str| {
+        let name = LowerName::from(Name::from_str(name).unwrap());
+        cache.get_or_parse(&name, || {
+            parses.set(parses.get() + 1);
+            ParsedName::new(Some("fib".to_string()), false, name.to_string(), vec!["10".to_string()])
+        })
+    };
+
+    let parsed = lookup("fib.10.example.net.");
+    assert_eq!(parsed.labels, ["fib", "10", "example", "net", ""]);
+    lookup("FIB.10.Example.NET.");
+    assert_eq!(parses.get(), 1);
+
+    // The least recently used name goes first
+    lookup("fib.11.example.net.");
+    lookup("fib.10.example.net.");
+    lookup("fib.12.example.net.");
+    assert_eq!(parses.get(), 3);
+    lookup("fib.10.example.net.");
+    assert_eq!(parses.get(), 3);
+    lookup("fib.11.example.net.");
+    assert_eq!(parses.get(), 4);
+
+    let stats = cache.stats();
+    assert_eq!((stats.entries, stats.hits, stats.misses), (2, 3, 4));
+}
+
+#[tokio::test]
+async fn parsed_names_answer_as_freshly_parsed_ones() {
+    let cached = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", "example.net", "--response-cache-size", "0"]));
+    let uncached = Handler::from_options(&Options::parse_from([
+        "rusty-dns",
+        "--domain",
+        "example.net",
+        "--response-cache-size",
+        "0",
+        "--name-cache-size",
+        "0",
+    ]));
+
+    // Names differing only in case share an entry, but zones that keep the client's case still see it
+    for name in [
+        "10.0.0.0.24.cidr.example.net.",
+        "10.0.0.0.24.CIDR.Example.Net.",
+        "str.reverse.Hello.example.net.",
+        "STR.reverse.hELLO.example.net.",
+        "str.reverse.Hello.example.net.",
+        "fib.10.example.net.",
+        "fib.x.example.net.",
+        "nosuchzone.example.net.",
+        "example.net.",
+    ] {
+        for _ in 0..2 {
+            assert_eq!(ask(&cached, name, false).await, ask(&uncached, name, false).await, "{name}");
+        }
+    }
+
+    let stats = cached.name_cache.as_ref().unwrap().stats();
+    assert!(stats.hits > stats.misses, "{stats:?}");
+}
@@ -0,0 +1,91 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use trust_dns_server::{
+    client::rr::{Name, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::{Protocol, RequestHandler},
+};
+
+// The domain the handler serves.
+const DOMAIN: &str = "mentisnovae.tech";
+
+// Creates a handler that injects one of the given faults into every query
+fn handler(faults: &str) -> Handler {
+    let options = Options::parse_from(["rusty-dns", "--domain", DOMAIN, "--chaos", "1", "--chaos-faults", faults, "--chaos-max-delay-ms", "300"]);
+    Handler::from_options(&options)
+}
+
+// Answers a query for a name below the domain over a transport
+async fn answer(handler: &Handler, label: &str, protocol: Protocol) -> Message {
+    let name = Name::from_str(&format!("{label}.{DOMAIN}.")).unwrap();
+    let request = RequestBuilder::new(name, RecordType::TXT).protocol(protocol).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    Message::from_vec(&responder.take().expect("the handler sent no answer")).unwrap()
+}
+
+#[tokio::test]
+async fn servfail_replaces_the_answer() {
+    let answer = answer(&handler("servfail"), "dice", Protocol::Udp).await;
+    assert_eq!(answer.response_code(), ResponseCode::ServFail);
+    assert!(answer.answers().is_empty());
+}
+
+#[tokio::test]
+async fn truncation_only_applies_over_udp() {
+    let handler = handler("truncate");
+
+    let answer_udp = answer(&handler, "dice", Protocol::Udp).await;
+    assert!(answer_udp.truncated());
+    assert!(answer_udp.answers().is_empty());
+
+    let answer_tcp = answer(&handler, "dice", Protocol::Tcp).await;
+    assert!(!answer_tcp.truncated());
+    assert_eq!(answer_tcp.answers().len(), 1);
+}
+
+#[tokio::test]
+async fn malformed_answers_match_no_question() {
+    let answer = answer(&handler("malformed"), "dice", Protocol::Udp).await;
+    assert_eq!(answer.response_code(), ResponseCode::NoError);
+    assert_eq!(answer.answers().len(), 1);
+
+    let question = &answer.queries()[0];
+    let record = &answer.answers()[0];
+    assert_ne!(record.name(), question.name());
+    assert_ne!(record.record_type(), question.query_type());
+}
+
+#[tokio::test]
+async fn delayed_answers_are_answered_normally() {
+    let handler = handler("delay");
+    let started = Instant::now();
+    let answer = answer(&handler, "dice", Protocol::Udp).await;
+
+    assert_eq!(answer.response_code(), ResponseCode::NoError);
+    assert_eq!(answer.answers().len(), 1);
+    assert!(started.elapsed() <= Duration::from_millis(400), "delayed by {:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn health_checks_are_left_alone() {
+    let answer = answer(&handler("servfail"), "health", Protocol::Udp).await;
+    assert_eq!(answer.response_code(), ResponseCode::NoError);
+    assert!(!answer.answers().is_empty());
+}
+
+#[tokio::test]
+async fn chaos_mode_is_off_by_default() {
+    let handler = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", DOMAIN]));
+    for _ in 0..20 {
+        assert_eq!(answer(&handler, "dice", Protocol::Udp).await.response_code(), ResponseCode::NoError);
+    }
+}
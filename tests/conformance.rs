@@ -0,0 +1,57 @@
+use clap::Parser;
+use my_project::{
+    conformance::{self, Check, Transport},
+    handlers::Handler,
+    options::Options,
+};
+use std::{net::SocketAddr, time::Duration};
+use tokio::net::{TcpListener, UdpSocket};
+use trust_dns_server::{client::rr::Name, ServerFuture};
+
+// The domain the server serves.
+const DOMAIN: &str = "mentisnovae.tech";
+
+// How long each check waits for an answer; the server answers on the loopback interface at once, or not at all.
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+// The checks the server is known to fail, over UDP and over TCP. Fixing one makes the test fail until it is taken off the list, so the list only ever shrinks.
+const KNOWN_FAILURES: &[(Check, Transport)] = &[
+    (Check::Edns, Transport::Udp),
+    (Check::Edns, Transport::Tcp),
+    (Check::EdnsVersion, Transport::Udp),
+    (Check::EdnsVersion, Transport::Tcp),
+    (Check::Truncation, Transport::Udp),
+    (Check::UnknownOpCode, Transport::Udp),
+    (Check::UnknownOpCode, Transport::Tcp),
+];
+
+// Starts a server on an ephemeral loopback port, for both UDP and TCP, and returns its address; it runs until the test's runtime stops
+async fn server() -> SocketAddr {
+    let options = Options::parse_from(["rusty-dns", "--domain", DOMAIN]);
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+    let listener = TcpListener::bind(address).await.unwrap();
+
+    let mut server = ServerFuture::new(Handler::from_options(&options));
+    server.register_socket(socket);
+    server.register_listener(listener, Duration::from_secs(5));
+    tokio::spawn(async move { server.block_until_done().await });
+    address
+}
+
+#[tokio::test]
+async fn conformance_matches_the_known_failures() {
+    let outcomes = conformance::run(server().await, &Name::from_ascii(DOMAIN).unwrap(), TIMEOUT).await;
+    assert_eq!(outcomes.len(), Check::ALL.len() * Transport::ALL.len());
+
+    let mut unexpected = Vec::new();
+    for outcome in &outcomes {
+        let known = KNOWN_FAILURES.contains(&(outcome.check, outcome.transport));
+        match (outcome.passed, known) {
+            (false, false) => unexpected.push(format!("{} ({}) fails: {}", outcome.check, outcome.transport, outcome.failure.as_deref().unwrap_or_default())),
+            (true, true) => unexpected.push(format!("{} ({}) passes now; take it off KNOWN_FAILURES", outcome.check, outcome.transport)),
+            _ => {}
+        }
+    }
+    assert!(unexpected.is_empty(), "{}", unexpected.join("\n"));
+}
@@ -0,0 +1,79 @@
+use clap::Parser;
+use my_project::{
+    counter::{self, NamedCounters},
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{collections::BTreeMap, net::SocketAddr, str::FromStr};
+use trust_dns_server::{
+    client::rr::{Name, RData, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// Asks a handler for the TXT records of a name from a client
+async fn answer(handler: &Handler, name: &str, client: &str) -> Message {
+    let source: SocketAddr = format!("{client}:53000").parse().unwrap();
+    let request = RequestBuilder::new(Name::from_str(name).unwrap(), RecordType::TXT).source(source).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    Message::from_vec(&responder.take().unwrap()).unwrap()
+}
+
+// Returns the text of the first answer
+fn text(answer: &Message) -> String {
+    match answer.answers().first().and_then(|record| record.data()) {
+        Some(RData::TXT(txt)) => txt.to_string(),
+        other => panic!("expected a TXT record, got {other:?}"),
+    }
+}
+
+#[test]
+fn named_counters_are_created_up_to_their_capacity() {
+    let counters = NamedCounters::new(BTreeMap::from([("hits".to_string(), 41)]), 2);
+    assert_eq!(counters.increment("hits"), Some(42));
+    assert_eq!(counters.increment("signups"), Some(1));
+    assert_eq!(counters.increment("errors"), None);
+
+    // A reset frees the counter's place
+    assert_eq!(counters.reset("signups"), 1);
+    assert_eq!(counters.get("signups"), 0);
+    assert_eq!(counters.increment("errors"), Some(1));
+
+    assert!(counter::valid_name("build-42"));
+    assert!(!counter::valid_name("-hits") && !counter::valid_name("_peek") && !counter::valid_name(&"a".repeat(64)));
+}
+
+#[test]
+fn named_counters_are_read_after_the_request_counter() {
+    let path = std::env::temp_dir().join(format!("rusty-dns-counter-{}", std::process::id()));
+    std::fs::write(&path, "1532\nhits 42\nnot a counter\nsignups 7\n").unwrap();
+    assert_eq!(counter::load(&path), 1532);
+    assert_eq!(counter::load_named(&path), BTreeMap::from([("hits".to_string(), 42), ("signups".to_string(), 7)]));
+
+    // Files written before named counters hold the request counter alone
+    std::fs::write(&path, "1532").unwrap();
+    assert_eq!(counter::load(&path), 1532);
+    assert!(counter::load_named(&path).is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn only_operators_peek_at_and_reset_counters() {
+    let mut options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    options.config.counter.operators = vec!["10.0.0.0/8".parse().unwrap()];
+    let handler = Handler::from_options(&options);
+
+    assert_eq!(text(&answer(&handler, "hits.counter.mentisnovae.tech.", "192.0.2.7").await), "1");
+    assert_eq!(text(&answer(&handler, "HITS.counter.mentisnovae.tech.", "192.0.2.7").await), "2");
+    assert_eq!(answer(&handler, "_peek.hits.counter.mentisnovae.tech.", "192.0.2.7").await.response_code(), ResponseCode::Refused);
+    assert_eq!(answer(&handler, "_reset.hits.counter.mentisnovae.tech.", "192.0.2.7").await.response_code(), ResponseCode::Refused);
+
+    assert_eq!(text(&answer(&handler, "_peek.hits.counter.mentisnovae.tech.", "10.1.2.3").await), "2");
+    assert_eq!(text(&answer(&handler, "_reset.hits.counter.mentisnovae.tech.", "10.1.2.3").await), "2");
+    assert_eq!(text(&answer(&handler, "_peek.hits.counter.mentisnovae.tech.", "10.1.2.3").await), "0");
+
+    let answer = answer(&handler, "a.b.c.counter.mentisnovae.tech.", "10.1.2.3").await;
+    assert_eq!(answer.response_code(), ResponseCode::NXDomain);
+}
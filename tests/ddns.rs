@@ -0,0 +1,163 @@
+use base64::Engine;
+use clap::Parser;
+use my_project::{
+    ddns::{self, Ddns, DdnsError},
+    handlers::Handler,
+    options::Options,
+    testing::answer,
+};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use trust_dns_server::{client::rr::RecordType, proto::op::ResponseCode};
+
+// The domain the handler serves.
+const DOMAIN: &str = "mentisnovae.tech";
+
+// The addresses hosts are updated to.
+const IPV4: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 7));
+const OTHER_IPV4: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 9));
+const IPV6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+
+// Returns a hosts file of its own for a test, removing any left by an earlier run
+fn hosts_file(test: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rusty-dns-ddns-{test}-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+// Creates a handler serving the dyn zone from a hosts file
+fn handler(path: &Path) -> Handler {
+    let options = Options::parse_from([
+        "rusty-dns",
+        "--domain",
+        DOMAIN,
+        "--ddns-api",
+        "127.0.0.1:0",
+        "--ddns-hosts",
+        path.to_str().unwrap(),
+    ]);
+    Handler::from_options(&options)
+}
+
+// Sends one request to the DDNS API and returns the status, head and body of its answer
+async fn http(address: SocketAddr, request: &str) -> (u16, String, String) {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, head.to_string(), body.to_string())
+}
+
+#[tokio::test]
+async fn updated_hosts_are_served_as_a_and_aaaa_records() {
+    let path = hosts_file("served");
+    let token = ddns::add_host(&path, "home").unwrap();
+    let handler = handler(&path);
+    let zone = handler.ddns.clone().unwrap();
+
+    // Until its first update, the host has no addresses
+    let empty = answer(&handler, "home.dyn.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(empty.response_code(), ResponseCode::NoError);
+    assert!(empty.answers().is_empty());
+
+    assert_eq!(zone.update("Home.dyn.mentisnovae.tech", &token, &[IPV4, IPV6]), Ok(true));
+    let a = answer(&handler, "home.dyn.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(a.answers().len(), 1);
+    assert_eq!(a.answers()[0].ttl(), ddns::TTL);
+    assert!(a.answers()[0].to_string().contains("192.0.2.7"));
+    let aaaa = answer(&handler, "home.dyn.mentisnovae.tech.", RecordType::AAAA).await;
+    assert!(aaaa.answers()[0].to_string().contains("2001:db8::1"));
+    let txt = answer(&handler, "home.dyn.mentisnovae.tech.", RecordType::TXT).await;
+    assert_eq!(txt.response_code(), ResponseCode::NoError);
+    assert!(txt.answers().is_empty());
+
+    let unknown = answer(&handler, "away.dyn.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(unknown.response_code(), ResponseCode::NXDomain);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn updates_need_the_hosts_token() {
+    let path = hosts_file("tokens");
+    let token = ddns::add_host(&path, "home").unwrap();
+    let other = ddns::add_host(&path, "office").unwrap();
+    let zone = Ddns::open(DOMAIN, &path).unwrap();
+
+    assert_eq!(zone.update("home.dyn.mentisnovae.tech", &other, &[IPV4]), Err(DdnsError::BadAuth));
+    assert_eq!(zone.update("cabin.dyn.mentisnovae.tech", &token, &[IPV4]), Err(DdnsError::NoHost));
+    assert_eq!(zone.update("home.example.com", &token, &[IPV4]), Err(DdnsError::NotFqdn));
+    assert_eq!(zone.update("home.dyn.mentisnovae.tech", &token, &[IPV4]), Ok(true));
+    assert_eq!(zone.update("home.dyn.mentisnovae.tech", &token, &[IPV4]), Ok(false));
+
+    // A new token replaces the old one
+    let token_again = ddns::add_host(&path, "home").unwrap();
+    assert_eq!(zone.update("home.dyn.mentisnovae.tech", &token, &[IPV4]), Err(DdnsError::BadAuth));
+    assert_eq!(zone.update("home.dyn.mentisnovae.tech", &token_again, &[IPV4]), Ok(false));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn addresses_are_kept_per_family_and_survive_a_restart() {
+    let path = hosts_file("restart");
+    let token = ddns::add_host(&path, "home").unwrap();
+    let zone = Ddns::open(DOMAIN, &path).unwrap();
+    zone.update("home.dyn.mentisnovae.tech", &token, &[IPV4, IPV6]).unwrap();
+    zone.update("home.dyn.mentisnovae.tech", &token, &[OTHER_IPV4]).unwrap();
+
+    let reopened = Ddns::open(DOMAIN, &path).unwrap();
+    let expected = match (OTHER_IPV4, IPV6) {
+        (IpAddr::V4(ipv4), IpAddr::V6(ipv6)) => (Some(ipv4), Some(ipv6)),
+        _ => unreachable!(),
+    };
+    assert_eq!(reopened.addresses("home"), Some(expected));
+
+    assert!(ddns::remove_host(&path, "home").unwrap());
+    assert!(!ddns::remove_host(&path, "home").unwrap());
+    assert!(ddns::add_host(&path, "-home").is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn the_api_speaks_the_dyndns_protocol() {
+    let path = hosts_file("api");
+    let token = ddns::add_host(&path, "home").unwrap();
+    let handler = handler(&path);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(ddns::serve(listener, handler.clone()));
+
+    let update = |password: &str, query: &str| {
+        let credentials = base64::engine::general_purpose::STANDARD.encode(format!("router:{password}"));
+        format!("GET /nic/update?{query} HTTP/1.1\r\nHost: localhost\r\nAuthorization: Basic {credentials}\r\n\r\n")
+    };
+
+    // Without credentials, the client is asked for them
+    let (status, head, body) = http(address, "GET /nic/update?hostname=home.dyn.mentisnovae.tech HTTP/1.1\r\n\r\n").await;
+    assert_eq!((status, body.as_str()), (401, "badauth\n"));
+    assert!(head.to_ascii_lowercase().contains("www-authenticate: basic"));
+
+    let (status, _, body) = http(address, &update("wrong", "hostname=home.dyn.mentisnovae.tech&myip=192.0.2.7")).await;
+    assert_eq!((status, body.as_str()), (200, "badauth\n"));
+
+    let query = "hostname=home.dyn.mentisnovae.tech,cabin.dyn.mentisnovae.tech&myip=192.0.2.7%2C2001%3Adb8%3A%3A1";
+    let (status, _, body) = http(address, &update(&token, query)).await;
+    assert_eq!((status, body.as_str()), (200, "good 192.0.2.7,2001:db8::1\nnohost\n"));
+
+    // Without myip, the client's own address is used
+    let (_, _, body) = http(address, &update(&token, "hostname=home.dyn.mentisnovae.tech")).await;
+    assert_eq!(body, "good 127.0.0.1\n");
+    let (_, _, body) = http(address, &update(&token, "hostname=home.dyn.mentisnovae.tech")).await;
+    assert_eq!(body, "nochg 127.0.0.1\n");
+
+    let a = answer(&handler, "home.dyn.mentisnovae.tech.", RecordType::A).await;
+    assert!(a.answers()[0].to_string().contains("127.0.0.1"));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,129 @@
+use clap::Parser;
+use my_project::{
+    config::DelegationConfig,
+    delegation::{Delegation, DelegationError},
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use trust_dns_server::{
+    client::rr::{LowerName, Name, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// svc.mentisnovae.tech delegated to a backend on a loopback port
+fn config(port: u16) -> DelegationConfig {
+    DelegationConfig {
+        zone: "svc.mentisnovae.tech".to_string(),
+        url: format!("http://127.0.0.1:{port}/dns"),
+        headers: [("X-Token".to_string(), "secret".to_string())].into(),
+        timeout_ms: 1000,
+        cache_size: 100,
+        max_cache_ttl: 300,
+        negative_ttl: 30,
+        ttl: 60,
+        failure_threshold: 2,
+        open_for: 30,
+    }
+}
+
+// Starts an HTTP backend on 127.0.0.1 that counts its requests and answers each with the status and body given for the name in it
+async fn backend(requests: Arc<AtomicUsize>, answer: fn(&str) -> (u16, &'static str)) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            requests.fetch_add(1, Ordering::SeqCst);
+            let mut request = Vec::new();
+            let mut buffer = vec![0; 4096];
+            while !String::from_utf8_lossy(&request).contains('}') {
+                match stream.read(&mut buffer).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => request.extend_from_slice(&buffer[..read]),
+                }
+            }
+            let request = String::from_utf8_lossy(&request).to_string();
+            let (status, body) = match request.contains("x-token: secret") {
+                true => answer(&request),
+                false => (401, "{}"),
+            };
+            let response = format!(
+                "HTTP/1.1 {status} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+    port
+}
+
+// Answers web.svc with two addresses and every other name with NXDOMAIN
+fn inventory(request: &str) -> (u16, &'static str) {
+    match request.contains(r#""name":"web.svc.mentisnovae.tech.""#) {
+        true => (200, r#"{"answers": [{"type": "A", "data": "192.0.2.10", "ttl": 30}, {"type": "A", "data": "192.0.2.11"}]}"#),
+        false => (200, r#"{"rcode": "NXDOMAIN"}"#),
+    }
+}
+
+// Returns a name as the delegation looks it up
+fn name(text: &str) -> LowerName {
+    LowerName::from(Name::from_str(text).unwrap())
+}
+
+#[tokio::test]
+async fn backends_answer_delegated_names_and_are_cached() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let port = backend(requests.clone(), inventory).await;
+    let mut options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    options.config.delegation = vec![config(port)];
+    let handler = Handler::from_options(&options);
+
+    let mut answers = Vec::new();
+    for query in ["web.svc.mentisnovae.tech.", "web.svc.mentisnovae.tech.", "dice.svc.mentisnovae.tech."] {
+        let request = RequestBuilder::new(Name::from_str(query).unwrap(), RecordType::A).build();
+        let responder = CaptureResponder::default();
+        handler.handle_request(&request, responder.clone()).await;
+        answers.push(Message::from_vec(&responder.take().unwrap()).unwrap());
+    }
+
+    // The second question is answered from the cache, and keyword zones don't answer within the subzone
+    let addresses: Vec<(String, u32)> = answers[0].answers().iter().map(|record| (record.data().unwrap().to_string(), record.ttl())).collect();
+    assert_eq!(addresses, [("192.0.2.10".to_string(), 30), ("192.0.2.11".to_string(), 60)]);
+    assert_eq!(answers[1].answers().len(), 2);
+    assert_eq!(answers[2].response_code(), ResponseCode::NXDomain);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn failing_backends_are_left_alone_after_the_threshold() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let port = backend(requests.clone(), |_| (500, "{}")).await;
+    let delegation = Delegation::new(&[config(port)]).unwrap();
+    let web = name("web.svc.mentisnovae.tech.");
+
+    for _ in 0..2 {
+        assert!(matches!(delegation.answer(&web, RecordType::A).await, Some(Err(DelegationError::Status(_)))));
+    }
+    assert!(matches!(delegation.answer(&web, RecordType::A).await, Some(Err(DelegationError::CircuitOpen(2)))));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    assert!(delegation.status(&web).unwrap().2);
+
+    // Names outside the subzone aren't delegated, and records outside it are refused
+    assert!(delegation.answer(&name("web.mentisnovae.tech."), RecordType::A).await.is_none());
+    let port = backend(Arc::new(AtomicUsize::new(0)), |_| (200, r#"{"answers": [{"name": "evil.example.com", "type": "A", "data": "192.0.2.66"}]}"#)).await;
+    let delegation = Delegation::new(&[config(port)]).unwrap();
+    let answer = delegation.answer(&web, RecordType::A).await.unwrap();
+    assert!(matches!(answer, Err(DelegationError::MalformedResponse(_))), "{answer:?}");
+}
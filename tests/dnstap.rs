@@ -0,0 +1,171 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+use trust_dns_server::{
+    client::rr::{Name, RecordType},
+    proto::op::{Message, MessageType},
+    server::RequestHandler,
+};
+
+// A decoded protobuf field value
+#[derive(Debug, PartialEq)]
+enum Value {
+    Varint(u64),
+    Fixed32(u32),
+    Bytes(Vec<u8>),
+}
+
+// Reads a protobuf varint, advancing past it
+fn varint(bytes: &[u8], at: &mut usize) -> u64 {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = bytes[*at];
+        *at += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    value
+}
+
+// Decodes a protobuf message into its fields by number, failing on wire types dnstap doesn't use
+fn decode(bytes: &[u8]) -> HashMap<u64, Value> {
+    let mut fields = HashMap::new();
+    let mut at = 0;
+    while at < bytes.len() {
+        let key = varint(bytes, &mut at);
+        let value = match key & 7 {
+            0 => Value::Varint(varint(bytes, &mut at)),
+            2 => {
+                let length = varint(bytes, &mut at) as usize;
+                at += length;
+                Value::Bytes(bytes[at - length..at].to_vec())
+            }
+            5 => {
+                at += 4;
+                Value::Fixed32(u32::from_le_bytes(bytes[at - 4..at].try_into().unwrap()))
+            }
+            wire_type => panic!("unexpected wire type {wire_type}"),
+        };
+        assert!(fields.insert(key >> 3, value).is_none(), "field {} repeated", key >> 3);
+    }
+    fields
+}
+
+// Returns a length-delimited field
+fn bytes(fields: &HashMap<u64, Value>, field: u64) -> &[u8] {
+    match fields.get(&field) {
+        Some(Value::Bytes(bytes)) => bytes,
+        other => panic!("expected bytes in field {field}, got {other:?}"),
+    }
+}
+
+// Answers a query from 192.0.2.77:53000 with dnstap logging to a file, and returns the data frames written
+async fn frames(test: &str, extra: &[&str]) -> Vec<Vec<u8>> {
+    let path: PathBuf = std::env::temp_dir().join(format!("rusty-dns-dnstap-{test}-{}.fstrm", std::process::id()));
+    let mut arguments = vec!["rusty-dns", "--domain", "example.net", "--dnstap-file", path.to_str().unwrap()];
+    arguments.extend_from_slice(extra);
+    let handler = Handler::from_options(&Options::parse_from(arguments));
+
+    let request = RequestBuilder::new(Name::from_str("age.1990-05-17.example.net.").unwrap(), RecordType::TXT)
+        .id(4242)
+        .source("192.0.2.77:53000".parse::<SocketAddr>().unwrap())
+        .build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    assert!(responder.take().is_some());
+
+    // The writer flushes after every batch, so wait until both frames are in the file
+    for _ in 0..100 {
+        let stream = std::fs::read(&path).unwrap_or_default();
+        let frames = data_frames(&stream);
+        if frames.len() == 2 {
+            let _ = std::fs::remove_file(&path);
+            return frames;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("dnstap frames were not written to {path:?}");
+}
+
+// Splits a unidirectional Frame Streams file into its data frames, checking the START frame it begins with
+fn data_frames(stream: &[u8]) -> Vec<Vec<u8>> {
+    const START: &[u8] = b"\0\0\0\0\0\0\0\x22\0\0\0\x02\0\0\0\x01\0\0\0\x16protobuf:dnstap.Dnstap";
+    if stream.len() < START.len() {
+        return Vec::new();
+    }
+    assert_eq!(&stream[..START.len()], START);
+
+    let mut frames = Vec::new();
+    let mut at = START.len();
+    while at + 4 <= stream.len() {
+        let length = u32::from_be_bytes(stream[at..at + 4].try_into().unwrap()) as usize;
+        assert_ne!(length, 0, "unexpected control frame");
+        if at + 4 + length > stream.len() {
+            break;
+        }
+        frames.push(stream[at + 4..at + 4 + length].to_vec());
+        at += 4 + length;
+    }
+    frames
+}
+
+#[tokio::test]
+async fn queries_and_responses_are_written_as_dnstap_messages() {
+    let frames = frames("plain", &[]).await;
+    for (frame, kind) in frames.iter().zip([1, 2]) {
+        // The Dnstap wrapper: identity, version and a MESSAGE
+        let dnstap = decode(frame);
+        assert_eq!(bytes(&dnstap, 1), b"example.net");
+        assert!(bytes(&dnstap, 2).starts_with(env!("CARGO_PKG_NAME").as_bytes()));
+        assert_eq!(dnstap.get(&15), Some(&Value::Varint(1)));
+
+        // The Message: an AUTH_QUERY or AUTH_RESPONSE over UDP from the client
+        let message = decode(bytes(&dnstap, 14));
+        assert_eq!(message.get(&1), Some(&Value::Varint(kind)));
+        assert_eq!(message.get(&2), Some(&Value::Varint(1)));
+        assert_eq!(message.get(&3), Some(&Value::Varint(1)));
+        assert_eq!(bytes(&message, 4), [192, 0, 2, 77]);
+        assert_eq!(message.get(&6), Some(&Value::Varint(53000)));
+        assert!(matches!(message.get(&8), Some(Value::Varint(seconds)) if *seconds > 0));
+        assert!(matches!(message.get(&9), Some(Value::Fixed32(nanoseconds)) if *nanoseconds < 1_000_000_000));
+    }
+
+    // The query and the response, each in wire format in its own message
+    let query = decode(bytes(&decode(&frames[0]), 14));
+    let query = Message::from_vec(bytes(&query, 10)).unwrap();
+    assert_eq!((query.id(), query.message_type()), (4242, MessageType::Query));
+    assert_eq!(query.queries()[0].name().to_string(), "age.1990-05-17.example.net.");
+
+    let response = decode(bytes(&decode(&frames[1]), 14));
+    assert!(!response.contains_key(&10));
+    assert!(matches!(response.get(&12), Some(Value::Varint(_))));
+    let response = Message::from_vec(bytes(&response, 14)).unwrap();
+    assert_eq!((response.id(), response.message_type()), (4242, MessageType::Response));
+    assert_eq!(response.answers().len(), 1);
+}
+
+#[tokio::test]
+async fn anonymized_messages_keep_only_the_network_and_the_question() {
+    let frames = frames("anonymized", &["--anonymize", "--hash-qnames"]).await;
+    for frame in &frames {
+        let message = decode(bytes(&decode(frame), 14));
+        assert_eq!(bytes(&message, 4), [192, 0, 2, 0]);
+        assert!(!message.contains_key(&6));
+    }
+
+    // The arguments are hashed and the answer is dropped
+    let query = Message::from_vec(bytes(&decode(bytes(&decode(&frames[0]), 14)), 10)).unwrap();
+    let name = query.queries()[0].name().to_string();
+    assert!(name.starts_with("age.") && name.ends_with(".example.net."), "{name}");
+    assert!(!name.contains("1990"), "{name}");
+
+    let response = Message::from_vec(bytes(&decode(bytes(&decode(&frames[1]), 14)), 14)).unwrap();
+    assert_eq!(response.queries()[0].name().to_string(), name);
+    assert!(response.answers().is_empty());
+}
@@ -0,0 +1,132 @@
+use clap::Parser;
+use my_project::{
+    docker::{self, Docker},
+    handlers::Handler,
+    options::Options,
+    testing::answer,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixListener,
+};
+use trust_dns_server::{
+    client::rr::RecordType,
+    proto::op::{Message, ResponseCode},
+};
+
+// Two replicas of a Compose service and a container started on its own, as /containers/json lists them.
+const CONTAINERS: &str = r#"[
+    {
+        "Names": ["/shop-web-1"],
+        "Labels": {"com.docker.compose.service": "web", "com.docker.compose.project": "shop"},
+        "NetworkSettings": {"Networks": {"shop_default": {"IPAddress": "172.18.0.2", "GlobalIPv6Address": "fd00::2"}}}
+    },
+    {
+        "Names": ["/shop-web-2"],
+        "Labels": {"com.docker.compose.service": "web", "com.docker.compose.project": "shop"},
+        "NetworkSettings": {"Networks": {"shop_default": {"IPAddress": "172.18.0.3", "GlobalIPv6Address": ""}}}
+    },
+    {
+        "Names": ["/Redis"],
+        "Labels": {},
+        "NetworkSettings": {"Networks": {"bridge": {"IPAddress": "172.17.0.2", "GlobalIPv6Address": ""}}}
+    },
+    {
+        "Names": ["/offline"],
+        "Labels": {},
+        "NetworkSettings": {"Networks": {"none": {"IPAddress": "", "GlobalIPv6Address": ""}}}
+    }
+]"#;
+
+// Creates a handler serving the docker zone from a socket that is never watched
+fn handler() -> Handler {
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech", "--docker-socket", "/nonexistent/docker.sock"]);
+    Handler::from_options(&options)
+}
+
+// Returns the addresses in an answer, in order
+fn addresses(answer: &Message) -> Vec<String> {
+    answer.answers().iter().map(|record| record.to_string().rsplit(' ').next().unwrap().to_string()).collect()
+}
+
+#[test]
+fn containers_are_named_after_themselves_and_their_compose_service() {
+    let docker = Docker::default();
+    assert_eq!(docker.load(CONTAINERS.as_bytes()).unwrap(), 5);
+
+    let listed = |name: &str| docker.addresses(name).iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(listed("shop-web-1"), ["172.18.0.2", "fd00::2"]);
+    assert_eq!(listed("web"), ["172.18.0.2", "fd00::2", "172.18.0.3"]);
+    assert_eq!(listed("web.shop"), ["172.18.0.2", "fd00::2", "172.18.0.3"]);
+    assert_eq!(listed("redis"), ["172.17.0.2"]);
+    assert!(listed("offline").is_empty());
+
+    // A list that isn't JSON leaves the names alone
+    assert!(docker.load(b"not json").is_err());
+    assert_eq!(docker.names(), 5);
+}
+
+#[tokio::test]
+async fn containers_are_served_as_a_and_aaaa_records() {
+    let handler = handler();
+    handler.docker.as_ref().unwrap().load(CONTAINERS.as_bytes()).unwrap();
+
+    let a = answer(&handler, "web.docker.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(addresses(&a), ["172.18.0.2", "172.18.0.3"]);
+    assert_eq!(a.answers()[0].ttl(), docker::TTL);
+    let aaaa = answer(&handler, "web.shop.docker.mentisnovae.tech.", RecordType::AAAA).await;
+    assert_eq!(addresses(&aaaa), ["fd00::2"]);
+    let none = answer(&handler, "redis.docker.mentisnovae.tech.", RecordType::AAAA).await;
+    assert_eq!(none.response_code(), ResponseCode::NoError);
+    assert!(none.answers().is_empty());
+
+    let stopped = answer(&handler, "db.docker.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(stopped.response_code(), ResponseCode::NXDomain);
+}
+
+#[tokio::test]
+async fn the_zone_follows_the_docker_engines_events() {
+    let socket = std::env::temp_dir().join(format!("rusty-dns-docker-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).unwrap();
+
+    // A fake engine: an event stream that sends one event once the containers are listed, and a list that gains a container after it
+    tokio::spawn(async move {
+        let mut events = None;
+        let mut listed = 0;
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 1024];
+            let read = stream.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..read]).into_owned();
+            stream.write_all(b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n").await.unwrap();
+            if request.starts_with("GET /events") {
+                events = Some(stream);
+                continue;
+            }
+            listed += 1;
+            let body = match listed {
+                1 => r#"[]"#,
+                _ => r#"[{"Names": ["/db"], "NetworkSettings": {"Networks": {"bridge": {"IPAddress": "172.17.0.5"}}}}]"#,
+            };
+            stream.write_all(body.as_bytes()).await.unwrap();
+            drop(stream);
+            if listed == 1 {
+                let event = b"{\"Type\":\"container\",\"Action\":\"start\"}\n";
+                events.as_mut().unwrap().write_all(event).await.unwrap();
+            }
+        }
+    });
+
+    let docker = Arc::new(Docker::default());
+    tokio::spawn(docker::watch(docker.clone(), socket.clone()));
+    for _ in 0..100 {
+        if !docker.addresses("db").is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(docker.addresses("db").iter().map(ToString::to_string).collect::<Vec<_>>(), ["172.17.0.5"]);
+    std::fs::remove_file(&socket).unwrap();
+}
@@ -0,0 +1,126 @@
+use clap::Parser;
+use my_project::{
+    config::EnumConfig,
+    e164::{self, EnumZone},
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{collections::BTreeMap, str::FromStr};
+use trust_dns_server::{
+    client::rr::{LowerName, Name, RData, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// A lab's numbers: reception on its own, and every number of one block at the PBX
+fn config() -> EnumConfig {
+    EnumConfig {
+        zone: "e164.arpa".to_string(),
+        ttl: 300,
+        numbers: BTreeMap::from([(
+            "+44 20 7946 0000".to_string(),
+            vec!["sip:reception@pbx.example.com".to_string(), "mailto:reception@example.com".to_string()],
+        )]),
+        prefixes: BTreeMap::from([("+44-20-7946-01".to_string(), vec!["sip:{number}@pbx.example.com".to_string()])]),
+    }
+}
+
+// Returns the name of a number in e164.arpa
+fn name(number: &str) -> LowerName {
+    LowerName::from(Name::from_str(&e164::reverse_name(&e164::digits(number).unwrap(), "e164.arpa")).unwrap())
+}
+
+#[test]
+fn numbers_are_named_by_their_reversed_digits() {
+    assert_eq!(e164::digits("+44 (20) 7946-0000").as_deref(), Some("442079460000"));
+    assert_eq!(e164::digits("1234567890123456"), None);
+    assert_eq!(e164::digits("+44 20 CALL NOW"), None);
+    assert_eq!(e164::reverse_name("4420", "e164.arpa."), "0.2.4.4.e164.arpa.");
+
+    assert_eq!(e164::service("sip:alice@example.com"), Some("E2U+sip"));
+    assert_eq!(e164::service("MAILTO:alice@example.com"), Some("E2U+email:mailto"));
+    assert_eq!(e164::service("gopher:example.com"), None);
+
+    let zone = EnumZone::new(&config()).unwrap();
+    assert_eq!(zone.number(&name("+442079460000")).as_deref(), Some("442079460000"));
+    let not_a_number = LowerName::from(Name::from_str("www.e164.arpa.").unwrap());
+    assert!(zone.serves(&not_a_number));
+    assert_eq!(zone.number(&not_a_number), None);
+}
+
+#[test]
+fn numbers_map_to_naptr_records_and_prefixes_fill_in_the_number() {
+    let zone = EnumZone::new(&config()).unwrap();
+
+    let records = zone.lookup(&name("+442079460000")).unwrap();
+    let naptrs: Vec<(u16, u16, Vec<u8>, Vec<u8>)> = records
+        .iter()
+        .map(|record| match record.data() {
+            Some(RData::NAPTR(naptr)) => (naptr.order(), naptr.preference(), naptr.services().to_vec(), naptr.regexp().to_vec()),
+            other => panic!("not a NAPTR record: {other:?}"),
+        })
+        .collect();
+    assert_eq!(
+        naptrs,
+        [
+            (100, 10, b"E2U+sip".to_vec(), b"!^.*$!sip:reception@pbx.example.com!".to_vec()),
+            (100, 20, b"E2U+email:mailto".to_vec(), b"!^.*$!mailto:reception@example.com!".to_vec()),
+        ]
+    );
+
+    assert_eq!(zone.uris("442079460123"), Some(vec!["sip:+442079460123@pbx.example.com".to_string()]));
+    assert_eq!(zone.lookup(&name("+442079460123")).unwrap().len(), 1);
+
+    // The names on the way to a number exist, others don't
+    assert_eq!(zone.lookup(&name("+4420")), Some(Vec::new()));
+    assert_eq!(zone.lookup(&name("+442079469999")), None);
+}
+
+#[test]
+fn numbers_and_uris_are_checked() {
+    let problem = |change: fn(&mut EnumConfig)| {
+        let mut config = config();
+        change(&mut config);
+        EnumZone::new(&config).unwrap_err()
+    };
+    assert!(problem(|config| {
+        config.numbers.insert("555-CALL".to_string(), vec!["sip:a@example.com".to_string()]);
+    }).contains("not an E.164 number"));
+    assert!(problem(|config| {
+        config.numbers.insert("+15550100".to_string(), vec!["gopher:example.com".to_string()]);
+    }).contains("no ENUM service"));
+    assert!(problem(|config| {
+        config.numbers.insert("+15550100".to_string(), Vec::new());
+    }).contains("has no URIs"));
+    assert!(problem(|config| {
+        config.numbers.insert("+442079460000".to_string(), vec!["sip:a@example.com".to_string()]);
+    }).contains("more than once"));
+}
+
+#[tokio::test]
+async fn naptr_queries_are_answered_and_missing_numbers_do_not_exist() {
+    let mut options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    options.config.enum_zone = Some(config());
+    let handler = Handler::from_options(&options);
+
+    let ask = |handler: Handler, name: LowerName, record_type: RecordType| {
+        let request = RequestBuilder::new(Name::from(name), record_type).build();
+        async move {
+            let responder = CaptureResponder::default();
+            handler.handle_request(&request, responder.clone()).await;
+            Message::from_vec(&responder.take().unwrap()).unwrap()
+        }
+    };
+    let answer = ask(handler.clone(), name("+442079460000"), RecordType::NAPTR).await;
+    assert!(answer.authoritative());
+    assert_eq!(answer.answers().len(), 2);
+    assert_eq!(answer.answers()[0].ttl(), 300);
+
+    let answer = ask(handler.clone(), name("+442079460000"), RecordType::A).await;
+    assert_eq!(answer.response_code(), ResponseCode::NoError);
+    assert!(answer.answers().is_empty());
+
+    let answer = ask(handler, name("+15550100"), RecordType::NAPTR).await;
+    assert_eq!(answer.response_code(), ResponseCode::NXDomain);
+}
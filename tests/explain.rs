@@ -0,0 +1,59 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{net::SocketAddr, str::FromStr};
+use trust_dns_server::{
+    client::rr::{LowerName, Name, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// The client every query comes from
+const CLIENT: &str = "192.0.2.7:53000";
+
+// Explains a query to a handler started with the given flags and answers it, returning the checks and the response code
+async fn explain_and_answer(flags: &[&str], name: &str) -> (Vec<(&'static str, String)>, ResponseCode) {
+    let mut arguments = vec!["rusty-dns", "--domain", "mentisnovae.tech"];
+    arguments.extend_from_slice(flags);
+    let handler = Handler::from_options(&Options::parse_from(arguments));
+    let name = Name::from_str(name).unwrap();
+    let client = CLIENT.parse::<SocketAddr>().unwrap();
+
+    let steps = handler.explain(&LowerName::new(&name), RecordType::TXT, client.ip());
+    let request = RequestBuilder::new(name, RecordType::TXT).source(client).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    let response = Message::from_vec(&responder.take().unwrap()).unwrap();
+    (steps, response.response_code())
+}
+
+#[tokio::test]
+async fn explain_stops_where_the_query_is_refused() {
+    let (steps, response_code) = explain_and_answer(&["--deny", "192.0.2.0/24"], "coin.mentisnovae.tech.").await;
+    assert_eq!(steps.len(), 1);
+    assert_eq!(steps[0].0, "acl");
+    assert!(steps[0].1.starts_with("REFUSED: 192.0.2.7 is not allowed"), "{steps:?}");
+    assert_eq!(response_code, ResponseCode::Refused);
+}
+
+#[tokio::test]
+async fn explain_stops_where_the_name_breaks_the_limits() {
+    let (steps, response_code) = explain_and_answer(&["--max-labels", "3"], "a.b.coin.mentisnovae.tech.").await;
+    let checks: Vec<&str> = steps.iter().map(|(check, _)| *check).collect();
+    assert_eq!(checks, ["acl", "limits"]);
+    assert!(steps[1].1.starts_with("FORMERR: "), "{steps:?}");
+    assert_eq!(response_code, ResponseCode::FormErr);
+}
+
+#[tokio::test]
+async fn explain_goes_through_the_checks_in_order_to_the_handler() {
+    let (steps, response_code) = explain_and_answer(&["--response-cache-size", "16"], "coin.mentisnovae.tech.").await;
+    let checks: Vec<&str> = steps.iter().map(|(check, _)| *check).collect();
+    assert_eq!(checks, ["acl", "limits", "zone", "policy", "overload", "cache", "handler"]);
+    assert_eq!(steps[2].1, "the coin fixed zone, with the arguments []");
+    assert_eq!(steps[6].1, "answered by the coin zone's handler");
+    assert_eq!(response_code, ResponseCode::NoError);
+}
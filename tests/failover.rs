@@ -0,0 +1,153 @@
+use clap::Parser;
+use my_project::{
+    config::{FailoverConfig, HealthCheck},
+    failover::Failover,
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use trust_dns_server::{
+    client::rr::{LowerName, Name, RecordType},
+    proto::op::Message,
+    server::RequestHandler,
+};
+
+// api.example.com on two loopback addresses, checked by TCP on a port
+fn config(port: u16) -> FailoverConfig {
+    FailoverConfig {
+        name: "api.example.com".to_string(),
+        addresses: vec!["127.0.0.1".parse().unwrap(), "127.0.0.2".parse().unwrap()],
+        check: HealthCheck::Tcp,
+        port: Some(port),
+        path: "/".to_string(),
+        interval: 10,
+        timeout: 1,
+        healthy_threshold: 2,
+        unhealthy_threshold: 2,
+        ttl: 30,
+        fail_open: true,
+    }
+}
+
+// Returns the addresses failover answers api.example.com with
+fn addresses(failover: &Failover, record_type: RecordType) -> Option<Vec<String>> {
+    let name = LowerName::from(Name::from_str("api.example.com.").unwrap());
+    let records = failover.answer(&name, record_type)?;
+    Some(records.iter().map(|record| record.data().unwrap().to_string()).collect())
+}
+
+// Starts an HTTP server on 127.0.0.1 that answers every request with the status it is set to
+async fn http_server(status: Arc<AtomicU16>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let status = status.load(Ordering::SeqCst);
+            let mut buffer = vec![0; 4096];
+            let _ = stream.read(&mut buffer).await;
+            let response = format!("HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn targets_that_fail_their_checks_are_left_out_after_the_threshold() {
+    // Only 127.0.0.1 accepts connections on the port
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let failover = Failover::new(&[config(listener.local_addr().unwrap().port())]).unwrap();
+    assert_eq!(addresses(&failover, RecordType::A).unwrap(), ["127.0.0.1", "127.0.0.2"]);
+    assert_eq!(addresses(&failover, RecordType::AAAA), None);
+
+    failover.check_once().await;
+    assert_eq!(addresses(&failover, RecordType::A).unwrap(), ["127.0.0.1", "127.0.0.2"]);
+    failover.check_once().await;
+    assert_eq!(addresses(&failover, RecordType::A).unwrap(), ["127.0.0.1"]);
+
+    let name = LowerName::from(Name::from_str("api.example.com.").unwrap());
+    let status = failover.status(&name).unwrap();
+    assert_eq!(status, [("127.0.0.1".parse::<IpAddr>().unwrap(), true), ("127.0.0.2".parse().unwrap(), false)]);
+}
+
+#[tokio::test]
+async fn all_targets_are_answered_when_all_are_down_unless_failing_closed() {
+    let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+    let mut config = config(port);
+    config.unhealthy_threshold = 1;
+
+    let failover = Failover::new(&[config.clone()]).unwrap();
+    failover.check_once().await;
+    assert_eq!(addresses(&failover, RecordType::A).unwrap(), ["127.0.0.1", "127.0.0.2"]);
+
+    config.fail_open = false;
+    let failover = Failover::new(&[config]).unwrap();
+    failover.check_once().await;
+    assert_eq!(addresses(&failover, RecordType::A).unwrap(), Vec::<String>::new());
+}
+
+#[tokio::test]
+async fn http_checks_need_a_successful_status_to_come_back_up() {
+    let status = Arc::new(AtomicU16::new(503));
+    let mut config = config(http_server(status.clone()).await);
+    config.addresses = vec!["127.0.0.1".parse().unwrap()];
+    config.check = HealthCheck::Http;
+    config.path = "/healthz".to_string();
+    config.unhealthy_threshold = 1;
+    config.fail_open = false;
+    let failover = Failover::new(&[config]).unwrap();
+
+    failover.check_once().await;
+    assert_eq!(addresses(&failover, RecordType::A).unwrap(), Vec::<String>::new());
+
+    status.store(200, Ordering::SeqCst);
+    failover.check_once().await;
+    assert_eq!(addresses(&failover, RecordType::A).unwrap(), Vec::<String>::new());
+    failover.check_once().await;
+    assert_eq!(addresses(&failover, RecordType::A).unwrap(), ["127.0.0.1"]);
+}
+
+#[test]
+fn targets_are_checked() {
+    let problem = |change: fn(&mut FailoverConfig)| {
+        let mut config = config(80);
+        change(&mut config);
+        Failover::new(&[config]).unwrap_err()
+    };
+    assert!(problem(|config| config.addresses.clear()).contains("has no addresses"));
+    assert!(problem(|config| config.port = None).contains("need a port"));
+    assert!(Failover::new(&[config(80), config(80)]).unwrap_err().contains("more than once"));
+
+    let mut http = config(80);
+    http.check = HealthCheck::Http;
+    http.port = None;
+    assert!(Failover::new(&[http]).is_ok());
+}
+
+#[tokio::test]
+async fn failover_names_are_answered_authoritatively_with_their_ttl() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    options.config.failover = vec![config(listener.local_addr().unwrap().port())];
+    let handler = Handler::from_options(&options);
+
+    let request = RequestBuilder::new(Name::from_str("api.example.com.").unwrap(), RecordType::A).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    let answer = Message::from_vec(&responder.take().unwrap()).unwrap();
+    assert!(answer.authoritative());
+    assert_eq!(answer.answers().len(), 2);
+    assert_eq!(answer.answers()[0].ttl(), 30);
+}
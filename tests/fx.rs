@@ -0,0 +1,189 @@
+use clap::Parser;
+use my_project::{
+    fx::{self, Conversion, FxError, FxRates, RateTable},
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use trust_dns_server::{
+    client::rr::{Name, RData, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// Answers a TXT query for a name with a handler serving example.net and fetching rates from api_url
+async fn answer(api_url: &str, name: &str) -> Message {
+    let cache_dir = std::env::temp_dir().join(format!("rusty-dns-fx-{}", std::process::id()));
+    let cache_dir = cache_dir.to_str().unwrap();
+    let options = Options::parse_from(["rusty-dns", "--domain", "example.net", "--fx-api-url", api_url, "--fx-cache-dir", cache_dir]);
+    let handler = Handler::from_options(&options);
+    let request = RequestBuilder::new(Name::from_str(name).unwrap(), RecordType::TXT).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    Message::from_vec(&responder.take().unwrap()).unwrap()
+}
+
+// Returns the text of the first TXT record of a section
+fn text(records: &[trust_dns_server::client::rr::Record]) -> String {
+    match records.first().and_then(|record| record.data()) {
+        Some(RData::TXT(txt)) => txt.to_string(),
+        other => panic!("expected a TXT record, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn malformed_conversions_are_explained() {
+    // Nothing listens on the discard port, so a query that got as far as the API would fail differently
+    for (name, explanation) in [
+        ("fx.100.usd.example.net.", "expected fx.<amount>.<from>.<to>"),
+        ("fx.100.usd.eur.gbp.example.net.", "expected fx.<amount>.<from>.<to>"),
+        ("fx.lots.usd.eur.example.net.", "invalid amount lots"),
+        ("fx.1_2_3.usd.eur.example.net.", "invalid amount 1_2_3"),
+        ("fx.100.dollar.eur.example.net.", "Unknown currency dollar"),
+        ("fx.100.usd.e1r.example.net.", "Unknown currency e1r"),
+    ] {
+        let answer = answer("http://127.0.0.1:9/{base}", name).await;
+        assert_eq!(answer.response_code(), ResponseCode::FormErr, "{name}");
+        assert!(answer.answers().is_empty(), "{name}");
+        assert!(text(answer.additionals()).contains(explanation), "{name}: {}", text(answer.additionals()));
+    }
+}
+
+#[tokio::test]
+async fn unavailable_rates_are_a_server_failure() {
+    let answer = answer("http://127.0.0.1:9/{base}", "fx.100.chf.sek.example.net.").await;
+    assert_eq!(answer.response_code(), ResponseCode::ServFail);
+    assert!(text(answer.additionals()).contains("HTTP error"), "{}", text(answer.additionals()));
+}
+
+// Returns an empty cache directory of its own for a test
+fn cache_dir(test: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rusty-dns-fx-{test}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    path
+}
+
+// Serves an exchange-rate API on a local port that knows USD and nothing else, taking a while to answer.
+// Returns the API URL and the number of requests it has answered.
+async fn api() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/latest/{{base}}", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counted = requests.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let counted = counted.clone();
+            tokio::spawn(async move {
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                counted.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let body = match request.starts_with("GET /latest/USD ") {
+                    true => r#"{"result":"success","rates":{"USD":1.0,"EUR":0.5}}"#,
+                    false => r#"{"result":"error","rates":{}}"#,
+                };
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len());
+                stream.write_all(response.as_bytes()).await.unwrap();
+            });
+        }
+    });
+    (url, requests)
+}
+
+#[test]
+fn currency_codes_are_three_letters() {
+    assert_eq!(fx::normalize_code("usd").unwrap(), "USD");
+    assert_eq!(fx::normalize_code("Eur").unwrap(), "EUR");
+    for code in ["us", "usdt", "u5d", "", "\u{e9}ur"] {
+        assert!(matches!(fx::normalize_code(code), Err(FxError::UnknownCurrency(_))), "{code}");
+    }
+}
+
+#[test]
+fn freshness_uses_the_largest_unit() {
+    let conversion = |age, stale| Conversion { converted: 1.0, rate: 1.0, age, stale };
+    assert_eq!(fx::freshness(&conversion(42, false)), "updated 42s ago");
+    assert_eq!(fx::freshness(&conversion(300, false)), "updated 5m ago");
+    assert_eq!(fx::freshness(&conversion(3 * 3600 + 59, true)), "stale, updated 3h ago");
+    assert_eq!(fx::freshness(&conversion(2 * 86400, true)), "stale, updated 2d ago");
+}
+
+#[tokio::test]
+async fn rates_are_fetched_once_per_base_currency() {
+    let (url, requests) = api().await;
+    let rates = FxRates::new(&url, cache_dir("fetch"), Duration::from_secs(3600));
+
+    // Queries arriving while the rates are being fetched wait for that fetch
+    let convert = || rates.convert(10.0, "usd", "eur");
+    let conversions = tokio::join!(convert(), convert(), convert(), convert(), convert());
+    for conversion in [conversions.0, conversions.1, conversions.2, conversions.3, conversions.4] {
+        let conversion = conversion.unwrap();
+        assert_eq!((conversion.converted, conversion.rate, conversion.stale), (5.0, 0.5, false));
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    // Later ones are answered from memory, and a target the table lacks needs no fetch either
+    rates.convert(1.0, "USD", "eur").await.unwrap();
+    assert!(matches!(rates.convert(1.0, "usd", "xyz").await, Err(FxError::UnknownCurrency(code)) if code == "XYZ"));
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    assert_eq!(rates.cached_tables(), 1);
+}
+
+#[tokio::test]
+async fn failed_fetches_are_not_retried_at_once() {
+    let (url, requests) = api().await;
+    let rates = FxRates::new(&url, cache_dir("negative"), Duration::from_secs(3600));
+
+    // The API doesn't know XXX: the second query is answered from the failure the first one saw
+    for _ in 0..2 {
+        assert!(matches!(rates.convert(1.0, "xxx", "usd").await, Err(FxError::UnknownCurrency(code)) if code == "XXX"));
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    assert_eq!(rates.failures(), 0);
+
+    // An API that can't be reached counts as a failure, once
+    let rates = FxRates::new("http://127.0.0.1:9/{base}", cache_dir("unreachable"), Duration::from_secs(3600));
+    assert!(matches!(rates.convert(1.0, "usd", "eur").await, Err(FxError::Http(_))));
+    assert!(matches!(rates.convert(1.0, "usd", "eur").await, Err(FxError::Unavailable(_))));
+    assert_eq!(rates.failures(), 1);
+}
+
+#[tokio::test]
+async fn stale_rates_are_used_while_the_api_is_down() {
+    // Rates cached on disk a day ago, older than the maximum age of an hour
+    let dir = cache_dir("stale");
+    std::fs::create_dir_all(&dir).unwrap();
+    let table = RateTable {
+        base: "USD".to_string(),
+        fetched_at: chrono::Utc::now().timestamp() - 86400,
+        rates: HashMap::from([("EUR".to_string(), 0.9)]),
+    };
+    std::fs::write(dir.join("USD.json"), serde_json::to_vec(&table).unwrap()).unwrap();
+
+    let rates = FxRates::new("http://127.0.0.1:9/{base}", &dir, Duration::from_secs(3600));
+    let conversion = rates.convert(100.0, "usd", "eur").await.unwrap();
+    assert_eq!(conversion.rate, 0.9);
+    assert!(conversion.stale);
+    assert_eq!(fx::freshness(&conversion), "stale, updated 1d ago");
+    assert_eq!(rates.failures(), 1);
+
+    // Within the negative cache the stale rates are served without asking the API again
+    assert!(rates.convert(100.0, "usd", "eur").await.unwrap().stale);
+    assert_eq!(rates.failures(), 1);
+}
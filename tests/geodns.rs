@@ -0,0 +1,110 @@
+use clap::Parser;
+use my_project::{
+    config::{Config, ConfigError, GeoConfig},
+    geodns::{self, GeoDns},
+    geoip::Location,
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{collections::BTreeMap, net::IpAddr, str::FromStr};
+use trust_dns_server::{
+    client::rr::{LowerName, Name, RecordType},
+    proto::{
+        op::Message,
+        rr::rdata::opt::{EdnsCode, EdnsOption},
+    },
+    server::RequestHandler,
+};
+
+// The answer sets of www.example.com: one for the US, one for Europe and a fallback
+fn config() -> GeoConfig {
+    GeoConfig {
+        name: "www.example.com".to_string(),
+        record_type: "A".to_string(),
+        ttl: 60,
+        countries: BTreeMap::from([("us".to_string(), vec!["198.51.100.10".to_string()])]),
+        continents: BTreeMap::from([("EU".to_string(), vec!["192.0.2.10".to_string(), "192.0.2.11".to_string()])]),
+        fallback: vec!["203.0.113.10".to_string()],
+    }
+}
+
+// Returns the location of a country in a continent
+fn location(country: &str, continent: &str) -> Location {
+    Location {
+        country: Some(country.to_string()),
+        continent: Some(continent.to_string()),
+    }
+}
+
+// Returns the addresses a request for www.example.com from a location gets
+fn addresses(geo: &GeoDns, location: &Location) -> Vec<String> {
+    let name = LowerName::from(Name::from_str("www.example.com.").unwrap());
+    let records = geo.answer(&name, RecordType::A, location).unwrap();
+    records.iter().map(|record| record.data().unwrap().to_string()).collect()
+}
+
+#[test]
+fn countries_take_precedence_over_continents_and_the_fallback() {
+    let geo = GeoDns::new(&[config()]).unwrap();
+    assert_eq!(addresses(&geo, &location("US", "NA")), ["198.51.100.10"]);
+    assert_eq!(addresses(&geo, &location("DE", "EU")), ["192.0.2.10", "192.0.2.11"]);
+    assert_eq!(addresses(&geo, &location("JP", "AS")), ["203.0.113.10"]);
+    assert_eq!(addresses(&geo, &Location::default()), ["203.0.113.10"]);
+
+    let name = LowerName::from(Name::from_str("www.example.com.").unwrap());
+    assert!(geo.serves(&name));
+    assert_eq!(geo.answer(&name, RecordType::AAAA, &Location::default()), None);
+}
+
+#[test]
+fn answer_sets_are_checked() {
+    let problem = |change: fn(&mut GeoConfig)| {
+        let mut config = config();
+        change(&mut config);
+        GeoDns::new(&[config]).unwrap_err()
+    };
+    assert!(problem(|config| config.fallback.clear()).contains("fallback set is empty"));
+    assert!(problem(|config| config.fallback = vec!["not-an-address".to_string()]).contains("invalid A data"));
+    assert!(problem(|config| {
+        config.countries.insert("USA".to_string(), Vec::new());
+    }).contains("not an ISO country code"));
+    assert!(problem(|config| {
+        config.continents.insert("EUROPE".to_string(), Vec::new());
+    }).contains("not a continent code"));
+    assert!(GeoDns::new(&[config(), config()]).unwrap_err().contains("more than once"));
+
+    let path = std::env::temp_dir().join(format!("rusty-dns-geodns-{}.toml", std::process::id()));
+    std::fs::write(&path, "[[geo]]\nname = \"www.example.com\"\nfallback = [\"203.0.113.10\"]\n").unwrap();
+    let loaded = Config::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(loaded, Err(ConfigError::Invalid(_, message)) if message.contains("[geoip]")));
+}
+
+#[tokio::test]
+async fn client_subnets_are_read_and_echoed() {
+    let mut options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    options.config.geo = vec![config()];
+    let handler = Handler::from_options(&options);
+
+    // 198.51.100.0/24
+    let subnet = EdnsOption::from((EdnsCode::Subnet, &[0u8, 1, 24, 0, 198, 51, 100][..]));
+    let request = RequestBuilder::new(Name::from_str("www.example.com.").unwrap(), RecordType::A).edns_option(subnet).build();
+    assert_eq!(geodns::client_subnet(&request), Some((IpAddr::from([198, 51, 100, 0]), 24)));
+
+    // Without a GeoIP database every client is in an unknown location and gets the fallback set
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    let answer = Message::from_vec(&responder.take().unwrap()).unwrap();
+    assert!(answer.authoritative());
+    assert_eq!(answer.answers()[0].data().unwrap().to_string(), "203.0.113.10");
+    let echoed = answer.extensions().as_ref().unwrap().options().get(EdnsCode::Subnet).unwrap();
+    assert_eq!(Vec::<u8>::from(echoed), [0, 1, 24, 24, 198, 51, 100]);
+
+    // Types without answer sets get an empty answer
+    let request = RequestBuilder::new(Name::from_str("www.example.com.").unwrap(), RecordType::AAAA).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    let answer = Message::from_vec(&responder.take().unwrap()).unwrap();
+    assert!(answer.answers().is_empty());
+}
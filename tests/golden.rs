@@ -0,0 +1,184 @@
+use clap::Parser;
+use my_project::{cache::CaptureResponder, handlers::Handler, options::Options};
+use std::{fmt::Write, fs, net::SocketAddr, path::PathBuf};
+use tokio::runtime::Runtime;
+use trust_dns_server::{
+    authority::MessageRequest,
+    client::rr::{Name, RecordType},
+    proto::{
+        op::{Message, MessageType, OpCode, Query},
+        serialize::binary::BinDecodable,
+    },
+    server::{Protocol, Request, RequestHandler},
+};
+
+// The domain the handler serves.
+const DOMAIN: &str = "mentisnovae.tech";
+
+// The seed of the random zones, so their answers repeat.
+const SEED: &str = "42";
+
+// The time the date and time zones' clock is stopped at, so their answers repeat.
+const CLOCK: &str = "2024-02-29T12:00:00Z";
+
+// The ID of every query, which the answers echo.
+const ID: u16 = 4242;
+
+// The queries whose answers are compared with their golden files, as (golden file, query name below the domain, record type).
+// Zones whose answers depend on the network or on the server's own state (top, stats, health, fx) are left out, apart from their malformed queries.
+const CASES: &[(&str, &str, RecordType)] = &[
+    ("myip-txt", "myip", RecordType::TXT),
+    ("myip-a", "myip", RecordType::A),
+    ("myip-aaaa", "myip", RecordType::AAAA),
+    ("counter", "counter", RecordType::TXT),
+    ("coin", "coin", RecordType::TXT),
+    ("coin-a", "coin", RecordType::A),
+    ("dice", "dice", RecordType::TXT),
+    ("lotto", "lotto.6.49", RecordType::TXT),
+    ("lotto-aaaa", "lotto.6.49", RecordType::AAAA),
+    ("lotto-malformed", "lotto.60.49", RecordType::TXT),
+    ("rps", "rps.rock", RecordType::TXT),
+    ("rps-malformed", "rps.lizard", RecordType::TXT),
+    ("name", "name.3", RecordType::TXT),
+    ("name-malformed", "name.21", RecordType::TXT),
+    ("top-malformed", "top.0", RecordType::TXT),
+    ("health-malformed", "health.now", RecordType::TXT),
+    ("stats-malformed", "stats.servers", RecordType::TXT),
+    ("time", "time.1700000000", RecordType::TXT),
+    ("moon", "moon.2023-04-20", RecordType::TXT),
+    ("moon-now", "moon", RecordType::TXT),
+    ("moon-malformed", "moon.2023-02-30", RecordType::TXT),
+    ("sun", "sun.12_97.77_59.+0530", RecordType::TXT),
+    ("sun-malformed", "sun.91.77_59", RecordType::TXT),
+    ("week", "week.2023-04-18", RecordType::TXT),
+    ("week-now", "week", RecordType::TXT),
+    ("week-malformed", "week.2023.04.18", RecordType::TXT),
+    ("age", "age.1990-05-17", RecordType::TXT),
+    ("age-malformed", "age.2030-01-01", RecordType::TXT),
+    ("str-reverse", "str.reverse.Hello", RecordType::TXT),
+    ("str-length", "str.length.hello.world", RecordType::TXT),
+    ("str-malformed", "str.shuffle.hello", RecordType::TXT),
+    ("puny", "puny.bücher", RecordType::TXT),
+    ("unpuny", "unpuny.xn--80ak6aa92e", RecordType::TXT),
+    ("unpuny-malformed", "unpuny.xn--a", RecordType::TXT),
+    ("luhn", "luhn.4111111111111111", RecordType::TXT),
+    ("luhn-malformed", "luhn.4111-1111", RecordType::TXT),
+    ("crc32", "crc32.hello", RecordType::TXT),
+    ("crc32-malformed", "crc32", RecordType::TXT),
+    ("fib", "fib.10", RecordType::TXT),
+    ("fact", "fact.100", RecordType::TXT),
+    ("fact-malformed", "fact.441", RecordType::TXT),
+    ("ulid", "ulid", RecordType::TXT),
+    ("ulid-decode", "ulid.01gya3m2z3pq8x4n9c7t6r5e1w", RecordType::TXT),
+    ("ulid-malformed", "ulid.01gya3m2z3pq8x4n9c7t6r5e1", RecordType::TXT),
+    ("unknown-keyword", "nosuchzone", RecordType::TXT),
+    ("root", "", RecordType::TXT),
+];
+
+// Returns the directory of the golden files
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+// Builds a query as it would arrive over UDP from a fixed client; the labels are taken as raw bytes, as they are off the wire
+fn query(label: &str, record_type: RecordType) -> (Query, Vec<u8>) {
+    let name = match label {
+        "" => DOMAIN.to_string(),
+        label => format!("{label}.{DOMAIN}"),
+    };
+    let mut name = Name::from_labels(name.split('.').map(str::as_bytes)).unwrap();
+    name.set_fqdn(true);
+
+    let query = Query::query(name, record_type);
+    let mut message = Message::new();
+    message
+        .set_id(ID)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .add_query(query.clone());
+    (query, message.to_vec().unwrap())
+}
+
+// Answers a query with a new handler, with its own seeded generator, so no answer depends on the cases before it
+fn answer(runtime: &Runtime, bytes: &[u8]) -> Vec<u8> {
+    let options = Options::parse_from(["rusty-dns", "--domain", DOMAIN, "--seed", SEED, "--clock", CLOCK]);
+    let handler = {
+        let _guard = runtime.enter();
+        Handler::from_options(&options)
+    };
+
+    let request = Request::new(
+        MessageRequest::from_bytes(bytes).unwrap(),
+        SocketAddr::from(([192, 0, 2, 7], 53000)),
+        Protocol::Udp,
+    );
+    let responder = CaptureResponder::default();
+    runtime.block_on(handler.handle_request(&request, responder.clone()));
+    responder.take().expect("the handler sent no answer")
+}
+
+// Writes bytes as lines of 16 hexadecimal bytes
+fn to_hex(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for line in bytes.chunks(16) {
+        let line: Vec<String> = line.iter().map(|byte| format!("{byte:02x}")).collect();
+        writeln!(text, "{}", line.join(" ")).unwrap();
+    }
+    text
+}
+
+// Reads bytes written by to_hex
+fn from_hex(text: &str) -> Vec<u8> {
+    text.split_whitespace().map(|byte| u8::from_str_radix(byte, 16).unwrap()).collect()
+}
+
+// Describes a decoded answer for failure messages: its header and one line per record
+fn describe(bytes: &[u8]) -> String {
+    let message = match Message::from_vec(bytes) {
+        Ok(message) => message,
+        Err(error) => return format!("undecodable: {error}"),
+    };
+    let mut text = format!("{:?}\n", message.header());
+    for record in message.answers().iter().chain(message.name_servers()).chain(message.additionals()) {
+        writeln!(text, "{record}").unwrap();
+    }
+    text
+}
+
+/*
+Description:
+answers every case and checks the answer twice: decoded again with the protocol library, it must be a well-formed response to the query that survives being encoded and decoded again, and its bytes must equal the golden file. Run with UPDATE_GOLDEN=1 to write the golden files from the current answers after a deliberate change, and review the diff.
+*/
+#[test]
+fn answers_match_golden_files() {
+    let runtime = Runtime::new().unwrap();
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+
+    for (case, label, record_type) in CASES {
+        let (query, bytes) = query(label, *record_type);
+        let answer = answer(&runtime, &bytes);
+
+        let message = Message::from_vec(&answer).unwrap_or_else(|error| panic!("{case}: the answer doesn't decode: {error}"));
+        assert_eq!(message.id(), ID, "{case}: wrong ID");
+        assert_eq!(message.message_type(), MessageType::Response, "{case}: not a response");
+        assert_eq!(message.op_code(), OpCode::Query, "{case}: wrong op code");
+        assert_eq!(message.queries(), [query], "{case}: the question isn't echoed");
+        assert_eq!(Message::from_vec(&message.to_vec().unwrap()).unwrap(), message, "{case}: the answer doesn't survive encoding it again");
+
+        let path = golden_dir().join(format!("{case}.hex"));
+        if update {
+            fs::create_dir_all(golden_dir()).unwrap();
+            fs::write(&path, to_hex(&answer)).unwrap();
+            continue;
+        }
+        let golden = fs::read_to_string(&path).unwrap_or_else(|error| panic!("{case}: can't read {}: {error}", path.display()));
+        let golden = from_hex(&golden);
+        if golden != answer {
+            failures.push(format!("{case}:\nexpected:\n{}got:\n{}", describe(&golden), describe(&answer)));
+        }
+    }
+
+    assert!(failures.is_empty(), "answers differ from their golden files (UPDATE_GOLDEN=1 rewrites them):\n\n{}", failures.join("\n"));
+}
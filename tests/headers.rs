@@ -0,0 +1,60 @@
+use clap::Parser;
+use my_project::{
+    config::ZoneConfig,
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::str::FromStr;
+use trust_dns_server::{
+    client::rr::{Name, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// Asks a handler serving mentisnovae.tech for a name, with or without RD set
+async fn answer(name: Name, record_type: RecordType, recursion_desired: bool) -> Message {
+    let handler = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]));
+    ask(&handler, name, record_type, recursion_desired).await
+}
+
+// Asks a handler for a name, with or without RD set
+async fn ask(handler: &Handler, name: Name, record_type: RecordType, recursion_desired: bool) -> Message {
+    let request = RequestBuilder::new(name, record_type)
+        .id(4242)
+        .recursion_desired(recursion_desired)
+        .build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    Message::from_vec(&responder.take().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn answers_echo_rd_and_never_offer_recursion() {
+    let queries = [
+        (Name::from_str("dice.mentisnovae.tech.").unwrap(), RecordType::TXT, ResponseCode::NoError),
+        (Name::from_str("nowhere.mentisnovae.tech.").unwrap(), RecordType::A, ResponseCode::NXDomain),
+        (Name::from_labels([b"a b".as_slice(), b"dice", b"mentisnovae", b"tech"]).unwrap(), RecordType::TXT, ResponseCode::FormErr),
+    ];
+    for (name, record_type, response_code) in queries {
+        for recursion_desired in [true, false] {
+            let answer = answer(name.clone(), record_type, recursion_desired).await;
+            assert_eq!(answer.response_code(), response_code, "{name}");
+            assert_eq!(answer.id(), 4242);
+            assert_eq!(answer.recursion_desired(), recursion_desired, "{name}");
+            assert!(!answer.recursion_available(), "{name}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn zone_ttls_override_the_defaults() {
+    let mut options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    options.config.zones.insert("coin".to_string(), ZoneConfig { ttl: Some(5), ..Default::default() });
+    let handler = Handler::from_options(&options);
+
+    let coin = ask(&handler, Name::from_str("coin.mentisnovae.tech.").unwrap(), RecordType::TXT, false).await;
+    assert_eq!(coin.answers()[0].ttl(), 5);
+    let dice = ask(&handler, Name::from_str("dice.mentisnovae.tech.").unwrap(), RecordType::TXT, false).await;
+    assert_eq!(dice.answers()[0].ttl(), 60);
+}
@@ -0,0 +1,146 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    hostsfile::{self, HostsFiles},
+    options::Options,
+    testing::answer,
+};
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use trust_dns_server::{
+    client::rr::RecordType,
+    proto::op::{Message, ResponseCode},
+};
+
+// A LAN's static addressing, with the comments, aliases and odd lines hosts files have.
+const HOSTS: &str = "
+# Static addresses
+127.0.0.1   localhost
+192.168.1.10  nas.mentisnovae.tech  NAS files   # the file server
+192.168.1.11  printer.
+fd00::10      nas
+not-an-address  broken
+192.168.1.12
+";
+
+// Returns a directory of its own for a test's hosts files, removing any left by an earlier run
+fn directory(test: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rusty-dns-hosts-{test}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+// Writes a hosts file the way editors save one, to a temporary file renamed over it
+fn save(path: &Path, text: &str) {
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, text).unwrap();
+    std::fs::rename(&temporary, path).unwrap();
+}
+
+// Returns the data of each record of an answer, in order
+fn rdata(answer: &Message) -> Vec<String> {
+    answer.answers().iter().map(|record| record.to_string().rsplit(' ').next().unwrap().to_string()).collect()
+}
+
+#[test]
+fn entries_are_read_with_their_aliases_and_without_the_domain() {
+    let directory = directory("parse");
+    let path = directory.join("hosts");
+    std::fs::write(&path, HOSTS).unwrap();
+    let hosts = HostsFiles::new("mentisnovae.tech", vec![path]);
+
+    let addresses = |name: &str| hosts.addresses(name).iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(addresses("nas"), ["192.168.1.10", "fd00::10"]);
+    assert_eq!(addresses("Files"), ["192.168.1.10"]);
+    assert_eq!(addresses("printer"), ["192.168.1.11"]);
+    assert!(addresses("broken").is_empty());
+    assert!(addresses("the").is_empty());
+    assert_eq!(hosts.names(), 4);
+    assert_eq!(hosts.names_of("192.168.1.10".parse().unwrap()), ["nas", "files"]);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn reverse_names_stand_for_addresses() {
+    let address = |name: &str| hostsfile::reverse_address(name).map(|address| address.to_string());
+    assert_eq!(address("10.1.168.192.in-addr.arpa."), Some("192.168.1.10".to_string()));
+    assert_eq!(
+        address("0.1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.d.f.IP6.ARPA"),
+        Some("fd00::10".to_string())
+    );
+    assert_eq!(address("1.168.192.in-addr.arpa."), None);
+    assert_eq!(address("300.1.168.192.in-addr.arpa."), None);
+    assert_eq!(address("10.1.168.192.example.com."), None);
+    assert_eq!(address("10.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.d.f.ip6.arpa."), None);
+}
+
+#[tokio::test]
+async fn entries_are_served_as_address_and_ptr_records() {
+    let directory = directory("served");
+    let path = directory.join("hosts");
+    std::fs::write(&path, HOSTS).unwrap();
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech", "--hosts-file", path.to_str().unwrap()]);
+    let handler = Handler::from_options(&options);
+
+    let a = answer(&handler, "nas.hosts.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(rdata(&a), ["192.168.1.10"]);
+    assert_eq!(a.answers()[0].ttl(), hostsfile::TTL);
+    let aaaa = answer(&handler, "NAS.hosts.mentisnovae.tech.", RecordType::AAAA).await;
+    assert_eq!(rdata(&aaaa), ["fd00::10"]);
+    let none = answer(&handler, "printer.hosts.mentisnovae.tech.", RecordType::AAAA).await;
+    assert_eq!(none.response_code(), ResponseCode::NoError);
+    assert!(none.answers().is_empty());
+    let missing = answer(&handler, "router.hosts.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(missing.response_code(), ResponseCode::NXDomain);
+
+    let ptr = answer(&handler, "10.1.168.192.in-addr.arpa.", RecordType::PTR).await;
+    assert_eq!(rdata(&ptr), ["nas.hosts.mentisnovae.tech.", "files.hosts.mentisnovae.tech."]);
+    let ptr6 = answer(&handler, "0.1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.d.f.ip6.arpa.", RecordType::PTR).await;
+    assert_eq!(rdata(&ptr6), ["nas.hosts.mentisnovae.tech."]);
+    let unknown = answer(&handler, "99.1.168.192.in-addr.arpa.", RecordType::PTR).await;
+    assert_eq!(unknown.response_code(), ResponseCode::NXDomain);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn changed_files_are_read_again() {
+    let directory = directory("reload");
+    let path = directory.join("hosts");
+    let other = directory.join("hosts.lan");
+    save(&path, "192.168.1.10 nas\n");
+    save(&other, "192.168.1.20 camera\n");
+    let hosts = Arc::new(HostsFiles::new("mentisnovae.tech", vec![path.clone(), other.clone()]));
+    tokio::spawn(hostsfile::watch(hosts.clone()));
+    // Give the watch a moment to start before changing anything
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let wait_for = |name: &'static str, address: &'static str| {
+        let hosts = hosts.clone();
+        async move {
+            let address: IpAddr = address.parse().unwrap();
+            for _ in 0..100 {
+                if hosts.addresses(name) == [address] {
+                    return true;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            false
+        }
+    };
+
+    save(&path, "192.168.1.99 nas\n");
+    assert!(wait_for("nas", "192.168.1.99").await);
+    std::fs::write(&other, "192.168.1.21 camera\n").unwrap();
+    assert!(wait_for("camera", "192.168.1.21").await);
+
+    // A file that goes missing leaves the entries as they were
+    std::fs::remove_file(&other).unwrap();
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(hosts.addresses("camera"), ["192.168.1.21".parse::<IpAddr>().unwrap()]);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
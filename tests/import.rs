@@ -0,0 +1,145 @@
+use my_project::import::{self, ImportedRecord, ImportedZone};
+use serde_json::json;
+use std::str::FromStr;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use trust_dns_server::client::rr::Name;
+
+// A page of ListResourceRecordSets with a wildcard, an alias, a weighted set and more pages after it
+const ROUTE53_PAGE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+  <ResourceRecordSets>
+    <ResourceRecordSet><Name>example.com.</Name><Type>SOA</Type><TTL>900</TTL><ResourceRecords><ResourceRecord><Value>ns-1.awsdns-01.org. awsdns-hostmaster.amazon.com. 1 7200 900 1209600 86400</Value></ResourceRecord></ResourceRecords></ResourceRecordSet>
+    <ResourceRecordSet><Name>example.com.</Name><Type>NS</Type><TTL>172800</TTL><ResourceRecords><ResourceRecord><Value>ns-1.awsdns-01.org.</Value></ResourceRecord><ResourceRecord><Value>ns-2.awsdns-02.com.</Value></ResourceRecord></ResourceRecords></ResourceRecordSet>
+    <ResourceRecordSet><Name>\052.example.com.</Name><Type>TXT</Type><TTL>300</TTL><ResourceRecords><ResourceRecord><Value>"a &amp; b"</Value></ResourceRecord></ResourceRecords></ResourceRecordSet>
+    <ResourceRecordSet><Name>cdn.example.com.</Name><Type>A</Type><AliasTarget><HostedZoneId>Z2FDTNDATAQYW2</HostedZoneId><DNSName>d111111abcdef8.cloudfront.net.</DNSName><EvaluateTargetHealth>false</EvaluateTargetHealth></AliasTarget></ResourceRecordSet>
+    <ResourceRecordSet><Name>api.example.com.</Name><Type>A</Type><SetIdentifier>blue</SetIdentifier><Weight>90</Weight><TTL>60</TTL><ResourceRecords><ResourceRecord><Value>192.0.2.20</Value></ResourceRecord></ResourceRecords></ResourceRecordSet>
+  </ResourceRecordSets>
+  <IsTruncated>true</IsTruncated>
+  <NextRecordName>www.example.com.</NextRecordName>
+  <NextRecordType>A</NextRecordType>
+  <MaxItems>100</MaxItems>
+</ListResourceRecordSetsResponse>"#;
+
+#[test]
+fn route53_record_sets_are_read_and_aliases_skipped() {
+    let (records, skipped) = import::route53_records(ROUTE53_PAGE);
+    let texts: Vec<String> = records.iter().map(|record| format!("{} {} {} {}", record.name, record.ttl, record.record_type, record.data)).collect();
+    assert_eq!(
+        texts,
+        [
+            "example.com. 900 SOA ns-1.awsdns-01.org. awsdns-hostmaster.amazon.com. 1 7200 900 1209600 86400",
+            "example.com. 172800 NS ns-1.awsdns-01.org.",
+            "example.com. 172800 NS ns-2.awsdns-02.com.",
+            "*.example.com. 300 TXT \"a & b\"",
+        ]
+    );
+    assert_eq!(skipped.len(), 2);
+    assert!(skipped[0].contains("cdn.example.com.") && skipped[0].contains("alias of d111111abcdef8.cloudfront.net."));
+    assert!(skipped[1].contains("api.example.com.") && skipped[1].contains("blue"));
+}
+
+#[test]
+fn cloudflare_records_are_written_as_zone_file_data() {
+    let page = json!({
+        "success": true,
+        "result": [
+            {"name": "example.com", "type": "MX", "content": "mail.example.com", "priority": 10, "ttl": 3600, "proxied": false},
+            {"name": "www.example.com", "type": "A", "content": "192.0.2.10", "ttl": 1, "proxied": true},
+            {"name": "example.com", "type": "TXT", "content": "v=spf1 include:_spf.example.net ~all", "ttl": 300},
+            {"name": "_sip._tcp.example.com", "type": "SRV", "content": "5 5060 sip.example.com", "ttl": 300,
+             "data": {"priority": 10, "weight": 5, "port": 5060, "target": "sip.example.com"}},
+            {"name": "example.com", "type": "CAA", "content": "0 issue letsencrypt.org", "ttl": 300,
+             "data": {"flags": 0, "tag": "issue", "value": "letsencrypt.org"}},
+        ],
+        "result_info": {"page": 1, "total_pages": 1}
+    });
+    let (records, skipped) = import::cloudflare_records(&page);
+    assert!(skipped.is_empty());
+    let data: Vec<&str> = records.iter().map(|record| record.data.as_str()).collect();
+    assert_eq!(
+        data,
+        ["10 mail.example.com.", "192.0.2.10", "\"v=spf1 include:_spf.example.net ~all\"", "10 5 5060 sip.example.com.", "0 issue \"letsencrypt.org\""]
+    );
+    // Automatic TTLs are Cloudflare's 300 seconds, and proxied records say so
+    assert_eq!(records[1].ttl, 300);
+    assert!(records[1].comment.as_deref().unwrap().contains("proxied"));
+    assert_eq!(records[0].comment, None);
+}
+
+#[test]
+fn imported_zones_are_servable_zone_files() {
+    // Cloudflare exports no SOA record, so one is made up from the first name server
+    let record = |name: &str, record_type: &str, data: &str| ImportedRecord {
+        name: name.to_string(),
+        ttl: 300,
+        record_type: record_type.to_string(),
+        data: data.to_string(),
+        comment: None,
+    };
+    let zone = ImportedZone {
+        origin: "example.com.".to_string(),
+        records: vec![
+            record("www.example.com.", "A", "192.0.2.10"),
+            record("example.com.", "NS", "ada.ns.cloudflare.com."),
+            ImportedRecord {
+                comment: Some("proxied by Cloudflare".to_string()),
+                ..record("api.example.com.", "A", "192.0.2.20")
+            },
+        ],
+        skipped: Vec::new(),
+    };
+    let text = import::zone_file(&zone, "Cloudflare zone 023e105f4ecef8ad9ca31a8372d0c353");
+    assert!(text.starts_with("; example.com. imported from Cloudflare zone 023e105f4ecef8ad9ca31a8372d0c353 on "));
+    assert!(text.contains("example.com. 3600 IN SOA ada.ns.cloudflare.com. hostmaster.example.com. "));
+    assert!(text.contains("; proxied by Cloudflare\napi.example.com. 300 IN A 192.0.2.20\n"));
+
+    let origin = Name::from_str("example.com.").unwrap();
+    let records = my_project::zonesync::records(&origin, &text).unwrap();
+    let texts: Vec<String> = records.iter().map(ToString::to_string).collect();
+    assert_eq!(texts.len(), 4);
+    assert_eq!(texts[1], "example.com. 300 IN NS ada.ns.cloudflare.com.");
+}
+
+#[tokio::test]
+async fn cloudflare_zones_are_read_page_by_page() {
+    // An API with a zone whose two records are on two pages, which only answers the right token
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}/client/v4", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let read = stream.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..read]).to_string();
+            let path = request.split_whitespace().nth(1).unwrap_or_default().to_string();
+            let body = match (request.to_ascii_lowercase().contains("authorization: bearer secret"), path.as_str()) {
+                (false, _) => json!({"success": false, "errors": [{"code": 10000, "message": "Authentication error"}]}),
+                (true, "/client/v4/zones/z1") => json!({"success": true, "result": {"name": "example.com", "name_servers": ["ada.ns.cloudflare.com"]}}),
+                (true, path) => {
+                    let page = if path.contains("page=2") { 2 } else { 1 };
+                    json!({
+                        "success": true,
+                        "result": [{"name": format!("host{page}.example.com"), "type": "A", "content": format!("192.0.2.{page}"), "ttl": 120}],
+                        "result_info": {"page": page, "total_pages": 2}
+                    })
+                }
+            };
+            let status = if body["success"] == json!(true) { "200 OK" } else { "403 Forbidden" };
+            let body = body.to_string();
+            let response = format!("HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len());
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    let client = reqwest::Client::new();
+    let zone = import::cloudflare(&client, &endpoint, "z1", "secret").await.unwrap();
+    assert_eq!(zone.origin, "example.com.");
+    let names: Vec<&str> = zone.records.iter().map(|record| record.name.as_str()).collect();
+    assert_eq!(names, ["example.com.", "host1.example.com.", "host2.example.com."]);
+
+    let error = import::cloudflare(&client, &endpoint, "z1", "wrong").await.unwrap_err();
+    assert!(error.to_string().contains("Authentication error"));
+}
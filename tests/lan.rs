@@ -0,0 +1,131 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    lan::{self, Lan, MdnsData, MdnsRecord},
+    options::Options,
+    testing::answer,
+};
+use std::net::IpAddr;
+use trust_dns_server::{
+    client::rr::RecordType,
+    proto::op::{Message, ResponseCode},
+};
+
+// The kernel's ARP table with a complete entry, an incomplete one and one without a MAC address.
+const ARP_TABLE: &str = "IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.60     0x1         0x2         00:11:22:AA:BB:CC     *        eth0
+192.168.1.61     0x1         0x0         00:00:00:00:00:00     *        eth0
+192.168.1.62     0x1         0x2         00:00:00:00:00:00     *        eth0
+192.168.1.50     0x1         0x2         00:11:22:dd:ee:ff     *        eth0
+";
+
+// Encodes a name without compression
+fn name(name: &str) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for label in name.split('.') {
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded.push(0);
+    encoded
+}
+
+// Encodes a record with an already encoded owner name, with the cache-flush bit set on its class as mDNS responders do
+fn record(owner: &[u8], record_type: u16, ttl: u32, rdata: &[u8]) -> Vec<u8> {
+    let mut encoded = owner.to_vec();
+    encoded.extend_from_slice(&record_type.to_be_bytes());
+    encoded.extend_from_slice(&0x8001u16.to_be_bytes());
+    encoded.extend_from_slice(&ttl.to_be_bytes());
+    encoded.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    encoded.extend_from_slice(rdata);
+    encoded
+}
+
+// An mDNS response announcing a printer's addresses, the second record's owner compressed to the first's, and a service type
+fn announcement(ttl: u32) -> Vec<u8> {
+    let mut message = vec![0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 1];
+    message.extend(record(&name("printer.local"), 1, ttl, &[192, 168, 1, 50]));
+    let ipv6: IpAddr = "fd00::50".parse().unwrap();
+    let IpAddr::V6(ipv6) = ipv6 else { unreachable!() };
+    message.extend(record(&[0xc0, 12], 28, ttl, &ipv6.octets()));
+    message.extend(record(&name("_services._dns-sd._udp.local"), 12, 4500, &name("_ipp._tcp.local")));
+    message
+}
+
+// Returns the data of each record of an answer, in order
+fn rdata(answer: &Message) -> Vec<String> {
+    answer.answers().iter().map(|record| record.to_string().rsplit(' ').next().unwrap().to_string()).collect()
+}
+
+#[test]
+fn mdns_announcements_are_read_despite_compression_and_cache_flush_bits() {
+    let records = lan::parse_mdns(&announcement(120)).unwrap();
+    assert_eq!(
+        records,
+        [
+            MdnsRecord { name: "printer.local".to_string(), ttl: 120, data: MdnsData::Address("192.168.1.50".parse().unwrap()) },
+            MdnsRecord { name: "printer.local".to_string(), ttl: 120, data: MdnsData::Address("fd00::50".parse().unwrap()) },
+            MdnsRecord {
+                name: "_services._dns-sd._udp.local".to_string(),
+                ttl: 4500,
+                data: MdnsData::Pointer("_ipp._tcp.local".to_string()),
+            },
+        ]
+    );
+
+    // Truncated messages and pointers that loop are rejected
+    let message = announcement(120);
+    assert_eq!(lan::parse_mdns(&message[..message.len() - 3]), None);
+    let mut looping = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+    looping.extend(record(&[0xc0, 12], 1, 120, &[192, 168, 1, 50]));
+    assert_eq!(lan::parse_mdns(&looping), None);
+}
+
+#[test]
+fn mdns_queries_are_well_formed() {
+    let query = lan::mdns_query(&[("_services._dns-sd._udp.local", 12), ("printer.local.", 1)]);
+    let message = Message::from_vec(&query).unwrap();
+    let questions: Vec<String> = message.queries().iter().map(|query| format!("{} {}", query.name(), query.query_type())).collect();
+    assert_eq!(questions, ["_services._dns-sd._udp.local. PTR", "printer.local. A"]);
+}
+
+#[test]
+fn devices_are_learned_until_they_say_goodbye() {
+    let lan = Lan::default();
+    assert_eq!(lan.learn(&lan::parse_mdns(&announcement(120)).unwrap()), 2);
+    assert_eq!(lan.load_arp(ARP_TABLE), 2);
+
+    let addresses = |name: &str| lan.addresses(name).iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(addresses("Printer"), ["192.168.1.50", "fd00::50"]);
+    assert_eq!(addresses("00-11-22-aa-bb-cc"), ["192.168.1.60"]);
+    assert!(addresses("00-00-00-00-00-00").is_empty());
+    assert_eq!(lan.names_of("192.168.1.50".parse().unwrap()), ["printer", "00-11-22-dd-ee-ff"]);
+    assert_eq!(lan.hosts(), ["printer"]);
+
+    // A TTL of 0 withdraws the addresses
+    lan.learn(&lan::parse_mdns(&announcement(0)).unwrap());
+    assert!(addresses("printer").is_empty());
+    assert!(lan.hosts().is_empty());
+}
+
+#[tokio::test]
+async fn devices_are_served_as_address_and_ptr_records() {
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech", "--lan-discovery"]);
+    let handler = Handler::from_options(&options);
+    let lan = handler.lan.as_ref().unwrap();
+    lan.learn(&lan::parse_mdns(&announcement(120)).unwrap());
+    lan.load_arp(ARP_TABLE);
+
+    let a = answer(&handler, "printer.lan.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(rdata(&a), ["192.168.1.50"]);
+    assert_eq!(a.answers()[0].ttl(), lan::TTL);
+    let aaaa = answer(&handler, "printer.lan.mentisnovae.tech.", RecordType::AAAA).await;
+    assert_eq!(rdata(&aaaa), ["fd00::50"]);
+    let by_mac = answer(&handler, "00-11-22-aa-bb-cc.lan.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(rdata(&by_mac), ["192.168.1.60"]);
+    let missing = answer(&handler, "scanner.lan.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(missing.response_code(), ResponseCode::NXDomain);
+
+    let ptr = answer(&handler, "50.1.168.192.in-addr.arpa.", RecordType::PTR).await;
+    assert_eq!(rdata(&ptr), ["printer.lan.mentisnovae.tech.", "00-11-22-dd-ee-ff.lan.mentisnovae.tech."]);
+}
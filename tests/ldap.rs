@@ -0,0 +1,163 @@
+#![cfg(feature = "ldap")]
+
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    ldap::{Directory, LdapHosts},
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use trust_dns_server::{
+    client::rr::{LowerName, Name, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// A directory at a URL, serving its hosts under hosts.example.com
+fn directory(url: &str) -> Directory {
+    Directory {
+        url: url.to_string(),
+        bind: Some(("cn=dns,dc=example,dc=com".to_string(), "secret".to_string())),
+        base: "dc=example,dc=com".to_string(),
+        filter: "(&(objectClass=ipHost)(cn={host}))".to_string(),
+        attributes: vec!["ipHostNumber".to_string()],
+        zone: "hosts.example.com".to_string(),
+        pool_size: 2,
+        ttl: 60,
+    }
+}
+
+// Encodes a BER element
+fn ber(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+    match contents.len() {
+        length @ 0..=127 => element.push(length as u8),
+        length => element.extend([0x82, (length >> 8) as u8, length as u8]),
+    }
+    element.extend(contents);
+    element
+}
+
+// Encodes an LDAP message with a successful result of an operation
+fn success(id: &[u8], operation: u8) -> Vec<u8> {
+    let result = [ber(0x0a, &[0]), ber(0x04, b""), ber(0x04, b"")].concat();
+    ber(0x30, &[ber(0x02, id), ber(operation, &result)].concat())
+}
+
+// Starts a directory whose only host is web01, counting the searches it answers
+async fn mock_directory(searches: Arc<AtomicUsize>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ldap://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let searches = searches.clone();
+            tokio::spawn(async move {
+                let mut buffer = vec![0; 4096];
+                // Each request is small enough to arrive in one read, with a one-byte length and message ID
+                while let Ok(read) = stream.read(&mut buffer).await {
+                    if read < 7 {
+                        return;
+                    }
+                    let message = &buffer[..read];
+                    let id = &message[4..4 + usize::from(message[3])];
+                    let operation = message[4 + id.len()];
+                    let response = match operation {
+                        0x60 => success(id, 0x61),
+                        0x63 => {
+                            searches.fetch_add(1, Ordering::SeqCst);
+                            let mut response = Vec::new();
+                            if message.windows(5).any(|window| window == b"web01") {
+                                let values = [ber(0x04, b"192.0.2.10"), ber(0x04, b"2001:db8::10"), ber(0x04, b"not-an-address")].concat();
+                                let attribute = ber(0x30, &[ber(0x04, b"iphostnumber"), ber(0x31, &values)].concat());
+                                let entry = [ber(0x04, b"cn=web01,dc=example,dc=com"), ber(0x30, &attribute)].concat();
+                                response.extend(ber(0x30, &[ber(0x02, id), ber(0x64, &entry)].concat()));
+                            }
+                            response.extend(success(id, 0x65));
+                            response
+                        }
+                        _ => return,
+                    };
+                    if stream.write_all(&response).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    url
+}
+
+#[test]
+fn hosts_are_named_below_the_zone_and_escaped_in_the_filter() {
+    let hosts = LdapHosts::new(directory("ldap://127.0.0.1:1")).unwrap();
+    let name = |text: &str| LowerName::from(Name::from_str(text).unwrap());
+    assert!(hosts.serves(&name("web01.hosts.example.com.")));
+    assert!(!hosts.serves(&name("web01.example.com.")));
+    assert_eq!(hosts.host(&name("WEB01.hosts.example.com.")).as_deref(), Some("web01"));
+    assert_eq!(hosts.host(&name("hosts.example.com.")), None);
+
+    assert_eq!(hosts.filter("web01"), "(&(objectClass=ipHost)(cn=web01))");
+    assert_eq!(hosts.filter("a*)(uid=*"), "(&(objectClass=ipHost)(cn=a\\2a\\29\\28uid=\\2a))");
+
+    let mut fqdn = directory("ldap://127.0.0.1:1");
+    fqdn.filter = "(fqdn={fqdn})".to_string();
+    assert_eq!(LdapHosts::new(fqdn).unwrap().filter("web01"), "(fqdn=web01.hosts.example.com)");
+
+    let mut unnamed = directory("ldap://127.0.0.1:1");
+    unnamed.filter = "(objectClass=ipHost)".to_string();
+    assert!(LdapHosts::new(unnamed).unwrap_err().contains("{host}"));
+}
+
+#[tokio::test]
+async fn lookups_are_cached_for_the_ttl() {
+    let searches = Arc::new(AtomicUsize::new(0));
+    let url = mock_directory(searches.clone()).await;
+    let hosts = LdapHosts::new(directory(&url)).unwrap();
+
+    let expected: Vec<IpAddr> = vec!["192.0.2.10".parse().unwrap(), "2001:db8::10".parse().unwrap()];
+    assert_eq!(hosts.addresses("web01").await.unwrap(), Some(expected.clone()));
+    assert_eq!(hosts.addresses("web01").await.unwrap(), Some(expected));
+    assert_eq!(hosts.addresses("nope").await.unwrap(), None);
+    assert_eq!(hosts.addresses("nope").await.unwrap(), None);
+    assert_eq!(searches.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn hosts_are_answered_and_an_unreachable_directory_fails() {
+    let searches = Arc::new(AtomicUsize::new(0));
+    let url = mock_directory(searches).await;
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech", "--ldap", &url, "--ldap-base", "dc=example,dc=com"]);
+    let handler = Handler::from_options(&options);
+
+    let ask = |handler: Handler, name: &str, record_type: RecordType| {
+        let request = RequestBuilder::new(Name::from_str(name).unwrap(), record_type).build();
+        async move {
+            let responder = CaptureResponder::default();
+            handler.handle_request(&request, responder.clone()).await;
+            Message::from_vec(&responder.take().unwrap()).unwrap()
+        }
+    };
+    let answer = ask(handler.clone(), "web01.ldap.mentisnovae.tech.", RecordType::AAAA).await;
+    assert!(answer.authoritative());
+    assert_eq!(answer.answers().len(), 1);
+    assert_eq!(answer.answers()[0].data().unwrap().to_string(), "2001:db8::10");
+    let answer = ask(handler, "nope.ldap.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(answer.response_code(), ResponseCode::NXDomain);
+
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech", "--ldap", "ldap://127.0.0.1:1", "--ldap-base", "dc=example,dc=com"]);
+    let answer = ask(Handler::from_options(&options), "web01.ldap.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(answer.response_code(), ResponseCode::ServFail);
+}
@@ -0,0 +1,73 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    limits::{self, QueryLimits, QueryViolation},
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::str::FromStr;
+use trust_dns_server::{
+    authority::MessageRequest,
+    client::rr::{Name, RecordType},
+    proto::{
+        op::{Message, MessageType, OpCode, ResponseCode},
+        serialize::binary::BinDecodable,
+    },
+    server::{Request, RequestHandler},
+};
+
+// The limits of a server started without limit options
+const LIMITS: QueryLimits = QueryLimits { max_name_length: 253, max_labels: 40 };
+
+// Answers a request with a handler serving mentisnovae.tech
+async fn answer(request: &Request) -> Message {
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    let handler = Handler::from_options(&options);
+    let responder = CaptureResponder::default();
+    handler.handle_request(request, responder.clone()).await;
+    Message::from_vec(&responder.take().unwrap()).unwrap()
+}
+
+#[test]
+fn names_are_checked_against_the_limits() {
+    assert_eq!(LIMITS.check_name(["puny".as_bytes(), "bücher".as_bytes(), b"mentisnovae", b"tech"]), Ok(()));
+    assert_eq!(LIMITS.check_name([b"sun".as_slice(), b"12_97", b"+0530"]), Ok(()));
+
+    let labels = vec![b"a".as_slice(); 41];
+    assert_eq!(LIMITS.check_name(labels), Err(QueryViolation::LabelCount(41, 40)));
+    let label = [b'a'; 63];
+    assert_eq!(LIMITS.check_name([label.as_slice(); 4]), Err(QueryViolation::NameLength(255, 253)));
+
+    assert_eq!(LIMITS.check_name([b"a b".as_slice()]), Err(QueryViolation::Character("a b".to_string(), "a space")));
+    assert_eq!(LIMITS.check_name([b"a.b".as_slice()]), Err(QueryViolation::Character("a.b".to_string(), "a dot")));
+    assert!(matches!(LIMITS.check_name([b"a\x00b".as_slice()]), Err(QueryViolation::Character(_, "a control character"))));
+    assert!(matches!(LIMITS.check_name([b"\xff".as_slice()]), Err(QueryViolation::Character(_, "invalid UTF-8"))));
+
+    assert!(limits::parse_max_labels("128").is_err());
+    assert_eq!(limits::parse_max_name_length("64"), Ok(64));
+}
+
+#[tokio::test]
+async fn names_over_the_limits_are_answered_with_formerr() {
+    let name = Name::from_labels([b"dice".as_slice(), b"a\x01b", b"mentisnovae", b"tech"]).unwrap();
+    let answer = answer(&RequestBuilder::new(name, RecordType::TXT).build()).await;
+    assert_eq!(answer.response_code(), ResponseCode::FormErr);
+
+    let name = Name::from_str(&format!("{}mentisnovae.tech.", "a.".repeat(39))).unwrap();
+    let answer = self::answer(&RequestBuilder::new(name, RecordType::TXT).build()).await;
+    assert_eq!(answer.response_code(), ResponseCode::FormErr);
+    assert!(answer.additionals()[0].data().unwrap().to_string().contains("41 labels"));
+}
+
+#[test]
+fn queries_need_exactly_one_question() {
+    // trust-dns won't parse a query without a question, so it never reaches the handler
+    let mut message = Message::new();
+    message.set_id(7).set_message_type(MessageType::Query).set_op_code(OpCode::Query);
+    assert!(MessageRequest::from_bytes(&message.to_vec().unwrap()).is_err());
+
+    // Requests built some other way are checked by the limits
+    assert_eq!(LIMITS.check_question_count(1), Ok(()));
+    assert_eq!(LIMITS.check_question_count(0), Err(QueryViolation::QuestionCount(0)));
+    assert_eq!(LIMITS.check_question_count(2), Err(QueryViolation::QuestionCount(2)));
+}
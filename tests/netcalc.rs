@@ -0,0 +1,215 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    netcalc::{self, QueryError},
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use proptest::prelude::*;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+use trust_dns_server::{
+    client::rr::{Name, RData, RecordType},
+    proto::op::{Edns, Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// The reference implementation: builds the first and last address of the range one bit at a time, keeping the prefix bits of the address and clearing (first) or setting (last) the rest
+fn reference(address: u128, width: u32, prefix_len: u32) -> (u128, u128) {
+    let (mut first, mut last) = (0, 0);
+    for position in 0..width {
+        let bit = 1u128 << (width - 1 - position);
+        if position < prefix_len {
+            first |= address & bit;
+            last |= address & bit;
+        } else {
+            last |= bit;
+        }
+    }
+    (first, last)
+}
+
+// Returns the range of an IPv4 address as integers
+fn ipv4_range(address: u32, prefix_len: u8) -> (u128, u128) {
+    match netcalc::range(IpAddr::V4(Ipv4Addr::from(address)), prefix_len) {
+        (IpAddr::V4(first), IpAddr::V4(last)) => (u32::from(first).into(), u32::from(last).into()),
+        range => panic!("an IPv4 address gave the range {range:?}"),
+    }
+}
+
+// Returns the range of an IPv6 address as integers
+fn ipv6_range(address: u128, prefix_len: u8) -> (u128, u128) {
+    match netcalc::range(IpAddr::V6(Ipv6Addr::from(address)), prefix_len) {
+        (IpAddr::V6(first), IpAddr::V6(last)) => (u128::from(first), u128::from(last)),
+        range => panic!("an IPv6 address gave the range {range:?}"),
+    }
+}
+
+// Checks the properties every range must have: it contains the address, holds 2^(width - prefix) addresses, matches the reference and is its own range
+fn check(range: impl Fn(u128, u8) -> (u128, u128), address: u128, width: u32, prefix_len: u8) -> Result<(), TestCaseError> {
+    let (first, last) = range(address, prefix_len);
+    prop_assert!(first <= address && address <= last, "{first:#x}-{last:#x} doesn't contain {address:#x}/{prefix_len}");
+
+    let host_bits = width - u32::from(prefix_len);
+    let size_minus_one = if host_bits == 128 { u128::MAX } else { (1u128 << host_bits) - 1 };
+    prop_assert_eq!(last - first, size_minus_one, "wrong size for {:#x}/{}", address, prefix_len);
+
+    prop_assert_eq!((first, last), reference(address, width, prefix_len.into()));
+    prop_assert_eq!(range(first, prefix_len), (first, last));
+    prop_assert_eq!(range(last, prefix_len), (first, last));
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn ipv4_ranges(address in any::<u32>(), prefix_len in 0u8..=32) {
+        check(|address, prefix_len| ipv4_range(address as u32, prefix_len), address.into(), 32, prefix_len)?;
+    }
+
+    #[test]
+    fn ipv6_ranges(address in any::<u128>(), prefix_len in 0u8..=128) {
+        check(ipv6_range, address, 128, prefix_len)?;
+    }
+
+    #[test]
+    fn zero_prefix_covers_everything(ipv4 in any::<u32>(), ipv6 in any::<u128>()) {
+        check(|address, prefix_len| ipv4_range(address as u32, prefix_len), ipv4.into(), 32, 0)?;
+        check(ipv6_range, ipv6, 128, 0)?;
+    }
+}
+
+// A few ranges worked out by hand, in case the reference shares a mistake with the code
+#[test]
+fn ipv4_examples() {
+    for (address, prefix_len, first, last) in [
+        ("10.1.2.3", 8, "10.0.0.0", "10.255.255.255"),
+        ("192.168.1.77", 24, "192.168.1.0", "192.168.1.255"),
+        ("172.16.5.4", 12, "172.16.0.0", "172.31.255.255"),
+        ("203.0.113.9", 32, "203.0.113.9", "203.0.113.9"),
+    ] {
+        let range = netcalc::range(address.parse().unwrap(), prefix_len);
+        assert_eq!(range, (first.parse().unwrap(), last.parse().unwrap()), "{address}/{prefix_len}");
+    }
+}
+
+#[test]
+fn ipv6_examples() {
+    for (address, prefix_len, first, last) in [
+        ("2001:db8::1", 32, "2001:db8::", "2001:db8:ffff:ffff:ffff:ffff:ffff:ffff"),
+        ("2001:db8:1:2::5", 64, "2001:db8:1:2::", "2001:db8:1:2:ffff:ffff:ffff:ffff"),
+        ("fe80::1234", 10, "fe80::", "febf:ffff:ffff:ffff:ffff:ffff:ffff:ffff"),
+        ("::1", 128, "::1", "::1"),
+    ] {
+        let range = netcalc::range(address.parse().unwrap(), prefix_len);
+        assert_eq!(range, (first.parse().unwrap(), last.parse().unwrap()), "{address}/{prefix_len}");
+    }
+}
+
+#[test]
+fn networks_are_described_across_prefix_lengths() {
+    for (address, prefix_len, network, broadcast, usable, hosts) in [
+        ("10.1.2.3", 0, "0.0.0.0", Some("255.255.255.255"), ("0.0.0.1", "255.255.255.254"), 4294967294),
+        ("10.1.2.3", 8, "10.0.0.0", Some("10.255.255.255"), ("10.0.0.1", "10.255.255.254"), 16777214),
+        ("192.168.1.77", 24, "192.168.1.0", Some("192.168.1.255"), ("192.168.1.1", "192.168.1.254"), 254),
+        ("192.168.1.77", 30, "192.168.1.76", Some("192.168.1.79"), ("192.168.1.77", "192.168.1.78"), 2),
+        ("192.168.1.77", 31, "192.168.1.76", None, ("192.168.1.76", "192.168.1.77"), 2),
+        ("192.168.1.77", 32, "192.168.1.77", None, ("192.168.1.77", "192.168.1.77"), 1),
+        ("2001:db8::1", 64, "2001:db8::", None, ("2001:db8::", "2001:db8::ffff:ffff:ffff:ffff"), 1 << 64),
+        ("2001:db8::1", 128, "2001:db8::1", None, ("2001:db8::1", "2001:db8::1"), 1),
+    ] {
+        let described = netcalc::network(address.parse().unwrap(), prefix_len);
+        assert_eq!(described.network, network.parse::<IpAddr>().unwrap(), "{address}/{prefix_len}");
+        assert_eq!(described.broadcast, broadcast.map(|broadcast| broadcast.parse().unwrap()), "{address}/{prefix_len}");
+        assert_eq!(described.usable, (usable.0.parse().unwrap(), usable.1.parse().unwrap()), "{address}/{prefix_len}");
+        assert_eq!(described.hosts, Some(hosts), "{address}/{prefix_len}");
+    }
+
+    // Every IPv4 prefix length from 1 to 30 leaves out the network and broadcast address
+    for prefix_len in 1..=30u8 {
+        let described = netcalc::network("203.0.113.9".parse().unwrap(), prefix_len);
+        assert_eq!(described.hosts, Some((1u128 << (32 - prefix_len)) - 2), "/{prefix_len}");
+    }
+
+    // The whole IPv6 space holds more addresses than a u128 can count
+    let everything = netcalc::network("2001:db8::1".parse().unwrap(), 0);
+    assert_eq!(everything.usable, ("::".parse().unwrap(), "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff".parse().unwrap()));
+    assert_eq!(everything.hosts, None);
+}
+
+// Splits a name into labels the way the handler does
+fn labels(name: &str) -> Vec<String> {
+    name.split('.').map(String::from).collect()
+}
+
+#[test]
+fn queries_are_taken_apart_into_an_address_and_prefix_length() {
+    for (name, address, prefix_len) in [
+        ("10.0.0.0.24", "10.0.0.0", 24),
+        ("10-0-0-0.24", "10.0.0.0", 24),
+        ("2001-db8--.32", "2001:db8::", 32),
+        ("--1.128", "::1", 128),
+        ("2001:db8::.32", "2001:db8::", 32),
+    ] {
+        assert_eq!(netcalc::parse_query(&labels(name)), Ok((address.parse().unwrap(), prefix_len)), "{name}");
+    }
+}
+
+#[test]
+fn malformed_queries_say_what_is_wrong() {
+    for (name, error) in [
+        ("foo", QueryError::Layout("foo".to_string())),
+        ("10.0.0.0", QueryError::Address("10.0.0".to_string())),
+        ("10.0.0.24", QueryError::Address("10.0.0".to_string())),
+        ("not-an-ip.24", QueryError::Address("not-an-ip".to_string())),
+        ("10-0-0-256.24", QueryError::Address("10-0-0-256".to_string())),
+        ("2001-db8--.abc", QueryError::PrefixLength("abc".to_string())),
+        ("2001-db8--.-1", QueryError::PrefixLength("-1".to_string())),
+        ("10.0.0.0.33", QueryError::PrefixRange("33".to_string(), "IPv4", 32)),
+        ("10.0.0.0.256", QueryError::PrefixRange("256".to_string(), "IPv4", 32)),
+        ("2001-db8--.129", QueryError::PrefixRange("129".to_string(), "IPv6", 128)),
+        ("2001-db8--.99999999999", QueryError::PrefixLength("99999999999".to_string())),
+    ] {
+        assert_eq!(netcalc::parse_query(&labels(name)), Err(error), "{name}");
+    }
+    assert_eq!(netcalc::parse_query(&[]), Err(QueryError::Layout("the zone itself".to_string())));
+    assert_eq!(QueryError::Address("x".to_string()).to_string(), "x is not an IP address");
+}
+
+#[tokio::test]
+async fn malformed_queries_are_answered_with_an_explanation() {
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    let handler = Handler::from_options(&options);
+
+    let request = RequestBuilder::new(Name::from_str("foo.cidr.mentisnovae.tech.").unwrap(), RecordType::TXT).edns(Edns::new()).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    let answer = Message::from_vec(&responder.take().unwrap()).unwrap();
+    assert_eq!(answer.response_code(), ResponseCode::NXDomain);
+    assert!(answer.answers().is_empty());
+    let explanation = match answer.additionals()[0].data() {
+        Some(RData::TXT(txt)) => txt.to_string(),
+        other => panic!("expected a TXT record, got {other:?}"),
+    };
+    assert!(explanation.contains("expected <address>.<prefix length> below the cidr zone"), "{explanation}");
+
+    // The explanation is also an Extended DNS Error
+    let edns = answer.extensions().as_ref().unwrap();
+    let option = edns.options().as_ref().iter().find(|(code, _)| u16::from(**code) == 15);
+    assert!(option.is_some());
+}
+
+#[tokio::test]
+async fn queries_are_read_below_the_configured_domain() {
+    let options = Options::parse_from(["rusty-dns", "--domain", "example.com"]);
+    let handler = Handler::from_options(&options);
+
+    let request = RequestBuilder::new(Name::from_str("10-0-0-0.24.cidr.example.com.").unwrap(), RecordType::TXT).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    let answer = Message::from_vec(&responder.take().unwrap()).unwrap();
+    assert_eq!(answer.response_code(), ResponseCode::NoError);
+    let facts: Vec<String> = answer.answers().iter().map(|record| record.data().unwrap().to_string()).collect();
+    assert_eq!(facts, ["Network: 10.0.0.0/24", "Broadcast: 10.0.0.255", "Usable IP Range: 10.0.0.1 - 10.0.0.254", "Hosts: 254"]);
+}
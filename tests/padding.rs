@@ -0,0 +1,64 @@
+use clap::Parser;
+use my_project::{
+    config::{PaddingConfig, PaddingPolicy},
+    handlers::Handler,
+    options::Options,
+    padding::Padding,
+    random::Random,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{net::SocketAddr, str::FromStr};
+use trust_dns_server::{
+    client::rr::{Name, RecordType},
+    proto::{
+        op::Message,
+        rr::rdata::opt::{EdnsCode, EdnsOption},
+    },
+    server::{Protocol, RequestHandler},
+};
+
+// Asks a handler that pads answers to 127.0.0.1 for the dice zone over TCP, and returns the answer as it is sent
+async fn answer(source: &str, padding: bool) -> Vec<u8> {
+    let mut options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    options.config.padding = Some(PaddingConfig { encrypted_from: vec!["127.0.0.1".parse().unwrap()], ..Default::default() });
+    let handler = Handler::from_options(&options);
+
+    let mut request = RequestBuilder::new(Name::from_str("dice.mentisnovae.tech.").unwrap(), RecordType::TXT)
+        .source(source.parse::<SocketAddr>().unwrap())
+        .protocol(Protocol::Tcp);
+    if padding {
+        request = request.edns_option(EdnsOption::Unknown(12, vec![0; 16]));
+    }
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request.build(), responder.clone()).await;
+    responder.take().unwrap()
+}
+
+#[test]
+fn padding_fills_blocks_up_to_the_limit() {
+    let block = Padding::new(&PaddingConfig::default());
+    assert_eq!(block.padding_length(100, 65535, &Random::default()), 368);
+    assert_eq!(block.padding_length(468, 65535, &Random::default()), 0);
+    assert_eq!(block.padding_length(500, 512, &Random::default()), 12);
+
+    let random = Padding::new(&PaddingConfig { policy: PaddingPolicy::Random, block_size: 64, ..Default::default() });
+    let seeded = Random::seeded(7);
+    let lengths: Vec<usize> = (0..100).map(|_| random.padding_length(100, 65535, &seeded)).collect();
+    assert!(lengths.iter().all(|length| *length <= 64));
+}
+
+#[tokio::test]
+async fn answers_through_encrypting_proxies_are_padded_when_asked() {
+    let padded = answer("127.0.0.1:53000", true).await;
+    assert_eq!(padded.len() % 468, 0);
+    let message = Message::from_vec(&padded).unwrap();
+    assert_eq!(message.answers().len(), 1);
+    assert!(message.extensions().as_ref().and_then(|edns| edns.option(EdnsCode::Padding)).is_some());
+
+    // Clients other than the proxy, and queries that don't ask for padding, get answers as they are
+    for answer in [answer("192.0.2.7:53000", true).await, answer("127.0.0.1:53000", false).await] {
+        let message = Message::from_vec(&answer).unwrap();
+        assert!(message.extensions().as_ref().and_then(|edns| edns.option(EdnsCode::Padding)).is_none());
+        assert!(answer.len() < 468);
+    }
+}
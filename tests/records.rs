@@ -0,0 +1,225 @@
+use clap::Parser;
+use my_project::{
+    config::SoaConfig,
+    handlers::Handler,
+    options::Options,
+    records::{self, Lookup, RecordStore},
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use trust_dns_server::{
+    client::rr::{LowerName, Name, RData, Record, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// A zone with an apex, a CNAME chain, a name two levels below the apex and a record outside any zone.
+const ROWS: [(&str, &str, u32, &str); 9] = [
+    ("example.com", "SOA", 3600, "ns1.example.com hostmaster.example.com 2023050101 1h 15m 1w 300"),
+    ("example.com", "NS", 3600, "ns1.example.com"),
+    ("example.com", "MX", 3600, "10 mail.example.com"),
+    ("www.example.com", "A", 300, "192.0.2.10"),
+    ("www.example.com", "AAAA", 300, "2001:db8::10"),
+    ("shop.example.com", "CNAME", 300, "www.example.com."),
+    ("_sip._tcp.example.com", "SRV", 300, "10 60 5060 sip.example.com"),
+    ("example.com", "TXT", 300, "\"v=spf1 mx -all\" \"second \\\"part\\\"\""),
+    ("standalone.example.net", "A", 60, "198.51.100.7"),
+];
+
+// Returns a store holding the rows
+fn store() -> RecordStore {
+    let store = RecordStore::default();
+    let records: Vec<Record> = ROWS.iter().map(|(name, record_type, ttl, data)| records::record(name, record_type, *ttl, data).unwrap()).collect();
+    store.replace("test", records);
+    store
+}
+
+// Looks up a name in the store, returning the data of the answer's records
+fn lookup(store: &RecordStore, name: &str, record_type: RecordType) -> Option<Lookup> {
+    store.lookup(&LowerName::from(Name::from_str(name).unwrap()), record_type)
+}
+
+// Returns the text of each record of a section
+fn texts(records: &[Record]) -> Vec<String> {
+    records.iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn records_are_read_from_their_text() {
+    let mx = records::record("example.com", "mx", 3600, "10 Mail.Example.com").unwrap();
+    assert_eq!(mx.to_string(), "example.com. 3600 IN MX 10 mail.example.com.");
+    let txt = records::record("example.com", "TXT", 300, "\"v=spf1 mx -all\" \"second \\\"part\\\"\"").unwrap();
+    let Some(RData::TXT(txt)) = txt.data() else { panic!("not a TXT record: {txt}") };
+    let strings: Vec<String> = txt.iter().map(|string| String::from_utf8_lossy(string).into_owned()).collect();
+    assert_eq!(strings, ["v=spf1 mx -all", "second \"part\""]);
+
+    assert!(records::record("example.com", "A", 300, "not-an-address").unwrap_err().contains("invalid A data"));
+    assert!(records::record("example.com", "MX", 300, "mail.example.com").unwrap_err().contains("fields"));
+    assert!(records::record("example.com", "BOGUS", 300, "x").unwrap_err().contains("unknown record type"));
+    assert!(records::record("example.com", "HINFO", 300, "PC Linux").unwrap_err().contains("not supported"));
+}
+
+#[test]
+fn lookups_follow_cnames_and_tell_nodata_from_nxdomain() {
+    let store = store();
+    let answer = |name: &str, record_type: RecordType| match lookup(&store, name, record_type) {
+        Some(Lookup::Answer(records)) => texts(&records),
+        other => panic!("{name} {record_type}: {other:?}"),
+    };
+
+    assert_eq!(answer("WWW.example.com.", RecordType::A), ["www.example.com. 300 IN A 192.0.2.10"]);
+    assert_eq!(
+        answer("shop.example.com.", RecordType::AAAA),
+        ["shop.example.com. 300 IN CNAME www.example.com.", "www.example.com. 300 IN AAAA 2001:db8::10"]
+    );
+    assert_eq!(answer("shop.example.com.", RecordType::CNAME), ["shop.example.com. 300 IN CNAME www.example.com."]);
+    assert_eq!(answer("www.example.com.", RecordType::ANY).len(), 2);
+
+    // Names without the type, names that only have names below them, and names that don't exist
+    assert!(matches!(lookup(&store, "www.example.com.", RecordType::MX), Some(Lookup::NoData(Some(_)))));
+    assert!(matches!(lookup(&store, "_tcp.example.com.", RecordType::SRV), Some(Lookup::NoData(Some(_)))));
+    assert!(matches!(lookup(&store, "missing.example.com.", RecordType::A), Some(Lookup::NxDomain(soa)) if soa.name().to_string() == "example.com."));
+
+    // Outside the zones only the names with records are served
+    assert!(store.serves(&LowerName::from(Name::from_str("standalone.example.net.").unwrap())));
+    assert!(!store.serves(&LowerName::from(Name::from_str("other.example.net.").unwrap())));
+    assert_eq!(lookup(&store, "other.example.net.", RecordType::A), None);
+    assert!(matches!(lookup(&store, "standalone.example.net.", RecordType::AAAA), Some(Lookup::NoData(None))));
+
+    // Replacing a source's records drops the old ones
+    store.replace("test", Vec::new());
+    assert_eq!(store.names(), 0);
+    assert_eq!(lookup(&store, "www.example.com.", RecordType::A), None);
+}
+
+#[test]
+fn expiring_records_count_down_and_disappear() {
+    let store = RecordStore::default();
+    let record = |name: &str| records::record(name, "A", 300, "192.0.2.20").unwrap();
+    let now = Instant::now();
+    store.replace_expiring(
+        "test",
+        vec![
+            (record("soon.example.org"), Some(now + Duration::from_millis(1500))),
+            (record("gone.example.org"), Some(now)),
+            (record("kept.example.org"), None),
+        ],
+    );
+
+    let ttl = |name: &str| match lookup(&store, name, RecordType::A) {
+        Some(Lookup::Answer(records)) => Some(records[0].ttl()),
+        _ => None,
+    };
+    assert_eq!(ttl("soon.example.org."), Some(2));
+    assert_eq!(ttl("gone.example.org."), None);
+    assert!(!store.serves(&LowerName::from(Name::from_str("gone.example.org.").unwrap())));
+    assert_eq!(ttl("kept.example.org."), Some(300));
+}
+
+#[tokio::test]
+async fn the_store_is_served_authoritatively() {
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    let mut handler = Handler::from_options(&options);
+    handler.records = Some(Arc::new(store()));
+
+    let answer = |name: &str, record_type: RecordType| {
+        let request = RequestBuilder::new(Name::from_str(name).unwrap(), record_type).build();
+        let responder = CaptureResponder::default();
+        let handler = &handler;
+        async move {
+            handler.handle_request(&request, responder.clone()).await;
+            Message::from_vec(&responder.take().expect("the handler sent no answer")).unwrap()
+        }
+    };
+
+    let mx = answer("example.com.", RecordType::MX).await;
+    assert!(mx.authoritative());
+    assert_eq!(texts(mx.answers()), ["example.com. 3600 IN MX 10 mail.example.com."]);
+    let srv = answer("_sip._tcp.example.com.", RecordType::SRV).await;
+    assert_eq!(texts(srv.answers()), ["_sip._tcp.example.com. 300 IN SRV 10 60 5060 sip.example.com."]);
+
+    let missing = answer("missing.example.com.", RecordType::A).await;
+    assert_eq!(missing.response_code(), ResponseCode::NXDomain);
+    assert_eq!(missing.name_servers()[0].record_type(), RecordType::SOA);
+    let nodata = answer("www.example.com.", RecordType::TXT).await;
+    assert_eq!(nodata.response_code(), ResponseCode::NoError);
+    assert!(nodata.answers().is_empty());
+    assert_eq!(nodata.name_servers()[0].record_type(), RecordType::SOA);
+}
+
+#[cfg(feature = "postgres")]
+#[test]
+fn invalid_rows_are_skipped() {
+    let rows = [
+        ("www.example.com", "A", "192.0.2.10", 300),
+        ("bad.example.com", "A", "not-an-address", 300),
+        ("negative.example.com", "A", "192.0.2.11", -1),
+    ];
+    let records = my_project::postgres::records(rows);
+    assert_eq!(texts(&records), ["www.example.com. 300 IN A 192.0.2.10"]);
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_keys_name_their_records() {
+    use my_project::redis_records;
+
+    let values = ["192.0.2.10\n192.0.2.11".to_string()];
+    let records = redis_records::records("dns:", "dns:www.example.com:A", &values, 299_001).unwrap();
+    assert_eq!(texts(&records), ["www.example.com. 300 IN A 192.0.2.10", "www.example.com. 300 IN A 192.0.2.11"]);
+    let persistent = redis_records::records("dns:", "dns:example.com:MX", &["10 mail.example.com".to_string()], -1).unwrap();
+    assert_eq!(persistent[0].ttl(), redis_records::PERSISTENT_TTL);
+
+    assert!(redis_records::records("dns:", "dns:no-type", &values, -1).is_err());
+    assert!(redis_records::records("dns:", "dns:www.example.com:AAAA", &values, -1).is_err());
+}
+
+#[test]
+fn the_soa_record_of_the_domain_defaults_its_names() {
+    let zone = Name::from_str("mentisnovae.tech.").unwrap();
+    let soa = records::soa_record(&zone, &SoaConfig::default()).unwrap();
+    assert_eq!(soa.ttl(), 300);
+    let Some(RData::SOA(data)) = soa.data() else { panic!("expected an SOA record, got {soa:?}") };
+    assert_eq!(data.mname().to_string(), "ns1.mentisnovae.tech.");
+    assert_eq!(data.rname().to_string(), "hostmaster.mentisnovae.tech.");
+
+    // A hostmaster's mail address becomes a name, with the dots of its local part escaped
+    let config = SoaConfig { hostmaster: Some("dns.admin@example.com".to_string()), ttl: 60, ..SoaConfig::default() };
+    let soa = records::soa_record(&zone, &config).unwrap();
+    let Some(RData::SOA(data)) = soa.data() else { panic!("expected an SOA record, got {soa:?}") };
+    assert_eq!(data.rname().num_labels(), 3);
+    assert_eq!(soa.ttl(), 60);
+}
+
+#[tokio::test]
+async fn negative_answers_carry_the_soa_record_of_the_domain() {
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    let handler = Handler::from_options(&options);
+    let answer = |name: &str, record_type: RecordType| {
+        let request = RequestBuilder::new(Name::from_str(name).unwrap(), record_type).build();
+        let handler = handler.clone();
+        async move {
+            let responder = CaptureResponder::default();
+            handler.handle_request(&request, responder.clone()).await;
+            Message::from_vec(&responder.take().unwrap()).unwrap()
+        }
+    };
+
+    // NXDOMAIN
+    let nxdomain = answer("nosuchzone.mentisnovae.tech.", RecordType::TXT).await;
+    assert_eq!(nxdomain.response_code(), ResponseCode::NXDomain);
+    assert_eq!(nxdomain.name_servers().len(), 1);
+    assert_eq!(nxdomain.name_servers()[0].record_type(), RecordType::SOA);
+    assert_eq!(nxdomain.name_servers()[0].name().to_string(), "mentisnovae.tech.");
+
+    // Positive answers and errors don't
+    let positive = answer("coin.mentisnovae.tech.", RecordType::TXT).await;
+    assert!(positive.name_servers().is_empty());
+    let formerr = answer("soon.time.mentisnovae.tech.", RecordType::TXT).await;
+    assert_eq!(formerr.response_code(), ResponseCode::FormErr);
+    assert!(formerr.name_servers().is_empty());
+}
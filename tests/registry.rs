@@ -0,0 +1,157 @@
+use base64::Engine;
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    options::Options,
+    registry::{self, Backend, Registry},
+    testing::answer,
+};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use trust_dns_server::{client::rr::RecordType, proto::op::ResponseCode};
+
+// Two nodes, as Consul's /v1/catalog/nodes lists them.
+const NODES: &str = r#"[{"Node": "web1", "Address": "10.0.0.1"}, {"Node": "web2", "Address": "10.0.0.2"}]"#;
+
+// The healthy instances of the web service, as /v1/health/service/web?passing lists them; the second has an address of its own.
+const WEB: &str = r#"[
+    {"Node": {"Node": "web1", "Address": "10.0.0.1"}, "Service": {"Service": "web", "Address": "", "Port": 8080, "Tags": ["v2"]}},
+    {"Node": {"Node": "web2", "Address": "10.0.0.2"}, "Service": {"Service": "web", "Address": "fd00::2", "Port": 8081, "Tags": null}}
+]"#;
+
+// Returns the text of each record of a section
+fn texts(records: &[trust_dns_server::client::rr::Record]) -> Vec<String> {
+    records.iter().map(ToString::to_string).collect()
+}
+
+// Encodes a key or value for etcd's JSON gateway
+fn base64(text: &str) -> String {
+    base64::engine::general_purpose::STANDARD.encode(text)
+}
+
+#[test]
+fn consul_nodes_services_and_tags_are_named_like_consuls_dns() {
+    let names = registry::consul_names(NODES.as_bytes(), &[WEB.as_bytes().to_vec()]).unwrap();
+    let addresses = |name: &str| names[name].iter().map(|instance| instance.address.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(addresses("web1.node"), ["10.0.0.1"]);
+    assert_eq!(addresses("web.service"), ["10.0.0.1", "fd00::2"]);
+    assert_eq!(addresses("v2.web.service"), ["10.0.0.1"]);
+    assert_eq!(names["web.service"][1].port, Some(8081));
+    assert_eq!(names["web.service"][1].target, "web2.node");
+    assert!(registry::consul_names(b"{}", &[]).is_err());
+}
+
+#[test]
+fn etcd_keys_name_records_and_their_instances() {
+    let range = serde_json::json!({
+        "header": { "revision": "42" },
+        "kvs": [
+            { "key": base64("/rusty-dns/db"), "value": base64("10.0.1.5") },
+            { "key": base64("/rusty-dns/api/a"), "value": base64(r#"{"host": "10.0.2.1", "port": 9000}"#) },
+            { "key": base64("/rusty-dns/api/b"), "value": base64(r#"{"host": "10.0.2.2", "port": 9000}"#) },
+            { "key": base64("/rusty-dns/cache"), "value": base64("not an address") },
+        ]
+    });
+    let (names, revision) = registry::etcd_names("/rusty-dns/", range.to_string().as_bytes()).unwrap();
+
+    assert_eq!(revision, 42);
+    assert_eq!(names["db"][0].address.to_string(), "10.0.1.5");
+    assert_eq!(names["api"].len(), 2);
+    assert_eq!(names["a.api"][0].port, Some(9000));
+    assert_eq!(names["api"][1].target, "b.api");
+    assert!(!names.contains_key("cache"));
+}
+
+#[tokio::test]
+async fn registry_names_are_served_with_srv_records() {
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech", "--consul", "http://127.0.0.1:8500", "--registry-zone", "sd"]);
+    let handler = Handler::from_options(&options);
+    assert_eq!(Backend::from_options(&options), Some(Backend::Consul { url: "http://127.0.0.1:8500".to_string(), token: None }));
+    handler.registry.as_ref().unwrap().replace(registry::consul_names(NODES.as_bytes(), &[WEB.as_bytes().to_vec()]).unwrap());
+
+    let a = answer(&handler, "web.service.sd.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(texts(a.answers()), ["web.service.sd.mentisnovae.tech. 10 IN A 10.0.0.1"]);
+
+    let srv = answer(&handler, "web.service.sd.mentisnovae.tech.", RecordType::SRV).await;
+    assert_eq!(
+        texts(srv.answers()),
+        [
+            "web.service.sd.mentisnovae.tech. 10 IN SRV 1 1 8080 web1.node.sd.mentisnovae.tech.",
+            "web.service.sd.mentisnovae.tech. 10 IN SRV 1 1 8081 web2.node.sd.mentisnovae.tech.",
+        ]
+    );
+    assert_eq!(
+        texts(srv.additionals()),
+        ["web1.node.sd.mentisnovae.tech. 10 IN A 10.0.0.1", "web2.node.sd.mentisnovae.tech. 10 IN A 10.0.0.2"]
+    );
+
+    let missing = answer(&handler, "db.service.sd.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(missing.response_code(), ResponseCode::NXDomain);
+}
+
+#[tokio::test]
+async fn changes_in_consul_are_served_within_seconds() {
+    // A fake Consul whose catalog gains the web service at index 2, answering blocking queries for the current index once it changes
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let index = Arc::new(AtomicU64::new(1));
+    let consul_index = index.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let index = consul_index.clone();
+            tokio::spawn(async move {
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).into_owned();
+                let path = request.split_whitespace().nth(1).unwrap().to_string();
+                while path.contains(&format!("index={}&", index.load(Ordering::SeqCst))) {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                let current = index.load(Ordering::SeqCst);
+                let body = match (path.split('?').next().unwrap(), current) {
+                    ("/v1/catalog/nodes", _) => NODES,
+                    ("/v1/catalog/services", 1) => r#"{"consul": []}"#,
+                    ("/v1/catalog/services", _) => r#"{"consul": [], "web": ["v2"]}"#,
+                    ("/v1/health/service/web", _) => WEB,
+                    _ => "[]",
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nX-Consul-Index: {current}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            });
+        }
+    });
+
+    let registry = Arc::new(Registry::default());
+    tokio::spawn(registry::watch(registry.clone(), Backend::Consul { url, token: None }));
+    let wait_for = |name: &'static str| {
+        let registry = registry.clone();
+        async move {
+            for _ in 0..200 {
+                if !registry.instances(name).is_empty() {
+                    return true;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            false
+        }
+    };
+
+    assert!(wait_for("web1.node").await);
+    assert!(registry.instances("web.service").is_empty());
+    index.store(2, Ordering::SeqCst);
+    assert!(wait_for("web.service").await);
+}
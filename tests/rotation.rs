@@ -0,0 +1,121 @@
+use clap::Parser;
+use my_project::{
+    config::{FailoverConfig, HealthCheck, NameRotationConfig, RotationConfig, RotationPolicy},
+    handlers::Handler,
+    options::Options,
+    random::Random,
+    records,
+    rotation::Rotation,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{collections::BTreeMap, str::FromStr};
+use trust_dns_server::{
+    client::rr::{Name, Record, RecordType},
+    proto::op::Message,
+    server::RequestHandler,
+};
+
+// The answer of www.example.com: a CNAME record to api.example.com, which has three addresses
+fn answer() -> Vec<Record> {
+    let mut answer = vec![records::record("www.example.com", "CNAME", 60, "api.example.com").unwrap()];
+    for address in ["192.0.2.20", "192.0.2.21", "192.0.2.22"] {
+        answer.push(records::record("api.example.com", "A", 60, address).unwrap());
+    }
+    answer
+}
+
+// Returns the data of the records of an answer
+fn data(records: &[Record]) -> Vec<String> {
+    records.iter().map(|record| record.data().unwrap().to_string()).collect()
+}
+
+// Returns a rotation with one policy for every name
+fn rotation(policy: RotationPolicy) -> Rotation {
+    Rotation::new(&RotationConfig { policy, names: BTreeMap::new() }).unwrap()
+}
+
+#[test]
+fn round_robin_rotates_the_addresses_by_one_place_per_answer() {
+    let rotation = rotation(RotationPolicy::RoundRobin);
+    let random = Random::default();
+    let firsts: Vec<String> = (0..4).map(|_| data(&rotation.apply(answer(), &random))[1].clone()).collect();
+    assert_eq!(firsts, ["192.0.2.20", "192.0.2.21", "192.0.2.22", "192.0.2.20"]);
+
+    // The CNAME record stays in front of the addresses
+    assert_eq!(rotation.apply(answer(), &random)[0].record_type(), RecordType::CNAME);
+    assert_eq!(data(&self::rotation(RotationPolicy::Fixed).apply(answer(), &random)), data(&answer()));
+}
+
+#[test]
+fn shuffles_keep_every_address_and_weights_decide_which_comes_first() {
+    let random = Random::seeded(7);
+    let mut shuffled = data(&rotation(RotationPolicy::Shuffle).apply(answer(), &random));
+    let mut expected = data(&answer());
+    shuffled.sort();
+    expected.sort();
+    assert_eq!(shuffled, expected);
+
+    let names = BTreeMap::from([(
+        "api.example.com".to_string(),
+        NameRotationConfig {
+            policy: RotationPolicy::Weighted,
+            weights: BTreeMap::from([("192.0.2.20".to_string(), 9), ("192.0.2.22".to_string(), 0)]),
+            count: Some(2),
+        },
+    )]);
+    let weighted = Rotation::new(&RotationConfig { policy: RotationPolicy::Fixed, names }).unwrap();
+    let mut heavy = 0;
+    for _ in 0..1000 {
+        let answer = data(&weighted.apply(answer(), &random));
+        assert_eq!(answer.len(), 3);
+        assert!(!answer.contains(&"192.0.2.22".to_string()));
+        if answer[1] == "192.0.2.20" {
+            heavy += 1;
+        }
+    }
+    // 192.0.2.20 weighs 9 against 192.0.2.21's 1, so it comes first about 90% of the time
+    assert!((850..=950).contains(&heavy), "192.0.2.20 came first {heavy} times out of 1000");
+}
+
+#[test]
+fn policies_are_checked() {
+    let problem = |name: NameRotationConfig| {
+        let names = BTreeMap::from([("api.example.com".to_string(), name)]);
+        Rotation::new(&RotationConfig { policy: RotationPolicy::Fixed, names }).unwrap_err()
+    };
+    let weights = BTreeMap::from([("not-an-address".to_string(), 1)]);
+    assert!(problem(NameRotationConfig { policy: RotationPolicy::Weighted, weights, count: None }).contains("is not an address"));
+    let weights = BTreeMap::from([("192.0.2.20".to_string(), 1)]);
+    assert!(problem(NameRotationConfig { policy: RotationPolicy::RoundRobin, weights, count: None }).contains("only used by the weighted policy"));
+    assert!(problem(NameRotationConfig { policy: RotationPolicy::Fixed, weights: BTreeMap::new(), count: Some(0) }).contains("at least 1"));
+}
+
+#[tokio::test]
+async fn answers_of_backends_are_rotated() {
+    let mut options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    options.config.failover = vec![FailoverConfig {
+        name: "api.example.com".to_string(),
+        addresses: vec!["192.0.2.20".parse().unwrap(), "192.0.2.21".parse().unwrap()],
+        check: HealthCheck::Tcp,
+        port: Some(443),
+        path: "/".to_string(),
+        interval: 10,
+        timeout: 1,
+        healthy_threshold: 2,
+        unhealthy_threshold: 3,
+        ttl: 30,
+        fail_open: true,
+    }];
+    options.config.rotation = Some(RotationConfig { policy: RotationPolicy::RoundRobin, names: BTreeMap::new() });
+    let handler = Handler::from_options(&options);
+
+    let mut firsts = Vec::new();
+    for _ in 0..2 {
+        let request = RequestBuilder::new(Name::from_str("api.example.com.").unwrap(), RecordType::A).build();
+        let responder = CaptureResponder::default();
+        handler.handle_request(&request, responder.clone()).await;
+        let answer = Message::from_vec(&responder.take().unwrap()).unwrap();
+        firsts.push(answer.answers()[0].data().unwrap().to_string());
+    }
+    assert_eq!(firsts, ["192.0.2.20", "192.0.2.21"]);
+}
@@ -0,0 +1,114 @@
+#![cfg(feature = "sim")]
+
+use clap::Parser;
+use my_project::{
+    client,
+    handlers::Handler,
+    options::Options,
+    sim::{FaultyRelay, Faults},
+};
+use std::{
+    collections::HashSet,
+    io,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tokio::net::UdpSocket;
+use trust_dns_server::{
+    client::rr::{Name, RecordType},
+    proto::op::{Message, MessageType, Query},
+    ServerFuture,
+};
+
+// The domain the server serves.
+const DOMAIN: &str = "mentisnovae.tech";
+
+// Starts a server on an ephemeral loopback port and returns its address; it runs until the test's runtime stops
+async fn server() -> SocketAddr {
+    let options = Options::parse_from(["rusty-dns", "--domain", DOMAIN]);
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+
+    let mut server = ServerFuture::new(Handler::from_options(&options));
+    server.register_socket(socket);
+    tokio::spawn(async move { server.block_until_done().await });
+    address
+}
+
+// Returns the name of the fib zone's query for n
+fn fib(n: u32) -> Name {
+    format!("fib.{n}.{DOMAIN}.").parse().unwrap()
+}
+
+// Sends one query through a relay with the given faults and returns the answer, or the error of the exchange
+async fn exchange(faults: Faults, timeout: Duration) -> io::Result<Message> {
+    let relay = FaultyRelay::start(server().await, faults).await.unwrap();
+    client::exchange(relay.local_addr(), &fib(10), RecordType::TXT, false, timeout).await
+}
+
+#[tokio::test]
+async fn answers_pass_through_a_clean_link() {
+    let answer = exchange(Faults::default(), Duration::from_secs(2)).await.unwrap();
+    assert_eq!(answer.answers().len(), 1);
+}
+
+#[tokio::test]
+async fn duplicated_answers_are_ignored() {
+    let faults = Faults { duplicate: 1.0, ..Faults::default() };
+    let answer = exchange(faults, Duration::from_secs(2)).await.unwrap();
+    assert_eq!(answer.answers().len(), 1);
+}
+
+#[tokio::test]
+async fn lost_datagrams_time_out() {
+    let faults = Faults { loss: 1.0, ..Faults::default() };
+    let error = exchange(faults, Duration::from_millis(200)).await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+}
+
+#[tokio::test]
+async fn latency_delays_the_answer() {
+    let faults = Faults { latency: Duration::from_millis(100), ..Faults::default() };
+
+    // The query and the answer are each delayed, so the answer takes at least twice the latency
+    let started = Instant::now();
+    exchange(faults.clone(), Duration::from_secs(2)).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(200), "answered in {:?}", started.elapsed());
+
+    let error = exchange(faults, Duration::from_millis(150)).await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+}
+
+#[tokio::test]
+async fn reordered_and_jittered_answers_all_arrive() {
+    let faults = Faults {
+        reorder: 0.5,
+        jitter: Duration::from_millis(20),
+        seed: Some(7),
+        ..Faults::default()
+    };
+    let relay = FaultyRelay::start(server().await, faults).await.unwrap();
+
+    // Send a burst of queries from one socket and collect the answers in whatever order they come
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(relay.local_addr()).await.unwrap();
+    let ids: HashSet<u16> = (1..=20).collect();
+    for id in &ids {
+        let mut query = Message::new();
+        query
+            .set_id(*id)
+            .set_message_type(MessageType::Query)
+            .add_query(Query::query(fib(u32::from(*id)), RecordType::TXT));
+        socket.send(&query.to_vec().unwrap()).await.unwrap();
+    }
+
+    let mut order = Vec::new();
+    let mut buffer = vec![0; 4096];
+    while order.len() < ids.len() {
+        let length = tokio::time::timeout(Duration::from_secs(2), socket.recv(&mut buffer)).await.unwrap().unwrap();
+        order.push(Message::from_vec(&buffer[..length]).unwrap().id());
+    }
+
+    assert_eq!(order.iter().copied().collect::<HashSet<u16>>(), ids, "answers went missing: {order:?}");
+    assert!(order.windows(2).any(|pair| pair[0] > pair[1]), "no answer was reordered: {order:?}");
+}
@@ -0,0 +1,142 @@
+use clap::Parser;
+use my_project::{handlers::Handler, options::Options, tcp};
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpSocket, TcpStream},
+    time::timeout,
+};
+use trust_dns_server::{
+    client::rr::{Name, RecordType},
+    proto::op::{Message, MessageType, OpCode, Query, ResponseCode},
+};
+
+// Serves example.net on a local port within the given budget, and returns the port's address
+async fn serve(max_connections: usize, max_per_client: usize, idle_timeout: Duration) -> SocketAddr {
+    let handler = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", "example.net"]));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let limits = tcp::Limits { idle_timeout, max_connections, max_per_client, max_in_flight: 2 };
+    tokio::spawn(tcp::serve(listener, handler, limits));
+    address
+}
+
+// Connects to the server from a loopback address of the client's own, e.g. 127.0.0.2
+async fn connect(server: SocketAddr, client: &str) -> TcpStream {
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.bind(SocketAddr::new(IpAddr::from_str(client).unwrap(), 0)).unwrap();
+    socket.connect(server).await.unwrap()
+}
+
+// Writes a length-prefixed query for counter.example.net
+async fn send_query(stream: &mut TcpStream, id: u16) {
+    let mut message = Message::new();
+    message
+        .set_id(id)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .add_query(Query::query(Name::from_str("counter.example.net.").unwrap(), RecordType::TXT));
+    let bytes = message.to_vec().unwrap();
+    stream.write_all(&(bytes.len() as u16).to_be_bytes()).await.unwrap();
+    stream.write_all(&bytes).await.unwrap();
+}
+
+// Reads a length-prefixed answer
+async fn read_answer(stream: &mut TcpStream) -> Message {
+    let mut length = [0; 2];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut length)).await.unwrap().unwrap();
+    let mut bytes = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut bytes).await.unwrap();
+    Message::from_vec(&bytes).unwrap()
+}
+
+// Asks a question on a connection and checks that it is answered
+async fn ask(stream: &mut TcpStream, id: u16) {
+    send_query(stream, id).await;
+    let answer = read_answer(stream).await;
+    assert_eq!((answer.id(), answer.response_code()), (id, ResponseCode::NoError));
+}
+
+// Checks that the server closed a connection without answering it
+async fn assert_closed(stream: &mut TcpStream) {
+    let mut byte = [0; 1];
+    match timeout(Duration::from_secs(5), stream.read(&mut byte)).await {
+        Ok(Ok(0)) | Ok(Err(_)) => {}
+        other => panic!("expected the connection to be closed, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn pipelined_queries_are_all_answered() {
+    let server = serve(10, 2, Duration::from_secs(10)).await;
+    let mut stream = connect(server, "127.0.0.1").await;
+
+    // More queries than are handled at once, written before any answer is read
+    for id in 1..=10 {
+        send_query(&mut stream, id).await;
+    }
+    let mut ids = Vec::new();
+    for _ in 1..=10 {
+        let answer = read_answer(&mut stream).await;
+        assert_eq!(answer.response_code(), ResponseCode::NoError);
+        ids.push(answer.id());
+    }
+    ids.sort_unstable();
+    assert_eq!(ids, (1..=10).collect::<Vec<u16>>());
+}
+
+#[tokio::test]
+async fn clients_are_held_to_their_share() {
+    let server = serve(10, 2, Duration::from_secs(10)).await;
+    let mut first = connect(server, "127.0.0.2").await;
+    let mut second = connect(server, "127.0.0.2").await;
+    ask(&mut first, 1).await;
+    ask(&mut second, 2).await;
+
+    // A third connection from the same client is refused, while other clients still get in
+    let mut third = connect(server, "127.0.0.2").await;
+    assert_closed(&mut third).await;
+    ask(&mut connect(server, "127.0.0.3").await, 3).await;
+    ask(&mut first, 4).await;
+}
+
+#[tokio::test]
+async fn a_full_listener_sheds_the_heaviest_clients_idlest_connection() {
+    let server = serve(3, 0, Duration::from_secs(10)).await;
+    let mut idle = connect(server, "127.0.0.2").await;
+    ask(&mut idle, 1).await;
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let mut busy = connect(server, "127.0.0.2").await;
+    ask(&mut busy, 2).await;
+    let mut other = connect(server, "127.0.0.3").await;
+    ask(&mut other, 3).await;
+
+    // A newcomer takes the place of the idlest connection of the client holding two
+    let mut newcomer = connect(server, "127.0.0.4").await;
+    ask(&mut newcomer, 4).await;
+    assert_closed(&mut idle).await;
+    ask(&mut busy, 5).await;
+    ask(&mut other, 6).await;
+
+    // Now every client holds one, so one asking for a second is refused rather than shedding another's
+    let mut refused = connect(server, "127.0.0.3").await;
+    assert_closed(&mut refused).await;
+    ask(&mut busy, 7).await;
+    ask(&mut other, 8).await;
+    ask(&mut newcomer, 9).await;
+}
+
+#[tokio::test]
+async fn idle_connections_are_closed() {
+    let server = serve(10, 2, Duration::from_millis(200)).await;
+    let mut stream = connect(server, "127.0.0.1").await;
+    ask(&mut stream, 1).await;
+
+    // Half a query is as good as none
+    stream.write_all(&[0, 40, 0]).await.unwrap();
+    assert_closed(&mut stream).await;
+}
@@ -0,0 +1,81 @@
+use clap::Parser;
+use my_project::{cache::CaptureResponder, handlers::Handler, options::Options};
+use std::{net::SocketAddr, str::FromStr};
+use trust_dns_server::{
+    authority::MessageRequest,
+    client::rr::{Name, RecordType},
+    proto::{
+        op::{Edns, Message, MessageType, OpCode, Query},
+        serialize::binary::BinDecodable,
+    },
+    server::{Protocol, Request, RequestHandler},
+};
+
+// Encodes a query the way a client would send it
+fn query(id: u16, name: &str, query_type: RecordType, recursion_desired: bool, checking_disabled: bool, edns: bool) -> Vec<u8> {
+    let mut message = Message::new();
+    message
+        .set_id(id)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(recursion_desired)
+        .set_checking_disabled(checking_disabled)
+        .add_query(Query::query(Name::from_str(name).unwrap(), query_type));
+    if edns {
+        let mut edns = Edns::new();
+        edns.set_max_payload(1232);
+        message.set_edns(edns);
+    }
+    message.to_vec().unwrap()
+}
+
+// Answers a query datagram through the full path, decoding the query and building and encoding the response
+async fn full_path(handler: &Handler, src: SocketAddr, bytes: &[u8]) -> Vec<u8> {
+    let request = Request::new(MessageRequest::from_bytes(bytes).unwrap(), src, Protocol::Udp);
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    responder.take().unwrap()
+}
+
+#[tokio::test]
+async fn templates_answer_exactly_as_the_full_path_does() {
+    let handler = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", "example.net"]));
+    let src = SocketAddr::from(([192, 0, 2, 7], 53000));
+    for bytes in [
+        query(1, "nosuchzone.example.net.", RecordType::A, true, false, false),
+        query(2, "NoSuchZone.Example.NET.", RecordType::TXT, false, false, false),
+        query(3, "a.b.nosuchzone.example.net.", RecordType::AAAA, true, true, false),
+        query(4, "nosuchzone.example.net.", RecordType::MX, true, false, true),
+        query(65535, "x.example.net.", RecordType::CAA, false, true, true),
+    ] {
+        let mut templated = Vec::new();
+        assert!(handler.answer_from_template(src, &bytes, &mut templated), "{:?}", Message::from_vec(&bytes));
+        assert_eq!(templated, full_path(&handler, src, &bytes).await, "{:?}", Message::from_vec(&bytes));
+    }
+}
+
+#[tokio::test]
+async fn served_names_take_the_full_path() {
+    let handler = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", "example.net"]));
+    let src = SocketAddr::from(([192, 0, 2, 7], 53000));
+    for bytes in [
+        query(1, "dice.example.net.", RecordType::TXT, true, false, false),
+        query(2, "x.dice.example.net.", RecordType::TXT, true, false, false),
+        query(3, "FX.100.usd.eur.example.net.", RecordType::TXT, true, false, false),
+        query(4, "www.example.org.", RecordType::A, true, false, false),
+        query(5, "nosuchzone.example.net.", RecordType::AXFR, true, false, false),
+    ] {
+        let mut templated = Vec::new();
+        assert!(!handler.answer_from_template(src, &bytes, &mut templated), "{:?}", Message::from_vec(&bytes));
+    }
+
+    // Responses, other op codes and truncated queries aren't parsed as plain queries
+    let mut response = query(6, "nosuchzone.example.net.", RecordType::A, true, false, false);
+    response[2] |= 0x80;
+    let mut notify = query(7, "nosuchzone.example.net.", RecordType::A, true, false, false);
+    notify[2] |= 0x20;
+    let truncated = query(8, "nosuchzone.example.net.", RecordType::A, true, false, false)[..20].to_vec();
+    for bytes in [response, notify, truncated] {
+        assert!(!handler.answer_from_template(src, &bytes, &mut Vec::new()));
+    }
+}
@@ -0,0 +1,116 @@
+use clap::Parser;
+use my_project::{
+    config::{BlocklistConfig, TenantConfig},
+    handlers::Handler,
+    options::Options,
+    stats,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{path::PathBuf, str::FromStr};
+use trust_dns_server::{
+    client::rr::{LowerName, Name, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// The main domain, and the domain hosted next to it.
+const DOMAIN: &str = "mentisnovae.tech";
+const HOSTED: &str = "example.org";
+
+// Returns a blocklist file of its own for a test, listing the given domains
+fn blocklist_file(test: &str, domains: &[&str]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rusty-dns-tenants-{test}-{}.txt", std::process::id()));
+    std::fs::write(&path, domains.join("\n")).unwrap();
+    path
+}
+
+// Creates a handler for the main domain hosting example.org with the given rate limit and blocklist
+fn handler(rate_limit: u32, blocklist: Option<PathBuf>) -> Handler {
+    let mut options = Options::parse_from(["rusty-dns", "--domain", DOMAIN]);
+    options.config.tenants = vec![TenantConfig {
+        domain: HOSTED.to_string(),
+        admin_token: Some("secret".to_string()),
+        rate_limit,
+        counter_file: None,
+        blocklist: blocklist.map(|path| BlocklistConfig {
+            sources: vec![path.to_str().unwrap().to_string()],
+            compiled: None,
+            refresh_interval: 86400,
+            response: Default::default(),
+            allow: Vec::new(),
+            exempt: Default::default(),
+        }),
+    }];
+    Handler::from_options(&options)
+}
+
+// Returns the handler of the hosted domain
+fn hosted(handler: &Handler) -> &Handler {
+    let name = LowerName::from(Name::from_str(HOSTED).unwrap());
+    &handler.tenants.as_ref().unwrap().for_name(&name).unwrap().handler
+}
+
+// Answers a TXT query for a name
+async fn ask(handler: &Handler, name: &str) -> Message {
+    let request = RequestBuilder::new(Name::from_str(name).unwrap(), RecordType::TXT).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    Message::from_vec(&responder.take().expect("the handler sent no answer")).unwrap()
+}
+
+#[tokio::test]
+async fn hosted_domains_are_answered_and_counted_apart() {
+    let handler = handler(0, None);
+    for _ in 0..3 {
+        let answer = ask(&handler, "dice.example.org.").await;
+        assert_eq!(answer.response_code(), ResponseCode::NoError);
+        assert_eq!(answer.answers()[0].name().to_string(), "dice.example.org.");
+    }
+    assert_eq!(ask(&handler, "coin.mentisnovae.tech.").await.response_code(), ResponseCode::NoError);
+
+    assert_eq!(handler.counter.load(), 1);
+    assert_eq!(hosted(&handler).counter.load(), 3);
+    let dump = stats::collect(&handler);
+    assert_eq!(dump.queries, 1);
+    assert_eq!(dump.tenants["example.org."].queries, 3);
+    assert!(dump.tenants["example.org."].tenants.is_empty());
+
+    // The hosted domain shares the main domain's health, but not its statistics
+    assert!(std::sync::Arc::ptr_eq(&handler.health, &hosted(&handler).health));
+    assert!(!std::sync::Arc::ptr_eq(&handler.metrics, &hosted(&handler).metrics));
+}
+
+#[tokio::test]
+async fn rate_limits_and_blocklists_apply_to_their_own_domain() {
+    let path = blocklist_file("limits", &["dice.example.org", "dice.mentisnovae.tech"]);
+    let handler = handler(2, Some(path.clone()));
+    hosted(&handler).blocklist.as_ref().unwrap().wait_loaded().await;
+
+    // The hosted domain's blocklist doesn't apply to the main domain
+    assert_eq!(ask(&handler, "dice.example.org.").await.response_code(), ResponseCode::NXDomain);
+    assert_eq!(ask(&handler, "dice.mentisnovae.tech.").await.response_code(), ResponseCode::NoError);
+
+    // Its rate limit of 2 queries per second is used up by the blocked query and one more, while the main domain has none
+    assert_eq!(ask(&handler, "coin.example.org.").await.response_code(), ResponseCode::NoError);
+    assert_eq!(ask(&handler, "coin.example.org.").await.response_code(), ResponseCode::Refused);
+    for _ in 0..5 {
+        assert_eq!(ask(&handler, "coin.mentisnovae.tech.").await.response_code(), ResponseCode::NoError);
+    }
+
+    let dump = stats::collect(&handler);
+    assert_eq!(dump.rate_limited, None);
+    assert!(dump.blocklist.is_none());
+    assert_eq!(dump.tenants["example.org."].rate_limited, Some(1));
+    assert_eq!(dump.tenants["example.org."].blocklist.as_ref().unwrap().blocked, 1);
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn admin_tokens_must_match_in_full() {
+    let handler = handler(0, None);
+    let tenants = handler.tenants.as_ref().unwrap();
+    assert_eq!(tenants.for_token("secret").unwrap().domain.to_string(), "example.org.");
+    for token in ["secreT", "secre", "secrets", ""] {
+        assert!(tenants.for_token(token).is_none(), "{token}");
+    }
+}
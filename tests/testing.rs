@@ -0,0 +1,85 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::{
+    net::{Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+use trust_dns_server::{
+    client::rr::{Name, RData, RecordType},
+    proto::{
+        op::{Message, OpCode, ResponseCode},
+        rr::rdata::opt::{EdnsCode, EdnsOption},
+    },
+    server::{Protocol, RequestHandler},
+};
+
+// The domain the handler serves.
+const DOMAIN: &str = "mentisnovae.tech";
+
+// An EDNS option code from the range reserved for local use (RFC 6891 section 9).
+const LOCAL_OPTION: u16 = 65001;
+
+// Creates a handler with the default options and no configuration file
+fn handler() -> Handler {
+    Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", DOMAIN]))
+}
+
+// Returns the name of a label below the domain
+fn name(label: &str) -> Name {
+    Name::from_str(&format!("{label}.{DOMAIN}.")).unwrap()
+}
+
+#[test]
+fn the_request_carries_what_was_set() {
+    let source = SocketAddr::from(([192, 0, 2, 7], 5353));
+    let request = RequestBuilder::new(name("dice"), RecordType::TXT)
+        .id(4242)
+        .recursion_desired(false)
+        .source(source)
+        .protocol(Protocol::Tcp)
+        .edns_option(EdnsOption::Unknown(LOCAL_OPTION, vec![1, 2, 3]))
+        .build();
+
+    assert_eq!(request.id(), 4242);
+    assert_eq!(request.op_code(), OpCode::Query);
+    assert!(!request.recursion_desired());
+    assert_eq!(request.src(), source);
+    assert!(matches!(request.protocol(), Protocol::Tcp));
+    assert_eq!(request.query().name(), &name("dice").into());
+    assert_eq!(request.query().query_type(), RecordType::TXT);
+
+    let edns = request.edns().expect("the request has no OPT record");
+    assert_eq!(edns.max_payload(), 1232);
+    assert_eq!(edns.option(EdnsCode::Unknown(LOCAL_OPTION)), Some(&EdnsOption::Unknown(LOCAL_OPTION, vec![1, 2, 3])));
+}
+
+#[test]
+fn the_defaults_are_a_plain_udp_query() {
+    let request = RequestBuilder::new(name("dice"), RecordType::TXT).build();
+
+    assert_eq!(request.id(), 0);
+    assert!(request.recursion_desired());
+    assert!(request.edns().is_none());
+    assert!(matches!(request.protocol(), Protocol::Udp));
+    assert!(request.src().ip().is_loopback());
+}
+
+#[tokio::test]
+async fn a_handler_answers_the_built_request() {
+    let address = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7);
+    let request = RequestBuilder::new(name("myip"), RecordType::AAAA)
+        .source(SocketAddr::from((address, 53000)))
+        .build();
+
+    let responder = CaptureResponder::default();
+    handler().handle_request(&request, responder.clone()).await;
+    let answer = Message::from_vec(&responder.take().expect("the handler sent no answer")).unwrap();
+
+    assert_eq!(answer.response_code(), ResponseCode::NoError);
+    assert_eq!(answer.answers().len(), 1);
+    assert_eq!(answer.answers()[0].data(), Some(&RData::AAAA(address)));
+}
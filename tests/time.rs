@@ -0,0 +1,71 @@
+use clap::Parser;
+use my_project::{
+    calendar::{self, EpochFormat},
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+};
+use std::str::FromStr;
+use trust_dns_server::{
+    client::rr::{Name, RData, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// Answers a TXT query for a name with a handler serving a domain
+async fn answer(domain: &str, name: &str) -> Message {
+    let options = Options::parse_from(["rusty-dns", "--domain", domain]);
+    let handler = Handler::from_options(&options);
+    let request = RequestBuilder::new(Name::from_str(name).unwrap(), RecordType::TXT).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    Message::from_vec(&responder.take().unwrap()).unwrap()
+}
+
+// Returns the text of the first TXT record of a section
+fn text(records: &[trust_dns_server::client::rr::Record]) -> String {
+    match records.first().and_then(|record| record.data()) {
+        Some(RData::TXT(txt)) => txt.to_string(),
+        other => panic!("expected a TXT record, got {other:?}"),
+    }
+}
+
+#[test]
+fn timestamps_are_written_in_each_format() {
+    assert_eq!(calendar::format_epoch(1700000000, EpochFormat::Human).unwrap(), "2023-11-14 22:13:20 UTC");
+    assert_eq!(calendar::format_epoch(1700000000, EpochFormat::Iso).unwrap(), "2023-11-14T22:13:20Z");
+    assert_eq!(calendar::format_epoch(1700000000, EpochFormat::Rfc2822).unwrap(), "Tue, 14 Nov 2023 22:13:20 +0000");
+    assert_eq!(calendar::format_epoch(-86400, EpochFormat::Date).unwrap(), "1969-12-31");
+    assert_eq!(calendar::format_epoch(i64::MAX, EpochFormat::Human), None);
+    assert!("unix".parse::<EpochFormat>().unwrap_err().contains("human, iso, rfc2822 or date"));
+}
+
+#[tokio::test]
+async fn the_zone_follows_the_configured_domain() {
+    let answer = answer("example.net", "1700000000.time.example.net.").await;
+    assert_eq!(answer.response_code(), ResponseCode::NoError);
+    assert_eq!(text(answer.answers()), "2023-11-14 22:13:20 UTC");
+
+    let answer = self::answer("example.net", "iso.1700000000.time.example.net.").await;
+    assert_eq!(text(answer.answers()), "2023-11-14T22:13:20Z");
+
+    // The zone and its arguments are read case-insensitively
+    let answer = self::answer("example.net", "ISO.1700000000.Time.Example.NET.").await;
+    assert_eq!(text(answer.answers()), "2023-11-14T22:13:20Z");
+}
+
+#[tokio::test]
+async fn unreadable_timestamps_are_explained() {
+    for (name, explanation) in [
+        ("soon.time.example.net.", "soon is not a timestamp"),
+        ("unix.1700000000.time.example.net.", "unknown format unix"),
+        ("time.example.net.", "expected [<format>.]<timestamp>.time.example.net"),
+        ("a.b.c.time.example.net.", "expected [<format>.]<timestamp>.time.example.net"),
+        ("99999999999999999.time.example.net.", "out of range"),
+    ] {
+        let answer = answer("example.net", name).await;
+        assert_eq!(answer.response_code(), ResponseCode::FormErr, "{name}");
+        assert!(answer.answers().is_empty(), "{name}");
+        assert!(text(answer.additionals()).contains(explanation), "{name}: {}", text(answer.additionals()));
+    }
+}
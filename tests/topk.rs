@@ -0,0 +1,107 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+    topk::TopTalkers,
+};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+use trust_dns_server::{
+    client::rr::{Name, RData, RecordType},
+    proto::op::Message,
+    server::RequestHandler,
+};
+
+// Returns the address of one of many clients
+fn client(n: u32) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::from(0xc000_0200 + n))
+}
+
+#[test]
+fn heavy_hitters_are_tracked_with_bounded_error() {
+    let talkers = TopTalkers::new(Duration::from_secs(300), 10);
+
+    // Two heavy clients and a lighter one among a thousand that send one query each
+    for n in 0..1000 {
+        talkers.record(client(1000 + n), "dice.example.net.");
+        if n % 2 == 0 {
+            talkers.record(client(1), "coin.example.net.");
+        }
+        if n % 4 == 0 {
+            talkers.record(client(2), "coin.example.net.");
+        }
+        if n % 10 == 0 {
+            talkers.record(client(3), "rps.example.net.");
+        }
+    }
+
+    // Clients with more than a tenth of the 1850 queries are guaranteed to be tracked, and rank first
+    let top = talkers.top_clients(10);
+    assert_eq!(top.iter().take(2).map(|entry| entry.item).collect::<Vec<_>>(), [client(1), client(2)]);
+    for entry in &top {
+        // Counts never underestimate, and overestimate by at most the error they report
+        let actual = match entry.item {
+            ip if ip == client(1) => 500,
+            ip if ip == client(2) => 250,
+            ip if ip == client(3) => 100,
+            _ => 1,
+        };
+        assert!(entry.count >= actual && entry.count - entry.error <= actual, "{entry:?}");
+    }
+
+    // The distinct names fit in the sketch, so they are counted exactly
+    let qnames = talkers.top_qnames(10);
+    let qnames: Vec<(&str, u64, u64)> = qnames.iter().map(|entry| (entry.item.as_str(), entry.count, entry.error)).collect();
+    assert_eq!(qnames, [("dice.example.net.", 1000, 0), ("coin.example.net.", 750, 0), ("rps.example.net.", 100, 0)]);
+    assert_eq!(talkers.top_qnames(1).len(), 1);
+}
+
+#[test]
+fn counts_leave_the_window() {
+    // The window slides by a fifth of its length, but at least a second at a time
+    let talkers = TopTalkers::new(Duration::from_secs(1), 10);
+    talkers.record(client(1), "dice.example.net.");
+    assert_eq!(talkers.top_clients(10).len(), 1);
+
+    std::thread::sleep(Duration::from_millis(5100));
+    talkers.record(client(2), "coin.example.net.");
+    assert_eq!(talkers.top_clients(10).iter().map(|entry| entry.item).collect::<Vec<_>>(), [client(2)]);
+    assert_eq!(talkers.top_qnames(10).iter().map(|entry| entry.item.as_str()).collect::<Vec<_>>(), ["coin.example.net."]);
+}
+
+#[tokio::test]
+async fn the_stats_zone_lists_the_heavy_hitters() {
+    let options = Options::parse_from(["rusty-dns", "--domain", "example.net"]);
+    let handler = Handler::from_options(&options);
+    let ask = |name: &str, source: &str| {
+        let request = RequestBuilder::new(Name::from_str(name).unwrap(), RecordType::TXT)
+            .source(source.parse::<SocketAddr>().unwrap())
+            .build();
+        let handler = &handler;
+        async move {
+            let responder = CaptureResponder::default();
+            handler.handle_request(&request, responder.clone()).await;
+            Message::from_vec(&responder.take().unwrap()).unwrap()
+        }
+    };
+    for _ in 0..3 {
+        ask("counter.example.net.", "192.0.2.7:53000").await;
+    }
+    ask("counter.example.net.", "192.0.2.8:53000").await;
+
+    // The stats query itself is counted before it is answered
+    let answer = ask("stats.clients.2.example.net.", "192.0.2.7:53000").await;
+    let lines: Vec<String> = answer
+        .answers()
+        .iter()
+        .map(|record| match record.data() {
+            Some(RData::TXT(txt)) => txt.to_string(),
+            other => panic!("expected a TXT record, got {other:?}"),
+        })
+        .collect();
+    assert_eq!(lines, ["window=300s", "clients: 192.0.2.7 4 (+-0)", "clients: 192.0.2.8 1 (+-0)"]);
+}
@@ -0,0 +1,80 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+    trace::Tracer,
+};
+use std::{net::SocketAddr, str::FromStr};
+use tokio::net::UdpSocket;
+use trust_dns_server::{
+    client::rr::{Name, RData, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+    ServerFuture,
+};
+
+// Starts a server for mentisnovae.tech on an ephemeral loopback port to stand in for the root servers, and returns its address
+async fn root() -> SocketAddr {
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+
+    let mut server = ServerFuture::new(Handler::from_options(&options));
+    server.register_socket(socket);
+    tokio::spawn(async move { server.block_until_done().await });
+    address
+}
+
+// Asks a handler for a name and returns the answer
+async fn ask(handler: &Handler, name: &str) -> Message {
+    let request = RequestBuilder::new(Name::from_str(name).unwrap(), RecordType::TXT).build();
+    let responder = CaptureResponder::default();
+    handler.handle_request(&request, responder.clone()).await;
+    Message::from_vec(&responder.take().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn traces_list_every_server_asked() {
+    let root = root().await;
+    let tracer = Tracer::new(vec![root]);
+    let name = Name::from_str("myip.mentisnovae.tech.").unwrap();
+    let trace = tracer.trace(&name, RecordType::A).await.unwrap();
+    assert_eq!(trace.hops.len(), 1);
+    assert!(trace.stopped.is_none());
+
+    let lines = trace.lines(&name, RecordType::A);
+    assert_eq!(lines[0].split(':').next().unwrap(), "trace of myip.mentisnovae.tech. A");
+    assert!(lines[1].starts_with("1 . 127.0.0.1 "), "{}", lines[1]);
+    assert!(lines[1].ends_with("ms NOERROR: myip.mentisnovae.tech. 60 IN A 127.0.0.1"), "{}", lines[1]);
+
+    // A name the server doesn't serve ends the trace with its answer
+    let missing = Name::from_str("nothing.mentisnovae.tech.").unwrap();
+    let trace = tracer.trace(&missing, RecordType::A).await.unwrap();
+    assert_eq!(trace.stopped, Some("the name does not exist"));
+    assert!(trace.lines(&missing, RecordType::A)[1].contains("NXDOMAIN"));
+}
+
+#[tokio::test]
+async fn the_trace_zone_answers_only_when_enabled() {
+    let root = root().await.to_string();
+    let enabled = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech", "--trace", "--trace-roots", &root]));
+    let answer = ask(&enabled, "trace.myip.mentisnovae.tech.mentisnovae.tech.").await;
+    assert_eq!(answer.response_code(), ResponseCode::NoError);
+    assert_eq!(answer.answers()[0].ttl(), 0);
+    let lines: Vec<String> = answer
+        .answers()
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(RData::TXT(txt)) => Some(txt.to_string()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].contains(&format!(" {} ", root.split(':').next().unwrap())));
+
+    // Without a name to trace the query is malformed, and the zone doesn't exist unless enabled
+    assert_eq!(ask(&enabled, "trace.mentisnovae.tech.").await.response_code(), ResponseCode::FormErr);
+    let disabled = Handler::from_options(&Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]));
+    assert_eq!(ask(&disabled, "trace.myip.mentisnovae.tech.mentisnovae.tech.").await.response_code(), ResponseCode::NXDomain);
+}
@@ -0,0 +1,78 @@
+use clap::Parser;
+use my_project::{
+    config::{TunnelingAction, TunnelingConfig},
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+    tunneling::{self, TunnelDetector, Verdict},
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use trust_dns_server::{
+    client::rr::{Name, RecordType},
+    proto::op::{Message, ResponseCode},
+    server::RequestHandler,
+};
+
+// Encodes a number as a hex label, the way a tunnel encodes its data
+fn encoded(i: u64) -> String {
+    let i = i + 1;
+    format!("{:016x}{:016x}", i.wrapping_mul(0x9e37_79b9_7f4a_7c15), i.wrapping_mul(0xc2b2_ae3d_27d4_eb4f))
+}
+
+#[test]
+fn entropy_tells_words_from_encoded_data() {
+    assert_eq!(tunneling::entropy(""), 0.0);
+    assert_eq!(tunneling::entropy("aaaa"), 0.0);
+    assert_eq!(tunneling::entropy("abcd"), 2.0);
+    assert!(tunneling::entropy("dice") < 2.5);
+    assert!(tunneling::entropy(&encoded(7)) > 3.0);
+}
+
+#[test]
+fn clients_sending_new_encoded_names_are_flagged() {
+    let detector = TunnelDetector::new(&TunnelingConfig::default());
+    let (tunnel, player): (IpAddr, IpAddr) = ("192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap());
+    let start = Instant::now();
+
+    let mut verdicts = Vec::new();
+    for i in 0..25 {
+        verdicts.push(detector.observe(tunnel, &format!("str.upper.{}", encoded(i)), RecordType::TXT, start));
+        assert_eq!(detector.observe(player, "dice", RecordType::TXT, start), Verdict::Clear);
+    }
+
+    // Nobody is scored before min_queries, and the tunnel is flagged once and then stays suspected
+    assert!(verdicts[..19].iter().all(|verdict| *verdict == Verdict::Clear));
+    assert!(matches!(verdicts[19], Verdict::Flagged(score) if score.total() > 0.8 && score.txt == 1.0));
+    assert!(verdicts[20..].iter().all(|verdict| *verdict == Verdict::Suspected));
+    assert_eq!(detector.stats().flagged, 1);
+    assert_eq!(detector.stats().blocked, 0);
+
+    // The suspicion lapses after block_for
+    let later = start + Duration::from_secs(601);
+    assert_eq!(detector.observe(tunnel, &encoded(99), RecordType::TXT, later), Verdict::Clear);
+    assert!(detector.stats().suspected.is_empty());
+}
+
+#[tokio::test]
+async fn suspected_clients_are_refused_when_blocking() {
+    let mut options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    options.config.tunneling = Some(TunnelingConfig { action: TunnelingAction::Block, min_queries: 5, ..Default::default() });
+    let handler = Handler::from_options(&options);
+    let source: SocketAddr = "192.0.2.1:53000".parse().unwrap();
+
+    let mut response_codes = Vec::new();
+    for i in 0..6 {
+        let name = Name::from_str(&format!("str.upper.{}.mentisnovae.tech.", encoded(i))).unwrap();
+        let request = RequestBuilder::new(name, RecordType::TXT).source(source).build();
+        let responder = CaptureResponder::default();
+        handler.handle_request(&request, responder.clone()).await;
+        response_codes.push(Message::from_vec(&responder.take().unwrap()).unwrap().response_code());
+    }
+    assert_eq!(response_codes[..4], [ResponseCode::NoError; 4]);
+    assert_eq!(response_codes[4..], [ResponseCode::Refused; 2]);
+    assert_eq!(handler.tunneling.as_ref().unwrap().stats().blocked, 2);
+}
@@ -0,0 +1,137 @@
+#![cfg(target_os = "linux")]
+
+use clap::Parser;
+use my_project::{handlers::Handler, options::Options, udp_batch};
+use std::{
+    net::{Ipv6Addr, SocketAddr, SocketAddrV6},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::net::UdpSocket;
+use trust_dns_server::{
+    client::rr::{Name, RecordType},
+    proto::op::{Message, MessageType, OpCode, Query, ResponseCode},
+};
+
+// Returns a datagram of a given size to a client
+fn datagram(size: usize, port: u16) -> (Vec<u8>, SocketAddr) {
+    (vec![0; size], SocketAddr::from(([192, 0, 2, 7], port)))
+}
+
+#[test]
+fn equally_sized_answers_to_a_client_are_sent_together() {
+    // Runs end at a shorter datagram, which is sent as the last segment
+    let datagrams = [datagram(100, 53000), datagram(100, 53000), datagram(60, 53000), datagram(100, 53000)];
+    assert_eq!(udp_batch::gso_runs(&datagrams), vec![3, 1]);
+
+    // A longer datagram or another client starts a new run
+    let datagrams = [datagram(60, 53000), datagram(100, 53000), datagram(100, 53001), datagram(100, 53001)];
+    assert_eq!(udp_batch::gso_runs(&datagrams), vec![1, 1, 2]);
+
+    // The kernel takes at most 64 segments, and no more than a UDP payload in all
+    let datagrams = vec![datagram(100, 53000); 100];
+    assert_eq!(udp_batch::gso_runs(&datagrams), vec![64, 36]);
+    let datagrams = vec![datagram(30000, 53000); 3];
+    assert_eq!(udp_batch::gso_runs(&datagrams), vec![2, 1]);
+
+    // Empty datagrams can't be segmented
+    let datagrams = [datagram(0, 53000), datagram(0, 53000)];
+    assert_eq!(udp_batch::gso_runs(&datagrams), vec![1, 1]);
+    assert!(udp_batch::gso_runs(&[]).is_empty());
+}
+
+#[test]
+fn socket_addresses_survive_the_kernel_representation() {
+    let addresses = [
+        SocketAddr::from(([192, 0, 2, 7], 53000)),
+        SocketAddr::from(([255, 255, 255, 255], 1)),
+        SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from_str("2001:db8::7").unwrap(), 53000, 0, 0)),
+        SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from_str("fe80::1").unwrap(), 853, 0x12345, 3)),
+    ];
+    for address in addresses {
+        let (raw, length) = udp_batch::to_raw_addr(&address);
+        let expected = match address {
+            SocketAddr::V4(_) => std::mem::size_of::<libc::sockaddr_in>(),
+            SocketAddr::V6(_) => std::mem::size_of::<libc::sockaddr_in6>(),
+        };
+        assert_eq!(length as usize, expected, "{address}");
+        assert_eq!(udp_batch::to_socket_addr(&raw), Some(address));
+    }
+
+    // Families other than IPv4 and IPv6 aren't addresses a query can come from
+    // SAFETY: a sockaddr_storage is plain data, for which all zeroes is valid.
+    let mut unix: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    unix.ss_family = libc::AF_UNIX as libc::sa_family_t;
+    assert_eq!(udp_batch::to_socket_addr(&unix), None);
+}
+
+#[tokio::test]
+async fn queries_read_in_batches_are_each_answered() {
+    let options = Options::parse_from(["rusty-dns", "--domain", "example.net"]);
+    let handler = Handler::from_options(&options);
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = server.local_addr().unwrap();
+    tokio::spawn(udp_batch::serve(server, handler, 4));
+
+    // Send more queries than fit in one batch before reading any answer
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    for id in 1..=10u16 {
+        let mut message = Message::new();
+        message
+            .set_id(id)
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .add_query(Query::query(Name::from_str("counter.example.net.").unwrap(), RecordType::TXT));
+        client.send_to(&message.to_vec().unwrap(), address).await.unwrap();
+    }
+
+    let mut ids = Vec::new();
+    let mut buffer = vec![0; 4096];
+    for _ in 1..=10 {
+        let (length, from) = tokio::time::timeout(Duration::from_secs(5), client.recv_from(&mut buffer)).await.unwrap().unwrap();
+        assert_eq!(from, address);
+        let answer = Message::from_vec(&buffer[..length]).unwrap();
+        assert_eq!(answer.response_code(), ResponseCode::NoError);
+        assert_eq!(answer.answers().len(), 1);
+        ids.push(answer.id());
+    }
+    ids.sort_unstable();
+    assert_eq!(ids, (1..=10).collect::<Vec<u16>>());
+}
+
+#[tokio::test]
+async fn equally_sized_answers_arrive_as_separate_datagrams() {
+    let options = Options::parse_from(["rusty-dns", "--domain", "example.net"]);
+    let handler = Handler::from_options(&options);
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = server.local_addr().unwrap();
+    tokio::spawn(udp_batch::serve(server, handler, 32));
+
+    // The answers to one client's myip queries are all the same size, so they can leave as one segmented message
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    for id in 1..=20u16 {
+        let mut message = Message::new();
+        message
+            .set_id(id)
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .add_query(Query::query(Name::from_str("myip.example.net.").unwrap(), RecordType::A));
+        client.send_to(&message.to_vec().unwrap(), address).await.unwrap();
+    }
+
+    // However they were sent, each answer is received on its own and whole
+    let mut ids = Vec::new();
+    let mut lengths = Vec::new();
+    let mut buffer = vec![0; 65536];
+    for _ in 1..=20 {
+        let (length, _) = tokio::time::timeout(Duration::from_secs(5), client.recv_from(&mut buffer)).await.unwrap().unwrap();
+        let answer = Message::from_vec(&buffer[..length]).unwrap();
+        assert_eq!(answer.response_code(), ResponseCode::NoError);
+        assert_eq!(answer.answers().len(), 1);
+        ids.push(answer.id());
+        lengths.push(length);
+    }
+    ids.sort_unstable();
+    assert_eq!(ids, (1..=20).collect::<Vec<u16>>());
+    assert!(lengths.iter().all(|length| *length == lengths[0]), "{lengths:?}");
+}
@@ -0,0 +1,147 @@
+use clap::Parser;
+use my_project::{
+    config::{Config, ConfigError, WebhookEvent},
+    handlers::Handler,
+    options::Options,
+    testing::{CaptureResponder, RequestBuilder},
+    webhooks::{self, SIGNATURE_HEADER},
+};
+use std::{str::FromStr, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::mpsc,
+};
+use trust_dns_server::{
+    client::rr::{Name, RecordType},
+    server::RequestHandler,
+};
+
+// Writes a configuration file for the test and loads it
+fn load(test: &str, text: &str) -> Result<Config, ConfigError> {
+    let path = std::env::temp_dir().join(format!("rusty-dns-webhooks-{test}-{}.toml", std::process::id()));
+    std::fs::write(&path, text).unwrap();
+    let config = Config::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    config
+}
+
+// Creates a handler with the given configuration
+fn handler(config: Config) -> Handler {
+    let mut options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech"]);
+    options.config = config;
+    Handler::from_options(&options)
+}
+
+// Sends a query for a name to the handler
+async fn query(handler: &Handler, name: &str) {
+    let request = RequestBuilder::new(Name::from_str(name).unwrap(), RecordType::A).build();
+    handler.handle_request(&request, CaptureResponder::default()).await;
+}
+
+// Reads an HTTP request up to the end of its body, returning its head and body
+async fn read_request(stream: &mut tokio::net::TcpStream) -> (String, String) {
+    let mut request = Vec::new();
+    let mut buffer = vec![0; 4096];
+    loop {
+        let read = stream.read(&mut buffer).await.unwrap();
+        request.extend_from_slice(&buffer[..read]);
+        let text = String::from_utf8_lossy(&request).into_owned();
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let length = head
+                .lines()
+                .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|value| value.trim().parse::<usize>().unwrap()))
+                .unwrap_or(0);
+            if body.len() >= length || read == 0 {
+                return (head.to_string(), body.to_string());
+            }
+        }
+    }
+}
+
+#[test]
+fn webhooks_need_names_to_watch_and_thresholds() {
+    let unwatched = load("unwatched", "[[webhooks]]\nurl = \"http://127.0.0.1:1/\"\nevents = [\"query\"]\n");
+    assert!(matches!(unwatched, Err(ConfigError::Invalid(_, message)) if message.contains("no names to watch")));
+    let spike = load("spike", "[[webhooks]]\nurl = \"http://127.0.0.1:1/\"\nevents = [\"nxdomain_spike\"]\n");
+    assert!(matches!(spike, Err(ConfigError::Invalid(_, message)) if message.contains("nxdomain_spike_above")));
+
+    let config = load("valid", "[[webhooks]]\nurl = \"http://127.0.0.1:1/\"\nevents = [\"blocked\", \"nxdomain_spike\"]\nnxdomain_spike_above = 100\n").unwrap();
+    assert_eq!(config.webhooks[0].events, [WebhookEvent::Blocked, WebhookEvent::NxdomainSpike]);
+    assert_eq!((config.webhooks[0].batch_interval, config.webhooks[0].batch_size, config.webhooks[0].retries), (10, 100, 5));
+}
+
+#[test]
+fn batches_are_signed_with_hmac_sha256() {
+    // RFC 4231, test case 2
+    assert_eq!(
+        webhooks::sign("Jefe", b"what do ya want for nothing?"),
+        "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
+
+#[tokio::test]
+async fn only_queries_for_watched_names_are_queued() {
+    let config = load(
+        "watched",
+        "[[webhooks]]\nurl = \"http://127.0.0.1:1/\"\nevents = [\"query\"]\nwatch = [\"*.canary.mentisnovae.tech\", \"secret.mentisnovae.tech\"]\nbatch_interval = 3600\n",
+    )
+    .unwrap();
+    let handler = handler(config);
+
+    query(&handler, "aws-keys.Canary.mentisnovae.tech.").await;
+    query(&handler, "canary.mentisnovae.tech.").await;
+    query(&handler, "other.secret.mentisnovae.tech.").await;
+    query(&handler, "myip.mentisnovae.tech.").await;
+
+    let pending = handler.webhooks.as_ref().unwrap().pending(0);
+    let names: Vec<&str> = pending.iter().map(|event| event.qname.as_deref().unwrap()).collect();
+    assert_eq!(names, ["aws-keys.canary.mentisnovae.tech.", "canary.mentisnovae.tech."]);
+    assert_eq!(pending[0].event, WebhookEvent::Query);
+    assert_eq!(pending[0].qtype.as_deref(), Some("A"));
+    assert_eq!(pending[0].rcode.as_deref(), Some("Non-Existent Domain"));
+}
+
+#[tokio::test]
+async fn failed_batches_are_retried_and_signed() {
+    // A receiver that fails the first request and accepts the next
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, mut received) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for status in ["500 Internal Server Error", "200 OK"] {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut stream).await;
+            let response = format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            stream.write_all(response.as_bytes()).await.unwrap();
+            sender.send(request).unwrap();
+        }
+    });
+
+    let config = load(
+        "retried",
+        &format!(
+            "[[webhooks]]\nurl = \"{url}\"\nevents = [\"query\"]\nwatch = [\"*.canary.mentisnovae.tech\"]\nsecret = \"hunter2\"\nbatch_interval = 1\nretries = 2\n"
+        ),
+    )
+    .unwrap();
+    let handler = handler(config);
+    query(&handler, "one.canary.mentisnovae.tech.").await;
+    query(&handler, "two.canary.mentisnovae.tech.").await;
+
+    let wait = Duration::from_secs(10);
+    let (_, first) = tokio::time::timeout(wait, received.recv()).await.unwrap().unwrap();
+    let (head, body) = tokio::time::timeout(wait, received.recv()).await.unwrap().unwrap();
+    assert_eq!(first, body);
+    assert!(head.starts_with("POST /hook "));
+    let signature = format!("{}: {}", SIGNATURE_HEADER.to_ascii_lowercase(), webhooks::sign("hunter2", body.as_bytes()));
+    assert!(head.to_ascii_lowercase().lines().any(|line| line == signature));
+
+    let batch: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(batch["source"], "mentisnovae.tech");
+    assert_eq!(batch["dropped"], 0);
+    let names: Vec<&str> = batch["events"].as_array().unwrap().iter().map(|event| event["qname"].as_str().unwrap()).collect();
+    assert_eq!(names, ["one.canary.mentisnovae.tech.", "two.canary.mentisnovae.tech."]);
+    assert_eq!(batch["events"][0]["event"], "query");
+    assert!(batch["events"][0].get("count").is_none());
+}
@@ -0,0 +1,95 @@
+use clap::Parser;
+use my_project::{
+    handlers::Handler,
+    options::Options,
+    testing::answer,
+    wireguard::{self, Source},
+};
+use std::os::unix::fs::PermissionsExt;
+use trust_dns_server::{
+    client::rr::RecordType,
+    proto::op::{Message, ResponseCode},
+};
+
+// A wg-quick configuration with peers named both ways, one unnamed and one routing a whole site.
+const CONFIG: &str = "
+[Interface]
+Address = 10.8.0.1/24
+PrivateKey = yAnz5TF+lXXJte14tji3zlMNq+hd2rYUIgJBgB3fBmk=
+
+[Peer]
+# Name = Alice's Laptop
+PublicKey = xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=
+AllowedIPs = 10.8.0.2/32, fd08::2/128
+
+# phone
+[Peer]
+PublicKey = TrMvSoP4jYQlY6RIzBgbssQqY3vxI2Pi+y71lOWWXX0=
+AllowedIPs = 10.8.0.3/32
+
+[Peer]
+PublicKey = gN65BkIKy1eCE9pP1wdc8ROUtkHLF2PfAqYdyYBz6EA=
+AllowedIPs = 10.8.0.4/32, 192.168.50.0/24
+";
+
+// Returns the data of each record of an answer, in order
+fn rdata(answer: &Message) -> Vec<String> {
+    answer.answers().iter().map(|record| record.to_string().rsplit(' ').next().unwrap().to_string()).collect()
+}
+
+#[test]
+fn peers_are_named_by_their_comments_or_keys() {
+    let peers = wireguard::parse_config(CONFIG);
+    let names: Vec<&str> = peers.iter().map(|peer| peer.name.as_str()).collect();
+    assert_eq!(names, ["alice-s-laptop", "phone", "80deb90642"]);
+    assert_eq!(peers[0].public_key, "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=");
+    let addresses = |index: usize| peers[index].addresses.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(addresses(0), ["10.8.0.2", "fd08::2"]);
+    // Only single hosts are a peer's own addresses
+    assert_eq!(addresses(2), ["10.8.0.4"]);
+}
+
+#[test]
+fn the_kernels_peers_take_their_names_from_the_configuration() {
+    let directory = std::env::temp_dir().join(format!("rusty-dns-wireguard-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let config = directory.join("wg0.conf");
+    std::fs::write(&config, CONFIG).unwrap();
+
+    // A wg that lists the interface's peers: a configured one whose address changed, and one added since
+    let wg = directory.join("wg");
+    std::fs::write(
+        &wg,
+        "#!/bin/sh\nprintf 'xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=\\t10.8.0.12/32\\nAAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=\\t(none)\\n'\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&wg, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::env::set_var("PATH", format!("{}:{}", directory.display(), std::env::var("PATH").unwrap_or_default()));
+
+    let source = Source { config: Some(config), interface: Some("wg0".to_string()) };
+    let peers = source.peers().unwrap();
+    assert_eq!(peers.len(), 2);
+    assert_eq!((peers[0].name.as_str(), peers[0].addresses[0].to_string()), ("alice-s-laptop", "10.8.0.12".to_string()));
+    assert_eq!(peers[1].name, "0001020304");
+    assert!(peers[1].addresses.is_empty());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn peers_are_served_as_a_and_aaaa_records() {
+    let options = Options::parse_from(["rusty-dns", "--domain", "mentisnovae.tech", "--wireguard-config", "/nonexistent/wg0.conf"]);
+    let handler = Handler::from_options(&options);
+    handler.wireguard.as_ref().unwrap().replace(wireguard::parse_config(CONFIG));
+
+    let a = answer(&handler, "alice-s-laptop.wg.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(rdata(&a), ["10.8.0.2"]);
+    assert_eq!(a.answers()[0].ttl(), wireguard::TTL);
+    let aaaa = answer(&handler, "Alice-S-Laptop.wg.mentisnovae.tech.", RecordType::AAAA).await;
+    assert_eq!(rdata(&aaaa), ["fd08::2"]);
+    let none = answer(&handler, "phone.wg.mentisnovae.tech.", RecordType::AAAA).await;
+    assert_eq!(none.response_code(), ResponseCode::NoError);
+    assert!(none.answers().is_empty());
+    let missing = answer(&handler, "bob.wg.mentisnovae.tech.", RecordType::A).await;
+    assert_eq!(missing.response_code(), ResponseCode::NXDomain);
+}